@@ -36,6 +36,7 @@ use crate::error::Error;
 use crate::fees::{tx_execution_gas_limit, FeeUpdates};
 use crate::inbox::{Deposit, Transaction, TransactionContent};
 use crate::indexable_storage::IndexableStorage;
+use crate::rejected_transactions::store_rejected_transaction;
 use crate::storage::{index_account, read_ticketer};
 use crate::{tick_model, CONFIG};
 
@@ -276,6 +277,7 @@ fn apply_ethereum_transaction_common<Host: Runtime>(
     precompiles: &PrecompileBTreeMap<Host>,
     evm_account_storage: &mut EthereumAccountStorage,
     transaction: &EthereumTransactionCommon,
+    tx_hash: &TransactionHash,
     allocated_ticks: u64,
     retriable: bool,
 ) -> Result<ExecutionResult<TransactionResult>, anyhow::Error> {
@@ -288,7 +290,10 @@ fn apply_ethereum_transaction_common<Host: Runtime>(
         effective_gas_price,
     )? {
         Validity::Valid(caller, gas_limit) => (caller, gas_limit),
-        _reason => return Ok(ExecutionResult::Invalid),
+        reason => {
+            store_rejected_transaction(host, tx_hash, reason, block_constants.number)?;
+            return Ok(ExecutionResult::Invalid);
+        }
     };
 
     let to = transaction.to;
@@ -559,6 +564,7 @@ pub fn apply_transaction<Host: Runtime>(
             precompiles,
             evm_account_storage,
             tx,
+            &transaction.tx_hash,
             allocated_ticks,
             retriable,
         )?,
@@ -604,18 +610,25 @@ pub fn apply_transaction<Host: Runtime>(
 mod tests {
     use std::vec;
 
+    use crate::inbox::{Transaction, TransactionContent};
+    use crate::rejected_transactions::read_rejected_transaction;
+    use crate::storage::init_account_index;
+    use crate::tick_model::constants::MAX_ALLOWED_TICKS;
     use crate::{apply::Validity, fees::gas_for_fees};
     use evm_execution::account_storage::{account_path, EthereumAccountStorage};
+    use evm_execution::precompiles;
     use primitive_types::{H160, U256};
     use tezos_ethereum::{
         block::{BlockConstants, BlockFees},
-        transaction::TransactionType,
+        transaction::{TransactionType, TRANSACTION_HASH_SIZE},
         tx_common::EthereumTransactionCommon,
     };
     use tezos_smart_rollup_encoding::timestamp::Timestamp;
     use tezos_smart_rollup_mock::MockHost;
 
-    use super::is_valid_ethereum_transaction_common;
+    use super::{
+        apply_transaction, is_valid_ethereum_transaction_common, ExecutionResult,
+    };
 
     const CHAIN_ID: u32 = 1337;
 
@@ -926,4 +939,56 @@ mod tests {
             "Transaction should have been rejected"
         );
     }
+
+    #[test]
+    fn rejection_is_kept_once_transaction_is_applied() {
+        let mut host = MockHost::default();
+        let mut evm_account_storage =
+            evm_execution::account_storage::init_account_storage().unwrap();
+        let mut accounts_index = init_account_index().unwrap();
+        let precompiles = precompiles::precompile_set();
+        let block_constants = mock_block_constants();
+
+        // setup
+        let address = address_from_str("af1276cbb260bb13deddb4209ae99ae6e497f446");
+        let gas_price = block_constants.base_fee_per_gas();
+        let fee_gas = gas_for_fees_no_data(&block_constants, gas_price);
+        let transaction = Transaction {
+            tx_hash: [1; TRANSACTION_HASH_SIZE],
+            content: TransactionContent::Ethereum(valid_tx(21000 + fee_gas)),
+        };
+        let mut apply =
+            |host: &mut MockHost, evm_account_storage: &mut EthereumAccountStorage| {
+                apply_transaction(
+                    host,
+                    &block_constants,
+                    &precompiles,
+                    &transaction,
+                    0,
+                    evm_account_storage,
+                    &mut accounts_index,
+                    MAX_ALLOWED_TICKS,
+                    false,
+                )
+                .expect("Applying the transaction should not have raised an error")
+            };
+
+        // the sender cannot pay for the transaction yet
+        set_balance(&mut host, &mut evm_account_storage, &address, U256::zero());
+        let result = apply(&mut host, &mut evm_account_storage);
+        assert!(matches!(result, ExecutionResult::Invalid));
+        assert!(read_rejected_transaction(&host, &transaction.tx_hash)
+            .unwrap()
+            .is_some());
+
+        // the same transaction is included once the sender is funded
+        let balance = U256::from(10000000000000000000u64);
+        set_balance(&mut host, &mut evm_account_storage, &address, balance);
+        let result = apply(&mut host, &mut evm_account_storage);
+        assert!(matches!(result, ExecutionResult::Valid(_)));
+        // the rejection is kept as history
+        assert!(read_rejected_transaction(&host, &transaction.tx_hash)
+            .unwrap()
+            .is_some());
+    }
 }
@@ -9,6 +9,7 @@ use crate::apply::{apply_transaction, ExecutionInfo, ExecutionResult};
 use crate::blueprint_storage::{drop_head_blueprint, read_next_blueprint};
 use crate::error::Error;
 use crate::indexable_storage::IndexableStorage;
+use crate::rejected_transactions::clear_expired_rejections;
 use crate::safe_storage::KernelRuntime;
 use crate::storage;
 use crate::storage::init_account_index;
@@ -159,12 +160,17 @@ fn next_bip_from_blueprints<Host: Runtime>(
                     return Ok(None);
                 }
             }
+            // The rejections recorded in the slot of the new block are
+            // erased once, rather than by each rejected transaction.
+            let cleared_rejections =
+                clear_expired_rejections(host, current_block_number)?;
             let bip = block_in_progress::BlockInProgress::from_blueprint(
                 blueprint,
                 current_block_number,
                 current_block_parent_hash,
                 current_constants,
-                tick_counter.c,
+                tick_counter.c
+                    + tick_model::ticks_of_rejections_clearing(cleared_rejections),
             );
             Ok(Some(bip))
         }
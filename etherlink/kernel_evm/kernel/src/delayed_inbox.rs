@@ -328,6 +328,18 @@ impl DelayedInbox {
         })
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Removes the oldest item of the delayed inbox, if any.
+    pub fn pop_first<Host: Runtime>(
+        &mut self,
+        host: &mut Host,
+    ) -> Result<Option<DelayedInboxItem>> {
+        self.0.pop_first(host)
+    }
+
     pub fn delete<Host: Runtime>(&mut self, host: &mut Host) -> Result<()> {
         self.0.delete(host)
     }
@@ -63,6 +63,8 @@ pub enum UpgradeProcessError {
     Fallback,
     #[error("Missing dictator key")]
     NoDictator,
+    #[error("Storage migration cannot progress")]
+    StuckMigration,
 }
 
 #[derive(Error, Debug)]
@@ -13,7 +13,8 @@ use crate::storage::{
     chunked_transaction_path, clear_events, create_chunked_transaction,
     get_and_increment_deposit_nonce, read_last_info_per_level_timestamp,
     remove_chunked_transaction, remove_sequencer, store_l1_level,
-    store_last_info_per_level_timestamp, store_sequencer, store_transaction_chunk,
+    store_last_info_per_level_timestamp, store_logging_config, store_sequencer,
+    store_transaction_chunk,
 };
 use crate::upgrade::*;
 use crate::Error;
@@ -22,7 +23,7 @@ use primitive_types::{H160, U256};
 use rlp::{Decodable, DecoderError, Encodable};
 use sha3::{Digest, Keccak256};
 use tezos_crypto_rs::hash::ContractKt1Hash;
-use tezos_ethereum::rlp_helpers::{decode_field, decode_tx_hash, next};
+use tezos_ethereum::rlp_helpers::{decode_field, decode_list, decode_tx_hash, next};
 use tezos_ethereum::transaction::{TransactionHash, TRANSACTION_HASH_SIZE};
 use tezos_ethereum::tx_common::EthereumTransactionCommon;
 use tezos_evm_logging::{log, Level::*};
@@ -155,6 +156,32 @@ pub struct InboxContent {
     pub sequencer_blueprints: Vec<SequencerBlueprint>,
 }
 
+impl Encodable for InboxContent {
+    fn rlp_append(&self, stream: &mut rlp::RlpStream) {
+        stream.begin_list(2);
+        stream.append_list(&self.transactions);
+        stream.append_list(&self.sequencer_blueprints);
+    }
+}
+
+impl Decodable for InboxContent {
+    fn decode(decoder: &rlp::Rlp) -> Result<Self, DecoderError> {
+        if !decoder.is_list() {
+            return Err(DecoderError::RlpExpectedToBeList);
+        }
+        if decoder.item_count()? != 2 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+        let mut it = decoder.iter();
+        let transactions = decode_list(&next(&mut it)?, "transactions")?;
+        let sequencer_blueprints = decode_list(&next(&mut it)?, "sequencer_blueprints")?;
+        Ok(InboxContent {
+            transactions,
+            sequencer_blueprints,
+        })
+    }
+}
+
 pub fn read_input<Host: Runtime>(
     host: &mut Host,
     smart_rollup_address: [u8; 20],
@@ -302,6 +329,11 @@ pub fn handle_input(
             }
         }
         Input::Upgrade(kernel_upgrade) => store_kernel_upgrade(host, &kernel_upgrade)?,
+        Input::LoggingConfig(config) => {
+            store_logging_config(host, &config)?;
+            log!(host, Info, "Logging configuration updated: {:?}", config);
+            tezos_evm_logging::set_config(config)
+        }
         Input::RemoveSequencer => remove_sequencer(host)?,
         Input::NewSequencer(sequencer) => store_sequencer(host, sequencer)?,
         Input::Info(info) => {
@@ -428,13 +460,17 @@ mod tests {
     use tezos_crypto_rs::hash::SmartRollupHash;
     use tezos_data_encoding::types::Bytes;
     use tezos_ethereum::transaction::TRANSACTION_HASH_SIZE;
+    use tezos_evm_logging::{Config as LoggingConfig, Level};
     use tezos_smart_rollup_core::PREIMAGE_HASH_SIZE;
     use tezos_smart_rollup_encoding::contract::Contract;
-    use tezos_smart_rollup_encoding::inbox::ExternalMessageFrame;
+    use tezos_smart_rollup_encoding::inbox::{
+        ExternalMessageFrame, InboxMessage, InternalInboxMessage, Transfer,
+    };
     use tezos_smart_rollup_encoding::michelson::{MichelsonBytes, MichelsonOr};
     use tezos_smart_rollup_encoding::public_key_hash::PublicKeyHash;
     use tezos_smart_rollup_encoding::smart_rollup::SmartRollupAddress;
     use tezos_smart_rollup_encoding::timestamp::Timestamp;
+    use tezos_smart_rollup_host::input::Message;
     use tezos_smart_rollup_mock::{MockHost, TransferMetadata};
 
     const SMART_ROLLUP_ADDRESS: [u8; 20] = [
@@ -650,6 +686,103 @@ mod tests {
         assert_eq!(stored_kernel_upgrade, expected_upgrade);
     }
 
+    fn admin_contract() -> ContractKt1Hash {
+        match Contract::from_b58check("KT1HJphVV3LUxqZnc7YSH6Zdfd3up1DjLqZv").unwrap() {
+            Contract::Originated(kt1) => kt1,
+            _ => panic!("The contract must be a KT1"),
+        }
+    }
+
+    fn logging_config() -> LoggingConfig {
+        LoggingConfig {
+            level: Level::Error,
+            targets: vec![("evm_kernel::inbox".into(), Level::Info)],
+            structured: true,
+        }
+    }
+
+    // Parse a transfer of `bytes` from the administrator.
+    fn parse_admin_message(bytes: Vec<u8>) -> InputResult {
+        let admin = admin_contract();
+        let payload: RollupType = MichelsonOr::Right(MichelsonBytes(bytes));
+        let transfer = Transfer {
+            payload,
+            sender: admin.clone(),
+            source: PublicKeyHash::from_b58check("tz1NiaviJwtMbpEcNqSP6neeoBYj8Brb3QPv")
+                .unwrap(),
+            destination: smart_rollup_address(),
+        };
+        let mut message = Vec::new();
+        InboxMessage::Internal(InternalInboxMessage::Transfer(transfer))
+            .serialize(&mut message)
+            .unwrap();
+
+        InputResult::parse(
+            &mut MockHost::default(),
+            Message::new(0, 0, message),
+            SMART_ROLLUP_ADDRESS,
+            &TezosContracts {
+                ticketer: None,
+                admin: Some(admin),
+                sequencer_admin: None,
+            },
+            &None,
+            &None,
+        )
+    }
+
+    #[test]
+    fn parse_admin_messages() {
+        let config = logging_config();
+        assert_eq!(
+            parse_admin_message(config.rlp_bytes().to_vec()),
+            InputResult::Input(Input::LoggingConfig(config))
+        );
+
+        let kernel_upgrade = KernelUpgrade {
+            preimage_hash: [1; PREIMAGE_HASH_SIZE],
+            activation_timestamp: Timestamp::from(0i64),
+        };
+        assert_eq!(
+            parse_admin_message(kernel_upgrade.rlp_bytes().to_vec()),
+            InputResult::Input(Input::Upgrade(KernelUpgrade {
+                preimage_hash: [1; PREIMAGE_HASH_SIZE],
+                activation_timestamp: Timestamp::from(0i64),
+            }))
+        );
+    }
+
+    #[test]
+    fn parse_valid_logging_config() {
+        let mut host = MockHost::default();
+
+        let config = logging_config();
+        let payload: RollupType =
+            MichelsonOr::Right(MichelsonBytes(config.rlp_bytes().to_vec()));
+        let source =
+            PublicKeyHash::from_b58check("tz1NiaviJwtMbpEcNqSP6neeoBYj8Brb3QPv").unwrap();
+        let sender = admin_contract();
+
+        let transfer_metadata = TransferMetadata::new(sender.clone(), source);
+        host.add_transfer(payload, &transfer_metadata);
+        let _inbox_content = read_inbox(
+            &mut host,
+            [0; 20],
+            &TezosContracts {
+                ticketer: None,
+                admin: Some(sender),
+                sequencer_admin: None,
+            },
+            None,
+            None,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(Some(config), read_logging_config(&host).unwrap());
+        assert_eq!(None, crate::upgrade::read_kernel_upgrade(&host).unwrap());
+    }
+
     #[test]
     // Assert that trying to create a chunked transaction has no impact. Only
     // the first `NewChunkedTransaction` should be considered.
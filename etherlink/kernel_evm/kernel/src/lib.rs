@@ -7,10 +7,12 @@
 
 use crate::configuration::{fetch_configuration, Configuration};
 use crate::error::Error;
-use crate::error::UpgradeProcessError::Fallback;
+use crate::error::UpgradeProcessError::{Fallback, StuckMigration};
 use crate::migration::storage_migration;
-use crate::safe_storage::{InternalStorage, KernelRuntime, SafeStorage, TMP_PATH};
-use crate::stage_one::fetch;
+use crate::safe_storage::{
+    InternalRuntime, InternalStorage, KernelRuntime, SafeStorage, TMP_PATH,
+};
+use crate::stage_one::{apply_kernel_upgrade, fetch, fetch_pending_inputs};
 use crate::Error::UpgradeError;
 use anyhow::Context;
 use delayed_inbox::DelayedInbox;
@@ -46,6 +48,7 @@ mod linked_list;
 mod migration;
 mod mock_internal;
 mod parsing;
+mod rejected_transactions;
 mod safe_storage;
 mod sequencer_blueprint;
 mod simulation;
@@ -155,6 +158,19 @@ fn retrieve_block_fees<Host: Runtime>(host: &mut Host) -> Result<BlockFees, Erro
 }
 
 pub fn main<Host: KernelRuntime>(host: &mut Host) -> Result<(), anyhow::Error> {
+    // The logging configuration is reset at every kernel run. A malformed
+    // configuration must not prevent the kernel from running, the default
+    // one is used instead.
+    match storage::read_logging_config(host) {
+        Ok(config) => tezos_evm_logging::set_config(config.unwrap_or_default()),
+        Err(e) => log!(
+            host,
+            Error,
+            "Failed to read the logging configuration: {:?}",
+            e
+        ),
+    }
+
     let chain_id = retrieve_chain_id(host).context("Failed to retrieve chain id")?;
 
     // We always start by doing the migration if needed.
@@ -180,6 +196,9 @@ pub fn main<Host: KernelRuntime>(host: &mut Host) -> Result<(), anyhow::Error> {
             host.mark_for_reboot()?;
             return Ok(());
         }
+        // The migration resumes at the next level, the inputs of the current
+        // one are kept aside meanwhile.
+        MigrationStatus::OutOfReboots => return keep_inputs_aside(host),
     };
 
     // In the very worst case, we want to be able to upgrade the kernel at
@@ -213,6 +232,41 @@ pub fn main<Host: KernelRuntime>(host: &mut Host) -> Result<(), anyhow::Error> {
         .context("Failed during stage 2")
 }
 
+/// Kernel run replacing `main` when the storage migration cannot progress
+/// during the current level, the inbox is still consumed and kept aside.
+fn keep_inputs_aside<Host: KernelRuntime>(host: &mut Host) -> Result<(), anyhow::Error> {
+    let smart_rollup_address = Runtime::reveal_metadata(host).raw_rollup_address;
+    let configuration = fetch_configuration(host);
+    fetch_pending_inputs(host, smart_rollup_address, &configuration)
+        .context("Failed to keep the inputs aside during the migration")
+}
+
+/// Runs `step` on a fresh copy of the durable storage, which is promoted if
+/// the step succeeds, and discarded otherwise.
+fn run_in_temporary_storage<'a, 'b, Host: Runtime, Internal: InternalRuntime>(
+    host: &mut SafeStorage<&'a mut Host, &'b mut Internal>,
+    step: fn(
+        &mut SafeStorage<&'a mut Host, &'b mut Internal>,
+    ) -> Result<(), anyhow::Error>,
+) {
+    host.host
+        .store_copy(&EVM_PATH, &TMP_PATH)
+        .expect("The kernel failed to create the temporary directory");
+    match step(host) {
+        Ok(()) => {
+            host.promote_upgrade()
+                .expect("Potential kernel upgrade promotion failed");
+            host.promote(&EVM_PATH)
+                .expect("The kernel failed to promote the temporary directory")
+        }
+        Err(e) => {
+            log_error(host.host, &e).expect("The kernel failed to write the error");
+            host.revert()
+                .expect("The kernel failed to delete the temporary directory")
+        }
+    }
+}
+
 const EVM_PATH: RefPath = RefPath::assert_from(b"/evm");
 
 const ERRORS_PATH: RefPath = RefPath::assert_from(b"/errors");
@@ -234,6 +288,16 @@ fn log_error<Host: Runtime>(
 }
 
 pub fn kernel_loop<Host: Runtime>(host: &mut Host) {
+    let mut internal_storage = InternalStorage();
+    kernel_run(host, &mut internal_storage)
+}
+
+/// A kernel run, see [kernel_loop], the internal runtime being a parameter so
+/// that kernel runs can be tested.
+fn kernel_run<Host: Runtime, Internal: InternalRuntime>(
+    host: &mut Host,
+    internal: &mut Internal,
+) {
     // In order to setup the temporary directory, we need to move something
     // from /evm to /tmp, so /evm must be non empty, this only happen
     // at the first run.
@@ -248,11 +312,7 @@ pub fn kernel_loop<Host: Runtime>(host: &mut Host) {
     host.store_copy(&EVM_PATH, &TMP_PATH)
         .expect("The kernel failed to create the temporary directory");
 
-    let mut internal_storage = InternalStorage();
-    let mut host = SafeStorage {
-        host,
-        internal: &mut internal_storage,
-    };
+    let mut host = SafeStorage { host, internal };
     match main(&mut host) {
         Ok(()) => {
             host.promote_upgrade()
@@ -267,6 +327,18 @@ pub fn kernel_loop<Host: Runtime>(host: &mut Host) {
                     .expect("The kernel failed to delete the temporary directory");
                 host.fallback_backup_kernel()
                     .expect("Fallback mechanism failed");
+            } else if let Some(UpgradeError(StuckMigration)) = e.downcast_ref::<Error>() {
+                log_error(host.host, &e).expect("The kernel failed to write the error");
+                // The changes from the failed migration are reverted, and
+                // the inbox is consumed from the storage of the previous
+                // kernel run. The inputs are kept aside first, and a kernel
+                // upgrade, the only way to fix the migration, is applied in
+                // a separate step so that a failing upgrade does not drop
+                // them.
+                host.revert()
+                    .expect("The kernel failed to delete the temporary directory");
+                run_in_temporary_storage(&mut host, keep_inputs_aside);
+                run_in_temporary_storage(&mut host, apply_kernel_upgrade);
             } else {
                 log_error(host.host, &e).expect("The kernel failed to write the error");
                 log!(host, Error, "The kernel produced an error: {:?}", e);
@@ -310,6 +382,7 @@ mod tests {
         transaction::{TransactionHash, TransactionType},
         tx_common::EthereumTransactionCommon,
     };
+    use tezos_evm_logging::{Config as LoggingConfig, Level};
     use tezos_smart_rollup_core::{SmartRollupCore, PREIMAGE_HASH_SIZE};
     use tezos_smart_rollup_debug::Runtime;
     use tezos_smart_rollup_encoding::timestamp::Timestamp;
@@ -510,4 +583,80 @@ mod tests {
         // test reboot is set
         assert_marked_for_reboot(host.host)
     }
+
+    #[test]
+    fn inputs_are_kept_when_upgrade_fails_during_stuck_migration() {
+        const PENDING_INPUTS: RefPath = RefPath::assert_from(b"/evm/pending_inputs");
+        const KERNEL_UPGRADE: RefPath = RefPath::assert_from(b"/evm/kernel_upgrade");
+
+        let mut mock_host = MockHost::default();
+        // A kernel which cannot be installed.
+        let preimage_hash = mock_host.set_preimage(vec![0xff]);
+
+        let mut internal = MockInternal();
+        let mut host = SafeStorage {
+            host: &mut mock_host,
+            internal: &mut internal,
+        };
+        host.host
+            .store_copy(&crate::EVM_PATH, &crate::TMP_PATH)
+            .unwrap();
+        // A migration which made progress in a committed kernel run, and
+        // then fails.
+        storage::store_storage_version(&mut host, storage::STORAGE_VERSION - 1).unwrap();
+        host.store_write_all(
+            &RefPath::assert_from(b"/migration/origin"),
+            &(storage::STORAGE_VERSION - 1).to_le_bytes(),
+        )
+        .unwrap();
+        host.store_write_all(&RefPath::assert_from(b"/migration/progress"), &[0])
+            .unwrap();
+        let kernel_upgrade = KernelUpgrade {
+            preimage_hash,
+            activation_timestamp: Timestamp::from(0i64),
+        };
+        crate::upgrade::store_kernel_upgrade(&mut host, &kernel_upgrade).unwrap();
+        host.promote(&crate::EVM_PATH).unwrap();
+
+        crate::kernel_run(&mut mock_host, &mut MockInternal());
+
+        // The inputs of the level are kept aside although the kernel upgrade
+        // failed, and the upgrade is retried at the next level.
+        assert!(mock_host.store_has(&PENDING_INPUTS).unwrap().is_some());
+        assert!(mock_host.store_has(&KERNEL_UPGRADE).unwrap().is_some());
+        assert_eq!(
+            2,
+            mock_host.store_count_subkeys(&crate::ERRORS_PATH).unwrap()
+        );
+    }
+
+    #[test]
+    fn logging_config_is_applied_at_every_kernel_run() {
+        let mut mock_host = MockHost::default();
+        let mut internal = MockInternal();
+        let mut host = SafeStorage {
+            host: &mut mock_host,
+            internal: &mut internal,
+        };
+
+        let config = LoggingConfig {
+            level: Level::Error,
+            targets: vec![("evm_kernel::inbox".into(), Level::Info)],
+            structured: false,
+        };
+        storage::store_logging_config(&mut host, &config)
+            .expect("Should be able to store the logging config");
+        // The configuration set by a previous kernel run is not kept.
+        tezos_evm_logging::set_config(LoggingConfig::default());
+
+        // Only the configuration applied by the kernel run matters, not its
+        // outcome.
+        let _ = crate::main(&mut host);
+
+        assert!(!tezos_evm_logging::enabled(
+            Level::Info,
+            "evm_kernel::block"
+        ));
+        assert!(tezos_evm_logging::enabled(Level::Info, "evm_kernel::inbox"));
+    }
 }
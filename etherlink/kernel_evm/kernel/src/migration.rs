@@ -1,54 +1,465 @@
 // SPDX-FileCopyrightText: 2023 Functori <contact@functori.com>
-// SPDX-FileCopyrightText: 2023 Nomadic Labs <contact@nomadic-labs.com>
+// SPDX-FileCopyrightText: 2023-2024 Nomadic Labs <contact@nomadic-labs.com>
 //
 // SPDX-License-Identifier: MIT
 use crate::delayed_inbox::DelayedInbox;
 use crate::error::Error;
-use crate::error::UpgradeProcessError::Fallback;
+use crate::error::UpgradeProcessError::{Fallback, StuckMigration};
 use crate::storage::{read_storage_version, store_storage_version, STORAGE_VERSION};
-use tezos_smart_rollup_host::runtime::Runtime;
+use crate::tick_model::constants::{
+    MAX_ALLOWED_TICKS, SAFETY_MARGIN, TX_OBJ_TICKS_COEF, TX_OBJ_TICKS_INTERCEPT,
+};
+use rlp::Encodable;
+use tezos_evm_logging::{log, Level};
+use tezos_smart_rollup_core::MAX_INPUT_MESSAGE_SIZE;
+use tezos_smart_rollup_host::path::RefPath;
+use tezos_smart_rollup_host::runtime::{Runtime, RuntimeError};
 
 pub enum MigrationStatus {
     None,
     InProgress,
     Done,
+    /// The migration is started, but the level has not enough reboots left
+    /// to continue it. It resumes at the next level.
+    OutOfReboots,
+}
+
+/// Outcome of a kernel run of a migration step.
+#[derive(Debug, PartialEq)]
+pub enum StepStatus {
+    /// The step ran out of ticks, it resumes from its saved progress at the
+    /// next kernel run.
+    InProgress,
+    Done,
+}
+
+/// Ticks left to the migration in the current kernel run. Steps are expected
+/// to charge an overapproximation of the ticks of each unit of work, and to
+/// save their progress and stop once the budget is exhausted.
+pub struct TickBudget {
+    remaining: u64,
+}
+
+impl TickBudget {
+    pub fn new(ticks: u64) -> Self {
+        Self { remaining: ticks }
+    }
+
+    /// Whether `ticks` can still be spent in this kernel run.
+    pub fn allows(&self, ticks: u64) -> bool {
+        ticks <= self.remaining
+    }
+
+    pub fn consume(&mut self, ticks: u64) {
+        self.remaining = self.remaining.saturating_sub(ticks)
+    }
+}
+
+/// A migration of the storage from one version to the next.
+pub struct MigrationStep<Host> {
+    pub name: &'static str,
+    pub run: fn(&mut Host, &mut TickBudget) -> anyhow::Result<StepStatus>,
+}
+
+// Progress of the step being executed, its encoding is left to the step.
+const MIGRATION_PROGRESS: RefPath = RefPath::assert_from(b"/migration/progress");
+// Storage version the migration started from, written as soon as a migration
+// spans several kernel runs.
+const MIGRATION_ORIGIN: RefPath = RefPath::assert_from(b"/migration/origin");
+const MIGRATION: RefPath = RefPath::assert_from(b"/migration");
+
+// Ticks of a kernel run left to the steps. The bookkeeping of the migration
+// is not charged to the budget, and the steps only overapproximate their
+// work, hence the extra margin.
+const MIGRATION_TICKS: u64 = MAX_ALLOWED_TICKS - SAFETY_MARGIN;
+
+// Below this number of reboots left, a started migration stops for the
+// current level so that its inbox can still be consumed.
+const MIN_REBOOTS_LEFT: u32 = 1;
+
+// Upper bound of the size of an item of the delayed inbox: its transaction
+// was carried by a single inbox message.
+const MAX_DELAYED_INBOX_ITEM_SIZE: u64 = MAX_INPUT_MESSAGE_SIZE as u64;
+
+// Size of the pointers of the delayed inbox read or written when removing
+// an item: the pointers of the item and of the next one, the updated next
+// pointer, and the front and back pointers of the list. Each pointer is the
+// RLP encoding of at most three hashes, i.e. at most 105 bytes.
+const DELAYED_INBOX_POINTERS_SIZE: u64 = 5 * 105;
+
+// Overapproximation of the ticks of removing an item of the delayed inbox of
+// `size` bytes. Removing items has not been benchmarked, its cost is derived
+// from the model of storing transaction objects: each access to the durable
+// storage is counted as `TX_OBJ_TICKS_INTERCEPT`, and each byte read or
+// written as `TX_OBJ_TICKS_COEF`. Removing an item makes 9 accesses: reading
+// the pointers of the item and of the next one (4), reading the item (2) and
+// deleting it, and saving the next pointer and the list.
+fn ticks_of_delayed_inbox_item(size: u64) -> u64 {
+    (DELAYED_INBOX_POINTERS_SIZE + size)
+        .saturating_mul(TX_OBJ_TICKS_COEF)
+        .saturating_add(9 * TX_OBJ_TICKS_INTERCEPT)
+}
+
+/// Progress saved by the current step in a previous kernel run, if any.
+pub fn read_step_progress<Host: Runtime>(host: &Host) -> Result<Option<Vec<u8>>, Error> {
+    match host.store_read_all(&MIGRATION_PROGRESS) {
+        Ok(progress) => Ok(Some(progress)),
+        Err(RuntimeError::PathNotFound) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub fn store_step_progress<Host: Runtime>(
+    host: &mut Host,
+    progress: &[u8],
+) -> Result<(), Error> {
+    host.store_write_all(&MIGRATION_PROGRESS, progress)
+        .map_err(Error::from)
+}
+
+fn migration_started<Host: Runtime>(host: &Host) -> Result<bool, Error> {
+    Ok(host.store_has(&MIGRATION_ORIGIN)?.is_some())
+}
+
+// Flush the delayed inbox, oldest item first. The progress is the number
+// of items removed in the previous kernel runs.
+fn flush_delayed_inbox<Host: Runtime>(
+    host: &mut Host,
+    budget: &mut TickBudget,
+) -> anyhow::Result<StepStatus> {
+    let mut flushed = match read_step_progress(host)? {
+        Some(progress) => u64::from_le_bytes(progress.as_slice().try_into()?),
+        None => 0,
+    };
+    let mut delayed_inbox = DelayedInbox::new(host)?;
+    while !delayed_inbox.is_empty() {
+        // The size of the item is only known once it is read.
+        if !budget.allows(ticks_of_delayed_inbox_item(MAX_DELAYED_INBOX_ITEM_SIZE)) {
+            store_step_progress(host, &flushed.to_le_bytes())?;
+            return Ok(StepStatus::InProgress);
+        }
+        if let Some(item) = delayed_inbox.pop_first(host)? {
+            budget.consume(ticks_of_delayed_inbox_item(item.rlp_bytes().len() as u64));
+        }
+        flushed += 1;
+    }
+    delayed_inbox.delete(host)?;
+    log!(
+        host,
+        Level::Info,
+        "Flushed {} items of the delayed inbox.",
+        flushed
+    );
+    Ok(StepStatus::Done)
+}
+
+/// The step migrating the storage from `version` to `version + 1`.
+fn migration_step<Host: Runtime>(version: u64) -> Option<MigrationStep<Host>> {
+    match version {
+        4 => Some(MigrationStep {
+            name: "flush delayed inbox",
+            run: flush_delayed_inbox,
+        }),
+        _ => None,
+    }
 }
 
 // The workflow for migration is the following:
 //
 // - bump `storage::STORAGE_VERSION` by one
-// - fill the scope inside the conditional in `storage_migration` with all the
-//   needed migration functions
+// - register in `migration_step` the step migrating from the previous
+//   version. A step that cannot be done in a single kernel run charges its
+//   work to the tick budget, saves its progress with `store_step_progress`
+//   and returns `StepStatus::InProgress`. It is called again at the next
+//   kernel run, and resumes from `read_step_progress`.
 // - compile the kernel and run all the E2E migration tests to make sure all the
 //   data is still available from the EVM proxy-node.
 //
-// /!\
-//     If the migration takes more than 999 reboots, we will lose the inbox
-//     of a level. At least one reboot must be allocated to the stage one
-//     to consume the inbox. Therefore, if the migration happens to take more
-//     than 999 reboots, you have to rethink this. This limitation exists
-//     because we consider that the inbox should not be collected during
-//     a migration because it impacts the storage. We could in theory end up
-//     in an inconsistent storage.
-// /!\
+// Steps are applied in order, from the version found in the storage up to
+// `STORAGE_VERSION`, and the storage version is bumped after each of them.
 //
-fn migration<Host: Runtime>(host: &mut Host) -> anyhow::Result<MigrationStatus> {
-    let current_version = read_storage_version(host)?;
-    if STORAGE_VERSION == current_version + 1 {
-        // MIGRATION CODE - START
-        // Flush delayed inbox
-        let mut delayed_inbox = DelayedInbox::new(host)?;
-        delayed_inbox.delete(host)?;
-        // MIGRATION CODE - END
-        store_storage_version(host, STORAGE_VERSION)?;
-        return Ok(MigrationStatus::Done);
+// If the migration fails during its first kernel run, the upgrade is
+// reverted and the previous kernel is restored. Once a kernel run of the
+// migration has been committed, the backup kernel is gone and the storage
+// no longer matches the previous kernel. A failure then reverts the current
+// kernel run and fails with `StuckMigration`: the inbox of the level is
+// consumed, and the kernel upgrades are applied right away, so that a
+// kernel fixing the migration can be installed. The other inputs are kept
+// aside, and handled by stage one once the migration is done. The migration
+// is retried at the next level.
+//
+// The inbox should not be collected during a migration because it impacts
+// the storage, we could in theory end up in an inconsistent storage. A
+// started migration therefore stops with `MigrationStatus::OutOfReboots`
+// when the level runs out of reboots, and resumes at the next level. The
+// inputs of such a level are kept aside in the same way, including a kernel
+// upgrade, which is applied once the migration is done.
+//
+fn migrate<Host: Runtime>(
+    host: &mut Host,
+    target_version: u64,
+    step: impl Fn(u64) -> Option<MigrationStep<Host>>,
+    budget: &mut TickBudget,
+) -> anyhow::Result<MigrationStatus> {
+    let origin = read_storage_version(host)?;
+    if origin >= target_version {
+        return Ok(MigrationStatus::None);
     }
-    Ok(MigrationStatus::None)
+
+    if migration_started(host)? && host.reboot_left()? <= MIN_REBOOTS_LEFT {
+        log!(
+            host,
+            Level::Info,
+            "Not enough reboots left to continue the migration, it resumes at the next level."
+        );
+        return Ok(MigrationStatus::OutOfReboots);
+    }
+
+    let mut version = origin;
+    while version < target_version {
+        let MigrationStep { name, run } = step(version).ok_or_else(|| {
+            anyhow::anyhow!("No migration from storage version {}", version)
+        })?;
+        log!(
+            host,
+            Level::Info,
+            "Migrating storage from version {}: {}.",
+            version,
+            name
+        );
+        match run(host, budget)? {
+            StepStatus::InProgress => {
+                if !migration_started(host)? {
+                    host.store_write_all(&MIGRATION_ORIGIN, &origin.to_le_bytes())?;
+                }
+                return Ok(MigrationStatus::InProgress);
+            }
+            StepStatus::Done => {
+                if host.store_has(&MIGRATION_PROGRESS)?.is_some() {
+                    host.store_delete(&MIGRATION_PROGRESS)?;
+                }
+                version += 1;
+                store_storage_version(host, version)?;
+            }
+        }
+    }
+
+    if host.store_has(&MIGRATION)?.is_some() {
+        host.store_delete(&MIGRATION)?;
+    }
+    Ok(MigrationStatus::Done)
+}
+
+fn migration<Host: Runtime>(host: &mut Host) -> anyhow::Result<MigrationStatus> {
+    let mut budget = TickBudget::new(MIGRATION_TICKS);
+    migrate(host, STORAGE_VERSION, migration_step::<Host>, &mut budget)
 }
 
 pub fn storage_migration<Host: Runtime>(
     host: &mut Host,
 ) -> Result<MigrationStatus, Error> {
     let migration_result = migration(host);
-    migration_result.map_err(|_| Error::UpgradeError(Fallback))
+    migration_result.map_err(|err| migration_error(host, err))
+}
+
+fn migration_error<Host: Runtime>(host: &mut Host, err: anyhow::Error) -> Error {
+    log!(host, Level::Error, "Storage migration failed: {:?}", err);
+    match migration_started(host) {
+        // The previous kernel can no longer be restored.
+        Ok(true) => Error::UpgradeError(StuckMigration),
+        _ => Error::UpgradeError(Fallback),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delayed_inbox::DELAYED_INBOX_PATH;
+    use crate::inbox::{Deposit, Transaction, TransactionContent};
+    use primitive_types::{H160, U256};
+    use tezos_ethereum::transaction::TRANSACTION_HASH_SIZE;
+    use tezos_smart_rollup_encoding::timestamp::Timestamp;
+    use tezos_smart_rollup_mock::MockHost;
+
+    const ITEMS: RefPath = RefPath::assert_from(b"/items");
+    const TICKS_PER_ITEM: u64 = 10;
+    const NB_ITEMS: u64 = 5;
+
+    // Rewrites `NB_ITEMS` items, one per `TICKS_PER_ITEM` ticks.
+    fn rewrite_items(
+        host: &mut MockHost,
+        budget: &mut TickBudget,
+    ) -> anyhow::Result<StepStatus> {
+        let mut next = match read_step_progress(host)? {
+            Some(progress) => progress[0] as u64,
+            None => 0,
+        };
+        while next < NB_ITEMS {
+            if !budget.allows(TICKS_PER_ITEM) {
+                store_step_progress(host, &[next as u8])?;
+                return Ok(StepStatus::InProgress);
+            }
+            budget.consume(TICKS_PER_ITEM);
+            host.store_write(&ITEMS, &[1], next as usize)?;
+            next += 1;
+        }
+        Ok(StepStatus::Done)
+    }
+
+    fn noop(_: &mut MockHost, _: &mut TickBudget) -> anyhow::Result<StepStatus> {
+        Ok(StepStatus::Done)
+    }
+
+    // Saves some progress in its first kernel run, then fails.
+    fn fail_after_progress(
+        host: &mut MockHost,
+        _budget: &mut TickBudget,
+    ) -> anyhow::Result<StepStatus> {
+        match read_step_progress(host)? {
+            None => {
+                store_step_progress(host, &[0])?;
+                Ok(StepStatus::InProgress)
+            }
+            Some(_) => anyhow::bail!("The step failed"),
+        }
+    }
+
+    fn steps(version: u64) -> Option<MigrationStep<MockHost>> {
+        match version {
+            1 => Some(MigrationStep {
+                name: "noop",
+                run: noop,
+            }),
+            2 => Some(MigrationStep {
+                name: "rewrite items",
+                run: rewrite_items,
+            }),
+            _ => None,
+        }
+    }
+
+    fn failing_steps(version: u64) -> Option<MigrationStep<MockHost>> {
+        match version {
+            1 => Some(MigrationStep {
+                name: "fail after progress",
+                run: fail_after_progress,
+            }),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn migration_resumes_across_kernel_runs() {
+        let mut host = MockHost::default();
+        store_storage_version(&mut host, 1).unwrap();
+
+        let mut runs = 0;
+        loop {
+            runs += 1;
+            // Two items per kernel run.
+            let mut budget = TickBudget::new(2 * TICKS_PER_ITEM + 1);
+            match migrate(&mut host, 3, steps, &mut budget).unwrap() {
+                MigrationStatus::InProgress => {
+                    assert!(migration_started(&host).unwrap())
+                }
+                MigrationStatus::Done => break,
+                MigrationStatus::None | MigrationStatus::OutOfReboots => {
+                    panic!("Migration should be needed")
+                }
+            }
+        }
+
+        assert_eq!(3, runs);
+        assert_eq!(3, read_storage_version(&mut host).unwrap());
+        assert_eq!(
+            vec![1; NB_ITEMS as usize],
+            host.store_read_all(&ITEMS).unwrap()
+        );
+        assert!(host.store_has(&MIGRATION).unwrap().is_none());
+
+        let mut budget = TickBudget::new(0);
+        assert!(matches!(
+            migrate(&mut host, 3, steps, &mut budget).unwrap(),
+            MigrationStatus::None
+        ));
+    }
+
+    #[test]
+    fn missing_step_fails_the_migration() {
+        let mut host = MockHost::default();
+        store_storage_version(&mut host, 0).unwrap();
+
+        let mut budget = TickBudget::new(MAX_ALLOWED_TICKS);
+        let err = migrate(&mut host, 3, steps, &mut budget).unwrap_err();
+        assert!(matches!(
+            migration_error(&mut host, err),
+            Error::UpgradeError(Fallback)
+        ));
+    }
+
+    #[test]
+    fn step_failing_after_a_committed_run_is_stuck() {
+        let mut host = MockHost::default();
+        store_storage_version(&mut host, 1).unwrap();
+        let mut budget = TickBudget::new(MAX_ALLOWED_TICKS);
+        assert!(matches!(
+            migrate(&mut host, 2, failing_steps, &mut budget).unwrap(),
+            MigrationStatus::InProgress
+        ));
+
+        // The previous kernel cannot be restored, the kernel upgrades are
+        // fetched instead and the migration is retried at every level.
+        for _ in 0..2 {
+            let mut budget = TickBudget::new(MAX_ALLOWED_TICKS);
+            let err = migrate(&mut host, 2, failing_steps, &mut budget).unwrap_err();
+            assert!(matches!(
+                migration_error(&mut host, err),
+                Error::UpgradeError(StuckMigration)
+            ));
+            assert!(migration_started(&host).unwrap());
+            assert_eq!(Some(vec![0]), read_step_progress(&host).unwrap());
+            assert_eq!(1, read_storage_version(&mut host).unwrap());
+        }
+    }
+
+    #[test]
+    fn delayed_inbox_flushed_across_kernel_runs() {
+        let mut host = MockHost::default();
+        let mut delayed_inbox = DelayedInbox::new(&mut host).unwrap();
+        for i in 0..3 {
+            let deposit = Transaction {
+                tx_hash: [i; TRANSACTION_HASH_SIZE],
+                content: TransactionContent::Deposit(Deposit {
+                    amount: U256::one(),
+                    receiver: H160::zero(),
+                }),
+            };
+            delayed_inbox
+                .save_transaction(&mut host, deposit, Timestamp::from(0i64), 0)
+                .unwrap();
+        }
+
+        // A single item fits the budget, as items are bounded by their
+        // maximum size before being read.
+        let max_item_ticks = ticks_of_delayed_inbox_item(MAX_DELAYED_INBOX_ITEM_SIZE);
+        let mut budget = TickBudget::new(max_item_ticks + 1);
+        assert_eq!(
+            StepStatus::InProgress,
+            flush_delayed_inbox(&mut host, &mut budget).unwrap()
+        );
+        assert_eq!(
+            Some(1u64.to_le_bytes().to_vec()),
+            read_step_progress(&host).unwrap()
+        );
+        assert!(!DelayedInbox::new(&mut host).unwrap().is_empty());
+
+        // The items are charged their actual size once read, which is far
+        // below the maximum for deposits.
+        let mut budget = TickBudget::new(2 * max_item_ticks);
+        assert_eq!(
+            StepStatus::Done,
+            flush_delayed_inbox(&mut host, &mut budget).unwrap()
+        );
+        assert!(host.store_has(&DELAYED_INBOX_PATH).unwrap().is_none());
+    }
 }
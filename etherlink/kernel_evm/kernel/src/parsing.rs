@@ -21,7 +21,7 @@ use tezos_ethereum::{
     tx_common::EthereumTransactionCommon,
     wei::eth_from_mutez,
 };
-use tezos_evm_logging::{log, Level::*};
+use tezos_evm_logging::{log, Config as LoggingConfig, Level::*};
 use tezos_smart_rollup_encoding::{
     contract::Contract,
     inbox::{
@@ -89,6 +89,7 @@ pub enum Input {
     SimpleTransaction(Box<Transaction>),
     Deposit(Deposit),
     Upgrade(KernelUpgrade),
+    LoggingConfig(LoggingConfig),
     RemoveSequencer,
     NewSequencer(PublicKey),
     NewChunkedTransaction {
@@ -200,6 +201,16 @@ impl InputResult {
         Self::Input(Input::Upgrade(kernel_upgrade))
     }
 
+    fn parse_admin_message(bytes: &[u8]) -> Self {
+        // Kernel upgrades and logging configurations are RLP lists of
+        // respectively 2 and 3 elements, they cannot be mistaken for one
+        // another.
+        match LoggingConfig::from_rlp_bytes(bytes) {
+            Ok(config) => Self::Input(Input::LoggingConfig(config)),
+            Err(_) => Self::parse_kernel_upgrade(bytes),
+        }
+    }
+
     fn parse_sequencer_update(bytes: &[u8]) -> Self {
         if bytes.is_empty() {
             Self::Input(Input::RemoveSequencer)
@@ -376,7 +387,7 @@ impl InputResult {
             },
             MichelsonOr::Right(MichelsonBytes(bytes)) => {
                 if tezos_contracts.is_admin(&source) {
-                    Self::parse_kernel_upgrade(&bytes)
+                    Self::parse_admin_message(&bytes)
                 } else if tezos_contracts.is_sequencer_admin(&source) {
                     Self::parse_sequencer_update(&bytes)
                 } else {
@@ -0,0 +1,362 @@
+// SPDX-FileCopyrightText: 2024 Nomadic Labs <contact@nomadic-labs.com>
+//
+// SPDX-License-Identifier: MIT
+
+//! Index of the transactions rejected during block production, so that the
+//! node can tell users why their transaction was dropped.
+//!
+//! Rejections are indexed by transaction hash, and grouped by block in
+//! [RETAINED_BLOCKS] slots: the rejections of a block reuse the slot of the
+//! block [RETAINED_BLOCKS] before, which are erased by
+//! [clear_expired_rejections] once the production of the block starts. At
+//! most [MAX_REJECTIONS_PER_BLOCK] rejections are recorded per block.
+//!
+//! Rejections are history: a transaction included later under the same hash,
+//! e.g. once its sender has been funded, keeps its rejection until it expires.
+
+use crate::apply::Validity;
+use crate::error::Error;
+use crate::storage::{read_rlp, read_u256, store_rlp, write_u256};
+use primitive_types::U256;
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use tezos_ethereum::rlp_helpers::{
+    append_u256_le, decode_field, decode_field_u256_le, next,
+};
+use tezos_ethereum::transaction::{TransactionHash, TRANSACTION_HASH_SIZE};
+use tezos_evm_logging::{log, Level::*};
+use tezos_smart_rollup_host::path::{concat, OwnedPath, RefPath};
+use tezos_smart_rollup_host::runtime::{Runtime, RuntimeError};
+
+/// Number of block slots in the index.
+pub const RETAINED_BLOCKS: u64 = 128;
+
+/// Maximum number of rejections recorded for a single block.
+pub const MAX_REJECTIONS_PER_BLOCK: u64 = 128;
+
+const REJECTED_BY_HASH: RefPath = RefPath::assert_from(b"/rejected_transactions/by_hash");
+const REJECTED_BY_BLOCK: RefPath =
+    RefPath::assert_from(b"/rejected_transactions/by_block");
+const BLOCK_NUMBER: RefPath = RefPath::assert_from(b"/number");
+// Hashes of the rejected transactions of the block, concatenated.
+const BLOCK_HASHES: RefPath = RefPath::assert_from(b"/hashes");
+
+/// Why and when a transaction was rejected.
+#[derive(Debug, PartialEq)]
+pub struct RejectedTransaction {
+    pub reason: Validity,
+    pub block_number: U256,
+}
+
+fn reason_tag(reason: &Validity) -> Option<u8> {
+    match reason {
+        Validity::Valid(..) => None,
+        Validity::InvalidChainId => Some(1),
+        Validity::InvalidSignature => Some(2),
+        Validity::InvalidNonce => Some(3),
+        Validity::InvalidPrePay => Some(4),
+        Validity::InvalidCode => Some(5),
+        Validity::InvalidMaxBaseFee => Some(6),
+        Validity::InvalidNotEnoughGasForFees => Some(7),
+    }
+}
+
+fn reason_from_tag(tag: u8) -> Result<Validity, DecoderError> {
+    match tag {
+        1 => Ok(Validity::InvalidChainId),
+        2 => Ok(Validity::InvalidSignature),
+        3 => Ok(Validity::InvalidNonce),
+        4 => Ok(Validity::InvalidPrePay),
+        5 => Ok(Validity::InvalidCode),
+        6 => Ok(Validity::InvalidMaxBaseFee),
+        7 => Ok(Validity::InvalidNotEnoughGasForFees),
+        _ => Err(DecoderError::Custom("Unknown rejection reason")),
+    }
+}
+
+impl Encodable for RejectedTransaction {
+    fn rlp_append(&self, stream: &mut RlpStream) {
+        stream.begin_list(2);
+        // Valid transactions are never recorded.
+        stream.append(&reason_tag(&self.reason).unwrap_or_default());
+        append_u256_le(stream, &self.block_number);
+    }
+}
+
+impl Decodable for RejectedTransaction {
+    fn decode(decoder: &Rlp) -> Result<Self, DecoderError> {
+        if !decoder.is_list() {
+            return Err(DecoderError::RlpExpectedToBeList);
+        }
+        if decoder.item_count()? != 2 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+
+        let mut it = decoder.iter();
+        let tag: u8 = decode_field(&next(&mut it)?, "reason")?;
+        let reason = reason_from_tag(tag)?;
+        let block_number = decode_field_u256_le(&next(&mut it)?, "block_number")?;
+
+        Ok(Self {
+            reason,
+            block_number,
+        })
+    }
+}
+
+fn rejected_path(tx_hash: &TransactionHash) -> Result<OwnedPath, Error> {
+    let hash = hex::encode(tx_hash);
+    let raw_path: Vec<u8> = format!("/{}", &hash).into();
+    let path = OwnedPath::try_from(raw_path)?;
+    concat(&REJECTED_BY_HASH, &path).map_err(Error::from)
+}
+
+fn block_slot_path(block_number: U256) -> Result<OwnedPath, Error> {
+    let slot = (block_number % RETAINED_BLOCKS).as_u64();
+    let raw_path: Vec<u8> = format!("/{}", slot).into();
+    let path = OwnedPath::try_from(raw_path)?;
+    concat(&REJECTED_BY_BLOCK, &path).map_err(Error::from)
+}
+
+/// Number of rejections recorded in the block slot.
+fn slot_rejections<Host: Runtime>(
+    host: &Host,
+    hashes_path: &OwnedPath,
+) -> Result<u64, Error> {
+    match host.store_value_size(hashes_path) {
+        Ok(size) => Ok((size / TRANSACTION_HASH_SIZE) as u64),
+        Err(RuntimeError::PathNotFound) => Ok(0),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Erase the rejections of the block previously using the slot, and return
+/// their number.
+fn clear_slot<Host: Runtime>(
+    host: &mut Host,
+    slot_path: &OwnedPath,
+) -> Result<u64, Error> {
+    if host.store_has(slot_path)?.is_none() {
+        return Ok(0);
+    }
+
+    let hashes_path = concat(slot_path, &BLOCK_HASHES)?;
+    let rejections = slot_rejections(host, &hashes_path)?;
+    if rejections > 0 {
+        let slot_number = read_u256(host, &concat(slot_path, &BLOCK_NUMBER)?)?;
+        let hashes = host.store_read_all(&hashes_path)?;
+        for tx_hash in hashes.chunks_exact(TRANSACTION_HASH_SIZE) {
+            let tx_hash: TransactionHash = tx_hash.try_into().unwrap();
+            // The transaction may have been rejected again since, in a more
+            // recent block.
+            match read_rejected_transaction(host, &tx_hash)? {
+                Some(rejection) if rejection.block_number == slot_number => {
+                    host.store_delete(&rejected_path(&tx_hash)?)?
+                }
+                _ => (),
+            }
+        }
+    }
+    host.store_delete(slot_path)?;
+    Ok(rejections)
+}
+
+/// Erase the rejections of the block which previously used the slot of the
+/// block `block_number`, unless the slot already belongs to it, and return
+/// the number of erased rejections.
+///
+/// This reads and deletes up to [MAX_REJECTIONS_PER_BLOCK] rejections, and is
+/// done once per block, so that recording a rejection stays cheap.
+pub fn clear_expired_rejections<Host: Runtime>(
+    host: &mut Host,
+    block_number: U256,
+) -> Result<u64, Error> {
+    let slot_path = block_slot_path(block_number)?;
+    let number_path = concat(&slot_path, &BLOCK_NUMBER)?;
+    if host.store_has(&number_path)?.is_some()
+        && read_u256(host, &number_path)? == block_number
+    {
+        return Ok(0);
+    }
+    clear_slot(host, &slot_path)
+}
+
+/// Record that the transaction was rejected in the block `block_number`, for
+/// the given reason. Valid transactions are ignored, and so are rejections
+/// while the slot of the block has not been cleared by
+/// [clear_expired_rejections].
+pub fn store_rejected_transaction<Host: Runtime>(
+    host: &mut Host,
+    tx_hash: &TransactionHash,
+    reason: Validity,
+    block_number: U256,
+) -> Result<(), Error> {
+    if reason_tag(&reason).is_none() {
+        return Ok(());
+    }
+
+    let slot_path = block_slot_path(block_number)?;
+    let number_path = concat(&slot_path, &BLOCK_NUMBER)?;
+    if host.store_has(&number_path)?.is_some()
+        && read_u256(host, &number_path)? != block_number
+    {
+        log!(
+            host,
+            Debug,
+            "Rejections of block {} have not been cleared, {} is not recorded.",
+            block_number,
+            hex::encode(tx_hash)
+        );
+        return Ok(());
+    }
+
+    let hashes_path = concat(&slot_path, &BLOCK_HASHES)?;
+    let rejections = slot_rejections(host, &hashes_path)?;
+    if rejections >= MAX_REJECTIONS_PER_BLOCK {
+        log!(
+            host,
+            Debug,
+            "Too many rejected transactions in block {}, {} is not recorded.",
+            block_number,
+            hex::encode(tx_hash)
+        );
+        return Ok(());
+    }
+
+    let rejection = RejectedTransaction {
+        reason,
+        block_number,
+    };
+    store_rlp(&rejection, host, &rejected_path(tx_hash)?)?;
+    host.store_write(
+        &hashes_path,
+        tx_hash,
+        rejections as usize * TRANSACTION_HASH_SIZE,
+    )?;
+    write_u256(host, &number_path, block_number)
+}
+
+/// The reason why the transaction was rejected, if it was rejected in one of
+/// the last blocks.
+pub fn read_rejected_transaction<Host: Runtime>(
+    host: &Host,
+    tx_hash: &TransactionHash,
+) -> Result<Option<RejectedTransaction>, Error> {
+    let path = rejected_path(tx_hash)?;
+    if host.store_has(&path)?.is_some() {
+        read_rlp(host, &path).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tezos_smart_rollup_mock::MockHost;
+
+    #[test]
+    fn rejected_transaction_is_recorded() {
+        let mut host = MockHost::default();
+        let tx_hash = [1; TRANSACTION_HASH_SIZE];
+
+        store_rejected_transaction(
+            &mut host,
+            &tx_hash,
+            Validity::InvalidNonce,
+            U256::from(3),
+        )
+        .unwrap();
+
+        assert_eq!(
+            Some(RejectedTransaction {
+                reason: Validity::InvalidNonce,
+                block_number: U256::from(3)
+            }),
+            read_rejected_transaction(&host, &tx_hash).unwrap()
+        );
+        assert_eq!(
+            None,
+            read_rejected_transaction(&host, &[2; TRANSACTION_HASH_SIZE]).unwrap()
+        );
+    }
+
+    #[test]
+    fn old_rejections_are_erased() {
+        let mut host = MockHost::default();
+        let old_tx = [1; TRANSACTION_HASH_SIZE];
+        let retried_tx = [2; TRANSACTION_HASH_SIZE];
+        let new_tx = [3; TRANSACTION_HASH_SIZE];
+
+        for tx_hash in [&old_tx, &retried_tx] {
+            store_rejected_transaction(
+                &mut host,
+                tx_hash,
+                Validity::InvalidPrePay,
+                U256::from(1),
+            )
+            .unwrap();
+        }
+        store_rejected_transaction(
+            &mut host,
+            &retried_tx,
+            Validity::InvalidNonce,
+            U256::from(2),
+        )
+        .unwrap();
+
+        // The block reuses the slot of block 1.
+        let block_number = U256::from(1 + RETAINED_BLOCKS);
+        assert_eq!(
+            2,
+            clear_expired_rejections(&mut host, block_number).unwrap()
+        );
+        // Clearing again erases nothing.
+        assert_eq!(
+            0,
+            clear_expired_rejections(&mut host, block_number).unwrap()
+        );
+        store_rejected_transaction(
+            &mut host,
+            &new_tx,
+            Validity::InvalidChainId,
+            block_number,
+        )
+        .unwrap();
+
+        assert_eq!(None, read_rejected_transaction(&host, &old_tx).unwrap());
+        assert_eq!(
+            Some(U256::from(2)),
+            read_rejected_transaction(&host, &retried_tx)
+                .unwrap()
+                .map(|rejection| rejection.block_number)
+        );
+        assert!(read_rejected_transaction(&host, &new_tx).unwrap().is_some());
+    }
+
+    #[test]
+    fn rejections_are_not_recorded_in_expired_slot() {
+        let mut host = MockHost::default();
+        let old_tx = [1; TRANSACTION_HASH_SIZE];
+        let new_tx = [2; TRANSACTION_HASH_SIZE];
+
+        store_rejected_transaction(
+            &mut host,
+            &old_tx,
+            Validity::InvalidPrePay,
+            U256::from(1),
+        )
+        .unwrap();
+
+        // The slot of block 1 has not been cleared for the block.
+        store_rejected_transaction(
+            &mut host,
+            &new_tx,
+            Validity::InvalidNonce,
+            U256::from(1 + RETAINED_BLOCKS),
+        )
+        .unwrap();
+
+        assert!(read_rejected_transaction(&host, &old_tx).unwrap().is_some());
+        assert_eq!(None, read_rejected_transaction(&host, &new_tx).unwrap());
+    }
+}
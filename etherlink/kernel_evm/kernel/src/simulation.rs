@@ -8,6 +8,7 @@
 // when the proxy node simulates directly
 
 use crate::fees::{simulation_add_gas_for_fees, tx_execution_gas_limit};
+use crate::rejected_transactions::{read_rejected_transaction, RejectedTransaction};
 use crate::{error::Error, error::StorageError, storage};
 
 use crate::{
@@ -19,9 +20,10 @@ use evm_execution::handler::ExtendedExitReason;
 use evm_execution::{account_storage, handler::ExecutionOutcome, precompiles};
 use evm_execution::{run_transaction, EthereumError};
 use primitive_types::{H160, U256};
-use rlp::{Decodable, DecoderError, Rlp};
+use rlp::{Decodable, DecoderError, Encodable, Rlp};
 use tezos_ethereum::block::BlockConstants;
 use tezos_ethereum::rlp_helpers::{decode_field, decode_option, next};
+use tezos_ethereum::transaction::TransactionHash;
 use tezos_ethereum::tx_common::EthereumTransactionCommon;
 use tezos_evm_logging::{log, Level::*};
 use tezos_smart_rollup_host::runtime::Runtime;
@@ -36,6 +38,9 @@ pub const SIMULATION_CHUNK_TAG: u8 = 3;
 pub const EVALUATION_TAG: u8 = 0x00;
 /// Tag indicating simulation is a validation.
 pub const VALIDATION_TAG: u8 = 0x01;
+/// Tag indicating simulation is a query of why a transaction was rejected.
+/// The message is followed by the hash of the transaction.
+pub const REJECTION_TAG: u8 = 0x02;
 
 /// Container for eth_call data, used in messages sent by the rollup node
 /// simulation.
@@ -333,6 +338,7 @@ impl TryFrom<&[u8]> for TxValidation {
 enum Message {
     Evaluation(Evaluation),
     TxValidation(Box<TxValidation>),
+    Rejection(TransactionHash),
 }
 
 impl TryFrom<&[u8]> for Message {
@@ -346,6 +352,10 @@ impl TryFrom<&[u8]> for Message {
             EVALUATION_TAG => Evaluation::try_from(bytes).map(Message::Evaluation),
             VALIDATION_TAG => TxValidation::try_from(bytes)
                 .map(|tx| Message::TxValidation(Box::new(tx))),
+            REJECTION_TAG => bytes
+                .try_into()
+                .map(Message::Rejection)
+                .map_err(|_| DecoderError::RlpInvalidLength),
             _ => Err(DecoderError::Custom("Unknown message to simulate")),
         }
     }
@@ -521,6 +531,27 @@ fn store_tx_validation_outcome<Host: Runtime>(
     }
 }
 
+// The rejection is stored RLP encoded, as the list of the tag of the
+// `Validity` reason and of the block number.
+fn store_rejection_outcome<Host: Runtime>(
+    host: &mut Host,
+    rejection: Option<RejectedTransaction>,
+) -> Result<(), anyhow::Error> {
+    match rejection {
+        Some(rejection) => {
+            storage::store_simulation_status(host, true)?;
+            storage::store_simulation_result(host, Some(rejection.rlp_bytes().to_vec()))
+        }
+        None => {
+            storage::store_simulation_status(host, false)?;
+            storage::store_simulation_result(
+                host,
+                Some(b"The transaction was not rejected in the last blocks.".to_vec()),
+            )
+        }
+    }
+}
+
 pub fn start_simulation_mode<Host: Runtime>(
     host: &mut Host,
 ) -> Result<(), anyhow::Error> {
@@ -535,6 +566,10 @@ pub fn start_simulation_mode<Host: Runtime>(
             let outcome = tx_validation.run(host)?;
             store_tx_validation_outcome(host, outcome)
         }
+        Message::Rejection(tx_hash) => {
+            let rejection = read_rejected_transaction(host, &tx_hash)?;
+            store_rejection_outcome(host, rejection)
+        }
     }
 }
 
@@ -937,6 +972,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_rejection_query() {
+        let tx_hash = [7; 32];
+        let mut input = vec![
+            parsing::SIMULATION_TAG,
+            SIMULATION_SIMPLE_TAG,
+            REJECTION_TAG,
+        ];
+        input.extend_from_slice(&tx_hash);
+
+        assert_eq!(
+            Input::Simple(Box::new(Message::Rejection(tx_hash))),
+            Input::parse(&input),
+            "should have been parsed as a rejection query"
+        );
+    }
+
     fn address_from_str(s: &str) -> Option<H160> {
         let data = &hex::decode(s).unwrap();
         Some(H160::from_slice(data))
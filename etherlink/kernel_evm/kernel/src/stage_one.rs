@@ -6,39 +6,148 @@ use crate::blueprint::Blueprint;
 use crate::blueprint_storage::{
     store_immediate_blueprint, store_inbox_blueprint, store_sequencer_blueprint,
 };
-use crate::configuration::{Configuration, ConfigurationMode, TezosContracts};
+use crate::configuration::{Configuration, ConfigurationMode};
 use crate::current_timestamp;
 use crate::delayed_inbox::DelayedInbox;
+use crate::error::StorageError;
 use crate::inbox::read_inbox;
 use crate::inbox::InboxContent;
 use crate::read_last_info_per_level_timestamp;
-use crate::storage::read_l1_level;
+use crate::storage::{read_l1_level, read_rlp, store_read_slice, store_rlp};
+use crate::upgrade;
 use anyhow::Ok;
 use std::ops::Add;
-use tezos_crypto_rs::hash::ContractKt1Hash;
 use tezos_evm_logging::{log, Level::*};
-use tezos_smart_rollup_encoding::public_key::PublicKey;
 use tezos_smart_rollup_host::metadata::RAW_ROLLUP_ADDRESS_SIZE;
+use tezos_smart_rollup_host::path::{concat, OwnedPath, RefPath};
 
 use tezos_smart_rollup_host::runtime::Runtime;
 
-pub fn fetch_inbox_blueprints<Host: Runtime>(
+// Inputs of the levels consumed while the storage migration could not
+// progress, stored as an `InboxContent` per level under its index. They are
+// handled in order once the migration is done, see `fetch`.
+const PENDING_INPUTS: RefPath = RefPath::assert_from(b"/pending_inputs");
+// Index of the oldest pending level.
+const PENDING_INPUTS_HEAD: RefPath = RefPath::assert_from(b"/pending_inputs/head");
+// Index of the next pending level to be stored.
+const PENDING_INPUTS_TAIL: RefPath = RefPath::assert_from(b"/pending_inputs/tail");
+
+fn pending_inputs_path(index: u64) -> Result<OwnedPath, StorageError> {
+    let index_as_path: Vec<u8> = format!("/{}", index).into();
+    // The key being an integer value, it will always be valid as a path,
+    // `assert_from` cannot fail.
+    let index_subkey = RefPath::assert_from(&index_as_path);
+    concat(&PENDING_INPUTS, &index_subkey).map_err(StorageError::from)
+}
+
+fn read_pending_inputs_index<Host: Runtime>(
+    host: &Host,
+    path: &RefPath,
+) -> Result<u64, anyhow::Error> {
+    if host.store_has(path)?.is_none() {
+        return Ok(0);
+    }
+    let mut buffer = [0u8; 8];
+    store_read_slice(host, path, &mut buffer, 8)?;
+    Ok(u64::from_le_bytes(buffer))
+}
+
+fn has_pending_inputs<Host: Runtime>(host: &Host) -> Result<bool, anyhow::Error> {
+    Ok(host.store_has(&PENDING_INPUTS)?.is_some())
+}
+
+fn push_pending_inputs<Host: Runtime>(
     host: &mut Host,
-    smart_rollup_address: [u8; RAW_ROLLUP_ADDRESS_SIZE],
-    tezos_contracts: &TezosContracts,
+    inbox_content: &InboxContent,
 ) -> Result<(), anyhow::Error> {
-    if let Some(InboxContent {
+    let tail = read_pending_inputs_index(host, &PENDING_INPUTS_TAIL)?;
+    store_rlp(inbox_content, host, &pending_inputs_path(tail)?)?;
+    host.store_write_all(&PENDING_INPUTS_TAIL, &(tail + 1).to_le_bytes())?;
+    Ok(())
+}
+
+fn pop_pending_inputs<Host: Runtime>(
+    host: &mut Host,
+) -> Result<Option<InboxContent>, anyhow::Error> {
+    let head = read_pending_inputs_index(host, &PENDING_INPUTS_HEAD)?;
+    let tail = read_pending_inputs_index(host, &PENDING_INPUTS_TAIL)?;
+    if head >= tail {
+        return Ok(None);
+    }
+    let path = pending_inputs_path(head)?;
+    let inbox_content = read_rlp(host, &path)?;
+    if head + 1 == tail {
+        host.store_delete(&PENDING_INPUTS)?;
+    } else {
+        host.store_delete(&path)?;
+        host.store_write_all(&PENDING_INPUTS_HEAD, &(head + 1).to_le_bytes())?;
+    }
+    Ok(Some(inbox_content))
+}
+
+fn read_inbox_content<Host: Runtime>(
+    host: &mut Host,
+    smart_rollup_address: [u8; RAW_ROLLUP_ADDRESS_SIZE],
+    config: &Configuration,
+) -> Result<Option<InboxContent>, anyhow::Error> {
+    let (delayed_bridge, sequencer) = match &config.mode {
+        ConfigurationMode::Sequencer {
+            delayed_bridge,
+            sequencer,
+            ..
+        } => (Some(delayed_bridge.clone()), Some(sequencer.clone())),
+        ConfigurationMode::Proxy => (None, None),
+    };
+    read_inbox(
+        host,
+        smart_rollup_address,
+        &config.tezos_contracts,
+        delayed_bridge,
+        sequencer,
+    )
+}
+
+fn store_proxy_inputs<Host: Runtime>(
+    host: &mut Host,
+    InboxContent {
         transactions,
         sequencer_blueprints: _,
-    }) = read_inbox(host, smart_rollup_address, tezos_contracts, None, None)?
-    {
-        let timestamp = current_timestamp(host);
-        let blueprint = Blueprint {
-            transactions,
-            timestamp,
-        };
-        // Store the blueprint.
-        store_inbox_blueprint(host, blueprint)?;
+    }: InboxContent,
+) -> Result<(), anyhow::Error> {
+    let timestamp = current_timestamp(host);
+    let blueprint = Blueprint {
+        transactions,
+        timestamp,
+    };
+    // Store the blueprint.
+    store_inbox_blueprint(host, blueprint)?;
+    Ok(())
+}
+
+/// Consumes the inbox while the storage migration cannot progress. The
+/// inputs cannot be applied on a storage being migrated, they are kept aside
+/// and handled by [fetch] once the migration is done. A kernel upgrade is
+/// stored as usual, see [apply_kernel_upgrade].
+pub fn fetch_pending_inputs<Host: Runtime>(
+    host: &mut Host,
+    smart_rollup_address: [u8; RAW_ROLLUP_ADDRESS_SIZE],
+    config: &Configuration,
+) -> Result<(), anyhow::Error> {
+    if let Some(inbox_content) = read_inbox_content(host, smart_rollup_address, config)? {
+        push_pending_inputs(host, &inbox_content)?;
+    }
+    Ok(())
+}
+
+/// Applies the stored kernel upgrade while the storage migration is stuck,
+/// as it is the only way to fix the migration. As during block production,
+/// the upgrade is only applied once its activation timestamp is reached,
+/// using the timestamp a blueprint of the level would have.
+pub fn apply_kernel_upgrade<Host: Runtime>(host: &mut Host) -> Result<(), anyhow::Error> {
+    if let Some(kernel_upgrade) = upgrade::read_kernel_upgrade(host)? {
+        if current_timestamp(host) >= kernel_upgrade.activation_timestamp {
+            upgrade::upgrade(host, kernel_upgrade.preimage_hash)?;
+        }
     }
     Ok(())
 }
@@ -82,76 +191,219 @@ fn fetch_timed_out_transactions<Host: Runtime>(
     Ok(())
 }
 
-fn fetch_sequencer_blueprints<Host: Runtime>(
+fn store_sequencer_inputs<Host: Runtime>(
     host: &mut Host,
-    smart_rollup_address: [u8; RAW_ROLLUP_ADDRESS_SIZE],
-    tezos_contracts: &TezosContracts,
-    delayed_bridge: ContractKt1Hash,
     delayed_inbox: &mut DelayedInbox,
-    sequencer: PublicKey,
-) -> Result<(), anyhow::Error> {
-    if let Some(InboxContent {
+    InboxContent {
         transactions,
         sequencer_blueprints,
-    }) = read_inbox(
-        host,
-        smart_rollup_address,
-        tezos_contracts,
-        Some(delayed_bridge),
-        Some(sequencer),
-    )? {
-        let previous_timestamp = read_last_info_per_level_timestamp(host)?;
-        let level = read_l1_level(host)?;
-        // Store the transactions in the delayed inbox.
-        for transaction in transactions {
-            delayed_inbox.save_transaction(
+    }: InboxContent,
+) -> Result<(), anyhow::Error> {
+    let previous_timestamp = read_last_info_per_level_timestamp(host)?;
+    let level = read_l1_level(host)?;
+    // Store the transactions in the delayed inbox.
+    for transaction in transactions {
+        delayed_inbox.save_transaction(host, transaction, previous_timestamp, level)?;
+    }
+    // Check if there are timed-out transactions in the delayed inbox
+    let timed_out = delayed_inbox.first_has_timed_out(host)?;
+    if timed_out {
+        fetch_timed_out_transactions(host, delayed_inbox)?
+    } else {
+        // Store the sequencer blueprints.
+        for seq_blueprint in sequencer_blueprints {
+            log!(
                 host,
-                transaction,
-                previous_timestamp,
-                level,
-            )?;
-        }
-        // Check if there are timed-out transactions in the delayed inbox
-        let timed_out = delayed_inbox.first_has_timed_out(host)?;
-        if timed_out {
-            fetch_timed_out_transactions(host, delayed_inbox)?
-        } else {
-            // Store the sequencer blueprints.
-            for seq_blueprint in sequencer_blueprints {
-                log!(
-                    host,
-                    Debug,
-                    "Storing chunk {} of sequencer blueprint number {}",
-                    seq_blueprint.blueprint.chunk_index,
-                    seq_blueprint.blueprint.number
-                );
-                store_sequencer_blueprint(host, seq_blueprint)?
-            }
+                Debug,
+                "Storing chunk {} of sequencer blueprint number {}",
+                seq_blueprint.blueprint.chunk_index,
+                seq_blueprint.blueprint.number
+            );
+            store_sequencer_blueprint(host, seq_blueprint)?
         }
     }
     Ok(())
 }
 
+fn store_inbox_content<Host: Runtime>(
+    host: &mut Host,
+    config: &mut Configuration,
+    inbox_content: InboxContent,
+) -> Result<(), anyhow::Error> {
+    match &mut config.mode {
+        ConfigurationMode::Sequencer { delayed_inbox, .. } => {
+            store_sequencer_inputs(host, delayed_inbox, inbox_content)
+        }
+        ConfigurationMode::Proxy => store_proxy_inputs(host, inbox_content),
+    }
+}
+
+/// Consumes the inbox, and stores its transactions and blueprints to be
+/// processed by the block production.
+///
+/// If inputs were kept aside while the migration was stuck, the inputs of
+/// the current level are queued after them, and the oldest pending level is
+/// handled instead. The kernel reboots until all of them are handled, so that
+/// the inputs keep their order and a kernel run handles a single level.
 pub fn fetch<Host: Runtime>(
     host: &mut Host,
     smart_rollup_address: [u8; RAW_ROLLUP_ADDRESS_SIZE],
     config: &mut Configuration,
 ) -> Result<(), anyhow::Error> {
-    match &mut config.mode {
-        ConfigurationMode::Sequencer {
-            delayed_bridge,
-            delayed_inbox,
-            sequencer,
-        } => fetch_sequencer_blueprints(
+    let inbox_content = read_inbox_content(host, smart_rollup_address, config)?;
+    if !has_pending_inputs(host)? {
+        if let Some(inbox_content) = inbox_content {
+            store_inbox_content(host, config, inbox_content)?;
+        }
+        return Ok(());
+    }
+
+    if let Some(inbox_content) = inbox_content {
+        push_pending_inputs(host, &inbox_content)?;
+    }
+    if let Some(inbox_content) = pop_pending_inputs(host)? {
+        log!(
             host,
-            smart_rollup_address,
-            &config.tezos_contracts,
-            delayed_bridge.clone(),
-            delayed_inbox,
-            sequencer.clone(),
-        ),
-        ConfigurationMode::Proxy => {
-            fetch_inbox_blueprints(host, smart_rollup_address, &config.tezos_contracts)
+            Info,
+            "Handling inputs consumed while the migration was stuck"
+        );
+        store_inbox_content(host, config, inbox_content)?;
+    }
+    if has_pending_inputs(host)? {
+        host.mark_for_reboot()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blueprint_storage::read_next_blueprint;
+    use crate::configuration::TezosContracts;
+    use crate::inbox::{Deposit, Transaction, TransactionContent};
+    use crate::parsing::RollupType;
+    use crate::upgrade::KernelUpgrade;
+    use primitive_types::H160;
+    use tezos_crypto_rs::hash::ContractKt1Hash;
+    use tezos_ethereum::wei::eth_from_mutez;
+    use tezos_smart_rollup_core::PREIMAGE_HASH_SIZE;
+    use tezos_smart_rollup_encoding::contract::Contract;
+    use tezos_smart_rollup_encoding::michelson::ticket::{FA2_1Ticket, Ticket};
+    use tezos_smart_rollup_encoding::michelson::{
+        MichelsonBytes, MichelsonOption, MichelsonOr, MichelsonPair,
+    };
+    use tezos_smart_rollup_encoding::public_key_hash::PublicKeyHash;
+    use tezos_smart_rollup_encoding::timestamp::Timestamp;
+    use tezos_smart_rollup_mock::{MockHost, TransferMetadata};
+
+    const REBOOT_PATH: RefPath = RefPath::assert_from(b"/kernel/env/reboot");
+    const BLUEPRINT_1: RefPath = RefPath::assert_from(b"/blueprints/1");
+
+    fn ticketer() -> ContractKt1Hash {
+        ContractKt1Hash::from_base58_check("KT1HJphVV3LUxqZnc7YSH6Zdfd3up1DjLqZv")
+            .unwrap()
+    }
+
+    fn proxy_configuration() -> Configuration {
+        Configuration {
+            tezos_contracts: TezosContracts {
+                ticketer: Some(ticketer()),
+                ..TezosContracts::default()
+            },
+            mode: ConfigurationMode::Proxy,
         }
     }
+
+    fn add_deposit(host: &mut MockHost, receiver: H160, amount: u64) {
+        let ticket: FA2_1Ticket = Ticket::new(
+            Contract::Originated(ticketer()),
+            MichelsonPair(0.into(), MichelsonOption(None)),
+            amount,
+        )
+        .unwrap();
+        let payload: RollupType = MichelsonOr::Left(MichelsonOr::Left(MichelsonPair(
+            MichelsonBytes(receiver.as_bytes().to_vec()),
+            ticket,
+        )));
+        let source =
+            PublicKeyHash::from_b58check("tz1NiaviJwtMbpEcNqSP6neeoBYj8Brb3QPv").unwrap();
+        host.add_transfer(payload, &TransferMetadata::new(ticketer(), source));
+    }
+
+    fn deposits(transactions: &[Transaction]) -> Vec<Deposit> {
+        transactions
+            .iter()
+            .filter_map(|transaction| match &transaction.content {
+                TransactionContent::Deposit(deposit) => Some(deposit.clone()),
+                TransactionContent::Ethereum(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn deposits_of_a_stuck_level_are_kept() {
+        let mut host = MockHost::default();
+        let smart_rollup_address = host.reveal_metadata().raw_rollup_address;
+        let mut config = proxy_configuration();
+        let receiver = H160::from_low_u64_be(1);
+
+        // The migration is stuck during the level of a deposit.
+        add_deposit(&mut host, receiver, 10);
+        fetch_pending_inputs(&mut host, smart_rollup_address, &config).unwrap();
+        assert!(read_next_blueprint(&mut host, &mut config)
+            .unwrap()
+            .is_none());
+        assert!(has_pending_inputs(&host).unwrap());
+
+        // The migration is done, the pending deposit is handled before the
+        // one of the current level.
+        add_deposit(&mut host, receiver, 20);
+        fetch(&mut host, smart_rollup_address, &mut config).unwrap();
+        let blueprint = read_next_blueprint(&mut host, &mut config)
+            .unwrap()
+            .expect("The pending deposit should be in a blueprint");
+        assert_eq!(
+            vec![Deposit {
+                amount: eth_from_mutez(10),
+                receiver
+            }],
+            deposits(&blueprint.transactions)
+        );
+        let pending: InboxContent =
+            read_rlp(&host, &pending_inputs_path(1).unwrap()).unwrap();
+        assert_eq!(
+            vec![Deposit {
+                amount: eth_from_mutez(20),
+                receiver
+            }],
+            deposits(&pending.transactions)
+        );
+        assert!(host.store_has(&REBOOT_PATH).unwrap().is_some());
+
+        // The deposit of the current level is handled after a reboot.
+        host.store_delete(&REBOOT_PATH).unwrap();
+        fetch(&mut host, smart_rollup_address, &mut config).unwrap();
+        assert!(host.store_has(&BLUEPRINT_1).unwrap().is_some());
+        assert!(!has_pending_inputs(&host).unwrap());
+        assert!(host.store_has(&REBOOT_PATH).unwrap().is_none());
+    }
+
+    #[test]
+    fn stuck_migration_upgrade_waits_for_activation() {
+        let mut host = MockHost::default();
+        // The kernel cannot be revealed, it must not be installed yet.
+        let kernel_upgrade = KernelUpgrade {
+            preimage_hash: [0; PREIMAGE_HASH_SIZE],
+            activation_timestamp: Timestamp::from(1_000_000i64),
+        };
+        upgrade::store_kernel_upgrade(&mut host, &kernel_upgrade).unwrap();
+
+        apply_kernel_upgrade(&mut host).unwrap();
+
+        assert_eq!(
+            Some(kernel_upgrade),
+            upgrade::read_kernel_upgrade(&host).unwrap()
+        );
+        assert!(host.store_has(&REBOOT_PATH).unwrap().is_none());
+    }
 }
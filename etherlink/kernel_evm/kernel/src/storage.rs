@@ -11,7 +11,7 @@ use crate::indexable_storage::IndexableStorage;
 use anyhow::Context;
 use evm_execution::account_storage::EthereumAccount;
 use tezos_crypto_rs::hash::{ContractKt1Hash, HashTrait};
-use tezos_evm_logging::{log, Level::*};
+use tezos_evm_logging::{log, Config as LoggingConfig, Level::*};
 use tezos_smart_rollup_core::MAX_FILE_CHUNK_SIZE;
 use tezos_smart_rollup_encoding::public_key::PublicKey;
 use tezos_smart_rollup_encoding::timestamp::Timestamp;
@@ -107,6 +107,9 @@ pub const WORD_SIZE: usize = 32usize;
 // at this path, the kernel is in proxy mode.
 const SEQUENCER: RefPath = RefPath::assert_from(b"/sequencer");
 
+// RLP encoded runtime configuration of the logger, set by the administrator.
+const LOGGING_CONFIG: RefPath = RefPath::assert_from(b"/logging_config");
+
 pub fn store_read_slice<Host: Runtime, T: Path>(
     host: &Host,
     path: &T,
@@ -125,7 +128,7 @@ pub fn store_read_slice<Host: Runtime, T: Path>(
 }
 
 /// Read a single unsigned 256 bit value from storage at the path given.
-fn read_u256(host: &impl Runtime, path: &OwnedPath) -> Result<U256, Error> {
+pub fn read_u256(host: &impl Runtime, path: &OwnedPath) -> Result<U256, Error> {
     let bytes = host.store_read(path, 0, WORD_SIZE)?;
     Ok(Wei::from_little_endian(&bytes))
 }
@@ -861,6 +864,19 @@ pub fn store_sequencer<Host: Runtime>(
     host.store_write_all(&SEQUENCER, bytes).map_err(Into::into)
 }
 
+pub fn store_logging_config<Host: Runtime>(
+    host: &mut Host,
+    config: &LoggingConfig,
+) -> anyhow::Result<()> {
+    store_rlp(config, host, &LOGGING_CONFIG).context("Failed to store logging config")
+}
+
+pub fn read_logging_config<Host: Runtime>(
+    host: &Host,
+) -> anyhow::Result<Option<LoggingConfig>> {
+    read_optional_rlp(host, &LOGGING_CONFIG)
+}
+
 pub fn clear_events<Host: Runtime>(host: &mut Host) -> anyhow::Result<()> {
     if host.store_has(&EVENTS)?.is_some() {
         host.store_delete(&EVENTS)
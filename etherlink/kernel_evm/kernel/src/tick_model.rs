@@ -100,6 +100,34 @@ pub mod constants {
     pub const TRANSACTION_OVERHEAD_INTERCEPT: u64 = 1_150_000;
     pub const TRANSACTION_OVERHEAD_COEF: u64 = 880;
     pub const TRANSFERT_OBJ_SIZE: u64 = 347;
+
+    /// Size of the data stored for a rejected transaction: its RLP encoding
+    /// (list header, reason tag and 33 bytes of block number), its hash in
+    /// the slot of the block, and the block number of the slot.
+    pub const REJECTION_SIZE: u64 = 35 + 32 + 32;
+
+    /// The rejection index has not been benchmarked. Its costs are derived
+    /// from the model of storing transaction objects above: each access to
+    /// the durable storage is counted as [TX_OBJ_TICKS_INTERCEPT], and each
+    /// byte read or written as [TX_OBJ_TICKS_COEF].
+    ///
+    /// Recording a rejection makes 6 accesses: reading the block number of the
+    /// slot (2), the number of rejections of the slot, and writing the
+    /// rejection, its hash and the block number.
+    pub const TICKS_FOR_REJECTION_STORAGE: u64 =
+        6 * TX_OBJ_TICKS_INTERCEPT + REJECTION_SIZE * TX_OBJ_TICKS_COEF;
+
+    /// Erasing the rejections of a slot makes 6 accesses besides the
+    /// rejections themselves: checking the slot, its block number (2), and its
+    /// hashes (2), and deleting it. See [TICKS_FOR_REJECTION_STORAGE].
+    pub const REJECTIONS_CLEARING_INTERCEPT: u64 =
+        6 * TX_OBJ_TICKS_INTERCEPT + 32 * TX_OBJ_TICKS_COEF;
+
+    /// Erasing each rejection of a slot makes 3 accesses: reading the
+    /// rejection (2) and deleting it, besides reading its hash. See
+    /// [TICKS_FOR_REJECTION_STORAGE].
+    pub const REJECTIONS_CLEARING_COEF: u64 =
+        3 * TX_OBJ_TICKS_INTERCEPT + REJECTION_SIZE * TX_OBJ_TICKS_COEF;
 }
 
 /// Estimation of the number of ticks the kernel can safely spend in the
@@ -127,10 +155,12 @@ fn ticks_of_transaction_overhead(tx_data_size: u64) -> u64 {
 /// An invalid transaction could not be transmitted to the VM, eg. the nonce
 /// was wrong, or the signature verification failed.
 pub fn ticks_of_invalid_transaction(tx_data_size: u64) -> u64 {
-    // If the transaction is invalid, only the base cost is considered.
+    // If the transaction is invalid, only the base cost and recording the
+    // rejection are considered.
     constants::BASE_GAS
         .saturating_mul(constants::TICKS_PER_GAS)
         .saturating_add(ticks_of_transaction_overhead(tx_data_size))
+        .saturating_add(constants::TICKS_FOR_REJECTION_STORAGE)
 }
 
 /// Adds the possible overhead this is not accounted during the validation of
@@ -150,6 +180,14 @@ pub fn ticks_of_valid_transaction(
     }
 }
 
+/// Ticks used to erase the `rejections` of the block previously using the
+/// slot of a new block, see [crate::rejected_transactions].
+pub fn ticks_of_rejections_clearing(rejections: u64) -> u64 {
+    rejections
+        .saturating_mul(constants::REJECTIONS_CLEARING_COEF)
+        .saturating_add(constants::REJECTIONS_CLEARING_INTERCEPT)
+}
+
 /// A valid transaction is a transaction that could be transmitted to
 /// evm_execution. It can succeed (with or without effect on the state)
 /// or fail (if the VM encountered an error).
@@ -2,17 +2,54 @@
 //
 // SPDX-License-Identifier: MIT
 
+//! Logging of the EVM kernel.
+//!
+//! Messages are written to the debug output with the [log] macro, and
+//! filtered at runtime according to the current [Config]: a global level,
+//! possibly overridden for some targets. The target of a message is the path
+//! of the module logging it, and an override applies to every module under
+//! its target (e.g. `evm_execution` covers `evm_execution::precompiles`).
+//!
+//! `Debug` messages are only compiled in crates built with their `debug`
+//! feature, whatever the configuration.
+//!
+//! Messages are written either as `[Info] message key=value`, or in the
+//! structured encoding, as a list of `key=value` fields:
+//!
+//! ```text
+//! level=info target=evm_kernel::inbox msg="Deposit of 10 to 0x..." key=value
+//! ```
+//!
+//! where values containing spaces, quotes or `=` are quoted, and quotes,
+//! backslashes and newlines in them are escaped.
+
+use rlp::{Decodable, DecoderError, Encodable, Rlp, RlpStream};
+use std::cell::RefCell;
+use std::fmt::{Display, Write};
+
 #[doc(hidden)]
 pub use tezos_smart_rollup_debug::debug_msg;
 
-#[derive(PartialEq)]
+/// Level of a message, from the least to the most verbose.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub enum Level {
-    Info,
-    Error,
     Fatal,
+    Error,
+    Info,
     Debug,
 }
 
+impl Level {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Level::Fatal => "fatal",
+            Level::Error => "error",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        }
+    }
+}
+
 impl std::fmt::Display for Level {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self {
@@ -24,15 +61,253 @@ impl std::fmt::Display for Level {
     }
 }
 
+impl From<Level> for u8 {
+    fn from(level: Level) -> Self {
+        level as u8
+    }
+}
+
+impl TryFrom<u8> for Level {
+    type Error = DecoderError;
+
+    fn try_from(byte: u8) -> Result<Self, DecoderError> {
+        match byte {
+            0 => Ok(Level::Fatal),
+            1 => Ok(Level::Error),
+            2 => Ok(Level::Info),
+            3 => Ok(Level::Debug),
+            _ => Err(DecoderError::Custom("Unknown log level")),
+        }
+    }
+}
+
+/// Runtime configuration of the logger.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Config {
+    /// Most verbose level of the messages written.
+    pub level: Level,
+    /// Levels overriding `level` for the messages of some targets.
+    pub targets: Vec<(String, Level)>,
+    /// Whether messages are written in the structured encoding.
+    pub structured: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            level: Level::Debug,
+            targets: vec![],
+            structured: false,
+        }
+    }
+}
+
+impl Config {
+    /// The most verbose level written for `target`, the level of the most
+    /// specific override applying to it if any.
+    pub fn level_for(&self, target: &str) -> Level {
+        self.targets
+            .iter()
+            .filter(|(prefix, _)| {
+                target
+                    .strip_prefix(prefix.as_str())
+                    .map_or(false, |rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.level, |(_, level)| *level)
+    }
+}
+
+impl Encodable for Config {
+    fn rlp_append(&self, stream: &mut RlpStream) {
+        stream.begin_list(3);
+        stream.append(&u8::from(self.level));
+        stream.append(&self.structured);
+        stream.begin_list(self.targets.len());
+        for (target, level) in &self.targets {
+            stream.begin_list(2);
+            stream.append(&target.as_bytes());
+            stream.append(&u8::from(*level));
+        }
+    }
+}
+
+impl Decodable for Config {
+    fn decode(decoder: &Rlp) -> Result<Self, DecoderError> {
+        if !decoder.is_list() {
+            return Err(DecoderError::RlpExpectedToBeList);
+        }
+        if decoder.item_count()? != 3 {
+            return Err(DecoderError::RlpIncorrectListLen);
+        }
+
+        let level = Level::try_from(decoder.val_at::<u8>(0)?)?;
+        let structured = decoder.val_at(1)?;
+        let targets = decoder
+            .at(2)?
+            .iter()
+            .map(|target| {
+                if target.item_count()? != 2 {
+                    return Err(DecoderError::RlpIncorrectListLen);
+                }
+                let name: Vec<u8> = target.val_at(0)?;
+                let name = String::from_utf8(name)
+                    .map_err(|_| DecoderError::Custom("Invalid log target"))?;
+                let level = Level::try_from(target.val_at::<u8>(1)?)?;
+                Ok((name, level))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            level,
+            targets,
+            structured,
+        })
+    }
+}
+
+thread_local! {
+    static CONFIG: RefCell<Config> = RefCell::new(Config::default());
+}
+
+/// Set the configuration used by the following messages. It is reset to the
+/// default configuration at every kernel run.
+pub fn set_config(config: Config) {
+    CONFIG.with(|current| *current.borrow_mut() = config)
+}
+
+#[doc(hidden)]
+pub fn enabled(level: Level, target: &str) -> bool {
+    CONFIG.with(|config| level <= config.borrow().level_for(target))
+}
+
+fn write_value(line: &mut String, value: &str) {
+    let needs_quotes = value.is_empty()
+        || value
+            .chars()
+            .any(|c| c.is_whitespace() || c == '"' || c == '=' || c == '\\');
+    if !needs_quotes {
+        line.push_str(value);
+        return;
+    }
+
+    line.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            '\n' => line.push_str("\\n"),
+            c => line.push(c),
+        }
+    }
+    line.push('"');
+}
+
+#[doc(hidden)]
+pub fn format_line(
+    level: Level,
+    target: &str,
+    message: &str,
+    fields: &[(&str, &dyn Display)],
+) -> String {
+    let structured = CONFIG.with(|config| config.borrow().structured);
+    let mut line = String::new();
+
+    if structured {
+        let _ = write!(line, "level={} target={} msg=", level.as_str(), target);
+        write_value(&mut line, message);
+    } else {
+        let _ = write!(line, "[{}] {}", level, message);
+    }
+
+    for (key, value) in fields {
+        let _ = write!(line, " {}=", key);
+        write_value(&mut line, &value.to_string());
+    }
+
+    line.push('\n');
+    line
+}
+
+/// Write a message at the given level, if enabled for the calling module.
+///
+/// The message can be followed by a list of fields:
+///
+/// ```ignore
+/// log!(host, Info, "Deposit of {} to {}.", amount, receiver; nonce = nonce);
+/// ```
 #[cfg(feature = "alloc")]
 #[macro_export]
 macro_rules! log {
-    ($host: expr, $level: expr, $($args: expr),*) => {
+    ($host: expr, $level: expr, $($args: expr),* $(; $($key: ident = $value: expr),+ $(,)?)?) => {
         {
+            let level: $crate::Level = $level;
             // Display `Debug` level only if the feature flag is actived
-            if $level != $crate::Level::Debug || ($level == $crate::Level::Debug && cfg!(feature = "debug")) {
-                $crate::debug_msg!($host, "[{}] {}\n", $level, { &alloc::format!($($args), *) });
+            if (level != $crate::Level::Debug || cfg!(feature = "debug"))
+                && $crate::enabled(level, module_path!())
+            {
+                let line = $crate::format_line(
+                    level,
+                    module_path!(),
+                    &alloc::format!($($args), *),
+                    &[$($((stringify!($key), &$value as &dyn core::fmt::Display)),+)?],
+                );
+                $crate::debug_msg!($host, "{}", line);
             }
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            level: Level::Error,
+            targets: vec![
+                ("evm_execution".to_owned(), Level::Info),
+                ("evm_execution::precompiles".to_owned(), Level::Debug),
+            ],
+            structured: true,
+        }
+    }
+
+    #[test]
+    fn config_rlp_roundtrip() {
+        let config = config();
+        let decoded = Config::decode(&Rlp::new(&config.rlp_bytes())).unwrap();
+        assert_eq!(config, decoded);
+    }
+
+    #[test]
+    fn most_specific_target_applies() {
+        let config = config();
+        assert_eq!(Level::Error, config.level_for("evm_kernel::inbox"));
+        assert_eq!(Level::Info, config.level_for("evm_execution::handler"));
+        assert_eq!(
+            Level::Debug,
+            config.level_for("evm_execution::precompiles::blake2")
+        );
+        assert_eq!(Level::Error, config.level_for("evm_execution_extra"));
+    }
+
+    #[test]
+    fn structured_encoding_quotes_values() {
+        set_config(config());
+        let line = format_line(
+            Level::Info,
+            "evm_kernel::inbox",
+            "Deposit of \"10\"",
+            &[("nonce", &3), ("to", &"a b")],
+        );
+        assert_eq!(
+            "level=info target=evm_kernel::inbox msg=\"Deposit of \\\"10\\\"\" nonce=3 to=\"a b\"\n",
+            line
+        );
+
+        set_config(Config::default());
+        let line = format_line(Level::Info, "evm_kernel::inbox", "Deposit", &[]);
+        assert_eq!("[Info] Deposit\n", line);
+    }
+}
@@ -970,6 +970,8 @@ let octez_risc_v_pvm =
           [S "source_tree"; S "../interpreter/src"];
           [S "glob_files"; S "../machine_state/*"];
           [S "source_tree"; S "../machine_state/src"];
+          [S "glob_files"; S "../../kernel_sdk/constants/*"];
+          [S "source_tree"; S "../../kernel_sdk/constants/src"];
         ];
         [
           S "action";
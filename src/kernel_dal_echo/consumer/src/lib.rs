@@ -0,0 +1,667 @@
+// SPDX-FileCopyrightText: 2023 Nomadic Labs <contact@nomadic-labs.com>
+//
+// SPDX-License-Identifier: MIT
+
+//! Consumer of DAL slots, for kernels reading their inputs from the DAL.
+//!
+//! A [DalConsumer] tracks a set of subscribed slot indexes. At each level, it
+//! downloads the pages of the subscribed slots published `attestation_lag`
+//! levels earlier, and records in durable storage whether each slot was
+//! attested, together with the hash of its contents. A slot whose pages cannot
+//! be revealed is recorded as not attested. The contents of attested slots can
+//! then be iterated over with [DalConsumer::attested_slots].
+//!
+//! Everything is stored under the root path given to the consumer:
+//!
+//! ```text
+//! <root>/subscriptions                            subscribed slot indexes, one byte each
+//! <root>/last_level                               last processed published level (i32 LE)
+//! <root>/levels/<published level>/attested        attested slot indexes, one byte each
+//! <root>/levels/<published level>/<slot>/status   0x00 if not attested, or
+//!                                                 0x01 followed by the content hash
+//! <root>/levels/<published level>/<slot>/content  contents of attested slots
+//! ```
+//!
+//! Only the `history` most recent published levels are kept.
+
+use tezos_crypto_rs::blake2b::{digest_256, Blake2bError};
+use tezos_smart_rollup_host::dal_parameters::RollupDalParameters;
+use tezos_smart_rollup_host::path::{concat, OwnedPath, Path, PathError, RefPath};
+use tezos_smart_rollup_host::runtime::{Runtime, RuntimeError};
+use thiserror::Error;
+
+/// Size of the hash of slot contents.
+pub const CONTENT_HASH_SIZE: usize = 32;
+
+const SUBSCRIPTIONS: RefPath = RefPath::assert_from(b"/subscriptions");
+const LAST_LEVEL: RefPath = RefPath::assert_from(b"/last_level");
+const LEVELS: RefPath = RefPath::assert_from(b"/levels");
+const ATTESTED: RefPath = RefPath::assert_from(b"/attested");
+const STATUS: RefPath = RefPath::assert_from(b"/status");
+const CONTENT: RefPath = RefPath::assert_from(b"/content");
+
+const NOT_ATTESTED_TAG: u8 = 0x00;
+const ATTESTED_TAG: u8 = 0x01;
+
+/// Errors of the DAL consumer.
+#[derive(Error, Debug)]
+pub enum DalConsumerError {
+    /// Error accessing durable storage.
+    #[error("Runtime error: {0}")]
+    Runtime(RuntimeError),
+    /// Invalid path in durable storage.
+    #[error("Invalid path: {0}")]
+    Path(PathError),
+    /// Failed to hash the contents of a slot.
+    #[error("Failed to hash slot contents: {0:?}")]
+    Hash(Blake2bError),
+    /// A value in durable storage is not in the expected format.
+    #[error("Malformed value in durable storage")]
+    MalformedValue,
+    /// The slot index is not valid for the DAL parameters.
+    #[error("Invalid slot index {0}")]
+    InvalidSlotIndex(u8),
+}
+
+impl From<RuntimeError> for DalConsumerError {
+    fn from(error: RuntimeError) -> Self {
+        Self::Runtime(error)
+    }
+}
+
+impl From<PathError> for DalConsumerError {
+    fn from(error: PathError) -> Self {
+        Self::Path(error)
+    }
+}
+
+impl From<Blake2bError> for DalConsumerError {
+    fn from(error: Blake2bError) -> Self {
+        Self::Hash(error)
+    }
+}
+
+/// Attestation status of a slot at a published level.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlotStatus {
+    /// The slot was attested. Holds the hash of its contents.
+    Attested([u8; CONTENT_HASH_SIZE]),
+    /// The slot was not attested, or nothing was published in it.
+    NotAttested,
+}
+
+impl SlotStatus {
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Self::Attested(hash) => [&[ATTESTED_TAG], hash.as_slice()].concat(),
+            Self::NotAttested => vec![NOT_ATTESTED_TAG],
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DalConsumerError> {
+        match bytes {
+            [NOT_ATTESTED_TAG] => Ok(Self::NotAttested),
+            [ATTESTED_TAG, hash @ ..] => hash
+                .try_into()
+                .map(Self::Attested)
+                .map_err(|_| DalConsumerError::MalformedValue),
+            _ => Err(DalConsumerError::MalformedValue),
+        }
+    }
+}
+
+/// Contents of an attested slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttestedSlot {
+    /// Level at which the slot was published.
+    pub published_level: i32,
+    /// Index of the slot.
+    pub slot_index: u8,
+    /// Hash of the contents.
+    pub hash: [u8; CONTENT_HASH_SIZE],
+    /// Contents of the slot, up to its last non-empty page.
+    pub content: Vec<u8>,
+}
+
+/// Consumer of DAL slots, keeping its state under a root path of the durable
+/// storage.
+#[derive(Debug, Clone)]
+pub struct DalConsumer {
+    root: OwnedPath,
+    history: u32,
+}
+
+impl DalConsumer {
+    /// Create a consumer storing its state under `root`, and keeping the
+    /// status of the last `history` published levels. A `history` of `0` is
+    /// treated as `1`.
+    pub fn new(root: &impl Path, history: u32) -> Self {
+        Self {
+            root: OwnedPath::from(root),
+            history: history.max(1),
+        }
+    }
+
+    /// The subscribed slot indexes, in increasing order.
+    pub fn subscriptions(&self, host: &impl Runtime) -> Result<Vec<u8>, DalConsumerError> {
+        match host.store_read_all(&concat(&self.root, &SUBSCRIPTIONS)?) {
+            Ok(slot_indexes) => Ok(slot_indexes),
+            Err(RuntimeError::PathNotFound) => Ok(vec![]),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Subscribe to the slot at `slot_index`, starting with the next processed
+    /// level.
+    pub fn subscribe(
+        &self,
+        host: &mut impl Runtime,
+        slot_index: u8,
+    ) -> Result<(), DalConsumerError> {
+        let number_of_slots = host.reveal_dal_parameters().number_of_slots;
+        if u64::from(slot_index) >= number_of_slots {
+            return Err(DalConsumerError::InvalidSlotIndex(slot_index));
+        }
+
+        let mut slot_indexes = self.subscriptions(host)?;
+        if let Err(position) = slot_indexes.binary_search(&slot_index) {
+            slot_indexes.insert(position, slot_index);
+            self.write_subscriptions(host, &slot_indexes)?;
+        }
+        Ok(())
+    }
+
+    /// Unsubscribe from the slot at `slot_index`. Its history is kept until it
+    /// expires.
+    pub fn unsubscribe(
+        &self,
+        host: &mut impl Runtime,
+        slot_index: u8,
+    ) -> Result<(), DalConsumerError> {
+        let mut slot_indexes = self.subscriptions(host)?;
+        if let Ok(position) = slot_indexes.binary_search(&slot_index) {
+            slot_indexes.remove(position);
+            self.write_subscriptions(host, &slot_indexes)?;
+        }
+        Ok(())
+    }
+
+    fn write_subscriptions(
+        &self,
+        host: &mut impl Runtime,
+        slot_indexes: &[u8],
+    ) -> Result<(), DalConsumerError> {
+        let path = concat(&self.root, &SUBSCRIPTIONS)?;
+        host.store_write_all(&path, slot_indexes)?;
+        Ok(())
+    }
+
+    /// The last published level processed, if any.
+    pub fn last_published_level(
+        &self,
+        host: &impl Runtime,
+    ) -> Result<Option<i32>, DalConsumerError> {
+        let mut buffer = [0_u8; 4];
+        match host.store_read_slice(&concat(&self.root, &LAST_LEVEL)?, 0, &mut buffer) {
+            Ok(4) => Ok(Some(i32::from_le_bytes(buffer))),
+            Ok(_) => Err(DalConsumerError::MalformedValue),
+            Err(RuntimeError::PathNotFound) => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Download and record the subscribed slots published `attestation_lag`
+    /// levels before `level`, which should be the level of the inbox being
+    /// read.
+    ///
+    /// Returns the processed published level, or `None` if there is none yet
+    /// or if it was already processed, e.g. before a reboot.
+    pub fn process_level(
+        &self,
+        host: &mut impl Runtime,
+        level: u32,
+    ) -> Result<Option<i32>, DalConsumerError> {
+        let parameters = host.reveal_dal_parameters();
+        let published_level = level as i64 - parameters.attestation_lag as i64;
+        let Ok(published_level) = i32::try_from(published_level) else {
+            return Ok(None);
+        };
+        let last_published_level = self.last_published_level(host)?;
+        if published_level < 0
+            || matches!(last_published_level, Some(last) if last >= published_level)
+        {
+            return Ok(None);
+        }
+
+        let mut attested = Vec::new();
+        for slot_index in self.subscriptions(host)? {
+            let status = self.process_slot(host, &parameters, published_level, slot_index)?;
+            if let SlotStatus::Attested(_) = status {
+                attested.push(slot_index);
+            }
+        }
+        let path = concat(&self.level_path(published_level)?, &ATTESTED)?;
+        host.store_write_all(&path, &attested)?;
+
+        // Levels may have been skipped since the last processed one, expire
+        // all the recorded levels which are now out of the history.
+        if let Some(last) = last_published_level {
+            let history = self.history as i32;
+            let first = (last + 1 - history).max(0);
+            let end = last.min(published_level - history);
+            for expired in first..=end {
+                match host.store_delete(&self.level_path(expired)?) {
+                    Ok(()) | Err(RuntimeError::PathNotFound) => {}
+                    Err(error) => return Err(error.into()),
+                }
+            }
+        }
+
+        let path = concat(&self.root, &LAST_LEVEL)?;
+        host.store_write_all(&path, &published_level.to_le_bytes())?;
+
+        Ok(Some(published_level))
+    }
+
+    fn process_slot(
+        &self,
+        host: &mut impl Runtime,
+        parameters: &RollupDalParameters,
+        published_level: i32,
+        slot_index: u8,
+    ) -> Result<SlotStatus, DalConsumerError> {
+        let page_size = parameters.page_size as usize;
+        let num_pages = (parameters.slot_size / parameters.page_size) as usize;
+
+        let mut content = vec![0_u8; page_size * num_pages];
+        let mut size = 0;
+        for page_index in 0..num_pages {
+            // Pages of slots which were not attested are empty.
+            let offset = page_index * page_size;
+            let read = match host.reveal_dal_page(
+                published_level,
+                slot_index,
+                page_index as i16,
+                &mut content[offset..offset + page_size],
+            ) {
+                Ok(read) => read,
+                Err(_) => {
+                    // Failing the level would retry it forever, the slot is
+                    // considered not attested instead.
+                    size = 0;
+                    break;
+                }
+            };
+            if read == 0 {
+                break;
+            }
+            size = offset + read;
+        }
+        content.truncate(size);
+
+        let slot_path = self.slot_path(published_level, slot_index)?;
+        let content_path = concat(&slot_path, &CONTENT)?;
+        let status = if content.is_empty() {
+            SlotStatus::NotAttested
+        } else {
+            let hash = digest_256(&content)?;
+            host.store_write_all(&content_path, &content)?;
+            SlotStatus::Attested(
+                hash.try_into()
+                    .map_err(|_| DalConsumerError::MalformedValue)?,
+            )
+        };
+
+        host.store_write_all(&concat(&slot_path, &STATUS)?, &status.encode())?;
+        Ok(status)
+    }
+
+    /// The attestation status of a slot, if it was processed and has not
+    /// expired yet.
+    pub fn status(
+        &self,
+        host: &impl Runtime,
+        published_level: i32,
+        slot_index: u8,
+    ) -> Result<Option<SlotStatus>, DalConsumerError> {
+        let path = concat(&self.slot_path(published_level, slot_index)?, &STATUS)?;
+        match host.store_read_all(&path) {
+            Ok(bytes) => SlotStatus::decode(&bytes).map(Some),
+            Err(RuntimeError::PathNotFound) => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Iterate over the attested slots of a published level, in increasing
+    /// order of slot index. The iterator is empty if the level was not
+    /// processed, or has expired.
+    pub fn attested_slots<'a, Host: Runtime>(
+        &'a self,
+        host: &'a Host,
+        published_level: i32,
+    ) -> Result<AttestedSlots<'a, Host>, DalConsumerError> {
+        let path = concat(&self.level_path(published_level)?, &ATTESTED)?;
+        let slot_indexes = match host.store_read_all(&path) {
+            Ok(slot_indexes) => slot_indexes,
+            Err(RuntimeError::PathNotFound) => vec![],
+            Err(error) => return Err(error.into()),
+        };
+
+        Ok(AttestedSlots {
+            consumer: self,
+            host,
+            published_level,
+            slot_indexes: slot_indexes.into_iter(),
+        })
+    }
+
+    fn level_path(&self, published_level: i32) -> Result<OwnedPath, PathError> {
+        concat(
+            &concat(&self.root, &LEVELS)?,
+            &OwnedPath::try_from(format!("/{published_level}"))?,
+        )
+    }
+
+    fn slot_path(&self, published_level: i32, slot_index: u8) -> Result<OwnedPath, PathError> {
+        concat(
+            &self.level_path(published_level)?,
+            &OwnedPath::try_from(format!("/{slot_index}"))?,
+        )
+    }
+}
+
+/// Iterator over the attested slots of a published level, reading their
+/// contents from durable storage.
+pub struct AttestedSlots<'a, Host> {
+    consumer: &'a DalConsumer,
+    host: &'a Host,
+    published_level: i32,
+    slot_indexes: std::vec::IntoIter<u8>,
+}
+
+impl<'a, Host: Runtime> AttestedSlots<'a, Host> {
+    fn read_slot(&self, slot_index: u8) -> Result<AttestedSlot, DalConsumerError> {
+        let consumer = self.consumer;
+        let Some(SlotStatus::Attested(hash)) =
+            consumer.status(self.host, self.published_level, slot_index)?
+        else {
+            return Err(DalConsumerError::MalformedValue);
+        };
+
+        let slot_path = consumer.slot_path(self.published_level, slot_index)?;
+        let content = self.host.store_read_all(&concat(&slot_path, &CONTENT)?)?;
+
+        Ok(AttestedSlot {
+            published_level: self.published_level,
+            slot_index,
+            hash,
+            content,
+        })
+    }
+}
+
+impl<'a, Host: Runtime> Iterator for AttestedSlots<'a, Host> {
+    type Item = Result<AttestedSlot, DalConsumerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let slot_index = self.slot_indexes.next()?;
+        Some(self.read_slot(slot_index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::slice::from_raw_parts;
+    use tezos_smart_rollup_core::smart_rollup_core::{ReadInputMessageInfo, SmartRollupCore};
+    use tezos_smart_rollup_core::GENERIC_INVALID_ACCESS;
+    use tezos_smart_rollup_mock::MockHost;
+
+    const ROOT: RefPath = RefPath::assert_from(b"/dal");
+
+    /// Host failing to reveal the pages of one slot, and otherwise behaving
+    /// as the [MockHost] it wraps.
+    struct FailingRevealHost {
+        host: MockHost,
+        slot_index: u8,
+    }
+
+    unsafe impl SmartRollupCore for FailingRevealHost {
+        unsafe fn read_input(
+            &self,
+            message_info: *mut ReadInputMessageInfo,
+            dst: *mut u8,
+            max_bytes: usize,
+        ) -> i32 {
+            SmartRollupCore::read_input(&self.host, message_info, dst, max_bytes)
+        }
+
+        unsafe fn write_output(&self, src: *const u8, num_bytes: usize) -> i32 {
+            SmartRollupCore::write_output(&self.host, src, num_bytes)
+        }
+
+        unsafe fn write_debug(&self, src: *const u8, num_bytes: usize) {
+            SmartRollupCore::write_debug(&self.host, src, num_bytes)
+        }
+
+        unsafe fn store_has(&self, path: *const u8, path_len: usize) -> i32 {
+            SmartRollupCore::store_has(&self.host, path, path_len)
+        }
+
+        unsafe fn store_read(
+            &self,
+            path: *const u8,
+            path_len: usize,
+            offset: usize,
+            dst: *mut u8,
+            max_bytes: usize,
+        ) -> i32 {
+            SmartRollupCore::store_read(&self.host, path, path_len, offset, dst, max_bytes)
+        }
+
+        unsafe fn store_write(
+            &self,
+            path: *const u8,
+            path_len: usize,
+            offset: usize,
+            src: *const u8,
+            num_bytes: usize,
+        ) -> i32 {
+            SmartRollupCore::store_write(&self.host, path, path_len, offset, src, num_bytes)
+        }
+
+        unsafe fn store_delete(&self, path: *const u8, len: usize) -> i32 {
+            SmartRollupCore::store_delete(&self.host, path, len)
+        }
+
+        unsafe fn store_delete_value(&self, path: *const u8, len: usize) -> i32 {
+            SmartRollupCore::store_delete_value(&self.host, path, len)
+        }
+
+        unsafe fn store_list_size(&self, path: *const u8, path_len: usize) -> i64 {
+            SmartRollupCore::store_list_size(&self.host, path, path_len)
+        }
+
+        unsafe fn store_move(
+            &self,
+            from_path: *const u8,
+            from_path_len: usize,
+            to_path: *const u8,
+            to_path_len: usize,
+        ) -> i32 {
+            SmartRollupCore::store_move(&self.host, from_path, from_path_len, to_path, to_path_len)
+        }
+
+        unsafe fn store_copy(
+            &self,
+            from_path: *const u8,
+            from_path_len: usize,
+            to_path: *const u8,
+            to_path_len: usize,
+        ) -> i32 {
+            SmartRollupCore::store_copy(&self.host, from_path, from_path_len, to_path, to_path_len)
+        }
+
+        unsafe fn reveal_preimage(
+            &self,
+            hash_addr: *const u8,
+            hash_len: usize,
+            destination_addr: *mut u8,
+            max_bytes: usize,
+        ) -> i32 {
+            SmartRollupCore::reveal_preimage(
+                &self.host,
+                hash_addr,
+                hash_len,
+                destination_addr,
+                max_bytes,
+            )
+        }
+
+        unsafe fn store_value_size(&self, path: *const u8, path_len: usize) -> i32 {
+            SmartRollupCore::store_value_size(&self.host, path, path_len)
+        }
+
+        unsafe fn reveal_metadata(&self, destination_addr: *mut u8, max_bytes: usize) -> i32 {
+            SmartRollupCore::reveal_metadata(&self.host, destination_addr, max_bytes)
+        }
+
+        unsafe fn reveal(
+            &self,
+            payload_addr: *const u8,
+            payload_len: usize,
+            destination_addr: *mut u8,
+            max_bytes: usize,
+        ) -> i32 {
+            // DAL page requests are the tag `2`, the published level, the
+            // slot index and the page index.
+            match from_raw_parts(payload_addr, payload_len) {
+                [2, _, _, _, _, slot_index, _, _] if *slot_index == self.slot_index => {
+                    GENERIC_INVALID_ACCESS
+                }
+                _ => SmartRollupCore::reveal(
+                    &self.host,
+                    payload_addr,
+                    payload_len,
+                    destination_addr,
+                    max_bytes,
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn records_attestation_status_of_subscribed_slots() {
+        let mut host = MockHost::default();
+        let lag = host.dal_parameters().attestation_lag as u32;
+        let consumer = DalConsumer::new(&ROOT, 2);
+
+        consumer.subscribe(&mut host, 2).unwrap();
+        consumer.subscribe(&mut host, 0).unwrap();
+        consumer.subscribe(&mut host, 2).unwrap();
+        assert_eq!(vec![0, 2], consumer.subscriptions(&host).unwrap());
+
+        host.set_dal_slot(10, 0, b"first slot");
+        host.set_dal_slot(10, 1, b"not subscribed");
+
+        assert_eq!(
+            Some(10),
+            consumer.process_level(&mut host, 10 + lag).unwrap()
+        );
+        // Processing the same level again is a no-op.
+        assert_eq!(None, consumer.process_level(&mut host, 10 + lag).unwrap());
+
+        assert!(matches!(
+            consumer.status(&host, 10, 0).unwrap(),
+            Some(SlotStatus::Attested(_))
+        ));
+        assert_eq!(None, consumer.status(&host, 10, 1).unwrap());
+        assert_eq!(
+            Some(SlotStatus::NotAttested),
+            consumer.status(&host, 10, 2).unwrap()
+        );
+
+        let slots = consumer
+            .attested_slots(&host, 10)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(1, slots.len());
+        assert_eq!(0, slots[0].slot_index);
+        assert!(slots[0].content.starts_with(b"first slot"));
+        assert_eq!(
+            digest_256(&slots[0].content).unwrap(),
+            slots[0].hash.to_vec()
+        );
+    }
+
+    #[test]
+    fn expires_levels_past_history() {
+        let mut host = MockHost::default();
+        let lag = host.dal_parameters().attestation_lag as u32;
+        let consumer = DalConsumer::new(&ROOT, 2);
+        consumer.subscribe(&mut host, 0).unwrap();
+
+        for published_level in 10..13 {
+            host.set_dal_slot(published_level, 0, b"content");
+            consumer
+                .process_level(&mut host, published_level + lag)
+                .unwrap();
+        }
+
+        assert_eq!(Some(12), consumer.last_published_level(&host).unwrap());
+        assert_eq!(None, consumer.status(&host, 10, 0).unwrap());
+        assert!(consumer.status(&host, 11, 0).unwrap().is_some());
+        assert_eq!(1, consumer.attested_slots(&host, 12).unwrap().count());
+    }
+
+    #[test]
+    fn expires_skipped_levels_past_history() {
+        let mut host = MockHost::default();
+        let lag = host.dal_parameters().attestation_lag as u32;
+        let consumer = DalConsumer::new(&ROOT, 2);
+        consumer.subscribe(&mut host, 0).unwrap();
+
+        for published_level in [10, 11, 15] {
+            host.set_dal_slot(published_level, 0, b"content");
+            consumer
+                .process_level(&mut host, published_level + lag)
+                .unwrap();
+        }
+
+        assert_eq!(Some(15), consumer.last_published_level(&host).unwrap());
+        assert_eq!(None, consumer.status(&host, 10, 0).unwrap());
+        assert_eq!(None, consumer.status(&host, 11, 0).unwrap());
+        assert!(consumer.status(&host, 15, 0).unwrap().is_some());
+    }
+
+    #[test]
+    fn records_slots_failing_to_reveal_as_not_attested() {
+        let mut host = FailingRevealHost {
+            host: MockHost::default(),
+            slot_index: 1,
+        };
+        let lag = host.host.dal_parameters().attestation_lag as u32;
+        let consumer = DalConsumer::new(&ROOT, 2);
+        consumer.subscribe(&mut host, 0).unwrap();
+        consumer.subscribe(&mut host, 1).unwrap();
+
+        host.host.set_dal_slot(10, 0, b"content");
+        host.host.set_dal_slot(10, 1, b"content");
+
+        assert_eq!(
+            Some(10),
+            consumer.process_level(&mut host, 10 + lag).unwrap()
+        );
+        assert_eq!(Some(10), consumer.last_published_level(&host).unwrap());
+        assert!(matches!(
+            consumer.status(&host, 10, 0).unwrap(),
+            Some(SlotStatus::Attested(_))
+        ));
+        assert_eq!(
+            Some(SlotStatus::NotAttested),
+            consumer.status(&host, 10, 1).unwrap()
+        );
+        assert_eq!(1, consumer.attested_slots(&host, 10).unwrap().count());
+    }
+}
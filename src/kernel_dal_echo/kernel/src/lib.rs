@@ -3,67 +3,54 @@
 //
 // SPDX-License-Identifier: MIT
 
+use dal_consumer::{DalConsumer, DalConsumerError, SlotStatus};
 use tezos_smart_rollup_debug::debug_msg;
 use tezos_smart_rollup_entrypoint::kernel_entry;
-use tezos_smart_rollup_host::dal_parameters::RollupDalParameters;
-use tezos_smart_rollup_host::path::OwnedPath;
+use tezos_smart_rollup_host::path::{OwnedPath, RefPath};
 use tezos_smart_rollup_host::runtime::Runtime;
 
-fn process_slot(
+// Number of published levels for which the attestation status of slots is kept.
+const HISTORY_LEVELS: u32 = 64;
+
+const CONSUMER_ROOT: RefPath = RefPath::assert_from(b"/dal");
+
+fn echo_attested_slots(
     host: &mut impl Runtime,
-    published_level: i32,
-    num_pages: usize,
-    page_size: usize,
-    slot_index: u8,
-) {
-    let mut buffer = vec![0u8; page_size * num_pages];
+    consumer: &DalConsumer,
+    level: u32,
+) -> Result<(), DalConsumerError> {
+    let Some(published_level) = consumer.process_level(host, level)? else {
+        return Ok(());
+    };
+
+    for slot_index in consumer.subscriptions(host)? {
+        if let Some(SlotStatus::NotAttested) = consumer.status(host, published_level, slot_index)? {
+            debug_msg!(
+                host,
+                "Slot {} not attested for level {}\n",
+                slot_index,
+                published_level
+            );
+        }
+    }
 
-    for page_index in 0..num_pages {
-        let result = host.reveal_dal_page(
+    let slots = consumer
+        .attested_slots(host, published_level)?
+        .collect::<Result<Vec<_>, _>>()?;
+    for slot in slots {
+        debug_msg!(
+            host,
+            "Retrieved slot {} for level {}. {} bytes read\n",
+            slot.slot_index,
             published_level,
-            slot_index,
-            page_index.try_into().unwrap(),
-            &mut buffer[page_index * page_size..(page_index + 1) * page_size],
+            slot.content.len()
         );
-
-        match result {
-            Ok(num) if num == 0 => {
-                // Currently, we send empty pages to kernels for non-attested slots.
-                debug_msg!(
-                    host,
-                    "Slot {} not attested for level {}\n",
-                    slot_index,
-                    published_level
-                );
-                return;
-            }
-            Ok(num) => {
-                debug_msg!(
-                    host,
-                    "Retrieved page {} for level {}, slot index {} successfully. {} bytes read\n",
-                    page_index,
-                    published_level,
-                    slot_index,
-                    num
-                );
-                let slot_path = format!("/output/slot-{}", slot_index);
-                let path: OwnedPath = slot_path.as_bytes().to_vec().try_into().unwrap();
-                host.store_write(&path, &buffer, 0)
-                    .map_err(|_| "Error writing to storage".to_string())
-                    .unwrap_or_default();
-            }
-            Err(err) => {
-                debug_msg!(
-                    host,
-                    "Failed to retrieve one of the pages. Slot {} not processed. Error: {}\n",
-                    slot_index,
-                    &err.to_string()
-                );
-                // Stop fetching pages on error
-                return;
-            }
-        }
+        let slot_path = format!("/output/slot-{}", slot.slot_index);
+        let path: OwnedPath = slot_path.as_bytes().to_vec().try_into()?;
+        host.store_write_all(&path, &slot.content)?;
     }
+
+    Ok(())
 }
 
 fn get_slot_indexes_from_env() -> Vec<u8> {
@@ -85,27 +72,27 @@ fn get_slot_indexes_from_env() -> Vec<u8> {
 pub fn entry(host: &mut impl Runtime) {
     let parameters = host.reveal_dal_parameters();
     debug_msg!(host, "Running kernel with parameters: {:?}\n", parameters);
-    let RollupDalParameters {
-        number_of_slots: _,
-        attestation_lag,
-        slot_size,
-        page_size,
-    } = parameters;
 
-    let slot_indexes = get_slot_indexes_from_env();
+    let consumer = DalConsumer::new(&CONSUMER_ROOT, HISTORY_LEVELS);
 
     match host.read_input() {
         Ok(Some(message)) => {
-            let level = message.level;
-            let published_level = (level as i32) - (attestation_lag as i32);
-            let num_pages = (slot_size / page_size) as usize;
-            for slot_index in slot_indexes {
-                process_slot(
+            for slot_index in get_slot_indexes_from_env() {
+                if let Err(err) = consumer.subscribe(host, slot_index) {
+                    debug_msg!(
+                        host,
+                        "Failed to subscribe to slot {}: {}\n",
+                        slot_index,
+                        err
+                    );
+                }
+            }
+            if let Err(err) = echo_attested_slots(host, &consumer, message.level) {
+                debug_msg!(
                     host,
-                    published_level,
-                    num_pages,
-                    page_size as usize,
-                    slot_index,
+                    "Failed to process slots for level {}: {}\n",
+                    message.level,
+                    err
                 );
             }
         }
@@ -29,3 +29,36 @@ pub const SBI_TEZOS_ED25519_SIGN: u64 = 0x06;
 
 /// Function ID for `sbi_tezos_blake2b_hash256`
 pub const SBI_TEZOS_BLAKE2B_HASH256: u64 = 0x07;
+
+/// Function ID for `sbi_tezos_reveal_preimage`
+pub const SBI_TEZOS_REVEAL_PREIMAGE: u64 = 0x08;
+
+/// Function ID for `sbi_tezos_write_output`
+pub const SBI_TEZOS_WRITE_OUTPUT: u64 = 0x09;
+
+/// Function ID for `sbi_tezos_store_has`
+pub const SBI_TEZOS_STORE_HAS: u64 = 0x0A;
+
+/// Function ID for `sbi_tezos_store_read`
+pub const SBI_TEZOS_STORE_READ: u64 = 0x0B;
+
+/// Function ID for `sbi_tezos_store_write`
+pub const SBI_TEZOS_STORE_WRITE: u64 = 0x0C;
+
+/// Function ID for `sbi_tezos_store_delete`
+pub const SBI_TEZOS_STORE_DELETE: u64 = 0x0D;
+
+/// Function ID for `sbi_tezos_store_delete_value`
+pub const SBI_TEZOS_STORE_DELETE_VALUE: u64 = 0x0E;
+
+/// Function ID for `sbi_tezos_store_list_size`
+pub const SBI_TEZOS_STORE_LIST_SIZE: u64 = 0x0F;
+
+/// Function ID for `sbi_tezos_store_move`
+pub const SBI_TEZOS_STORE_MOVE: u64 = 0x10;
+
+/// Function ID for `sbi_tezos_store_copy`
+pub const SBI_TEZOS_STORE_COPY: u64 = 0x11;
+
+/// Function ID for `sbi_tezos_store_value_size`
+pub const SBI_TEZOS_STORE_VALUE_SIZE: u64 = 0x12;
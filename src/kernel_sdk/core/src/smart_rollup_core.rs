@@ -353,7 +353,11 @@ mod riscv64_hermit {
     };
     use tezos_smart_rollup_constants::riscv::{
         SBI_FIRMWARE_TEZOS, SBI_TEZOS_INBOX_NEXT, SBI_TEZOS_META_ADDRESS,
-        SBI_TEZOS_META_ORIGINATION_LEVEL,
+        SBI_TEZOS_META_ORIGINATION_LEVEL, SBI_TEZOS_REVEAL_PREIMAGE,
+        SBI_TEZOS_STORE_COPY, SBI_TEZOS_STORE_DELETE, SBI_TEZOS_STORE_DELETE_VALUE,
+        SBI_TEZOS_STORE_HAS, SBI_TEZOS_STORE_LIST_SIZE, SBI_TEZOS_STORE_MOVE,
+        SBI_TEZOS_STORE_READ, SBI_TEZOS_STORE_VALUE_SIZE, SBI_TEZOS_STORE_WRITE,
+        SBI_TEZOS_WRITE_OUTPUT,
     };
 
     /// Information about the next inbox level
@@ -422,6 +426,28 @@ mod riscv64_hermit {
         result
     }
 
+    /// Invoke a Tezos SBI function which takes up to 5 arguments and returns
+    /// a single (possibly negative) result. Unused arguments are ignored by
+    /// the SBI implementation.
+    #[inline(always)]
+    unsafe fn sbi_tezos_call(function: u64, args: [u64; 5]) -> i64 {
+        let result: i64;
+
+        core::arch::asm!(
+            "ecall",
+            in("a0") args[0],
+            in("a1") args[1],
+            in("a2") args[2],
+            in("a3") args[3],
+            in("a4") args[4],
+            in("a7") SBI_FIRMWARE_TEZOS,
+            in("a6") function,
+            lateout("a0") result
+        );
+
+        result
+    }
+
     pub unsafe fn read_input(
         message_info: *mut ReadInputMessageInfo,
         dst: *mut u8,
@@ -440,8 +466,11 @@ mod riscv64_hermit {
         info.length as i32
     }
 
-    pub unsafe fn write_output(_src: *const u8, _num_bytes: usize) -> i32 {
-        unimplemented!()
+    pub unsafe fn write_output(src: *const u8, num_bytes: usize) -> i32 {
+        sbi_tezos_call(
+            SBI_TEZOS_WRITE_OUTPUT,
+            [src as u64, num_bytes as u64, 0, 0, 0],
+        ) as i32
     }
 
     pub unsafe fn write_debug(src: *const u8, num_bytes: usize) {
@@ -451,67 +480,119 @@ mod riscv64_hermit {
             .expect("Writing to stdout failed");
     }
 
-    pub unsafe fn store_has(_path: *const u8, _path_len: usize) -> i32 {
-        unimplemented!()
+    pub unsafe fn store_has(path: *const u8, path_len: usize) -> i32 {
+        sbi_tezos_call(SBI_TEZOS_STORE_HAS, [path as u64, path_len as u64, 0, 0, 0])
+            as i32
     }
 
     pub unsafe fn store_read(
-        _path: *const u8,
-        _path_len: usize,
-        _offset: usize,
-        _dst: *mut u8,
-        _max_bytes: usize,
+        path: *const u8,
+        path_len: usize,
+        offset: usize,
+        dst: *mut u8,
+        max_bytes: usize,
     ) -> i32 {
-        unimplemented!()
+        sbi_tezos_call(
+            SBI_TEZOS_STORE_READ,
+            [
+                path as u64,
+                path_len as u64,
+                offset as u64,
+                dst as u64,
+                max_bytes as u64,
+            ],
+        ) as i32
     }
 
     pub unsafe fn store_write(
-        _path: *const u8,
-        _path_len: usize,
-        _offset: usize,
-        _src: *const u8,
-        _num_bytes: usize,
+        path: *const u8,
+        path_len: usize,
+        offset: usize,
+        src: *const u8,
+        num_bytes: usize,
     ) -> i32 {
-        unimplemented!()
+        sbi_tezos_call(
+            SBI_TEZOS_STORE_WRITE,
+            [
+                path as u64,
+                path_len as u64,
+                offset as u64,
+                src as u64,
+                num_bytes as u64,
+            ],
+        ) as i32
     }
 
-    pub unsafe fn store_delete(_path: *const u8, _len: usize) -> i32 {
-        unimplemented!()
+    pub unsafe fn store_delete(path: *const u8, len: usize) -> i32 {
+        sbi_tezos_call(SBI_TEZOS_STORE_DELETE, [path as u64, len as u64, 0, 0, 0]) as i32
     }
 
-    pub unsafe fn store_delete_value(_path: *const u8, _len: usize) -> i32 {
-        unimplemented!()
+    pub unsafe fn store_delete_value(path: *const u8, len: usize) -> i32 {
+        sbi_tezos_call(
+            SBI_TEZOS_STORE_DELETE_VALUE,
+            [path as u64, len as u64, 0, 0, 0],
+        ) as i32
     }
 
-    pub unsafe fn store_list_size(_path: *const u8, _path_len: usize) -> i64 {
-        unimplemented!()
+    pub unsafe fn store_list_size(path: *const u8, path_len: usize) -> i64 {
+        sbi_tezos_call(
+            SBI_TEZOS_STORE_LIST_SIZE,
+            [path as u64, path_len as u64, 0, 0, 0],
+        )
     }
 
     pub unsafe fn store_move(
-        _from_path: *const u8,
-        _from_path_len: usize,
-        _to_path: *const u8,
-        _to_path_len: usize,
+        from_path: *const u8,
+        from_path_len: usize,
+        to_path: *const u8,
+        to_path_len: usize,
     ) -> i32 {
-        unimplemented!()
+        sbi_tezos_call(
+            SBI_TEZOS_STORE_MOVE,
+            [
+                from_path as u64,
+                from_path_len as u64,
+                to_path as u64,
+                to_path_len as u64,
+                0,
+            ],
+        ) as i32
     }
 
     pub unsafe fn store_copy(
-        _from_path: *const u8,
-        _from_path_len: usize,
-        _to_path: *const u8,
-        _to_path_len: usize,
+        from_path: *const u8,
+        from_path_len: usize,
+        to_path: *const u8,
+        to_path_len: usize,
     ) -> i32 {
-        unimplemented!()
+        sbi_tezos_call(
+            SBI_TEZOS_STORE_COPY,
+            [
+                from_path as u64,
+                from_path_len as u64,
+                to_path as u64,
+                to_path_len as u64,
+                0,
+            ],
+        ) as i32
     }
 
     pub unsafe fn reveal_preimage(
-        _hash_addr: *const u8,
-        _hash_len: usize,
-        _destination_addr: *mut u8,
-        _max_bytes: usize,
+        hash_addr: *const u8,
+        hash_len: usize,
+        destination_addr: *mut u8,
+        max_bytes: usize,
     ) -> i32 {
-        unimplemented!()
+        sbi_tezos_call(
+            SBI_TEZOS_REVEAL_PREIMAGE,
+            [
+                hash_addr as u64,
+                hash_len as u64,
+                destination_addr as u64,
+                max_bytes as u64,
+                0,
+            ],
+        ) as i32
     }
 
     #[cfg(feature = "proto-alpha")]
@@ -524,8 +605,11 @@ mod riscv64_hermit {
         unimplemented!()
     }
 
-    pub unsafe fn store_value_size(_path: *const u8, _path_len: usize) -> i32 {
-        unimplemented!()
+    pub unsafe fn store_value_size(path: *const u8, path_len: usize) -> i32 {
+        sbi_tezos_call(
+            SBI_TEZOS_STORE_VALUE_SIZE,
+            [path as u64, path_len as u64, 0, 0, 0],
+        ) as i32
     }
 
     pub unsafe fn reveal_metadata(buffer: *mut u8, max_bytes: usize) -> i32 {
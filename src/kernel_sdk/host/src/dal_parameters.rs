@@ -52,3 +52,23 @@ impl TryFrom<[u8; DAL_PARAMETERS_SIZE]> for RollupDalParameters {
         })
     }
 }
+
+impl From<RollupDalParameters> for [u8; DAL_PARAMETERS_SIZE] {
+    fn from(value: RollupDalParameters) -> [u8; DAL_PARAMETERS_SIZE] {
+        let mut data = [0; DAL_PARAMETERS_SIZE];
+        let fields = [
+            value.number_of_slots,
+            value.attestation_lag,
+            value.slot_size,
+            value.page_size,
+        ];
+        for (chunk, field) in data
+            .chunks_exact_mut(core::mem::size_of::<i64>())
+            .zip(fields)
+        {
+            // Parameters are encoded as (positive) i64 by the protocol
+            chunk.copy_from_slice(&i64::to_be_bytes(field as i64));
+        }
+        data
+    }
+}
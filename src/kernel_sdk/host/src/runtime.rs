@@ -726,6 +726,75 @@ where
     }
 }
 
+/// Implements the methods of [Runtime] gated behind feature flags, by
+/// forwarding them to the runtime held in the given field of `self`. [Runtime]
+/// must be in scope where it is used.
+///
+/// Which methods are declared depends on the features of this crate, rather
+/// than those of the crate wrapping a runtime, so that a wrapper implements
+/// exactly the methods of the trait whatever the feature unification.
+///
+/// ```ignore
+/// impl<Host: Runtime> Runtime for Wrapper<Host> {
+///     // ... other methods of the trait ...
+///     tezos_smart_rollup_host::forward_gated_runtime_methods!(host);
+/// }
+/// ```
+#[macro_export]
+macro_rules! forward_gated_runtime_methods {
+    ($field:tt) => {
+        $crate::__forward_reveal_dal_page!($field);
+        $crate::__forward_reveal_dal_parameters!($field);
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(all(feature = "alloc", feature = "proto-alpha"))]
+macro_rules! __forward_reveal_dal_page {
+    ($field:tt) => {
+        fn reveal_dal_page(
+            &self,
+            published_level: i32,
+            slot_index: u8,
+            page_index: i16,
+            destination: &mut [u8],
+        ) -> ::core::result::Result<usize, $crate::runtime::RuntimeError> {
+            self.$field.reveal_dal_page(
+                published_level,
+                slot_index,
+                page_index,
+                destination,
+            )
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(all(feature = "alloc", feature = "proto-alpha")))]
+macro_rules! __forward_reveal_dal_page {
+    ($field:tt) => {};
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "proto-alpha")]
+macro_rules! __forward_reveal_dal_parameters {
+    ($field:tt) => {
+        fn reveal_dal_parameters(&self) -> $crate::dal_parameters::RollupDalParameters {
+            self.$field.reveal_dal_parameters()
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "proto-alpha"))]
+macro_rules! __forward_reveal_dal_parameters {
+    ($field:tt) => {};
+}
+
 #[cfg(feature = "alloc")]
 fn check_path_has_value<T: Path>(
     runtime: &impl Runtime,
@@ -1,4 +1,4 @@
-// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+// SPDX-FileCopyrightText: 2023-2024 TriliTech <contact@trili.tech>
 //
 // SPDX-License-Identifier: MIT
 
@@ -30,4 +30,42 @@ pub enum Commands {
         #[arg(short, long, value_name = "DISPLAY_ROOT_HASH")]
         display_root_hash: bool,
     },
+    /// Decode the config program of an installer kernel to yaml.
+    DecodeConfig {
+        #[arg(short, long, value_name = "INSTALLER_KERNEL")]
+        installer: OsString,
+
+        /// Print the config to stdout when omitted.
+        #[arg(short, long, value_name = "CONFIG_OUTPUT_FILE")]
+        output: Option<OsString>,
+    },
+    /// Check that a preimages dir contains all the pages of a root hash.
+    CheckPreimages {
+        #[arg(short, long, value_name = "ROOT_HASH")]
+        root_hash: String,
+
+        #[arg(short = 'P', long, value_name = "PREIMAGES_DIR")]
+        preimages_dir: OsString,
+
+        /// Also check that the pages reveal this kernel.
+        #[arg(short, long, value_name = "KERNEL")]
+        kernel: Option<OsString>,
+    },
+    /// Compute the root hash of a kernel, without writing its preimages.
+    GetRootHash {
+        #[arg(short, long, value_name = "KERNEL")]
+        kernel: OsString,
+    },
+    /// Produce the payload upgrading a running rollup to a kernel.
+    GetUpgradePayload {
+        #[arg(short, long, value_name = "UPGRADE_TO_KERNEL")]
+        upgrade_to: OsString,
+
+        #[arg(short = 'P', long, value_name = "PREIMAGES_OUTPUT_DIR")]
+        preimages_dir: OsString,
+
+        /// Print the payload to stdout, in hex, when omitted.
+        #[arg(short, long, value_name = "PAYLOAD_OUTPUT_FILE")]
+        output: Option<OsString>,
+    },
 }
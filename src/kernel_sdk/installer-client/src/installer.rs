@@ -1,9 +1,13 @@
-// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+// SPDX-FileCopyrightText: 2023-2024 TriliTech <contact@trili.tech>
 //
 // SPDX-License-Identifier: MIT
 
+use std::ffi::OsStr;
+use std::path::Path;
 use tezos_data_encoding::enc::BinWriter;
+use tezos_smart_rollup_encoding::dac::PreimageHash;
 use tezos_smart_rollup_installer_config::binary::owned::OwnedConfigProgram;
+use tezos_smart_rollup_installer_config::binary::promote::KernelUpgrade;
 use wasm_gen::write_custom_section;
 
 const INSTALLER_KERNEL: &[u8] = include_bytes!("../installer.wasm");
@@ -33,3 +37,35 @@ pub fn with_config_program(config_programm: OwnedConfigProgram) -> Vec<u8> {
 
     installer
 }
+
+/// Read an installer kernel from `installer_path`.
+///
+/// The installer is expected to be encoded as either hex or wasm, depending
+/// on the extension, as when saved with `get-reveal-installer`.
+pub fn read_installer(installer_path: &Path) -> std::io::Result<Vec<u8>> {
+    let installer = std::fs::read(installer_path)?;
+
+    if installer_path.extension() == Some(OsStr::new("wasm")) {
+        return Ok(installer);
+    }
+
+    hex::decode(String::from_utf8_lossy(&installer).trim())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// Decode the config program set by [with_config_program].
+pub fn config_program(installer: &[u8]) -> Result<OwnedConfigProgram, &'static str> {
+    OwnedConfigProgram::decode_from_kernel(installer)
+}
+
+/// Payload upgrading a running kernel to the kernel of `root_hash`.
+///
+/// The payload is a [KernelUpgrade] message: a running kernel parses it with
+/// [KernelUpgrade::parse], then reveals the new kernel and moves it to
+/// `/kernel/boot.wasm` with [KernelUpgrade::install].
+pub fn upgrade_payload(root_hash: PreimageHash) -> Vec<u8> {
+    KernelUpgrade {
+        root_hash: root_hash.into(),
+    }
+    .to_bytes()
+}
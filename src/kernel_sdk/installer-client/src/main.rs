@@ -11,7 +11,10 @@ use clap::Parser;
 use commands::Cli;
 use commands::Commands;
 use std::path::Path;
+use tezos_smart_rollup_core::PREIMAGE_HASH_SIZE;
+use tezos_smart_rollup_encoding::dac::PreimageHash;
 use tezos_smart_rollup_installer::config::{create_installer_config, ConfigurationError};
+use tezos_smart_rollup_installer_config::yaml::YamlConfig;
 use thiserror::Error;
 
 fn main() -> Result<(), ClientError> {
@@ -43,11 +46,81 @@ fn main() -> Result<(), ClientError> {
                 println!("ROOT_HASH: {}", root_hash_hex);
             };
         }
+        Commands::DecodeConfig { installer, output } => {
+            let installer = installer::read_installer(Path::new(&installer))
+                .map_err(ClientError::ReadInstaller)?;
+
+            let config = installer::config_program(&installer)
+                .map_err(ClientError::DecodeConfig)?;
+            let config = serde_yaml::to_string(&YamlConfig::from_config_program(&config))
+                .map_err(ClientError::SerializeConfig)?;
+
+            match output {
+                Some(output) => std::fs::write(Path::new(&output), config)
+                    .map_err(ClientError::SaveOutput)?,
+                None => print!("{}", config),
+            }
+        }
+        Commands::CheckPreimages {
+            root_hash,
+            preimages_dir,
+            kernel,
+        } => {
+            let root_hash = parse_root_hash(&root_hash)?;
+
+            let content =
+                preimages::check_preimages(&root_hash, Path::new(&preimages_dir))?;
+
+            if let Some(kernel) = kernel {
+                let kernel = std::fs::read(Path::new(&kernel))
+                    .map_err(preimages::Error::ContentFile)?;
+                if kernel != content {
+                    return Err(ClientError::KernelMismatch);
+                }
+            }
+
+            println!("Preimages are valid, revealing {} bytes.", content.len());
+        }
+        Commands::GetRootHash { kernel } => {
+            let kernel = std::fs::read(Path::new(&kernel))
+                .map_err(preimages::Error::ContentFile)?;
+
+            let root_hash = preimages::content_root_hash(kernel)?;
+
+            println!("ROOT_HASH: {}", hex::encode(root_hash.as_ref()));
+        }
+        Commands::GetUpgradePayload {
+            upgrade_to,
+            preimages_dir,
+            output,
+        } => {
+            let kernel = std::fs::read(Path::new(&upgrade_to))
+                .map_err(preimages::Error::ContentFile)?;
+
+            let root_hash =
+                preimages::content_to_preimages(kernel, Path::new(&preimages_dir))?;
+            let payload = installer::upgrade_payload(root_hash);
+
+            match output {
+                Some(output) => std::fs::write(Path::new(&output), payload)
+                    .map_err(ClientError::SaveOutput)?,
+                None => println!("{}", hex::encode(payload)),
+            }
+        }
     }
 
     Ok(())
 }
 
+fn parse_root_hash(root_hash: &str) -> Result<PreimageHash, ClientError> {
+    let root_hash = hex::decode(root_hash)
+        .map_err(|e| ClientError::InvalidRootHash(e.to_string()))?;
+    let root_hash: [u8; PREIMAGE_HASH_SIZE] = root_hash.try_into().map_err(|_| {
+        ClientError::InvalidRootHash(format!("expected {} bytes", PREIMAGE_HASH_SIZE))
+    })?;
+    Ok(PreimageHash::from(&root_hash))
+}
+
 #[derive(Debug, Error)]
 enum ClientError {
     #[error("Error preimaging kernel: {0}")]
@@ -56,4 +129,16 @@ enum ClientError {
     ConfigError(#[from] ConfigurationError),
     #[error("Unable to save installer kernel: {0}")]
     SaveInstaller(std::io::Error),
+    #[error("Unable to read installer kernel: {0}")]
+    ReadInstaller(std::io::Error),
+    #[error("Unable to decode installer config: {0}")]
+    DecodeConfig(&'static str),
+    #[error("Unable to serialize config to yaml: {0}")]
+    SerializeConfig(serde_yaml::Error),
+    #[error("Unable to save output: {0}")]
+    SaveOutput(std::io::Error),
+    #[error("Invalid root hash: {0}")]
+    InvalidRootHash(String),
+    #[error("Preimages do not reveal the given kernel")]
+    KernelMismatch,
 }
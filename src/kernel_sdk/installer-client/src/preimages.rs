@@ -4,7 +4,10 @@
 
 use std::fs;
 use std::path::Path;
-use tezos_smart_rollup_encoding::dac::pages::prepare_preimages;
+use tezos_smart_rollup_core::PREIMAGE_HASH_SIZE;
+use tezos_smart_rollup_encoding::dac::pages::{
+    make_preimage_hash, prepare_preimages, SlicePage,
+};
 use tezos_smart_rollup_encoding::dac::PreimageHash;
 use thiserror::Error;
 
@@ -16,6 +19,12 @@ pub enum Error {
     PreimagesDir(std::io::Error),
     #[error("Failed to produce preimages from content: {0}.")]
     Preimage(String),
+    #[error("Unable to read preimage {0}: {1}.")]
+    MissingPreimage(String, std::io::Error),
+    #[error("Preimage {0} does not match its hash.")]
+    InvalidPreimage(String),
+    #[error("Unable to decode preimage {0} as a page.")]
+    InvalidPage(String),
 }
 
 pub fn content_to_preimages(
@@ -38,3 +47,49 @@ pub fn content_to_preimages(
     prepare_preimages(content.as_ref(), save_preimages)
         .map_err(|e| Error::Preimage(e.to_string()))
 }
+
+/// Compute the root hash of `content`, without saving its preimages.
+pub fn content_root_hash(content: impl AsRef<[u8]>) -> Result<PreimageHash, Error> {
+    prepare_preimages(content.as_ref(), |_, _| {})
+        .map_err(|e| Error::Preimage(e.to_string()))
+}
+
+/// Check that `preimage_dir` contains every page of the tree of `root_hash`.
+///
+/// Each page is checked against its hash. Returns the content revealed by
+/// the tree.
+pub fn check_preimages(
+    root_hash: &PreimageHash,
+    preimage_dir: &Path,
+) -> Result<Vec<u8>, Error> {
+    let mut content = Vec::new();
+    check_page(root_hash.as_ref(), preimage_dir, &mut content)?;
+    Ok(content)
+}
+
+fn check_page(
+    hash: &[u8; PREIMAGE_HASH_SIZE],
+    preimage_dir: &Path,
+    content: &mut Vec<u8>,
+) -> Result<(), Error> {
+    let name = hex::encode(hash);
+    let page = fs::read(preimage_dir.join(&name))
+        .map_err(|e| Error::MissingPreimage(name.clone(), e))?;
+
+    match make_preimage_hash(&page) {
+        Ok(page_hash) if &page_hash == hash => (),
+        _ => return Err(Error::InvalidPreimage(name)),
+    }
+
+    match SlicePage::try_from(page.as_slice()) {
+        Ok(SlicePage::V0HashPage(hashes)) => {
+            for hash in hashes.hashes() {
+                check_page(hash, preimage_dir, content)?;
+            }
+        }
+        Ok(SlicePage::V0ContentPage(page)) => content.extend_from_slice(page.as_ref()),
+        Err(_) => return Err(Error::InvalidPage(name)),
+    }
+
+    Ok(())
+}
@@ -13,11 +13,17 @@ use tezos_smart_rollup::host::Runtime;
 use tezos_smart_rollup_host::path::{OwnedPath, RefPath};
 use tezos_smart_rollup_host::runtime::RuntimeError;
 use tezos_smart_rollup_installer::config::create_installer_config;
-use tezos_smart_rollup_installer::installer::with_config_program;
+use tezos_smart_rollup_installer::installer::{
+    config_program, upgrade_payload, with_config_program,
+};
+use tezos_smart_rollup_installer::preimages::{
+    check_preimages, content_root_hash, content_to_preimages, Error,
+};
 use tezos_smart_rollup_installer::KERNEL_BOOT_PATH;
 use tezos_smart_rollup_installer_config::binary::owned::{
     OwnedBytes, OwnedConfigInstruction, OwnedConfigProgram,
 };
+use tezos_smart_rollup_installer_config::binary::promote::KernelUpgrade;
 use tezos_smart_rollup_mock::MockHost;
 
 fn write_kernel_to_boot_path(host: &mut MockHost, kernel: Vec<u8>) {
@@ -178,3 +184,76 @@ fn yaml_config_set_execute() {
 
     assert_eq!(expected, actual)
 }
+
+#[test]
+fn decode_installer_config() {
+    let root_hash = PreimageHash::from(&[7; 33]);
+    let config = create_installer_config(
+        root_hash.clone(),
+        Some(OsString::from("tests/resources/move_config.yaml")),
+        None,
+    )
+    .unwrap();
+    let expected = create_installer_config(
+        root_hash,
+        Some(OsString::from("tests/resources/move_config.yaml")),
+        None,
+    )
+    .unwrap();
+
+    let installer = with_config_program(config);
+
+    assert_eq!(Ok(expected), config_program(&installer));
+}
+
+#[test]
+fn check_kernel_preimages() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let preimages_dir = tmp_dir.path();
+
+    let kernel = fs::read("tests/resources/single_page_kernel.wasm").unwrap();
+    let root_hash = content_to_preimages(&kernel, preimages_dir).unwrap();
+
+    assert_eq!(root_hash, content_root_hash(&kernel).unwrap());
+    assert_eq!(kernel, check_preimages(&root_hash, preimages_dir).unwrap());
+
+    // Corrupt the root page.
+    let root_page = preimages_dir.join(hex::encode(root_hash.as_ref()));
+    fs::write(&root_page, b"not a page").unwrap();
+    assert!(matches!(
+        check_preimages(&root_hash, preimages_dir),
+        Err(Error::InvalidPreimage(_))
+    ));
+
+    fs::remove_file(&root_page).unwrap();
+    assert!(matches!(
+        check_preimages(&root_hash, preimages_dir),
+        Err(Error::MissingPreimage(_, _))
+    ));
+}
+
+#[test]
+fn upgrade_with_payload() {
+    let mut host = MockHost::default();
+
+    let kernel = fs::read("tests/resources/single_page_kernel.wasm").unwrap();
+    let save_preimages = |_hash: PreimageHash, preimage: Vec<u8>| {
+        host.set_preimage(preimage);
+    };
+    let root_hash = prepare_preimages(&kernel, save_preimages).unwrap();
+
+    // A running kernel parses the payload from its inbox, then installs it.
+    let payload = upgrade_payload(root_hash.clone());
+    let upgrade = KernelUpgrade::parse(&payload).expect("Payload should be an upgrade");
+    assert_eq!(root_hash.as_ref(), &upgrade.root_hash);
+    upgrade.install(&mut host).unwrap();
+
+    let boot_kernel = host
+        .store_read(&KERNEL_BOOT_PATH, 0, MAX_FILE_CHUNK_SIZE)
+        .unwrap();
+    assert_eq!(kernel, boot_kernel);
+
+    // Truncated or untagged payloads are not upgrades.
+    assert_eq!(None, KernelUpgrade::parse(&payload[..payload.len() - 1]));
+    assert_eq!(None, KernelUpgrade::parse(&payload[1..]));
+}
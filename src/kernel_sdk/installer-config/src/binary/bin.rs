@@ -185,4 +185,37 @@ mod test {
             &mut vec![],
         );
     }
+
+    #[test]
+    fn decode_program_from_kernel() {
+        use crate::binary::owned::{
+            OwnedBytes, OwnedConfigInstruction, OwnedConfigProgram,
+        };
+        use tezos_smart_rollup_host::path::{OwnedPath, RefPath};
+
+        let program = OwnedConfigProgram(vec![
+            OwnedConfigInstruction::reveal_instr(
+                vec![3; 33].into(),
+                OwnedPath::from(RefPath::assert_from(b"/reveal/to")),
+            ),
+            OwnedConfigInstruction::move_instr(
+                OwnedPath::from(RefPath::assert_from(b"/from")),
+                OwnedPath::from(RefPath::assert_from(b"/to")),
+            ),
+            OwnedConfigInstruction::set_instr(
+                OwnedBytes(b"value".to_vec()),
+                OwnedPath::from(RefPath::assert_from(b"/set")),
+            ),
+        ]);
+
+        let mut kernel = b"kernel bytes".to_vec();
+        program.bin_write(&mut kernel).unwrap();
+
+        assert_eq!(Ok(program), OwnedConfigProgram::decode_from_kernel(&kernel));
+
+        assert!(OwnedConfigProgram::decode_from_kernel(b"abc").is_err());
+        assert!(
+            OwnedConfigProgram::decode_from_kernel(&[0, 0, 0, 1, 5, 0, 0, 0]).is_err()
+        );
+    }
 }
@@ -55,6 +55,7 @@ pub type RefConfigInstruction<'a> = ConfigInstruction<RefPath<'a>, RefBytes<'a>>
 #[cfg(feature = "alloc")]
 pub mod owned {
     use crate::binary::evaluation::eval_config_instr;
+    use crate::binary::{completed, NomReader};
     use tezos_smart_rollup_encoding::dac::PreimageHash;
     use tezos_smart_rollup_host::{
         path::{OwnedPath, PathError},
@@ -85,6 +86,72 @@ pub mod owned {
             }
             Ok(())
         }
+
+        /// Decode the config program found at the end of `kernel`.
+        ///
+        /// This is the inverse of appending the binary encoding of a program
+        /// to a kernel: the program is read backwards from its size, given by
+        /// the last 4 bytes, in the same way as the installer kernel does.
+        pub fn decode_from_kernel(kernel: &[u8]) -> Result<Self, &'static str> {
+            let read_size = |offset: usize| -> Result<usize, &'static str> {
+                let size = kernel
+                    .get(offset..offset + 4)
+                    .ok_or("Couldn't read size from kernel")?;
+                Ok(u32::from_le_bytes(size.try_into().unwrap()) as usize)
+            };
+
+            let end_offset = kernel
+                .len()
+                .checked_sub(4)
+                .ok_or("Kernel too small to contain a config program")?;
+            let program_size = read_size(end_offset)?;
+            let mut offset = end_offset
+                .checked_sub(program_size)
+                .ok_or("Invalid size of config program")?;
+
+            let mut instructions = vec![];
+            while offset < end_offset {
+                let instr_size = read_size(offset)?;
+                offset += 4;
+
+                if offset + instr_size > end_offset {
+                    return Err("Invalid size of config instruction");
+                }
+                let instr =
+                    RefConfigInstruction::nom_read(&kernel[offset..offset + instr_size])
+                        .map_err(|_| "Couldn't decode config instruction")
+                        .and_then(completed)?;
+                instructions.push(instr.into());
+                offset += instr_size;
+            }
+
+            Ok(OwnedConfigProgram(instructions))
+        }
+    }
+
+    impl<'a> From<RefConfigInstruction<'a>> for OwnedConfigInstruction {
+        fn from(instr: RefConfigInstruction<'a>) -> Self {
+            match instr {
+                ConfigInstruction::Reveal(RevealInstruction { hash, to }) => {
+                    ConfigInstruction::Reveal(RevealInstruction {
+                        hash: OwnedBytes(hash.0.to_vec()),
+                        to: to.into(),
+                    })
+                }
+                ConfigInstruction::Move(MoveInstruction { from, to }) => {
+                    ConfigInstruction::Move(MoveInstruction {
+                        from: from.into(),
+                        to: to.into(),
+                    })
+                }
+                ConfigInstruction::Set(SetInstruction { value, to }) => {
+                    ConfigInstruction::Set(SetInstruction {
+                        value: OwnedBytes(value.0.to_vec()),
+                        to: to.into(),
+                    })
+                }
+            }
+        }
     }
 
     #[derive(Debug, Error, PartialEq)]
@@ -178,6 +245,7 @@ pub mod promote {
     use tezos_smart_rollup_core::PREIMAGE_HASH_SIZE;
     use tezos_smart_rollup_host::{
         path::{OwnedPath, RefPath},
+        runtime::Runtime,
         KERNEL_BOOT_PATH,
     };
 
@@ -197,4 +265,45 @@ pub mod promote {
             ),
         ])
     }
+
+    /// Tag of the [KernelUpgrade] messages.
+    pub const KERNEL_UPGRADE_TAG: u8 = 0x01;
+
+    /// Message upgrading a running kernel to the kernel revealed from a root
+    /// hash, as DAC payloads are.
+    ///
+    /// The message is [KERNEL_UPGRADE_TAG], followed by the root hash.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct KernelUpgrade {
+        /// Root hash of the preimages of the new kernel.
+        pub root_hash: [u8; PREIMAGE_HASH_SIZE],
+    }
+
+    impl KernelUpgrade {
+        /// Parse an upgrade message, returning `None` if the bytes are not
+        /// one.
+        pub fn parse(bytes: &[u8]) -> Option<Self> {
+            match bytes.split_first() {
+                Some((&KERNEL_UPGRADE_TAG, root_hash)) => Some(Self {
+                    root_hash: root_hash.try_into().ok()?,
+                }),
+                _ => None,
+            }
+        }
+
+        /// Encode the upgrade message.
+        pub fn to_bytes(&self) -> Vec<u8> {
+            let mut bytes = Vec::with_capacity(1 + PREIMAGE_HASH_SIZE);
+            bytes.push(KERNEL_UPGRADE_TAG);
+            bytes.extend_from_slice(&self.root_hash);
+            bytes
+        }
+
+        /// Reveal the new kernel, and move it to `/kernel/boot.wasm`.
+        ///
+        /// The new kernel runs once the current one yields.
+        pub fn install(&self, host: &mut impl Runtime) -> Result<(), &'static str> {
+            upgrade_reveal_flow(self.root_hash).evaluate(host)
+        }
+    }
 }
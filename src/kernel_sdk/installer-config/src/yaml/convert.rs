@@ -4,11 +4,14 @@
 // SPDX-License-Identifier: MIT
 
 use crate::binary::owned::{OwnedBytes, OwnedConfigInstruction, OwnedConfigProgram};
-use crate::yaml::YamlConfig;
+use crate::binary::{
+    ConfigInstruction, MoveInstruction, RevealInstruction, SetInstruction,
+};
+use crate::yaml::{MoveArgs, RevealArgs, SetArgs, YamlConfig};
 use hex::FromHexError;
 use tezos_smart_rollup_core::PREIMAGE_HASH_SIZE;
 use tezos_smart_rollup_encoding::dac::PreimageHash;
-use tezos_smart_rollup_host::path::{OwnedPath, PathError};
+use tezos_smart_rollup_host::path::{OwnedPath, Path, PathError};
 use thiserror::Error;
 
 use crate::yaml::Instr;
@@ -86,6 +89,42 @@ impl YamlConfig {
             .collect::<Result<Vec<OwnedConfigInstruction>, ConfigConversionError>>()
             .map(OwnedConfigProgram)
     }
+
+    /// Convert a config program back to its yaml representation.
+    ///
+    /// `set` instructions whose values were turned into `reveal` instructions,
+    /// when converting the config to a program, are kept as `reveal`s.
+    pub fn from_config_program(program: &OwnedConfigProgram) -> YamlConfig {
+        let path_str =
+            |path: &OwnedPath| String::from_utf8_lossy(path.as_bytes()).into_owned();
+
+        let instructions = program
+            .0
+            .iter()
+            .map(|instr| match instr {
+                ConfigInstruction::Reveal(RevealInstruction { hash, to }) => {
+                    Instr::Reveal(RevealArgs {
+                        reveal: hex::encode(hash),
+                        to: path_str(to),
+                    })
+                }
+                ConfigInstruction::Move(MoveInstruction { from, to }) => {
+                    Instr::Move(MoveArgs {
+                        from: path_str(from),
+                        to: path_str(to),
+                    })
+                }
+                ConfigInstruction::Set(SetInstruction { value, to }) => {
+                    Instr::Set(SetArgs {
+                        value: hex::encode(value),
+                        to: path_str(to),
+                    })
+                }
+            })
+            .collect();
+
+        YamlConfig { instructions }
+    }
 }
 
 #[cfg(test)]
@@ -130,6 +169,18 @@ mod test {
         );
     }
 
+    #[test]
+    fn convert_program_to_yaml() {
+        let source_yaml = read_to_string("tests/resources/config_example1.yaml").unwrap();
+        let instrs = serde_yaml::from_str::<YamlConfig>(&source_yaml).unwrap();
+        let program = serde_yaml::from_str::<YamlConfig>(&source_yaml)
+            .unwrap()
+            .to_config_program(unreachable_content_to_preimages)
+            .unwrap();
+
+        assert_eq!(instrs, YamlConfig::from_config_program(&program));
+    }
+
     #[test]
     fn convert_invalid_reveal_hash_size() {
         let source_yaml =
@@ -11,7 +11,7 @@
 use crate::state::{HostState, NextInput};
 use crate::MockHost;
 use core::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     ptr,
     slice::{from_raw_parts, from_raw_parts_mut},
 };
@@ -25,6 +25,11 @@ impl From<HostState> for MockHost {
         Self {
             info: super::info_for_level(state.curr_level as i32),
             state: RefCell::new(state),
+            tick_model: None,
+            run_ticks: Cell::new(None),
+            last_run_ticks: 0,
+            last_level_kernel_runs: 0,
+            durable_history: None,
         }
     }
 }
@@ -42,9 +47,10 @@ unsafe impl SmartRollupCore for MockHost {
         dst: *mut u8,
         max_bytes: usize,
     ) -> i32 {
-        if let Some(NextInput { level, id, payload }) =
-            self.state.borrow_mut().handle_read_input(max_bytes)
-        {
+        let next_input = self.state.borrow_mut().handle_read_input(max_bytes);
+        self.consume_ticks(next_input.as_ref().map_or(0, |i| i.payload.len()));
+
+        if let Some(NextInput { level, id, payload }) = next_input {
             let input_message_info = ReadInputMessageInfo {
                 level: level as i32,
                 id: id as i32,
@@ -62,6 +68,8 @@ unsafe impl SmartRollupCore for MockHost {
     }
 
     unsafe fn write_debug(&self, src: *const u8, num_bytes: usize) {
+        self.consume_ticks(num_bytes);
+
         let debug_out = from_raw_parts(src, num_bytes).to_vec();
 
         let debug = String::from_utf8(debug_out).expect("unexpected non-utf8 debug log");
@@ -70,6 +78,8 @@ unsafe impl SmartRollupCore for MockHost {
     }
 
     unsafe fn write_output(&self, src: *const u8, num_bytes: usize) -> i32 {
+        self.consume_ticks(num_bytes);
+
         let output = from_raw_parts(src, num_bytes).to_vec();
 
         self.state
@@ -80,6 +90,7 @@ unsafe impl SmartRollupCore for MockHost {
     }
 
     unsafe fn store_has(&self, path: *const u8, len: usize) -> i32 {
+        self.consume_ticks(len);
         self.state.borrow().store.store_has(path, len)
     }
 
@@ -91,10 +102,13 @@ unsafe impl SmartRollupCore for MockHost {
         dst: *mut u8,
         max_bytes: usize,
     ) -> i32 {
-        self.state
+        let res = self
+            .state
             .borrow()
             .store
-            .store_read(path, len, offset, dst, max_bytes)
+            .store_read(path, len, offset, dst, max_bytes);
+        self.consume_ticks(len + res.max(0) as usize);
+        res
     }
 
     unsafe fn store_write(
@@ -105,6 +119,7 @@ unsafe impl SmartRollupCore for MockHost {
         src: *const u8,
         num_bytes: usize,
     ) -> i32 {
+        self.consume_ticks(len + num_bytes);
         self.state
             .borrow_mut()
             .store
@@ -112,14 +127,17 @@ unsafe impl SmartRollupCore for MockHost {
     }
 
     unsafe fn store_delete(&self, path: *const u8, len: usize) -> i32 {
+        self.consume_ticks(len);
         self.state.borrow_mut().store.store_delete(path, len)
     }
 
     unsafe fn store_delete_value(&self, path: *const u8, len: usize) -> i32 {
+        self.consume_ticks(len);
         self.state.borrow_mut().store.store_delete_value(path, len)
     }
 
     unsafe fn store_list_size(&self, path: *const u8, len: usize) -> i64 {
+        self.consume_ticks(len);
         self.state.borrow().store.store_list_size(path, len)
     }
 
@@ -130,6 +148,7 @@ unsafe impl SmartRollupCore for MockHost {
         to_path: *const u8,
         to_path_len: usize,
     ) -> i32 {
+        self.consume_ticks(from_path_len + to_path_len);
         self.state.borrow_mut().store.store_move(
             from_path,
             from_path_len,
@@ -145,6 +164,7 @@ unsafe impl SmartRollupCore for MockHost {
         to_path: *const u8,
         to_path_len: usize,
     ) -> i32 {
+        self.consume_ticks(from_path_len + to_path_len);
         self.state.borrow_mut().store.store_copy(
             from_path,
             from_path_len,
@@ -172,6 +192,7 @@ unsafe impl SmartRollupCore for MockHost {
             .to_vec();
 
         assert!(bytes.len() <= max_bytes);
+        self.consume_ticks(hash_len + bytes.len());
 
         let slice = from_raw_parts_mut(destination_addr, bytes.len());
         slice.copy_from_slice(bytes.as_slice());
@@ -180,11 +201,13 @@ unsafe impl SmartRollupCore for MockHost {
     }
 
     unsafe fn store_value_size(&self, path: *const u8, path_len: usize) -> i32 {
+        self.consume_ticks(path_len);
         self.state.borrow().store.store_value_size(path, path_len)
     }
 
     unsafe fn reveal_metadata(&self, destination_addr: *mut u8, max_bytes: usize) -> i32 {
         assert!(METADATA_SIZE <= max_bytes);
+        self.consume_ticks(METADATA_SIZE);
         let metadata: [u8; METADATA_SIZE] =
             self.state.borrow().get_metadata().clone().into();
         let slice = from_raw_parts_mut(destination_addr, metadata.len());
@@ -195,13 +218,27 @@ unsafe impl SmartRollupCore for MockHost {
     #[cfg(feature = "proto-alpha")]
     unsafe fn reveal(
         &self,
-        _payload_addr: *const u8,
-        _payload_len: usize,
-        _destination_addr: *mut u8,
-        _max_bytes: usize,
+        payload_addr: *const u8,
+        payload_len: usize,
+        destination_addr: *mut u8,
+        max_bytes: usize,
     ) -> i32 {
-        // TODO: https://gitlab.com/tezos/tezos/-/issues/6171
-        unimplemented!("The `reveal` host function is not yet mocked.")
+        let payload = from_raw_parts(payload_addr, payload_len);
+
+        let result = self.state.borrow().handle_reveal(payload, max_bytes);
+        self.consume_ticks(payload_len + result.as_ref().map_or(0, Vec::len));
+
+        match result {
+            Ok(bytes) => {
+                assert!(bytes.len() <= max_bytes);
+
+                let slice = from_raw_parts_mut(destination_addr, bytes.len());
+                slice.copy_from_slice(bytes.as_slice());
+
+                bytes.len().try_into().unwrap()
+            }
+            Err(e) => e.code(),
+        }
     }
 }
 
@@ -212,6 +249,8 @@ mod tests {
 
     use crate::state::HostState;
     use tezos_smart_rollup_core::{MAX_FILE_CHUNK_SIZE, MAX_INPUT_MESSAGE_SIZE};
+    #[cfg(feature = "proto-alpha")]
+    use tezos_smart_rollup_host::dal_parameters::RollupDalParameters;
     use tezos_smart_rollup_host::input::Message;
     use tezos_smart_rollup_host::{
         metadata::RollupMetadata,
@@ -285,6 +324,128 @@ mod tests {
         assert_eq!(expected_metadata, result);
     }
 
+    #[cfg(feature = "proto-alpha")]
+    #[test]
+    fn test_reveal_dal_page() {
+        // Arrange
+        let mut mock_host = MockHost::default();
+        let parameters = mock_host.dal_parameters();
+        let page_size = parameters.page_size as usize;
+
+        let content: Vec<u8> = (0..page_size + 10).map(|i| i as u8).collect();
+        mock_host.set_dal_slot(10, 1, &content);
+
+        let mut buffer = vec![0; page_size];
+
+        // Act & Assert
+        let result = mock_host.reveal_dal_page(10, 1, 0, &mut buffer);
+        assert_eq!(Ok(page_size), result);
+        assert_eq!(content[..page_size], buffer);
+
+        let result = mock_host.reveal_dal_page(10, 1, 1, &mut buffer);
+        assert_eq!(Ok(page_size), result);
+        assert_eq!(content[page_size..], buffer[..10]);
+        assert!(buffer[10..].iter().all(|b| *b == 0));
+
+        // Pages past the end of the slot, or of unpublished slots, are empty.
+        let num_pages = (parameters.slot_size / parameters.page_size) as i16;
+        let result = mock_host.reveal_dal_page(10, 1, num_pages, &mut buffer);
+        assert_eq!(Ok(0), result);
+
+        let result = mock_host.reveal_dal_page(10, 2, 0, &mut buffer);
+        assert_eq!(Ok(0), result);
+
+        let result = mock_host.reveal_dal_page(11, 1, 0, &mut buffer);
+        assert_eq!(Ok(0), result);
+    }
+
+    #[cfg(feature = "proto-alpha")]
+    #[test]
+    fn test_reveal_dal_parameters() {
+        // Arrange
+        let mut mock_host = MockHost::default();
+        let expected = RollupDalParameters {
+            number_of_slots: 16,
+            attestation_lag: 4,
+            slot_size: 4096,
+            page_size: 128,
+        };
+        mock_host.set_dal_parameters(expected.clone());
+
+        // Act
+        let result = mock_host.reveal_dal_parameters();
+
+        // Assert
+        assert_eq!(expected, result);
+    }
+
+    #[cfg(feature = "proto-alpha")]
+    #[test]
+    fn test_reveal_raw_requests() {
+        use tezos_smart_rollup_host::metadata::METADATA_SIZE;
+        use tezos_smart_rollup_host::Error;
+
+        fn reveal(host: &MockHost, payload: &[u8], buffer: &mut [u8]) -> i32 {
+            unsafe {
+                tezos_smart_rollup_core::smart_rollup_core::SmartRollupCore::reveal(
+                    host,
+                    payload.as_ptr(),
+                    payload.len(),
+                    buffer.as_mut_ptr(),
+                    buffer.len(),
+                )
+            }
+        }
+
+        // Arrange
+        let mut mock_host = MockHost::default();
+        let hash = mock_host.set_preimage(vec![b'a'; 100]);
+
+        let mut buffer = [0; 50];
+
+        // Act & Assert
+        let payload = [&[0u8][..], &hash[..]].concat();
+        assert_eq!(50, reveal(&mock_host, &payload, &mut buffer));
+        assert_eq!([b'a'; 50], buffer);
+
+        assert_eq!(METADATA_SIZE as i32, reveal(&mock_host, &[1], &mut buffer));
+        let metadata: [u8; METADATA_SIZE] = buffer[..METADATA_SIZE].try_into().unwrap();
+        assert_eq!(mock_host.reveal_metadata(), RollupMetadata::from(metadata));
+
+        // Unknown tags and malformed requests are rejected.
+        for payload in [&[4u8][..], &[2, 0, 0], &[0, 1, 2, 3]] {
+            assert_eq!(
+                Error::GenericInvalidAccess.code(),
+                reveal(&mock_host, payload, &mut buffer)
+            );
+        }
+    }
+
+    #[test]
+    fn durable_history_between_levels() {
+        // Arrange
+        const PATH: RefPath<'static> = RefPath::assert_from(b"/some/path");
+        let mut mock = MockHost::default();
+        mock.record_durable_history();
+
+        // Act
+        let first = mock.run_level(|host| host.store_write(&PATH, &[1], 0).unwrap());
+        let second = mock.run_level(|host| host.store_write(&PATH, &[2, 3], 0).unwrap());
+
+        // Assert
+        let diff = mock.durable_diff_between_levels(first, second).unwrap();
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        assert_eq!(1, diff.changed.len());
+        assert_eq!("/some/path", diff.changed[0].path);
+        assert_eq!((1, 2), (diff.changed[0].old_size, diff.changed[0].new_size));
+
+        // The reboot counter is rewritten every level, but with the same value.
+        let snapshot = mock.durable_at_level(first).unwrap();
+        mock.rollback_durable(&snapshot);
+        assert_eq!(Ok(vec![1]), mock.store_read_all(&PATH));
+        assert_eq!(None, mock.durable_at_level(second + 1));
+    }
+
     #[test]
     fn read_value_slice_not_found() {
         let mock = MockHost::default();
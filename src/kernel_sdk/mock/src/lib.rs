@@ -8,6 +8,7 @@
 
 mod host;
 mod state;
+mod ticks;
 
 extern crate tezos_crypto_rs as crypto;
 
@@ -22,10 +23,14 @@ use tezos_smart_rollup_encoding::michelson::MichelsonUnit;
 use tezos_smart_rollup_encoding::public_key_hash::PublicKeyHash;
 use tezos_smart_rollup_encoding::smart_rollup::SmartRollupAddress;
 use tezos_smart_rollup_encoding::timestamp::Timestamp;
+use tezos_smart_rollup_host::dal_parameters::RollupDalParameters;
 use tezos_smart_rollup_host::metadata::RollupMetadata;
 
 use state::HostState;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use ticks::TooManyTicks;
 
 const MAXIMUM_REBOOTS_PER_INPUT: i32 = 1000;
 
@@ -39,18 +44,36 @@ const TOO_MANY_REBOOT_FLAG_KEY: &str = "/readonly/kernel/env/too_many_reboot";
 /// with a given inbox. Written as i32 (little-endian).
 const REBOOT_COUNTER_KEY: &str = "/readonly/kernel/env/reboot_counter";
 
+/// Flag set by the PVM when the last `kernel_run` was aborted, cleared by the
+/// next successful one.
+const STUCK_FLAG_KEY: &str = "/readonly/kernel/env/stuck";
+
 const NAIROBI_ACTIVATION_LEVEL: u32 = 3_760_129;
 const NAIROBI_BLOCK_TIME: i64 = 15;
 // Nairobi activated approximately at 0:07AM UTC on June 24th 2023.
 const NAIROBI_ACTIVATION_TIMESTAMP: i64 = 1_687_561_630;
 
-pub use state::InMemoryStore;
+// Default DAL parameters, as used on mainnet.
+const DAL_NUMBER_OF_SLOTS: u64 = 32;
+const DAL_ATTESTATION_LAG: u64 = 8;
+const DAL_SLOT_SIZE: u64 = 126_944;
+const DAL_PAGE_SIZE: u64 = 3967;
+
+pub use state::{DurableDiff, DurableSnapshot, InMemoryStore, PathChange, PathSize};
+pub use ticks::{TickModel, MAX_TICKS_PER_KERNEL_RUN};
 
 /// The runtime host when _not_ running in **wasm**.
 #[derive(Debug)]
 pub struct MockHost {
     state: RefCell<HostState>,
     info: inbox::InfoPerLevel,
+    tick_model: Option<TickModel>,
+    // Ticks consumed by the ongoing `kernel_run`, if any.
+    run_ticks: Cell<Option<u64>>,
+    last_run_ticks: u64,
+    last_level_kernel_runs: u32,
+    // Snapshots of the durable storage after each level, when recorded.
+    durable_history: Option<BTreeMap<u32, DurableSnapshot>>,
 }
 
 impl Default for MockHost {
@@ -123,6 +146,11 @@ impl MockHost {
         let mut host = Self {
             state: state.into(),
             info,
+            tick_model: None,
+            run_ticks: Cell::new(None),
+            last_run_ticks: 0,
+            last_level_kernel_runs: 0,
+            durable_history: None,
         };
 
         // Ensure inbox setup correctly
@@ -173,24 +201,71 @@ impl MockHost {
         self.as_mut().set_preimage(preimage)
     }
 
+    /// Set the DAL parameters returned by `reveal_dal_parameters`.
+    ///
+    /// Slots published afterwards are split into pages according to these
+    /// parameters.
+    pub fn set_dal_parameters(&mut self, parameters: RollupDalParameters) {
+        self.as_mut().dal_parameters = parameters;
+    }
+
+    /// Returns the DAL parameters revealed to the kernel.
+    pub fn dal_parameters(&self) -> RollupDalParameters {
+        self.state.borrow().dal_parameters.clone()
+    }
+
+    /// Publish the contents of a DAL slot at `published_level`, and consider it attested.
+    ///
+    /// Its pages are then available to `reveal_dal_page`, the content being
+    /// zero-padded up to the slot size. Pages of slots which were not
+    /// published are revealed as empty, as for non-attested slots.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `slot_index` is not a valid slot, or if `content` is larger
+    /// than the slot size.
+    pub fn set_dal_slot(&mut self, published_level: u32, slot_index: u8, content: &[u8]) {
+        self.as_mut()
+            .set_dal_slot(published_level as i32, slot_index, content.to_vec())
+    }
+
+    /// Set the tick model used to account for the ticks consumed by each
+    /// `kernel_run` in [`MockHost::run_level`].
+    ///
+    /// With no tick model (the default), ticks are not accounted for.
+    pub fn set_tick_model(&mut self, tick_model: Option<TickModel>) {
+        self.tick_model = tick_model;
+    }
+
     /// Runs `kernel_run` against the current level's inbox.
     ///
     /// - Includes the `StartOfLevel`, `InfoPerLevel` & `EndOfLevel` messages.
+    /// - Reruns `kernel_run` as long as a reboot is requested, up to the
+    ///   reboot limit of the PVM.
+    /// - When a [`TickModel`] is set, a `kernel_run` exceeding its tick budget
+    ///   is aborted: its changes to the durable storage & outbox are reverted,
+    ///   and the kernel is not run again until the next level. This is
+    ///   reported to the kernel by `Runtime::last_run_aborted`.
     /// - Returns the level the kernel was run at.
     pub fn run_level(&mut self, kernel_run: fn(&mut Self)) -> u32 {
         self.finalise_inputs();
 
         let mut reboots = MAXIMUM_REBOOTS_PER_INPUT;
+        self.last_level_kernel_runs = 0;
 
         loop {
             let bytes = reboots.to_le_bytes().to_vec();
             self.as_mut().store.0.set_value(REBOOT_COUNTER_KEY, bytes);
 
-            kernel_run(self);
+            let aborted = self.kernel_run_with_tick_budget(kernel_run);
             self.as_mut().store.0.node_delete(TOO_MANY_REBOOT_FLAG_KEY);
 
             reboots -= 1;
 
+            if aborted {
+                break;
+            }
+
             let reboot_requested = self
                 .as_mut()
                 .store
@@ -214,11 +289,58 @@ impl MockHost {
         }
 
         let level_ran_at = self.state.borrow().curr_level;
+        if let Some(history) = self.durable_history.as_mut() {
+            history.insert(level_ran_at, self.state.borrow().store.snapshot());
+        }
         self.bump_level();
 
         level_ran_at
     }
 
+    /// Ticks consumed by the last `kernel_run` of [`MockHost::run_level`].
+    ///
+    /// Always zero when no [`TickModel`] is set.
+    pub fn last_run_ticks(&self) -> u64 {
+        self.last_run_ticks
+    }
+
+    /// Number of times `kernel_run` was called during the last
+    /// [`MockHost::run_level`], i.e. one more than the number of reboots.
+    pub fn last_level_kernel_runs(&self) -> u32 {
+        self.last_level_kernel_runs
+    }
+
+    /// Take a snapshot of the durable storage.
+    pub fn snapshot_durable(&self) -> DurableSnapshot {
+        self.state.borrow().store.snapshot()
+    }
+
+    /// Restore the durable storage to a previous snapshot.
+    ///
+    /// The inbox, outbox and current level are left unchanged.
+    pub fn rollback_durable(&mut self, snapshot: &DurableSnapshot) {
+        self.as_mut().store.rollback(snapshot)
+    }
+
+    /// Record a snapshot of the durable storage at the end of every subsequent
+    /// [`MockHost::run_level`], to be retrieved with [`MockHost::durable_at_level`].
+    pub fn record_durable_history(&mut self) {
+        self.durable_history.get_or_insert_with(Default::default);
+    }
+
+    /// Snapshot of the durable storage at the end of the given level, if recorded.
+    pub fn durable_at_level(&self, level: u32) -> Option<DurableSnapshot> {
+        self.durable_history.as_ref()?.get(&level).cloned()
+    }
+
+    /// Changes made to the durable storage between the end of level `from` and
+    /// the end of level `to`, if both were recorded.
+    pub fn durable_diff_between_levels(&self, from: u32, to: u32) -> Option<DurableDiff> {
+        let from = self.durable_at_level(from)?;
+        let to = self.durable_at_level(to)?;
+        Some(from.diff(&to))
+    }
+
     /// Returns the level of the next `kernel_run`.
     pub fn level(&self) -> u32 {
         self.state.borrow().curr_level
@@ -234,6 +356,55 @@ impl MockHost {
         self.state.borrow().store.0.outbox_at(level).to_vec()
     }
 
+    /// Runs `kernel_run` once, charging the host function calls it makes
+    /// against the tick budget.
+    ///
+    /// Returns `true` if the run was aborted, in which case its changes to
+    /// the durable storage & outbox are reverted.
+    fn kernel_run_with_tick_budget(&mut self, kernel_run: fn(&mut Self)) -> bool {
+        // Runs can only be aborted when ticks are accounted for.
+        let snapshot = self
+            .tick_model
+            .is_some()
+            .then(|| self.state.borrow().store.clone());
+
+        self.run_ticks.set(Some(0));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| kernel_run(self)));
+        self.last_run_ticks = self.run_ticks.take().unwrap_or_default();
+        self.last_level_kernel_runs += 1;
+
+        match result {
+            Ok(()) => {
+                self.as_mut().store.0.node_delete(STUCK_FLAG_KEY);
+                false
+            }
+            Err(payload) if payload.is::<TooManyTicks>() => {
+                if let Some(store) = snapshot {
+                    self.as_mut().store = store;
+                }
+                self.as_mut().store.0.set_value(STUCK_FLAG_KEY, vec![]);
+                true
+            }
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+
+    /// Charges a host function call copying `num_bytes` to the ongoing
+    /// `kernel_run`, aborting it if its tick budget is exceeded.
+    pub(crate) fn consume_ticks(&self, num_bytes: usize) {
+        if let (Some(tick_model), Some(ticks)) = (&self.tick_model, self.run_ticks.get())
+        {
+            let ticks = ticks.saturating_add(tick_model.host_call_ticks(num_bytes));
+            self.run_ticks.set(Some(ticks));
+
+            if ticks > tick_model.max_ticks_per_run {
+                // Does not run the panic hook, as this is not an error of the
+                // kernel under test.
+                panic::resume_unwind(Box::new(TooManyTicks));
+            }
+        }
+    }
+
     fn bump_level(&mut self) {
         let state = self.as_mut();
         state.curr_level += 1;
@@ -273,6 +444,15 @@ impl MockHost {
     }
 }
 
+fn default_dal_parameters() -> RollupDalParameters {
+    RollupDalParameters {
+        number_of_slots: DAL_NUMBER_OF_SLOTS,
+        attestation_lag: DAL_ATTESTATION_LAG,
+        slot_size: DAL_SLOT_SIZE,
+        page_size: DAL_PAGE_SIZE,
+    }
+}
+
 fn info_for_level(level: i32) -> inbox::InfoPerLevel {
     let timestamp = (level as i64 - 1 - (NAIROBI_ACTIVATION_LEVEL as i64))
         * NAIROBI_BLOCK_TIME
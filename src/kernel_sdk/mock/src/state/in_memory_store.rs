@@ -4,6 +4,7 @@
 //
 // SPDX-License-Identifier: MIT
 
+use super::snapshot::DurableSnapshot;
 use super::store::Store;
 use core::slice::{from_raw_parts, from_raw_parts_mut};
 use tezos_smart_rollup_core::MAX_FILE_CHUNK_SIZE;
@@ -128,6 +129,18 @@ impl InMemoryStore {
     }
 }
 
+impl InMemoryStore {
+    /// Take a snapshot of the durable storage.
+    pub fn snapshot(&self) -> DurableSnapshot {
+        DurableSnapshot(self.0.durable.clone())
+    }
+
+    /// Restore the durable storage to a previous snapshot.
+    pub fn rollback(&mut self, snapshot: &DurableSnapshot) {
+        self.0.durable = snapshot.0.clone();
+    }
+}
+
 fn validate_path(s: &[u8]) -> Result<String, Error> {
     match RefPath::try_from(s) {
         Err(PathError::PathTooLong) => Err(Error::StoreKeyTooLarge),
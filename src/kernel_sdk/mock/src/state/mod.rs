@@ -10,15 +10,33 @@ use crypto::hash::SmartRollupHash;
 use tezos_smart_rollup_core::{
     MAX_INPUT_MESSAGE_SIZE, MAX_OUTPUT_SIZE, PREIMAGE_HASH_SIZE,
 };
-use tezos_smart_rollup_host::{metadata::RollupMetadata, Error};
+use tezos_smart_rollup_host::{
+    dal_parameters::RollupDalParameters, metadata::RollupMetadata, Error,
+};
 
 pub(crate) mod in_memory_store;
+pub(crate) mod snapshot;
 pub(crate) mod store;
 
 pub use self::in_memory_store::InMemoryStore;
+pub use self::snapshot::{DurableDiff, DurableSnapshot, PathChange, PathSize};
 
 const MAX_OUTPUTS_PER_LEVEL: usize = 100;
 
+// Tags of the reveal requests, matching the encoding declared in the Tezos protocol.
+#[cfg(feature = "proto-alpha")]
+const REVEAL_RAW_DATA_TAG: u8 = 0;
+#[cfg(feature = "proto-alpha")]
+const REVEAL_METADATA_TAG: u8 = 1;
+#[cfg(feature = "proto-alpha")]
+const REVEAL_DAL_PAGE_TAG: u8 = 2;
+#[cfg(feature = "proto-alpha")]
+const REVEAL_DAL_PARAMETERS_TAG: u8 = 3;
+
+/// Size of a DAL page request: published level (i32), slot index (u8) & page index (i16).
+#[cfg(feature = "proto-alpha")]
+const DAL_PAGE_REQUEST_SIZE: usize = 4 + 1 + 2;
+
 #[derive(Debug, PartialEq, Eq)]
 pub(crate) struct NextInput {
     pub level: u32,
@@ -32,6 +50,7 @@ pub(crate) struct HostState {
     /// Key-value store of runtime state.
     pub store: InMemoryStore,
     pub metadata: RollupMetadata,
+    pub dal_parameters: RollupDalParameters,
     // Inbox metadata
     pub(crate) curr_level: u32,
     pub(crate) curr_input_id: usize,
@@ -55,6 +74,7 @@ impl Default for HostState {
         Self {
             store,
             metadata,
+            dal_parameters: crate::default_dal_parameters(),
             curr_level: crate::NAIROBI_ACTIVATION_LEVEL,
             curr_input_id: 0,
             input: vec![],
@@ -133,6 +153,110 @@ impl HostState {
     pub(crate) fn get_metadata(&self) -> &RollupMetadata {
         &self.metadata
     }
+
+    pub(crate) fn set_dal_slot(
+        &mut self,
+        published_level: i32,
+        slot_index: u8,
+        mut content: Vec<u8>,
+    ) {
+        let RollupDalParameters {
+            number_of_slots,
+            slot_size,
+            ..
+        } = self.dal_parameters;
+
+        if u64::from(slot_index) >= number_of_slots {
+            panic!(
+                "slot index out of bounds -index:{} -number_of_slots:{}",
+                slot_index, number_of_slots
+            );
+        }
+
+        if content.len() as u64 > slot_size {
+            panic!(
+                "slot content too big -size:{} -max:{}",
+                content.len(),
+                slot_size
+            );
+        }
+
+        // Slots are always published in full: unused space is zero-filled.
+        content.resize(slot_size as usize, 0);
+        self.store
+            .0
+            .add_dal_slot(published_level, slot_index, content);
+    }
+}
+
+#[cfg(feature = "proto-alpha")]
+impl HostState {
+    /// Returns the contents of the requested page, or an empty page if the
+    /// slot was not published (or attested) at the given level.
+    pub(crate) fn handle_reveal_dal_page(
+        &self,
+        published_level: i32,
+        slot_index: u8,
+        page_index: i16,
+    ) -> &[u8] {
+        let page_size = self.dal_parameters.page_size as usize;
+
+        if page_index < 0 {
+            return &[];
+        }
+        let page_index = page_index as usize;
+
+        self.store
+            .0
+            .retrieve_dal_slot(published_level, slot_index)
+            .and_then(|slot| {
+                slot.get(page_index * page_size..(page_index + 1) * page_size)
+            })
+            .unwrap_or(&[])
+    }
+
+    /// Answers a raw reveal request, as sent to the `reveal` host function.
+    ///
+    /// The response is truncated to `max_bytes`.
+    pub(crate) fn handle_reveal(
+        &self,
+        payload: &[u8],
+        max_bytes: usize,
+    ) -> Result<Vec<u8>, Error> {
+        let response = match payload.split_first() {
+            Some((&REVEAL_RAW_DATA_TAG, hash)) => {
+                let hash = hash.try_into().map_err(|_| Error::GenericInvalidAccess)?;
+                self.handle_reveal_preimage(hash, max_bytes).to_vec()
+            }
+            Some((&REVEAL_METADATA_TAG, [])) => {
+                let metadata: [u8; tezos_smart_rollup_host::METADATA_SIZE] =
+                    self.metadata.clone().into();
+                metadata.to_vec()
+            }
+            Some((&REVEAL_DAL_PAGE_TAG, request))
+                if request.len() == DAL_PAGE_REQUEST_SIZE =>
+            {
+                let (published_level, request) = request.split_at(4);
+                let (slot_index, page_index) = request.split_at(1);
+
+                let published_level =
+                    i32::from_be_bytes(published_level.try_into().unwrap());
+                let page_index = i16::from_be_bytes(page_index.try_into().unwrap());
+
+                self.handle_reveal_dal_page(published_level, slot_index[0], page_index)
+                    .to_vec()
+            }
+            Some((&REVEAL_DAL_PARAMETERS_TAG, [])) => {
+                let parameters: [u8; tezos_smart_rollup_host::DAL_PARAMETERS_SIZE] =
+                    self.dal_parameters.clone().into();
+                parameters.to_vec()
+            }
+            _ => return Err(Error::GenericInvalidAccess),
+        };
+
+        let len = usize::min(max_bytes, response.len());
+        Ok(response[..len].to_vec())
+    }
 }
 
 #[cfg(test)]
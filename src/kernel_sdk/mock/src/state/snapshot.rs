@@ -0,0 +1,264 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Snapshots of the durable storage, and diffs between them.
+
+use super::store::{Node, Store, VALUE_NAME};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::rc::Rc;
+
+/// An immutable copy of the durable storage, at a given point in time.
+///
+/// Snapshots share their unchanged subtrees with the store they were taken
+/// from, and are therefore cheap to take.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DurableSnapshot(pub(crate) Rc<Node>);
+
+/// A value of the durable storage, and its size in bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathSize {
+    /// Path of the value.
+    pub path: String,
+    /// Size of the value, in bytes.
+    pub size: usize,
+}
+
+/// A value of the durable storage modified between two snapshots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathChange {
+    /// Path of the value.
+    pub path: String,
+    /// Size of the value in the older snapshot, in bytes.
+    pub old_size: usize,
+    /// Size of the value in the newer snapshot, in bytes.
+    pub new_size: usize,
+}
+
+/// Differences between the values of two [`DurableSnapshot`]s.
+///
+/// Each list is ordered by path.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DurableDiff {
+    /// Values present only in the newer snapshot.
+    pub added: Vec<PathSize>,
+    /// Values present only in the older snapshot.
+    pub removed: Vec<PathSize>,
+    /// Values present in both snapshots, with different contents.
+    pub changed: Vec<PathChange>,
+}
+
+impl DurableSnapshot {
+    /// Compute the changes needed to go from `self` to the `newer` snapshot.
+    ///
+    /// Subtrees shared by both snapshots are skipped, so the cost of a diff is
+    /// proportional to the amount of changes rather than to the storage size.
+    pub fn diff(&self, newer: &DurableSnapshot) -> DurableDiff {
+        let mut diff = DurableDiff::default();
+        diff_nodes("", Some(&self.0), Some(&newer.0), &mut diff);
+        diff
+    }
+
+    /// Write all the values of the snapshot to a file.
+    ///
+    /// Each value is written on its own line, as its path followed by its
+    /// hex-encoded contents.
+    pub fn dump(&self, file: impl AsRef<std::path::Path>) -> io::Result<()> {
+        fs::write(file, self.0.to_string())
+    }
+
+    /// Read a snapshot from a file written by [`DurableSnapshot::dump`].
+    pub fn load(file: impl AsRef<std::path::Path>) -> io::Result<Self> {
+        let contents = fs::read_to_string(file)?;
+        let mut store = Store::default();
+
+        for line in contents.lines() {
+            let (path, value) = line
+                .split_once(' ')
+                .ok_or_else(|| invalid_data(format!("missing value: {}", line)))?;
+            let value = hex::decode(value).map_err(|e| invalid_data(e.to_string()))?;
+            store.set_value(path, value);
+        }
+
+        Ok(Self(store.durable))
+    }
+}
+
+impl DurableDiff {
+    /// Returns `true` if both snapshots have the same values.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl std::fmt::Display for DurableDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for PathSize { path, size } in self.added.iter() {
+            writeln!(f, "+ {} ({} bytes)", path, size)?;
+        }
+        for PathSize { path, size } in self.removed.iter() {
+            writeln!(f, "- {} ({} bytes)", path, size)?;
+        }
+        for PathChange {
+            path,
+            old_size,
+            new_size,
+        } in self.changed.iter()
+        {
+            writeln!(f, "~ {} ({} -> {} bytes)", path, old_size, new_size)?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid_data(error: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn diff_nodes(
+    path: &str,
+    old: Option<&Rc<Node>>,
+    new: Option<&Rc<Node>>,
+    diff: &mut DurableDiff,
+) {
+    if let (Some(old), Some(new)) = (old, new) {
+        if Rc::ptr_eq(old, new) {
+            return;
+        }
+    }
+
+    let old_value = old.and_then(|n| n.value.as_ref());
+    let new_value = new.and_then(|n| n.value.as_ref());
+
+    match (old_value, new_value) {
+        (None, None) => (),
+        (None, Some(new)) => diff.added.push(PathSize {
+            path: path.to_string(),
+            size: new.len(),
+        }),
+        (Some(old), None) => diff.removed.push(PathSize {
+            path: path.to_string(),
+            size: old.len(),
+        }),
+        (Some(old), Some(new)) => {
+            if old != new {
+                diff.changed.push(PathChange {
+                    path: path.to_string(),
+                    old_size: old.len(),
+                    new_size: new.len(),
+                })
+            }
+        }
+    }
+
+    let keys: BTreeSet<&String> = old
+        .into_iter()
+        .chain(new)
+        .flat_map(|n| n.inner.keys())
+        .filter(|k| *k != VALUE_NAME)
+        .collect();
+
+    for key in keys {
+        let path = format!("{}/{}", path, key);
+        diff_nodes(
+            &path,
+            old.and_then(|n| n.inner.get(key)),
+            new.and_then(|n| n.inner.get(key)),
+            diff,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::InMemoryStore;
+
+    fn path_size(path: &str, size: usize) -> PathSize {
+        PathSize {
+            path: path.to_string(),
+            size,
+        }
+    }
+
+    #[test]
+    fn diff_snapshots() {
+        // Arrange
+        let mut store = InMemoryStore::default();
+        store.handle_store_write(b"/a/b", 0, &[1, 2]).unwrap();
+        store.handle_store_write(b"/a/c", 0, &[3]).unwrap();
+        store.handle_store_write(b"/d", 0, &[4, 5, 6]).unwrap();
+
+        let before = store.snapshot();
+
+        store.handle_store_write(b"/a/b", 0, &[7, 8, 9]).unwrap();
+        store.handle_store_delete(b"/a/c").unwrap();
+        store.handle_store_write(b"/e/f", 0, &[]).unwrap();
+
+        // Act
+        let after = store.snapshot();
+        let diff = before.diff(&after);
+
+        // Assert
+        assert_eq!(vec![path_size("/e/f", 0)], diff.added);
+        assert_eq!(vec![path_size("/a/c", 1)], diff.removed);
+        assert_eq!(
+            vec![PathChange {
+                path: "/a/b".to_string(),
+                old_size: 2,
+                new_size: 3
+            }],
+            diff.changed
+        );
+
+        assert!(after.diff(&after).is_empty());
+        assert_eq!(diff.added, after.diff(&before).removed);
+    }
+
+    #[test]
+    fn rollback_to_snapshot() {
+        // Arrange
+        let mut store = InMemoryStore::default();
+        store.handle_store_write(b"/a/b", 0, &[1, 2]).unwrap();
+        let snapshot = store.snapshot();
+
+        store.handle_store_write(b"/a/b", 0, &[3]).unwrap();
+        store.handle_store_write(b"/c", 0, &[4]).unwrap();
+
+        // Act
+        store.rollback(&snapshot);
+
+        // Assert
+        assert_eq!(Ok(vec![1, 2]), store.handle_store_read(b"/a/b", 0, 4096));
+        assert_eq!(
+            Ok(tezos_smart_rollup_core::VALUE_TYPE_NONE),
+            store.handle_store_has(b"/c")
+        );
+        assert!(snapshot.diff(&store.snapshot()).is_empty());
+    }
+
+    #[test]
+    fn dump_and_load() {
+        // Arrange
+        let mut store = InMemoryStore::default();
+        store.handle_store_write(b"/a/b", 0, &[1, 2]).unwrap();
+        store.handle_store_write(b"/a/b/c", 0, &[]).unwrap();
+        store.handle_store_write(b"/d", 0, &[255; 100]).unwrap();
+        let snapshot = store.snapshot();
+
+        let file = std::env::temp_dir().join(format!(
+            "tezos-smart-rollup-mock-dump-{}",
+            std::process::id()
+        ));
+
+        // Act
+        snapshot.dump(&file).unwrap();
+        let loaded = DurableSnapshot::load(&file);
+        std::fs::remove_file(&file).unwrap();
+
+        // Assert
+        assert_eq!(snapshot, loaded.unwrap());
+    }
+}
@@ -15,6 +15,7 @@ pub(crate) struct Store {
     preimages: HashMap<[u8; PREIMAGE_HASH_SIZE], Vec<u8>>,
     outbox: HashMap<u32, Vec<Vec<u8>>>,
     inbox: HashMap<u32, Vec<Vec<u8>>>,
+    dal_slots: HashMap<(i32, u8), Vec<u8>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
@@ -187,6 +188,20 @@ impl Store {
             .expect("Cannot retrieve preimage")
             .as_ref()
     }
+
+    pub fn add_dal_slot(&mut self, published_level: i32, slot_index: u8, slot: Vec<u8>) {
+        self.dal_slots.insert((published_level, slot_index), slot);
+    }
+
+    pub fn retrieve_dal_slot(
+        &self,
+        published_level: i32,
+        slot_index: u8,
+    ) -> Option<&[u8]> {
+        self.dal_slots
+            .get(&(published_level, slot_index))
+            .map(|s| s.as_ref())
+    }
 }
 
 fn path_steps(path: &str) -> impl Iterator<Item = &'_ str> {
@@ -0,0 +1,81 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Emulation of the tick accounting of the PVM.
+//!
+//! Native code has no notion of ticks, so only host function calls are
+//! charged. This is enough to catch kernels which do too much I/O in a
+//! single `kernel_run`, which is the usual cause of tick overruns.
+
+/// Maximum number of ticks of a `kernel_run` in the WASM PVM.
+pub const MAX_TICKS_PER_KERNEL_RUN: u64 = 11_000_000_000;
+
+// Benchmarked cost of copying a byte between the kernel memory and the host,
+// see `lib_webassembly/exec/tick_model.ml`.
+const TICKS_PER_BYTE: u64 = 42;
+
+// Flat cost of a host function call, covering the durable storage access.
+const TICKS_PER_HOST_CALL: u64 = 1_000;
+
+/// Tick cost model of the host functions, used by [`MockHost`].
+///
+/// [`MockHost`]: crate::MockHost
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TickModel {
+    /// Ticks charged for each host function call.
+    pub ticks_per_host_call: u64,
+    /// Ticks charged for each byte copied between the kernel and the host.
+    pub ticks_per_byte: u64,
+    /// Maximum number of ticks a single `kernel_run` can consume, before it
+    /// is aborted.
+    pub max_ticks_per_run: u64,
+}
+
+impl Default for TickModel {
+    fn default() -> Self {
+        Self {
+            ticks_per_host_call: TICKS_PER_HOST_CALL,
+            ticks_per_byte: TICKS_PER_BYTE,
+            max_ticks_per_run: MAX_TICKS_PER_KERNEL_RUN,
+        }
+    }
+}
+
+impl TickModel {
+    /// Create a tick model with default costs and the given budget per `kernel_run`.
+    pub fn with_max_ticks_per_run(max_ticks_per_run: u64) -> Self {
+        Self {
+            max_ticks_per_run,
+            ..Self::default()
+        }
+    }
+
+    /// Number of ticks consumed by a host function call copying `num_bytes`.
+    pub fn host_call_ticks(&self, num_bytes: usize) -> u64 {
+        self.ticks_per_byte
+            .saturating_mul(num_bytes as u64)
+            .saturating_add(self.ticks_per_host_call)
+    }
+}
+
+/// Unwinding payload used to abort a `kernel_run` which exceeded its tick budget.
+#[derive(Debug)]
+pub(crate) struct TooManyTicks;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_call_ticks_saturate() {
+        let model = TickModel::default();
+
+        assert_eq!(TICKS_PER_HOST_CALL, model.host_call_ticks(0));
+        assert_eq!(
+            TICKS_PER_HOST_CALL + 10 * TICKS_PER_BYTE,
+            model.host_call_ticks(10)
+        );
+        assert_eq!(u64::MAX, model.host_call_ticks(usize::MAX));
+    }
+}
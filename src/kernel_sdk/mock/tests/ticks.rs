@@ -0,0 +1,122 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Test of the tick accounting & reboot emulation of [MockHost::run_level].
+
+use tezos_smart_rollup_host::path::RefPath;
+use tezos_smart_rollup_host::runtime::Runtime;
+use tezos_smart_rollup_mock::{MockHost, TickModel};
+
+const COUNTER_PATH: RefPath = RefPath::assert_from(b"/counter");
+const DATA_PATH: RefPath = RefPath::assert_from(b"/data");
+
+fn read_counter(host: &impl Runtime) -> u32 {
+    host.store_read(&COUNTER_PATH, 0, 4)
+        .map(|bytes| bytes.try_into().unwrap_or_default())
+        .map(u32::from_le_bytes)
+        .unwrap_or_default()
+}
+
+fn increment_counter(host: &mut impl Runtime) {
+    let counter = read_counter(host) + 1;
+    host.store_write(&COUNTER_PATH, &counter.to_le_bytes(), 0)
+        .unwrap();
+}
+
+// Counts its runs, and writes 1KB at each of them, rebooting until 10 runs happened.
+fn write_and_reboot(host: &mut MockHost) {
+    increment_counter(host);
+    host.store_write(&DATA_PATH, &[1; 1024], 0).unwrap();
+
+    if read_counter(host) % 10 != 0 {
+        host.mark_for_reboot().unwrap();
+    }
+}
+
+// Writes to the storage forever.
+fn write_forever(host: &mut MockHost) {
+    increment_counter(host);
+    loop {
+        host.store_write(&DATA_PATH, &[2; 1024], 0).unwrap();
+    }
+}
+
+// Always asks for a reboot.
+fn reboot_forever(host: &mut MockHost) {
+    increment_counter(host);
+    host.mark_for_reboot().unwrap();
+}
+
+#[test]
+fn no_tick_accounting_by_default() {
+    let mut host = MockHost::default();
+
+    host.run_level(write_and_reboot);
+
+    assert_eq!(10, read_counter(&host));
+    assert_eq!(10, host.last_level_kernel_runs());
+    assert_eq!(0, host.last_run_ticks());
+    assert!(!host.last_run_aborted().unwrap());
+}
+
+#[test]
+fn ticks_accounted_per_kernel_run() {
+    let mut host = MockHost::default();
+    host.set_tick_model(Some(TickModel::default()));
+
+    host.run_level(write_and_reboot);
+
+    assert_eq!(10, read_counter(&host));
+    assert!(!host.last_run_aborted().unwrap());
+    // At least the 1KB written by the kernel are accounted for.
+    assert!(host.last_run_ticks() > TickModel::default().host_call_ticks(1024));
+}
+
+#[test]
+fn kernel_run_over_budget_is_aborted() {
+    let mut host = MockHost::default();
+    host.store_write(&DATA_PATH, &[0; 16], 0).unwrap();
+
+    let budget = 100 * TickModel::default().host_call_ticks(1024);
+    host.set_tick_model(Some(TickModel::with_max_ticks_per_run(budget)));
+
+    host.run_level(write_forever);
+
+    assert!(host.last_run_aborted().unwrap());
+    assert_eq!(1, host.last_level_kernel_runs());
+    assert!(host.last_run_ticks() > budget);
+
+    // Changes of the aborted run are reverted.
+    assert_eq!(0, read_counter(&host));
+    assert_eq!(vec![0; 16], host.store_read_all(&DATA_PATH).unwrap());
+
+    // The kernel is run again at the next level.
+    host.set_tick_model(None);
+    host.run_level(write_and_reboot);
+
+    assert!(!host.last_run_aborted().unwrap());
+    assert_eq!(10, read_counter(&host));
+}
+
+#[test]
+fn reboots_are_limited_per_level() {
+    const TOO_MANY_REBOOT: RefPath =
+        RefPath::assert_from(b"/readonly/kernel/env/too_many_reboot");
+
+    let mut host = MockHost::default();
+
+    host.run_level(reboot_forever);
+
+    // The first kernel run of the level, followed by the 999 reboots allowed
+    // by the PVM.
+    assert_eq!(1 + 999, host.last_level_kernel_runs());
+    assert_eq!(1 + 999, read_counter(&host));
+    assert!(host.store_has(&TOO_MANY_REBOOT).unwrap().is_some());
+
+    // The flag is visible to the first run of the next level only.
+    host.run_level(write_and_reboot);
+
+    assert_eq!(10, host.last_level_kernel_runs());
+    assert!(host.store_has(&TOO_MANY_REBOOT).unwrap().is_none());
+}
@@ -0,0 +1,300 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Key-value map in durable storage.
+
+use super::vec::{DurableVec, DurableVecIter};
+use super::{delete, field_path, read, read_optional, write, DurableEncoding};
+use crate::StorageError;
+use core::marker::PhantomData;
+use tezos_smart_rollup_host::path::{concat, OwnedPath, Path, RefPath};
+use tezos_smart_rollup_host::runtime::Runtime;
+
+const KEYS: RefPath = RefPath::assert_from(b"/keys");
+const ENTRIES: RefPath = RefPath::assert_from(b"/entries");
+const VALUE: RefPath = RefPath::assert_from(b"/value");
+const INDEX: RefPath = RefPath::assert_from(b"/index");
+
+/// Map from keys to values in durable storage.
+///
+/// The entry of a key is kept at `<path>/entries/0x<hex of the encoded key>`,
+/// which limits the size of the encoded keys to a bit less than half of
+/// [PATH_MAX_SIZE]. The keys are also kept in a [DurableVec] at
+/// `<path>/keys`, for iteration: iterating over a map yields its entries in
+/// insertion order, unless some were removed.
+///
+/// [PATH_MAX_SIZE]: tezos_smart_rollup_host::path::PATH_MAX_SIZE
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DurableMap<K, V> {
+    path: OwnedPath,
+    phantom: PhantomData<(K, V)>,
+}
+
+impl<K, V> From<OwnedPath> for DurableMap<K, V> {
+    fn from(path: OwnedPath) -> Self {
+        Self {
+            path,
+            phantom: PhantomData,
+        }
+    }
+}
+
+fn hex_segment(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+    let mut segment = String::with_capacity(3 + 2 * bytes.len());
+    segment.push_str("/0x");
+    for b in bytes {
+        segment.push(DIGITS[(b >> 4) as usize] as char);
+        segment.push(DIGITS[(b & 0xf) as usize] as char);
+    }
+    segment
+}
+
+impl<K: DurableEncoding, V: DurableEncoding> DurableMap<K, V> {
+    /// Create a map stored under `path`.
+    ///
+    /// If the storage already holds a map at that path, it is reused.
+    pub fn new(path: &impl Path) -> Self {
+        OwnedPath::from(path).into()
+    }
+
+    /// Path of the map in durable storage.
+    pub fn path(&self) -> &OwnedPath {
+        &self.path
+    }
+
+    fn keys_vec(&self) -> Result<DurableVec<K>, StorageError> {
+        Ok(field_path(&self.path, &KEYS)?.into())
+    }
+
+    fn entry_path(&self, key: &K) -> Result<OwnedPath, StorageError> {
+        let entries = field_path(&self.path, &ENTRIES)?;
+        let entry = OwnedPath::try_from(hex_segment(&key.encode()))?;
+        Ok(concat(&entries, &entry)?)
+    }
+
+    /// Number of entries of the map.
+    pub fn len(&self, host: &impl Runtime) -> Result<u64, StorageError> {
+        self.keys_vec()?.len(host)
+    }
+
+    /// Returns `true` if the map has no entries.
+    pub fn is_empty(&self, host: &impl Runtime) -> Result<bool, StorageError> {
+        self.keys_vec()?.is_empty(host)
+    }
+
+    /// Returns `true` if the map has an entry for `key`.
+    pub fn contains_key(
+        &self,
+        host: &impl Runtime,
+        key: &K,
+    ) -> Result<bool, StorageError> {
+        let index = field_path(&self.entry_path(key)?, &INDEX)?;
+        Ok(host.store_has(&index)?.is_some())
+    }
+
+    /// Get the value associated to `key`.
+    pub fn get(&self, host: &impl Runtime, key: &K) -> Result<Option<V>, StorageError> {
+        read_optional(host, &field_path(&self.entry_path(key)?, &VALUE)?)
+    }
+
+    /// Associate `value` to `key`, replacing the previous value if any.
+    pub fn insert(
+        &self,
+        host: &mut impl Runtime,
+        key: &K,
+        value: &V,
+    ) -> Result<(), StorageError> {
+        let entry = self.entry_path(key)?;
+        let index = field_path(&entry, &INDEX)?;
+
+        if host.store_has(&index)?.is_none() {
+            let position = self.keys_vec()?.push(host, key)?;
+            write(host, &index, &position)?;
+        }
+
+        write(host, &field_path(&entry, &VALUE)?, value)
+    }
+
+    /// Remove the entry of `key`, and return its value.
+    ///
+    /// The last inserted entry takes the place of the removed one in the
+    /// iteration order.
+    pub fn remove(
+        &self,
+        host: &mut impl Runtime,
+        key: &K,
+    ) -> Result<Option<V>, StorageError> {
+        let entry = self.entry_path(key)?;
+        let index_path = field_path(&entry, &INDEX)?;
+
+        let Some(index) = read_optional::<u64>(host, &index_path)? else {
+            return Ok(None);
+        };
+        let value = read(host, &field_path(&entry, &VALUE)?)?;
+
+        let keys = self.keys_vec()?;
+        keys.swap_remove(host, index)?;
+        delete(host, &entry)?;
+
+        // The last key moved to the position of the removed one.
+        if let Some(moved) = keys.get(host, index)? {
+            let moved_index = field_path(&self.entry_path(&moved)?, &INDEX)?;
+            write(host, &moved_index, &index)?;
+        }
+
+        Ok(Some(value))
+    }
+
+    /// Remove all the entries of the map.
+    pub fn clear(&self, host: &mut impl Runtime) -> Result<(), StorageError> {
+        delete(host, &self.path)
+    }
+
+    /// Iterate over the keys of the map.
+    pub fn keys<'a, Host: Runtime>(
+        &self,
+        host: &'a Host,
+    ) -> Result<DurableVecIter<'a, K, Host>, StorageError> {
+        self.keys_vec()?.iter(host)
+    }
+
+    /// Iterate over the entries of the map.
+    ///
+    /// The number of entries is read once, when the iterator is created.
+    pub fn iter<'a, Host: Runtime>(
+        &'a self,
+        host: &'a Host,
+    ) -> Result<DurableMapIter<'a, K, V, Host>, StorageError> {
+        Ok(DurableMapIter {
+            map: self,
+            host,
+            keys: self.keys(host)?,
+        })
+    }
+}
+
+/// Iterator over the entries of a [DurableMap].
+pub struct DurableMapIter<'a, K, V, Host> {
+    map: &'a DurableMap<K, V>,
+    host: &'a Host,
+    keys: DurableVecIter<'a, K, Host>,
+}
+
+impl<'a, K, V, Host> Iterator for DurableMapIter<'a, K, V, Host>
+where
+    K: DurableEncoding,
+    V: DurableEncoding,
+    Host: Runtime,
+{
+    type Item = Result<(K, V), StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let key = match self.keys.next()? {
+            Ok(key) => key,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let value = self
+            .map
+            .entry_path(&key)
+            .and_then(|entry| field_path(&entry, &VALUE))
+            .and_then(|path| read(self.host, &path));
+        Some(value.map(|value| (key, value)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.keys.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+    use tezos_smart_rollup_host::path::PathError;
+    use tezos_smart_rollup_mock::MockHost;
+
+    const MAP_PATH: RefPath = RefPath::assert_from(b"/map");
+
+    fn entries<Host: Runtime>(
+        map: &DurableMap<String, u32>,
+        host: &Host,
+    ) -> Vec<(String, u32)> {
+        map.iter(host).unwrap().collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn insert_get_remove() {
+        let mut host = MockHost::default();
+        let map = DurableMap::<String, u32>::new(&MAP_PATH);
+        let (a, b, c) = ("a".to_string(), "b".to_string(), String::new());
+
+        map.insert(&mut host, &a, &1).unwrap();
+        map.insert(&mut host, &b, &2).unwrap();
+        map.insert(&mut host, &c, &3).unwrap();
+        map.insert(&mut host, &a, &4).unwrap();
+
+        assert_eq!(Ok(3), map.len(&host));
+        assert_eq!(Ok(Some(4)), map.get(&host, &a));
+        assert_eq!(Ok(Some(3)), map.get(&host, &c));
+        assert_eq!(Ok(None), map.get(&host, &"d".to_string()));
+        assert_eq!(
+            vec![(a.clone(), 4), (b.clone(), 2), (c.clone(), 3)],
+            entries(&map, &host)
+        );
+
+        assert_eq!(Ok(Some(4)), map.remove(&mut host, &a));
+        assert_eq!(Ok(None), map.remove(&mut host, &a));
+        assert_eq!(Ok(false), map.contains_key(&host, &a));
+        assert_eq!(vec![(c.clone(), 3), (b.clone(), 2)], entries(&map, &host));
+
+        // The index of the moved entry was updated.
+        assert_eq!(Ok(Some(3)), map.remove(&mut host, &c));
+        assert_eq!(vec![(b.clone(), 2)], entries(&map, &host));
+
+        map.clear(&mut host).unwrap();
+        assert_eq!(Ok(true), map.is_empty(&host));
+        assert_eq!(Ok(false), map.contains_key(&host, &b));
+    }
+
+    #[test]
+    fn key_too_long() {
+        let mut host = MockHost::default();
+        let map = DurableMap::<Vec<u8>, u32>::new(&MAP_PATH);
+
+        assert_eq!(
+            Err(StorageError::PathError(PathError::PathTooLong)),
+            map.insert(&mut host, &vec![0; 200], &1)
+        );
+        assert_eq!(Ok(true), map.is_empty(&host));
+    }
+
+    #[test]
+    fn map_in_transaction() {
+        let mut host = MockHost::default();
+        let mut storage = Storage::<DurableMap<String, u32>>::init(&MAP_PATH).unwrap();
+        let id = RefPath::assert_from(b"/balances");
+        let (a, b) = ("a".to_string(), "b".to_string());
+
+        let map = storage.get_or_create(&host, &id).unwrap();
+        map.insert(&mut host, &a, &1).unwrap();
+
+        storage.begin_transaction(&mut host).unwrap();
+        let map = storage.get_or_create(&host, &id).unwrap();
+        map.insert(&mut host, &b, &2).unwrap();
+
+        // Nested transaction, rolled back.
+        storage.begin_transaction(&mut host).unwrap();
+        let map = storage.get_or_create(&host, &id).unwrap();
+        map.remove(&mut host, &a).unwrap();
+        storage.rollback_transaction(&mut host).unwrap();
+
+        storage.commit_transaction(&mut host).unwrap();
+
+        let map = storage.get_or_create(&host, &id).unwrap();
+        assert_eq!(vec![(a, 1), (b, 2)], entries(&map, &host));
+    }
+}
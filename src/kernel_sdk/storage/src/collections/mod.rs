@@ -0,0 +1,158 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Typed collections in durable storage.
+//!
+//! [DurableVec], [DurableQueue] and [DurableMap] keep their elements under a
+//! single prefix of the durable storage, each element being encoded with
+//! [DurableEncoding]. Nothing is cached in memory: every operation reads and
+//! writes the durable storage directly, so collections can be dropped and
+//! recreated from their path at any point, for example between reboots.
+//!
+//! All collections implement `From<OwnedPath>`, and can therefore be used as
+//! objects of a [Storage], to update them in transactions.
+//!
+//! [Storage]: crate::storage::Storage
+
+use crate::StorageError;
+use tezos_smart_rollup_host::path::{concat, OwnedPath, Path, RefPath};
+use tezos_smart_rollup_host::runtime::{Runtime, RuntimeError};
+
+mod map;
+mod queue;
+mod vec;
+
+pub use map::{DurableMap, DurableMapIter};
+pub use queue::{DurableQueue, DurableQueueIter};
+pub use vec::{DurableVec, DurableVecIter};
+
+/// Encoding of the elements of a collection in durable storage.
+///
+/// Encoded values are not limited in size, as they are read and written
+/// with [Runtime::store_read_all] and [Runtime::store_write_all].
+pub trait DurableEncoding: Sized {
+    /// Encode the value as bytes.
+    fn encode(&self) -> Vec<u8>;
+
+    /// Decode a value encoded by [DurableEncoding::encode].
+    fn decode(bytes: &[u8]) -> Result<Self, StorageError>;
+}
+
+macro_rules! impl_durable_encoding_le {
+    ($($t:ty),*) => {
+        $(
+            impl DurableEncoding for $t {
+                fn encode(&self) -> Vec<u8> {
+                    self.to_le_bytes().to_vec()
+                }
+
+                fn decode(bytes: &[u8]) -> Result<Self, StorageError> {
+                    bytes
+                        .try_into()
+                        .map(<$t>::from_le_bytes)
+                        .map_err(|_| StorageError::DecodingError)
+                }
+            }
+        )*
+    };
+}
+
+impl_durable_encoding_le!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl DurableEncoding for bool {
+    fn encode(&self) -> Vec<u8> {
+        vec![u8::from(*self)]
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, StorageError> {
+        match bytes {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            _ => Err(StorageError::DecodingError),
+        }
+    }
+}
+
+impl DurableEncoding for Vec<u8> {
+    fn encode(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, StorageError> {
+        Ok(bytes.to_vec())
+    }
+}
+
+impl DurableEncoding for String {
+    fn encode(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, StorageError> {
+        String::from_utf8(bytes.to_vec()).map_err(|_| StorageError::DecodingError)
+    }
+}
+
+impl<const N: usize> DurableEncoding for [u8; N] {
+    fn encode(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, StorageError> {
+        bytes.try_into().map_err(|_| StorageError::DecodingError)
+    }
+}
+
+/// Path of the element at `index`, under `prefix`.
+fn index_path(prefix: &impl Path, index: u64) -> Result<OwnedPath, StorageError> {
+    let index = OwnedPath::try_from(format!("/{}", index))?;
+    Ok(concat(prefix, &index)?)
+}
+
+/// Path of a field of a collection, under `prefix`.
+fn field_path(prefix: &impl Path, field: &RefPath) -> Result<OwnedPath, StorageError> {
+    Ok(concat(prefix, field)?)
+}
+
+/// Read a value, if there is one at `path`.
+fn read_optional<T: DurableEncoding>(
+    host: &impl Runtime,
+    path: &impl Path,
+) -> Result<Option<T>, StorageError> {
+    match host.store_read_all(path) {
+        Ok(bytes) => Ok(Some(T::decode(&bytes)?)),
+        Err(RuntimeError::PathNotFound) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Read a value which must exist at `path`.
+fn read<T: DurableEncoding>(
+    host: &impl Runtime,
+    path: &impl Path,
+) -> Result<T, StorageError> {
+    let bytes = host.store_read_all(path)?;
+    T::decode(&bytes)
+}
+
+fn write<T: DurableEncoding>(
+    host: &mut impl Runtime,
+    path: &impl Path,
+    value: &T,
+) -> Result<(), StorageError> {
+    Ok(host.store_write_all(path, &value.encode())?)
+}
+
+/// Read a counter, which defaults to zero.
+fn read_u64(host: &impl Runtime, path: &impl Path) -> Result<u64, StorageError> {
+    Ok(read_optional(host, path)?.unwrap_or_default())
+}
+
+/// Delete the subtree at `path`, if any.
+fn delete(host: &mut impl Runtime, path: &impl Path) -> Result<(), StorageError> {
+    match host.store_delete(path) {
+        Ok(()) | Err(RuntimeError::PathNotFound) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
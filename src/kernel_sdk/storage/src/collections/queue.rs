@@ -0,0 +1,210 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! First-in, first-out queue in durable storage.
+
+use super::DurableEncoding;
+use super::{delete, field_path, index_path, read, read_optional, read_u64, write};
+use crate::StorageError;
+use core::marker::PhantomData;
+use tezos_smart_rollup_host::path::{OwnedPath, Path, RefPath};
+use tezos_smart_rollup_host::runtime::Runtime;
+
+const HEAD: RefPath = RefPath::assert_from(b"/head");
+const TAIL: RefPath = RefPath::assert_from(b"/tail");
+
+/// Queue of values in durable storage.
+///
+/// Elements are kept at `<path>/<i>`, for `i` between the indices stored
+/// at `<path>/head` (included) and `<path>/tail` (excluded). Both indices
+/// are reset once the queue is emptied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DurableQueue<T> {
+    path: OwnedPath,
+    phantom: PhantomData<T>,
+}
+
+impl<T> From<OwnedPath> for DurableQueue<T> {
+    fn from(path: OwnedPath) -> Self {
+        Self {
+            path,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: DurableEncoding> DurableQueue<T> {
+    /// Create a queue stored under `path`.
+    ///
+    /// If the storage already holds a queue at that path, it is reused.
+    pub fn new(path: &impl Path) -> Self {
+        OwnedPath::from(path).into()
+    }
+
+    /// Path of the queue in durable storage.
+    pub fn path(&self) -> &OwnedPath {
+        &self.path
+    }
+
+    fn bounds(&self, host: &impl Runtime) -> Result<(u64, u64), StorageError> {
+        let head = read_u64(host, &field_path(&self.path, &HEAD)?)?;
+        let tail = read_u64(host, &field_path(&self.path, &TAIL)?)?;
+        Ok((head, tail))
+    }
+
+    /// Number of elements in the queue.
+    pub fn len(&self, host: &impl Runtime) -> Result<u64, StorageError> {
+        let (head, tail) = self.bounds(host)?;
+        Ok(tail - head)
+    }
+
+    /// Returns `true` if the queue has no elements.
+    pub fn is_empty(&self, host: &impl Runtime) -> Result<bool, StorageError> {
+        Ok(self.len(host)? == 0)
+    }
+
+    /// Add an element at the back of the queue.
+    pub fn push_back(
+        &self,
+        host: &mut impl Runtime,
+        value: &T,
+    ) -> Result<(), StorageError> {
+        let tail = read_u64(host, &field_path(&self.path, &TAIL)?)?;
+        write(host, &index_path(&self.path, tail)?, value)?;
+        write(host, &field_path(&self.path, &TAIL)?, &(tail + 1))
+    }
+
+    /// Get the element at the front of the queue, without removing it.
+    pub fn peek_front(&self, host: &impl Runtime) -> Result<Option<T>, StorageError> {
+        let (head, tail) = self.bounds(host)?;
+        if head == tail {
+            return Ok(None);
+        }
+        read_optional(host, &index_path(&self.path, head)?)
+    }
+
+    /// Remove the element at the front of the queue, and return it.
+    pub fn pop_front(&self, host: &mut impl Runtime) -> Result<Option<T>, StorageError> {
+        let (head, tail) = self.bounds(host)?;
+        if head == tail {
+            return Ok(None);
+        }
+
+        let path = index_path(&self.path, head)?;
+        let value = read(host, &path)?;
+
+        if head + 1 == tail {
+            self.clear(host)?;
+        } else {
+            delete(host, &path)?;
+            write(host, &field_path(&self.path, &HEAD)?, &(head + 1))?;
+        }
+
+        Ok(Some(value))
+    }
+
+    /// Remove all the elements of the queue.
+    pub fn clear(&self, host: &mut impl Runtime) -> Result<(), StorageError> {
+        delete(host, &self.path)
+    }
+
+    /// Iterate over the elements of the queue, from front to back.
+    ///
+    /// The bounds of the queue are read once, when the iterator is created.
+    pub fn iter<'a, Host: Runtime>(
+        &'a self,
+        host: &'a Host,
+    ) -> Result<DurableQueueIter<'a, T, Host>, StorageError> {
+        let (head, tail) = self.bounds(host)?;
+        Ok(DurableQueueIter {
+            queue: self,
+            host,
+            next: head,
+            tail,
+        })
+    }
+}
+
+/// Iterator over the elements of a [DurableQueue].
+pub struct DurableQueueIter<'a, T, Host> {
+    queue: &'a DurableQueue<T>,
+    host: &'a Host,
+    next: u64,
+    tail: u64,
+}
+
+impl<'a, T: DurableEncoding, Host: Runtime> Iterator for DurableQueueIter<'a, T, Host> {
+    type Item = Result<T, StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.tail {
+            return None;
+        }
+
+        let item = index_path(&self.queue.path, self.next)
+            .and_then(|path| read(self.host, &path));
+        self.next += 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.tail - self.next) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+    use tezos_smart_rollup_mock::MockHost;
+
+    const QUEUE_PATH: RefPath = RefPath::assert_from(b"/queue");
+
+    #[test]
+    fn first_in_first_out() {
+        let mut host = MockHost::default();
+        let queue = DurableQueue::<Vec<u8>>::new(&QUEUE_PATH);
+
+        assert_eq!(Ok(None), queue.pop_front(&mut host));
+
+        queue.push_back(&mut host, &vec![1]).unwrap();
+        queue.push_back(&mut host, &vec![2; 3000]).unwrap();
+        queue.push_back(&mut host, &vec![3]).unwrap();
+
+        assert_eq!(Ok(3), queue.len(&host));
+        assert_eq!(Ok(Some(vec![1])), queue.peek_front(&host));
+        assert_eq!(Ok(Some(vec![1])), queue.pop_front(&mut host));
+
+        let elements: Result<Vec<Vec<u8>>, _> = queue.iter(&host).unwrap().collect();
+        assert_eq!(Ok(vec![vec![2; 3000], vec![3]]), elements);
+
+        assert_eq!(Ok(Some(vec![2; 3000])), queue.pop_front(&mut host));
+        assert_eq!(Ok(Some(vec![3])), queue.pop_front(&mut host));
+        assert_eq!(Ok(None), queue.pop_front(&mut host));
+
+        // The queue is reset once emptied.
+        assert_eq!(Ok(None), host.store_has(&QUEUE_PATH));
+    }
+
+    #[test]
+    fn queue_in_transaction() {
+        let mut host = MockHost::default();
+        let mut storage = Storage::<DurableQueue<u32>>::init(&QUEUE_PATH).unwrap();
+        let id = RefPath::assert_from(b"/pending");
+
+        let queue = storage.get_or_create(&host, &id).unwrap();
+        queue.push_back(&mut host, &1).unwrap();
+        queue.push_back(&mut host, &2).unwrap();
+
+        storage.begin_transaction(&mut host).unwrap();
+        let queue = storage.get_or_create(&host, &id).unwrap();
+        assert_eq!(Ok(Some(1)), queue.pop_front(&mut host));
+        storage.rollback_transaction(&mut host).unwrap();
+
+        let queue = storage.get_or_create(&host, &id).unwrap();
+        assert_eq!(Ok(2), queue.len(&host));
+        assert_eq!(Ok(Some(1)), queue.peek_front(&host));
+    }
+}
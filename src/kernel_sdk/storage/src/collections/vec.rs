@@ -0,0 +1,282 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Growable array in durable storage.
+
+use super::DurableEncoding;
+use super::{delete, field_path, index_path, read, read_optional, read_u64, write};
+use crate::StorageError;
+use core::marker::PhantomData;
+use tezos_smart_rollup_host::path::{OwnedPath, Path, RefPath};
+use tezos_smart_rollup_host::runtime::Runtime;
+
+const LENGTH: RefPath = RefPath::assert_from(b"/length");
+
+/// Array of values in durable storage.
+///
+/// The number of elements is kept at `<path>/length`, and the element at
+/// index `i` at `<path>/<i>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DurableVec<T> {
+    path: OwnedPath,
+    phantom: PhantomData<T>,
+}
+
+impl<T> From<OwnedPath> for DurableVec<T> {
+    fn from(path: OwnedPath) -> Self {
+        Self {
+            path,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: DurableEncoding> DurableVec<T> {
+    /// Create a vector stored under `path`.
+    ///
+    /// If the storage already holds a vector at that path, it is reused.
+    pub fn new(path: &impl Path) -> Self {
+        OwnedPath::from(path).into()
+    }
+
+    /// Path of the vector in durable storage.
+    pub fn path(&self) -> &OwnedPath {
+        &self.path
+    }
+
+    /// Number of elements of the vector.
+    pub fn len(&self, host: &impl Runtime) -> Result<u64, StorageError> {
+        read_u64(host, &field_path(&self.path, &LENGTH)?)
+    }
+
+    /// Returns `true` if the vector has no elements.
+    pub fn is_empty(&self, host: &impl Runtime) -> Result<bool, StorageError> {
+        Ok(self.len(host)? == 0)
+    }
+
+    fn set_len(&self, host: &mut impl Runtime, len: u64) -> Result<(), StorageError> {
+        write(host, &field_path(&self.path, &LENGTH)?, &len)
+    }
+
+    /// Get the element at `index`, or `None` if it is out of bounds.
+    pub fn get(
+        &self,
+        host: &impl Runtime,
+        index: u64,
+    ) -> Result<Option<T>, StorageError> {
+        if index >= self.len(host)? {
+            return Ok(None);
+        }
+        read_optional(host, &index_path(&self.path, index)?)
+    }
+
+    /// Replace the element at `index`.
+    pub fn set(
+        &self,
+        host: &mut impl Runtime,
+        index: u64,
+        value: &T,
+    ) -> Result<(), StorageError> {
+        if index >= self.len(host)? {
+            return Err(StorageError::IndexOutOfBounds);
+        }
+        write(host, &index_path(&self.path, index)?, value)
+    }
+
+    /// Append an element at the end of the vector, and return its index.
+    pub fn push(&self, host: &mut impl Runtime, value: &T) -> Result<u64, StorageError> {
+        let index = self.len(host)?;
+        write(host, &index_path(&self.path, index)?, value)?;
+        self.set_len(host, index + 1)?;
+        Ok(index)
+    }
+
+    /// Remove the last element of the vector, and return it.
+    pub fn pop(&self, host: &mut impl Runtime) -> Result<Option<T>, StorageError> {
+        let len = self.len(host)?;
+        if len == 0 {
+            return Ok(None);
+        }
+
+        let path = index_path(&self.path, len - 1)?;
+        let value = read(host, &path)?;
+        delete(host, &path)?;
+        self.set_len(host, len - 1)?;
+        Ok(Some(value))
+    }
+
+    /// Remove the element at `index`, replacing it by the last element of
+    /// the vector. Returns the removed element.
+    ///
+    /// This does not preserve the order of the elements, but is O(1).
+    pub fn swap_remove(
+        &self,
+        host: &mut impl Runtime,
+        index: u64,
+    ) -> Result<T, StorageError> {
+        let len = self.len(host)?;
+        if index >= len {
+            return Err(StorageError::IndexOutOfBounds);
+        }
+
+        let path = index_path(&self.path, index)?;
+        let value = read(host, &path)?;
+
+        let last = index_path(&self.path, len - 1)?;
+        if index != len - 1 {
+            host.store_move(&last, &path)?;
+        } else {
+            delete(host, &last)?;
+        }
+        self.set_len(host, len - 1)?;
+
+        Ok(value)
+    }
+
+    /// Remove all the elements of the vector.
+    pub fn clear(&self, host: &mut impl Runtime) -> Result<(), StorageError> {
+        delete(host, &self.path)
+    }
+
+    /// Iterate over the elements of the vector, in order.
+    ///
+    /// The length of the vector is read once, when the iterator is created.
+    pub fn iter<'a, Host: Runtime>(
+        &self,
+        host: &'a Host,
+    ) -> Result<DurableVecIter<'a, T, Host>, StorageError> {
+        Ok(DurableVecIter {
+            path: self.path.clone(),
+            host,
+            phantom: PhantomData,
+            next: 0,
+            len: self.len(host)?,
+        })
+    }
+}
+
+/// Iterator over the elements of a [DurableVec].
+pub struct DurableVecIter<'a, T, Host> {
+    path: OwnedPath,
+    host: &'a Host,
+    next: u64,
+    len: u64,
+    phantom: PhantomData<T>,
+}
+
+impl<'a, T: DurableEncoding, Host: Runtime> Iterator for DurableVecIter<'a, T, Host> {
+    type Item = Result<T, StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.len {
+            return None;
+        }
+
+        let item =
+            index_path(&self.path, self.next).and_then(|path| read(self.host, &path));
+        self.next += 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.len - self.next) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storage;
+    use tezos_smart_rollup_mock::MockHost;
+
+    const VEC_PATH: RefPath = RefPath::assert_from(b"/vec");
+
+    #[test]
+    fn push_get_pop() {
+        let mut host = MockHost::default();
+        let vec = DurableVec::<u64>::new(&VEC_PATH);
+
+        assert!(vec.is_empty(&host).unwrap());
+        assert_eq!(Ok(None), vec.pop(&mut host));
+
+        assert_eq!(Ok(0), vec.push(&mut host, &10));
+        assert_eq!(Ok(1), vec.push(&mut host, &20));
+        assert_eq!(Ok(2), vec.push(&mut host, &30));
+
+        assert_eq!(Ok(3), vec.len(&host));
+        assert_eq!(Ok(Some(20)), vec.get(&host, 1));
+        assert_eq!(Ok(None), vec.get(&host, 3));
+
+        vec.set(&mut host, 1, &21).unwrap();
+        assert_eq!(Ok(Some(21)), vec.get(&host, 1));
+        assert_eq!(
+            Err(StorageError::IndexOutOfBounds),
+            vec.set(&mut host, 3, &40)
+        );
+
+        assert_eq!(Ok(Some(30)), vec.pop(&mut host));
+        assert_eq!(Ok(2), vec.len(&host));
+
+        // A vector recreated from the same path sees the same elements.
+        let same_vec = DurableVec::<u64>::new(&VEC_PATH);
+        let elements: Result<Vec<u64>, _> = same_vec.iter(&host).unwrap().collect();
+        assert_eq!(Ok(vec![10, 21]), elements);
+    }
+
+    #[test]
+    fn swap_remove_and_clear() {
+        let mut host = MockHost::default();
+        let vec = DurableVec::<String>::new(&VEC_PATH);
+
+        for s in ["a", "b", "c", "d"] {
+            vec.push(&mut host, &s.to_string()).unwrap();
+        }
+
+        assert_eq!(Ok("b".to_string()), vec.swap_remove(&mut host, 1));
+        assert_eq!(Ok("d".to_string()), vec.swap_remove(&mut host, 1));
+        assert_eq!(
+            Err(StorageError::IndexOutOfBounds),
+            vec.swap_remove(&mut host, 2)
+        );
+
+        let elements: Result<Vec<String>, _> = vec.iter(&host).unwrap().collect();
+        assert_eq!(Ok(vec!["a".to_string(), "c".to_string()]), elements);
+
+        vec.clear(&mut host).unwrap();
+        assert!(vec.is_empty(&host).unwrap());
+        assert_eq!(Ok(None), host.store_has(&VEC_PATH));
+    }
+
+    #[test]
+    fn vec_in_transaction() {
+        let mut host = MockHost::default();
+        let mut storage = Storage::<DurableVec<u64>>::init(&VEC_PATH).unwrap();
+        let id = RefPath::assert_from(b"/numbers");
+
+        let vec = storage.get_or_create(&host, &id).unwrap();
+        vec.push(&mut host, &1).unwrap();
+
+        // Changes of a rolled back transaction are discarded.
+        storage.begin_transaction(&mut host).unwrap();
+        let vec = storage.get_or_create(&host, &id).unwrap();
+        vec.push(&mut host, &2).unwrap();
+        vec.set(&mut host, 0, &3).unwrap();
+        storage.rollback_transaction(&mut host).unwrap();
+
+        let vec = storage.get_or_create(&host, &id).unwrap();
+        let elements: Result<Vec<u64>, _> = vec.iter(&host).unwrap().collect();
+        assert_eq!(Ok(vec![1]), elements);
+
+        // Changes of a committed transaction are kept.
+        storage.begin_transaction(&mut host).unwrap();
+        let vec = storage.get_or_create(&host, &id).unwrap();
+        vec.push(&mut host, &2).unwrap();
+        storage.commit_transaction(&mut host).unwrap();
+
+        let vec = storage.get_or_create(&host, &id).unwrap();
+        let elements: Result<Vec<u64>, _> = vec.iter(&host).unwrap().collect();
+        assert_eq!(Ok(vec![1, 2]), elements);
+    }
+}
@@ -41,6 +41,18 @@ pub enum StorageError {
     /// happen when doing some transaction operation.
     #[error("Runtrime error")]
     RuntimeError(host::runtime::RuntimeError),
+    /// Some value in durable storage could not be decoded. This may happen
+    /// when reading an element of a [collections] type.
+    #[error("Decoding error")]
+    DecodingError,
+    /// Tried to access an element past the end of a
+    /// [collections::DurableVec].
+    #[error("Index out of bounds")]
+    IndexOutOfBounds,
+    /// Tried to roll back to a savepoint which was taken in another
+    /// transaction, or which was released.
+    #[error("Invalid savepoint")]
+    InvalidSavepoint,
 }
 
 impl From<host::path::PathError> for StorageError {
@@ -55,5 +67,7 @@ impl From<host::runtime::RuntimeError> for StorageError {
     }
 }
 
+pub mod collections;
 mod layer;
 pub mod storage;
+pub mod transaction;
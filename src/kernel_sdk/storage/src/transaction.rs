@@ -0,0 +1,945 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Copy-on-write transactions over the whole durable storage.
+//!
+//! [TransactionalHost] wraps a [Runtime], and keeps the changes made to the
+//! durable storage during a transaction in memory, in a tree of the changed
+//! paths. Reads of the paths not changed by the transaction fall through to
+//! the wrapped runtime, which is left untouched until the outermost
+//! transaction is committed.
+//!
+//! Transactions can be nested, and savepoints taken within a transaction to
+//! roll back part of it. Beginning and committing a nested transaction, or
+//! taking a savepoint, take constant time. Committing the outermost
+//! transaction writes its changes to the durable storage, at a cost
+//! proportional to the number of changed paths: deleted, copied and moved
+//! subtrees are written with a couple of host calls each, whatever their size.
+//!
+//! Only the durable storage is transactional: inputs read and outputs
+//! written during a transaction are not undone when it is rolled back.
+
+use crate::StorageError;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use tezos_smart_rollup_core::{MAX_FILE_CHUNK_SIZE, PREIMAGE_HASH_SIZE};
+use tezos_smart_rollup_host::input::Message;
+use tezos_smart_rollup_host::metadata::RollupMetadata;
+use tezos_smart_rollup_host::path::{
+    concat, OwnedPath, Path, RefPath, PATH_MAX_SIZE, PATH_SEPARATOR,
+};
+use tezos_smart_rollup_host::runtime::{Runtime, RuntimeError, ValueType};
+use tezos_smart_rollup_host::Error;
+
+/// Where copied subtrees are kept while committing a transaction.
+const TMP_PATH: RefPath = RefPath::assert_from(b"/__transactional_host");
+
+/// Contents of a subtree which replaced the one in the durable storage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Origin {
+    /// The subtree was deleted.
+    Empty,
+    /// The subtree was copied from the given path of the durable storage.
+    Host(Vec<u8>),
+}
+
+impl Origin {
+    fn child(&self, segment: &[u8]) -> Self {
+        match self {
+            Origin::Empty => Origin::Empty,
+            Origin::Host(path) => {
+                let mut path = path.clone();
+                path.push(PATH_SEPARATOR);
+                path.extend_from_slice(segment);
+                Origin::Host(path)
+            }
+        }
+    }
+}
+
+/// Changes of the subtree at a path.
+///
+/// Nodes are shared between the transactions and savepoints, and copied on
+/// write.
+#[derive(Debug, Clone, Default)]
+struct Node {
+    /// Set when the whole subtree was replaced.
+    origin: Option<Origin>,
+    /// Set when the value was written (`Some`) or deleted (`None`).
+    value: Option<Option<Rc<Vec<u8>>>>,
+    /// Changes of the children of the node, by path step.
+    children: BTreeMap<Vec<u8>, Rc<Node>>,
+}
+
+/// A value, as seen by a transaction.
+enum Value {
+    /// Written in the transaction.
+    Changed(Rc<Vec<u8>>),
+    /// Unchanged, at the given path of the durable storage.
+    Host(OwnedPath),
+}
+
+fn steps(path: &[u8]) -> impl Iterator<Item = &[u8]> {
+    path.split(|b| *b == PATH_SEPARATOR).skip(1)
+}
+
+/// Path of the durable storage, from the steps of valid paths.
+fn host_path(bytes: &[u8]) -> Result<OwnedPath, RuntimeError> {
+    if bytes.len() > PATH_MAX_SIZE {
+        return Err(RuntimeError::HostErr(Error::StoreKeyTooLarge));
+    }
+    // SAFETY: `bytes` is made of the steps of valid paths.
+    Ok(unsafe { OwnedPath::from_bytes_unchecked(bytes.to_vec()) })
+}
+
+fn tmp_path(index: usize) -> Result<OwnedPath, RuntimeError> {
+    let index = OwnedPath::try_from(format!("/{}", index))
+        .map_err(|_| RuntimeError::HostErr(Error::StoreInvalidKey))?;
+    concat(&TMP_PATH, &index).map_err(|_| RuntimeError::HostErr(Error::StoreKeyTooLarge))
+}
+
+/// Find the changes at `path`, if any, and where its unchanged contents are.
+fn locate<'a>(root: &'a Node, path: &[u8]) -> (Option<&'a Node>, Origin) {
+    let mut node = Some(root);
+    let mut origin = Origin::Host(Vec::new());
+
+    for step in steps(path) {
+        node = node.and_then(|n| n.children.get(step)).map(Rc::as_ref);
+        origin = match node.and_then(|n| n.origin.as_ref()) {
+            Some(replaced) => replaced.clone(),
+            None => origin.child(step),
+        };
+    }
+
+    (node, origin)
+}
+
+/// Get the changes at `path`, creating them if needed.
+fn locate_mut<'a>(root: &'a mut Rc<Node>, path: &[u8]) -> &'a mut Node {
+    let mut node = Rc::make_mut(root);
+    for step in steps(path) {
+        node = Rc::make_mut(node.children.entry(step.to_vec()).or_default());
+    }
+    node
+}
+
+/// A point in a transaction, which can be rolled back to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Savepoint {
+    depth: usize,
+    index: usize,
+}
+
+struct Transaction {
+    /// Changes when the transaction began.
+    start: Rc<Node>,
+    /// Changes when each savepoint was taken.
+    savepoints: Vec<Rc<Node>>,
+}
+
+/// Runtime with transactional durable storage.
+///
+/// Outside of a transaction, all calls go straight to the wrapped runtime.
+/// Changes made in a transaction are discarded if it is not committed
+/// before the `TransactionalHost` is dropped.
+///
+/// ```
+/// use tezos_smart_rollup_host::path::RefPath;
+/// use tezos_smart_rollup_host::runtime::Runtime;
+/// use tezos_smart_rollup_storage::transaction::TransactionalHost;
+/// use tezos_smart_rollup_mock::MockHost;
+///
+/// const PATH: RefPath = RefPath::assert_from(b"/counter");
+///
+/// let mut mock = MockHost::default();
+/// let mut host = TransactionalHost::new(&mut mock);
+///
+/// host.begin_transaction();
+/// host.store_write_all(&PATH, &[1]).unwrap();
+/// let savepoint = host.savepoint().unwrap();
+/// host.store_write_all(&PATH, &[2]).unwrap();
+/// host.rollback_to_savepoint(savepoint).unwrap();
+/// host.commit_transaction().unwrap();
+///
+/// assert_eq!(vec![1], mock.store_read_all(&PATH).unwrap());
+/// ```
+pub struct TransactionalHost<'a, Host> {
+    host: &'a mut Host,
+    changes: Rc<Node>,
+    transactions: Vec<Transaction>,
+}
+
+impl<'a, Host: Runtime> TransactionalHost<'a, Host> {
+    /// Wrap a runtime, with no transaction in progress.
+    pub fn new(host: &'a mut Host) -> Self {
+        Self {
+            host,
+            changes: Rc::default(),
+            transactions: Vec::new(),
+        }
+    }
+
+    /// Number of nested transactions in progress.
+    pub fn transaction_depth(&self) -> usize {
+        self.transactions.len()
+    }
+
+    fn in_transaction(&self) -> bool {
+        !self.transactions.is_empty()
+    }
+
+    /// Begin a new transaction, nested in the current one if any.
+    pub fn begin_transaction(&mut self) {
+        self.transactions.push(Transaction {
+            start: self.changes.clone(),
+            savepoints: Vec::new(),
+        });
+    }
+
+    /// Commit the current transaction.
+    ///
+    /// Its changes are merged into the enclosing transaction if any, and
+    /// are written to the durable storage otherwise. If writing them fails,
+    /// the durable storage may be partially updated.
+    pub fn commit_transaction(&mut self) -> Result<(), StorageError> {
+        self.transactions
+            .pop()
+            .ok_or(StorageError::NoCurrentTransaction)?;
+
+        if !self.in_transaction() {
+            let changes = core::mem::take(&mut self.changes);
+            self.write_changes(&changes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Discard the changes of the current transaction.
+    pub fn rollback_transaction(&mut self) -> Result<(), StorageError> {
+        let transaction = self
+            .transactions
+            .pop()
+            .ok_or(StorageError::NoCurrentTransaction)?;
+        self.changes = transaction.start;
+        Ok(())
+    }
+
+    /// Take a savepoint in the current transaction.
+    pub fn savepoint(&mut self) -> Result<Savepoint, StorageError> {
+        let depth = self.transactions.len();
+        let transaction = self
+            .transactions
+            .last_mut()
+            .ok_or(StorageError::NoCurrentTransaction)?;
+
+        transaction.savepoints.push(self.changes.clone());
+        Ok(Savepoint {
+            depth,
+            index: transaction.savepoints.len() - 1,
+        })
+    }
+
+    /// Discard the changes made in the current transaction since `savepoint`.
+    ///
+    /// The savepoints taken after `savepoint` are released, while
+    /// `savepoint` itself can be rolled back to again.
+    pub fn rollback_to_savepoint(
+        &mut self,
+        savepoint: Savepoint,
+    ) -> Result<(), StorageError> {
+        let depth = self.transactions.len();
+        let transaction = self
+            .transactions
+            .last_mut()
+            .ok_or(StorageError::NoCurrentTransaction)?;
+
+        if savepoint.depth != depth || savepoint.index >= transaction.savepoints.len() {
+            return Err(StorageError::InvalidSavepoint);
+        }
+
+        transaction.savepoints.truncate(savepoint.index + 1);
+        self.changes = transaction.savepoints[savepoint.index].clone();
+        Ok(())
+    }
+
+    fn value(&self, path: &impl Path) -> Result<Option<Value>, RuntimeError> {
+        let (node, origin) = locate(&self.changes, path.as_bytes());
+        self.value_at(node, &origin)
+    }
+
+    fn value_at(
+        &self,
+        node: Option<&Node>,
+        origin: &Origin,
+    ) -> Result<Option<Value>, RuntimeError> {
+        if let Some(value) = node.and_then(|n| n.value.as_ref()) {
+            return Ok(value.clone().map(Value::Changed));
+        }
+
+        match origin {
+            Origin::Empty => Ok(None),
+            Origin::Host(path) => {
+                let path = host_path(path)?;
+                match self.host.store_has(&path)? {
+                    Some(ValueType::Value | ValueType::ValueWithSubtree) => {
+                        Ok(Some(Value::Host(path)))
+                    }
+                    _ => Ok(None),
+                }
+            }
+        }
+    }
+
+    /// Number of subkeys of the durable storage at `origin`, not counting
+    /// its value.
+    fn host_children(&self, origin: &Origin) -> Result<u64, RuntimeError> {
+        let Origin::Host(path) = origin else {
+            return Ok(0);
+        };
+        let path = host_path(path)?;
+
+        match self.host.store_has(&path)? {
+            None | Some(ValueType::Value) => Ok(0),
+            Some(ValueType::Subtree) => self.host.store_count_subkeys(&path),
+            Some(ValueType::ValueWithSubtree) => {
+                Ok(self.host.store_count_subkeys(&path)? - 1)
+            }
+        }
+    }
+
+    /// Number of subkeys of a path, not counting its value.
+    fn children(
+        &self,
+        node: Option<&Node>,
+        origin: &Origin,
+    ) -> Result<u64, RuntimeError> {
+        let mut count = self.host_children(origin)?;
+        let Some(node) = node else {
+            return Ok(count);
+        };
+
+        for (step, child) in node.children.iter() {
+            let unchanged = origin.child(step);
+            let in_host = match &unchanged {
+                Origin::Empty => false,
+                Origin::Host(path) => self.host.store_has(&host_path(path)?)?.is_some(),
+            };
+            let origin = child.origin.clone().unwrap_or(unchanged);
+            let exists = self.exists(Some(child), &origin)?;
+
+            match (in_host, exists) {
+                (false, true) => count += 1,
+                (true, false) => count -= 1,
+                _ => (),
+            }
+        }
+
+        Ok(count)
+    }
+
+    fn exists(&self, node: Option<&Node>, origin: &Origin) -> Result<bool, RuntimeError> {
+        Ok(self.value_at(node, origin)?.is_some() || self.children(node, origin)? > 0)
+    }
+
+    fn check_path_exists(&self, path: &impl Path) -> Result<(), RuntimeError> {
+        match self.store_has(path)? {
+            Some(_) => Ok(()),
+            None => Err(RuntimeError::PathNotFound),
+        }
+    }
+
+    /// Error when reading the whole value of a path without one.
+    fn no_value_error(&self, path: &impl Path) -> RuntimeError {
+        match self.check_path_exists(path) {
+            Ok(()) => RuntimeError::HostErr(Error::StoreNotAValue),
+            Err(e) => e,
+        }
+    }
+
+    /// The subtree at `path`, to be copied elsewhere.
+    fn subtree(&self, path: &impl Path) -> Result<Node, RuntimeError> {
+        let (node, origin) = locate(&self.changes, path.as_bytes());
+
+        // Copying a subtree which only exists in the transaction.
+        let origin = match origin {
+            Origin::Host(path) if self.host.store_has(&host_path(&path)?)?.is_none() => {
+                Origin::Empty
+            }
+            origin => origin,
+        };
+
+        Ok(Node {
+            origin: Some(origin),
+            value: node.and_then(|n| n.value.clone()),
+            children: node.map(|n| n.children.clone()).unwrap_or_default(),
+        })
+    }
+
+    fn write_changes(&mut self, changes: &Node) -> Result<(), RuntimeError> {
+        // The origins of the copied subtrees may be changed by the
+        // transaction, so they are set aside before writing anything.
+        let mut copies = Vec::new();
+        collect_copies(changes, &mut copies);
+        for (index, origin) in copies.iter().enumerate() {
+            self.host
+                .store_copy(&host_path(origin)?, &tmp_path(index)?)?;
+        }
+
+        let mut next_copy = 0;
+        let mut path = Vec::new();
+        for (step, child) in changes.children.iter() {
+            path.push(PATH_SEPARATOR);
+            path.extend_from_slice(step);
+            self.write_node(child, &mut path, &mut next_copy)?;
+            path.clear();
+        }
+
+        if self.host.store_has(&TMP_PATH)?.is_some() {
+            self.host.store_delete(&TMP_PATH)?;
+        }
+        Ok(())
+    }
+
+    fn write_node(
+        &mut self,
+        node: &Node,
+        path: &mut Vec<u8>,
+        next_copy: &mut usize,
+    ) -> Result<(), RuntimeError> {
+        if let Some(origin) = &node.origin {
+            let target = host_path(path)?;
+            if self.host.store_has(&target)?.is_some() {
+                self.host.store_delete(&target)?;
+            }
+            if let Origin::Host(_) = origin {
+                self.host.store_move(&tmp_path(*next_copy)?, &target)?;
+                *next_copy += 1;
+            }
+        }
+
+        match &node.value {
+            Some(Some(value)) => self.host.store_write_all(&host_path(path)?, value)?,
+            Some(None) => self.host.store_delete_value(&host_path(path)?)?,
+            None => (),
+        }
+
+        for (step, child) in node.children.iter() {
+            let len = path.len();
+            path.push(PATH_SEPARATOR);
+            path.extend_from_slice(step);
+            self.write_node(child, path, next_copy)?;
+            path.truncate(len);
+        }
+
+        Ok(())
+    }
+}
+
+fn read_slice(
+    value: &[u8],
+    from_offset: usize,
+    buffer: &mut [u8],
+) -> Result<usize, RuntimeError> {
+    if from_offset > value.len() {
+        return Err(RuntimeError::HostErr(Error::StoreInvalidAccess));
+    }
+
+    let size = usize::min(
+        MAX_FILE_CHUNK_SIZE,
+        usize::min(buffer.len(), value.len() - from_offset),
+    );
+    buffer[..size].copy_from_slice(&value[from_offset..from_offset + size]);
+    Ok(size)
+}
+
+/// Origins of the copied subtrees, in the order they are written.
+fn collect_copies(node: &Node, copies: &mut Vec<Vec<u8>>) {
+    if let Some(Origin::Host(path)) = &node.origin {
+        copies.push(path.clone());
+    }
+    for child in node.children.values() {
+        collect_copies(child, copies);
+    }
+}
+
+impl<'a, Host: Runtime> Runtime for TransactionalHost<'a, Host> {
+    fn write_output(&mut self, from: &[u8]) -> Result<(), RuntimeError> {
+        self.host.write_output(from)
+    }
+
+    fn write_debug(&self, msg: &str) {
+        self.host.write_debug(msg)
+    }
+
+    fn read_input(&mut self) -> Result<Option<Message>, RuntimeError> {
+        self.host.read_input()
+    }
+
+    fn store_has<T: Path>(&self, path: &T) -> Result<Option<ValueType>, RuntimeError> {
+        if !self.in_transaction() {
+            return self.host.store_has(path);
+        }
+
+        let (node, origin) = locate(&self.changes, path.as_bytes());
+        let has_value = self.value_at(node, &origin)?.is_some();
+        let has_subtree = self.children(node, &origin)? > 0;
+
+        Ok(match (has_value, has_subtree) {
+            (false, false) => None,
+            (true, false) => Some(ValueType::Value),
+            (false, true) => Some(ValueType::Subtree),
+            (true, true) => Some(ValueType::ValueWithSubtree),
+        })
+    }
+
+    fn store_read<T: Path>(
+        &self,
+        path: &T,
+        from_offset: usize,
+        max_bytes: usize,
+    ) -> Result<Vec<u8>, RuntimeError> {
+        if !self.in_transaction() {
+            return self.host.store_read(path, from_offset, max_bytes);
+        }
+
+        match self.value(path)? {
+            None => Err(RuntimeError::PathNotFound),
+            Some(Value::Host(path)) => {
+                self.host.store_read(&path, from_offset, max_bytes)
+            }
+            Some(Value::Changed(value)) => {
+                let mut buffer = vec![0; usize::min(MAX_FILE_CHUNK_SIZE, max_bytes)];
+                let size = read_slice(&value, from_offset, &mut buffer)?;
+                buffer.truncate(size);
+                Ok(buffer)
+            }
+        }
+    }
+
+    fn store_read_slice<T: Path>(
+        &self,
+        path: &T,
+        from_offset: usize,
+        buffer: &mut [u8],
+    ) -> Result<usize, RuntimeError> {
+        if !self.in_transaction() {
+            return self.host.store_read_slice(path, from_offset, buffer);
+        }
+
+        match self.value(path)? {
+            None => Err(RuntimeError::HostErr(Error::StoreNotAValue)),
+            Some(Value::Host(path)) => {
+                self.host.store_read_slice(&path, from_offset, buffer)
+            }
+            Some(Value::Changed(value)) => read_slice(&value, from_offset, buffer),
+        }
+    }
+
+    fn store_read_all(&self, path: &impl Path) -> Result<Vec<u8>, RuntimeError> {
+        if !self.in_transaction() {
+            return self.host.store_read_all(path);
+        }
+
+        match self.value(path)? {
+            None => Err(self.no_value_error(path)),
+            Some(Value::Host(path)) => self.host.store_read_all(&path),
+            Some(Value::Changed(value)) => Ok(value.as_ref().clone()),
+        }
+    }
+
+    fn store_write<T: Path>(
+        &mut self,
+        path: &T,
+        src: &[u8],
+        at_offset: usize,
+    ) -> Result<(), RuntimeError> {
+        if !self.in_transaction() {
+            return self.host.store_write(path, src, at_offset);
+        }
+
+        let mut value = match self.value(path)? {
+            None => Vec::new(),
+            Some(Value::Host(path)) => self.host.store_read_all(&path)?,
+            Some(Value::Changed(value)) => value.as_ref().clone(),
+        };
+
+        if at_offset > value.len() {
+            return Err(RuntimeError::HostErr(Error::StoreInvalidAccess));
+        } else if at_offset + src.len() <= value.len() {
+            value[at_offset..at_offset + src.len()].copy_from_slice(src);
+        } else {
+            value.truncate(at_offset);
+            value.extend_from_slice(src);
+        }
+
+        locate_mut(&mut self.changes, path.as_bytes()).value = Some(Some(Rc::new(value)));
+        Ok(())
+    }
+
+    fn store_write_all<T: Path>(
+        &mut self,
+        path: &T,
+        src: &[u8],
+    ) -> Result<(), RuntimeError> {
+        if !self.in_transaction() {
+            return self.host.store_write_all(path, src);
+        }
+
+        locate_mut(&mut self.changes, path.as_bytes()).value =
+            Some(Some(Rc::new(src.to_vec())));
+        Ok(())
+    }
+
+    fn store_delete<T: Path>(&mut self, path: &T) -> Result<(), RuntimeError> {
+        if !self.in_transaction() {
+            return self.host.store_delete(path);
+        }
+
+        self.check_path_exists(path)?;
+        *locate_mut(&mut self.changes, path.as_bytes()) = Node {
+            origin: Some(Origin::Empty),
+            ..Node::default()
+        };
+        Ok(())
+    }
+
+    fn store_delete_value<T: Path>(&mut self, path: &T) -> Result<(), RuntimeError> {
+        if !self.in_transaction() {
+            return self.host.store_delete_value(path);
+        }
+
+        locate_mut(&mut self.changes, path.as_bytes()).value = Some(None);
+        Ok(())
+    }
+
+    fn store_count_subkeys<T: Path>(&self, prefix: &T) -> Result<u64, RuntimeError> {
+        if !self.in_transaction() {
+            return self.host.store_count_subkeys(prefix);
+        }
+
+        let (node, origin) = locate(&self.changes, prefix.as_bytes());
+        let has_value = self.value_at(node, &origin)?.is_some();
+        let children = self.children(node, &origin)?;
+
+        if has_value || children > 0 {
+            Ok(u64::from(has_value) + children)
+        } else {
+            Err(RuntimeError::HostErr(Error::StoreNotANode))
+        }
+    }
+
+    fn store_move(
+        &mut self,
+        from_path: &impl Path,
+        to_path: &impl Path,
+    ) -> Result<(), RuntimeError> {
+        if !self.in_transaction() {
+            return self.host.store_move(from_path, to_path);
+        }
+
+        self.store_copy(from_path, to_path)?;
+        if from_path.as_bytes() != to_path.as_bytes() {
+            self.store_delete(from_path)?;
+        }
+        Ok(())
+    }
+
+    fn store_copy(
+        &mut self,
+        from_path: &impl Path,
+        to_path: &impl Path,
+    ) -> Result<(), RuntimeError> {
+        if !self.in_transaction() {
+            return self.host.store_copy(from_path, to_path);
+        }
+
+        self.check_path_exists(from_path)?;
+        let subtree = self.subtree(from_path)?;
+        *locate_mut(&mut self.changes, to_path.as_bytes()) = subtree;
+        Ok(())
+    }
+
+    fn reveal_preimage(
+        &self,
+        hash: &[u8; PREIMAGE_HASH_SIZE],
+        destination: &mut [u8],
+    ) -> Result<usize, RuntimeError> {
+        self.host.reveal_preimage(hash, destination)
+    }
+
+    fn store_value_size(&self, path: &impl Path) -> Result<usize, RuntimeError> {
+        if !self.in_transaction() {
+            return self.host.store_value_size(path);
+        }
+
+        match self.value(path)? {
+            None => Err(self.no_value_error(path)),
+            Some(Value::Host(path)) => self.host.store_value_size(&path),
+            Some(Value::Changed(value)) => Ok(value.len()),
+        }
+    }
+
+    fn mark_for_reboot(&mut self) -> Result<(), RuntimeError> {
+        self.host.mark_for_reboot()
+    }
+
+    fn reveal_metadata(&self) -> RollupMetadata {
+        self.host.reveal_metadata()
+    }
+
+    fn last_run_aborted(&self) -> Result<bool, RuntimeError> {
+        self.host.last_run_aborted()
+    }
+
+    fn upgrade_failed(&self) -> Result<bool, RuntimeError> {
+        self.host.upgrade_failed()
+    }
+
+    fn restart_forced(&self) -> Result<bool, RuntimeError> {
+        self.host.restart_forced()
+    }
+
+    fn reboot_left(&self) -> Result<u32, RuntimeError> {
+        self.host.reboot_left()
+    }
+
+    fn runtime_version(&self) -> Result<String, RuntimeError> {
+        self.host.runtime_version()
+    }
+
+    tezos_smart_rollup_host::forward_gated_runtime_methods!(host);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tezos_smart_rollup_mock::MockHost;
+
+    const A: RefPath = RefPath::assert_from(b"/a");
+    const A_B: RefPath = RefPath::assert_from(b"/a/b");
+    const A_B_C: RefPath = RefPath::assert_from(b"/a/b/c");
+    const A_D: RefPath = RefPath::assert_from(b"/a/d");
+    const B: RefPath = RefPath::assert_from(b"/b");
+    const B_B: RefPath = RefPath::assert_from(b"/b/b");
+    const C: RefPath = RefPath::assert_from(b"/c");
+    const C_X: RefPath = RefPath::assert_from(b"/c/x");
+
+    const PATHS: [RefPath; 11] = [
+        A,
+        A_B,
+        A_B_C,
+        A_D,
+        B,
+        B_B,
+        RefPath::assert_from(b"/b/b/c"),
+        RefPath::assert_from(b"/b/d"),
+        C,
+        C_X,
+        RefPath::assert_from(b"/a/b/c/x"),
+    ];
+
+    type Observation = (
+        Result<Option<ValueType>, RuntimeError>,
+        Result<Vec<u8>, RuntimeError>,
+        Result<Vec<u8>, RuntimeError>,
+        Result<usize, RuntimeError>,
+        Result<u64, RuntimeError>,
+    );
+
+    fn observe(host: &impl Runtime) -> Vec<Observation> {
+        PATHS
+            .iter()
+            .map(|path| {
+                (
+                    host.store_has(path),
+                    host.store_read(path, 1, 10),
+                    host.store_read_all(path),
+                    host.store_value_size(path),
+                    host.store_count_subkeys(path),
+                )
+            })
+            .collect()
+    }
+
+    fn populate(host: &mut impl Runtime) {
+        host.store_write_all(&A_B, &[1, 2, 3]).unwrap();
+        host.store_write_all(&A_B_C, &[4]).unwrap();
+        host.store_write_all(&A_D, &[5, 6]).unwrap();
+        host.store_write_all(&C_X, &[7]).unwrap();
+    }
+
+    #[test]
+    fn changes_written_on_commit() {
+        let mut mock = MockHost::default();
+        populate(&mut mock);
+
+        let mut host = TransactionalHost::new(&mut mock);
+        host.begin_transaction();
+        host.store_write(&A_B, &[8, 9], 2).unwrap();
+        host.store_delete(&C).unwrap();
+
+        assert_eq!(Ok(vec![1, 2, 8, 9]), host.store_read_all(&A_B));
+        assert_eq!(Ok(None), host.store_has(&C));
+
+        host.commit_transaction().unwrap();
+        assert_eq!(0, host.transaction_depth());
+
+        assert_eq!(Ok(vec![1, 2, 8, 9]), mock.store_read_all(&A_B));
+        assert_eq!(Ok(None), mock.store_has(&C));
+    }
+
+    #[test]
+    fn storage_unchanged_until_commit() {
+        let mut mock = MockHost::default();
+        populate(&mut mock);
+        let before = observe(&mock);
+
+        let mut host = TransactionalHost::new(&mut mock);
+        host.begin_transaction();
+        host.store_write_all(&B_B, &[1]).unwrap();
+        host.store_move(&A, &C).unwrap();
+        drop(host);
+
+        assert_eq!(before, observe(&mock));
+    }
+
+    #[test]
+    fn nested_transactions() {
+        let mut mock = MockHost::default();
+        let mut host = TransactionalHost::new(&mut mock);
+
+        host.begin_transaction();
+        host.store_write_all(&A, &[1]).unwrap();
+
+        // Rolled back inner transaction.
+        host.begin_transaction();
+        host.store_write_all(&A, &[2]).unwrap();
+        host.store_write_all(&B, &[2]).unwrap();
+        host.rollback_transaction().unwrap();
+
+        assert_eq!(Ok(vec![1]), host.store_read_all(&A));
+        assert_eq!(Ok(None), host.store_has(&B));
+
+        // Committed inner transaction.
+        host.begin_transaction();
+        host.store_write_all(&B, &[3]).unwrap();
+        host.commit_transaction().unwrap();
+
+        assert_eq!(1, host.transaction_depth());
+        host.commit_transaction().unwrap();
+        assert_eq!(
+            Err(StorageError::NoCurrentTransaction),
+            host.commit_transaction()
+        );
+
+        assert_eq!(Ok(vec![1]), mock.store_read_all(&A));
+        assert_eq!(Ok(vec![3]), mock.store_read_all(&B));
+    }
+
+    #[test]
+    fn rollback_to_savepoints() {
+        let mut mock = MockHost::default();
+        let mut host = TransactionalHost::new(&mut mock);
+
+        assert_eq!(Err(StorageError::NoCurrentTransaction), host.savepoint());
+
+        host.begin_transaction();
+        host.store_write_all(&A, &[1]).unwrap();
+        let first = host.savepoint().unwrap();
+        host.store_write_all(&A, &[2]).unwrap();
+        let second = host.savepoint().unwrap();
+        host.store_write_all(&A, &[3]).unwrap();
+
+        host.rollback_to_savepoint(second).unwrap();
+        assert_eq!(Ok(vec![2]), host.store_read_all(&A));
+
+        host.rollback_to_savepoint(first).unwrap();
+        assert_eq!(Ok(vec![1]), host.store_read_all(&A));
+
+        // Savepoints after the one rolled back to are released.
+        assert_eq!(
+            Err(StorageError::InvalidSavepoint),
+            host.rollback_to_savepoint(second)
+        );
+
+        // Savepoints belong to their transaction.
+        host.begin_transaction();
+        assert_eq!(
+            Err(StorageError::InvalidSavepoint),
+            host.rollback_to_savepoint(first)
+        );
+        host.commit_transaction().unwrap();
+
+        host.store_write_all(&A, &[4]).unwrap();
+        host.rollback_to_savepoint(first).unwrap();
+        host.commit_transaction().unwrap();
+
+        assert_eq!(Ok(vec![1]), mock.store_read_all(&A));
+    }
+
+    enum Operation {
+        Write(RefPath<'static>, &'static [u8], usize),
+        WriteAll(RefPath<'static>, &'static [u8]),
+        Delete(RefPath<'static>),
+        DeleteValue(RefPath<'static>),
+        Copy(RefPath<'static>, RefPath<'static>),
+        Move(RefPath<'static>, RefPath<'static>),
+    }
+
+    fn apply(host: &mut impl Runtime, operation: &Operation) -> Result<(), RuntimeError> {
+        match operation {
+            Operation::Write(path, src, offset) => host.store_write(path, src, *offset),
+            Operation::WriteAll(path, src) => host.store_write_all(path, src),
+            Operation::Delete(path) => host.store_delete(path),
+            Operation::DeleteValue(path) => host.store_delete_value(path),
+            Operation::Copy(from, to) => host.store_copy(from, to),
+            Operation::Move(from, to) => host.store_move(from, to),
+        }
+    }
+
+    #[test]
+    fn same_results_as_durable_storage() {
+        use Operation::*;
+
+        let operations = [
+            Write(A_B, &[4, 5], 1),
+            Copy(A, B),
+            WriteAll(A_D, &[6]),
+            DeleteValue(B_B),
+            Write(B_B, &[1], 1),
+            Write(B_B, &[1], 0),
+            Move(C, A_B_C),
+            Delete(A_D),
+            Delete(A_D),
+            WriteAll(C_X, &[7, 8]),
+            Copy(B, A),
+            Copy(C, B_B),
+            Move(A_B, C),
+            Delete(B),
+            Copy(A_B_C, A_D),
+        ];
+
+        let mut expected = MockHost::default();
+        populate(&mut expected);
+
+        let mut mock = MockHost::default();
+        populate(&mut mock);
+        let mut actual = TransactionalHost::new(&mut mock);
+        actual.begin_transaction();
+
+        for (i, operation) in operations.iter().enumerate() {
+            assert_eq!(
+                apply(&mut expected, operation),
+                apply(&mut actual, operation),
+                "operation {}",
+                i
+            );
+            assert_eq!(observe(&expected), observe(&actual), "operation {}", i);
+        }
+
+        actual.commit_transaction().unwrap();
+        assert_eq!(observe(&expected), observe(&mock));
+        assert_eq!(Ok(None), mock.store_has(&TMP_PATH));
+    }
+}
@@ -0,0 +1,217 @@
+// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Structured events parsed from the TX kernel debug log.
+//!
+//! The kernel logs the level of each inbox message it reads, and the outcome of
+//! each operation it executes:
+//!
+//! ```text
+//! Processing MessageData 3 at level 42
+//! Operation 5f3a...c2 by tz1...: applied
+//! Operation 77b0...9e by tz1...: failed: Account operation counter at 2, transaction had 1
+//! ```
+//!
+//! Other lines are ignored.
+
+use serde::Serialize;
+
+/// Outcome of an operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationResult {
+    Applied,
+    Failed,
+}
+
+/// Event forwarded to clients as JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// An operation was executed by the kernel
+    Operation {
+        /// Inbox level at which the operation was executed, if known
+        level: Option<u32>,
+        /// Hex encoded hash of the operation
+        hash: String,
+        /// Address of the signer of the operation
+        account: String,
+        result: OperationResult,
+        /// Reason of the failure, for failed operations
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+const LEVEL_MARKER: &str = " at level ";
+const OPERATION_PREFIX: &str = "Operation ";
+const MESSAGE_PREFIX: &str = "Processing MessageData ";
+
+/// Incremental parser of the kernel log.
+#[derive(Debug, Default)]
+pub struct LogParser {
+    /// Level of the last inbox message read by the kernel
+    level: Option<u32>,
+
+    /// Incomplete last line of the log read so far
+    partial: Vec<u8>,
+}
+
+impl LogParser {
+    /// Parse the next bytes of the log, returning the events of the lines
+    /// they complete.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Event> {
+        self.partial.extend_from_slice(bytes);
+
+        let Some(end) = self.partial.iter().rposition(|b| *b == b'\n') else {
+            return Vec::new();
+        };
+        let rest = self.partial.split_off(end + 1);
+        let complete = std::mem::replace(&mut self.partial, rest);
+
+        String::from_utf8_lossy(&complete)
+            .lines()
+            .filter_map(|line| self.parse_line(line))
+            .collect()
+    }
+
+    fn parse_line(&mut self, line: &str) -> Option<Event> {
+        if let Some(message) = line.strip_prefix(MESSAGE_PREFIX) {
+            let (_, level) = message.split_once(LEVEL_MARKER)?;
+            self.level = level.trim().parse().ok();
+            return None;
+        }
+
+        let operation = line.strip_prefix(OPERATION_PREFIX)?;
+        let (hash, operation) = operation.split_once(" by ")?;
+        let (account, outcome) = operation.split_once(": ")?;
+
+        let (result, error) = match outcome.split_once(": ") {
+            None if outcome == "applied" => (OperationResult::Applied, None),
+            Some(("failed", error)) => (OperationResult::Failed, Some(error.to_owned())),
+            _ => return None,
+        };
+
+        Some(Event::Operation {
+            level: self.level,
+            hash: hash.to_owned(),
+            account: account.to_owned(),
+            result,
+            error,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn operation(level: Option<u32>, hash: &str, error: Option<&str>) -> Event {
+        Event::Operation {
+            level,
+            hash: hash.to_owned(),
+            account: format!("tz1{hash}"),
+            result: match error {
+                None => OperationResult::Applied,
+                Some(_) => OperationResult::Failed,
+            },
+            error: error.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn lines_split_across_reads() {
+        let mut parser = LogParser::default();
+
+        assert!(parser.feed(b"Processing MessageData 3 at le").is_empty());
+        assert!(parser.feed(b"vel 42\nOperation aa by tz1").is_empty());
+        assert!(parser.feed(b"aa: app").is_empty());
+        assert_eq!(
+            vec![operation(Some(42), "aa", None)],
+            parser.feed(b"lied\nOperation bb")
+        );
+        assert_eq!(
+            vec![operation(Some(42), "bb", None)],
+            parser.feed(b" by tz1bb: applied\n")
+        );
+    }
+
+    #[test]
+    fn applied_and_failed_operations() {
+        let mut parser = LogParser::default();
+
+        let events = parser.feed(
+            b"Operation aa by tz1aa: applied\n\
+              Operation bb by tz1bb: failed: Account operation counter at 2, transaction had 1\n\
+              Operation cc by tz1cc: failed: Invalid ticket: expected red: got blue\n\
+              Operation dd by tz1dd: pending\n\
+              Operation ee by tz1ee: applied: twice\n\
+              Kernel booted\n",
+        );
+
+        assert_eq!(
+            vec![
+                operation(None, "aa", None),
+                operation(
+                    None,
+                    "bb",
+                    Some("Account operation counter at 2, transaction had 1")
+                ),
+                operation(None, "cc", Some("Invalid ticket: expected red: got blue")),
+            ],
+            events
+        );
+    }
+
+    #[test]
+    fn operations_carry_the_last_level() {
+        let mut parser = LogParser::default();
+
+        let events = parser.feed(
+            b"Operation aa by tz1aa: applied\n\
+              Processing MessageData 0 at level 7\n\
+              Operation bb by tz1bb: applied\n\
+              Processing MessageData 2 at level 8\n\
+              Operation cc by tz1cc: applied\n\
+              Processing MessageData 0 at level ?\n\
+              Operation dd by tz1dd: applied\n",
+        );
+
+        assert_eq!(
+            vec![
+                operation(None, "aa", None),
+                operation(Some(7), "bb", None),
+                operation(Some(8), "cc", None),
+                operation(None, "dd", None),
+            ],
+            events
+        );
+    }
+
+    #[test]
+    fn events_as_json() {
+        assert_eq!(
+            json!({
+                "type": "operation",
+                "level": 7,
+                "hash": "aa",
+                "account": "tz1aa",
+                "result": "applied",
+            }),
+            serde_json::to_value(operation(Some(7), "aa", None)).unwrap()
+        );
+        assert_eq!(
+            json!({
+                "type": "operation",
+                "level": null,
+                "hash": "bb",
+                "account": "tz1bb",
+                "result": "failed",
+                "error": "Not enough funds",
+            }),
+            serde_json::to_value(operation(None, "bb", Some("Not enough funds"))).unwrap()
+        );
+    }
+}
@@ -0,0 +1,209 @@
+// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Inbox file of operation batches injected by clients.
+//!
+//! The file follows the inputs format of the rollup debugger and mock runner:
+//! a JSON list of levels, each a list of messages such as
+//! `{"external": "<hex encoded message>"}`. Every injected batch is appended
+//! as a new level, framed for the configured rollup.
+
+use anyhow::{anyhow, bail, Context};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::PathBuf;
+use tezos_data_encoding::enc::BinWriter;
+use tezos_smart_rollup_encoding::inbox::ExternalMessageFrame;
+use tezos_smart_rollup_encoding::smart_rollup::SmartRollupAddress;
+use tx_kernel::inbox::ParsedExternalInboxMessage;
+
+/// Inbox file, kept in memory and rewritten on every injection.
+pub struct InboxFile {
+    path: PathBuf,
+    address: SmartRollupAddress,
+    levels: Vec<Value>,
+}
+
+impl InboxFile {
+    /// Open the inbox file at `path`, keeping the levels it already holds.
+    pub fn open(path: PathBuf, address: SmartRollupAddress) -> anyhow::Result<Self> {
+        let levels = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .with_context(|| format!("Malformed inbox file {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            path,
+            address,
+            levels,
+        })
+    }
+
+    /// Append a new level, holding the given external message. The message
+    /// must be a batch of operations, as encoded by the kernel's
+    /// `ExternalInboxMessage::OpList`.
+    ///
+    /// Returns the index of the new level in the file.
+    pub fn inject(&mut self, message: &[u8]) -> anyhow::Result<usize> {
+        let (remaining, parsed) = ParsedExternalInboxMessage::parse(message)
+            .map_err(|e| anyhow!("Malformed batch: {e:?}"))?;
+        if !remaining.is_empty() {
+            bail!("{} unexpected trailing bytes", remaining.len());
+        }
+        match parsed {
+            ParsedExternalInboxMessage::OpList(batch) if batch.operations.is_empty() => {
+                bail!("Empty batch")
+            }
+            ParsedExternalInboxMessage::OpList(_) => {}
+            _ => bail!("Not a batch of operations"),
+        }
+
+        let frame = ExternalMessageFrame::Targetted {
+            address: self.address.clone(),
+            contents: message,
+        };
+        let mut framed = Vec::new();
+        frame
+            .bin_write(&mut framed)
+            .map_err(|e| anyhow!("Could not frame message: {e:?}"))?;
+
+        let level = json!([{ "external": hex::encode(framed) }]);
+        self.levels.push(level);
+        self.write()?;
+
+        Ok(self.levels.len() - 1)
+    }
+
+    /// Write the inbox file atomically, so that runners never read a partial
+    /// file.
+    fn write(&self) -> anyhow::Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(&self.levels)?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tezos_crypto_rs::PublicKeyWithHash;
+    use tezos_smart_rollup_encoding::contract::Contract;
+    use tezos_smart_rollup_encoding::michelson::ticket::StringTicket;
+    use tezos_smart_rollup_encoding::michelson::MichelsonString;
+    use tx_kernel::inbox::external::sendable::ExternalInboxMessage;
+    use tx_kernel::inbox::external::testing::gen_ed25519_keys;
+    use tx_kernel::inbox::v1::sendable::Batch;
+    use tx_kernel::inbox::v1::{Operation, OperationContent};
+    use tx_kernel::inbox::Signer;
+
+    const ROLLUP: &str = "sr163Lv22CdE8QagCwf48PWDTquk6isQwv57";
+
+    fn inbox_path(name: &str) -> PathBuf {
+        let file = format!("tx-demo-collector-{}-{name}.json", std::process::id());
+        let path = std::env::temp_dir().join(file);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn encode(message: ExternalInboxMessage) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        message.bin_write(&mut encoded).unwrap();
+        encoded
+    }
+
+    // A batch of `operations` transfers from a single account.
+    fn batch(operations: i64) -> Vec<u8> {
+        let (pk, sk) = gen_ed25519_keys();
+        let destination = gen_ed25519_keys().0.pk_hash().unwrap();
+
+        let creator = Contract::from_b58check("KT1JW6PwhfaEJu6U3ENsxUeja48AdtqSoekd").unwrap();
+        let ticket = StringTicket::new(creator, MichelsonString("red".into()), 10).unwrap();
+
+        let operations = (0..operations)
+            .map(|counter| {
+                let operation = Operation {
+                    signer: Signer::PublicKey(pk.clone()),
+                    counter,
+                    fee: 0.into(),
+                    contents: OperationContent::transfer(
+                        destination.clone(),
+                        ticket.hash().unwrap(),
+                        1,
+                    )
+                    .unwrap(),
+                };
+                (operation, sk.clone())
+            })
+            .collect();
+
+        encode(ExternalInboxMessage::OpList(Batch::new(operations)))
+    }
+
+    #[test]
+    fn inject_appends_framed_levels() {
+        // Arrange
+        let path = inbox_path("inject");
+        let address = SmartRollupAddress::from_b58check(ROLLUP).unwrap();
+        let mut inbox = InboxFile::open(path.clone(), address.clone()).unwrap();
+
+        let messages = [batch(1), batch(2)];
+
+        // Act
+        let levels: Vec<usize> = messages
+            .iter()
+            .map(|message| inbox.inject(message).unwrap())
+            .collect();
+
+        // Assert
+        assert_eq!(vec![0, 1], levels);
+
+        let written: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        let written = written.as_array().unwrap();
+        assert_eq!(messages.len(), written.len());
+        for (level, message) in written.iter().zip(messages.iter()) {
+            let level = level.as_array().unwrap();
+            assert_eq!(1, level.len());
+
+            let framed = hex::decode(level[0]["external"].as_str().unwrap()).unwrap();
+            let expected = ExternalMessageFrame::Targetted {
+                address: address.clone(),
+                contents: message,
+            };
+            assert_eq!(expected, ExternalMessageFrame::parse(&framed).unwrap());
+        }
+
+        // Levels already in the file are kept when reopening it.
+        let mut inbox = InboxFile::open(path.clone(), address).unwrap();
+        assert_eq!(2, inbox.inject(&batch(1)).unwrap());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn inject_rejects_other_messages() {
+        // Arrange
+        let path = inbox_path("reject");
+        let address = SmartRollupAddress::from_b58check(ROLLUP).unwrap();
+        let mut inbox = InboxFile::open(path.clone(), address).unwrap();
+
+        let account = gen_ed25519_keys().0.pk_hash().unwrap();
+        let mut trailing = batch(1);
+        trailing.push(0);
+
+        // Act & Assert
+        for message in [
+            encode(ExternalInboxMessage::ChangeDalSlot(3)),
+            encode(ExternalInboxMessage::QueryBalance(account)),
+            batch(0),
+            trailing,
+            vec![0xff],
+        ] {
+            assert!(inbox.inject(&message).is_err());
+        }
+        assert!(!path.exists());
+    }
+}
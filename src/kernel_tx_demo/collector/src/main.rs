@@ -2,39 +2,103 @@
 //
 // SPDX-License-Identifier: MIT
 
-use clap::{CommandFactory, Parser};
+mod events;
+mod inbox;
+
+use clap::{CommandFactory, Parser, ValueEnum};
+use events::LogParser;
+use inbox::InboxFile;
 use notify::{RecursiveMode, Result, Watcher};
 use std::cmp;
 use std::fs::File;
 use std::io::{Read, Seek};
 use std::net::TcpStream;
-use websocket::sender::Sender;
-use websocket::sync::{Client, Server};
-use websocket::ws::Sender as SenderTrait;
-use websocket::Message;
+use std::sync::{Arc, Mutex};
+use tezos_smart_rollup_encoding::smart_rollup::SmartRollupAddress;
+use websocket::sync::{Reader, Server, Writer};
+use websocket::{Message, OwnedMessage};
+
+/// How the kernel log is forwarded to clients
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Raw 4-byte pixel updates, prefixed by the grid position
+    Pixels,
+    /// JSON events parsed from the kernel debug messages
+    Events,
+}
 
-/// Simple program to greet a person
+/// Forward the TX kernel log to websocket clients
 #[derive(Parser)]
 struct Args {
-    /// Name of the person to greet
+    /// Path of the kernel log file
     #[arg(short = 'f', long)]
     log_path: std::path::PathBuf,
 
-    /// Number of times to greet
+    /// Port on which to accept websocket connections
     #[arg(short, long)]
     port: u16,
 
+    /// Format of the messages sent to clients
+    #[arg(long, value_enum, default_value = "pixels")]
+    format: Format,
+
+    /// Grid row, for the pixels format
     #[arg(short, long)]
-    row: u8,
+    row: Option<u8>,
 
+    /// Grid column, for the pixels format
     #[arg(short, long)]
-    column: u8,
+    column: Option<u8>,
 
     #[arg(short = 'm', long, default_value = "40")]
     min_buffer_size: usize,
 
     #[arg(short = 'M', long, default_value = "1024")]
     max_buffer_size: usize,
+
+    /// Inbox file to which operation batches sent by clients are appended
+    #[arg(short, long, requires = "rollup_address")]
+    inbox: Option<std::path::PathBuf>,
+
+    /// Address of the rollup targetted by the injected batches
+    #[arg(short = 'a', long, requires = "inbox")]
+    rollup_address: Option<String>,
+}
+
+/// Send a JSON value to the client as a text message.
+fn send_json(writer: &Mutex<Writer<TcpStream>>, value: &impl serde::Serialize) -> bool {
+    let Ok(json) = serde_json::to_string(value) else {
+        return false;
+    };
+    writer
+        .lock()
+        .unwrap()
+        .send_message(&Message::text(json))
+        .is_ok()
+}
+
+/// Write the operation batches received from the client to the inbox file,
+/// acknowledging each of them.
+fn inject_batches(
+    mut reader: Reader<TcpStream>,
+    writer: Arc<Mutex<Writer<TcpStream>>>,
+    inbox: Arc<Mutex<InboxFile>>,
+) {
+    for message in reader.incoming_messages() {
+        let payload = match message {
+            Ok(OwnedMessage::Binary(payload)) => payload,
+            Ok(OwnedMessage::Close(_)) | Err(_) => return,
+            Ok(_) => continue,
+        };
+
+        let reply = match inbox.lock().unwrap().inject(&payload) {
+            Ok(level) => serde_json::json!({ "type": "injected", "level": level }),
+            Err(e) => serde_json::json!({ "type": "rejected", "error": e.to_string() }),
+        };
+        if !send_json(&writer, &reply) {
+            return;
+        }
+    }
 }
 
 fn main() -> anyhow::Result<()> {
@@ -43,12 +107,43 @@ fn main() -> anyhow::Result<()> {
     let Args {
         log_path,
         port,
+        format,
         row,
         column,
         min_buffer_size,
         max_buffer_size,
+        inbox,
+        rollup_address,
     } = Args::parse();
 
+    let (row, column) = match (format, row, column) {
+        (Format::Pixels, Some(row), Some(column)) => (row, column),
+        (Format::Pixels, _, _) => Args::command()
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "--row and --column are required by the pixels format",
+            )
+            .exit(),
+        (Format::Events, _, _) => (0, 0),
+    };
+
+    let inbox = match (inbox, rollup_address) {
+        (Some(path), Some(address)) => {
+            let address = match SmartRollupAddress::from_b58check(&address) {
+                Ok(address) => address,
+                Err(e) => Args::command()
+                    .error(
+                        clap::error::ErrorKind::InvalidValue,
+                        format_args!("Invalid rollup address: {e:?}"),
+                    )
+                    .exit(),
+            };
+            Some(Arc::new(Mutex::new(InboxFile::open(path, address)?)))
+        }
+        _ => None,
+    };
+    let mut log_parser = LogParser::default();
+
     let mut log_file = match File::open(&log_path) {
         Ok(f) => f,
         Err(e) => Args::command()
@@ -67,7 +162,8 @@ fn main() -> anyhow::Result<()> {
 
     println!("Ready to receive new connection");
 
-    let (listeners_tx, listeners_rx) = std::sync::mpsc::channel::<Option<Client<TcpStream>>>();
+    let (listeners_tx, listeners_rx) =
+        std::sync::mpsc::channel::<Option<websocket::sync::Client<TcpStream>>>();
     let listeners_tx_clone_for_ctrlc = listeners_tx.clone();
     std::thread::spawn(move || loop {
         match listener.accept() {
@@ -114,9 +210,16 @@ fn main() -> anyhow::Result<()> {
             break;
         }
         match listeners_rx.recv() {
-            Ok(Some(mut client)) => {
-                //let hello = socket.write_all("Hello!\n".as_bytes());
-                // Automatically select the best implementation for your platform.
+            Ok(Some(client)) => {
+                let (reader, writer) = client.split()?;
+                let writer = Arc::new(Mutex::new(writer));
+
+                if let Some(inbox) = &inbox {
+                    let writer = writer.clone();
+                    let inbox = inbox.clone();
+                    std::thread::spawn(move || inject_batches(reader, writer, inbox));
+                }
+
                 {
                     // Add a path to be watched. All files and directories at that path and
                     // below will be monitored for changes.
@@ -138,6 +241,20 @@ fn main() -> anyhow::Result<()> {
                         log_file.seek(pos)?;
                         loop {
                             let read_amount = log_file.read(buf)?;
+
+                            if format == Format::Events {
+                                if read_amount == 0 {
+                                    break;
+                                }
+                                pointer += read_amount;
+                                for event in log_parser.feed(&buf[..read_amount]) {
+                                    if !send_json(&writer, &event) {
+                                        break 'notify;
+                                    }
+                                }
+                                continue;
+                            }
+
                             if read_amount < 4 * min_buffer_size {
                                 break;
                             }
@@ -149,10 +266,7 @@ fn main() -> anyhow::Result<()> {
                                 .flat_map(|x| [row, column, x[0], x[1], x[2], x[3]])
                                 .collect();
                             let message = Message::binary(buf);
-                            let mut sender = Sender::new(false);
-                            let mut writer = Vec::new();
-                            sender.send_message(&mut writer, &message).unwrap();
-                            match client.writer_mut().write_all(&writer) {
+                            match writer.lock().unwrap().send_message(&message) {
                                 Ok(_) => {
                                     pointer += read_amount;
                                 }
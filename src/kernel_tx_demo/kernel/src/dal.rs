@@ -11,12 +11,16 @@
 extern crate alloc;
 extern crate tezos_crypto_rs as crypto;
 
-use tezos_smart_rollup_host::path::OwnedPath;
+use crate::transactions::store::{DAL_PAYLOAD_PATH, DAL_QUEUE_PATH};
+use tezos_smart_rollup_host::path::{OwnedPath, Path};
 
 #[cfg(feature = "debug")]
 use tezos_smart_rollup_debug::debug_msg;
 
-use tezos_smart_rollup_host::runtime::Runtime;
+use tezos_smart_rollup_host::runtime::{Runtime, RuntimeError};
+
+/// Size of a queued slot: its published level, followed by its index
+const QUEUED_SLOT_SIZE: usize = core::mem::size_of::<i32>() + 1;
 
 #[allow(dead_code)]
 pub(crate) fn store_dal_slot(
@@ -27,6 +31,23 @@ pub(crate) fn store_dal_slot(
     slot_index: u8,
     path_to_store: OwnedPath,
 ) {
+    // The payload of a previous slot is still pending, whether or not its
+    // processing started, and would be corrupted by the new one. The slot is
+    // queued instead, and revealed once the payload is done.
+    if path_to_store.as_bytes() == DAL_PAYLOAD_PATH.as_bytes()
+        && matches!(host.store_has(&DAL_PAYLOAD_PATH), Ok(Some(_)))
+    {
+        #[cfg(feature = "debug")]
+        debug_msg!(
+            host,
+            "Previous DAL payload not processed yet, queueing slot of level {published_level}\n"
+        );
+        queue_dal_slot(host, published_level, slot_index)
+            .map_err(|_| "Error writing to storage".to_string())
+            .unwrap_or_default();
+        return;
+    }
+
     let mut buffer = vec![0u8; page_size];
     for page_index in 0..(num_pages as i16) {
         let result = host.reveal_dal_page(published_level, slot_index, page_index, &mut buffer);
@@ -50,3 +71,141 @@ pub(crate) fn store_dal_slot(
         }
     }
 }
+
+/// Append a slot to the ones attested while a payload was pending.
+fn queue_dal_slot(
+    host: &mut impl Runtime,
+    published_level: i32,
+    slot_index: u8,
+) -> Result<(), RuntimeError> {
+    let size = match host.store_value_size(&DAL_QUEUE_PATH) {
+        Ok(size) => size,
+        Err(RuntimeError::PathNotFound) => 0,
+        Err(e) => return Err(e),
+    };
+
+    let mut entry = published_level.to_le_bytes().to_vec();
+    entry.push(slot_index);
+    host.store_write(&DAL_QUEUE_PATH, &entry, size)
+}
+
+/// Remove the first slot attested while a payload was pending, if any.
+fn dequeue_dal_slot(host: &mut impl Runtime) -> Result<Option<(i32, u8)>, RuntimeError> {
+    let queue = match host.store_read_all(&DAL_QUEUE_PATH) {
+        Ok(queue) => queue,
+        Err(RuntimeError::PathNotFound) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    if queue.len() < QUEUED_SLOT_SIZE {
+        host.store_delete(&DAL_QUEUE_PATH)?;
+        return Ok(None);
+    }
+
+    let (entry, rest) = queue.split_at(QUEUED_SLOT_SIZE);
+    if rest.len() < QUEUED_SLOT_SIZE {
+        host.store_delete(&DAL_QUEUE_PATH)?;
+    } else {
+        host.store_write_all(&DAL_QUEUE_PATH, rest)?;
+    }
+
+    let published_level = i32::from_le_bytes(entry[..4].try_into().unwrap());
+    Ok(Some((published_level, entry[4])))
+}
+
+/// Once the DAL payload is done, store the next slot attested while it was
+/// pending as the new payload. Queued slots without any content are skipped.
+pub(crate) fn store_next_queued_slot(host: &mut impl Runtime) {
+    let parameters = host.reveal_dal_parameters();
+    let page_size = parameters.page_size as usize;
+    let num_pages = (parameters.slot_size as usize + page_size - 1) / page_size;
+
+    while let Ok(None) = host.store_has(&DAL_PAYLOAD_PATH) {
+        match dequeue_dal_slot(host) {
+            Ok(Some((published_level, slot_index))) => store_dal_slot(
+                host,
+                published_level,
+                num_pages,
+                page_size,
+                slot_index,
+                DAL_PAYLOAD_PATH.into(),
+            ),
+            Ok(None) => return,
+            Err(_e) => {
+                #[cfg(feature = "debug")]
+                debug_msg!(host, "Failed to read the queued DAL slots: {:?}\n", _e);
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tezos_smart_rollup_host::dal_parameters::RollupDalParameters;
+    use tezos_smart_rollup_mock::MockHost;
+
+    fn set_up_slots(host: &mut MockHost) {
+        host.set_dal_parameters(RollupDalParameters {
+            number_of_slots: 1,
+            attestation_lag: 1,
+            slot_size: 8,
+            page_size: 4,
+        });
+        host.set_dal_slot(1, 0, &[1; 8]);
+        host.set_dal_slot(2, 0, &[2; 4]);
+        host.set_dal_slot(3, 0, &[3; 8]);
+    }
+
+    #[test]
+    fn pending_payload_is_not_overwritten() {
+        // Arrange
+        let mut host = MockHost::default();
+        set_up_slots(&mut host);
+
+        // Act
+        store_dal_slot(&mut host, 1, 2, 4, 0, DAL_PAYLOAD_PATH.into());
+        store_dal_slot(&mut host, 2, 2, 4, 0, DAL_PAYLOAD_PATH.into());
+        store_dal_slot(&mut host, 3, 2, 4, 0, DAL_PAYLOAD_PATH.into());
+
+        // Assert
+        assert_eq!(vec![1; 8], host.store_read_all(&DAL_PAYLOAD_PATH).unwrap());
+        assert_eq!(
+            vec![2, 0, 0, 0, 0, 3, 0, 0, 0, 0],
+            host.store_read_all(&DAL_QUEUE_PATH).unwrap()
+        );
+    }
+
+    #[test]
+    fn queued_slots_are_stored_in_order() {
+        // Arrange
+        let mut host = MockHost::default();
+        set_up_slots(&mut host);
+
+        store_dal_slot(&mut host, 1, 2, 4, 0, DAL_PAYLOAD_PATH.into());
+        store_dal_slot(&mut host, 2, 2, 4, 0, DAL_PAYLOAD_PATH.into());
+        store_dal_slot(&mut host, 3, 2, 4, 0, DAL_PAYLOAD_PATH.into());
+
+        // Act & Assert
+        store_next_queued_slot(&mut host);
+        assert_eq!(vec![1; 8], host.store_read_all(&DAL_PAYLOAD_PATH).unwrap());
+
+        host.store_delete(&DAL_PAYLOAD_PATH).unwrap();
+        store_next_queued_slot(&mut host);
+        assert_eq!(
+            vec![2, 2, 2, 2],
+            host.store_read_all(&DAL_PAYLOAD_PATH).unwrap()[..4]
+        );
+
+        host.store_delete(&DAL_PAYLOAD_PATH).unwrap();
+        store_next_queued_slot(&mut host);
+        assert_eq!(vec![3; 8], host.store_read_all(&DAL_PAYLOAD_PATH).unwrap());
+        assert!(host.store_has(&DAL_QUEUE_PATH).unwrap().is_none());
+
+        host.store_delete(&DAL_PAYLOAD_PATH).unwrap();
+        store_next_queued_slot(&mut host);
+        assert!(host.store_has(&DAL_PAYLOAD_PATH).unwrap().is_none());
+    }
+}
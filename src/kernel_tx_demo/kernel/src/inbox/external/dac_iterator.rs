@@ -14,6 +14,7 @@ use tezos_smart_rollup_host::path::PathError;
 use tezos_smart_rollup_host::runtime::{Runtime, RuntimeError};
 
 use super::v1::verifiable::VerifiableOperation;
+use super::v1::{BATCH_TAG, BATCH_WITHOUT_FEES_TAG};
 
 /// Errors that can be returned while traversing the list of [VerifiableOperation].
 #[derive(Debug)]
@@ -26,6 +27,9 @@ pub enum IteratorStateError {
     HostStoreWriteIteratorStateError(RuntimeError),
     /// Parsing an element of type [VerifiableOperation] failed.
     DecodeError,
+    /// The dac payload is prefixed neither by a batch tag nor, for payloads
+    /// predating batch tags, by the length of the list of operations.
+    InvalidBatchTag,
     /// Encoding the iterator data fails
     EncodeIteratorStateError(BinError),
     /// Error when constructing the Dac payload path
@@ -62,6 +66,8 @@ pub struct IteratorState {
     total_length: u32,
     /// The message index that the DAC iterator state/message payload are stored
     idx: u32,
+    /// Whether the operations predate fees, see [VerifiableOperation::parse_without_fee].
+    without_fees: bool,
 }
 
 impl IteratorState {
@@ -73,8 +79,6 @@ impl IteratorState {
         let total_length = host
             .store_value_size(&dac_payload_path)
             .map_err(IteratorStateError::HostStoreReadDacPayloadError)?;
-        let buffer_offset = 4; //skip prefix field containing list length
-
         let bytes_to_read_first_half = std::cmp::min(half_buffer_size, total_length);
         let mut buffer = vec![0; 2 * half_buffer_size];
 
@@ -110,6 +114,16 @@ impl IteratorState {
         let read_bytes = read_bytes_left_half + read_bytes_right_half;
         buffer.truncate(read_bytes);
 
+        // Skip the batch tag, if any, and the prefix field containing the list length.
+        // Payloads predating batch tags start with that length, whose most significant
+        // byte is zero as payloads are smaller than 16MiB.
+        let (buffer_offset, without_fees) = match buffer.first() {
+            Some(&BATCH_TAG) => (5, false),
+            Some(&BATCH_WITHOUT_FEES_TAG) => (5, true),
+            Some(0) => (4, true),
+            _ => return Err(IteratorStateError::InvalidBatchTag),
+        };
+
         // Determine the index from where to start reading the next chunk of DAC payload, if any.
         let next_offset = if read_bytes < total_length {
             Some(read_bytes as u32)
@@ -123,6 +137,7 @@ impl IteratorState {
             next_offset,
             total_length: total_length as u32,
             idx,
+            without_fees,
         })
     }
 
@@ -217,8 +232,13 @@ impl IteratorState {
         }
         let buffer_offset = self.buffer_offset as usize;
         let next_element_buf = &self.buffer[buffer_offset..self.buffer.len()];
-        let (remaining, verifiable_operation) = VerifiableOperation::parse(next_element_buf)
-            .map_err(|_| IteratorStateError::DecodeError)?;
+        let parse = if self.without_fees {
+            VerifiableOperation::parse_without_fee
+        } else {
+            VerifiableOperation::parse
+        };
+        let (remaining, verifiable_operation) =
+            parse(next_element_buf).map_err(|_| IteratorStateError::DecodeError)?;
         // Bump buffer_offset to match remaining portion of buffer.
         self.buffer_offset = (self.buffer.len() - remaining.len()) as u32;
         Ok(Some(verifiable_operation))
@@ -276,7 +296,7 @@ mod tests {
     use tezos_smart_rollup_host::runtime::Runtime;
     use tezos_smart_rollup_mock::MockHost;
 
-    use crate::inbox::dac_iterator::IteratorState;
+    use crate::inbox::dac_iterator::{IteratorState, IteratorStateError};
     use crate::inbox::v1::sendable::Batch;
     use crate::inbox::v1::Operation;
     use crate::transactions::store::dac_payload_path;
@@ -303,7 +323,7 @@ mod tests {
 
             let batch = Batch::new(operations_with_signatures);
             let mut dac_preimage = Vec::new();
-            batch.bin_write(&mut dac_preimage).unwrap();
+            batch.bin_write_tagged(&mut dac_preimage).unwrap();
 
             let dac_payload_path = dac_payload_path(0).unwrap();
             host.store_write_all(&dac_payload_path, &dac_preimage).unwrap();
@@ -338,7 +358,7 @@ mod tests {
 
             let batch = Batch::new(operations_with_signatures);
             let mut dac_preimage = Vec::new();
-            batch.bin_write(&mut dac_preimage).unwrap();
+            batch.bin_write_tagged(&mut dac_preimage).unwrap();
 
             let dac_payload_path = dac_payload_path(1).unwrap();
             host.store_write_all(&dac_payload_path, &dac_preimage).unwrap();
@@ -368,7 +388,7 @@ mod tests {
 
             let batch = Batch::new(operations_with_signatures);
             let mut dac_preimage = Vec::new();
-            batch.bin_write(&mut dac_preimage).unwrap();
+            batch.bin_write_tagged(&mut dac_preimage).unwrap();
 
             let dac_payload_path = dac_payload_path(2).unwrap();
             host.store_write_all(&dac_payload_path, &dac_preimage).unwrap();
@@ -404,7 +424,7 @@ mod tests {
 
             let batch = Batch::new(operations);
             let mut dac_preimage = Vec::new();
-            batch.bin_write(&mut dac_preimage).unwrap();
+            batch.bin_write_tagged(&mut dac_preimage).unwrap();
 
             let dac_payload_path = dac_payload_path(3).unwrap();
             host.store_write_all(&dac_payload_path, &dac_preimage).unwrap();
@@ -439,6 +459,42 @@ mod tests {
 
             assert_eq!(verifiable_operations_iter.next(), None);
         }
+
+        /// DAC payloads predating the batch tag hold operations predating fees.
+        #[test]
+        fn dac_iterator_traverse_untagged(
+            operations_with_signatures in collection::vec(Operation::arb_with_signer(), 0..50),
+        ) {
+            let mut host = MockHost::default();
+
+            let operations_with_signatures: Vec<_> = operations_with_signatures
+                .into_iter()
+                .map(|(operation, sk)| (Operation { fee: 0.into(), ..operation }, sk))
+                .collect();
+            let original_operations: Vec<Operation> = operations_with_signatures.iter().map(|(operation, _ )| {
+                let mut buf = Vec::new();
+                operation.bin_write(&mut buf).unwrap();
+                let (_, op) = Operation::nom_read(&buf).unwrap();
+                op
+            }).collect();
+
+            let batch = Batch::new(operations_with_signatures);
+            let mut dac_preimage = Vec::new();
+            batch.bin_write_without_fees(&mut dac_preimage).unwrap();
+            // Drop the batch tag.
+            dac_preimage.remove(0);
+
+            let dac_payload_path = dac_payload_path(4).unwrap();
+            host.store_write_all(&dac_payload_path, &dac_preimage).unwrap();
+
+            let mut iterator = IteratorState::new(&mut host, 4).unwrap();
+            let mut original_operations_iterator = original_operations.into_iter();
+            while let Some(next_verifiable_operation) = iterator.next(&mut host).unwrap() {
+                let original_operation = original_operations_iterator.next().unwrap();
+                assert_eq!(original_operation, next_verifiable_operation.operation)
+            };
+            assert_eq!(original_operations_iterator.next(), None);
+        }
     }
 
     /// This test checks the case where the DAC payload is the empty list of transactions
@@ -449,7 +505,7 @@ mod tests {
 
         let batch = Batch::new(operations);
         let mut dac_preimage = Vec::new();
-        batch.bin_write(&mut dac_preimage).unwrap();
+        batch.bin_write_tagged(&mut dac_preimage).unwrap();
 
         let dac_payload_path = dac_payload_path(5).unwrap();
         host.store_write_all(&dac_payload_path, &dac_preimage)
@@ -459,4 +515,20 @@ mod tests {
         let next_operation = iterator.next(&mut host).unwrap();
         assert_eq!(next_operation, None);
     }
+
+    /// DAC payloads with an unknown batch tag are rejected, rather than parsed
+    /// from the wrong offset.
+    #[test]
+    fn dac_iterator_rejects_unknown_tag() {
+        let mut host = MockHost::default();
+
+        let dac_payload_path = dac_payload_path(6).unwrap();
+        host.store_write_all(&dac_payload_path, &[2, 0, 0, 0, 0])
+            .unwrap();
+
+        assert!(matches!(
+            IteratorState::new(&mut host, 6),
+            Err(IteratorStateError::InvalidBatchTag)
+        ));
+    }
 }
@@ -41,23 +41,40 @@ pub enum ParsedExternalInboxMessage<'a> {
     OpList(v1::ParsedBatch<'a>),
     /// Change the DAL slot that the kernel downloads and processes.
     ChangeDalSlot(u8),
+    /// Request a balance report for an account.
+    QueryBalance(ContractTz1Hash),
 }
 
 impl<'a> ParsedExternalInboxMessage<'a> {
     const PARSED_DAC_MESSAGE_DAC: u8 = 0;
-    const PARSED_BATCH_TAG: u8 = 1;
+    const PARSED_CHANGE_DAL_SLOT_TAG: u8 = 2;
+    const PARSED_QUERY_BALANCE_TAG: u8 = 3;
 
     /// Parse an external inbox message.
     pub fn parse(input: &'a [u8]) -> NomResult<Self> {
         alt((
             map(
-                preceded(tag([Self::PARSED_BATCH_TAG]), v1::ParsedBatch::parse),
+                v1::ParsedBatch::parse_tagged,
                 ParsedExternalInboxMessage::OpList,
             ),
             map(
                 preceded(tag([Self::PARSED_DAC_MESSAGE_DAC]), V0Certificate::nom_read),
                 |c| ParsedExternalInboxMessage::DAC(Certificate::V0(c)),
             ),
+            map(
+                preceded(
+                    tag([Self::PARSED_CHANGE_DAL_SLOT_TAG]),
+                    nom::number::complete::u8,
+                ),
+                ParsedExternalInboxMessage::ChangeDalSlot,
+            ),
+            map(
+                preceded(
+                    tag([Self::PARSED_QUERY_BALANCE_TAG]),
+                    ContractTz1Hash::nom_read,
+                ),
+                ParsedExternalInboxMessage::QueryBalance,
+            ),
         ))(input)
     }
 }
@@ -90,6 +107,7 @@ impl Signer {
 mod test {
     use super::*;
     use crypto::hash::BlsSignature;
+    use crypto::hash::HashTrait;
     use proptest::prelude::*;
     use tezos_data_encoding::enc::BinWriter;
     use tezos_data_encoding::nom::NomReader;
@@ -150,4 +168,51 @@ mod test {
         assert_eq!(expected.aggregated_signature, cert.aggregated_signature);
         assert_eq!(expected.witnesses, cert.witnesses);
     }
+
+    #[test]
+    fn parse_change_dal_slot_message() {
+        let (remaining, message) = ParsedExternalInboxMessage::parse(&[2, 7])
+            .expect("The external message should be parsable");
+
+        assert!(remaining.is_empty());
+        assert!(matches!(
+            message,
+            ParsedExternalInboxMessage::ChangeDalSlot(7)
+        ));
+    }
+
+    #[test]
+    fn parse_batch_without_fees() {
+        // An empty batch, with the tag of batches predating operation fees.
+        let bytes = [1, 0, 0, 0, 0];
+
+        let (remaining, message) = ParsedExternalInboxMessage::parse(&bytes)
+            .expect("The external message should be parsable");
+
+        assert!(remaining.is_empty());
+        assert!(matches!(
+            message,
+            ParsedExternalInboxMessage::OpList(batch) if batch.operations.is_empty()
+        ));
+    }
+
+    #[test]
+    fn parse_query_balance_message() {
+        let address =
+            ContractTz1Hash::from_b58check("tz1Ke2h7sDdakHJQh8WX4Z372du1KChsksyU").unwrap();
+
+        let mut bytes = Vec::new();
+        sendable::ExternalInboxMessage::QueryBalance(address.clone())
+            .bin_write(&mut bytes)
+            .unwrap();
+
+        let (remaining, message) = ParsedExternalInboxMessage::parse(&bytes)
+            .expect("The external message should be parsable");
+
+        assert!(remaining.is_empty());
+        assert!(matches!(
+            message,
+            ParsedExternalInboxMessage::QueryBalance(parsed) if parsed == address
+        ));
+    }
 }
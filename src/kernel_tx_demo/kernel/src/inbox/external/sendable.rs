@@ -4,6 +4,7 @@
 
 //! Constructing external inbox messages for sending to the kernel.
 
+use crypto::hash::ContractTz1Hash;
 use tezos_data_encoding::{enc::BinWriter, encoding::HasEncoding};
 use tezos_smart_rollup_encoding::dac::certificate::V0Certificate;
 
@@ -15,7 +16,14 @@ pub enum ExternalInboxMessage {
     #[encoding(tag = 0)]
     /// DAC certificate
     Dac(V0Certificate),
-    #[encoding(tag = 1)]
-    /// Version 1 of operation batching
+    #[encoding(tag = 4)]
+    /// Version 1 of operation batching, with operation fees. Tag `1` was used
+    /// by batches without fees.
     OpList(v1::sendable::Batch),
+    #[encoding(tag = 2)]
+    /// Change the DAL slot that the kernel downloads and processes
+    ChangeDalSlot(u8),
+    #[encoding(tag = 3)]
+    /// Request a balance report for an account
+    QueryBalance(ContractTz1Hash),
 }
@@ -2,15 +2,22 @@
 //
 // SPDX-License-Identifier: MIT
 
-//! Arbitrary generation of [Signer], linked to a [SecretKeyEd25519].
+//! Arbitrary generation of [Signer], linked to a [SecretKeyEd25519], and
+//! fixtures of accounts holding tickets.
+use crypto::hash::ContractTz1Hash;
 use crypto::hash::HashType;
 use crypto::hash::PublicKeyEd25519;
 use crypto::hash::SecretKeyEd25519;
 use crypto::hash::SeedEd25519;
 use crypto::PublicKeyWithHash;
 use proptest::prelude::*;
+use tezos_smart_rollup_encoding::contract::Contract;
+use tezos_smart_rollup_encoding::michelson::ticket::StringTicket;
+use tezos_smart_rollup_encoding::michelson::MichelsonString;
+use tezos_smart_rollup_host::runtime::Runtime;
 
 use super::Signer;
+use crate::storage::{account_path, deposit_ticket_to_storage, AccountStorage};
 
 impl Signer {
     /// Generate an arbitrary `Signer`
@@ -55,3 +62,43 @@ pub fn gen_ed25519_keys() -> (PublicKeyEd25519, SecretKeyEd25519) {
 
     seed.keypair().unwrap()
 }
+
+/// Creator of the tickets generated by [string_ticket].
+pub fn ticket_creator() -> Contract {
+    Contract::from_b58check("KT1JW6PwhfaEJu6U3ENsxUeja48AdtqSoekd").unwrap()
+}
+
+/// Generate a ticket of [ticket_creator], with the given contents & amount.
+pub fn string_ticket(contents: &str, amount: u64) -> StringTicket {
+    StringTicket::new(ticket_creator(), MichelsonString(contents.into()), amount).unwrap()
+}
+
+/// Generate an account, holding the given tickets.
+pub fn funded_account<Host: Runtime>(
+    host: &mut Host,
+    account_storage: &mut AccountStorage,
+    tickets: &[&StringTicket],
+) -> (PublicKeyEd25519, SecretKeyEd25519) {
+    let (pk, sk) = gen_ed25519_keys();
+    let address = pk.pk_hash().unwrap();
+
+    for ticket in tickets {
+        deposit_ticket_to_storage(host, account_storage, &address, ticket).unwrap();
+    }
+
+    (pk, sk)
+}
+
+/// Amount of the ticket `ticket_id` held by an account, if any.
+pub fn ticket_amount(
+    host: &impl Runtime,
+    account_storage: &AccountStorage,
+    address: &ContractTz1Hash,
+    ticket_id: u64,
+) -> Option<u64> {
+    let path = account_path(address).unwrap();
+    account_storage
+        .get(host, &path)
+        .unwrap()
+        .and_then(|account| account.ticket_amount(host, ticket_id).unwrap())
+}
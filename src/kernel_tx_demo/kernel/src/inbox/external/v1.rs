@@ -4,10 +4,11 @@
 
 //! V1 of external inbox message parsed into a kernel-specific data structure.
 //!
-//! Specifically, v1 allows a user to [transfer] & [withdraw].
+//! Specifically, v1 allows a user to [transfer], [withdraw] & [swap].
 //!
 //! [transfer]: OperationTransfer
 //! [withdraw]: OperationWithdraw
+//! [swap]: OperationSwap
 
 use self::verifiable::TransactionError;
 
@@ -15,8 +16,11 @@ use super::Signer;
 use crypto::hash::TryFromPKError;
 use crypto::hash::{ContractKt1Hash, ContractTz1Hash};
 use crypto::CryptoError;
+use nom::branch::alt;
+use nom::bytes::complete::tag;
 use nom::combinator::map;
 use nom::multi::many0;
+use nom::sequence::preceded;
 use tezos_crypto_rs::blake2b::Blake2bError;
 use tezos_data_encoding::enc::{BinError, BinWriter};
 use tezos_data_encoding::encoding::Encoding;
@@ -73,6 +77,24 @@ pub struct OperationTransferCompressed {
     amount: TicketAmount,
 }
 
+/// Exchanges tickets between the signer's account and the layer-2 `counterparty`.
+///
+/// The signer transfers `amount` of `ticket` to the counterparty, who in turn
+/// transfers `counter_amount` of `counter_ticket` to the signer.
+///
+/// The operation must also be signed by the counterparty, whose account counter
+/// must match `counterparty_counter`. Either both transfers are applied, or
+/// neither is.
+#[derive(Debug, PartialEq, Eq, HasEncoding, NomReader, BinWriter)]
+pub struct OperationSwap {
+    counterparty: Signer,
+    counterparty_counter: i64,
+    ticket: TicketHash,
+    amount: TicketAmount,
+    counter_ticket: TicketHash,
+    counter_amount: TicketAmount,
+}
+
 /// A serialised data for ticket amount
 #[derive(Debug, PartialEq, Eq)]
 pub struct TicketAmount {
@@ -85,6 +107,12 @@ pub struct TicketIndex {
     index: u64,
 }
 
+impl From<u64> for TicketAmount {
+    fn from(amount: u64) -> Self {
+        Self { amount }
+    }
+}
+
 impl HasEncoding for TicketAmount {
     fn encoding() -> Encoding {
         Encoding::Custom
@@ -123,7 +151,7 @@ impl BinWriter for TicketIndex {
     }
 }
 
-/// An operation either to withdraw or transfer a ticket.
+/// An operation either to withdraw, transfer or swap tickets.
 #[derive(Debug, PartialEq, Eq, HasEncoding, NomReader, BinWriter)]
 pub enum OperationContent {
     /// Withdraw a ticket from the signer account.
@@ -132,6 +160,8 @@ pub enum OperationContent {
     Transfer(OperationTransfer),
     /// Compressed transfer
     CTransfer(OperationTransferCompressed),
+    /// Swap tickets between the signer account and another Layer-2 account.
+    Swap(OperationSwap),
 }
 
 impl OperationContent {
@@ -181,6 +211,36 @@ impl OperationContent {
             Err(TransactionError::ZeroTicket)
         }
     }
+
+    /// Create a new swap operation.
+    pub fn swap(
+        counterparty: Signer,
+        counterparty_counter: i64,
+        ticket: TicketHash,
+        amount: u64,
+        counter_ticket: TicketHash,
+        counter_amount: u64,
+    ) -> Result<OperationContent, TransactionError> {
+        if amount > 0 && counter_amount > 0 {
+            Ok(OperationContent::Swap(OperationSwap {
+                counterparty,
+                counterparty_counter,
+                ticket,
+                amount: TicketAmount { amount },
+                counter_ticket,
+                counter_amount: TicketAmount {
+                    amount: counter_amount,
+                },
+            }))
+        } else {
+            Err(TransactionError::ZeroTicket)
+        }
+    }
+
+    /// Whether the operation must also be signed by a counterparty.
+    pub fn has_counterparty(&self) -> bool {
+        matches!(self, OperationContent::Swap(_))
+    }
 }
 
 /// A group of operations, operating on the account given by `signer`.
@@ -190,10 +250,35 @@ pub struct Operation {
     pub signer: Signer,
     /// The counter of this operation - must match the signer's current counter.
     pub counter: i64,
+    /// The fee paid by the signer for this operation, in the fee ticket
+    /// designated by the kernel.
+    pub fee: TicketAmount,
     /// The individual actions contained within this operation.
     pub contents: OperationContent,
 }
 
+/// An [Operation] as encoded before operations carried a fee: it pays none.
+#[derive(Debug, PartialEq, Eq, HasEncoding, NomReader, BinWriter)]
+pub struct OperationWithoutFee {
+    /// See [Operation::signer].
+    pub signer: Signer,
+    /// See [Operation::counter].
+    pub counter: i64,
+    /// See [Operation::contents].
+    pub contents: OperationContent,
+}
+
+impl From<OperationWithoutFee> for Operation {
+    fn from(operation: OperationWithoutFee) -> Self {
+        Operation {
+            signer: operation.signer,
+            counter: operation.counter,
+            fee: 0.into(),
+            contents: operation.contents,
+        }
+    }
+}
+
 /// Errors occurring when signing or serializing operations, transactions and batches.
 #[derive(Debug, Error)]
 pub enum ToBytesError {
@@ -214,6 +299,15 @@ pub enum ToBytesError {
     IncorrectKey,
 }
 
+/// Tag of batches of operations, whether posted as external messages, revealed
+/// from DAC or published on the DAL.
+pub const BATCH_TAG: u8 = 4;
+
+/// Tag of batches of operations predating fees, see [OperationWithoutFee].
+///
+/// Such batches are still accepted, while clients move to [BATCH_TAG].
+pub const BATCH_WITHOUT_FEES_TAG: u8 = 1;
+
 /// A batch of operations, associated with an aggregated signature.
 #[derive(Debug, PartialEq, Eq)]
 pub struct ParsedBatch<'a> {
@@ -228,6 +322,23 @@ impl<'a> ParsedBatch<'a> {
             ParsedBatch { operations }
         })(input)
     }
+
+    /// Parse a batch of operations predating fees, see [OperationWithoutFee].
+    pub fn parse_without_fees(input: &'a [u8]) -> tezos_data_encoding::nom::NomResult<Self> {
+        map(
+            dynamic(many0(VerifiableOperation::parse_without_fee)),
+            |operations| ParsedBatch { operations },
+        )(input)
+    }
+
+    /// Parse a batch prefixed by [BATCH_TAG], or by [BATCH_WITHOUT_FEES_TAG]
+    /// for batches predating fees.
+    pub fn parse_tagged(input: &'a [u8]) -> tezos_data_encoding::nom::NomResult<Self> {
+        alt((
+            preceded(tag([BATCH_TAG]), Self::parse),
+            preceded(tag([BATCH_WITHOUT_FEES_TAG]), Self::parse_without_fees),
+        ))(input)
+    }
 }
 
 #[cfg(test)]
@@ -276,5 +387,24 @@ mod test {
 
             assert_eq!(operation, decoded);
         }
+
+        #[test]
+        fn operation_swap_encode_decode(
+            operation in OperationContent::arb_swap(),
+            remaining_input in any::<Vec<u8>>(),
+        ) {
+            let mut encoded = Vec::new();
+            operation
+                .bin_write(&mut encoded)
+                .expect("encoding swap operation should work");
+            encoded.extend_from_slice(remaining_input.as_slice());
+
+            let (remaining, decoded) = OperationContent::nom_read(encoded.as_slice())
+                .expect("decoding swap operation should work");
+
+            assert_eq!(remaining, remaining_input.as_slice());
+
+            assert_eq!(operation, decoded);
+        }
     }
 }
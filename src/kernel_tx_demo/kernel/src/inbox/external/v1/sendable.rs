@@ -4,7 +4,7 @@
 
 //! Sendable/serializable versions of `Parsed*` structs.
 
-use super::{Operation, ToBytesError};
+use super::{Operation, ToBytesError, BATCH_TAG, BATCH_WITHOUT_FEES_TAG};
 use crypto::hash::SecretKeyEd25519;
 use tezos_crypto_rs::blake2b::digest_256;
 use tezos_data_encoding::enc::{self, BinError, BinWriter};
@@ -13,34 +13,84 @@ use tezos_data_encoding::encoding::{Encoding, HasEncoding};
 #[derive(Debug, PartialEq)]
 /// A batch of operations, for serialization as an external inbox message.
 pub struct Batch {
-    ops_with_keys: Vec<(Operation, SecretKeyEd25519)>,
+    ops_with_keys: Vec<(Operation, Vec<SecretKeyEd25519>)>,
 }
 
 impl Batch {
     /// Create a new batch from a list of operations.
     pub fn new(ops_with_keys: Vec<(Operation, SecretKeyEd25519)>) -> Self {
+        let ops_with_keys = ops_with_keys
+            .into_iter()
+            .map(|(op, sk)| (op, vec![sk]))
+            .collect();
         Self { ops_with_keys }
     }
-}
 
-impl BinWriter for Batch {
-    /// Serialize a batch of transactions, including the aggregate signature.
+    /// Create a new batch from a list of operations, each signed by several keys.
     ///
-    /// Compatible with [ParsedBatch::parse].
+    /// The signer's key comes first, followed by the counterparty's key for
+    /// operations which have one, such as swaps.
+    pub fn new_multi_signed(ops_with_keys: Vec<(Operation, Vec<SecretKeyEd25519>)>) -> Self {
+        Self { ops_with_keys }
+    }
+
+    /// Serialize the batch prefixed by [BATCH_TAG], as revealed from DAC or
+    /// published on the DAL.
     ///
-    /// [ParsedBatch::parse]: super::ParsedBatch::parse
-    fn bin_write(&self, output: &mut Vec<u8>) -> enc::BinResult {
+    /// Compatible with [ParsedBatch::parse_tagged].
+    ///
+    /// [ParsedBatch::parse_tagged]: super::ParsedBatch::parse_tagged
+    pub fn bin_write_tagged(&self, output: &mut Vec<u8>) -> enc::BinResult {
+        output.push(BATCH_TAG);
+        self.bin_write(output)
+    }
+
+    /// Serialize the batch prefixed by [BATCH_WITHOUT_FEES_TAG], encoding its
+    /// operations as they were before operations carried a fee. Fails if any
+    /// operation has a non-zero fee.
+    ///
+    /// Compatible with [ParsedBatch::parse_tagged].
+    ///
+    /// [ParsedBatch::parse_tagged]: super::ParsedBatch::parse_tagged
+    pub fn bin_write_without_fees(&self, output: &mut Vec<u8>) -> enc::BinResult {
+        output.push(BATCH_WITHOUT_FEES_TAG);
+        self.write_signed(output, |op, bytes| {
+            if op.fee.amount != 0 {
+                return Err(BinError::custom(
+                    "operation with a fee in a batch without fees".to_string(),
+                ));
+            }
+            op.signer.bin_write(bytes)?;
+            bytes.extend_from_slice(&op.counter.to_be_bytes());
+            op.contents.bin_write(bytes)
+        })
+    }
+
+    /// Serialize the signed operations of the batch, each operation being
+    /// encoded by `encode`.
+    fn write_signed(
+        &self,
+        output: &mut Vec<u8>,
+        encode: impl Fn(&Operation, &mut Vec<u8>) -> enc::BinResult,
+    ) -> enc::BinResult {
         let msgs = self
             .ops_with_keys
             .iter()
-            .map(|(op, sk)| {
+            .map(|(op, sks)| {
                 let mut bytes = Vec::new();
-                op.bin_write(&mut bytes)?;
+                encode(op, &mut bytes)?;
                 // TODO: https://github.com/trilitech/tezedge/issues/44
                 // Consider moving the hashing logic into `sk.sign`.
                 let hash = digest_256(&bytes)?;
-                let mut sig = sk.sign(hash.as_slice())?;
-                bytes.append(&mut sig.0);
+
+                // All parties sign the operation itself, not each other's signatures.
+                let sigs = sks
+                    .iter()
+                    .map(|sk| sk.sign(hash.as_slice()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                for mut sig in sigs {
+                    bytes.append(&mut sig.0);
+                }
 
                 Ok(bytes)
             })
@@ -55,6 +105,17 @@ impl BinWriter for Batch {
     }
 }
 
+impl BinWriter for Batch {
+    /// Serialize a batch of transactions, including the aggregate signature.
+    ///
+    /// Compatible with [ParsedBatch::parse].
+    ///
+    /// [ParsedBatch::parse]: super::ParsedBatch::parse
+    fn bin_write(&self, output: &mut Vec<u8>) -> enc::BinResult {
+        self.write_signed(output, |op, bytes| op.bin_write(bytes))
+    }
+}
+
 impl HasEncoding for Batch {
     fn encoding() -> Encoding {
         Encoding::Custom
@@ -99,5 +160,68 @@ mod test {
                 assert_eq!(original, parsed.operation);
             }
         }
+
+        #[test]
+        fn sendable_batch_with_swaps_encode_decode(
+            operations in collection::vec(Operation::arb_swap_with_keys(), 0..3),
+            remaining_input in any::<Vec<u8>>(),
+        ) {
+            let batch = Batch::new_multi_signed(operations);
+
+            let mut encoded = Vec::new();
+            batch.bin_write(&mut encoded).expect("Failed to encode batch");
+
+            encoded.extend_from_slice(remaining_input.as_slice());
+
+            let (remaining, parsed_batch) = ParsedBatch::parse(encoded.as_slice())
+                .expect("Parsing of encoded batch failed");
+
+            assert_eq!(remaining_input, remaining);
+
+            let original = batch.ops_with_keys;
+            assert_eq!(original.len(), parsed_batch.operations.len());
+
+            for ((original, _), parsed) in original.into_iter()
+                .zip(parsed_batch.operations.into_iter()) {
+                assert_eq!(original, parsed.operation);
+                assert!(parsed.counterparty_signature.is_some());
+            }
+        }
+
+        #[test]
+        fn sendable_batch_without_fees_encode_decode(
+            operations in collection::vec(Operation::arb_with_signer(), 0..3),
+            remaining_input in any::<Vec<u8>>(),
+        ) {
+            let operations = operations
+                .into_iter()
+                .map(|(op, sk)| (Operation { fee: 0.into(), ..op }, sk))
+                .collect();
+            let batch = Batch::new(operations);
+
+            let mut encoded = Vec::new();
+            batch.bin_write_without_fees(&mut encoded).expect("Failed to encode batch");
+
+            encoded.extend_from_slice(remaining_input.as_slice());
+
+            let (remaining, parsed_batch) = ParsedBatch::parse_tagged(encoded.as_slice())
+                .expect("Parsing of encoded batch failed");
+
+            assert_eq!(remaining_input, remaining);
+
+            let original = batch.ops_with_keys;
+            assert_eq!(original.len(), parsed_batch.operations.len());
+
+            for ((original, _), parsed) in original.into_iter()
+                .zip(parsed_batch.operations.into_iter()) {
+                assert_eq!(original, parsed.operation);
+                // Signers signed the encoding without the fee.
+                assert_eq!(parsed.parsed.len() + 8, {
+                    let mut bytes = Vec::new();
+                    parsed.operation.bin_write(&mut bytes).unwrap();
+                    bytes.len()
+                });
+            }
+        }
     }
 }
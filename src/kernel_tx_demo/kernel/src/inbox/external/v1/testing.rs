@@ -38,6 +38,34 @@ impl OperationContent {
             })
             .boxed()
     }
+
+    /// Generation strategy for swap operations.
+    pub fn arb_swap() -> BoxedStrategy<OperationContent> {
+        Signer::arb_with_sk()
+            .prop_flat_map(|(counterparty, _sk)| Self::arb_swap_with(counterparty))
+            .boxed()
+    }
+
+    /// Generation strategy for swap operations with the given counterparty.
+    fn arb_swap_with(counterparty: Signer) -> BoxedStrategy<OperationContent> {
+        (
+            i64::arbitrary().prop_map(|i| if i < 0 { -(i + 1) } else { i }),
+            StringTicket::arb(),
+            StringTicket::arb(),
+        )
+            .prop_map(move |(counter, ticket, counter_ticket)| {
+                OperationContent::swap(
+                    counterparty.clone(),
+                    counter,
+                    ticket.hash().unwrap(),
+                    ticket.amount_as().unwrap(),
+                    counter_ticket.hash().unwrap(),
+                    counter_ticket.amount_as().unwrap(),
+                )
+                .unwrap()
+            })
+            .boxed()
+    }
 }
 
 impl Operation {
@@ -55,8 +83,9 @@ impl Operation {
                 }
             }),
             bool::arbitrary(),
+            any::<u64>(),
         )
-            .prop_map(|(seed, counter, contents, signer_as_address)| {
+            .prop_map(|(seed, counter, contents, signer_as_address, fee)| {
                 let (pk, sk) = seed.keypair().unwrap();
                 let signer = if signer_as_address {
                     Signer::Tz1(pk.pk_hash().unwrap())
@@ -67,10 +96,36 @@ impl Operation {
                 let op = Operation {
                     signer,
                     counter,
+                    fee: fee.into(),
                     contents,
                 };
                 (op, sk)
             })
             .boxed()
     }
+
+    /// Generation strategy for swap operations, together with the keys of the
+    /// signer and of the counterparty, in that order.
+    pub fn arb_swap_with_keys() -> BoxedStrategy<(Operation, Vec<SecretKeyEd25519>)> {
+        (
+            Signer::arb_with_sk(),
+            Signer::arb_with_sk(),
+            i64::arbitrary().prop_map(|i| if i < 0 { -(i + 1) } else { i }),
+            any::<u64>(),
+        )
+            .prop_flat_map(
+                |((signer, sk), (counterparty, counterparty_sk), counter, fee)| {
+                    OperationContent::arb_swap_with(counterparty).prop_map(move |contents| {
+                        let op = Operation {
+                            signer: signer.clone(),
+                            counter,
+                            fee: fee.into(),
+                            contents,
+                        };
+                        (op, vec![sk.clone(), counterparty_sk.clone()])
+                    })
+                },
+            )
+            .boxed()
+    }
 }
@@ -4,19 +4,21 @@
 
 //! Parsing of operations, and verification that they are well-formed and signed.
 use super::{
-    Operation, OperationContent, OperationTransfer, OperationTransferCompressed, OperationWithdraw,
-    TicketAmount, TicketIndex,
+    Operation, OperationContent, OperationSwap, OperationTransfer, OperationTransferCompressed,
+    OperationWithdraw, OperationWithoutFee, TicketAmount, TicketIndex,
 };
+use crate::inbox::Signer;
+use crate::index;
 use crate::storage::get_or_set_ticket_id;
 use crate::storage::Account;
 use crate::storage::{account_path, AccountStorage, AccountStorageError};
+use crate::transactions::fees::{charge_fee, FeeConfig};
 use crate::transactions::withdrawal::Withdrawal;
 use crypto::hash::Signature;
 use crypto::hash::{ContractTz1Hash, TryFromPKError};
 use crypto::CryptoError;
 use crypto::PublicKeySignatureVerifier;
 use nom::combinator::{consumed, map};
-use nom::sequence::pair;
 use num_bigint::{BigInt, TryFromBigIntError};
 use tezos_crypto_rs::blake2b::digest_256;
 use tezos_crypto_rs::blake2b::Blake2bError;
@@ -61,6 +63,18 @@ pub enum TransactionError {
     /// Incorrect signature
     #[error("Signature verification failed")]
     SignatureVerificationError,
+    /// The counterparty did not sign the operation
+    #[error("Missing counterparty signature")]
+    MissingCounterpartySignature,
+    /// The operation pays a fee, but the kernel has no fee ticket configured.
+    #[error("Fees are disabled, but the operation pays a fee of {0}")]
+    FeesDisabled(u64),
+    /// The operation pays less than the minimum fee.
+    #[error("Minimum fee is {0}, operation pays {1}")]
+    FeeTooLow(u64, u64),
+    /// The signer does not hold enough of the fee ticket.
+    #[error("Account {0} cannot pay the operation fee")]
+    InsufficientFeeBalance(ContractTz1Hash),
     /// Cannot transfer from & to the same account.
     #[error("Invalid self-transfer at address {0}")]
     InvalidSelfTransfer(ContractTz1Hash),
@@ -94,44 +108,101 @@ impl From<TryFromBigIntError<BigInt>> for TransactionError {
 ///
 /// Contains the slice repressenting the binary encoding for the
 /// operation - to be used with signature checking.
+///
+/// Operations with a counterparty (i.e. swaps) are signed by both parties,
+/// the counterparty's signature following the signer's.
 #[derive(Debug, PartialEq, Eq)]
 pub struct VerifiableOperation<'a> {
     pub(crate) parsed: &'a [u8],
     pub(crate) operation: Operation,
     pub(crate) signature: Signature,
+    pub(crate) counterparty_signature: Option<Signature>,
+}
+
+/// Check that the operation given by `parsed` was signed by `signer`, linking
+/// its public key to `account` if needed.
+fn verify_sig(
+    host: &mut impl Runtime,
+    parsed: &[u8],
+    signer: &Signer,
+    signature: &Signature,
+    account: &mut Account,
+) -> Result<(), TransactionError> {
+    let pk = match signer {
+        Signer::Tz1(address) => {
+            if let Some(pk) = account.public_key(host)? {
+                pk
+            } else {
+                return Err(TransactionError::NoPublicKeyForAddress(address.clone()));
+            }
+        }
+        Signer::PublicKey(pk) => {
+            account.link_public_key(host, pk)?;
+            pk.clone()
+        }
+    };
+
+    // TODO: https://github.com/trilitech/tezedge/issues/44
+    // Consider moving the hashing logic into `verify_signature`.
+    let hash = digest_256(parsed)?;
+    pk.verify_signature(signature, &hash)?;
+
+    Ok(())
 }
 
 impl<'a> VerifiableOperation<'a> {
-    fn verify_sig(
+    /// Hash of the operation, as signed by its signer(s).
+    pub fn hash(&self) -> Result<Vec<u8>, Blake2bError> {
+        digest_256(self.parsed)
+    }
+
+    /// Authorise the operation, and make its signer pay for it: verify the
+    /// signer's signature, increment their counter and charge the fee.
+    ///
+    /// Once paid for, an operation cannot be replayed, whether its execution
+    /// succeeds or not. Assumes that a current transaction is in progress in
+    /// durable storage, and that it will be rolled back if an error is returned.
+    pub fn prepay<Host: Runtime>(
         &self,
-        host: &mut impl Runtime,
-        account: &mut crate::storage::Account,
+        host: &mut Host,
+        account_storage: &mut AccountStorage,
+        fees: Option<&FeeConfig>,
     ) -> Result<(), TransactionError> {
-        let pk = match &self.operation.signer {
-            crate::inbox::Signer::Tz1(address) => {
-                if let Some(pk) = account.public_key(host)? {
-                    pk
-                } else {
-                    return Err(TransactionError::NoPublicKeyForAddress(address.clone()));
-                }
-            }
-            crate::inbox::Signer::PublicKey(pk) => {
-                account.link_public_key(host, pk)?;
-                pk.clone()
-            }
-        };
+        let signer_address = self.operation.signer.address()?;
 
-        // TODO: https://github.com/trilitech/tezedge/issues/44
-        // Consider moving the hashing logic into `verify_signature`.
-        let hash = digest_256(self.parsed)?;
-        pk.verify_signature(&self.signature, &hash)?;
+        let signer_path: OwnedPath = account_path(&signer_address)?;
 
-        Ok(())
+        let Some(mut signer_account) = account_storage.get(host, &signer_path)? else {
+            return Err(TransactionError::NoAccountOfAddress(signer_address));
+        };
+
+        #[cfg(not(feature = "tx-kernel-no-sig-verif"))]
+        verify_sig(
+            host,
+            self.parsed,
+            &self.operation.signer,
+            &self.signature,
+            &mut signer_account,
+        )?;
+
+        signer_account.check_and_inc_counter(host, self.operation.counter)?;
+
+        charge_fee(
+            host,
+            account_storage,
+            fees,
+            &mut signer_account,
+            &signer_address,
+            self.operation.fee.amount,
+        )
     }
 
-    /// Execute the operation directly on durable storage. Assumes that a current
-    /// transaction is in progress in durable storage and will operate on that. Also,
-    /// assumes that the transaction will be rolled back, if an error is returned.
+    /// Execute the contents of a [prepaid] operation directly on durable storage.
+    /// Assumes that a current transaction is in progress in durable storage and
+    /// will operate on that. Also, assumes that the transaction will be rolled
+    /// back, if an error is returned.
+    ///
+    /// [prepaid]: Self::prepay
     pub fn execute<Host: Runtime>(
         self,
         host: &mut Host,
@@ -144,11 +215,6 @@ impl<'a> VerifiableOperation<'a> {
         let mut withdrawals = Vec::new();
 
         if let Some(mut signer_account) = account_storage.get(host, &signer_path)? {
-            #[cfg(not(feature = "tx-kernel-no-sig-verif"))]
-            self.verify_sig(host, &mut signer_account)?;
-
-            signer_account.check_and_inc_counter(host, self.operation.counter)?;
-
             match self.operation.contents {
                 OperationContent::Withdraw(OperationWithdraw {
                     destination,
@@ -162,6 +228,7 @@ impl<'a> VerifiableOperation<'a> {
                     }
 
                     let ticket_id = get_or_set_ticket_id(host, &identity)?;
+                    index::record_ticket_details(host, ticket_id, &identity, &ticket);
                     signer_account.remove_ticket(host, ticket_id, amount)?;
 
                     #[cfg(feature = "debug")]
@@ -192,6 +259,7 @@ impl<'a> VerifiableOperation<'a> {
                         host,
                         account_storage,
                         &mut signer_account,
+                        &signer_address,
                         destination,
                         ticket_id,
                         amount,
@@ -207,11 +275,73 @@ impl<'a> VerifiableOperation<'a> {
                         host,
                         account_storage,
                         &mut signer_account,
+                        &signer_address,
                         destination,
                         ticket,
                         amount,
                     )?;
                 }
+
+                OperationContent::Swap(OperationSwap {
+                    counterparty,
+                    counterparty_counter,
+                    ticket,
+                    amount: TicketAmount { amount },
+                    counter_ticket,
+                    counter_amount,
+                }) => {
+                    let counter_amount = counter_amount.amount;
+                    let counterparty_address = counterparty.address()?;
+                    if counterparty_address == signer_address {
+                        return Err(TransactionError::InvalidSelfTransfer(counterparty_address));
+                    }
+                    if amount == 0 || counter_amount == 0 {
+                        return Err(TransactionError::ZeroTicket);
+                    }
+
+                    let counterparty_path = account_path(&counterparty_address)?;
+                    let Some(mut counterparty_account) =
+                        account_storage.get(host, &counterparty_path)?
+                    else {
+                        return Err(TransactionError::NoAccountOfAddress(counterparty_address));
+                    };
+
+                    #[cfg(not(feature = "tx-kernel-no-sig-verif"))]
+                    verify_sig(
+                        host,
+                        self.parsed,
+                        &counterparty,
+                        self.counterparty_signature
+                            .as_ref()
+                            .ok_or(TransactionError::MissingCounterpartySignature)?,
+                        &mut counterparty_account,
+                    )?;
+
+                    counterparty_account.check_and_inc_counter(host, counterparty_counter)?;
+
+                    // Both legs operate within the caller's transaction, so a
+                    // failure of either leaves both accounts untouched.
+                    let ticket_id = get_or_set_ticket_id(host, &ticket)?;
+                    let counter_ticket_id = get_or_set_ticket_id(host, &counter_ticket)?;
+                    handle_transfer(
+                        host,
+                        account_storage,
+                        &mut signer_account,
+                        &signer_address,
+                        counterparty_address.clone(),
+                        ticket_id,
+                        amount,
+                    )?;
+                    handle_transfer(
+                        host,
+                        account_storage,
+                        &mut counterparty_account,
+                        &counterparty_address,
+                        signer_address,
+                        counter_ticket_id,
+                        counter_amount,
+                    )?;
+                }
             }
         } else {
             return Err(TransactionError::NoAccountOfAddress(signer_address));
@@ -222,14 +352,41 @@ impl<'a> VerifiableOperation<'a> {
 
     /// Parse an operation, remembering the parsed slice.
     pub fn parse(input: &'a [u8]) -> tezos_data_encoding::nom::NomResult<Self> {
-        map(
-            pair(consumed(Operation::nom_read), Signature::nom_read),
-            |((parsed, operation), signature)| Self {
+        let (input, (parsed, operation)) = consumed(Operation::nom_read)(input)?;
+        Self::parse_signatures(input, parsed, operation)
+    }
+
+    /// Parse an operation predating fees, remembering the parsed slice, which
+    /// is what its signers signed.
+    pub fn parse_without_fee(input: &'a [u8]) -> tezos_data_encoding::nom::NomResult<Self> {
+        let (input, (parsed, operation)) =
+            consumed(map(OperationWithoutFee::nom_read, Operation::from))(input)?;
+        Self::parse_signatures(input, parsed, operation)
+    }
+
+    /// Parse the signatures following the `parsed` encoding of `operation`.
+    fn parse_signatures(
+        input: &'a [u8],
+        parsed: &'a [u8],
+        operation: Operation,
+    ) -> tezos_data_encoding::nom::NomResult<'a, Self> {
+        let (input, signature) = Signature::nom_read(input)?;
+
+        let (input, counterparty_signature) = if operation.contents.has_counterparty() {
+            map(Signature::nom_read, Some)(input)?
+        } else {
+            (input, None)
+        };
+
+        Ok((
+            input,
+            Self {
                 parsed,
                 operation,
                 signature,
+                counterparty_signature,
             },
-        )(input)
+        ))
     }
 }
 
@@ -237,6 +394,7 @@ fn handle_transfer(
     host: &mut impl Runtime,
     account_storage: &mut AccountStorage,
     signer_account: &mut Account,
+    source: &ContractTz1Hash,
     destination: ContractTz1Hash,
     ticket: u64,
     amount: u64,
@@ -251,10 +409,13 @@ fn handle_transfer(
     #[cfg(feature = "debug")]
     debug_msg!(host, "Transferring {amount} of {ticket} to {destination}\n");
 
-    let _source_id = signer_account.get_or_set_id(host)?;
+    let _source_id = signer_account.get_or_set_id(host, source)?;
 
     let (_id, _amount) = if _source_id == 0 {
-        (dest_account.get_or_set_id(host)? - 1, _dest_amount as u8)
+        (
+            dest_account.get_or_set_id(host, &destination)? - 1,
+            _dest_amount as u8,
+        )
     } else {
         (_source_id - 1, _source_amount as u8)
     };
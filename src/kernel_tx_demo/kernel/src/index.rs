@@ -0,0 +1,712 @@
+// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! State index & balance reports
+//!
+//! Accounts and tickets are stored by address and ticket id, which makes the
+//! durable storage hard to read from the outside. The kernel therefore keeps
+//! an index alongside its state:
+//! - `/index/version` holds the version of the index, currently [INDEX_VERSION].
+//! - `/index/tickets/<id>` holds the encoded [TicketInfo] of each ticket id.
+//! - `/index/accounts/<id>` holds the encoded address of each account id.
+//!
+//! The index is only read by clients: failing to update it does not fail the
+//! operations or deposits updating it. Ticket & account ids assigned before
+//! the index existed are indexed the next time they are used.
+//!
+//! On request, the kernel also writes a [BalanceReport] for an account under
+//! `/queries/balances/<tz1>`. Clients decode it with [BalanceReport::decode].
+//! Only the latest [MAX_BALANCE_REPORTS] accounts queried keep their report:
+//! `/queries/slots/<n>` holds the address of each of them.
+
+use crate::storage::{account_path, AccountStorage, AccountStorageError};
+use crypto::hash::ContractTz1Hash;
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::combinator::map;
+use nom::multi::length_count;
+use nom::number::complete::{le_i64, le_u16, le_u32, le_u64};
+use nom::sequence::{preceded, tuple};
+use tezos_data_encoding::enc::{BinError, BinResult, BinWriter};
+use tezos_data_encoding::encoding::{Encoding, HasEncoding};
+use tezos_data_encoding::nom::{NomReader, NomResult};
+use tezos_smart_rollup_encoding::contract::Contract;
+use tezos_smart_rollup_encoding::michelson::ticket::{StringTicket, TicketHash};
+use tezos_smart_rollup_encoding::michelson::MichelsonString;
+use tezos_smart_rollup_host::path::{concat, OwnedPath, RefPath};
+use tezos_smart_rollup_host::runtime::{Runtime, RuntimeError};
+use thiserror::Error;
+
+/// Version of the index layout and of the encodings it stores.
+pub const INDEX_VERSION: u8 = 1;
+
+const INDEX_VERSION_PATH: RefPath = RefPath::assert_from(b"/index/version");
+const TICKETS_INDEX_PATH: RefPath = RefPath::assert_from(b"/index/tickets");
+const ACCOUNTS_INDEX_PATH: RefPath = RefPath::assert_from(b"/index/accounts");
+
+/// Path under which balance reports are written, per account address.
+pub const BALANCE_REPORTS_PATH: RefPath = RefPath::assert_from(b"/queries/balances");
+
+/// Maximum number of balance reports kept in storage.
+pub const MAX_BALANCE_REPORTS: u64 = 256;
+
+const REPORT_SLOTS_PATH: RefPath = RefPath::assert_from(b"/queries/slots");
+const REPORTS_WRITTEN_PATH: RefPath = RefPath::assert_from(b"/queries/written");
+
+/// Tags of optional fields, as in the Tezos binary encoding.
+const NONE_TAG: u8 = 0x00;
+const SOME_TAG: u8 = 0xff;
+
+/// Errors when reading or updating the index.
+#[derive(Error, Debug)]
+pub enum IndexError {
+    /// Some runtime error happened while using the hosts durable storage.
+    #[error("Runtime error: {0}")]
+    RuntimeError(#[from] RuntimeError),
+    /// Some error happened when constructing the path to an index entry.
+    #[error("Path error")]
+    PathError(tezos_smart_rollup_host::path::PathError),
+    /// An index entry could not be encoded.
+    #[error("Failed to encode index entry: {0:?}")]
+    Encoding(BinError),
+    /// An index entry in storage is malformed.
+    #[error("Malformed index entry")]
+    MalformedEntry,
+    /// The index was written by a different version of the kernel.
+    #[error("Unsupported index version {0}")]
+    UnsupportedVersion(u8),
+}
+
+impl From<tezos_smart_rollup_host::path::PathError> for IndexError {
+    fn from(error: tezos_smart_rollup_host::path::PathError) -> Self {
+        IndexError::PathError(error)
+    }
+}
+
+impl From<BinError> for IndexError {
+    fn from(error: BinError) -> Self {
+        IndexError::Encoding(error)
+    }
+}
+
+fn write_option<T: BinWriter>(value: &Option<T>, output: &mut Vec<u8>) -> BinResult {
+    match value {
+        None => output.push(NONE_TAG),
+        Some(value) => {
+            output.push(SOME_TAG);
+            value.bin_write(output)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_option<'a, T>(
+    read: impl FnMut(&'a [u8]) -> NomResult<'a, T>,
+) -> impl FnMut(&'a [u8]) -> NomResult<'a, Option<T>> {
+    alt((
+        map(tag([NONE_TAG]), |_| None),
+        map(preceded(tag([SOME_TAG]), read), Some),
+    ))
+}
+
+/// Creator & contents of a ticket, known once it has been deposited or
+/// withdrawn.
+#[derive(Debug, PartialEq, Eq, HasEncoding, NomReader, BinWriter)]
+pub struct TicketDetails {
+    /// Contract which created the ticket
+    pub creator: Contract,
+    /// Contents of the ticket
+    pub contents: MichelsonString,
+}
+
+/// Index entry of a ticket id.
+///
+/// Tickets first seen in a transfer are only known by their hash.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TicketInfo {
+    /// Hash identifying the ticket
+    pub hash: TicketHash,
+    /// Creator & contents of the ticket, if known
+    pub details: Option<TicketDetails>,
+}
+
+impl HasEncoding for TicketInfo {
+    fn encoding() -> Encoding {
+        Encoding::Custom
+    }
+}
+
+impl NomReader for TicketInfo {
+    fn nom_read(input: &[u8]) -> NomResult<Self> {
+        map(
+            tuple((TicketHash::nom_read, read_option(TicketDetails::nom_read))),
+            |(hash, details)| Self { hash, details },
+        )(input)
+    }
+}
+
+impl BinWriter for TicketInfo {
+    fn bin_write(&self, output: &mut Vec<u8>) -> BinResult {
+        self.hash.bin_write(output)?;
+        write_option(&self.details, output)
+    }
+}
+
+/// Amount of a ticket held by an account.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TicketBalance {
+    /// Id of the ticket in the kernel
+    pub ticket_id: u64,
+    /// Index entry of the ticket, missing for tickets recorded before the
+    /// index existed and not used since
+    pub ticket: Option<TicketInfo>,
+    /// Amount held
+    pub amount: u64,
+}
+
+impl HasEncoding for TicketBalance {
+    fn encoding() -> Encoding {
+        Encoding::Custom
+    }
+}
+
+impl NomReader for TicketBalance {
+    fn nom_read(input: &[u8]) -> NomResult<Self> {
+        map(
+            tuple((le_u64, read_option(TicketInfo::nom_read), le_u64)),
+            |(ticket_id, ticket, amount)| Self {
+                ticket_id,
+                ticket,
+                amount,
+            },
+        )(input)
+    }
+}
+
+impl BinWriter for TicketBalance {
+    fn bin_write(&self, output: &mut Vec<u8>) -> BinResult {
+        output.extend_from_slice(&self.ticket_id.to_le_bytes());
+        write_option(&self.ticket, output)?;
+        output.extend_from_slice(&self.amount.to_le_bytes());
+        Ok(())
+    }
+}
+
+/// Balances of an account, as written in answer to a query.
+///
+/// The encoding starts with the [INDEX_VERSION] it was written with.
+#[derive(Debug, PartialEq, Eq)]
+pub struct BalanceReport {
+    /// Address of the account
+    pub address: ContractTz1Hash,
+    /// Id of the account, if it has been assigned one
+    pub account_id: Option<u16>,
+    /// Counter of the next operation expected from the account
+    pub counter: i64,
+    /// Non-zero balances of the account, by increasing ticket id
+    pub balances: Vec<TicketBalance>,
+}
+
+impl HasEncoding for BalanceReport {
+    fn encoding() -> Encoding {
+        Encoding::Custom
+    }
+}
+
+impl NomReader for BalanceReport {
+    fn nom_read(input: &[u8]) -> NomResult<Self> {
+        map(
+            preceded(
+                tag([INDEX_VERSION]),
+                tuple((
+                    ContractTz1Hash::nom_read,
+                    read_option(le_u16),
+                    le_i64,
+                    length_count(le_u32, TicketBalance::nom_read),
+                )),
+            ),
+            |(address, account_id, counter, balances)| Self {
+                address,
+                account_id,
+                counter,
+                balances,
+            },
+        )(input)
+    }
+}
+
+impl BinWriter for BalanceReport {
+    fn bin_write(&self, output: &mut Vec<u8>) -> BinResult {
+        output.push(INDEX_VERSION);
+        self.address.bin_write(output)?;
+        match self.account_id {
+            None => output.push(NONE_TAG),
+            Some(id) => {
+                output.push(SOME_TAG);
+                output.extend_from_slice(&id.to_le_bytes());
+            }
+        }
+        output.extend_from_slice(&self.counter.to_le_bytes());
+        output.extend_from_slice(&(self.balances.len() as u32).to_le_bytes());
+        for balance in self.balances.iter() {
+            balance.bin_write(output)?;
+        }
+        Ok(())
+    }
+}
+
+impl BalanceReport {
+    /// Decode a report read from the kernel's durable storage.
+    pub fn decode(bytes: &[u8]) -> Result<Self, IndexError> {
+        match Self::nom_read(bytes) {
+            Ok(([], report)) => Ok(report),
+            Ok(_) => Err(IndexError::MalformedEntry),
+            Err(_) => match bytes.first() {
+                Some(&version) if version != INDEX_VERSION => {
+                    Err(IndexError::UnsupportedVersion(version))
+                }
+                _ => Err(IndexError::MalformedEntry),
+            },
+        }
+    }
+}
+
+fn ticket_index_path(ticket_id: u64) -> Result<OwnedPath, IndexError> {
+    let id_path = OwnedPath::try_from(format!("/{ticket_id}"))?;
+    concat(&TICKETS_INDEX_PATH, &id_path).map_err(IndexError::from)
+}
+
+fn account_index_path(account_id: u16) -> Result<OwnedPath, IndexError> {
+    let id_path = OwnedPath::try_from(format!("/{account_id}"))?;
+    concat(&ACCOUNTS_INDEX_PATH, &id_path).map_err(IndexError::from)
+}
+
+/// Path of the balance report of the given account.
+pub fn balance_report_path(address: &ContractTz1Hash) -> Result<OwnedPath, IndexError> {
+    let address_path = OwnedPath::try_from(format!("/{address}"))?;
+    concat(&BALANCE_REPORTS_PATH, &address_path).map_err(IndexError::from)
+}
+
+fn report_slot_path(slot: u64) -> Result<OwnedPath, IndexError> {
+    let slot_path = OwnedPath::try_from(format!("/{slot}"))?;
+    concat(&REPORT_SLOTS_PATH, &slot_path).map_err(IndexError::from)
+}
+
+/// Version of the index in storage, if any has been written.
+pub fn index_version(host: &impl Runtime) -> Result<Option<u8>, IndexError> {
+    let mut buffer = [0_u8; 1];
+    match host.store_read_slice(&INDEX_VERSION_PATH, 0, &mut buffer) {
+        Ok(1) => Ok(Some(buffer[0])),
+        Ok(_) => Err(IndexError::MalformedEntry),
+        Err(RuntimeError::PathNotFound) => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+fn write_entry(
+    host: &mut impl Runtime,
+    path: &OwnedPath,
+    entry: &impl BinWriter,
+) -> Result<(), IndexError> {
+    match index_version(host)? {
+        Some(INDEX_VERSION) => (),
+        Some(version) => return Err(IndexError::UnsupportedVersion(version)),
+        None => host.store_write(&INDEX_VERSION_PATH, &[INDEX_VERSION], 0)?,
+    }
+
+    let mut encoded = Vec::new();
+    entry.bin_write(&mut encoded)?;
+    host.store_write_all(path, &encoded)?;
+    Ok(())
+}
+
+fn read_entry<T: NomReader>(
+    host: &impl Runtime,
+    path: &OwnedPath,
+) -> Result<Option<T>, IndexError> {
+    let bytes = match host.store_read_all(path) {
+        Ok(bytes) => bytes,
+        Err(RuntimeError::PathNotFound) => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+
+    match T::nom_read(&bytes) {
+        Ok(([], entry)) => Ok(Some(entry)),
+        _ => Err(IndexError::MalformedEntry),
+    }
+}
+
+/// Log a failed update of the index, for instance when it was written by
+/// another version of the kernel.
+fn log_failure(_host: &impl Runtime, _result: Result<(), IndexError>) {
+    #[cfg(feature = "debug")]
+    if let Err(error) = _result {
+        tezos_smart_rollup_debug::debug_msg!(_host, "Failed to update the index: {error}\n");
+    }
+}
+
+/// Record the hash of a newly assigned ticket id.
+pub fn record_ticket_hash(host: &mut impl Runtime, ticket_id: u64, hash: &TicketHash) {
+    let info = TicketInfo {
+        hash: hash.clone(),
+        details: None,
+    };
+    let result = ticket_index_path(ticket_id).and_then(|path| write_entry(host, &path, &info));
+    log_failure(host, result)
+}
+
+/// Record the hash of a ticket id, unless already indexed.
+pub fn backfill_ticket_hash(host: &mut impl Runtime, ticket_id: u64, hash: &TicketHash) {
+    let result = ticket_index_path(ticket_id).and_then(|path| match host.store_has(&path)? {
+        Some(_) => Ok(()),
+        None => {
+            let info = TicketInfo {
+                hash: hash.clone(),
+                details: None,
+            };
+            write_entry(host, &path, &info)
+        }
+    });
+    log_failure(host, result)
+}
+
+/// Record the creator & contents of a ticket, unless already known.
+pub fn record_ticket_details(
+    host: &mut impl Runtime,
+    ticket_id: u64,
+    hash: &TicketHash,
+    ticket: &StringTicket,
+) {
+    let result = write_ticket_details(host, ticket_id, hash, ticket);
+    log_failure(host, result)
+}
+
+fn write_ticket_details(
+    host: &mut impl Runtime,
+    ticket_id: u64,
+    hash: &TicketHash,
+    ticket: &StringTicket,
+) -> Result<(), IndexError> {
+    let path = ticket_index_path(ticket_id)?;
+    if let Some(TicketInfo {
+        details: Some(_), ..
+    }) = read_entry::<TicketInfo>(host, &path)?
+    {
+        return Ok(());
+    }
+
+    let info = TicketInfo {
+        hash: hash.clone(),
+        details: Some(TicketDetails {
+            creator: ticket.creator().0.clone(),
+            contents: MichelsonString(ticket.contents().0.clone()),
+        }),
+    };
+    write_entry(host, &path, &info)
+}
+
+/// Record the address of a newly assigned account id.
+pub fn record_account(host: &mut impl Runtime, account_id: u16, address: &ContractTz1Hash) {
+    let result = account_index_path(account_id).and_then(|path| write_entry(host, &path, address));
+    log_failure(host, result)
+}
+
+/// Record the address of an account id, unless already indexed.
+pub fn backfill_account(host: &mut impl Runtime, account_id: u16, address: &ContractTz1Hash) {
+    let result = account_index_path(account_id).and_then(|path| match host.store_has(&path)? {
+        Some(_) => Ok(()),
+        None => write_entry(host, &path, address),
+    });
+    log_failure(host, result)
+}
+
+/// Index entry of a ticket id.
+pub fn ticket_info(host: &impl Runtime, ticket_id: u64) -> Result<Option<TicketInfo>, IndexError> {
+    read_entry(host, &ticket_index_path(ticket_id)?)
+}
+
+/// Address of an account id.
+pub fn account_address(
+    host: &impl Runtime,
+    account_id: u16,
+) -> Result<Option<ContractTz1Hash>, IndexError> {
+    read_entry(host, &account_index_path(account_id)?)
+}
+
+/// Compute the balances of an account, if it exists.
+pub fn balance_report<Host: Runtime>(
+    host: &Host,
+    account_storage: &AccountStorage,
+    address: &ContractTz1Hash,
+) -> Result<Option<BalanceReport>, AccountStorageError> {
+    let Some(account) = account_storage.get(host, &account_path(address)?)? else {
+        return Ok(None);
+    };
+
+    let mut report = BalanceReport {
+        address: address.clone(),
+        account_id: account.id(host)?,
+        counter: account.counter(host)?,
+        balances: Vec::new(),
+    };
+
+    let mut ticket_ids = account.held_tickets(host)?;
+    ticket_ids.sort_unstable();
+
+    for ticket_id in ticket_ids {
+        if let Some(amount) = account.ticket_amount(host, ticket_id)? {
+            report.balances.push(TicketBalance {
+                ticket_id,
+                ticket: ticket_info(host, ticket_id)?,
+                amount,
+            });
+        }
+    }
+
+    Ok(Some(report))
+}
+
+/// Reserve a report slot for an account without a report, deleting the
+/// report of the account queried [MAX_BALANCE_REPORTS] reports ago.
+fn claim_report_slot(host: &mut impl Runtime, address: &ContractTz1Hash) -> Result<(), IndexError> {
+    let mut buffer = [0_u8; 8];
+    let written = match host.store_read_slice(&REPORTS_WRITTEN_PATH, 0, &mut buffer) {
+        Ok(8) => u64::from_le_bytes(buffer),
+        Ok(_) => return Err(IndexError::MalformedEntry),
+        Err(RuntimeError::PathNotFound) => 0,
+        Err(error) => return Err(error.into()),
+    };
+
+    let slot = report_slot_path(written % MAX_BALANCE_REPORTS)?;
+    if let Some(evicted) = read_entry::<ContractTz1Hash>(host, &slot)? {
+        host.store_delete(&balance_report_path(&evicted)?)?;
+    }
+
+    let mut encoded = Vec::new();
+    address.bin_write(&mut encoded)?;
+    host.store_write_all(&slot, &encoded)?;
+    host.store_write(&REPORTS_WRITTEN_PATH, &(written + 1).to_le_bytes(), 0)?;
+    Ok(())
+}
+
+/// Answer a balance query, by writing the report of the account under
+/// [BALANCE_REPORTS_PATH].
+///
+/// Queries for accounts that don't exist are ignored. Once
+/// [MAX_BALANCE_REPORTS] accounts have a report, the oldest one is deleted to
+/// make room for a new account.
+pub fn write_balance_report<Host: Runtime>(
+    host: &mut Host,
+    account_storage: &AccountStorage,
+    address: &ContractTz1Hash,
+) -> Result<(), AccountStorageError> {
+    let Some(report) = balance_report(host, account_storage, address)? else {
+        return Ok(());
+    };
+
+    let path = balance_report_path(address)?;
+    if host.store_has(&path)?.is_none() {
+        claim_report_slot(host, address)?;
+    }
+
+    let mut encoded = Vec::new();
+    report.bin_write(&mut encoded).map_err(IndexError::from)?;
+    host.store_write_all(&path, &encoded)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::inbox::external::testing::{
+        funded_account, gen_ed25519_keys, string_ticket, ticket_creator,
+    };
+    use crate::storage::{deposit_ticket_to_storage, init_account_storage};
+    use crypto::PublicKeyWithHash;
+    use tezos_smart_rollup_mock::MockHost;
+
+    #[test]
+    fn balance_report_round_trip() {
+        // Arrange
+        let mut host = MockHost::default();
+        let mut account_storage = init_account_storage().unwrap();
+
+        let red = string_ticket("red", 10);
+        let blue = string_ticket("blue", 5);
+        let (pk, _sk) = funded_account(&mut host, &mut account_storage, &[&red, &blue]);
+        let address = pk.pk_hash().unwrap();
+        let unknown = gen_ed25519_keys().0.pk_hash().unwrap();
+
+        // Act
+        write_balance_report(&mut host, &account_storage, &address).unwrap();
+        write_balance_report(&mut host, &account_storage, &unknown).unwrap();
+
+        // Assert
+        let bytes = host
+            .store_read_all(&balance_report_path(&address).unwrap())
+            .unwrap();
+        let report = BalanceReport::decode(&bytes).unwrap();
+
+        assert_eq!(Some(INDEX_VERSION), index_version(&host).unwrap());
+        assert_eq!(Some(0), report.account_id);
+        assert_eq!(Some(address.clone()), account_address(&host, 0).unwrap());
+        assert_eq!(
+            vec![
+                TicketBalance {
+                    ticket_id: 0,
+                    ticket: Some(TicketInfo {
+                        hash: red.hash().unwrap(),
+                        details: Some(TicketDetails {
+                            creator: ticket_creator(),
+                            contents: MichelsonString("red".into()),
+                        }),
+                    }),
+                    amount: 10,
+                },
+                TicketBalance {
+                    ticket_id: 1,
+                    ticket: Some(TicketInfo {
+                        hash: blue.hash().unwrap(),
+                        details: Some(TicketDetails {
+                            creator: ticket_creator(),
+                            contents: MichelsonString("blue".into()),
+                        }),
+                    }),
+                    amount: 5,
+                },
+            ],
+            report.balances
+        );
+
+        assert!(host
+            .store_has(&balance_report_path(&unknown).unwrap())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn index_failures_do_not_fail_deposits() {
+        // Arrange
+        let mut host = MockHost::default();
+        let mut account_storage = init_account_storage().unwrap();
+
+        let address = gen_ed25519_keys().0.pk_hash().unwrap();
+        let red = string_ticket("red", 10);
+
+        // An index written by another version of the kernel
+        host.store_write(&INDEX_VERSION_PATH, &[INDEX_VERSION + 1], 0)
+            .unwrap();
+
+        // Act
+        deposit_ticket_to_storage(&mut host, &mut account_storage, &address, &red).unwrap();
+        write_balance_report(&mut host, &account_storage, &address).unwrap();
+
+        // Assert
+        let report = balance_report(&host, &account_storage, &address)
+            .unwrap()
+            .unwrap();
+        assert_eq!(None, ticket_info(&host, 0).unwrap());
+        assert_eq!(
+            vec![TicketBalance {
+                ticket_id: 0,
+                ticket: None,
+                amount: 10,
+            }],
+            report.balances
+        );
+    }
+
+    #[test]
+    fn state_before_the_index_is_backfilled() {
+        // Arrange
+        let mut host = MockHost::default();
+        let mut account_storage = init_account_storage().unwrap();
+
+        let red = string_ticket("red", 10);
+        let blue = string_ticket("blue", 5);
+        let green = string_ticket("green", 1);
+        let (pk, _sk) = funded_account(&mut host, &mut account_storage, &[&red, &blue]);
+        let address = pk.pk_hash().unwrap();
+
+        // State written before the index & the lists of held tickets existed
+        let held_tickets = OwnedPath::try_from(format!("/accounts/{address}/tickets")).unwrap();
+        host.store_delete(&RefPath::assert_from(b"/index")).unwrap();
+        host.store_delete(&held_tickets).unwrap();
+
+        let balances = |host: &MockHost| {
+            balance_report(host, &account_storage, &address)
+                .unwrap()
+                .unwrap()
+                .balances
+                .iter()
+                .map(|balance| (balance.ticket_id, balance.amount))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![(0, 10), (1, 5)], balances(&host));
+        assert_eq!(None, ticket_info(&host, 0).unwrap());
+        assert_eq!(None, account_address(&host, 0).unwrap());
+
+        // Act
+        deposit_ticket_to_storage(&mut host, &mut account_storage, &address, &red).unwrap();
+        deposit_ticket_to_storage(&mut host, &mut account_storage, &address, &green).unwrap();
+
+        // Assert
+        assert_eq!(vec![(0, 20), (1, 5), (2, 1)], balances(&host));
+        assert_eq!(
+            vec![0, 1, 2],
+            account_storage
+                .get(&host, &account_path(&address).unwrap())
+                .unwrap()
+                .unwrap()
+                .held_tickets(&host)
+                .unwrap()
+        );
+        assert!(host.store_has(&held_tickets).unwrap().is_some());
+
+        assert_eq!(Some(address.clone()), account_address(&host, 0).unwrap());
+        assert_eq!(
+            Some(red.hash().unwrap()),
+            ticket_info(&host, 0).unwrap().map(|info| info.hash)
+        );
+        // Tickets not used since are still missing.
+        assert_eq!(None, ticket_info(&host, 1).unwrap());
+    }
+
+    #[test]
+    fn balance_reports_are_bounded() {
+        // Arrange
+        let mut host = MockHost::default();
+        let mut account_storage = init_account_storage().unwrap();
+
+        let ticket = string_ticket("red", 1);
+        let addresses: Vec<_> = (0..=MAX_BALANCE_REPORTS)
+            .map(|_| {
+                let (pk, _sk) = funded_account(&mut host, &mut account_storage, &[&ticket]);
+                pk.pk_hash().unwrap()
+            })
+            .collect();
+
+        // Act
+        for address in addresses.iter() {
+            write_balance_report(&mut host, &account_storage, address).unwrap();
+        }
+        // Querying again an account with a report keeps the others.
+        write_balance_report(&mut host, &account_storage, &addresses[1]).unwrap();
+
+        // Assert
+        let has_report = |address| {
+            host.store_has(&balance_report_path(address).unwrap())
+                .unwrap()
+                .is_some()
+        };
+        assert!(!has_report(&addresses[0]));
+        assert!(addresses[1..].iter().all(has_report));
+        assert_eq!(
+            MAX_BALANCE_REPORTS,
+            host.store_count_subkeys(&BALANCE_REPORTS_PATH).unwrap()
+        );
+    }
+}
@@ -17,6 +17,7 @@ extern crate tezos_crypto_rs as crypto;
 #[cfg(feature = "dal")]
 pub mod dal;
 pub mod inbox;
+pub mod index;
 pub mod storage;
 pub mod transactions;
 
@@ -40,14 +41,12 @@ use transactions::external_inbox::ProcessExtMsgError;
 use transactions::process::execute_one_operation;
 use transactions::store::{CACHED_MESSAGES_STORE_PREFIX, DAL_PAYLOAD_PATH};
 
-use crate::inbox::v1::ParsedBatch;
 use crate::inbox::InboxDeposit;
 use crate::storage::{
     deposit_ticket_to_storage, init_account_storage, AccountStorage, AccountStorageError,
 };
-use crate::transactions::external_inbox::process_batch_message;
+use crate::transactions::dal_inbox::process_dal_payload;
 use crate::transactions::store::cached_message_path;
-use crate::transactions::withdrawal::process_withdrawals;
 
 impl TryFrom<MichelsonPair<MichelsonString, StringTicket>> for InboxDeposit {
     type Error = DepositFromInternalPayloadError;
@@ -93,35 +92,16 @@ where
             debug_msg!(host, "Error enumerating cached header payload {}", _err);
         }
         return;
-    } else if let Ok(data) = host.store_read_all(&DAL_PAYLOAD_PATH) {
-        // TODO: https://gitlab.com/tezos/tezos/-/issues/6393
-        // Enable processing DAL payload incrementally with reboots.
+    } else if let Ok(Some(_)) = host.store_has(&DAL_PAYLOAD_PATH) {
         #[cfg(feature = "debug")]
         debug_msg!(host, "Found cached DAL payload, processing\n");
-        match ParsedBatch::parse(&data) {
-            Ok((_, batch)) => {
-                #[cfg(feature = "debug")]
-                debug_msg!(host, "Process parsed batch {:?}\n", batch);
-                for withdrawals in process_batch_message(host, &mut account_storage, batch) {
-                    process_withdrawals(host, withdrawals)
-                }
-            }
-            Err(_e) => {
-                #[cfg(feature = "debug")]
-                debug_msg!(host, "Failed to parse DAL payload. Error: {:?}\n", _e);
-            }
-        }
-        match host.store_delete(&DAL_PAYLOAD_PATH) {
-            Ok(()) => {}
-            Err(_e) => {
-                #[cfg(feature = "debug")]
-                debug_msg!(
-                    host,
-                    "Failed to delete processed DAL payload. Error: {:?}\n",
-                    _e
-                );
-            }
+        if let Err(_err) = process_dal_payload(host, &mut account_storage) {
+            #[cfg(feature = "debug")]
+            debug_msg!(host, "Error processing DAL payload: {}\n", _err);
         }
+        // Slots attested while the payload was pending are processed in turn.
+        #[cfg(feature = "dal")]
+        dal::store_next_queued_slot(host);
         return;
     }
 
@@ -203,24 +183,26 @@ fn filter_inbox_message<'a, Host: Runtime>(
             debug_msg!(host, "InboxMetadata: {}\n", _msg);
             #[cfg(feature = "dal")]
             {
-                // TODO: https://gitlab.com/tezos/tezos/-/issues/6270
-                // Make DAL parameters available to the kernel.
-                let attestation_lag = 4;
-                let slot_size = 32768;
-                let page_size = 128;
-                let num_pages = slot_size / page_size;
-                let published_level = (_inbox_level - attestation_lag) as i32;
+                let parameters = host.reveal_dal_parameters();
+                let page_size = parameters.page_size as usize;
+                let num_pages = (parameters.slot_size as usize + page_size - 1) / page_size;
                 // TODO: https://gitlab.com/tezos/tezos/-/issues/6400
                 // Make it possible to track multiple slot indexes.
                 let slot_index = storage::dal::get_or_set_slot_index(host, 0 as u8)?;
-                dal::store_dal_slot(
-                    host,
-                    published_level,
-                    num_pages,
-                    page_size,
-                    slot_index,
-                    DAL_PAYLOAD_PATH.into(),
-                );
+
+                // Slots are only attested `attestation_lag` levels after publication.
+                if let Some(published_level) =
+                    (_inbox_level as u64).checked_sub(parameters.attestation_lag)
+                {
+                    dal::store_dal_slot(
+                        host,
+                        published_level as i32,
+                        num_pages,
+                        page_size,
+                        slot_index,
+                        DAL_PAYLOAD_PATH.into(),
+                    );
+                }
             }
             Ok(())
         }
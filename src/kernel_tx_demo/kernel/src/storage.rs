@@ -9,6 +9,7 @@
 //! is rolled back. This module allows for both dealing with transactions
 //! and the updates to accounts that happen within said transactions.
 
+use crate::index::{self, IndexError};
 use crypto::hash::ContractTz1Hash;
 use crypto::hash::HashType;
 use crypto::hash::PublicKeyEd25519;
@@ -17,7 +18,7 @@ use tezos_smart_rollup_encoding::michelson::ticket::StringTicket;
 use tezos_smart_rollup_encoding::michelson::ticket::{TicketHash, TicketHashError};
 use tezos_smart_rollup_host::path::{concat, OwnedPath, RefPath};
 use tezos_smart_rollup_host::runtime::RuntimeError::*;
-use tezos_smart_rollup_host::runtime::{Runtime, RuntimeError};
+use tezos_smart_rollup_host::runtime::{Runtime, RuntimeError, ValueType};
 use tezos_smart_rollup_host::Error::*;
 use tezos_smart_rollup_storage::storage::Storage;
 use thiserror::Error;
@@ -71,6 +72,9 @@ pub enum AccountStorageError {
     /// Invalid Ticket repr
     #[error("ticket amount out of range {0}")]
     InvalidAmount(TryFromBigIntError<BigInt>),
+    /// Failed to update or read the state index.
+    #[error("Index error: {0}")]
+    Index(IndexError),
 }
 
 impl From<tezos_smart_rollup_storage::StorageError> for AccountStorageError {
@@ -97,6 +101,12 @@ impl From<TicketHashError> for AccountStorageError {
     }
 }
 
+impl From<IndexError> for AccountStorageError {
+    fn from(error: IndexError) -> Self {
+        AccountStorageError::Index(error)
+    }
+}
+
 impl From<TryFromBigIntError<BigInt>> for AccountStorageError {
     fn from(error: TryFromBigIntError<BigInt>) -> Self {
         AccountStorageError::InvalidAmount(error)
@@ -113,6 +123,7 @@ impl From<TryFromBigIntError<BigInt>> for AccountStorageError {
 ///   named by the hex encoded hash of the ticket. Each such path holds
 ///   the amount of such tickets held by the account encoded as unsigned
 ///   64 bit integers.
+/// - the `tickets` field. The ids of the tickets held by the account.
 ///
 /// If a path is absent (counter- or ticket- path), then the value defaults
 /// to zero. If the public key path doesn't contain anything, it is given as
@@ -128,6 +139,10 @@ const COUNTER_PATH: RefPath = RefPath::assert_from(b"/counter");
 /// The sub-path for the accounts public key.
 const PUBLIC_KEY_PATH: RefPath = RefPath::assert_from(b"/public.key");
 
+/// The sub-path for the ids of the tickets held by the account, as 64 bit
+/// unsigned integers.
+const HELD_TICKETS_PATH: RefPath = RefPath::assert_from(b"/tickets");
+
 /// Find the path where account information is stored from the account address.
 pub fn account_path(address: &ContractTz1Hash) -> Result<OwnedPath, AccountStorageError> {
     let address_name: Vec<u8> = alloc::format!("/{}", address).into();
@@ -166,25 +181,112 @@ impl Account {
         }
     }
 
-    /// Get or set the account id
-    pub fn get_or_set_id(&mut self, host: &mut impl Runtime) -> Result<u16, AccountStorageError> {
+    /// Get the ids of the tickets held by the account, in the order they were
+    /// first credited.
+    ///
+    /// Accounts which last gained or ran out of a ticket before the list of
+    /// held tickets existed have no such list: their tickets are found among
+    /// all the ticket ids assigned so far instead, by increasing id. The list
+    /// is stored once the account gains or runs out of a ticket.
+    pub fn held_tickets(&self, host: &impl Runtime) -> Result<Vec<u64>, AccountStorageError> {
+        let path = concat(&self.path, &HELD_TICKETS_PATH)?;
+
+        let bytes = match host.store_read_all(&path) {
+            Ok(bytes) => bytes,
+            Err(PathNotFound | HostErr(StoreNotAValue)) => return self.scan_held_tickets(host),
+            Err(error) => return Err(AccountStorageError::from(error)),
+        };
+
+        if bytes.len() % 8 != 0 {
+            return Err(AccountStorageError::MalformedValue);
+        }
+
+        Ok(bytes
+            .chunks_exact(8)
+            .map(|id| u64::from_le_bytes(id.try_into().unwrap()))
+            .collect())
+    }
+
+    /// Find the tickets held by the account, by checking every ticket id
+    /// assigned so far.
+    fn scan_held_tickets(&self, host: &impl Runtime) -> Result<Vec<u64>, AccountStorageError> {
+        let mut tickets = Vec::new();
+
+        for ticket in 0..ticket_count(host)? {
+            if self.ticket_amount(host, ticket)?.is_some() {
+                tickets.push(ticket);
+            }
+        }
+
+        Ok(tickets)
+    }
+
+    /// Add or remove a ticket from the ids of the tickets held by the account.
+    fn set_held<Host: Runtime>(
+        &self,
+        host: &mut Host,
+        ticket: u64,
+        held: bool,
+    ) -> Result<(), AccountStorageError> {
+        let path = concat(&self.path, &HELD_TICKETS_PATH)?;
+        let stored = matches!(
+            host.store_has(&path)?,
+            Some(ValueType::Value | ValueType::ValueWithSubtree)
+        );
+
+        let mut tickets = self.held_tickets(host)?;
+        let position = tickets.iter().position(|id| *id == ticket);
+
+        // Without a stored list, the scanned tickets already reflect the
+        // change, they still need storing.
+        match (position, held) {
+            (None, true) => tickets.push(ticket),
+            (Some(index), false) => {
+                tickets.remove(index);
+            }
+            _ if stored => return Ok(()),
+            _ => (),
+        }
+
+        let bytes: Vec<u8> = tickets.iter().flat_map(|id| id.to_le_bytes()).collect();
+        host.store_write_all(&path, &bytes)
+            .map_err(AccountStorageError::from)
+    }
+
+    /// Get the account id, if one has been assigned
+    pub fn id(&self, host: &impl Runtime) -> Result<Option<u16>, AccountStorageError> {
         let path = concat(&self.path, &ID_PATH)?;
 
         let mut buffer = [0_u8; 2];
 
         match host.store_read_slice(&path, 0, &mut buffer) {
-            Ok(2) => {
-                let value = u16::from_le_bytes(buffer);
-                Ok(value)
-            }
-            _ => {
-                let next_id = next_account_id(host)?;
-                host.store_write(&path, &next_id.to_le_bytes(), 0)?;
-                Ok(next_id)
-            }
+            Ok(2) => Ok(Some(u16::from_le_bytes(buffer))),
+            _ => Ok(None),
         }
     }
 
+    /// Get or set the account id
+    ///
+    /// Ids are recorded in the state index, along with the `address` of the
+    /// account, when assigned or, for ids assigned before the index existed,
+    /// when next used.
+    pub fn get_or_set_id(
+        &mut self,
+        host: &mut impl Runtime,
+        address: &ContractTz1Hash,
+    ) -> Result<u16, AccountStorageError> {
+        if let Some(id) = self.id(host)? {
+            index::backfill_account(host, id, address);
+            return Ok(id);
+        }
+
+        let path = concat(&self.path, &ID_PATH)?;
+        let next_id = next_account_id(host)?;
+        host.store_write(&path, &next_id.to_le_bytes(), 0)?;
+        index::record_account(host, next_id, address);
+        Ok(next_id)
+    }
+
     /// Take number of tickets from account
     ///
     /// This function is made to match the use case of transactions. TX kernel
@@ -209,6 +311,7 @@ impl Account {
                     Some(0) => {
                         host.store_delete(&path)
                             .map_err(AccountStorageError::from)?;
+                        self.set_held(host, ticket, false)?;
                         Ok(0)
                     }
                     Some(new_amount) => {
@@ -262,6 +365,9 @@ impl Account {
                 if let Some(new_value) = old_amount.checked_add(amount) {
                     host.store_write(&path, &new_value.to_le_bytes(), 0)
                         .map_err(AccountStorageError::from)?;
+                    if old_amount == 0 && new_value != 0 {
+                        self.set_held(host, ticket_index, true)?;
+                    }
                     Ok(new_value)
                 } else {
                     Err(AccountStorageError::AmountOverflow)
@@ -270,6 +376,9 @@ impl Account {
             Err(PathNotFound | HostErr(StoreNotAValue)) => {
                 host.store_write(&path, &amount.to_le_bytes(), 0)
                     .map_err(AccountStorageError::from)?;
+                if amount != 0 {
+                    self.set_held(host, ticket_index, true)?;
+                }
                 Ok(amount)
             }
             Ok(_) => Err(AccountStorageError::MalformedValue),
@@ -428,6 +537,9 @@ impl From<OwnedPath> for Account {
 }
 
 /// Record ticket
+///
+/// Ticket ids assigned before the state index existed are recorded in it
+/// when next used.
 pub fn get_or_set_ticket_id(
     host: &mut impl Runtime,
     ticket: &TicketHash,
@@ -440,11 +552,13 @@ pub fn get_or_set_ticket_id(
     match host.store_read_slice(&path, 0, &mut buffer) {
         Ok(SIZE) => {
             let value = u64::from_le_bytes(buffer);
+            index::backfill_ticket_hash(host, value, ticket);
             Ok(value)
         }
         _ => {
             let next_id = next_ticket_id(host)?;
             host.store_write(&path, &next_id.to_le_bytes(), 0)?;
+            index::record_ticket_hash(host, next_id, ticket);
             Ok(next_id)
         }
     }
@@ -483,9 +597,10 @@ pub fn deposit_ticket_to_storage<Host: Runtime>(
     let amount: u64 = ticket.amount_as()?;
 
     let ticket_id = get_or_set_ticket_id(host, &ticket_hash)?;
+    index::record_ticket_details(host, ticket_id, &ticket_hash, ticket);
 
     account.add_ticket(host, ticket_id, amount)?;
-    account.get_or_set_id(host)?;
+    account.get_or_set_id(host, destination)?;
 
     #[cfg(feature = "debug")]
     tezos_smart_rollup_debug::debug_msg!(host, "Depositing {ticket:?} to {destination}\n");
@@ -505,15 +620,24 @@ fn next_account_id(host: &mut impl Runtime) -> Result<u16, RuntimeError> {
     Ok(value)
 }
 
-/// Current ticket counter
-fn next_ticket_id(host: &mut impl Runtime) -> Result<u64, RuntimeError> {
-    const NEXT_ID: RefPath = RefPath::assert_from(b"/next_ticket_id");
+/// Path of the next ticket id to assign
+const NEXT_TICKET_ID_PATH: RefPath = RefPath::assert_from(b"/next_ticket_id");
 
+/// Number of ticket ids assigned so far
+fn ticket_count(host: &impl Runtime) -> Result<u64, RuntimeError> {
     let mut buffer = [0_u8; 8];
 
-    let _ = host.store_read_slice(&NEXT_ID, 0, &mut buffer);
-    let value = u64::from_le_bytes(buffer);
-    host.store_write(&NEXT_ID, &(value + 1).to_le_bytes(), 0)?;
+    match host.store_read_slice(&NEXT_TICKET_ID_PATH, 0, &mut buffer) {
+        Ok(_) => Ok(u64::from_le_bytes(buffer)),
+        Err(PathNotFound) => Ok(0),
+        Err(error) => Err(error),
+    }
+}
+
+/// Current ticket counter
+fn next_ticket_id(host: &mut impl Runtime) -> Result<u64, RuntimeError> {
+    let value = ticket_count(host)?;
+    host.store_write(&NEXT_TICKET_ID_PATH, &(value + 1).to_le_bytes(), 0)?;
     Ok(value)
 }
 
@@ -0,0 +1,395 @@
+// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Processing operation batches published on the DAL.
+//!
+//! At the start of each level, the pages of the configured DAL slot are
+//! revealed and stored contiguously under [DAL_PAYLOAD_PATH]. The payload is a
+//! sequence of frames, each holding a batch of operations in the same encoding
+//! as [ParsedBatch::parse_tagged], i.e. prefixed by [BATCH_TAG] and its length.
+//! Frames may span several pages, and the payload ends at the first frame
+//! starting with a zero byte, as slots are zero-padded.
+//!
+//! Batches of operations predating fees are prefixed by [BATCH_WITHOUT_FEES_TAG]
+//! instead. A frame with any other tag cannot be delimited, so the rest of the
+//! payload is skipped.
+//!
+//! Payloads predating batch tags are still accepted: their frames are prefixed
+//! by their length only, and hold operations predating fees. Such payloads are
+//! told apart by their first byte, the most significant byte of the length of
+//! the first frame, which is zero as slots are smaller than 16MiB, followed by
+//! a non-zero length.
+//!
+//! Batches are applied in order, and the offset of the next frame is persisted
+//! under [DAL_PROGRESS_PATH], so that processing a slot may span several reboots.
+//! The kernel always reboots after processing part of the payload, so that
+//! the inbox of the level is still read once the payload is done.
+//!
+//! Slots attested while a payload is still pending are queued under
+//! `/kernel/dal/queue`, and stored in turn once the payload is done.
+
+use super::external_inbox::process_batch_message;
+use super::store::{DAL_PAYLOAD_PATH, DAL_PROGRESS_PATH};
+use super::utils::{read_large_store_chunk_at, transactions_per_kernel_run};
+use crate::inbox::v1::{ParsedBatch, BATCH_TAG, BATCH_WITHOUT_FEES_TAG};
+use crate::storage::AccountStorage;
+use crate::transactions::withdrawal::process_withdrawals;
+#[cfg(feature = "debug")]
+use tezos_smart_rollup_debug::debug_msg;
+use tezos_smart_rollup_host::runtime::{Runtime, RuntimeError};
+use thiserror::Error;
+
+/// Size of the length prefix of each frame
+const FRAME_LENGTH_SIZE: usize = core::mem::size_of::<u32>();
+
+/// Encoding of the frames of a DAL payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Framing {
+    /// Frames prefixed by a batch tag, see [ParsedBatch::parse_tagged]
+    Tagged,
+    /// Frames predating batch tags, prefixed by their length only, see
+    /// [ParsedBatch::parse_without_fees]
+    Untagged,
+}
+
+impl Framing {
+    /// Size of the prefix of each frame
+    fn prefix_size(self) -> usize {
+        match self {
+            Framing::Tagged => 1 + FRAME_LENGTH_SIZE,
+            Framing::Untagged => FRAME_LENGTH_SIZE,
+        }
+    }
+
+    /// Parse a whole frame, including its prefix
+    fn parse(self, frame: &[u8]) -> tezos_data_encoding::nom::NomResult<ParsedBatch> {
+        match self {
+            Framing::Tagged => ParsedBatch::parse_tagged(frame),
+            Framing::Untagged => ParsedBatch::parse_without_fees(frame),
+        }
+    }
+}
+
+/// Errors when processing the DAL payload
+#[derive(Error, Debug)]
+pub enum DalInboxError {
+    /// Reading or writing the payload or progress failed
+    #[error("Store error while processing DAL payload: {0}")]
+    Store(RuntimeError),
+    /// Requesting a reboot to resume processing failed
+    #[error("Failed to reboot while processing DAL payload: {0}")]
+    Reboot(RuntimeError),
+}
+
+/// Offset of the next frame to process in the DAL payload
+fn read_progress(host: &impl Runtime) -> Result<usize, DalInboxError> {
+    if host
+        .store_has(&DAL_PROGRESS_PATH)
+        .map_err(DalInboxError::Store)?
+        .is_none()
+    {
+        return Ok(0);
+    }
+
+    let mut buf = [0u8; 4];
+    host.store_read_slice(&DAL_PROGRESS_PATH, 0, &mut buf)
+        .map_err(DalInboxError::Store)?;
+    Ok(u32::from_le_bytes(buf) as usize)
+}
+
+/// Framing of the payload, told apart by the first bytes of the payload.
+fn read_framing(host: &impl Runtime, payload_size: usize) -> Result<Framing, DalInboxError> {
+    if payload_size < FRAME_LENGTH_SIZE {
+        return Ok(Framing::Tagged);
+    }
+
+    let mut length = [0u8; FRAME_LENGTH_SIZE];
+    host.store_read_slice(&DAL_PAYLOAD_PATH, 0, &mut length)
+        .map_err(DalInboxError::Store)?;
+
+    if length[0] == 0 && length != [0u8; FRAME_LENGTH_SIZE] {
+        Ok(Framing::Untagged)
+    } else {
+        Ok(Framing::Tagged)
+    }
+}
+
+/// Read the frame starting at `offset`, including its prefix.
+/// Returns `None` once the end of the payload is reached.
+fn read_frame(
+    host: &mut impl Runtime,
+    framing: Framing,
+    offset: usize,
+    payload_size: usize,
+) -> Result<Option<Vec<u8>>, DalInboxError> {
+    let prefix_size = framing.prefix_size();
+    if offset + prefix_size > payload_size {
+        return Ok(None);
+    }
+
+    let mut prefix = [0u8; 1 + FRAME_LENGTH_SIZE];
+    let prefix = &mut prefix[..prefix_size];
+    host.store_read_slice(&DAL_PAYLOAD_PATH, offset, prefix)
+        .map_err(DalInboxError::Store)?;
+
+    let length = match framing {
+        Framing::Tagged => {
+            let (tag, length) = prefix.split_first().unwrap();
+
+            if *tag == 0 {
+                // Zero padding of the slot
+                return Ok(None);
+            }
+
+            if *tag != BATCH_TAG && *tag != BATCH_WITHOUT_FEES_TAG {
+                #[cfg(feature = "debug")]
+                debug_msg!(host, "Unknown DAL frame tag {tag} at offset {offset}\n");
+                return Ok(None);
+            }
+
+            length
+        }
+        Framing::Untagged => {
+            if prefix.iter().all(|byte| *byte == 0) {
+                // Zero padding of the slot
+                return Ok(None);
+            }
+
+            prefix
+        }
+    };
+
+    let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+
+    let frame_size = prefix_size + length;
+    if offset + frame_size > payload_size {
+        #[cfg(feature = "debug")]
+        debug_msg!(host, "Truncated DAL frame at offset {offset}\n");
+        return Ok(None);
+    }
+
+    let mut frame = vec![0u8; frame_size];
+    read_large_store_chunk_at(host, &DAL_PAYLOAD_PATH, offset, &mut frame)
+        .map_err(DalInboxError::Store)?;
+    Ok(Some(frame))
+}
+
+/// Process the batches of the stored DAL payload, if any.
+///
+/// Whole batches are processed until at least the number of operations allowed
+/// per kernel run has been reached. If batches remain, processing resumes from
+/// there after a reboot. Once all batches have been processed, the payload and
+/// progress are removed from the store, and the kernel reboots to read the
+/// inbox.
+pub fn process_dal_payload<Host: Runtime>(
+    host: &mut Host,
+    account_storage: &mut AccountStorage,
+) -> Result<(), DalInboxError> {
+    let payload_size = match host.store_value_size(&DAL_PAYLOAD_PATH) {
+        Ok(size) => size,
+        Err(RuntimeError::PathNotFound) => return Ok(()),
+        Err(e) => return Err(DalInboxError::Store(e)),
+    };
+
+    let max_operations = transactions_per_kernel_run(host).max(1) as usize;
+    let framing = read_framing(host, payload_size)?;
+    let mut offset = read_progress(host)?;
+    let mut processed = 0;
+
+    while processed < max_operations {
+        let Some(frame) = read_frame(host, framing, offset, payload_size)? else {
+            #[cfg(feature = "debug")]
+            debug_msg!(host, "Finished processing DAL payload\n");
+
+            host.store_delete(&DAL_PAYLOAD_PATH)
+                .map_err(DalInboxError::Store)?;
+            if host
+                .store_has(&DAL_PROGRESS_PATH)
+                .map_err(DalInboxError::Store)?
+                .is_some()
+            {
+                host.store_delete(&DAL_PROGRESS_PATH)
+                    .map_err(DalInboxError::Store)?;
+            }
+            return host.mark_for_reboot().map_err(DalInboxError::Reboot);
+        };
+
+        offset += frame.len();
+
+        match framing.parse(&frame) {
+            Ok((_, batch)) => {
+                processed += batch.operations.len();
+                for withdrawals in process_batch_message(host, account_storage, batch) {
+                    process_withdrawals(host, withdrawals)
+                }
+            }
+            Err(_e) => {
+                #[cfg(feature = "debug")]
+                debug_msg!(host, "Skipping malformed DAL batch: {:?}\n", _e);
+            }
+        }
+    }
+
+    host.store_write(&DAL_PROGRESS_PATH, &(offset as u32).to_le_bytes(), 0)
+        .map_err(DalInboxError::Store)?;
+
+    host.mark_for_reboot().map_err(DalInboxError::Reboot)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::inbox::external::testing::{
+        funded_account, gen_ed25519_keys, string_ticket, ticket_amount,
+    };
+    use crate::inbox::v1::sendable::Batch;
+    use crate::inbox::v1::{Operation, OperationContent};
+    use crate::inbox::Signer;
+    use crate::storage::init_account_storage;
+    use crate::transactions::store::TRANSACTIONS_PER_KERNEL_RUN;
+    use crypto::PublicKeyWithHash;
+    use tezos_data_encoding::enc::BinWriter;
+    use tezos_smart_rollup_host::path::RefPath;
+    use tezos_smart_rollup_mock::MockHost;
+
+    const REBOOT_PATH: RefPath = RefPath::assert_from(b"/kernel/env/reboot");
+
+    #[test]
+    fn dal_payload_processed_across_reboots() {
+        // Arrange
+        let mut host = MockHost::default();
+        let mut account_storage = init_account_storage().unwrap();
+
+        let ticket = string_ticket("red", 10);
+        let (pk, sk) = funded_account(&mut host, &mut account_storage, &[&ticket]);
+        let address = pk.pk_hash().unwrap();
+        let destination = gen_ed25519_keys().0.pk_hash().unwrap();
+
+        let transfer = |counter, amount| Operation {
+            signer: Signer::PublicKey(pk.clone()),
+            counter,
+            fee: 0.into(),
+            contents: OperationContent::transfer(
+                destination.clone(),
+                ticket.hash().unwrap(),
+                amount,
+            )
+            .unwrap(),
+        };
+
+        // Two frames, followed by the zero padding of the slot
+        let mut payload = Vec::new();
+        Batch::new(vec![(transfer(0, 3), sk.clone())])
+            .bin_write_tagged(&mut payload)
+            .unwrap();
+        Batch::new(vec![(transfer(1, 4), sk)])
+            .bin_write_tagged(&mut payload)
+            .unwrap();
+        payload.resize(payload.len() + 64, 0);
+        host.store_write_all(&DAL_PAYLOAD_PATH, &payload).unwrap();
+
+        // Only one operation per kernel run
+        host.store_write(&TRANSACTIONS_PER_KERNEL_RUN, &1_i32.to_le_bytes(), 0)
+            .unwrap();
+
+        // Act & Assert
+        process_dal_payload(&mut host, &mut account_storage).unwrap();
+        assert_eq!(
+            Some(3),
+            ticket_amount(&host, &account_storage, &destination, 0)
+        );
+        assert!(host.store_has(&DAL_PAYLOAD_PATH).unwrap().is_some());
+        assert!(host.store_has(&DAL_PROGRESS_PATH).unwrap().is_some());
+
+        process_dal_payload(&mut host, &mut account_storage).unwrap();
+        assert_eq!(
+            Some(7),
+            ticket_amount(&host, &account_storage, &destination, 0)
+        );
+        assert_eq!(Some(3), ticket_amount(&host, &account_storage, &address, 0));
+
+        // The inbox of the level is read after a reboot, once the payload
+        // has been processed.
+        host.store_delete(&REBOOT_PATH).unwrap();
+        process_dal_payload(&mut host, &mut account_storage).unwrap();
+        assert!(host.store_has(&DAL_PAYLOAD_PATH).unwrap().is_none());
+        assert!(host.store_has(&DAL_PROGRESS_PATH).unwrap().is_none());
+        assert!(host.store_has(&REBOOT_PATH).unwrap().is_some());
+    }
+
+    /// Batches of operations predating fees are applied, whether tagged or
+    /// in payloads predating batch tags, but not frames with unknown tags.
+    fn dal_payload_without_fees(write: fn(&Batch, &mut Vec<u8>), expected: Option<u64>) {
+        // Arrange
+        let mut host = MockHost::default();
+        let mut account_storage = init_account_storage().unwrap();
+
+        let ticket = string_ticket("red", 10);
+        let (pk, sk) = funded_account(&mut host, &mut account_storage, &[&ticket]);
+        let address = pk.pk_hash().unwrap();
+        let destination = gen_ed25519_keys().0.pk_hash().unwrap();
+
+        let transfer = |counter| Operation {
+            signer: Signer::PublicKey(pk.clone()),
+            counter,
+            fee: 0.into(),
+            contents: OperationContent::transfer(destination.clone(), ticket.hash().unwrap(), 3)
+                .unwrap(),
+        };
+
+        let mut payload = Vec::new();
+        write(&Batch::new(vec![(transfer(0), sk.clone())]), &mut payload);
+        write(&Batch::new(vec![(transfer(1), sk)]), &mut payload);
+        payload.resize(payload.len() + 64, 0);
+        host.store_write_all(&DAL_PAYLOAD_PATH, &payload).unwrap();
+
+        // Act
+        process_dal_payload(&mut host, &mut account_storage).unwrap();
+
+        // Assert
+        assert_eq!(
+            expected,
+            ticket_amount(&host, &account_storage, &destination, 0)
+        );
+        assert_eq!(
+            Some(10 - expected.unwrap_or(0)),
+            ticket_amount(&host, &account_storage, &address, 0)
+        );
+        assert!(host.store_has(&DAL_PAYLOAD_PATH).unwrap().is_none());
+        assert!(host.store_has(&REBOOT_PATH).unwrap().is_some());
+    }
+
+    #[test]
+    fn dal_payload_tagged_without_fees() {
+        dal_payload_without_fees(
+            |batch, payload| batch.bin_write_without_fees(payload).unwrap(),
+            Some(6),
+        );
+    }
+
+    #[test]
+    fn dal_payload_without_batch_tag() {
+        dal_payload_without_fees(
+            |batch, payload| {
+                let mut frame = Vec::new();
+                batch.bin_write_without_fees(&mut frame).unwrap();
+                // Drop the batch tag
+                payload.extend_from_slice(&frame[1..]);
+            },
+            Some(6),
+        );
+    }
+
+    #[test]
+    fn dal_payload_with_unknown_tag_skipped() {
+        dal_payload_without_fees(
+            |batch, payload| {
+                payload.push(2);
+                batch.bin_write(payload).unwrap();
+            },
+            None,
+        );
+    }
+}
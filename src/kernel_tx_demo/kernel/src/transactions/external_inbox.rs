@@ -6,9 +6,11 @@
 //! Processing external inbox messages - withdrawals & transactions.
 
 use super::store::dac_payload_path;
+use crate::inbox::v1::verifiable::{TransactionError, VerifiableOperation};
 use crate::inbox::v1::ParsedBatch;
 use crate::inbox::ParsedExternalInboxMessage;
 use crate::storage::AccountStorage;
+use crate::transactions::fees::FeeConfig;
 use crate::transactions::withdrawal::Withdrawal;
 use crypto::hash::PublicKeyBls;
 use crypto::CryptoError;
@@ -40,7 +42,93 @@ pub fn reveal_and_store_dac_message<Host: Runtime>(
     Ok(())
 }
 
+/// Execute an operation in account storage transactions.
+///
+/// With the `debug` feature, the outcome of the operation is logged as either
+/// `Operation <hash> by <tz1>: applied` or
+/// `Operation <hash> by <tz1>: failed: <error>`, where `<hash>` is the hex
+/// encoded hash of the operation. External tools rely on this format.
+///
+/// The operation is first paid for, in its own transaction: if its signature,
+/// counter or fee are invalid, it is rejected without any effect. The contents
+/// are then executed in a nested transaction, whose effects are committed if
+/// execution succeeds, and rolled back otherwise. Either way, the signer's
+/// counter increment and fee are kept, so that the operation cannot be replayed.
+pub fn execute_operation<Host: Runtime>(
+    host: &mut Host,
+    account_storage: &mut AccountStorage,
+    operation: VerifiableOperation,
+) -> Result<Vec<Withdrawal>, TransactionError> {
+    #[cfg(feature = "debug")]
+    let (_hash, _signer) = (
+        operation.hash().map(hex::encode).unwrap_or_default(),
+        operation.operation.signer.address(),
+    );
+
+    let result = apply_operation(host, account_storage, operation);
+
+    #[cfg(feature = "debug")]
+    if let Ok(signer) = _signer {
+        match &result {
+            Ok(_) => debug_msg!(host, "Operation {_hash} by {signer}: applied\n"),
+            Err(e) => debug_msg!(host, "Operation {_hash} by {signer}: failed: {e}\n"),
+        }
+    }
+
+    result
+}
+
+fn apply_operation<Host: Runtime>(
+    host: &mut Host,
+    account_storage: &mut AccountStorage,
+    operation: VerifiableOperation,
+) -> Result<Vec<Withdrawal>, TransactionError> {
+    let fees = FeeConfig::load(host)?;
+
+    account_storage.begin_transaction(host)?;
+
+    if let Err(err) = operation.prepay(host, account_storage, fees.as_ref()) {
+        account_storage.rollback_transaction(host)?;
+        return Err(err);
+    }
+
+    match execute_contents(host, account_storage, operation) {
+        Ok(result) => {
+            account_storage.commit_transaction(host)?;
+            result
+        }
+        Err(err) => {
+            account_storage.rollback_transaction(host)?;
+            Err(err)
+        }
+    }
+}
+
+/// Execute the contents of a prepaid operation in a nested transaction.
+///
+/// The outer error is only returned when the nested transaction could not be
+/// opened or closed, in which case the prepayment must be rolled back too.
+fn execute_contents<Host: Runtime>(
+    host: &mut Host,
+    account_storage: &mut AccountStorage,
+    operation: VerifiableOperation,
+) -> Result<Result<Vec<Withdrawal>, TransactionError>, TransactionError> {
+    account_storage.begin_transaction(host)?;
+
+    let result = operation.execute(host, account_storage);
+    if result.is_ok() {
+        account_storage.commit_transaction(host)?;
+    } else {
+        account_storage.rollback_transaction(host)?;
+    }
+
+    Ok(result)
+}
+
 /// Process a list of operations.
+///
+/// Operations are applied in order, each one atomically: a failing operation
+/// is skipped without affecting the others.
 pub fn process_batch_message<Host: Runtime>(
     host: &mut Host,
     account_storage: &mut AccountStorage,
@@ -49,15 +137,10 @@ pub fn process_batch_message<Host: Runtime>(
     let mut all_withdrawals: Vec<Vec<Withdrawal>> = Vec::new();
 
     for transaction in batch.operations.into_iter() {
-        match transaction.execute(host, account_storage) {
-            Ok(withdrawals) => {
-                all_withdrawals.push(withdrawals);
-            }
-            Err(_err) => {
-                #[cfg(feature = "debug")]
-                debug_msg!(host, "Could not execute transaction: {}\n", _err);
-            }
-        };
+        // Failures are logged by `execute_operation`.
+        if let Ok(withdrawals) = execute_operation(host, account_storage, transaction) {
+            all_withdrawals.push(withdrawals);
+        }
     }
 
     all_withdrawals
@@ -158,3 +241,157 @@ pub enum LoadDacCommitteeError {
     #[error(transparent)]
     CryptoError(#[from] CryptoError),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::inbox::external::testing::{
+        funded_account, gen_ed25519_keys, string_ticket, ticket_amount,
+    };
+    use crate::inbox::v1::sendable::Batch;
+    use crate::inbox::v1::{Operation, OperationContent};
+    use crate::inbox::Signer;
+    use crate::storage::{account_path, init_account_storage};
+    use crate::transactions::store::{FEE_COLLECTOR_PATH, FEE_TICKET_PATH, MINIMUM_FEE_PATH};
+    use crypto::PublicKeyWithHash;
+    use tezos_data_encoding::enc::BinWriter;
+    use tezos_smart_rollup_mock::MockHost;
+
+    #[test]
+    fn swap_applies_both_transfers_or_neither() {
+        // Arrange
+        let mut host = MockHost::default();
+        let mut account_storage = init_account_storage().unwrap();
+
+        let red = string_ticket("red", 10);
+        let blue = string_ticket("blue", 5);
+
+        // Tickets are identified in order of deposit: red is 0 & blue is 1.
+        let (pk, sk) = funded_account(&mut host, &mut account_storage, &[&red]);
+        let (counterparty_pk, counterparty_sk) =
+            funded_account(&mut host, &mut account_storage, &[&blue]);
+        let address = pk.pk_hash().unwrap();
+        let counterparty_address = counterparty_pk.pk_hash().unwrap();
+
+        let swap = |counter, amount, counter_amount| Operation {
+            signer: Signer::PublicKey(pk.clone()),
+            counter,
+            fee: 0.into(),
+            contents: OperationContent::swap(
+                Signer::PublicKey(counterparty_pk.clone()),
+                0,
+                red.hash().unwrap(),
+                amount,
+                blue.hash().unwrap(),
+                counter_amount,
+            )
+            .unwrap(),
+        };
+
+        // The counterparty does not hold enough blue tickets for the first swap,
+        // whose transfers must be rolled back as a whole - including the
+        // counterparty's counter increment. The signer's is kept, as the signer
+        // paid for the operation.
+        let batch = Batch::new_multi_signed(vec![
+            (swap(0, 4, 6), vec![sk.clone(), counterparty_sk.clone()]),
+            (swap(1, 4, 2), vec![sk, counterparty_sk]),
+        ]);
+        let mut encoded = Vec::new();
+        batch.bin_write(&mut encoded).unwrap();
+        let (_, batch) = ParsedBatch::parse(&encoded).unwrap();
+
+        // Act
+        let withdrawals = process_batch_message(&mut host, &mut account_storage, batch);
+
+        // Assert
+        assert_eq!(vec![Vec::<Withdrawal>::new()], withdrawals);
+        assert_eq!(Some(6), ticket_amount(&host, &account_storage, &address, 0));
+        assert_eq!(Some(2), ticket_amount(&host, &account_storage, &address, 1));
+        assert_eq!(
+            Some(4),
+            ticket_amount(&host, &account_storage, &counterparty_address, 0)
+        );
+        assert_eq!(
+            Some(3),
+            ticket_amount(&host, &account_storage, &counterparty_address, 1)
+        );
+        assert_eq!(0, account_storage.stack_depth());
+    }
+
+    #[test]
+    fn fees_are_charged_even_if_execution_fails() {
+        // Arrange
+        let mut host = MockHost::default();
+        let mut account_storage = init_account_storage().unwrap();
+
+        let red = string_ticket("red", 10);
+        let (pk, sk) = funded_account(&mut host, &mut account_storage, &[&red]);
+        let address = pk.pk_hash().unwrap();
+        let destination = gen_ed25519_keys().0.pk_hash().unwrap();
+        let collector = gen_ed25519_keys().0.pk_hash().unwrap();
+
+        let mut fee_ticket = Vec::new();
+        red.hash().unwrap().bin_write(&mut fee_ticket).unwrap();
+        host.store_write_all(&FEE_TICKET_PATH, &fee_ticket).unwrap();
+        let mut fee_collector = Vec::new();
+        collector.bin_write(&mut fee_collector).unwrap();
+        host.store_write_all(&FEE_COLLECTOR_PATH, &fee_collector)
+            .unwrap();
+        host.store_write(&MINIMUM_FEE_PATH, &1_u64.to_le_bytes(), 0)
+            .unwrap();
+
+        let transfer = |counter, fee: u64, amount| {
+            let operation = Operation {
+                signer: Signer::PublicKey(pk.clone()),
+                counter,
+                fee: fee.into(),
+                contents: OperationContent::transfer(
+                    destination.clone(),
+                    red.hash().unwrap(),
+                    amount,
+                )
+                .unwrap(),
+            };
+            (operation, sk.clone())
+        };
+
+        let batch = Batch::new(vec![
+            // Succeeds
+            transfer(0, 2, 3),
+            // Fails to execute, but is paid for
+            transfer(1, 2, 10),
+            // Rejected: below the minimum fee
+            transfer(2, 0, 1),
+            // Rejected: not enough tickets left to pay the fee
+            transfer(2, 4, 1),
+            // Rejected: replay of a paid operation
+            transfer(1, 2, 10),
+        ]);
+        let mut encoded = Vec::new();
+        batch.bin_write(&mut encoded).unwrap();
+        let (_, batch) = ParsedBatch::parse(&encoded).unwrap();
+
+        // Act
+        let withdrawals = process_batch_message(&mut host, &mut account_storage, batch);
+
+        // Assert
+        assert_eq!(vec![Vec::<Withdrawal>::new()], withdrawals);
+        assert_eq!(Some(3), ticket_amount(&host, &account_storage, &address, 0));
+        assert_eq!(
+            Some(3),
+            ticket_amount(&host, &account_storage, &destination, 0)
+        );
+        assert_eq!(
+            Some(4),
+            ticket_amount(&host, &account_storage, &collector, 0)
+        );
+
+        let signer_account = account_storage
+            .get(&host, &account_path(&address).unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(2, signer_account.counter(&host).unwrap());
+        assert_eq!(0, account_storage.stack_depth());
+    }
+}
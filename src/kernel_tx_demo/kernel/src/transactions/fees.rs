@@ -0,0 +1,128 @@
+// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Operation fees
+//!
+//! Every operation specifies the fee its signer pays for it. Fees are paid in
+//! the ticket configured under [FEE_TICKET_PATH], and credited to the account
+//! configured under [FEE_COLLECTOR_PATH] - typically the sequencer's. Operations
+//! paying less than the minimum under [MINIMUM_FEE_PATH] are rejected.
+//!
+//! Without a configured fee ticket, fees are disabled and only operations
+//! paying no fee are accepted.
+
+use super::store::{FEE_COLLECTOR_PATH, FEE_TICKET_PATH, MINIMUM_FEE_PATH};
+use crate::inbox::v1::verifiable::TransactionError;
+use crate::storage::{account_path, get_or_set_ticket_id, Account};
+use crate::storage::{AccountStorage, AccountStorageError};
+use crypto::hash::ContractTz1Hash;
+use tezos_data_encoding::nom::NomReader;
+#[cfg(feature = "debug")]
+use tezos_smart_rollup_debug::debug_msg;
+use tezos_smart_rollup_encoding::michelson::ticket::TicketHash;
+use tezos_smart_rollup_host::path::RefPath;
+use tezos_smart_rollup_host::runtime::{Runtime, RuntimeError};
+
+/// Fee model of the kernel
+#[derive(Debug, PartialEq, Eq)]
+pub struct FeeConfig {
+    /// The ticket in which fees are paid
+    pub ticket: TicketHash,
+    /// The account credited with fees
+    pub collector: ContractTz1Hash,
+    /// The minimum fee of an operation
+    pub minimum: u64,
+}
+
+fn read_value<T: NomReader>(
+    host: &impl Runtime,
+    path: &RefPath,
+) -> Result<Option<T>, AccountStorageError> {
+    let bytes = match host.store_read_all(path) {
+        Ok(bytes) => bytes,
+        Err(RuntimeError::PathNotFound) => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+
+    match T::nom_read(&bytes) {
+        Ok(([], value)) => Ok(Some(value)),
+        _ => Err(AccountStorageError::MalformedValue),
+    }
+}
+
+impl FeeConfig {
+    /// Read the fee model from durable storage. Returns `None` if fees are
+    /// disabled.
+    pub fn load(host: &impl Runtime) -> Result<Option<Self>, AccountStorageError> {
+        let Some(ticket) = read_value(host, &FEE_TICKET_PATH)? else {
+            return Ok(None);
+        };
+        let Some(collector) = read_value(host, &FEE_COLLECTOR_PATH)? else {
+            return Ok(None);
+        };
+
+        let mut buffer = [0_u8; 8];
+        let minimum = match host.store_read_slice(&MINIMUM_FEE_PATH, 0, &mut buffer) {
+            Ok(8) => u64::from_le_bytes(buffer),
+            Ok(_) => return Err(AccountStorageError::MalformedValue),
+            Err(RuntimeError::PathNotFound) => 0,
+            Err(error) => return Err(error.into()),
+        };
+
+        Ok(Some(Self {
+            ticket,
+            collector,
+            minimum,
+        }))
+    }
+}
+
+/// Move the `fee` of an operation from the `payer` account to the fee
+/// collector.
+///
+/// Assumes that a transaction is in progress in durable storage, and that it
+/// will be rolled back if an error is returned.
+pub fn charge_fee<Host: Runtime>(
+    host: &mut Host,
+    account_storage: &mut AccountStorage,
+    fees: Option<&FeeConfig>,
+    payer: &mut Account,
+    payer_address: &ContractTz1Hash,
+    fee: u64,
+) -> Result<(), TransactionError> {
+    let Some(fees) = fees else {
+        return match fee {
+            0 => Ok(()),
+            fee => Err(TransactionError::FeesDisabled(fee)),
+        };
+    };
+
+    if fee < fees.minimum {
+        return Err(TransactionError::FeeTooLow(fees.minimum, fee));
+    }
+    if fee == 0 {
+        return Ok(());
+    }
+
+    let ticket_id = get_or_set_ticket_id(host, &fees.ticket)?;
+    match payer.remove_ticket(host, ticket_id, fee) {
+        Ok(_) => {}
+        Err(AccountStorageError::NotEnoughFunds(..)) => {
+            return Err(TransactionError::InsufficientFeeBalance(
+                payer_address.clone(),
+            ))
+        }
+        Err(error) => return Err(error.into()),
+    }
+
+    let collector_path = account_path(&fees.collector)?;
+    let mut collector = account_storage.get_or_create(host, &collector_path)?;
+    collector.add_ticket(host, ticket_id, fee)?;
+    collector.get_or_set_id(host, &fees.collector)?;
+
+    #[cfg(feature = "debug")]
+    debug_msg!(host, "Charged fee of {fee} to {payer_address}\n");
+
+    Ok(())
+}
@@ -4,7 +4,9 @@
 // SPDX-License-Identifier: MIT
 
 //! Transactions kernel core logic for handling messages
+pub mod dal_inbox;
 pub mod external_inbox;
+pub mod fees;
 pub mod process;
 pub mod store;
 pub mod utils;
@@ -9,10 +9,11 @@ use std::slice::from_mut;
 
 use crate::inbox::external::dac_iterator::IteratorState;
 use crate::inbox::ParsedExternalInboxMessage;
+use crate::index;
 use crate::storage::dal;
 use crate::storage::AccountStorage;
 use crate::transactions::external_inbox;
-use crate::transactions::external_inbox::process_batch_message;
+use crate::transactions::external_inbox::{execute_operation, process_batch_message};
 use crate::transactions::withdrawal::process_withdrawals;
 use crate::CachedTransactionError;
 use crate::MAX_ENVELOPE_CONTENT_SIZE;
@@ -27,8 +28,7 @@ use super::store::cached_message_path;
 use super::store::cached_message_stage_path;
 use super::store::KERNEL_PRIVATE_STATE;
 use super::store::PROGRESS_KEY;
-use super::store::TRANSACTIONS_PER_KERNEL_RUN;
-use super::utils::read_large_store_chunk;
+use super::utils::{read_large_store_chunk, transactions_per_kernel_run};
 
 pub(crate) fn execute_one_operation<Host: Runtime>(
     host: &mut Host,
@@ -212,6 +212,17 @@ fn process_at_start<Host: Runtime>(
                 Err(dal::StorageError::RuntimeError(e)) => Err(CachedTransactionError::Store(e)),
             }
         }
+        ParsedExternalInboxMessage::QueryBalance(address) => {
+            if let Err(_e) = index::write_balance_report(host, account_storage, &address) {
+                #[cfg(feature = "debug")]
+                debug_msg!(
+                    host,
+                    "Failed to write balance report of {address}: {}\n",
+                    _e
+                );
+            }
+            Ok(Decision::NextMessage)
+        }
     }
 }
 
@@ -245,6 +256,9 @@ fn process_verified<Host: Runtime>(
         ParsedExternalInboxMessage::ChangeDalSlot(_) => {
             unreachable!("ChangeDalSlot processed by previous step");
         }
+        ParsedExternalInboxMessage::QueryBalance(_) => {
+            unreachable!("QueryBalance processed by previous step");
+        }
     }
 }
 
@@ -253,20 +267,7 @@ fn process_revealed(
     account_storage: &mut AccountStorage,
     idx: u32,
 ) -> Result<Decision, CachedTransactionError> {
-    let mut tx_per_kernel_run_buffer = [0; core::mem::size_of::<i32>()];
-    let read_tx_per_kernel_run_result = host.store_read_slice(
-        &TRANSACTIONS_PER_KERNEL_RUN,
-        0,
-        &mut tx_per_kernel_run_buffer,
-    );
-    let max_messages = match read_tx_per_kernel_run_result {
-        Ok(_) => i32::from_le_bytes(tx_per_kernel_run_buffer),
-        Err(_e) => {
-            #[cfg(feature = "debug")]
-            debug_msg!(host, "Could not read transactions per kernel run value: {}\nSetting a default value of 150", _e);
-            150
-        }
-    };
+    let max_messages = transactions_per_kernel_run(host);
     let mut dac_iterator = match IteratorState::load(host, idx) {
         Ok(iterator) => iterator,
         Err(_e) => {
@@ -278,7 +279,7 @@ fn process_revealed(
     for _ in 0..max_messages {
         match dac_iterator.next(host) {
             Ok(Some(transaction)) => {
-                match transaction.execute(host, account_storage) {
+                match execute_operation(host, account_storage, transaction) {
                     Ok(ws) => {
                         process_withdrawals(host, ws);
                     }
@@ -18,6 +18,11 @@ pub(crate) const DAC_PAYLOAD_PATH_PREFIX: RefPath = RefPath::assert_from(b"/kern
 pub(crate) const DAC_ITERATOR_STATE_PREFIX: RefPath = RefPath::assert_from(b"/kernel/dac/iterator");
 
 pub(crate) const DAL_PAYLOAD_PATH: RefPath = RefPath::assert_from(b"/kernel/dal/payload");
+// 32-bit unsigned LE offset of the next batch to process in the DAL payload
+pub(crate) const DAL_PROGRESS_PATH: RefPath = RefPath::assert_from(b"/kernel/dal/progress");
+// Slots attested while a payload was pending, in order: the 32-bit signed LE
+// published level, followed by the slot index, of each
+pub(crate) const DAL_QUEUE_PATH: RefPath = RefPath::assert_from(b"/kernel/dal/queue");
 
 pub(crate) fn dac_payload_path(idx: u32) -> Result<OwnedPath, PathError> {
     concat(
@@ -29,6 +34,13 @@ pub(crate) fn dac_payload_path(idx: u32) -> Result<OwnedPath, PathError> {
 pub(crate) const TRANSACTIONS_PER_KERNEL_RUN: RefPath =
     RefPath::assert_from(b"/kernel/transactions.per.kernel.run");
 
+// Binary encoded hash of the ticket in which fees are paid
+pub(crate) const FEE_TICKET_PATH: RefPath = RefPath::assert_from(b"/kernel/fees/ticket");
+// Binary encoded tz1 address of the account credited with fees
+pub(crate) const FEE_COLLECTOR_PATH: RefPath = RefPath::assert_from(b"/kernel/fees/collector");
+// 64-bit unsigned LE minimum fee per operation
+pub(crate) const MINIMUM_FEE_PATH: RefPath = RefPath::assert_from(b"/kernel/fees/minimum");
+
 pub(crate) fn dac_iterator_state_path(idx: u32) -> Result<OwnedPath, PathError> {
     concat(
         &DAC_ITERATOR_STATE_PREFIX,
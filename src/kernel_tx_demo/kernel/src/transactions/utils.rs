@@ -4,7 +4,10 @@
 
 //! Supporting functions for transaction processors
 
+use super::store::TRANSACTIONS_PER_KERNEL_RUN;
 use tezos_smart_rollup_core::MAX_FILE_CHUNK_SIZE;
+#[cfg(feature = "debug")]
+use tezos_smart_rollup_debug::debug_msg;
 use tezos_smart_rollup_host::runtime::Runtime;
 use tezos_smart_rollup_host::{path::Path, runtime::RuntimeError};
 
@@ -12,11 +15,23 @@ pub(crate) fn read_large_store_chunk<'a>(
     host: &mut impl Runtime,
     path: &impl Path,
     buf: &'a mut [u8],
+) -> Result<&'a mut [u8], RuntimeError> {
+    read_large_store_chunk_at(host, path, 0, buf)
+}
+
+/// Read a value from the store, starting at `from_offset`, into `buf`. Returns
+/// the part of `buf` that was filled.
+pub(crate) fn read_large_store_chunk_at<'a>(
+    host: &mut impl Runtime,
+    path: &impl Path,
+    from_offset: usize,
+    buf: &'a mut [u8],
 ) -> Result<&'a mut [u8], RuntimeError> {
     let mut abuf = &mut *buf;
     let mut offset = 0;
     while abuf.len() > MAX_FILE_CHUNK_SIZE {
-        let bytes_read = host.store_read_slice(path, offset, &mut abuf[..MAX_FILE_CHUNK_SIZE])?;
+        let bytes_read =
+            host.store_read_slice(path, from_offset + offset, &mut abuf[..MAX_FILE_CHUNK_SIZE])?;
         offset += bytes_read;
         if bytes_read < MAX_FILE_CHUNK_SIZE {
             return Ok(&mut buf[..offset]);
@@ -24,12 +39,25 @@ pub(crate) fn read_large_store_chunk<'a>(
         abuf = &mut abuf[MAX_FILE_CHUNK_SIZE..];
     }
     if !abuf.is_empty() {
-        let bytes_read = host.store_read_slice(path, offset, abuf)?;
+        let bytes_read = host.store_read_slice(path, from_offset + offset, abuf)?;
         offset += bytes_read;
     }
     Ok(&mut buf[..offset])
 }
 
+/// Maximum number of operations to process in a single kernel run.
+pub(crate) fn transactions_per_kernel_run(host: &impl Runtime) -> i32 {
+    let mut buffer = [0; core::mem::size_of::<i32>()];
+    match host.store_read_slice(&TRANSACTIONS_PER_KERNEL_RUN, 0, &mut buffer) {
+        Ok(_) => i32::from_le_bytes(buffer),
+        Err(_e) => {
+            #[cfg(feature = "debug")]
+            debug_msg!(host, "Could not read transactions per kernel run value: {}\nSetting a default value of 150", _e);
+            150
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use tezos_smart_rollup_host::path::RefPath;
@@ -36,6 +36,30 @@ pub fn entry(host: &mut impl Runtime) {
         .expect("Could not read from storage");
     assert_eq!(read_msg.as_slice(), msg.as_bytes());
 
+    let dir: OwnedPath = "/dummy".as_bytes().to_vec().try_into().unwrap();
+    let first: OwnedPath = "/dummy/first".as_bytes().to_vec().try_into().unwrap();
+    let second: OwnedPath = "/dummy/second".as_bytes().to_vec().try_into().unwrap();
+    for path in [&first, &second] {
+        host.store_write(path, msg.as_bytes(), 0)
+            .expect("Could not write to storage");
+    }
+    let subkeys = host
+        .store_count_subkeys(&dir)
+        .expect("Could not list storage");
+    debug_msg!(host, "/dummy has {subkeys} subkeys\n");
+
+    host.store_delete(&first)
+        .expect("Could not delete from storage");
+    debug_msg!(
+        host,
+        "After deletion: /dummy/first is {:?}, /dummy is {:?}\n",
+        host.store_has(&first).expect("Could not check storage"),
+        host.store_has(&dir).expect("Could not check storage")
+    );
+
+    host.write_output(msg.as_bytes())
+        .expect("Could not write to the outbox");
+
     unsafe {
         let public_key: [u8; 32] = [
             171, 32, 104, 249, 65, 125, 118, 36, 210, 237, 61, 116, 43, 133, 16, 15, 177, 4, 114,
@@ -1,6 +1,12 @@
-// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+// SPDX-FileCopyrightText: 2023-2024 TriliTech <contact@trili.tech>
 //
 // SPDX-License-Identifier: MIT
 
 pub mod rv32i;
+pub mod rv64a;
+pub mod rv64c;
+pub mod rv64d;
+pub mod rv64f;
 pub mod rv64i;
+pub mod rv64m;
+pub mod rv64priv;
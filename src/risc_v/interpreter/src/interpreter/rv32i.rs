@@ -1,4 +1,4 @@
-// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+// SPDX-FileCopyrightText: 2023-2024 TriliTech <contact@trili.tech>
 //
 // SPDX-License-Identifier: MIT
 
@@ -7,11 +7,13 @@
 //! Chapter 2 - Unprivileged spec
 
 use crate::machine_state::{
-    bus::Address,
+    bus::{main_memory::MainMemoryLayout, Address},
+    mode::Mode,
     registers::{XRegister, XRegisters},
-    HartState,
+    HartState, MachineState,
 };
 use crate::state_backend as backend;
+use crate::traps::Exception;
 
 impl<M> XRegisters<M>
 where
@@ -58,6 +60,97 @@ where
         let result = self.read(rs1) ^ (imm as u64);
         self.write(rd, result)
     }
+
+    /// `SLTI` I-type instruction
+    ///
+    /// Saves 1 in `rd` if `val(rs1) < imm` in signed comparison, 0 otherwise
+    pub fn run_slti(&mut self, imm: i64, rs1: XRegister, rd: XRegister) {
+        let result = (self.read(rs1) as i64) < imm;
+        self.write(rd, result as u64)
+    }
+
+    /// `SLTIU` I-type instruction
+    ///
+    /// Saves 1 in `rd` if `val(rs1) < imm` in unsigned comparison, 0 otherwise.
+    /// The immediate is sign-extended before being treated as unsigned.
+    pub fn run_sltiu(&mut self, imm: i64, rs1: XRegister, rd: XRegister) {
+        let result = self.read(rs1) < imm as u64;
+        self.write(rd, result as u64)
+    }
+
+    /// `ADD` R-type instruction
+    pub fn run_add(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        // Overflows are ignored, only the lower XLEN bits are kept
+        let result = self.read(rs1).wrapping_add(self.read(rs2));
+        self.write(rd, result)
+    }
+
+    /// `SUB` R-type instruction
+    pub fn run_sub(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        // Overflows are ignored, only the lower XLEN bits are kept
+        let result = self.read(rs1).wrapping_sub(self.read(rs2));
+        self.write(rd, result)
+    }
+
+    /// `XOR` R-type instruction
+    pub fn run_xor(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let result = self.read(rs1) ^ self.read(rs2);
+        self.write(rd, result)
+    }
+
+    /// `OR` R-type instruction
+    pub fn run_or(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let result = self.read(rs1) | self.read(rs2);
+        self.write(rd, result)
+    }
+
+    /// `AND` R-type instruction
+    pub fn run_and(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let result = self.read(rs1) & self.read(rs2);
+        self.write(rd, result)
+    }
+
+    /// `SLL` R-type instruction
+    ///
+    /// Shift left logically by the lower 6 bits of `val(rs2)`
+    pub fn run_sll(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let sh_amt = self.read(rs2) & 0b11_1111;
+        self.write(rd, self.read(rs1) << sh_amt)
+    }
+
+    /// `SRL` R-type instruction
+    ///
+    /// Shift right logically by the lower 6 bits of `val(rs2)`
+    pub fn run_srl(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let sh_amt = self.read(rs2) & 0b11_1111;
+        self.write(rd, self.read(rs1) >> sh_amt)
+    }
+
+    /// `SRA` R-type instruction
+    ///
+    /// Shift right arithmetically by the lower 6 bits of `val(rs2)`
+    pub fn run_sra(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let sh_amt = self.read(rs2) & 0b11_1111;
+        // Right shift on i64 is an arithmetic shift
+        let result = (self.read(rs1) as i64) >> sh_amt;
+        self.write(rd, result as u64)
+    }
+
+    /// `SLT` R-type instruction
+    ///
+    /// Saves 1 in `rd` if `val(rs1) < val(rs2)` in signed comparison, 0 otherwise
+    pub fn run_slt(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let result = (self.read(rs1) as i64) < (self.read(rs2) as i64);
+        self.write(rd, result as u64)
+    }
+
+    /// `SLTU` R-type instruction
+    ///
+    /// Saves 1 in `rd` if `val(rs1) < val(rs2)` in unsigned comparison, 0 otherwise
+    pub fn run_sltu(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let result = self.read(rs1) < self.read(rs2);
+        self.write(rd, result as u64)
+    }
 }
 
 impl<M> HartState<M>
@@ -72,7 +165,7 @@ where
     }
 
     /// Generic `JALR` w.r.t instruction width
-    fn run_jalr_impl<const INSTR_WIDTH: u64>(
+    pub(crate) fn run_jalr_impl<const INSTR_WIDTH: u64>(
         &mut self,
         imm: i64,
         rs1: XRegister,
@@ -101,7 +194,11 @@ where
     }
 
     /// Generic `JAL` w.r.t. instruction width
-    fn run_jal_impl<const INSTR_WIDTH: u64>(&mut self, imm: i64, rd: XRegister) -> Address {
+    pub(crate) fn run_jal_impl<const INSTR_WIDTH: u64>(
+        &mut self,
+        imm: i64,
+        rd: XRegister,
+    ) -> Address {
         let current_pc = self.pc.read();
 
         // Save the address after jump instruction into rd
@@ -121,7 +218,7 @@ where
     }
 
     /// Generic `BEQ` w.r.t. instruction width
-    fn run_beq_impl<const INSTR_WIDTH: u64>(
+    pub(crate) fn run_beq_impl<const INSTR_WIDTH: u64>(
         &mut self,
         imm: i64,
         rs1: XRegister,
@@ -147,7 +244,7 @@ where
     }
 
     /// Generic `BNE` w.r.t. instruction width
-    fn run_bne_impl<const INSTR_WIDTH: u64>(
+    pub(crate) fn run_bne_impl<const INSTR_WIDTH: u64>(
         &mut self,
         imm: i64,
         rs1: XRegister,
@@ -239,14 +336,103 @@ where
             current_pc.wrapping_add(4)
         }
     }
+
+    /// `ECALL` instruction
+    ///
+    /// Always returns the environment call exception matching the current mode
+    pub fn run_ecall(&self) -> Exception {
+        match self.mode.read() {
+            Mode::User => Exception::EnvCallFromUMode,
+            Mode::Supervisor => Exception::EnvCallFromSMode,
+            Mode::Machine | Mode::Debug => Exception::EnvCallFromMMode,
+        }
+    }
+}
+
+impl<ML, M> MachineState<ML, M>
+where
+    ML: MainMemoryLayout,
+    M: backend::Manager,
+{
+    /// `LB` I-type instruction
+    ///
+    /// Loads a byte from `val(rs1) + imm` and sign-extends it into `rd`
+    pub fn run_lb(&mut self, imm: i64, rs1: XRegister, rd: XRegister) -> Result<(), Exception> {
+        let value: i8 = self.read_from_bus(imm, rs1)?;
+        self.hart.xregisters.write(rd, value as u64);
+        Ok(())
+    }
+
+    /// `LH` I-type instruction
+    ///
+    /// Loads a half-word from `val(rs1) + imm` and sign-extends it into `rd`
+    pub fn run_lh(&mut self, imm: i64, rs1: XRegister, rd: XRegister) -> Result<(), Exception> {
+        let value: i16 = self.read_from_bus(imm, rs1)?;
+        self.hart.xregisters.write(rd, value as u64);
+        Ok(())
+    }
+
+    /// `LW` I-type instruction
+    ///
+    /// Loads a word from `val(rs1) + imm` and sign-extends it into `rd`
+    pub fn run_lw(&mut self, imm: i64, rs1: XRegister, rd: XRegister) -> Result<(), Exception> {
+        let value: i32 = self.read_from_bus(imm, rs1)?;
+        self.hart.xregisters.write(rd, value as u64);
+        Ok(())
+    }
+
+    /// `LBU` I-type instruction
+    ///
+    /// Loads a byte from `val(rs1) + imm` and zero-extends it into `rd`
+    pub fn run_lbu(&mut self, imm: i64, rs1: XRegister, rd: XRegister) -> Result<(), Exception> {
+        let value: u8 = self.read_from_bus(imm, rs1)?;
+        self.hart.xregisters.write(rd, value as u64);
+        Ok(())
+    }
+
+    /// `LHU` I-type instruction
+    ///
+    /// Loads a half-word from `val(rs1) + imm` and zero-extends it into `rd`
+    pub fn run_lhu(&mut self, imm: i64, rs1: XRegister, rd: XRegister) -> Result<(), Exception> {
+        let value: u16 = self.read_from_bus(imm, rs1)?;
+        self.hart.xregisters.write(rd, value as u64);
+        Ok(())
+    }
+
+    /// `SB` S-type instruction
+    ///
+    /// Stores the lowest byte of `val(rs2)` at `val(rs1) + imm`
+    pub fn run_sb(&mut self, imm: i64, rs1: XRegister, rs2: XRegister) -> Result<(), Exception> {
+        let value = self.hart.xregisters.read(rs2) as u8;
+        self.write_to_bus(imm, rs1, value)
+    }
+
+    /// `SH` S-type instruction
+    ///
+    /// Stores the lowest half-word of `val(rs2)` at `val(rs1) + imm`
+    pub fn run_sh(&mut self, imm: i64, rs1: XRegister, rs2: XRegister) -> Result<(), Exception> {
+        let value = self.hart.xregisters.read(rs2) as u16;
+        self.write_to_bus(imm, rs1, value)
+    }
+
+    /// `SW` S-type instruction
+    ///
+    /// Stores the lowest word of `val(rs2)` at `val(rs1) + imm`
+    pub fn run_sw(&mut self, imm: i64, rs1: XRegister, rs2: XRegister) -> Result<(), Exception> {
+        let value = self.hart.xregisters.read(rs2) as u32;
+        self.write_to_bus(imm, rs1, value)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::machine_state::{
+        bus::{devices::DEVICES_ADDRESS_SPACE_LENGTH, main_memory::tests::T1K},
         registers::{a0, a1, a2, a3, a4, t1, t2, t3, t4, t5, t6, XRegisters, XRegistersLayout},
-        HartState, HartStateLayout,
+        HartState, HartStateLayout, MachineState, MachineStateLayout,
     };
+    use crate::state_backend::{tests::ManagerFor, Backend, Layout};
+    use crate::traps::Exception;
     use crate::{backend_test, create_backend, create_state};
     use proptest::{prelude::any, prop_assert_eq, prop_assume, proptest};
 
@@ -579,4 +765,108 @@ mod tests {
             prop_assert_eq!(xregs.read(a4), 0);
         });
     });
+
+    backend_test!(test_r_type, F, {
+        proptest!(|(v1 in any::<u64>(), v2 in any::<u64>())| {
+            let mut backend = create_backend!(XRegistersLayout, F);
+            let mut xregs = create_state!(XRegisters, F, backend);
+            xregs.write(a0, v1);
+            xregs.write(a1, v2);
+
+            xregs.run_add(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), v1.wrapping_add(v2));
+            xregs.run_sub(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), v1.wrapping_sub(v2));
+            xregs.run_xor(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), v1 ^ v2);
+            xregs.run_or(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), v1 | v2);
+            xregs.run_and(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), v1 & v2);
+
+            // Only the lower 6 bits of rs2 are the shift amount
+            xregs.run_sll(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), v1 << (v2 & 0x3F));
+            xregs.run_srl(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), v1 >> (v2 & 0x3F));
+            xregs.run_sra(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), ((v1 as i64) >> (v2 & 0x3F)) as u64);
+
+            xregs.run_slt(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), ((v1 as i64) < (v2 as i64)) as u64);
+            xregs.run_sltu(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), (v1 < v2) as u64);
+        });
+    });
+
+    backend_test!(test_slti, F, {
+        let imm_rs1_slti_sltiu = [
+            (0_i64, 0_u64, 0_u64, 0_u64),
+            (1, 0, 1, 1),
+            (0, -1_i64 as u64, 1, 0),
+            // The immediate is sign-extended, then compared as unsigned
+            (-1, 0xFFFF, 0, 1),
+            (-2048, -2049_i64 as u64, 1, 1),
+        ];
+
+        for (imm, rs1, slti, sltiu) in imm_rs1_slti_sltiu {
+            let mut backend = create_backend!(XRegistersLayout, F);
+            let mut xregs = create_state!(XRegisters, F, backend);
+            xregs.write(a0, rs1);
+
+            xregs.run_slti(imm, a0, a1);
+            assert_eq!(xregs.read(a1), slti);
+            xregs.run_sltiu(imm, a0, a1);
+            assert_eq!(xregs.read(a1), sltiu);
+        }
+    });
+
+    backend_test!(test_load_store, F, {
+        proptest!(|(value in any::<u64>(), offset in 0_i64..1016)| {
+            let mut backend = create_backend!(MachineStateLayout<T1K>, F);
+            let mut state: MachineState<T1K, ManagerFor<'_, F, MachineStateLayout<T1K>>> =
+                MachineState::bind(backend.allocate(MachineStateLayout::<T1K>::placed().into_location()));
+            state.hart.xregisters.write(t1, DEVICES_ADDRESS_SPACE_LENGTH);
+            state.hart.xregisters.write(a0, value);
+
+            prop_assert_eq!(state.run_sd(offset, t1, a0), Ok(()));
+            prop_assert_eq!(state.run_ld(offset, t1, a1), Ok(()));
+            prop_assert_eq!(state.hart.xregisters.read(a1), value);
+
+            // Narrow loads sign- or zero-extend the lowest bytes
+            prop_assert_eq!(state.run_lb(offset, t1, a1), Ok(()));
+            prop_assert_eq!(state.hart.xregisters.read(a1), value as i8 as u64);
+            prop_assert_eq!(state.run_lbu(offset, t1, a1), Ok(()));
+            prop_assert_eq!(state.hart.xregisters.read(a1), value as u8 as u64);
+            prop_assert_eq!(state.run_lh(offset, t1, a1), Ok(()));
+            prop_assert_eq!(state.hart.xregisters.read(a1), value as i16 as u64);
+            prop_assert_eq!(state.run_lhu(offset, t1, a1), Ok(()));
+            prop_assert_eq!(state.hart.xregisters.read(a1), value as u16 as u64);
+            prop_assert_eq!(state.run_lw(offset, t1, a1), Ok(()));
+            prop_assert_eq!(state.hart.xregisters.read(a1), value as i32 as u64);
+            prop_assert_eq!(state.run_lwu(offset, t1, a1), Ok(()));
+            prop_assert_eq!(state.hart.xregisters.read(a1), value as u32 as u64);
+
+            // Narrow stores only overwrite the lowest bytes
+            state.hart.xregisters.write(a2, u64::MAX);
+            prop_assert_eq!(state.run_sb(offset, t1, a2), Ok(()));
+            prop_assert_eq!(state.run_ld(offset, t1, a1), Ok(()));
+            prop_assert_eq!(state.hart.xregisters.read(a1), value | 0xFF);
+            prop_assert_eq!(state.run_sh(offset, t1, a2), Ok(()));
+            prop_assert_eq!(state.run_sw(offset, t1, a2), Ok(()));
+            prop_assert_eq!(state.run_ld(offset, t1, a1), Ok(()));
+            prop_assert_eq!(state.hart.xregisters.read(a1), value | 0xFFFF_FFFF);
+
+            // Accesses outside of the main memory fault
+            let address = DEVICES_ADDRESS_SPACE_LENGTH + 1024;
+            prop_assert_eq!(
+                state.run_lw(1024, t1, a1),
+                Err(Exception::LoadAccessFault(address))
+            );
+            prop_assert_eq!(
+                state.run_sb(1024, t1, a1),
+                Err(Exception::StoreAccessFault(address))
+            );
+        });
+    });
 }
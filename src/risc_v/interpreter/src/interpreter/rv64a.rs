@@ -0,0 +1,505 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Implementation of RV_64_A extension for RISC-V
+//!
+//! The machine has a single hart, so the `aq` and `rl` ordering bits have no
+//! effect and the reservation set of `LR`/`SC` is only ever invalidated by
+//! the hart itself.
+//!
+//! Chapter 8 - Unprivileged spec
+
+use crate::machine_state::{
+    address_translation::AccessType,
+    bus::{main_memory::MainMemoryLayout, Addressable},
+    registers::XRegister,
+    MachineState, NO_RESERVATION,
+};
+use crate::state_backend as backend;
+use crate::traps::Exception;
+
+impl<ML, M> MachineState<ML, M>
+where
+    ML: MainMemoryLayout,
+    M: backend::Manager,
+{
+    /// Generic `AMO*.W` instruction
+    ///
+    /// Atomically loads the word at `val(rs1)` into `rd`, sign-extended,
+    /// and stores `f(loaded, val(rs2))` in its place.
+    fn run_amo_w(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+        f: impl FnOnce(u32, u32) -> u32,
+    ) -> Result<(), Exception> {
+        let address = self.hart.xregisters.read(rs1);
+
+        // Misaligned AMOs are not emulated, they raise access faults
+        if address % 4 != 0 {
+            return Err(Exception::StoreAccessFault(address));
+        }
+
+        // Both the load and the store are covered by the store permissions
+        let phys_addr = self.translate(address, AccessType::Store)?;
+
+        let value: u32 = self
+            .bus
+            .read(phys_addr)
+            .map_err(|_| Exception::StoreAccessFault(address))?;
+        let operand = self.hart.xregisters.read(rs2) as u32;
+
+        self.bus
+            .write(phys_addr, f(value, operand))
+            .map_err(|_| Exception::StoreAccessFault(address))?;
+
+        // Note: u32 as i32 as u64 will sign-extend the lowest 32 bits
+        self.hart.xregisters.write(rd, value as i32 as u64);
+        Ok(())
+    }
+
+    /// Generic `AMO*.D` instruction
+    ///
+    /// Atomically loads the double-word at `val(rs1)` into `rd` and stores
+    /// `f(loaded, val(rs2))` in its place.
+    fn run_amo_d(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+        f: impl FnOnce(u64, u64) -> u64,
+    ) -> Result<(), Exception> {
+        let address = self.hart.xregisters.read(rs1);
+
+        // Misaligned AMOs are not emulated, they raise access faults
+        if address % 8 != 0 {
+            return Err(Exception::StoreAccessFault(address));
+        }
+
+        // Both the load and the store are covered by the store permissions
+        let phys_addr = self.translate(address, AccessType::Store)?;
+
+        let value: u64 = self
+            .bus
+            .read(phys_addr)
+            .map_err(|_| Exception::StoreAccessFault(address))?;
+        let operand = self.hart.xregisters.read(rs2);
+
+        self.bus
+            .write(phys_addr, f(value, operand))
+            .map_err(|_| Exception::StoreAccessFault(address))?;
+
+        self.hart.xregisters.write(rd, value);
+        Ok(())
+    }
+
+    /// `LR.W` R-type instruction
+    ///
+    /// Loads the word at `val(rs1)` into `rd`, sign-extended, and registers
+    /// a reservation on its address.
+    pub fn run_lrw(&mut self, rs1: XRegister, rd: XRegister) -> Result<(), Exception> {
+        let address = self.hart.xregisters.read(rs1);
+
+        if address % 4 != 0 {
+            return Err(Exception::LoadAccessFault(address));
+        }
+
+        let value: i32 = self.read_virtual(address, AccessType::Load)?;
+
+        self.hart.reservation_set.write(address);
+        self.hart.xregisters.write(rd, value as u64);
+        Ok(())
+    }
+
+    /// `LR.D` R-type instruction
+    ///
+    /// Loads the double-word at `val(rs1)` into `rd` and registers a
+    /// reservation on its address.
+    pub fn run_lrd(&mut self, rs1: XRegister, rd: XRegister) -> Result<(), Exception> {
+        let address = self.hart.xregisters.read(rs1);
+
+        if address % 8 != 0 {
+            return Err(Exception::LoadAccessFault(address));
+        }
+
+        let value: u64 = self.read_virtual(address, AccessType::Load)?;
+
+        self.hart.reservation_set.write(address);
+        self.hart.xregisters.write(rd, value);
+        Ok(())
+    }
+
+    /// `SC.W` R-type instruction
+    ///
+    /// Stores the lowest word of `val(rs2)` at `val(rs1)` if that address is
+    /// reserved, writing 0 to `rd` on success and 1 on failure. The
+    /// reservation is invalidated in both cases.
+    pub fn run_scw(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        let address = self.hart.xregisters.read(rs1);
+
+        if address % 4 != 0 {
+            return Err(Exception::StoreAccessFault(address));
+        }
+
+        let reserved = self.hart.reservation_set.read() == address;
+        self.hart.reservation_set.write(NO_RESERVATION);
+
+        if reserved {
+            let value = self.hart.xregisters.read(rs2) as u32;
+            self.write_virtual(address, value)?;
+        }
+
+        self.hart.xregisters.write(rd, !reserved as u64);
+        Ok(())
+    }
+
+    /// `SC.D` R-type instruction
+    ///
+    /// Stores `val(rs2)` at `val(rs1)` if that address is reserved, writing 0
+    /// to `rd` on success and 1 on failure. The reservation is invalidated in
+    /// both cases.
+    pub fn run_scd(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        let address = self.hart.xregisters.read(rs1);
+
+        if address % 8 != 0 {
+            return Err(Exception::StoreAccessFault(address));
+        }
+
+        let reserved = self.hart.reservation_set.read() == address;
+        self.hart.reservation_set.write(NO_RESERVATION);
+
+        if reserved {
+            let value = self.hart.xregisters.read(rs2);
+            self.write_virtual(address, value)?;
+        }
+
+        self.hart.xregisters.write(rd, !reserved as u64);
+        Ok(())
+    }
+
+    /// `AMOSWAP.W` R-type instruction
+    pub fn run_amoswapw(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_amo_w(rs1, rs2, rd, |_, operand| operand)
+    }
+
+    /// `AMOADD.W` R-type instruction
+    pub fn run_amoaddw(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_amo_w(rs1, rs2, rd, u32::wrapping_add)
+    }
+
+    /// `AMOXOR.W` R-type instruction
+    pub fn run_amoxorw(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_amo_w(rs1, rs2, rd, |value, operand| value ^ operand)
+    }
+
+    /// `AMOAND.W` R-type instruction
+    pub fn run_amoandw(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_amo_w(rs1, rs2, rd, |value, operand| value & operand)
+    }
+
+    /// `AMOOR.W` R-type instruction
+    pub fn run_amoorw(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_amo_w(rs1, rs2, rd, |value, operand| value | operand)
+    }
+
+    /// `AMOMIN.W` R-type instruction
+    pub fn run_amominw(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_amo_w(rs1, rs2, rd, |value, operand| {
+            (value as i32).min(operand as i32) as u32
+        })
+    }
+
+    /// `AMOMAX.W` R-type instruction
+    pub fn run_amomaxw(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_amo_w(rs1, rs2, rd, |value, operand| {
+            (value as i32).max(operand as i32) as u32
+        })
+    }
+
+    /// `AMOMINU.W` R-type instruction
+    pub fn run_amominuw(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_amo_w(rs1, rs2, rd, u32::min)
+    }
+
+    /// `AMOMAXU.W` R-type instruction
+    pub fn run_amomaxuw(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_amo_w(rs1, rs2, rd, u32::max)
+    }
+
+    /// `AMOSWAP.D` R-type instruction
+    pub fn run_amoswapd(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_amo_d(rs1, rs2, rd, |_, operand| operand)
+    }
+
+    /// `AMOADD.D` R-type instruction
+    pub fn run_amoaddd(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_amo_d(rs1, rs2, rd, u64::wrapping_add)
+    }
+
+    /// `AMOXOR.D` R-type instruction
+    pub fn run_amoxord(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_amo_d(rs1, rs2, rd, |value, operand| value ^ operand)
+    }
+
+    /// `AMOAND.D` R-type instruction
+    pub fn run_amoandd(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_amo_d(rs1, rs2, rd, |value, operand| value & operand)
+    }
+
+    /// `AMOOR.D` R-type instruction
+    pub fn run_amoord(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_amo_d(rs1, rs2, rd, |value, operand| value | operand)
+    }
+
+    /// `AMOMIN.D` R-type instruction
+    pub fn run_amomind(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_amo_d(rs1, rs2, rd, |value, operand| {
+            (value as i64).min(operand as i64) as u64
+        })
+    }
+
+    /// `AMOMAX.D` R-type instruction
+    pub fn run_amomaxd(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_amo_d(rs1, rs2, rd, |value, operand| {
+            (value as i64).max(operand as i64) as u64
+        })
+    }
+
+    /// `AMOMINU.D` R-type instruction
+    pub fn run_amominud(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_amo_d(rs1, rs2, rd, u64::min)
+    }
+
+    /// `AMOMAXU.D` R-type instruction
+    pub fn run_amomaxud(
+        &mut self,
+        rs1: XRegister,
+        rs2: XRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_amo_d(rs1, rs2, rd, u64::max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend_test, create_backend,
+        machine_state::{
+            bus::{devices::DEVICES_ADDRESS_SPACE_LENGTH, main_memory::tests::T1K, Addressable},
+            registers::{a0, a1, a2, t0},
+            MachineState, MachineStateLayout,
+        },
+        state_backend::{tests::ManagerFor, Backend, Layout},
+        traps::Exception,
+    };
+    use proptest::{arbitrary::any, prop_assert_eq, proptest};
+
+    macro_rules! test_amo {
+        ($state:ident, $amo_fn:tt, $ty:ty, $value:expr, $operand:expr, $expected:expr) => {
+            $state
+                .bus
+                .write(DEVICES_ADDRESS_SPACE_LENGTH, $value)
+                .unwrap();
+            $state.hart.xregisters.write(a1, $operand as u64);
+
+            prop_assert_eq!($state.$amo_fn(t0, a1, a2), Ok(()));
+            // The previous value is loaded, sign-extended
+            prop_assert_eq!($state.hart.xregisters.read(a2), $value as $ty as u64);
+            let stored: $ty = $state.bus.read(DEVICES_ADDRESS_SPACE_LENGTH).unwrap();
+            prop_assert_eq!(stored, $expected);
+        };
+    }
+
+    backend_test!(test_amo_w, F, {
+        proptest!(|(value in any::<i32>(), operand in any::<i32>())| {
+            let mut backend = create_backend!(MachineStateLayout<T1K>, F);
+            let mut state: MachineState<T1K, ManagerFor<'_, F, MachineStateLayout<T1K>>> =
+                MachineState::bind(backend.allocate(MachineStateLayout::<T1K>::placed().into_location()));
+            state.hart.xregisters.write(t0, DEVICES_ADDRESS_SPACE_LENGTH);
+
+            test_amo!(state, run_amoswapw, i32, value, operand, operand);
+            test_amo!(state, run_amoaddw, i32, value, operand, value.wrapping_add(operand));
+            test_amo!(state, run_amoxorw, i32, value, operand, value ^ operand);
+            test_amo!(state, run_amoandw, i32, value, operand, value & operand);
+            test_amo!(state, run_amoorw, i32, value, operand, value | operand);
+            test_amo!(state, run_amominw, i32, value, operand, value.min(operand));
+            test_amo!(state, run_amomaxw, i32, value, operand, value.max(operand));
+            test_amo!(
+                state, run_amominuw, i32, value, operand,
+                (value as u32).min(operand as u32) as i32
+            );
+            test_amo!(
+                state, run_amomaxuw, i32, value, operand,
+                (value as u32).max(operand as u32) as i32
+            );
+        });
+    });
+
+    backend_test!(test_amo_d, F, {
+        proptest!(|(value in any::<i64>(), operand in any::<i64>())| {
+            let mut backend = create_backend!(MachineStateLayout<T1K>, F);
+            let mut state: MachineState<T1K, ManagerFor<'_, F, MachineStateLayout<T1K>>> =
+                MachineState::bind(backend.allocate(MachineStateLayout::<T1K>::placed().into_location()));
+            state.hart.xregisters.write(t0, DEVICES_ADDRESS_SPACE_LENGTH);
+
+            test_amo!(state, run_amoswapd, i64, value, operand, operand);
+            test_amo!(state, run_amoaddd, i64, value, operand, value.wrapping_add(operand));
+            test_amo!(state, run_amoxord, i64, value, operand, value ^ operand);
+            test_amo!(state, run_amoandd, i64, value, operand, value & operand);
+            test_amo!(state, run_amoord, i64, value, operand, value | operand);
+            test_amo!(state, run_amomind, i64, value, operand, value.min(operand));
+            test_amo!(state, run_amomaxd, i64, value, operand, value.max(operand));
+            test_amo!(
+                state, run_amominud, i64, value, operand,
+                (value as u64).min(operand as u64) as i64
+            );
+            test_amo!(
+                state, run_amomaxud, i64, value, operand,
+                (value as u64).max(operand as u64) as i64
+            );
+        });
+    });
+
+    backend_test!(test_lr_sc, F, {
+        proptest!(|(first in any::<u64>(), second in any::<u64>())| {
+            let mut backend = create_backend!(MachineStateLayout<T1K>, F);
+            let mut state: MachineState<T1K, ManagerFor<'_, F, MachineStateLayout<T1K>>> =
+                MachineState::bind(backend.allocate(MachineStateLayout::<T1K>::placed().into_location()));
+            state.reset(crate::machine_state::mode::Mode::User, 0);
+            state.hart.xregisters.write(t0, DEVICES_ADDRESS_SPACE_LENGTH);
+            state.hart.xregisters.write(a0, first);
+            state.hart.xregisters.write(a1, second);
+
+            // SC without a reservation fails
+            prop_assert_eq!(state.run_scd(t0, a0, a2), Ok(()));
+            prop_assert_eq!(state.hart.xregisters.read(a2), 1);
+            prop_assert_eq!(Addressable::<u64>::read(&state.bus, DEVICES_ADDRESS_SPACE_LENGTH), Ok(0));
+
+            // SC after LR on the same address succeeds
+            prop_assert_eq!(state.run_lrd(t0, a2), Ok(()));
+            prop_assert_eq!(state.run_scd(t0, a0, a2), Ok(()));
+            prop_assert_eq!(state.hart.xregisters.read(a2), 0);
+            prop_assert_eq!(state.bus.read(DEVICES_ADDRESS_SPACE_LENGTH), Ok(first));
+
+            // The reservation is consumed by SC
+            prop_assert_eq!(state.run_scd(t0, a1, a2), Ok(()));
+            prop_assert_eq!(state.hart.xregisters.read(a2), 1);
+            prop_assert_eq!(state.bus.read(DEVICES_ADDRESS_SPACE_LENGTH), Ok(first));
+
+            // LR.W loads a sign-extended word
+            prop_assert_eq!(state.run_lrw(t0, a2), Ok(()));
+            prop_assert_eq!(state.hart.xregisters.read(a2), first as i32 as u64);
+            prop_assert_eq!(state.run_scw(t0, a1, a2), Ok(()));
+            prop_assert_eq!(state.hart.xregisters.read(a2), 0);
+            let stored: u32 = state.bus.read(DEVICES_ADDRESS_SPACE_LENGTH).unwrap();
+            prop_assert_eq!(stored, second as u32);
+
+            // Misaligned accesses fault
+            let address = DEVICES_ADDRESS_SPACE_LENGTH + 4;
+            state.hart.xregisters.write(t0, address);
+            prop_assert_eq!(state.run_lrd(t0, a2), Err(Exception::LoadAccessFault(address)));
+            prop_assert_eq!(
+                state.run_amoaddd(t0, a1, a2),
+                Err(Exception::StoreAccessFault(address))
+            );
+        });
+    });
+}
@@ -0,0 +1,121 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Implementation of RV_64_C extension for RISC-V
+//!
+//! Most compressed instructions expand to an uncompressed instruction and
+//! share its implementation. Only the control transfer instructions depend
+//! on the width of the instruction, they are implemented here.
+//!
+//! Chapter 16 - Unprivileged spec
+
+use crate::machine_state::{
+    bus::Address,
+    registers::{ra, x0, XRegister},
+    HartState,
+};
+use crate::state_backend as backend;
+
+impl<M> HartState<M>
+where
+    M: backend::Manager,
+{
+    /// `C.J` CJ-type compressed instruction
+    ///
+    /// Always returns the target address (current program counter + imm)
+    pub fn run_cj(&mut self, imm: i64) -> Address {
+        self.run_jal_impl::<2>(imm, x0)
+    }
+
+    /// `C.JR` CR-type compressed instruction
+    ///
+    /// Always returns the target address (val(rs1))
+    pub fn run_cjr(&mut self, rs1: XRegister) -> Address {
+        self.run_jalr_impl::<2>(0, rs1, x0)
+    }
+
+    /// `C.JALR` CR-type compressed instruction
+    ///
+    /// Saves the address of the next instruction in `ra`, and returns the
+    /// target address (val(rs1))
+    pub fn run_cjalr(&mut self, rs1: XRegister) -> Address {
+        self.run_jalr_impl::<2>(0, rs1, ra)
+    }
+
+    /// `C.BEQZ` CB-type compressed instruction
+    ///
+    /// Returns the target address if `val(rs1)` is zero,
+    /// otherwise the next instruction address
+    pub fn run_cbeqz(&mut self, imm: i64, rs1: XRegister) -> Address {
+        self.run_beq_impl::<2>(imm, rs1, x0)
+    }
+
+    /// `C.BNEZ` CB-type compressed instruction
+    ///
+    /// Returns the target address if `val(rs1)` is not zero,
+    /// otherwise the next instruction address
+    pub fn run_cbnez(&mut self, imm: i64, rs1: XRegister) -> Address {
+        self.run_bne_impl::<2>(imm, rs1, x0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend_test, create_backend, create_state,
+        machine_state::{
+            registers::{a0, ra},
+            HartState, HartStateLayout,
+        },
+    };
+    use proptest::{arbitrary::any, prop_assert_eq, proptest};
+
+    backend_test!(test_cj_cjr_cjalr, F, {
+        proptest!(|(
+            init_pc in any::<u64>(),
+            imm in any::<i64>(),
+            target in any::<u64>(),
+        )| {
+            let mut backend = create_backend!(HartStateLayout, F);
+            let mut state = create_state!(HartState, F, backend);
+            state.pc.write(init_pc);
+            state.xregisters.write(a0, target);
+            state.xregisters.write(ra, 0);
+
+            prop_assert_eq!(state.run_cj(imm), init_pc.wrapping_add(imm as u64));
+            prop_assert_eq!(state.xregisters.read(ra), 0);
+
+            // The least-significant bit of the target is cleared
+            prop_assert_eq!(state.run_cjr(a0), target & !1);
+            prop_assert_eq!(state.xregisters.read(ra), 0);
+
+            // The return address is 2 bytes ahead
+            prop_assert_eq!(state.run_cjalr(a0), target & !1);
+            prop_assert_eq!(state.xregisters.read(ra), init_pc.wrapping_add(2));
+        });
+    });
+
+    backend_test!(test_cbeqz_cbnez, F, {
+        proptest!(|(
+            init_pc in any::<u64>(),
+            imm in any::<i64>(),
+            value in 1_u64..,
+        )| {
+            let mut backend = create_backend!(HartStateLayout, F);
+            let mut state = create_state!(HartState, F, backend);
+            state.pc.write(init_pc);
+
+            let branch_pc = init_pc.wrapping_add(imm as u64);
+            let next_pc = init_pc.wrapping_add(2);
+
+            state.xregisters.write(a0, 0);
+            prop_assert_eq!(state.run_cbeqz(imm, a0), branch_pc);
+            prop_assert_eq!(state.run_cbnez(imm, a0), next_pc);
+
+            state.xregisters.write(a0, value);
+            prop_assert_eq!(state.run_cbeqz(imm, a0), next_pc);
+            prop_assert_eq!(state.run_cbnez(imm, a0), branch_pc);
+        });
+    });
+}
@@ -0,0 +1,501 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Implementation of RV_64_D extension for RISC-V
+//!
+//! The instructions share their implementation with the single-precision
+//! ones of [super::rv64f], on 64-bit values which need no NaN-boxing.
+//!
+//! Chapter 12 - Unprivileged spec
+
+use crate::machine_state::{
+    bus::main_memory::MainMemoryLayout,
+    registers::{FRegister, XRegister},
+    HartState, MachineState,
+};
+use crate::parser::instruction::InstrRoundingMode;
+use crate::softfloat::{self, F32, F64};
+use crate::state_backend as backend;
+use crate::traps::Exception;
+
+impl<M> HartState<M>
+where
+    M: backend::Manager,
+{
+    /// `FADD.D` R-type instruction
+    pub fn run_faddd(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fbinop_impl::<F64>(rs1, rs2, rm, rd, softfloat::add::<F64>)
+    }
+
+    /// `FSUB.D` R-type instruction
+    pub fn run_fsubd(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fbinop_impl::<F64>(rs1, rs2, rm, rd, softfloat::sub::<F64>)
+    }
+
+    /// `FMUL.D` R-type instruction
+    pub fn run_fmuld(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fbinop_impl::<F64>(rs1, rs2, rm, rd, softfloat::mul::<F64>)
+    }
+
+    /// `FDIV.D` R-type instruction
+    pub fn run_fdivd(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fbinop_impl::<F64>(rs1, rs2, rm, rd, softfloat::div::<F64>)
+    }
+
+    /// `FSQRT.D` R-type instruction
+    pub fn run_fsqrtd(
+        &mut self,
+        rs1: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fsqrt_impl::<F64>(rs1, rm, rd)
+    }
+
+    /// `FMIN.D` R-type instruction
+    pub fn run_fmind(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fminmax_impl::<F64>(rs1, rs2, rd, softfloat::min::<F64>)
+    }
+
+    /// `FMAX.D` R-type instruction
+    pub fn run_fmaxd(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fminmax_impl::<F64>(rs1, rs2, rd, softfloat::max::<F64>)
+    }
+
+    /// `FMADD.D` R4-type instruction
+    pub fn run_fmaddd(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rs3: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fmadd_impl::<F64>(rs1, rs2, rs3, rm, rd, false, false)
+    }
+
+    /// `FMSUB.D` R4-type instruction
+    pub fn run_fmsubd(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rs3: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fmadd_impl::<F64>(rs1, rs2, rs3, rm, rd, false, true)
+    }
+
+    /// `FNMSUB.D` R4-type instruction
+    pub fn run_fnmsubd(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rs3: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fmadd_impl::<F64>(rs1, rs2, rs3, rm, rd, true, false)
+    }
+
+    /// `FNMADD.D` R4-type instruction
+    pub fn run_fnmaddd(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rs3: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fmadd_impl::<F64>(rs1, rs2, rs3, rm, rd, true, true)
+    }
+
+    /// `FSGNJ.D` R-type instruction
+    pub fn run_fsgnjd(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fsgnj_impl::<F64>(rs1, rs2, rd, |_, b| b)
+    }
+
+    /// `FSGNJN.D` R-type instruction
+    pub fn run_fsgnjnd(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fsgnj_impl::<F64>(rs1, rs2, rd, |_, b| !b)
+    }
+
+    /// `FSGNJX.D` R-type instruction
+    pub fn run_fsgnjxd(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fsgnj_impl::<F64>(rs1, rs2, rd, |a, b| a ^ b)
+    }
+
+    /// `FEQ.D` R-type instruction
+    pub fn run_feqd(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcmp_impl::<F64>(rs1, rs2, rd, softfloat::eq::<F64>)
+    }
+
+    /// `FLT.D` R-type instruction
+    pub fn run_fltd(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcmp_impl::<F64>(rs1, rs2, rd, softfloat::lt::<F64>)
+    }
+
+    /// `FLE.D` R-type instruction
+    pub fn run_fled(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcmp_impl::<F64>(rs1, rs2, rd, softfloat::le::<F64>)
+    }
+
+    /// `FCLASS.D` R-type instruction
+    pub fn run_fclassd(&mut self, rs1: FRegister, rd: XRegister) -> Result<(), Exception> {
+        self.run_fclass_impl::<F64>(rs1, rd)
+    }
+
+    /// `FCVT.W.D` R-type instruction
+    pub fn run_fcvtwd(
+        &mut self,
+        rs1: FRegister,
+        rm: InstrRoundingMode,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcvt_to_int_impl::<F64>(rs1, rm, rd, true, 32)
+    }
+
+    /// `FCVT.WU.D` R-type instruction
+    pub fn run_fcvtwud(
+        &mut self,
+        rs1: FRegister,
+        rm: InstrRoundingMode,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcvt_to_int_impl::<F64>(rs1, rm, rd, false, 32)
+    }
+
+    /// `FCVT.L.D` R-type instruction
+    pub fn run_fcvtld(
+        &mut self,
+        rs1: FRegister,
+        rm: InstrRoundingMode,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcvt_to_int_impl::<F64>(rs1, rm, rd, true, 64)
+    }
+
+    /// `FCVT.LU.D` R-type instruction
+    pub fn run_fcvtlud(
+        &mut self,
+        rs1: FRegister,
+        rm: InstrRoundingMode,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcvt_to_int_impl::<F64>(rs1, rm, rd, false, 64)
+    }
+
+    /// `FCVT.D.W` R-type instruction
+    pub fn run_fcvtdw(
+        &mut self,
+        rs1: XRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcvt_from_int_impl::<F64>(rs1, rm, rd, true, 32)
+    }
+
+    /// `FCVT.D.WU` R-type instruction
+    pub fn run_fcvtdwu(
+        &mut self,
+        rs1: XRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcvt_from_int_impl::<F64>(rs1, rm, rd, false, 32)
+    }
+
+    /// `FCVT.D.L` R-type instruction
+    pub fn run_fcvtdl(
+        &mut self,
+        rs1: XRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcvt_from_int_impl::<F64>(rs1, rm, rd, true, 64)
+    }
+
+    /// `FCVT.D.LU` R-type instruction
+    pub fn run_fcvtdlu(
+        &mut self,
+        rs1: XRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcvt_from_int_impl::<F64>(rs1, rm, rd, false, 64)
+    }
+
+    /// `FCVT.S.D` R-type instruction
+    ///
+    /// Rounds a double-precision value to single precision
+    pub fn run_fcvtsd(
+        &mut self,
+        rs1: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcvt_float_impl::<F64, F32>(rs1, rm, rd)
+    }
+
+    /// `FCVT.D.S` R-type instruction
+    ///
+    /// Widens a single-precision value to double precision, always exactly
+    pub fn run_fcvtds(
+        &mut self,
+        rs1: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcvt_float_impl::<F32, F64>(rs1, rm, rd)
+    }
+
+    /// `FMV.X.D` R-type instruction
+    ///
+    /// Moves the bits of `rs1` to `rd`
+    pub fn run_fmvxd(&mut self, rs1: FRegister, rd: XRegister) -> Result<(), Exception> {
+        self.check_fs_on()?;
+
+        let value = u64::from(self.fregisters.read(rs1));
+        self.xregisters.write(rd, value);
+        Ok(())
+    }
+
+    /// `FMV.D.X` R-type instruction
+    ///
+    /// Moves the bits of `rs1` to `rd`
+    pub fn run_fmvdx(&mut self, rs1: XRegister, rd: FRegister) -> Result<(), Exception> {
+        self.check_fs_on()?;
+
+        let value = self.xregisters.read(rs1);
+        self.write_float::<F64>(rd, value);
+        Ok(())
+    }
+}
+
+impl<ML, M> MachineState<ML, M>
+where
+    ML: MainMemoryLayout,
+    M: backend::Manager,
+{
+    /// `FLD` I-type instruction
+    ///
+    /// Loads the double word at `val(rs1) + imm` into `rd`
+    pub fn run_fld(&mut self, imm: i64, rs1: XRegister, rd: FRegister) -> Result<(), Exception> {
+        self.hart.check_fs_on()?;
+
+        let value: u64 = self.read_from_bus(imm, rs1)?;
+        self.hart.write_float::<F64>(rd, value);
+        Ok(())
+    }
+
+    /// `FSD` S-type instruction
+    ///
+    /// Stores the bits of `rs2` at `val(rs1) + imm`
+    pub fn run_fsd(&mut self, imm: i64, rs1: XRegister, rs2: FRegister) -> Result<(), Exception> {
+        self.hart.check_fs_on()?;
+
+        let value = u64::from(self.hart.fregisters.read(rs2));
+        self.write_to_bus(imm, rs1, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend_test, create_backend, create_state,
+        machine_state::{
+            bus::{devices::DEVICES_ADDRESS_SPACE_LENGTH, main_memory::tests::T1K, Addressable},
+            csregisters::CSRegister,
+            mode::Mode,
+            registers::{a0, a1, fa0, fa1, fa2, fa3},
+            HartState, HartStateLayout, MachineState, MachineStateLayout,
+        },
+        parser::instruction::InstrRoundingMode,
+        softfloat::{Format, RoundingMode, F32, F64, FLAG_NV, FLAG_NX, FLAG_OF},
+        state_backend::{tests::ManagerFor, Backend, Layout},
+    };
+    use proptest::{arbitrary::any, prop_assert_eq, proptest};
+
+    const RNE: InstrRoundingMode = InstrRoundingMode::Static(RoundingMode::RNE);
+
+    backend_test!(test_arith_d, F, {
+        proptest!(|(v1 in any::<f64>(), v2 in any::<f64>(), v3 in any::<f64>())| {
+            let mut backend = create_backend!(HartStateLayout, F);
+            let mut state = create_state!(HartState, F, backend);
+            state.reset(Mode::Machine, 0);
+            state.write_float::<F64>(fa0, v1.to_bits());
+            state.write_float::<F64>(fa1, v2.to_bits());
+            state.write_float::<F64>(fa2, v3.to_bits());
+
+            // Compare with the host for non-NaN results, NaNs are canonical
+            let canonical = |value: f64| if value.is_nan() { f64::NAN } else { value }.to_bits();
+
+            prop_assert_eq!(state.run_faddd(fa0, fa1, RNE, fa3), Ok(()));
+            prop_assert_eq!(state.read_float::<F64>(fa3), canonical(v1 + v2));
+            prop_assert_eq!(state.run_fsubd(fa0, fa1, RNE, fa3), Ok(()));
+            prop_assert_eq!(state.read_float::<F64>(fa3), canonical(v1 - v2));
+            prop_assert_eq!(state.run_fmuld(fa0, fa1, RNE, fa3), Ok(()));
+            prop_assert_eq!(state.read_float::<F64>(fa3), canonical(v1 * v2));
+            prop_assert_eq!(state.run_fdivd(fa0, fa1, RNE, fa3), Ok(()));
+            prop_assert_eq!(state.read_float::<F64>(fa3), canonical(v1 / v2));
+            prop_assert_eq!(state.run_fsqrtd(fa0, RNE, fa3), Ok(()));
+            prop_assert_eq!(state.read_float::<F64>(fa3), canonical(v1.sqrt()));
+            prop_assert_eq!(state.run_fmsubd(fa0, fa1, fa2, RNE, fa3), Ok(()));
+            prop_assert_eq!(state.read_float::<F64>(fa3), canonical(v1.mul_add(v2, -v3)));
+            prop_assert_eq!(state.run_fnmaddd(fa0, fa1, fa2, RNE, fa3), Ok(()));
+            prop_assert_eq!(state.read_float::<F64>(fa3), canonical((-v1).mul_add(v2, -v3)));
+
+            prop_assert_eq!(state.run_fled(fa0, fa1, a0), Ok(()));
+            prop_assert_eq!(state.xregisters.read(a0), (v1 <= v2) as u64);
+            prop_assert_eq!(state.run_feqd(fa0, fa1, a0), Ok(()));
+            prop_assert_eq!(state.xregisters.read(a0), (v1 == v2) as u64);
+        });
+    });
+
+    backend_test!(test_convert_d, F, {
+        proptest!(|(value in any::<i64>(), single in any::<f32>())| {
+            let mut backend = create_backend!(HartStateLayout, F);
+            let mut state = create_state!(HartState, F, backend);
+            state.reset(Mode::Machine, 0);
+            state.xregisters.write(a0, value as u64);
+
+            prop_assert_eq!(state.run_fcvtdl(a0, RNE, fa0), Ok(()));
+            prop_assert_eq!(state.read_float::<F64>(fa0), (value as f64).to_bits());
+            prop_assert_eq!(state.run_fmvxd(fa0, a1), Ok(()));
+            prop_assert_eq!(state.xregisters.read(a1), (value as f64).to_bits());
+
+            // Rounding to an integer is exact, barring saturation
+            prop_assert_eq!(state.run_fcvtld(fa0, RNE, a1), Ok(()));
+            prop_assert_eq!(state.xregisters.read(a1), value as f64 as i64 as u64);
+
+            // Single-precision values are widened exactly
+            state.write_float::<F32>(fa1, single.to_bits() as u64);
+            prop_assert_eq!(state.run_fcvtds(fa1, RNE, fa2), Ok(()));
+            if !single.is_nan() {
+                prop_assert_eq!(state.read_float::<F64>(fa2), (single as f64).to_bits());
+                prop_assert_eq!(state.run_fcvtsd(fa2, RNE, fa1), Ok(()));
+                prop_assert_eq!(state.read_float::<F32>(fa1), single.to_bits() as u64);
+            }
+        });
+    });
+
+    backend_test!(test_convert_d_edge_cases, F, {
+        let mut backend = create_backend!(HartStateLayout, F);
+        let mut state = create_state!(HartState, F, backend);
+        state.reset(Mode::Machine, 0);
+
+        // Rounding to single precision overflows
+        state.write_float::<F64>(fa0, f64::MAX.to_bits());
+        assert_eq!(state.run_fcvtsd(fa0, RNE, fa1), Ok(()));
+        assert_eq!(state.read_float::<F32>(fa1), f32::INFINITY.to_bits() as u64);
+        assert_eq!(
+            state.csregisters.read(CSRegister::fflags),
+            FLAG_OF | FLAG_NX
+        );
+
+        // Out-of-range conversions saturate, 32-bit results are sign-extended
+        state.csregisters.write(CSRegister::fflags, 0);
+        state.write_float::<F64>(fa0, (-1.5_f64).to_bits());
+        assert_eq!(state.run_fcvtwud(fa0, RNE, a0), Ok(()));
+        assert_eq!(state.xregisters.read(a0), 0);
+        assert_eq!(state.csregisters.read(CSRegister::fflags), FLAG_NV);
+
+        state.write_float::<F64>(fa0, 1e10_f64.to_bits());
+        assert_eq!(state.run_fcvtwud(fa0, RNE, a0), Ok(()));
+        assert_eq!(state.xregisters.read(a0), u64::MAX);
+        assert_eq!(state.run_fcvtwd(fa0, RNE, a0), Ok(()));
+        assert_eq!(state.xregisters.read(a0), i32::MAX as u64);
+
+        state.write_float::<F64>(fa0, f64::NAN.to_bits());
+        assert_eq!(state.run_fcvtlud(fa0, RNE, a0), Ok(()));
+        assert_eq!(state.xregisters.read(a0), u64::MAX);
+
+        // Double-precision values are not NaN-boxed
+        state.xregisters.write(a0, 1.0_f64.to_bits());
+        assert_eq!(state.run_fmvdx(a0, fa3), Ok(()));
+        assert_eq!(state.read_float::<F32>(fa3), F32::CANONICAL_NAN);
+    });
+
+    backend_test!(test_load_store_d, F, {
+        proptest!(|(value in any::<u64>())| {
+            let mut backend = create_backend!(MachineStateLayout<T1K>, F);
+            let mut state: MachineState<T1K, ManagerFor<'_, F, MachineStateLayout<T1K>>> =
+                MachineState::bind(backend.allocate(MachineStateLayout::<T1K>::placed().into_location()));
+            state.reset(Mode::Machine, 0);
+            state.hart.xregisters.write(a0, DEVICES_ADDRESS_SPACE_LENGTH);
+
+            state.bus.write(DEVICES_ADDRESS_SPACE_LENGTH, value).unwrap();
+            prop_assert_eq!(state.run_fld(0, a0, fa0), Ok(()));
+            prop_assert_eq!(u64::from(state.hart.fregisters.read(fa0)), value);
+
+            prop_assert_eq!(state.run_fsd(8, a0, fa0), Ok(()));
+            let stored: u64 = state.bus.read(DEVICES_ADDRESS_SPACE_LENGTH + 8).unwrap();
+            prop_assert_eq!(stored, value);
+        });
+    });
+}
@@ -0,0 +1,845 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Implementation of RV_64_F extension for RISC-V
+//!
+//! All arithmetic is done by [crate::softfloat] on the raw bits of the
+//! registers. This module also holds the implementations shared with the
+//! D extension, generic over the floating-point format.
+//!
+//! Chapter 11 - Unprivileged spec
+
+use crate::machine_state::{
+    bus::main_memory::MainMemoryLayout,
+    csregisters::CSRegister,
+    registers::{FRegister, XRegister},
+    HartState, MachineState,
+};
+use crate::parser::instruction::InstrRoundingMode;
+use crate::softfloat::{self, ExceptionFlags, Format, RoundingMode, F32};
+use crate::state_backend as backend;
+use crate::traps::Exception;
+
+/// Bits of a register above a value of the format `F`, which must all be
+/// set for the value to be properly NaN-boxed. (Section 12.2)
+#[inline(always)]
+fn nan_box_mask<F: Format>() -> u64 {
+    u64::MAX.checked_shl(F::WIDTH).unwrap_or(0)
+}
+
+impl<M> HartState<M>
+where
+    M: backend::Manager,
+{
+    /// Read a value of the format `F` from `reg`.
+    ///
+    /// Values which are not properly NaN-boxed are read as the canonical NaN.
+    #[inline(always)]
+    pub fn read_float<F: Format>(&self, reg: FRegister) -> u64 {
+        let value = u64::from(self.fregisters.read(reg));
+        let mask = nan_box_mask::<F>();
+
+        if value & mask == mask {
+            value & !mask
+        } else {
+            F::CANONICAL_NAN
+        }
+    }
+
+    /// Write a value of the format `F` to `reg`, NaN-boxing it.
+    #[inline(always)]
+    pub fn write_float<F: Format>(&mut self, reg: FRegister, value: u64) {
+        self.fregisters
+            .write(reg, (value | nan_box_mask::<F>()).into());
+        self.csregisters.mark_fs_dirty();
+    }
+
+    /// Resolve the rounding mode of an instruction. The dynamic rounding
+    /// mode is held in `frm`, whose reserved values are illegal.
+    #[inline(always)]
+    fn rounding_mode(&mut self, rm: InstrRoundingMode) -> Result<RoundingMode, Exception> {
+        match rm {
+            InstrRoundingMode::Static(rm) => Ok(rm),
+            InstrRoundingMode::Dynamic => {
+                RoundingMode::try_from(self.csregisters.read(CSRegister::frm))
+                    .map_err(|_| Exception::IllegalInstruction)
+            }
+        }
+    }
+
+    /// Accrue exception flags in `fflags`.
+    #[inline(always)]
+    fn accrue_flags(&mut self, flags: ExceptionFlags) {
+        if flags != 0 {
+            self.csregisters.set_bits(CSRegister::fflags, flags);
+        }
+    }
+
+    /// Generic rounded binary operation: `FADD`, `FSUB`, `FMUL` & `FDIV`
+    pub(super) fn run_fbinop_impl<F: Format>(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+        op: fn(u64, u64, RoundingMode, &mut ExceptionFlags) -> u64,
+    ) -> Result<(), Exception> {
+        self.check_fs_on()?;
+        let rm = self.rounding_mode(rm)?;
+
+        let mut flags = 0;
+        let result = op(
+            self.read_float::<F>(rs1),
+            self.read_float::<F>(rs2),
+            rm,
+            &mut flags,
+        );
+
+        self.accrue_flags(flags);
+        self.write_float::<F>(rd, result);
+        Ok(())
+    }
+
+    /// Generic `FSQRT` instruction
+    pub(super) fn run_fsqrt_impl<F: Format>(
+        &mut self,
+        rs1: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.check_fs_on()?;
+        let rm = self.rounding_mode(rm)?;
+
+        let mut flags = 0;
+        let result = softfloat::sqrt::<F>(self.read_float::<F>(rs1), rm, &mut flags);
+
+        self.accrue_flags(flags);
+        self.write_float::<F>(rd, result);
+        Ok(())
+    }
+
+    /// Generic fused multiply-add: computes `±(val(rs1) * val(rs2)) ± val(rs3)`
+    /// with a single rounding, negating the product and the addend as required.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn run_fmadd_impl<F: Format>(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rs3: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+        negate_product: bool,
+        negate_addend: bool,
+    ) -> Result<(), Exception> {
+        self.check_fs_on()?;
+        let rm = self.rounding_mode(rm)?;
+
+        let negate = |value: u64, negate: bool| if negate { value ^ F::SIGN_BIT } else { value };
+        let a = negate(self.read_float::<F>(rs1), negate_product);
+        let b = self.read_float::<F>(rs2);
+        let c = negate(self.read_float::<F>(rs3), negate_addend);
+
+        let mut flags = 0;
+        let result = softfloat::mul_add::<F>(a, b, c, rm, &mut flags);
+
+        self.accrue_flags(flags);
+        self.write_float::<F>(rd, result);
+        Ok(())
+    }
+
+    /// Generic sign injection: the result has the magnitude of `val(rs1)`
+    /// and the sign bit `sign(val(rs1), val(rs2))`.
+    pub(super) fn run_fsgnj_impl<F: Format>(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rd: FRegister,
+        sign: fn(u64, u64) -> u64,
+    ) -> Result<(), Exception> {
+        self.check_fs_on()?;
+
+        let a = self.read_float::<F>(rs1);
+        let b = self.read_float::<F>(rs2);
+        let result = a & !F::SIGN_BIT | sign(a, b) & F::SIGN_BIT;
+
+        self.write_float::<F>(rd, result);
+        Ok(())
+    }
+
+    /// Generic `FMIN` & `FMAX` instructions
+    pub(super) fn run_fminmax_impl<F: Format>(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rd: FRegister,
+        op: fn(u64, u64, &mut ExceptionFlags) -> u64,
+    ) -> Result<(), Exception> {
+        self.check_fs_on()?;
+
+        let mut flags = 0;
+        let result = op(
+            self.read_float::<F>(rs1),
+            self.read_float::<F>(rs2),
+            &mut flags,
+        );
+
+        self.accrue_flags(flags);
+        self.write_float::<F>(rd, result);
+        Ok(())
+    }
+
+    /// Generic comparison: `FEQ`, `FLT` & `FLE`
+    ///
+    /// Writes 1 to the integer register `rd` if the comparison holds, 0 otherwise.
+    pub(super) fn run_fcmp_impl<F: Format>(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rd: XRegister,
+        op: fn(u64, u64, &mut ExceptionFlags) -> bool,
+    ) -> Result<(), Exception> {
+        self.check_fs_on()?;
+
+        let mut flags = 0;
+        let result = op(
+            self.read_float::<F>(rs1),
+            self.read_float::<F>(rs2),
+            &mut flags,
+        );
+
+        self.accrue_flags(flags);
+        self.xregisters.write(rd, result as u64);
+        Ok(())
+    }
+
+    /// Generic `FCLASS` instruction
+    pub(super) fn run_fclass_impl<F: Format>(
+        &mut self,
+        rs1: FRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.check_fs_on()?;
+
+        let result = softfloat::classify::<F>(self.read_float::<F>(rs1));
+        self.xregisters.write(rd, result);
+        Ok(())
+    }
+
+    /// Generic conversion to an integer of `width` bits, signed or not.
+    ///
+    /// 32-bit results are sign-extended, even when unsigned.
+    pub(super) fn run_fcvt_to_int_impl<F: Format>(
+        &mut self,
+        rs1: FRegister,
+        rm: InstrRoundingMode,
+        rd: XRegister,
+        signed: bool,
+        width: u32,
+    ) -> Result<(), Exception> {
+        self.check_fs_on()?;
+        let rm = self.rounding_mode(rm)?;
+
+        let mut flags = 0;
+        let value = self.read_float::<F>(rs1);
+        let result = softfloat::to_int::<F>(value, signed, width, rm, &mut flags);
+
+        let result = match width {
+            32 => result as i32 as u64,
+            _ => result as u64,
+        };
+
+        self.accrue_flags(flags);
+        self.xregisters.write(rd, result);
+        Ok(())
+    }
+
+    /// Generic conversion from the lower `width` bits of the integer
+    /// register `rs1`, signed or not.
+    pub(super) fn run_fcvt_from_int_impl<F: Format>(
+        &mut self,
+        rs1: XRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+        signed: bool,
+        width: u32,
+    ) -> Result<(), Exception> {
+        self.check_fs_on()?;
+        let rm = self.rounding_mode(rm)?;
+
+        let value = self.xregisters.read(rs1);
+        let value = match (signed, width) {
+            (true, 32) => value as i32 as i128,
+            (false, 32) => value as u32 as i128,
+            (true, _) => value as i64 as i128,
+            (false, _) => value as i128,
+        };
+
+        let mut flags = 0;
+        let result = softfloat::from_int::<F>(value, rm, &mut flags);
+
+        self.accrue_flags(flags);
+        self.write_float::<F>(rd, result);
+        Ok(())
+    }
+
+    /// Generic conversion between floating-point formats, from `S` to `D`
+    pub(super) fn run_fcvt_float_impl<S: Format, D: Format>(
+        &mut self,
+        rs1: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.check_fs_on()?;
+        let rm = self.rounding_mode(rm)?;
+
+        let mut flags = 0;
+        let result = softfloat::convert::<S, D>(self.read_float::<S>(rs1), rm, &mut flags);
+
+        self.accrue_flags(flags);
+        self.write_float::<D>(rd, result);
+        Ok(())
+    }
+
+    /// `FADD.S` R-type instruction
+    pub fn run_fadds(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fbinop_impl::<F32>(rs1, rs2, rm, rd, softfloat::add::<F32>)
+    }
+
+    /// `FSUB.S` R-type instruction
+    pub fn run_fsubs(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fbinop_impl::<F32>(rs1, rs2, rm, rd, softfloat::sub::<F32>)
+    }
+
+    /// `FMUL.S` R-type instruction
+    pub fn run_fmuls(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fbinop_impl::<F32>(rs1, rs2, rm, rd, softfloat::mul::<F32>)
+    }
+
+    /// `FDIV.S` R-type instruction
+    pub fn run_fdivs(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fbinop_impl::<F32>(rs1, rs2, rm, rd, softfloat::div::<F32>)
+    }
+
+    /// `FSQRT.S` R-type instruction
+    pub fn run_fsqrts(
+        &mut self,
+        rs1: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fsqrt_impl::<F32>(rs1, rm, rd)
+    }
+
+    /// `FMIN.S` R-type instruction
+    ///
+    /// A NaN is only returned if both operands are NaNs.
+    pub fn run_fmins(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fminmax_impl::<F32>(rs1, rs2, rd, softfloat::min::<F32>)
+    }
+
+    /// `FMAX.S` R-type instruction
+    ///
+    /// A NaN is only returned if both operands are NaNs.
+    pub fn run_fmaxs(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fminmax_impl::<F32>(rs1, rs2, rd, softfloat::max::<F32>)
+    }
+
+    /// `FMADD.S` R4-type instruction
+    ///
+    /// Saves `(val(rs1) * val(rs2)) + val(rs3)` in `rd`
+    pub fn run_fmadds(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rs3: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fmadd_impl::<F32>(rs1, rs2, rs3, rm, rd, false, false)
+    }
+
+    /// `FMSUB.S` R4-type instruction
+    ///
+    /// Saves `(val(rs1) * val(rs2)) - val(rs3)` in `rd`
+    pub fn run_fmsubs(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rs3: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fmadd_impl::<F32>(rs1, rs2, rs3, rm, rd, false, true)
+    }
+
+    /// `FNMSUB.S` R4-type instruction
+    ///
+    /// Saves `-(val(rs1) * val(rs2)) + val(rs3)` in `rd`
+    pub fn run_fnmsubs(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rs3: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fmadd_impl::<F32>(rs1, rs2, rs3, rm, rd, true, false)
+    }
+
+    /// `FNMADD.S` R4-type instruction
+    ///
+    /// Saves `-(val(rs1) * val(rs2)) - val(rs3)` in `rd`
+    pub fn run_fnmadds(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rs3: FRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fmadd_impl::<F32>(rs1, rs2, rs3, rm, rd, true, true)
+    }
+
+    /// `FSGNJ.S` R-type instruction
+    pub fn run_fsgnjs(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fsgnj_impl::<F32>(rs1, rs2, rd, |_, b| b)
+    }
+
+    /// `FSGNJN.S` R-type instruction
+    pub fn run_fsgnjns(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fsgnj_impl::<F32>(rs1, rs2, rd, |_, b| !b)
+    }
+
+    /// `FSGNJX.S` R-type instruction
+    pub fn run_fsgnjxs(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fsgnj_impl::<F32>(rs1, rs2, rd, |a, b| a ^ b)
+    }
+
+    /// `FEQ.S` R-type instruction
+    ///
+    /// Quiet comparison, only signaling NaNs raise the invalid flag.
+    pub fn run_feqs(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcmp_impl::<F32>(rs1, rs2, rd, softfloat::eq::<F32>)
+    }
+
+    /// `FLT.S` R-type instruction
+    ///
+    /// Signaling comparison, any NaN raises the invalid flag.
+    pub fn run_flts(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcmp_impl::<F32>(rs1, rs2, rd, softfloat::lt::<F32>)
+    }
+
+    /// `FLE.S` R-type instruction
+    ///
+    /// Signaling comparison, any NaN raises the invalid flag.
+    pub fn run_fles(
+        &mut self,
+        rs1: FRegister,
+        rs2: FRegister,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcmp_impl::<F32>(rs1, rs2, rd, softfloat::le::<F32>)
+    }
+
+    /// `FCLASS.S` R-type instruction
+    pub fn run_fclasss(&mut self, rs1: FRegister, rd: XRegister) -> Result<(), Exception> {
+        self.run_fclass_impl::<F32>(rs1, rd)
+    }
+
+    /// `FCVT.W.S` R-type instruction
+    pub fn run_fcvtws(
+        &mut self,
+        rs1: FRegister,
+        rm: InstrRoundingMode,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcvt_to_int_impl::<F32>(rs1, rm, rd, true, 32)
+    }
+
+    /// `FCVT.WU.S` R-type instruction
+    pub fn run_fcvtwus(
+        &mut self,
+        rs1: FRegister,
+        rm: InstrRoundingMode,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcvt_to_int_impl::<F32>(rs1, rm, rd, false, 32)
+    }
+
+    /// `FCVT.L.S` R-type instruction
+    pub fn run_fcvtls(
+        &mut self,
+        rs1: FRegister,
+        rm: InstrRoundingMode,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcvt_to_int_impl::<F32>(rs1, rm, rd, true, 64)
+    }
+
+    /// `FCVT.LU.S` R-type instruction
+    pub fn run_fcvtlus(
+        &mut self,
+        rs1: FRegister,
+        rm: InstrRoundingMode,
+        rd: XRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcvt_to_int_impl::<F32>(rs1, rm, rd, false, 64)
+    }
+
+    /// `FCVT.S.W` R-type instruction
+    pub fn run_fcvtsw(
+        &mut self,
+        rs1: XRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcvt_from_int_impl::<F32>(rs1, rm, rd, true, 32)
+    }
+
+    /// `FCVT.S.WU` R-type instruction
+    pub fn run_fcvtswu(
+        &mut self,
+        rs1: XRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcvt_from_int_impl::<F32>(rs1, rm, rd, false, 32)
+    }
+
+    /// `FCVT.S.L` R-type instruction
+    pub fn run_fcvtsl(
+        &mut self,
+        rs1: XRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcvt_from_int_impl::<F32>(rs1, rm, rd, true, 64)
+    }
+
+    /// `FCVT.S.LU` R-type instruction
+    pub fn run_fcvtslu(
+        &mut self,
+        rs1: XRegister,
+        rm: InstrRoundingMode,
+        rd: FRegister,
+    ) -> Result<(), Exception> {
+        self.run_fcvt_from_int_impl::<F32>(rs1, rm, rd, false, 64)
+    }
+
+    /// `FMV.X.W` R-type instruction
+    ///
+    /// Moves the lower 32 bits of `rs1` to `rd`, sign-extended. The NaN-boxing
+    /// of `rs1` is not checked.
+    pub fn run_fmvxw(&mut self, rs1: FRegister, rd: XRegister) -> Result<(), Exception> {
+        self.check_fs_on()?;
+
+        let value = u64::from(self.fregisters.read(rs1));
+        self.xregisters.write(rd, value as i32 as u64);
+        Ok(())
+    }
+
+    /// `FMV.W.X` R-type instruction
+    ///
+    /// Moves the lower 32 bits of `rs1` to `rd`, NaN-boxed.
+    pub fn run_fmvwx(&mut self, rs1: XRegister, rd: FRegister) -> Result<(), Exception> {
+        self.check_fs_on()?;
+
+        let value = self.xregisters.read(rs1) as u32;
+        self.write_float::<F32>(rd, value as u64);
+        Ok(())
+    }
+}
+
+impl<ML, M> MachineState<ML, M>
+where
+    ML: MainMemoryLayout,
+    M: backend::Manager,
+{
+    /// `FLW` I-type instruction
+    ///
+    /// Loads the word at `val(rs1) + imm` into `rd`, NaN-boxed.
+    pub fn run_flw(&mut self, imm: i64, rs1: XRegister, rd: FRegister) -> Result<(), Exception> {
+        self.hart.check_fs_on()?;
+
+        let value: u32 = self.read_from_bus(imm, rs1)?;
+        self.hart.write_float::<F32>(rd, value as u64);
+        Ok(())
+    }
+
+    /// `FSW` S-type instruction
+    ///
+    /// Stores the lower 32 bits of `rs2` at `val(rs1) + imm`. The NaN-boxing
+    /// of `rs2` is not checked.
+    pub fn run_fsw(&mut self, imm: i64, rs1: XRegister, rs2: FRegister) -> Result<(), Exception> {
+        self.hart.check_fs_on()?;
+
+        let value = u64::from(self.hart.fregisters.read(rs2)) as u32;
+        self.write_to_bus(imm, rs1, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend_test, create_backend, create_state,
+        machine_state::{
+            bus::{devices::DEVICES_ADDRESS_SPACE_LENGTH, main_memory::tests::T1K, Addressable},
+            csregisters::{xstatus, CSRegister},
+            mode::Mode,
+            registers::{a0, a1, fa0, fa1, fa2, fa3},
+            HartState, HartStateLayout, MachineState, MachineStateLayout,
+        },
+        parser::instruction::InstrRoundingMode,
+        softfloat::{RoundingMode, F32, FLAG_DZ, FLAG_NV, FLAG_NX},
+        state_backend::{tests::ManagerFor, Backend, Layout},
+        traps::Exception,
+    };
+    use proptest::{arbitrary::any, prop_assert_eq, proptest};
+
+    const RNE: InstrRoundingMode = InstrRoundingMode::Static(RoundingMode::RNE);
+    const DYN: InstrRoundingMode = InstrRoundingMode::Dynamic;
+
+    backend_test!(test_arith_s, F, {
+        proptest!(|(v1 in any::<f32>(), v2 in any::<f32>(), v3 in any::<f32>())| {
+            let mut backend = create_backend!(HartStateLayout, F);
+            let mut state = create_state!(HartState, F, backend);
+            state.reset(Mode::Machine, 0);
+            state.write_float::<F32>(fa0, v1.to_bits() as u64);
+            state.write_float::<F32>(fa1, v2.to_bits() as u64);
+            state.write_float::<F32>(fa2, v3.to_bits() as u64);
+
+            // Compare with the host for non-NaN results, NaNs are canonical
+            let check = |state: &mut HartState<_>, expected: f32| {
+                let result = state.read_float::<F32>(fa3);
+                let expected = if expected.is_nan() {
+                    f32::NAN.to_bits() as u64
+                } else {
+                    expected.to_bits() as u64
+                };
+                // Results are NaN-boxed
+                prop_assert_eq!(u64::from(state.fregisters.read(fa3)) >> 32, 0xffff_ffff);
+                prop_assert_eq!(result, expected);
+                Ok(())
+            };
+
+            prop_assert_eq!(state.run_fadds(fa0, fa1, RNE, fa3), Ok(()));
+            check(&mut state, v1 + v2)?;
+            prop_assert_eq!(state.run_fsubs(fa0, fa1, RNE, fa3), Ok(()));
+            check(&mut state, v1 - v2)?;
+            prop_assert_eq!(state.run_fmuls(fa0, fa1, RNE, fa3), Ok(()));
+            check(&mut state, v1 * v2)?;
+            prop_assert_eq!(state.run_fdivs(fa0, fa1, DYN, fa3), Ok(()));
+            check(&mut state, v1 / v2)?;
+            prop_assert_eq!(state.run_fsqrts(fa0, RNE, fa3), Ok(()));
+            check(&mut state, v1.sqrt())?;
+            prop_assert_eq!(state.run_fmadds(fa0, fa1, fa2, RNE, fa3), Ok(()));
+            check(&mut state, v1.mul_add(v2, v3))?;
+            prop_assert_eq!(state.run_fnmsubs(fa0, fa1, fa2, RNE, fa3), Ok(()));
+            check(&mut state, (-v1).mul_add(v2, v3))?;
+            prop_assert_eq!(state.run_fsgnjxs(fa0, fa1, fa3), Ok(()));
+            prop_assert_eq!(
+                state.read_float::<F32>(fa3),
+                (v1.to_bits() ^ (v2.to_bits() & 1 << 31)) as u64
+            );
+        });
+    });
+
+    backend_test!(test_convert_s, F, {
+        proptest!(|(value in any::<i32>())| {
+            let mut backend = create_backend!(HartStateLayout, F);
+            let mut state = create_state!(HartState, F, backend);
+            state.reset(Mode::Machine, 0);
+            state.xregisters.write(a0, value as u64);
+
+            prop_assert_eq!(state.run_fcvtsw(a0, RNE, fa0), Ok(()));
+            prop_assert_eq!(state.read_float::<F32>(fa0), (value as f32).to_bits() as u64);
+
+            // Moving to an integer register sign-extends the bits
+            prop_assert_eq!(state.run_fmvxw(fa0, a1), Ok(()));
+            prop_assert_eq!(state.xregisters.read(a1), (value as f32).to_bits() as i32 as u64);
+
+            prop_assert_eq!(state.run_fcvtws(fa0, RNE, a1), Ok(()));
+            prop_assert_eq!(state.xregisters.read(a1), value as f32 as i32 as u64);
+        });
+    });
+
+    backend_test!(test_nan_boxing, F, {
+        let mut backend = create_backend!(HartStateLayout, F);
+        let mut state = create_state!(HartState, F, backend);
+        state.reset(Mode::Machine, 0);
+
+        // Improperly boxed values are read as the canonical NaN
+        state.fregisters.write(fa0, 0x3f80_0000.into());
+        assert_eq!(state.read_float::<F32>(fa0), 0x7fc0_0000);
+        assert_eq!(state.run_fclasss(fa0, a0), Ok(()));
+        assert_eq!(state.xregisters.read(a0), 1 << 9);
+
+        // FMV.W.X boxes the value
+        state.xregisters.write(a0, 0x1234_5678_3f80_0000);
+        assert_eq!(state.run_fmvwx(a0, fa0), Ok(()));
+        assert_eq!(u64::from(state.fregisters.read(fa0)), 0xffff_ffff_3f80_0000);
+        assert_eq!(state.run_fclasss(fa0, a0), Ok(()));
+        assert_eq!(state.xregisters.read(a0), 1 << 6);
+    });
+
+    backend_test!(test_flags_and_rounding, F, {
+        let mut backend = create_backend!(HartStateLayout, F);
+        let mut state = create_state!(HartState, F, backend);
+        state.reset(Mode::Machine, 0);
+
+        // 1 / 0 raises the divide by zero flag
+        state.write_float::<F32>(fa0, 1.0_f32.to_bits() as u64);
+        state.write_float::<F32>(fa1, 0.0_f32.to_bits() as u64);
+        assert_eq!(state.run_fdivs(fa0, fa1, RNE, fa2), Ok(()));
+        assert_eq!(state.read_float::<F32>(fa2), f32::INFINITY.to_bits() as u64);
+        assert_eq!(state.csregisters.read(CSRegister::fflags), FLAG_DZ);
+
+        // Flags accrue, FLT is signaling
+        state.write_float::<F32>(fa1, f32::NAN.to_bits() as u64);
+        assert_eq!(state.run_flts(fa0, fa1, a0), Ok(()));
+        assert_eq!(state.xregisters.read(a0), 0);
+        assert_eq!(
+            state.csregisters.read(CSRegister::fflags),
+            FLAG_DZ | FLAG_NV
+        );
+
+        // The dynamic rounding mode is read from frm
+        state.csregisters.write(CSRegister::fflags, 0);
+        state.write_float::<F32>(fa1, 3.0_f32.to_bits() as u64);
+        state
+            .csregisters
+            .write(CSRegister::frm, RoundingMode::RUP as u64);
+        assert_eq!(state.run_fdivs(fa0, fa1, DYN, fa2), Ok(()));
+        assert_eq!(
+            state.read_float::<F32>(fa2),
+            (1.0_f32 / 3.0).to_bits() as u64
+        );
+        assert_eq!(state.csregisters.read(CSRegister::fflags), FLAG_NX);
+
+        state
+            .csregisters
+            .write(CSRegister::frm, RoundingMode::RTZ as u64);
+        assert_eq!(state.run_fdivs(fa0, fa1, DYN, fa2), Ok(()));
+        assert_eq!(
+            state.read_float::<F32>(fa2),
+            (1.0_f32 / 3.0).to_bits() as u64 - 1
+        );
+
+        // Reserved dynamic rounding modes are illegal
+        state.csregisters.write(CSRegister::frm, 0b101);
+        assert_eq!(
+            state.run_fdivs(fa0, fa1, DYN, fa2),
+            Err(Exception::IllegalInstruction)
+        );
+        assert_eq!(state.run_fdivs(fa0, fa1, RNE, fa2), Ok(()));
+    });
+
+    backend_test!(test_fs_off, F, {
+        let mut backend = create_backend!(HartStateLayout, F);
+        let mut state = create_state!(HartState, F, backend);
+        state.reset(Mode::Machine, 0);
+
+        let mstatus = state.csregisters.read(CSRegister::mstatus);
+        state.csregisters.write(
+            CSRegister::mstatus,
+            xstatus::set_FS(mstatus, xstatus::ExtensionValue::Off),
+        );
+
+        assert_eq!(
+            state.run_fadds(fa0, fa1, RNE, fa2),
+            Err(Exception::IllegalInstruction)
+        );
+        assert_eq!(state.run_fmvwx(a0, fa0), Err(Exception::IllegalInstruction));
+        assert_eq!(
+            state.csrrs(CSRegister::fflags, a0, a1),
+            Err(Exception::IllegalInstruction)
+        );
+
+        // Writing the floating-point state marks it dirty
+        state.csregisters.write(
+            CSRegister::mstatus,
+            xstatus::set_FS(mstatus, xstatus::ExtensionValue::Clean),
+        );
+        assert_eq!(state.run_fmvwx(a0, fa0), Ok(()));
+        let mstatus = state.csregisters.read(CSRegister::mstatus);
+        assert_eq!(xstatus::get_FS(mstatus), xstatus::ExtensionValue::Dirty);
+    });
+
+    backend_test!(test_load_store_s, F, {
+        proptest!(|(value in any::<u32>())| {
+            let mut backend = create_backend!(MachineStateLayout<T1K>, F);
+            let mut state: MachineState<T1K, ManagerFor<'_, F, MachineStateLayout<T1K>>> =
+                MachineState::bind(backend.allocate(MachineStateLayout::<T1K>::placed().into_location()));
+            state.reset(Mode::Machine, 0);
+            state.hart.xregisters.write(a0, DEVICES_ADDRESS_SPACE_LENGTH);
+
+            state.bus.write(DEVICES_ADDRESS_SPACE_LENGTH, value).unwrap();
+            prop_assert_eq!(state.run_flw(0, a0, fa0), Ok(()));
+            prop_assert_eq!(u64::from(state.hart.fregisters.read(fa0)), 0xffff_ffff_0000_0000 | value as u64);
+
+            prop_assert_eq!(state.run_fsw(4, a0, fa0), Ok(()));
+            let stored: u32 = state.bus.read(DEVICES_ADDRESS_SPACE_LENGTH + 4).unwrap();
+            prop_assert_eq!(stored, value);
+        });
+    });
+}
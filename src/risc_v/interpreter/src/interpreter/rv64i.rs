@@ -1,4 +1,4 @@
-// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+// SPDX-FileCopyrightText: 2023-2024 TriliTech <contact@trili.tech>
 //
 // SPDX-License-Identifier: MIT
 
@@ -6,8 +6,13 @@
 //!
 //! Chapter 5 - Unprivileged spec
 
-use crate::machine_state::registers::{XRegister, XRegisters};
+use crate::machine_state::{
+    bus::main_memory::MainMemoryLayout,
+    registers::{XRegister, XRegisters},
+    MachineState,
+};
 use crate::state_backend as backend;
+use crate::traps::Exception;
 
 impl<M> XRegisters<M>
 where
@@ -107,6 +112,84 @@ where
         // Note: i32 as u64 will sign-extend the lowest 32 bits
         self.write(rd, result as u64)
     }
+
+    /// `ADDW` R-type instruction
+    ///
+    /// Adds the lower 32 bits of both registers, sign-extending the result
+    pub fn run_addw(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let result = (self.read(rs1) as i32).wrapping_add(self.read(rs2) as i32);
+        self.write(rd, result as u64)
+    }
+
+    /// `SUBW` R-type instruction
+    ///
+    /// Subtracts the lower 32 bits of both registers, sign-extending the result
+    pub fn run_subw(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let result = (self.read(rs1) as i32).wrapping_sub(self.read(rs2) as i32);
+        self.write(rd, result as u64)
+    }
+
+    /// `SLLW` R-type instruction
+    ///
+    /// Shift left logically the lower 32 bits by the lower 5 bits of `val(rs2)`
+    pub fn run_sllw(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let sh_amt = self.read(rs2) & 0b1_1111;
+        let result = (self.read(rs1) as u32) << sh_amt;
+        // Note: u32 as i32 as u64 will sign-extend the lowest 32 bits
+        self.write(rd, result as i32 as u64)
+    }
+
+    /// `SRLW` R-type instruction
+    ///
+    /// Shift right logically the lower 32 bits by the lower 5 bits of `val(rs2)`
+    pub fn run_srlw(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let sh_amt = self.read(rs2) & 0b1_1111;
+        let result = (self.read(rs1) as u32) >> sh_amt;
+        // Note: u32 as i32 as u64 will sign-extend the lowest 32 bits
+        self.write(rd, result as i32 as u64)
+    }
+
+    /// `SRAW` R-type instruction
+    ///
+    /// Shift right arithmetically the lower 32 bits by the lower 5 bits of `val(rs2)`
+    pub fn run_sraw(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let sh_amt = self.read(rs2) & 0b1_1111;
+        // Right shift on i32 is an arithmetic shift
+        let result = (self.read(rs1) as i32) >> sh_amt;
+        self.write(rd, result as u64)
+    }
+}
+
+impl<ML, M> MachineState<ML, M>
+where
+    ML: MainMemoryLayout,
+    M: backend::Manager,
+{
+    /// `LWU` I-type instruction
+    ///
+    /// Loads a word from `val(rs1) + imm` and zero-extends it into `rd`
+    pub fn run_lwu(&mut self, imm: i64, rs1: XRegister, rd: XRegister) -> Result<(), Exception> {
+        let value: u32 = self.read_from_bus(imm, rs1)?;
+        self.hart.xregisters.write(rd, value as u64);
+        Ok(())
+    }
+
+    /// `LD` I-type instruction
+    ///
+    /// Loads a double-word from `val(rs1) + imm` into `rd`
+    pub fn run_ld(&mut self, imm: i64, rs1: XRegister, rd: XRegister) -> Result<(), Exception> {
+        let value: u64 = self.read_from_bus(imm, rs1)?;
+        self.hart.xregisters.write(rd, value);
+        Ok(())
+    }
+
+    /// `SD` S-type instruction
+    ///
+    /// Stores `val(rs2)` at `val(rs1) + imm`
+    pub fn run_sd(&mut self, imm: i64, rs1: XRegister, rs2: XRegister) -> Result<(), Exception> {
+        let value = self.hart.xregisters.read(rs2);
+        self.write_to_bus(imm, rs1, value)
+    }
 }
 
 #[cfg(test)]
@@ -274,4 +357,42 @@ mod tests {
             0xFFFF_FFFF_FFFF_FFFF
         );
     });
+
+    backend_test!(test_r_type_w, F, {
+        proptest!(|(v1 in any::<u64>(), v2 in any::<u64>())| {
+            let mut backend = create_backend!(HartStateLayout, F);
+            let mut state = create_state!(HartState, F, backend);
+            state.xregisters.write(a0, v1);
+            state.xregisters.write(t0, v2);
+
+            // Results are computed on the lowest 32 bits, then sign-extended
+            state.xregisters.run_addw(a0, t0, a1);
+            prop_assert_eq!(
+                state.xregisters.read(a1),
+                (v1 as i32).wrapping_add(v2 as i32) as u64
+            );
+            state.xregisters.run_subw(a0, t0, a1);
+            prop_assert_eq!(
+                state.xregisters.read(a1),
+                (v1 as i32).wrapping_sub(v2 as i32) as u64
+            );
+
+            // Only the lower 5 bits of rs2 are the shift amount
+            state.xregisters.run_sllw(a0, t0, a1);
+            prop_assert_eq!(
+                state.xregisters.read(a1),
+                ((v1 as u32) << (v2 & 0x1F)) as i32 as u64
+            );
+            state.xregisters.run_srlw(a0, t0, a1);
+            prop_assert_eq!(
+                state.xregisters.read(a1),
+                ((v1 as u32) >> (v2 & 0x1F)) as i32 as u64
+            );
+            state.xregisters.run_sraw(a0, t0, a1);
+            prop_assert_eq!(
+                state.xregisters.read(a1),
+                ((v1 as i32) >> (v2 & 0x1F)) as u64
+            );
+        });
+    });
 }
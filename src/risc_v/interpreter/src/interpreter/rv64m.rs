@@ -0,0 +1,286 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Implementation of RV_64_M extension for RISC-V
+//!
+//! Chapter 7 - Unprivileged spec
+
+use crate::machine_state::registers::{XRegister, XRegisters};
+use crate::state_backend as backend;
+
+impl<M> XRegisters<M>
+where
+    M: backend::Manager,
+{
+    /// `MUL` R-type instruction
+    ///
+    /// Saves in `rd` the lower XLEN bits of `val(rs1) * val(rs2)`
+    pub fn run_mul(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        // The lower bits are the same irrespective of sign
+        let result = self.read(rs1).wrapping_mul(self.read(rs2));
+        self.write(rd, result)
+    }
+
+    /// `MULH` R-type instruction
+    ///
+    /// Saves in `rd` the upper XLEN bits of `val(rs1) * val(rs2)`,
+    /// both operands being signed
+    pub fn run_mulh(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let lhs = self.read(rs1) as i64 as i128;
+        let rhs = self.read(rs2) as i64 as i128;
+        self.write(rd, ((lhs * rhs) >> 64) as u64)
+    }
+
+    /// `MULHSU` R-type instruction
+    ///
+    /// Saves in `rd` the upper XLEN bits of `val(rs1) * val(rs2)`,
+    /// `val(rs1)` being signed and `val(rs2)` unsigned
+    pub fn run_mulhsu(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let lhs = self.read(rs1) as i64 as i128;
+        let rhs = self.read(rs2) as i128;
+        // The product of a 64-bit signed and a 64-bit unsigned integer
+        // always fits in 128 signed bits
+        self.write(rd, ((lhs * rhs) >> 64) as u64)
+    }
+
+    /// `MULHU` R-type instruction
+    ///
+    /// Saves in `rd` the upper XLEN bits of `val(rs1) * val(rs2)`,
+    /// both operands being unsigned
+    pub fn run_mulhu(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let lhs = self.read(rs1) as u128;
+        let rhs = self.read(rs2) as u128;
+        self.write(rd, ((lhs * rhs) >> 64) as u64)
+    }
+
+    /// `DIV` R-type instruction
+    ///
+    /// Signed division, rounding towards zero.
+    /// Dividing by zero gives -1, and the overflowing `i64::MIN / -1` gives
+    /// `i64::MIN`. (Section 7.2)
+    pub fn run_div(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let lhs = self.read(rs1) as i64;
+        let rhs = self.read(rs2) as i64;
+
+        let result = if rhs == 0 { -1 } else { lhs.wrapping_div(rhs) };
+
+        self.write(rd, result as u64)
+    }
+
+    /// `DIVU` R-type instruction
+    ///
+    /// Unsigned division. Dividing by zero gives `u64::MAX`.
+    pub fn run_divu(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let lhs = self.read(rs1);
+        let rhs = self.read(rs2);
+
+        let result = lhs.checked_div(rhs).unwrap_or(u64::MAX);
+
+        self.write(rd, result)
+    }
+
+    /// `REM` R-type instruction
+    ///
+    /// Signed remainder, whose sign is the sign of the dividend.
+    /// The remainder of dividing by zero is the dividend, and the remainder
+    /// of the overflowing `i64::MIN / -1` is 0. (Section 7.2)
+    pub fn run_rem(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let lhs = self.read(rs1) as i64;
+        let rhs = self.read(rs2) as i64;
+
+        let result = if rhs == 0 { lhs } else { lhs.wrapping_rem(rhs) };
+
+        self.write(rd, result as u64)
+    }
+
+    /// `REMU` R-type instruction
+    ///
+    /// Unsigned remainder. The remainder of dividing by zero is the dividend.
+    pub fn run_remu(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let lhs = self.read(rs1);
+        let rhs = self.read(rs2);
+
+        let result = lhs.checked_rem(rhs).unwrap_or(lhs);
+
+        self.write(rd, result)
+    }
+
+    /// `MULW` R-type instruction
+    ///
+    /// Multiplies the lower 32 bits of both registers, sign-extending the
+    /// lower 32 bits of the result
+    pub fn run_mulw(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let result = (self.read(rs1) as i32).wrapping_mul(self.read(rs2) as i32);
+        self.write(rd, result as u64)
+    }
+
+    /// `DIVW` R-type instruction
+    ///
+    /// Signed division of the lower 32 bits of both registers, sign-extending
+    /// the result. Division by zero and overflow behave as for `DIV`.
+    pub fn run_divw(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let lhs = self.read(rs1) as i32;
+        let rhs = self.read(rs2) as i32;
+
+        let result = if rhs == 0 { -1 } else { lhs.wrapping_div(rhs) };
+
+        self.write(rd, result as u64)
+    }
+
+    /// `DIVUW` R-type instruction
+    ///
+    /// Unsigned division of the lower 32 bits of both registers,
+    /// sign-extending the result. Dividing by zero gives all ones.
+    pub fn run_divuw(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let lhs = self.read(rs1) as u32;
+        let rhs = self.read(rs2) as u32;
+
+        let result = lhs.checked_div(rhs).unwrap_or(u32::MAX);
+
+        // Note: u32 as i32 as u64 will sign-extend the lowest 32 bits
+        self.write(rd, result as i32 as u64)
+    }
+
+    /// `REMW` R-type instruction
+    ///
+    /// Signed remainder of the lower 32 bits of both registers,
+    /// sign-extending the result. Division by zero and overflow behave as
+    /// for `REM`.
+    pub fn run_remw(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let lhs = self.read(rs1) as i32;
+        let rhs = self.read(rs2) as i32;
+
+        let result = if rhs == 0 { lhs } else { lhs.wrapping_rem(rhs) };
+
+        self.write(rd, result as u64)
+    }
+
+    /// `REMUW` R-type instruction
+    ///
+    /// Unsigned remainder of the lower 32 bits of both registers,
+    /// sign-extending the result. The remainder of dividing by zero is the
+    /// dividend.
+    pub fn run_remuw(&mut self, rs1: XRegister, rs2: XRegister, rd: XRegister) {
+        let lhs = self.read(rs1) as u32;
+        let rhs = self.read(rs2) as u32;
+
+        let result = lhs.checked_rem(rhs).unwrap_or(lhs);
+
+        // Note: u32 as i32 as u64 will sign-extend the lowest 32 bits
+        self.write(rd, result as i32 as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend_test, create_backend, create_state,
+        machine_state::registers::{a0, a1, a2, XRegisters, XRegistersLayout},
+    };
+    use proptest::{arbitrary::any, prop_assert_eq, proptest};
+
+    backend_test!(test_mul, F, {
+        proptest!(|(v1 in any::<u64>(), v2 in any::<u64>())| {
+            let mut backend = create_backend!(XRegistersLayout, F);
+            let mut xregs = create_state!(XRegisters, F, backend);
+            xregs.write(a0, v1);
+            xregs.write(a1, v2);
+
+            let signed = (v1 as i64 as i128) * (v2 as i64 as i128);
+            let unsigned = (v1 as u128) * (v2 as u128);
+            let mixed = (v1 as i64 as i128) * (v2 as i128);
+
+            xregs.run_mul(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), unsigned as u64);
+            xregs.run_mulh(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), (signed >> 64) as u64);
+            xregs.run_mulhu(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), (unsigned >> 64) as u64);
+            xregs.run_mulhsu(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), (mixed >> 64) as u64);
+
+            xregs.run_mulw(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), (v1 as i32).wrapping_mul(v2 as i32) as u64);
+        });
+    });
+
+    backend_test!(test_div_rem, F, {
+        proptest!(|(v1 in any::<u64>(), v2 in any::<u64>())| {
+            let mut backend = create_backend!(XRegistersLayout, F);
+            let mut xregs = create_state!(XRegisters, F, backend);
+            xregs.write(a0, v1);
+
+            // Division by a non-zero value
+            let v2 = v2.max(1);
+            xregs.write(a1, v2);
+
+            xregs.run_div(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), (v1 as i64).wrapping_div(v2 as i64) as u64);
+            xregs.run_divu(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), v1 / v2);
+            xregs.run_rem(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), (v1 as i64).wrapping_rem(v2 as i64) as u64);
+            xregs.run_remu(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), v1 % v2);
+
+            // Division by zero
+            xregs.write(a1, 0);
+
+            xregs.run_div(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), u64::MAX);
+            xregs.run_divu(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), u64::MAX);
+            xregs.run_rem(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), v1);
+            xregs.run_remu(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), v1);
+
+            xregs.run_divw(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), u64::MAX);
+            xregs.run_divuw(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), u64::MAX);
+            xregs.run_remw(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), v1 as i32 as u64);
+            xregs.run_remuw(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), v1 as i32 as u64);
+        });
+    });
+
+    backend_test!(test_div_rem_w, F, {
+        proptest!(|(v1 in any::<u64>(), v2 in 1_u32..)| {
+            let mut backend = create_backend!(XRegistersLayout, F);
+            let mut xregs = create_state!(XRegisters, F, backend);
+            xregs.write(a0, v1);
+            // Upper bits are ignored by the 32-bit operations
+            xregs.write(a1, 0xFFFF_FFFF_0000_0000 | v2 as u64);
+
+            xregs.run_divw(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), (v1 as i32).wrapping_div(v2 as i32) as u64);
+            xregs.run_divuw(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), ((v1 as u32) / v2) as i32 as u64);
+            xregs.run_remw(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), (v1 as i32).wrapping_rem(v2 as i32) as u64);
+            xregs.run_remuw(a0, a1, a2);
+            prop_assert_eq!(xregs.read(a2), ((v1 as u32) % v2) as i32 as u64);
+        });
+    });
+
+    backend_test!(test_div_overflow, F, {
+        let mut backend = create_backend!(XRegistersLayout, F);
+        let mut xregs = create_state!(XRegisters, F, backend);
+        xregs.write(a1, -1_i64 as u64);
+
+        xregs.write(a0, i64::MIN as u64);
+        xregs.run_div(a0, a1, a2);
+        assert_eq!(xregs.read(a2), i64::MIN as u64);
+        xregs.run_rem(a0, a1, a2);
+        assert_eq!(xregs.read(a2), 0);
+
+        xregs.write(a0, i32::MIN as u64);
+        xregs.run_divw(a0, a1, a2);
+        assert_eq!(xregs.read(a2), i32::MIN as u64);
+        xregs.run_remw(a0, a1, a2);
+        assert_eq!(xregs.read(a2), 0);
+    });
+}
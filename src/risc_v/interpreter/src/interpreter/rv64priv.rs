@@ -0,0 +1,245 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Implementation of privileged instructions for RISC-V
+//!
+//! Chapter 3.3 - Privileged spec
+
+use crate::machine_state::{
+    bus::Address,
+    csregisters::{
+        xstatus::{self, MPPValue, SPPValue},
+        CSRegister,
+    },
+    mode::Mode,
+    HartState,
+};
+use crate::state_backend as backend;
+use crate::traps::Exception;
+
+impl<M> HartState<M>
+where
+    M: backend::Manager,
+{
+    /// `MRET` instruction
+    ///
+    /// Returns from a trap taken into M-mode: the privilege mode is restored
+    /// from `mstatus.MPP` and the address to continue from (`mepc`) is returned.
+    pub fn run_mret(&mut self) -> Result<Address, Exception> {
+        if self.mode.read() < Mode::Machine {
+            return Err(Exception::IllegalInstruction);
+        }
+
+        let mstatus = self.csregisters.read(CSRegister::mstatus);
+        let mpp = xstatus::get_MPP(mstatus);
+
+        // MIE is restored from MPIE, which is then set, and MPP is set to the
+        // least-privileged supported mode. (Section 3.1.6.1)
+        let mstatus = xstatus::set_MIE(mstatus, xstatus::get_MPIE(mstatus));
+        let mstatus = xstatus::set_MPIE(mstatus, true);
+        let mstatus = xstatus::set_MPP(mstatus, MPPValue::User);
+
+        // Returning to a mode other than M clears MPRV
+        let mstatus = if mpp != MPPValue::Machine {
+            xstatus::set_MPRV(mstatus, false)
+        } else {
+            mstatus
+        };
+        self.csregisters.write(CSRegister::mstatus, mstatus);
+
+        self.mode.write(match mpp {
+            MPPValue::User => Mode::User,
+            MPPValue::Supervisor => Mode::Supervisor,
+            MPPValue::Machine => Mode::Machine,
+        });
+
+        // IALIGN = 16 because of the C extension, mepc[0] is always 0
+        Ok(self.csregisters.read(CSRegister::mepc) & !1)
+    }
+
+    /// `SRET` instruction
+    ///
+    /// Returns from a trap taken into S-mode: the privilege mode is restored
+    /// from `sstatus.SPP` and the address to continue from (`sepc`) is returned.
+    pub fn run_sret(&mut self) -> Result<Address, Exception> {
+        let mstatus = self.csregisters.read(CSRegister::mstatus);
+
+        // SRET is illegal in U-mode, and in S-mode when mstatus.TSR is set
+        match self.mode.read() {
+            Mode::User => return Err(Exception::IllegalInstruction),
+            Mode::Supervisor if xstatus::get_TSR(mstatus) => {
+                return Err(Exception::IllegalInstruction)
+            }
+            _ => {}
+        }
+
+        let spp = xstatus::get_SPP(mstatus);
+
+        // SIE is restored from SPIE, which is then set, and SPP is set to
+        // U-mode. SPP is never M-mode, hence MPRV is always cleared.
+        // (Section 3.1.6.1)
+        let mstatus = xstatus::set_SIE(mstatus, xstatus::get_SPIE(mstatus));
+        let mstatus = xstatus::set_SPIE(mstatus, true);
+        let mstatus = xstatus::set_SPP(mstatus, SPPValue::User);
+        let mstatus = xstatus::set_MPRV(mstatus, false);
+        self.csregisters.write(CSRegister::mstatus, mstatus);
+
+        self.mode.write(match spp {
+            SPPValue::User => Mode::User,
+            SPPValue::Supervisor => Mode::Supervisor,
+        });
+
+        // IALIGN = 16 because of the C extension, sepc[0] is always 0
+        Ok(self.csregisters.read(CSRegister::sepc) & !1)
+    }
+
+    /// `WFI` instruction
+    ///
+    /// The hart has no interrupt sources, so waiting is a no-op. It is still
+    /// illegal below M-mode when `mstatus.TW` is set. (Section 3.1.6.5)
+    pub fn run_wfi(&mut self) -> Result<(), Exception> {
+        let mstatus = self.csregisters.read(CSRegister::mstatus);
+
+        if self.mode.read() < Mode::Machine && xstatus::get_TW(mstatus) {
+            return Err(Exception::IllegalInstruction);
+        }
+
+        Ok(())
+    }
+
+    /// `SFENCE.VMA` instruction
+    ///
+    /// Address translation has no caches, page-table updates are always
+    /// visible to the next access: the fence itself has no effect. It is
+    /// still illegal in U-mode, and in S-mode when `mstatus.TVM` is set.
+    /// (Section 3.1.6.5)
+    pub fn run_sfence_vma(&mut self) -> Result<(), Exception> {
+        match self.mode.read() {
+            Mode::User => Err(Exception::IllegalInstruction),
+            Mode::Supervisor if xstatus::get_TVM(self.csregisters.read(CSRegister::mstatus)) => {
+                Err(Exception::IllegalInstruction)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        backend_test, create_backend, create_state,
+        machine_state::{
+            csregisters::{
+                xstatus::{self, MPPValue, SPPValue},
+                CSRegister,
+            },
+            mode::Mode,
+            registers::a0,
+            HartState, HartStateLayout,
+        },
+        traps::Exception,
+    };
+    use proptest::{arbitrary::any, prop_assert_eq, proptest};
+
+    backend_test!(test_mret, F, {
+        proptest!(|(mepc in any::<u64>())| {
+            let mut backend = create_backend!(HartStateLayout, F);
+            let mut state = create_state!(HartState, F, backend);
+            state.reset(Mode::Machine, 0);
+
+            let mstatus = state.csregisters.read(CSRegister::mstatus);
+            let mstatus = xstatus::set_MPP(mstatus, MPPValue::Supervisor);
+            let mstatus = xstatus::set_MPIE(mstatus, true);
+            let mstatus = xstatus::set_MPRV(mstatus, true);
+            state.csregisters.write(CSRegister::mstatus, mstatus);
+            state.csregisters.write(CSRegister::mepc, mepc);
+
+            prop_assert_eq!(state.run_mret(), Ok(mepc & !1));
+            prop_assert_eq!(state.mode.read(), Mode::Supervisor);
+
+            let mstatus = state.csregisters.read(CSRegister::mstatus);
+            prop_assert_eq!(xstatus::get_MIE(mstatus), true);
+            prop_assert_eq!(xstatus::get_MPIE(mstatus), true);
+            prop_assert_eq!(xstatus::get_MPRV(mstatus), false);
+            prop_assert_eq!(xstatus::get_MPP(mstatus), MPPValue::User);
+
+            // MRET is only available in M-mode
+            prop_assert_eq!(state.run_mret(), Err(Exception::IllegalInstruction));
+        });
+    });
+
+    backend_test!(test_sret, F, {
+        proptest!(|(sepc in any::<u64>())| {
+            let mut backend = create_backend!(HartStateLayout, F);
+            let mut state = create_state!(HartState, F, backend);
+            state.reset(Mode::Supervisor, 0);
+
+            let mstatus = state.csregisters.read(CSRegister::mstatus);
+            let mstatus = xstatus::set_SPP(mstatus, SPPValue::User);
+            let mstatus = xstatus::set_SPIE(mstatus, false);
+            let mstatus = xstatus::set_SIE(mstatus, true);
+            state.csregisters.write(CSRegister::mstatus, mstatus);
+            state.csregisters.write(CSRegister::sepc, sepc);
+
+            prop_assert_eq!(state.run_sret(), Ok(sepc & !1));
+            prop_assert_eq!(state.mode.read(), Mode::User);
+
+            let mstatus = state.csregisters.read(CSRegister::mstatus);
+            prop_assert_eq!(xstatus::get_SIE(mstatus), false);
+            prop_assert_eq!(xstatus::get_SPIE(mstatus), true);
+
+            // SRET is not available in U-mode
+            prop_assert_eq!(state.run_sret(), Err(Exception::IllegalInstruction));
+
+            // SRET is trapped in S-mode when TSR is set
+            state.mode.write(Mode::Supervisor);
+            let mstatus = xstatus::set_TSR(mstatus, true);
+            state.csregisters.write(CSRegister::mstatus, mstatus);
+            prop_assert_eq!(state.run_sret(), Err(Exception::IllegalInstruction));
+        });
+    });
+
+    backend_test!(test_wfi, F, {
+        let mut backend = create_backend!(HartStateLayout, F);
+        let mut state = create_state!(HartState, F, backend);
+        state.reset(Mode::Supervisor, 0);
+
+        assert_eq!(state.run_wfi(), Ok(()));
+
+        let mstatus = state.csregisters.read(CSRegister::mstatus);
+        state
+            .csregisters
+            .write(CSRegister::mstatus, xstatus::set_TW(mstatus, true));
+        assert_eq!(state.run_wfi(), Err(Exception::IllegalInstruction));
+
+        state.mode.write(Mode::Machine);
+        assert_eq!(state.run_wfi(), Ok(()));
+    });
+
+    backend_test!(test_sfence_vma, F, {
+        let mut backend = create_backend!(HartStateLayout, F);
+        let mut state = create_state!(HartState, F, backend);
+        state.reset(Mode::Supervisor, 0);
+
+        assert_eq!(state.run_sfence_vma(), Ok(()));
+
+        // TVM traps SFENCE.VMA and accesses to satp in S-mode
+        let mstatus = state.csregisters.read(CSRegister::mstatus);
+        state
+            .csregisters
+            .write(CSRegister::mstatus, xstatus::set_TVM(mstatus, true));
+        assert_eq!(state.run_sfence_vma(), Err(Exception::IllegalInstruction));
+        assert_eq!(
+            state.csrrs(CSRegister::satp, a0, a0),
+            Err(Exception::IllegalInstruction)
+        );
+
+        state.mode.write(Mode::Machine);
+        assert_eq!(state.run_sfence_vma(), Ok(()));
+        assert_eq!(state.csrrs(CSRegister::satp, a0, a0), Ok(()));
+
+        state.mode.write(Mode::User);
+        assert_eq!(state.run_sfence_vma(), Err(Exception::IllegalInstruction));
+    });
+}
@@ -1,19 +1,7 @@
 mod interpreter;
 pub mod machine_state;
 pub mod parser;
+pub mod pvm;
+pub mod softfloat;
 pub mod state_backend;
-
-pub fn add(left: usize, right: usize) -> usize {
-    left + right
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
+pub mod traps;
@@ -1,19 +1,34 @@
-// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+// SPDX-FileCopyrightText: 2023-2024 TriliTech <contact@trili.tech>
 //
 // SPDX-License-Identifier: MIT
 
 #![deny(rustdoc::broken_intra_doc_links)]
 
+pub mod address_translation;
 pub mod bus;
 pub mod csregisters;
-mod mode;
+pub mod mode;
 pub mod registers;
 
 #[cfg(test)]
 extern crate proptest;
 
+use crate::parser::{self, instruction::Instr};
 use crate::state_backend::{self as backend, Atom, Cell};
-use bus::{main_memory, Address, Bus};
+use crate::traps::{EnvironException, Exception};
+use address_translation::AccessType;
+use bus::{main_memory, Address, Addressable, Bus};
+use csregisters::{
+    xstatus::{self, MPPValue, SPPValue},
+    CSRegister,
+};
+use registers::XRegister;
+use std::mem;
+
+/// Value of [HartState::reservation_set] when no address is reserved
+///
+/// Reserved addresses are always aligned, hence never odd.
+pub const NO_RESERVATION: Address = Address::MAX;
 
 /// RISC-V hart state
 pub struct HartState<M: backend::Manager> {
@@ -31,9 +46,48 @@ pub struct HartState<M: backend::Manager> {
 
     /// Program counter
     pub pc: Cell<Address, M>,
+
+    /// Address reserved by the last `LR` instruction, if any
+    pub reservation_set: Cell<Address, M>,
 }
 
 impl<M: backend::Manager> HartState<M> {
+    /// Checks that the floating-point unit is enabled, i.e. `mstatus.FS` is not Off.
+    ///
+    /// Throws [`Exception::IllegalInstruction`] otherwise.
+    /// Section 3.1.6.6 - privileged spec
+    #[inline(always)]
+    pub fn check_fs_on(&mut self) -> Result<(), Exception> {
+        let mstatus = self.csregisters.read(CSRegister::mstatus);
+        if xstatus::get_FS(mstatus) == xstatus::ExtensionValue::Off {
+            return Err(Exception::IllegalInstruction);
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the current mode can access `csr`. The floating-point
+    /// CSRs are not accessible while the floating-point unit is disabled,
+    /// and `satp` is not accessible from S-mode when `mstatus.TVM` is set.
+    #[inline(always)]
+    fn check_csr_access(&mut self, csr: csregisters::CSRegister) -> csregisters::Result<()> {
+        let mode = self.mode.read();
+        csregisters::check_privilege(csr, mode)?;
+
+        if csr == CSRegister::satp && mode == mode::Mode::Supervisor {
+            let mstatus = self.csregisters.read(CSRegister::mstatus);
+            if xstatus::get_TVM(mstatus) {
+                return Err(Exception::IllegalInstruction);
+            }
+        }
+
+        if csr.is_floating_point() {
+            self.check_fs_on()?;
+        }
+
+        Ok(())
+    }
+
     /// Execute a CSRRW instruction.
     #[inline(always)]
     pub fn csrrw(
@@ -66,8 +120,7 @@ impl<M: backend::Manager> HartState<M> {
         value: registers::XValue,
         rd: registers::XRegister,
     ) -> csregisters::Result<()> {
-        let mode = self.mode.read();
-        csregisters::check_privilege(csr, mode)?;
+        self.check_csr_access(csr)?;
         csregisters::check_write(csr)?;
 
         // When `rd = x0`, we don't want to trigger any CSR read effects.
@@ -88,8 +141,7 @@ impl<M: backend::Manager> HartState<M> {
         rs1: registers::XRegister,
         rd: registers::XRegister,
     ) -> csregisters::Result<()> {
-        let mode = self.mode.read();
-        csregisters::check_privilege(csr, mode)?;
+        self.check_csr_access(csr)?;
 
         // When `rs1 = x0`, we don't want to trigger any CSR write effects.
         let old = if rs1.is_zero() {
@@ -114,8 +166,7 @@ impl<M: backend::Manager> HartState<M> {
         rd: registers::XRegister,
     ) -> csregisters::Result<()> {
         let imm = imm & 0b11111;
-        let mode = self.mode.read();
-        csregisters::check_privilege(csr, mode)?;
+        self.check_csr_access(csr)?;
 
         // When `imm = 0`, we don't want to trigger any CSR write effects.
         let old = if imm == 0 {
@@ -138,8 +189,7 @@ impl<M: backend::Manager> HartState<M> {
         rs1: registers::XRegister,
         rd: registers::XRegister,
     ) -> csregisters::Result<()> {
-        let mode = self.mode.read();
-        csregisters::check_privilege(csr, mode)?;
+        self.check_csr_access(csr)?;
 
         // When `rs1 = x0`, we don't want to trigger any CSR write effects.
         let old = if rs1.is_zero() {
@@ -164,8 +214,7 @@ impl<M: backend::Manager> HartState<M> {
         rd: registers::XRegister,
     ) -> csregisters::Result<()> {
         let imm = imm & 0b11111;
-        let mode = self.mode.read();
-        csregisters::check_privilege(csr, mode)?;
+        self.check_csr_access(csr)?;
 
         // When `imm = 0`, we don't want to trigger any CSR write effects.
         let old = if imm == 0 {
@@ -181,6 +230,62 @@ impl<M: backend::Manager> HartState<M> {
     }
 }
 
+impl<M: backend::Manager> HartState<M> {
+    /// Take a trap for the synchronous `exception` raised by the instruction
+    /// at `current_pc`, returning the address of the trap handler.
+    ///
+    /// Exceptions raised in U-mode or S-mode are handled in S-mode if they
+    /// are delegated through `medeleg`, in M-mode otherwise.
+    /// Sections 3.1.8, 3.1.6.1 & 3.3.1 - privileged spec
+    pub fn take_trap(&mut self, exception: Exception, current_pc: Address) -> Address {
+        let mode = self.mode.read();
+        let code = exception.exception_code();
+        let medeleg = self.csregisters.read(CSRegister::medeleg);
+        let mstatus = self.csregisters.read(CSRegister::mstatus);
+
+        if mode <= mode::Mode::Supervisor && (medeleg >> code) & 1 == 1 {
+            self.csregisters.write(CSRegister::sepc, current_pc);
+            self.csregisters.write(CSRegister::scause, code);
+            self.csregisters
+                .write(CSRegister::stval, exception.trap_value());
+
+            let spp = match mode {
+                mode::Mode::User => SPPValue::User,
+                _ => SPPValue::Supervisor,
+            };
+            let mstatus = xstatus::set_SPIE(mstatus, xstatus::get_SIE(mstatus));
+            let mstatus = xstatus::set_SIE(mstatus, false);
+            let mstatus = xstatus::set_SPP(mstatus, spp);
+            self.csregisters.write(CSRegister::mstatus, mstatus);
+
+            self.mode.write(mode::Mode::Supervisor);
+
+            // Synchronous exceptions always jump to BASE, even in vectored mode
+            self.csregisters.read(CSRegister::stvec) & !0b11
+        } else {
+            self.csregisters.write(CSRegister::mepc, current_pc);
+            self.csregisters.write(CSRegister::mcause, code);
+            self.csregisters
+                .write(CSRegister::mtval, exception.trap_value());
+
+            let mpp = match mode {
+                mode::Mode::User => MPPValue::User,
+                mode::Mode::Supervisor => MPPValue::Supervisor,
+                mode::Mode::Machine | mode::Mode::Debug => MPPValue::Machine,
+            };
+            let mstatus = xstatus::set_MPIE(mstatus, xstatus::get_MIE(mstatus));
+            let mstatus = xstatus::set_MIE(mstatus, false);
+            let mstatus = xstatus::set_MPP(mstatus, mpp);
+            self.csregisters.write(CSRegister::mstatus, mstatus);
+
+            self.mode.write(mode::Mode::Machine);
+
+            // Synchronous exceptions always jump to BASE, even in vectored mode
+            self.csregisters.read(CSRegister::mtvec) & !0b11
+        }
+    }
+}
+
 /// Layout of [HartState]
 pub type HartStateLayout = (
     registers::XRegistersLayout,
@@ -188,6 +293,7 @@ pub type HartStateLayout = (
     csregisters::CSRegistersLayout,
     mode::ModeLayout,
     Atom<Address>, // Program counter layout
+    Atom<Address>, // Reservation set layout
 );
 
 impl<M: backend::Manager> HartState<M> {
@@ -199,6 +305,7 @@ impl<M: backend::Manager> HartState<M> {
             csregisters: csregisters::CSRegisters::bind(space.2),
             mode: mode::ModeCell::bind(space.3),
             pc: Cell::bind(space.4),
+            reservation_set: Cell::bind(space.5),
         }
     }
 
@@ -209,12 +316,32 @@ impl<M: backend::Manager> HartState<M> {
         self.csregisters.reset();
         self.mode.reset(mode);
         self.pc.write(pc);
+        self.reservation_set.write(NO_RESERVATION);
     }
 }
 
 /// Layout for the machine state
 pub type MachineStateLayout<ML> = (HartStateLayout, bus::BusLayout<ML>);
 
+/// How to update the program counter after running an instruction
+enum ProgramCounterUpdate {
+    /// Jump to the given address
+    Set(Address),
+    /// Move to the instruction that is the given number of bytes ahead
+    Add(u64),
+}
+
+/// Outcome of [MachineState::step]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepResult {
+    /// Number of instructions that were executed
+    pub steps: usize,
+
+    /// Exception which must be handled by the execution environment before
+    /// resuming execution, if it interrupted the run
+    pub exception: Option<EnvironException>,
+}
+
 /// Machine state
 pub struct MachineState<ML: main_memory::MainMemoryLayout, M: backend::Manager> {
     pub hart: HartState<M>,
@@ -235,18 +362,398 @@ impl<ML: main_memory::MainMemoryLayout, M: backend::Manager> MachineState<ML, M>
         self.hart.reset(mode, pc);
         self.bus.reset();
     }
+
+    /// Read a value from the virtual address `address`, for an access of
+    /// type `access`.
+    #[inline(always)]
+    pub fn read_virtual<E: backend::Elem>(
+        &mut self,
+        address: Address,
+        access: AccessType,
+    ) -> Result<E, Exception> {
+        let phys_addr = self.translate_access(address, mem::size_of::<E>() as u64, access)?;
+        self.bus
+            .read(phys_addr)
+            .map_err(|_| access.access_fault(address))
+    }
+
+    /// Write a value to the virtual address `address`.
+    #[inline(always)]
+    pub fn write_virtual<E: backend::Elem>(
+        &mut self,
+        address: Address,
+        value: E,
+    ) -> Result<(), Exception> {
+        let phys_addr =
+            self.translate_access(address, mem::size_of::<E>() as u64, AccessType::Store)?;
+        self.bus
+            .write(phys_addr, value)
+            .map_err(|_| Exception::StoreAccessFault(address))
+    }
+
+    /// Read a value from the address `val(rs1) + imm`.
+    #[inline(always)]
+    pub fn read_from_bus<E: backend::Elem>(
+        &mut self,
+        imm: i64,
+        rs1: XRegister,
+    ) -> Result<E, Exception> {
+        let address = self.hart.xregisters.read(rs1).wrapping_add(imm as u64);
+        self.read_virtual(address, AccessType::Load)
+    }
+
+    /// Write a value to the address `val(rs1) + imm`.
+    #[inline(always)]
+    pub fn write_to_bus<E: backend::Elem>(
+        &mut self,
+        imm: i64,
+        rs1: XRegister,
+        value: E,
+    ) -> Result<(), Exception> {
+        let address = self.hart.xregisters.read(rs1).wrapping_add(imm as u64);
+        self.write_virtual(address, value)
+    }
+
+    /// Fetch and parse the instruction at `pc`.
+    ///
+    /// Both halves of an uncompressed instruction are translated
+    /// separately, as they may lie on different pages.
+    fn fetch_instr(&mut self, pc: Address) -> Result<Instr, Exception> {
+        let first: u16 = self.read_virtual(pc, AccessType::Instruction)?;
+
+        parser::parse(first, || {
+            self.read_virtual(pc.wrapping_add(2), AccessType::Instruction)
+        })
+    }
+
+    /// Run an instruction, returning how the program counter must be updated.
+    fn run_instr(&mut self, instr: &Instr) -> Result<ProgramCounterUpdate, Exception> {
+        use ProgramCounterUpdate::{Add, Set};
+
+        let xregs = &mut self.hart.xregisters;
+
+        match instr {
+            // RV64I R-type instructions
+            Instr::Add(args) => xregs.run_add(args.rs1, args.rs2, args.rd),
+            Instr::Sub(args) => xregs.run_sub(args.rs1, args.rs2, args.rd),
+            Instr::Xor(args) => xregs.run_xor(args.rs1, args.rs2, args.rd),
+            Instr::Or(args) => xregs.run_or(args.rs1, args.rs2, args.rd),
+            Instr::And(args) => xregs.run_and(args.rs1, args.rs2, args.rd),
+            Instr::Sll(args) => xregs.run_sll(args.rs1, args.rs2, args.rd),
+            Instr::Srl(args) => xregs.run_srl(args.rs1, args.rs2, args.rd),
+            Instr::Sra(args) => xregs.run_sra(args.rs1, args.rs2, args.rd),
+            Instr::Slt(args) => xregs.run_slt(args.rs1, args.rs2, args.rd),
+            Instr::Sltu(args) => xregs.run_sltu(args.rs1, args.rs2, args.rd),
+            Instr::Addw(args) => xregs.run_addw(args.rs1, args.rs2, args.rd),
+            Instr::Subw(args) => xregs.run_subw(args.rs1, args.rs2, args.rd),
+            Instr::Sllw(args) => xregs.run_sllw(args.rs1, args.rs2, args.rd),
+            Instr::Srlw(args) => xregs.run_srlw(args.rs1, args.rs2, args.rd),
+            Instr::Sraw(args) => xregs.run_sraw(args.rs1, args.rs2, args.rd),
+
+            // RV64I I-type instructions
+            Instr::Addi(args) => xregs.run_addi(args.imm, args.rs1, args.rd),
+            Instr::Addiw(args) => xregs.run_addiw(args.imm, args.rs1, args.rd),
+            Instr::Xori(args) => xregs.run_xori(args.imm, args.rs1, args.rd),
+            Instr::Ori(args) => xregs.run_ori(args.imm, args.rs1, args.rd),
+            Instr::Andi(args) => xregs.run_andi(args.imm, args.rs1, args.rd),
+            Instr::Slli(args) => xregs.run_slli(args.imm, args.rs1, args.rd),
+            Instr::Srli(args) => xregs.run_srli(args.imm, args.rs1, args.rd),
+            Instr::Srai(args) => xregs.run_srai(args.imm, args.rs1, args.rd),
+            Instr::Slliw(args) => xregs.run_slliw(args.imm, args.rs1, args.rd),
+            Instr::Srliw(args) => xregs.run_srliw(args.imm, args.rs1, args.rd),
+            Instr::Sraiw(args) => xregs.run_sraiw(args.imm, args.rs1, args.rd),
+            Instr::Slti(args) => xregs.run_slti(args.imm, args.rs1, args.rd),
+            Instr::Sltiu(args) => xregs.run_sltiu(args.imm, args.rs1, args.rd),
+            Instr::Lb(args) => self.run_lb(args.imm, args.rs1, args.rd)?,
+            Instr::Lh(args) => self.run_lh(args.imm, args.rs1, args.rd)?,
+            Instr::Lw(args) => self.run_lw(args.imm, args.rs1, args.rd)?,
+            Instr::Lbu(args) => self.run_lbu(args.imm, args.rs1, args.rd)?,
+            Instr::Lhu(args) => self.run_lhu(args.imm, args.rs1, args.rd)?,
+            Instr::Lwu(args) => self.run_lwu(args.imm, args.rs1, args.rd)?,
+            Instr::Ld(args) => self.run_ld(args.imm, args.rs1, args.rd)?,
+
+            // The hart has no caches and is the only one of the machine,
+            // hence fences have no effect.
+            Instr::Fence(_) | Instr::FenceI => {}
+            Instr::Ecall => return Err(self.hart.run_ecall()),
+            Instr::Ebreak => return Err(Exception::Breakpoint),
+
+            // RV64I S-type instructions
+            Instr::Sb(args) => self.run_sb(args.imm, args.rs1, args.rs2)?,
+            Instr::Sh(args) => self.run_sh(args.imm, args.rs1, args.rs2)?,
+            Instr::Sw(args) => self.run_sw(args.imm, args.rs1, args.rs2)?,
+            Instr::Sd(args) => self.run_sd(args.imm, args.rs1, args.rs2)?,
+
+            // RV64I B-type instructions
+            Instr::Beq(args) => return Ok(Set(self.hart.run_beq(args.imm, args.rs1, args.rs2))),
+            Instr::Bne(args) => return Ok(Set(self.hart.run_bne(args.imm, args.rs1, args.rs2))),
+            Instr::Blt(args) => return Ok(Set(self.hart.run_blt(args.imm, args.rs1, args.rs2))),
+            Instr::Bge(args) => return Ok(Set(self.hart.run_bge(args.imm, args.rs1, args.rs2))),
+            Instr::Bltu(args) => return Ok(Set(self.hart.run_bltu(args.imm, args.rs1, args.rs2))),
+            Instr::Bgeu(args) => return Ok(Set(self.hart.run_bgeu(args.imm, args.rs1, args.rs2))),
+
+            // RV64I U-type instructions
+            Instr::Lui(args) => xregs.run_lui(args.imm, args.rd),
+            Instr::Auipc(args) => self.hart.run_auipc(args.imm, args.rd),
+
+            // RV64I jump instructions
+            Instr::Jal(args) => return Ok(Set(self.hart.run_jal(args.imm, args.rd))),
+            Instr::Jalr(args) => return Ok(Set(self.hart.run_jalr(args.imm, args.rs1, args.rd))),
+
+            // RV64M instructions
+            Instr::Mul(args) => xregs.run_mul(args.rs1, args.rs2, args.rd),
+            Instr::Mulh(args) => xregs.run_mulh(args.rs1, args.rs2, args.rd),
+            Instr::Mulhsu(args) => xregs.run_mulhsu(args.rs1, args.rs2, args.rd),
+            Instr::Mulhu(args) => xregs.run_mulhu(args.rs1, args.rs2, args.rd),
+            Instr::Div(args) => xregs.run_div(args.rs1, args.rs2, args.rd),
+            Instr::Divu(args) => xregs.run_divu(args.rs1, args.rs2, args.rd),
+            Instr::Rem(args) => xregs.run_rem(args.rs1, args.rs2, args.rd),
+            Instr::Remu(args) => xregs.run_remu(args.rs1, args.rs2, args.rd),
+            Instr::Mulw(args) => xregs.run_mulw(args.rs1, args.rs2, args.rd),
+            Instr::Divw(args) => xregs.run_divw(args.rs1, args.rs2, args.rd),
+            Instr::Divuw(args) => xregs.run_divuw(args.rs1, args.rs2, args.rd),
+            Instr::Remw(args) => xregs.run_remw(args.rs1, args.rs2, args.rd),
+            Instr::Remuw(args) => xregs.run_remuw(args.rs1, args.rs2, args.rd),
+
+            // RV64A instructions
+            Instr::Lrw(args) => self.run_lrw(args.rs1, args.rd)?,
+            Instr::Scw(args) => self.run_scw(args.rs1, args.rs2, args.rd)?,
+            Instr::Amoswapw(args) => self.run_amoswapw(args.rs1, args.rs2, args.rd)?,
+            Instr::Amoaddw(args) => self.run_amoaddw(args.rs1, args.rs2, args.rd)?,
+            Instr::Amoxorw(args) => self.run_amoxorw(args.rs1, args.rs2, args.rd)?,
+            Instr::Amoandw(args) => self.run_amoandw(args.rs1, args.rs2, args.rd)?,
+            Instr::Amoorw(args) => self.run_amoorw(args.rs1, args.rs2, args.rd)?,
+            Instr::Amominw(args) => self.run_amominw(args.rs1, args.rs2, args.rd)?,
+            Instr::Amomaxw(args) => self.run_amomaxw(args.rs1, args.rs2, args.rd)?,
+            Instr::Amominuw(args) => self.run_amominuw(args.rs1, args.rs2, args.rd)?,
+            Instr::Amomaxuw(args) => self.run_amomaxuw(args.rs1, args.rs2, args.rd)?,
+            Instr::Lrd(args) => self.run_lrd(args.rs1, args.rd)?,
+            Instr::Scd(args) => self.run_scd(args.rs1, args.rs2, args.rd)?,
+            Instr::Amoswapd(args) => self.run_amoswapd(args.rs1, args.rs2, args.rd)?,
+            Instr::Amoaddd(args) => self.run_amoaddd(args.rs1, args.rs2, args.rd)?,
+            Instr::Amoxord(args) => self.run_amoxord(args.rs1, args.rs2, args.rd)?,
+            Instr::Amoandd(args) => self.run_amoandd(args.rs1, args.rs2, args.rd)?,
+            Instr::Amoord(args) => self.run_amoord(args.rs1, args.rs2, args.rd)?,
+            Instr::Amomind(args) => self.run_amomind(args.rs1, args.rs2, args.rd)?,
+            Instr::Amomaxd(args) => self.run_amomaxd(args.rs1, args.rs2, args.rd)?,
+            Instr::Amominud(args) => self.run_amominud(args.rs1, args.rs2, args.rd)?,
+            Instr::Amomaxud(args) => self.run_amomaxud(args.rs1, args.rs2, args.rd)?,
+
+            // RV64F instructions
+            Instr::Flw(args) => self.run_flw(args.imm, args.rs1, args.rd)?,
+            Instr::Fsw(args) => self.run_fsw(args.imm, args.rs1, args.rs2)?,
+            Instr::Fadds(args) => self.hart.run_fadds(args.rs1, args.rs2, args.rm, args.rd)?,
+            Instr::Fsubs(args) => self.hart.run_fsubs(args.rs1, args.rs2, args.rm, args.rd)?,
+            Instr::Fmuls(args) => self.hart.run_fmuls(args.rs1, args.rs2, args.rm, args.rd)?,
+            Instr::Fdivs(args) => self.hart.run_fdivs(args.rs1, args.rs2, args.rm, args.rd)?,
+            Instr::Fsqrts(args) => self.hart.run_fsqrts(args.rs1, args.rm, args.rd)?,
+            Instr::Fmins(args) => self.hart.run_fmins(args.rs1, args.rs2, args.rd)?,
+            Instr::Fmaxs(args) => self.hart.run_fmaxs(args.rs1, args.rs2, args.rd)?,
+            Instr::Fmadds(args) => self
+                .hart
+                .run_fmadds(args.rs1, args.rs2, args.rs3, args.rm, args.rd)?,
+            Instr::Fmsubs(args) => self
+                .hart
+                .run_fmsubs(args.rs1, args.rs2, args.rs3, args.rm, args.rd)?,
+            Instr::Fnmsubs(args) => self
+                .hart
+                .run_fnmsubs(args.rs1, args.rs2, args.rs3, args.rm, args.rd)?,
+            Instr::Fnmadds(args) => self
+                .hart
+                .run_fnmadds(args.rs1, args.rs2, args.rs3, args.rm, args.rd)?,
+            Instr::Fsgnjs(args) => self.hart.run_fsgnjs(args.rs1, args.rs2, args.rd)?,
+            Instr::Fsgnjns(args) => self.hart.run_fsgnjns(args.rs1, args.rs2, args.rd)?,
+            Instr::Fsgnjxs(args) => self.hart.run_fsgnjxs(args.rs1, args.rs2, args.rd)?,
+            Instr::Feqs(args) => self.hart.run_feqs(args.rs1, args.rs2, args.rd)?,
+            Instr::Flts(args) => self.hart.run_flts(args.rs1, args.rs2, args.rd)?,
+            Instr::Fles(args) => self.hart.run_fles(args.rs1, args.rs2, args.rd)?,
+            Instr::Fclasss(args) => self.hart.run_fclasss(args.rs1, args.rd)?,
+            Instr::Fmvxw(args) => self.hart.run_fmvxw(args.rs1, args.rd)?,
+            Instr::Fmvwx(args) => self.hart.run_fmvwx(args.rs1, args.rd)?,
+            Instr::Fcvtws(args) => self.hart.run_fcvtws(args.rs1, args.rm, args.rd)?,
+            Instr::Fcvtwus(args) => self.hart.run_fcvtwus(args.rs1, args.rm, args.rd)?,
+            Instr::Fcvtls(args) => self.hart.run_fcvtls(args.rs1, args.rm, args.rd)?,
+            Instr::Fcvtlus(args) => self.hart.run_fcvtlus(args.rs1, args.rm, args.rd)?,
+            Instr::Fcvtsw(args) => self.hart.run_fcvtsw(args.rs1, args.rm, args.rd)?,
+            Instr::Fcvtswu(args) => self.hart.run_fcvtswu(args.rs1, args.rm, args.rd)?,
+            Instr::Fcvtsl(args) => self.hart.run_fcvtsl(args.rs1, args.rm, args.rd)?,
+            Instr::Fcvtslu(args) => self.hart.run_fcvtslu(args.rs1, args.rm, args.rd)?,
+
+            // RV64D instructions
+            Instr::Fld(args) => self.run_fld(args.imm, args.rs1, args.rd)?,
+            Instr::Fsd(args) => self.run_fsd(args.imm, args.rs1, args.rs2)?,
+            Instr::Faddd(args) => self.hart.run_faddd(args.rs1, args.rs2, args.rm, args.rd)?,
+            Instr::Fsubd(args) => self.hart.run_fsubd(args.rs1, args.rs2, args.rm, args.rd)?,
+            Instr::Fmuld(args) => self.hart.run_fmuld(args.rs1, args.rs2, args.rm, args.rd)?,
+            Instr::Fdivd(args) => self.hart.run_fdivd(args.rs1, args.rs2, args.rm, args.rd)?,
+            Instr::Fsqrtd(args) => self.hart.run_fsqrtd(args.rs1, args.rm, args.rd)?,
+            Instr::Fmind(args) => self.hart.run_fmind(args.rs1, args.rs2, args.rd)?,
+            Instr::Fmaxd(args) => self.hart.run_fmaxd(args.rs1, args.rs2, args.rd)?,
+            Instr::Fmaddd(args) => self
+                .hart
+                .run_fmaddd(args.rs1, args.rs2, args.rs3, args.rm, args.rd)?,
+            Instr::Fmsubd(args) => self
+                .hart
+                .run_fmsubd(args.rs1, args.rs2, args.rs3, args.rm, args.rd)?,
+            Instr::Fnmsubd(args) => self
+                .hart
+                .run_fnmsubd(args.rs1, args.rs2, args.rs3, args.rm, args.rd)?,
+            Instr::Fnmaddd(args) => self
+                .hart
+                .run_fnmaddd(args.rs1, args.rs2, args.rs3, args.rm, args.rd)?,
+            Instr::Fsgnjd(args) => self.hart.run_fsgnjd(args.rs1, args.rs2, args.rd)?,
+            Instr::Fsgnjnd(args) => self.hart.run_fsgnjnd(args.rs1, args.rs2, args.rd)?,
+            Instr::Fsgnjxd(args) => self.hart.run_fsgnjxd(args.rs1, args.rs2, args.rd)?,
+            Instr::Feqd(args) => self.hart.run_feqd(args.rs1, args.rs2, args.rd)?,
+            Instr::Fltd(args) => self.hart.run_fltd(args.rs1, args.rs2, args.rd)?,
+            Instr::Fled(args) => self.hart.run_fled(args.rs1, args.rs2, args.rd)?,
+            Instr::Fclassd(args) => self.hart.run_fclassd(args.rs1, args.rd)?,
+            Instr::Fmvxd(args) => self.hart.run_fmvxd(args.rs1, args.rd)?,
+            Instr::Fmvdx(args) => self.hart.run_fmvdx(args.rs1, args.rd)?,
+            Instr::Fcvtwd(args) => self.hart.run_fcvtwd(args.rs1, args.rm, args.rd)?,
+            Instr::Fcvtwud(args) => self.hart.run_fcvtwud(args.rs1, args.rm, args.rd)?,
+            Instr::Fcvtld(args) => self.hart.run_fcvtld(args.rs1, args.rm, args.rd)?,
+            Instr::Fcvtlud(args) => self.hart.run_fcvtlud(args.rs1, args.rm, args.rd)?,
+            Instr::Fcvtdw(args) => self.hart.run_fcvtdw(args.rs1, args.rm, args.rd)?,
+            Instr::Fcvtdwu(args) => self.hart.run_fcvtdwu(args.rs1, args.rm, args.rd)?,
+            Instr::Fcvtdl(args) => self.hart.run_fcvtdl(args.rs1, args.rm, args.rd)?,
+            Instr::Fcvtdlu(args) => self.hart.run_fcvtdlu(args.rs1, args.rm, args.rd)?,
+            Instr::Fcvtsd(args) => self.hart.run_fcvtsd(args.rs1, args.rm, args.rd)?,
+            Instr::Fcvtds(args) => self.hart.run_fcvtds(args.rs1, args.rm, args.rd)?,
+
+            // RV64C instructions, expanded to their base equivalent
+            Instr::CAddi4spn(args)
+            | Instr::CAddi(args)
+            | Instr::CAddi16sp(args)
+            | Instr::CLi(args) => xregs.run_addi(args.imm, args.rs1, args.rd),
+            Instr::CAddiw(args) => xregs.run_addiw(args.imm, args.rs1, args.rd),
+            Instr::CLw(args) | Instr::CLwsp(args) => self.run_lw(args.imm, args.rs1, args.rd)?,
+            Instr::CLd(args) | Instr::CLdsp(args) => self.run_ld(args.imm, args.rs1, args.rd)?,
+            Instr::CSw(args) | Instr::CSwsp(args) => self.run_sw(args.imm, args.rs1, args.rs2)?,
+            Instr::CSd(args) | Instr::CSdsp(args) => self.run_sd(args.imm, args.rs1, args.rs2)?,
+            Instr::CFld(args) | Instr::CFldsp(args) => self.run_fld(args.imm, args.rs1, args.rd)?,
+            Instr::CFsd(args) | Instr::CFsdsp(args) => {
+                self.run_fsd(args.imm, args.rs1, args.rs2)?
+            }
+            Instr::CNop => {}
+            Instr::CLui(args) => xregs.run_lui(args.imm, args.rd),
+            Instr::CSrli(args) => xregs.run_srli(args.imm, args.rs1, args.rd),
+            Instr::CSrai(args) => xregs.run_srai(args.imm, args.rs1, args.rd),
+            Instr::CSlli(args) => xregs.run_slli(args.imm, args.rs1, args.rd),
+            Instr::CAndi(args) => xregs.run_andi(args.imm, args.rs1, args.rd),
+            Instr::CSub(args) => xregs.run_sub(args.rs1, args.rs2, args.rd),
+            Instr::CXor(args) => xregs.run_xor(args.rs1, args.rs2, args.rd),
+            Instr::COr(args) => xregs.run_or(args.rs1, args.rs2, args.rd),
+            Instr::CAnd(args) => xregs.run_and(args.rs1, args.rs2, args.rd),
+            Instr::CSubw(args) => xregs.run_subw(args.rs1, args.rs2, args.rd),
+            Instr::CAddw(args) => xregs.run_addw(args.rs1, args.rs2, args.rd),
+            Instr::CMv(args) | Instr::CAdd(args) => xregs.run_add(args.rs1, args.rs2, args.rd),
+            Instr::CEbreak => return Err(Exception::Breakpoint),
+
+            // RV64C control transfer instructions
+            Instr::CJ { imm } => return Ok(Set(self.hart.run_cj(*imm))),
+            Instr::CJr { rs1 } => return Ok(Set(self.hart.run_cjr(*rs1))),
+            Instr::CJalr { rs1 } => return Ok(Set(self.hart.run_cjalr(*rs1))),
+            Instr::CBeqz { rs1, imm } => return Ok(Set(self.hart.run_cbeqz(*imm, *rs1))),
+            Instr::CBnez { rs1, imm } => return Ok(Set(self.hart.run_cbnez(*imm, *rs1))),
+
+            // Zicsr instructions
+            Instr::Csrrw(args) => self.hart.csrrw(args.csr, args.rs1, args.rd)?,
+            Instr::Csrrs(args) => self.hart.csrrs(args.csr, args.rs1, args.rd)?,
+            Instr::Csrrc(args) => self.hart.csrrc(args.csr, args.rs1, args.rd)?,
+            Instr::Csrrwi(args) => self.hart.csrrwi(args.csr, args.imm, args.rd)?,
+            Instr::Csrrsi(args) => self.hart.csrrsi(args.csr, args.imm, args.rd)?,
+            Instr::Csrrci(args) => self.hart.csrrci(args.csr, args.imm, args.rd)?,
+
+            // Privileged instructions
+            Instr::Mret => return Ok(Set(self.hart.run_mret()?)),
+            Instr::Sret => return Ok(Set(self.hart.run_sret()?)),
+            Instr::Wfi => self.hart.run_wfi()?,
+            Instr::SFenceVma { .. } => self.hart.run_sfence_vma()?,
+
+            Instr::Unknown { instr: _ } | Instr::UnknownCompressed { instr: _ } => {
+                return Err(Exception::IllegalInstruction)
+            }
+        }
+
+        Ok(Add(instr.width()))
+    }
+
+    /// Execute the instruction at the program counter.
+    ///
+    /// Exceptions are delivered to the trap handler of the hart, unless they
+    /// are for the execution environment. In that case, the program counter
+    /// moves past the faulting instruction and the exception is returned.
+    fn step_instr(&mut self) -> Result<(), EnvironException> {
+        let current_pc = self.hart.pc.read();
+
+        let result = self
+            .fetch_instr(current_pc)
+            .and_then(|instr| self.run_instr(&instr));
+
+        let next_pc = match result {
+            Ok(ProgramCounterUpdate::Set(address)) => address,
+            Ok(ProgramCounterUpdate::Add(width)) => current_pc.wrapping_add(width),
+            Err(exception) => match EnvironException::try_from(&exception) {
+                Ok(environ_exception) => {
+                    // Environment calls are never compressed
+                    self.hart.pc.write(current_pc.wrapping_add(4));
+                    return Err(environ_exception);
+                }
+                Err(()) => self.hart.take_trap(exception, current_pc),
+            },
+        };
+
+        self.hart.pc.write(next_pc);
+        Ok(())
+    }
+
+    /// Execute at most `n` instructions.
+    ///
+    /// The run stops early when an exception must be handled by the
+    /// execution environment, see [EnvironException]. The instruction which
+    /// raised it counts as executed.
+    pub fn step(&mut self, n: usize) -> StepResult {
+        for steps in 0..n {
+            if let Err(exception) = self.step_instr() {
+                return StepResult {
+                    steps: steps + 1,
+                    exception: Some(exception),
+                };
+            }
+        }
+
+        StepResult {
+            steps: n,
+            exception: None,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::{
-        backend::tests::{test_determinism, ManagerFor},
-        bus::main_memory::tests::T1K,
-        mode, HartState, HartStateLayout, MachineState, MachineStateLayout,
+        backend::{
+            tests::{test_determinism, ManagerFor},
+            Backend, Layout,
+        },
+        bus::{devices::DEVICES_ADDRESS_SPACE_LENGTH, main_memory::tests::T1K, Addressable},
+        csregisters::{xstatus, CSRegister},
+        mode, registers, HartState, HartStateLayout, MachineState, MachineStateLayout, StepResult,
     };
-    use crate::backend_test;
+    use crate::{backend_test, create_backend, traps::EnvironException};
     use strum::IntoEnumIterator;
 
+    const PROGRAM_START: u64 = DEVICES_ADDRESS_SPACE_LENGTH;
+    const HANDLER_START: u64 = PROGRAM_START + 0x200;
+
+    // Trap handler skipping the faulting instruction
+    //     csrr t0, mepc
+    //     addi t0, t0, 4
+    //     csrw mepc, t0
+    //     mret
+    const SKIP_HANDLER: [u32; 4] = [0x341022f3, 0x00428293, 0x34129073, 0x30200073];
+
     backend_test!(test_hart_state_reset, F, {
         mode::Mode::iter().for_each(|mode: mode::Mode| {
             proptest::proptest!(|(pc: u64)| {
@@ -269,4 +776,225 @@ mod tests {
             });
         });
     });
+
+    macro_rules! load_program {
+        ($state:ident, $address:expr, $program:expr) => {
+            for (i, instr) in $program.iter().enumerate() {
+                $state
+                    .bus
+                    .write($address + (std::mem::size_of_val(instr) * i) as u64, *instr)
+                    .expect("Program out of bounds");
+            }
+        };
+    }
+
+    backend_test!(test_step, F, {
+        let mut backend = create_backend!(MachineStateLayout<T1K>, F);
+        let mut state: MachineState<T1K, ManagerFor<'_, F, MachineStateLayout<T1K>>> =
+            MachineState::bind(
+                backend.allocate(MachineStateLayout::<T1K>::placed().into_location()),
+            );
+        state.reset(mode::Mode::User, PROGRAM_START);
+
+        let program: [u32; 6] = [
+            0x00500513, // li a0, 5
+            0x00700593, // li a1, 7
+            0x00b50633, // add a2, a0, a1
+            0x400002b7, // lui t0, 0x40000
+            0x10c2b023, // sd a2, 256(t0)
+            0x1002b683, // ld a3, 256(t0)
+        ];
+        load_program!(state, PROGRAM_START, program);
+
+        let result = state.step(4);
+        assert_eq!(
+            result,
+            StepResult {
+                steps: 4,
+                exception: None
+            }
+        );
+        assert_eq!(state.hart.xregisters.read(registers::a2), 12);
+        assert_eq!(state.hart.xregisters.read(registers::t0), PROGRAM_START);
+
+        let result = state.step(2);
+        assert_eq!(
+            result,
+            StepResult {
+                steps: 2,
+                exception: None
+            }
+        );
+        assert_eq!(state.hart.xregisters.read(registers::a3), 12);
+        assert_eq!(state.hart.pc.read(), PROGRAM_START + 24);
+    });
+
+    backend_test!(test_step_compressed, F, {
+        let mut backend = create_backend!(MachineStateLayout<T1K>, F);
+        let mut state: MachineState<T1K, ManagerFor<'_, F, MachineStateLayout<T1K>>> =
+            MachineState::bind(
+                backend.allocate(MachineStateLayout::<T1K>::placed().into_location()),
+            );
+        state.reset(mode::Mode::User, PROGRAM_START);
+
+        // Compressed and uncompressed instructions are mixed, the latter
+        // are split in 2 halves.
+        let program: [u16; 7] = [
+            0x4515, // c.li a0, 5
+            0x459d, // c.li a1, 7
+            0x0633, 0x02b5, // mul a2, a0, a1
+            0xe211, // c.bnez a2, 4
+            0x4601, // c.li a2, 0
+            0x0605, // c.addi a2, 1
+        ];
+        load_program!(state, PROGRAM_START, program);
+
+        let result = state.step(5);
+        assert_eq!(
+            result,
+            StepResult {
+                steps: 5,
+                exception: None
+            }
+        );
+        assert_eq!(state.hart.xregisters.read(registers::a2), 36);
+        assert_eq!(state.hart.pc.read(), PROGRAM_START + 14);
+    });
+
+    backend_test!(test_machine_trap, F, {
+        let mut backend = create_backend!(MachineStateLayout<T1K>, F);
+        let mut state: MachineState<T1K, ManagerFor<'_, F, MachineStateLayout<T1K>>> =
+            MachineState::bind(
+                backend.allocate(MachineStateLayout::<T1K>::placed().into_location()),
+            );
+        state.reset(mode::Mode::Machine, PROGRAM_START);
+        state
+            .hart
+            .csregisters
+            .write(CSRegister::mtvec, HANDLER_START);
+
+        let program: [u32; 3] = [
+            0xffffffff, // illegal instruction
+            0x00003503, // ld a0, 0(zero)
+            0x00500513, // li a0, 5
+        ];
+        load_program!(state, PROGRAM_START, program);
+        load_program!(state, HANDLER_START, SKIP_HANDLER);
+
+        // Illegal instruction is trapped into M-mode
+        state.step(1);
+        assert_eq!(state.hart.pc.read(), HANDLER_START);
+        assert_eq!(state.hart.csregisters.read(CSRegister::mcause), 2);
+        assert_eq!(state.hart.csregisters.read(CSRegister::mepc), PROGRAM_START);
+        let mstatus = state.hart.csregisters.read(CSRegister::mstatus);
+        assert_eq!(xstatus::get_MPP(mstatus), xstatus::MPPValue::Machine);
+
+        // The handler resumes execution after the faulting instruction
+        state.step(4);
+        assert_eq!(state.hart.pc.read(), PROGRAM_START + 4);
+        assert_eq!(state.hart.mode.read(), mode::Mode::Machine);
+
+        // Load from the devices address space
+        state.step(1);
+        assert_eq!(state.hart.pc.read(), HANDLER_START);
+        assert_eq!(state.hart.csregisters.read(CSRegister::mcause), 5);
+        assert_eq!(state.hart.csregisters.read(CSRegister::mtval), 0);
+
+        state.step(5);
+        assert_eq!(state.hart.xregisters.read(registers::a0), 5);
+        assert_eq!(state.hart.pc.read(), PROGRAM_START + 12);
+
+        // Fetching out of bounds instructions also traps
+        state.hart.pc.write(0x42);
+        state.step(1);
+        assert_eq!(state.hart.pc.read(), HANDLER_START);
+        assert_eq!(state.hart.csregisters.read(CSRegister::mcause), 1);
+        assert_eq!(state.hart.csregisters.read(CSRegister::mtval), 0x42);
+    });
+
+    backend_test!(test_delegated_trap, F, {
+        let mut backend = create_backend!(MachineStateLayout<T1K>, F);
+        let mut state: MachineState<T1K, ManagerFor<'_, F, MachineStateLayout<T1K>>> =
+            MachineState::bind(
+                backend.allocate(MachineStateLayout::<T1K>::placed().into_location()),
+            );
+        state.reset(mode::Mode::User, PROGRAM_START);
+        state
+            .hart
+            .csregisters
+            .write(CSRegister::stvec, HANDLER_START);
+        state
+            .hart
+            .csregisters
+            .write(CSRegister::mtvec, HANDLER_START + 0x100);
+
+        let program: [u32; 2] = [
+            0x00000073, // ecall
+            0x00000073, // ecall
+        ];
+        load_program!(state, PROGRAM_START, program);
+        load_program!(state, HANDLER_START, [0x10200073]); // sret
+
+        // Environment calls from U-mode are delegated to S-mode by default
+        assert_eq!(
+            state.step(1),
+            StepResult {
+                steps: 1,
+                exception: None
+            }
+        );
+        assert_eq!(state.hart.pc.read(), HANDLER_START);
+        assert_eq!(state.hart.mode.read(), mode::Mode::Supervisor);
+        assert_eq!(state.hart.csregisters.read(CSRegister::scause), 8);
+        assert_eq!(state.hart.csregisters.read(CSRegister::sepc), PROGRAM_START);
+
+        // SRET goes back to U-mode
+        state.step(1);
+        assert_eq!(state.hart.pc.read(), PROGRAM_START);
+        assert_eq!(state.hart.mode.read(), mode::Mode::User);
+
+        // Without delegation, the trap is taken into M-mode
+        state.hart.csregisters.write(CSRegister::medeleg, 0);
+        state.step(1);
+        assert_eq!(state.hart.pc.read(), HANDLER_START + 0x100);
+        assert_eq!(state.hart.mode.read(), mode::Mode::Machine);
+        assert_eq!(state.hart.csregisters.read(CSRegister::mcause), 8);
+        let mstatus = state.hart.csregisters.read(CSRegister::mstatus);
+        assert_eq!(xstatus::get_MPP(mstatus), xstatus::MPPValue::User);
+    });
+
+    backend_test!(test_environ_exception, F, {
+        let mut backend = create_backend!(MachineStateLayout<T1K>, F);
+        let mut state: MachineState<T1K, ManagerFor<'_, F, MachineStateLayout<T1K>>> =
+            MachineState::bind(
+                backend.allocate(MachineStateLayout::<T1K>::placed().into_location()),
+            );
+        state.reset(mode::Mode::Supervisor, PROGRAM_START);
+        state
+            .hart
+            .csregisters
+            .write(CSRegister::stvec, HANDLER_START);
+
+        let program: [u32; 3] = [
+            0x00500513, // li a0, 5
+            0x00000073, // ecall
+            0x00700593, // li a1, 7
+        ];
+        load_program!(state, PROGRAM_START, program);
+
+        // Environment calls from S-mode interrupt the run
+        let result = state.step(10);
+        assert_eq!(
+            result,
+            StepResult {
+                steps: 2,
+                exception: Some(EnvironException::EnvCallFromSMode)
+            }
+        );
+        assert_eq!(state.hart.pc.read(), PROGRAM_START + 8);
+        assert_eq!(state.hart.mode.read(), mode::Mode::Supervisor);
+
+        state.step(1);
+        assert_eq!(state.hart.xregisters.read(registers::a1), 7);
+    });
 }
@@ -0,0 +1,481 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Sv39 virtual-memory translation
+//!
+//! There is no translation cache: every access walks the page table in
+//! memory. Page-table updates are therefore visible to the very next access,
+//! which keeps the execution deterministic regardless of `SFENCE.VMA`
+//! placement. The accessed and dirty bits of the leaf PTEs are updated by
+//! the walk.
+//!
+//! Sections 4.3 & 4.4 - privileged spec
+
+use super::{
+    bus::{main_memory::MainMemoryLayout, Address, Addressable},
+    csregisters::{
+        xstatus::{self, MPPValue},
+        CSRValue, CSRegister,
+    },
+    mode::Mode,
+    MachineState,
+};
+use crate::state_backend as backend;
+use crate::traps::Exception;
+
+/// Size of a page, in bytes
+pub const PAGE_SIZE: u64 = 4096;
+
+/// Number of bits of the offset within a page
+const PAGE_OFFSET_BITS: u32 = 12;
+
+/// Number of levels of the Sv39 page table
+const LEVELS: u32 = 3;
+
+/// Number of bits of each virtual page number field
+const VPN_BITS: u32 = 9;
+
+/// Size of a page-table entry, in bytes
+const PTE_SIZE: u64 = 8;
+
+/// Number of significant bits of an Sv39 virtual address
+const VIRT_ADDR_BITS: u32 = 39;
+
+/// `satp.PPN = satp[43:0]`
+const SATP_PPN_MASK: CSRValue = (1 << 44) - 1;
+
+/// Page-table entry fields (c.f. Figure 4.21)
+mod pte {
+    pub const V: u64 = 1 << 0;
+    pub const R: u64 = 1 << 1;
+    pub const W: u64 = 1 << 2;
+    pub const X: u64 = 1 << 3;
+    pub const U: u64 = 1 << 4;
+    pub const A: u64 = 1 << 6;
+    pub const D: u64 = 1 << 7;
+
+    /// `PPN = pte[53:10]`
+    pub const PPN_OFFSET: u32 = 10;
+    pub const PPN_MASK: u64 = (1 << 44) - 1;
+
+    /// Bits 63:54 are reserved, or used by the unsupported Svnapot and
+    /// Svpbmt extensions. They must be zero.
+    pub const RESERVED_MASK: u64 = !((1 << 54) - 1);
+}
+
+/// Type of a memory access, which determines the permissions required and
+/// the exceptions raised by the translation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessType {
+    Instruction,
+    Load,
+    /// Stores and AMOs
+    Store,
+}
+
+impl AccessType {
+    /// Page fault raised when translating `addr` fails
+    pub fn page_fault(self, addr: Address) -> Exception {
+        match self {
+            AccessType::Instruction => Exception::InstructionPageFault(addr),
+            AccessType::Load => Exception::LoadPageFault(addr),
+            AccessType::Store => Exception::StorePageFault(addr),
+        }
+    }
+
+    /// Access fault raised when the physical memory behind `addr` can't be
+    /// accessed
+    pub fn access_fault(self, addr: Address) -> Exception {
+        match self {
+            AccessType::Instruction => Exception::InstructionAccessFault(addr),
+            AccessType::Load => Exception::LoadAccessFault(addr),
+            AccessType::Store => Exception::StoreAccessFault(addr),
+        }
+    }
+}
+
+impl<ML, M> MachineState<ML, M>
+where
+    ML: MainMemoryLayout,
+    M: backend::Manager,
+{
+    /// Privilege mode in which an access of type `access` is performed.
+    ///
+    /// Loads and stores from M-mode use the mode in `mstatus.MPP` when
+    /// `mstatus.MPRV` is set. (Section 3.1.6.3)
+    fn effective_mode(&mut self, access: AccessType) -> Mode {
+        let mode = self.hart.mode.read();
+        if access == AccessType::Instruction || mode != Mode::Machine {
+            return mode;
+        }
+
+        let mstatus = self.hart.csregisters.read(CSRegister::mstatus);
+        if !xstatus::get_MPRV(mstatus) {
+            return mode;
+        }
+
+        match xstatus::get_MPP(mstatus) {
+            MPPValue::User => Mode::User,
+            MPPValue::Supervisor => Mode::Supervisor,
+            MPPValue::Machine => Mode::Machine,
+        }
+    }
+
+    /// Translate the virtual address `virt_addr` to a physical address,
+    /// for an access of type `access`.
+    ///
+    /// Addresses are only translated in S-mode and U-mode when `satp`
+    /// selects Sv39, they are otherwise physical addresses already.
+    pub fn translate(
+        &mut self,
+        virt_addr: Address,
+        access: AccessType,
+    ) -> Result<Address, Exception> {
+        let mode = self.effective_mode(access);
+        if mode >= Mode::Machine {
+            return Ok(virt_addr);
+        }
+
+        let satp = self.hart.csregisters.read(CSRegister::satp);
+        match satp >> CSRegister::SATP_MODE_OFFSET {
+            CSRegister::SATP_MODE_SV39 => self.sv39_translate(virt_addr, access, mode, satp),
+            _ => Ok(virt_addr),
+        }
+    }
+
+    /// Translate the address of an access of `size` bytes starting at
+    /// `virt_addr`.
+    ///
+    /// Accesses spanning two pages must map to contiguous physical memory,
+    /// otherwise they raise an access fault. The spec allows misaligned
+    /// accesses to do so rather than being emulated. (Section 2.6 - unprivileged spec)
+    pub fn translate_access(
+        &mut self,
+        virt_addr: Address,
+        size: u64,
+        access: AccessType,
+    ) -> Result<Address, Exception> {
+        let phys_addr = self.translate(virt_addr, access)?;
+
+        let last = virt_addr.wrapping_add(size.saturating_sub(1));
+        if last / PAGE_SIZE != virt_addr / PAGE_SIZE {
+            let phys_last = self.translate(last, access)?;
+            if phys_last != phys_addr.wrapping_add(size - 1) {
+                return Err(access.access_fault(virt_addr));
+            }
+        }
+
+        Ok(phys_addr)
+    }
+
+    /// Walk the Sv39 page table for `virt_addr`. (Section 4.3.2)
+    fn sv39_translate(
+        &mut self,
+        virt_addr: Address,
+        access: AccessType,
+        mode: Mode,
+        satp: CSRValue,
+    ) -> Result<Address, Exception> {
+        // Bits 63:39 must all be equal to bit 38
+        let shift = u64::BITS - VIRT_ADDR_BITS;
+        if ((virt_addr << shift) as i64 >> shift) as u64 != virt_addr {
+            return Err(access.page_fault(virt_addr));
+        }
+
+        let mut table = (satp & SATP_PPN_MASK) * PAGE_SIZE;
+
+        for level in (0..LEVELS).rev() {
+            let vpn = (virt_addr >> (PAGE_OFFSET_BITS + level * VPN_BITS)) & ((1 << VPN_BITS) - 1);
+            let pte_addr = table.wrapping_add(vpn * PTE_SIZE);
+            let pte: u64 = self
+                .bus
+                .read(pte_addr)
+                .map_err(|_| access.access_fault(virt_addr))?;
+
+            let invalid = pte & pte::V == 0
+                || (pte & pte::R == 0 && pte & pte::W != 0)
+                || pte & pte::RESERVED_MASK != 0;
+            if invalid {
+                return Err(access.page_fault(virt_addr));
+            }
+
+            let ppn = (pte >> pte::PPN_OFFSET) & pte::PPN_MASK;
+
+            // Pointer to the next level of the page table
+            if pte & (pte::R | pte::X) == 0 {
+                table = ppn * PAGE_SIZE;
+                continue;
+            }
+
+            if !self.leaf_permits(pte, access, mode) {
+                return Err(access.page_fault(virt_addr));
+            }
+
+            // Superpages must be aligned to their size
+            let page_bits = PAGE_OFFSET_BITS + level * VPN_BITS;
+            let superpage_mask = (1 << (level * VPN_BITS)) - 1;
+            if ppn & superpage_mask != 0 {
+                return Err(access.page_fault(virt_addr));
+            }
+
+            // Set the accessed bit, and the dirty bit for stores
+            let mut updated = pte | pte::A;
+            if access == AccessType::Store {
+                updated |= pte::D;
+            }
+            if updated != pte {
+                self.bus
+                    .write(pte_addr, updated)
+                    .map_err(|_| access.access_fault(virt_addr))?;
+            }
+
+            let offset_mask = (1 << page_bits) - 1;
+            return Ok((ppn << PAGE_OFFSET_BITS) & !offset_mask | virt_addr & offset_mask);
+        }
+
+        // No leaf PTE was found at the last level
+        Err(access.page_fault(virt_addr))
+    }
+
+    /// Whether the leaf `pte` permits an access of type `access` in `mode`.
+    /// (Section 4.3.1)
+    fn leaf_permits(&mut self, pte: u64, access: AccessType, mode: Mode) -> bool {
+        let mstatus = self.hart.csregisters.read(CSRegister::mstatus);
+
+        let permitted = match access {
+            AccessType::Instruction => pte & pte::X != 0,
+            // With MXR, executable pages are readable too
+            AccessType::Load => {
+                pte & pte::R != 0 || (xstatus::get_MXR(mstatus) && pte & pte::X != 0)
+            }
+            AccessType::Store => pte & pte::W != 0,
+        };
+
+        // U-mode can only access user pages. S-mode can't execute them, and
+        // can only load or store to them when SUM is set.
+        let user_page = pte & pte::U != 0;
+        let mode_permitted = match mode {
+            Mode::User => user_page,
+            _ if user_page => access != AccessType::Instruction && xstatus::get_SUM(mstatus),
+            _ => true,
+        };
+
+        permitted && mode_permitted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pte, AccessType, PAGE_SIZE};
+    use crate::{
+        backend_test, create_backend,
+        machine_state::{
+            bus::{devices::DEVICES_ADDRESS_SPACE_LENGTH, main_memory::tests::T32K, Addressable},
+            csregisters::{
+                xstatus::{self, MPPValue},
+                CSRegister,
+            },
+            mode::Mode,
+            MachineState, MachineStateLayout,
+        },
+        state_backend::{tests::ManagerFor, Backend, Layout},
+        traps::Exception,
+    };
+    use proptest::{prop_assert_eq, proptest};
+
+    /// Physical address of the `n`-th page of main memory
+    const fn page(n: u64) -> u64 {
+        DEVICES_ADDRESS_SPACE_LENGTH + n * PAGE_SIZE
+    }
+
+    /// Page-table entry pointing to the physical address `addr`
+    const fn entry(addr: u64, flags: u64) -> u64 {
+        (addr / PAGE_SIZE) << pte::PPN_OFFSET | flags | pte::V
+    }
+
+    const SATP_SV39: u64 = 8 << 60;
+    const RWX: u64 = pte::R | pte::W | pte::X;
+
+    macro_rules! setup_state {
+        ($state:ident, $backend:ident, $F:ident) => {
+            let mut $backend = create_backend!(MachineStateLayout<T32K>, $F);
+            let mut $state: MachineState<T32K, ManagerFor<'_, $F, MachineStateLayout<T32K>>> =
+                MachineState::bind(
+                    $backend.allocate(MachineStateLayout::<T32K>::placed().into_location()),
+                );
+            $state.reset(Mode::Supervisor, 0);
+            // The root table is the first page, the next two pages hold the
+            // next levels of the page table
+            $state
+                .hart
+                .csregisters
+                .write(CSRegister::satp, SATP_SV39 | page(0) / PAGE_SIZE);
+        };
+    }
+
+    backend_test!(test_bare, F, {
+        setup_state!(state, backend, F);
+
+        // M-mode and Bare mode are not translated
+        state.hart.mode.write(Mode::Machine);
+        assert_eq!(state.translate(0x1234, AccessType::Load), Ok(0x1234));
+
+        state.hart.mode.write(Mode::Supervisor);
+        state.hart.csregisters.write(CSRegister::satp, 0);
+        assert_eq!(state.translate(0x1234, AccessType::Store), Ok(0x1234));
+    });
+
+    backend_test!(test_sv39_walk, F, {
+        proptest!(|(offset in 0..PAGE_SIZE, vpn0 in 0_u64..512)| {
+            setup_state!(state, backend, F);
+
+            // 4 KiB page at virtual address 0x4000_0000 + vpn0 * 4 KiB
+            let virt_addr = 0x4000_0000 + vpn0 * PAGE_SIZE + offset;
+            state.bus.write(page(0) + 8, entry(page(1), 0)).unwrap();
+            state.bus.write(page(1), entry(page(2), 0)).unwrap();
+            state.bus.write(page(2) + vpn0 * 8, entry(page(3), RWX)).unwrap();
+
+            prop_assert_eq!(state.translate(virt_addr, AccessType::Load), Ok(page(3) + offset));
+
+            // The accessed bit is set, and the dirty bit on stores
+            let leaf: u64 = state.bus.read(page(2) + vpn0 * 8).unwrap();
+            prop_assert_eq!(leaf & (pte::A | pte::D), pte::A);
+            prop_assert_eq!(state.translate(virt_addr, AccessType::Store), Ok(page(3) + offset));
+            let leaf: u64 = state.bus.read(page(2) + vpn0 * 8).unwrap();
+            prop_assert_eq!(leaf & (pte::A | pte::D), pte::A | pte::D);
+
+            // Other pages are not mapped
+            let other = virt_addr ^ (1 << 21);
+            prop_assert_eq!(
+                state.translate(other, AccessType::Instruction),
+                Err(Exception::InstructionPageFault(other))
+            );
+        });
+    });
+
+    backend_test!(test_superpages, F, {
+        setup_state!(state, backend, F);
+
+        // 1 GiB page mapping virtual address 0 to main memory
+        state.bus.write(page(0), entry(page(0), RWX)).unwrap();
+        assert_eq!(
+            state.translate(0x1234, AccessType::Load),
+            Ok(page(0) + 0x1234)
+        );
+
+        // 2 MiB page, whose physical address is not aligned
+        state.bus.write(page(0) + 8, entry(page(1), 0)).unwrap();
+        state.bus.write(page(1), entry(page(1), RWX)).unwrap();
+        assert_eq!(
+            state.translate(0x4000_0000, AccessType::Load),
+            Err(Exception::LoadPageFault(0x4000_0000))
+        );
+
+        // Virtual addresses must be sign-extended from bit 38
+        assert_eq!(
+            state.translate(1 << 39, AccessType::Load),
+            Err(Exception::LoadPageFault(1 << 39))
+        );
+    });
+
+    backend_test!(test_permissions, F, {
+        setup_state!(state, backend, F);
+        let addr = 0x1000;
+        state.bus.write(page(0), entry(page(1), 0)).unwrap();
+        state.bus.write(page(1), entry(page(2), 0)).unwrap();
+
+        let set_leaf = |state: &mut MachineState<_, _>, flags| {
+            state.bus.write(page(2) + 8, entry(page(3), flags)).unwrap()
+        };
+
+        // Execute-only page
+        set_leaf(&mut state, pte::X);
+        assert_eq!(state.translate(addr, AccessType::Instruction), Ok(page(3)));
+        assert_eq!(
+            state.translate(addr, AccessType::Load),
+            Err(Exception::LoadPageFault(addr))
+        );
+
+        // MXR makes it readable
+        let mstatus = state.hart.csregisters.read(CSRegister::mstatus);
+        state
+            .hart
+            .csregisters
+            .write(CSRegister::mstatus, xstatus::set_MXR(mstatus, true));
+        assert_eq!(state.translate(addr, AccessType::Load), Ok(page(3)));
+
+        // Write-only pages are reserved
+        set_leaf(&mut state, pte::W);
+        assert_eq!(
+            state.translate(addr, AccessType::Store),
+            Err(Exception::StorePageFault(addr))
+        );
+
+        // User pages can't be accessed from S-mode without SUM, and never executed
+        let mstatus = state.hart.csregisters.read(CSRegister::mstatus);
+        state
+            .hart
+            .csregisters
+            .write(CSRegister::mstatus, xstatus::set_SUM(mstatus, false));
+        set_leaf(&mut state, RWX | pte::U);
+        assert_eq!(
+            state.translate(addr, AccessType::Load),
+            Err(Exception::LoadPageFault(addr))
+        );
+        let mstatus = state.hart.csregisters.read(CSRegister::mstatus);
+        state
+            .hart
+            .csregisters
+            .write(CSRegister::mstatus, xstatus::set_SUM(mstatus, true));
+        assert_eq!(state.translate(addr, AccessType::Store), Ok(page(3)));
+        assert_eq!(
+            state.translate(addr, AccessType::Instruction),
+            Err(Exception::InstructionPageFault(addr))
+        );
+
+        // U-mode can only access user pages
+        state.hart.mode.write(Mode::User);
+        assert_eq!(state.translate(addr, AccessType::Instruction), Ok(page(3)));
+        set_leaf(&mut state, RWX);
+        assert_eq!(
+            state.translate(addr, AccessType::Load),
+            Err(Exception::LoadPageFault(addr))
+        );
+
+        // M-mode loads and stores are translated with MPRV
+        state.hart.mode.write(Mode::Machine);
+        let mstatus = state.hart.csregisters.read(CSRegister::mstatus);
+        let mstatus = xstatus::set_MPRV(mstatus, true);
+        let mstatus = xstatus::set_MPP(mstatus, MPPValue::Supervisor);
+        state.hart.csregisters.write(CSRegister::mstatus, mstatus);
+        assert_eq!(state.translate(addr, AccessType::Load), Ok(page(3)));
+        assert_eq!(state.translate(addr, AccessType::Instruction), Ok(addr));
+    });
+
+    backend_test!(test_page_crossing, F, {
+        setup_state!(state, backend, F);
+        state.bus.write(page(0), entry(page(1), 0)).unwrap();
+        state.bus.write(page(1), entry(page(2), 0)).unwrap();
+
+        // Virtual pages 0 and 1 map to physical pages 4 and 3
+        state.bus.write(page(2), entry(page(4), RWX)).unwrap();
+        state.bus.write(page(2) + 8, entry(page(3), RWX)).unwrap();
+
+        assert_eq!(
+            state.translate_access(PAGE_SIZE - 4, 4, AccessType::Load),
+            Ok(page(5) - 4)
+        );
+        assert_eq!(
+            state.translate_access(PAGE_SIZE - 4, 8, AccessType::Load),
+            Err(Exception::LoadAccessFault(PAGE_SIZE - 4))
+        );
+
+        // Contiguous physical pages can be accessed across the boundary
+        state.bus.write(page(2) + 8, entry(page(5), RWX)).unwrap();
+        assert_eq!(
+            state.translate_access(PAGE_SIZE - 4, 8, AccessType::Store),
+            Ok(page(5) - 4)
+        );
+    });
+}
@@ -126,6 +126,7 @@ pub mod tests {
     };
 
     gen_memory_layout!(T1K = 1 KiB);
+    gen_memory_layout!(T32K = 32 KiB);
 
     backend_test!(test_endianess, F, {
         let mut backend = F::new::<T1K>();
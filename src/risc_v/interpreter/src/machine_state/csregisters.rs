@@ -1,8 +1,8 @@
-// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+// SPDX-FileCopyrightText: 2023-2024 TriliTech <contact@trili.tech>
 //
 // SPDX-License-Identifier: MIT
 
-mod xstatus;
+pub mod xstatus;
 
 use crate::machine_state::backend::{self, Region};
 use crate::machine_state::mode::Mode;
@@ -24,7 +24,7 @@ pub const fn ones(n: u64) -> u64 {
 
 /// CSR index
 #[allow(non_camel_case_types)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, strum::EnumIter)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, strum::EnumIter, strum::FromRepr)]
 #[repr(usize)]
 pub enum CSRegister {
     // Unprivileged Floating-Point CSRs
@@ -316,6 +316,12 @@ impl CSRegister {
     const SXLEN: u64 = CSRegister::MXLEN;
     const MXL_ENCODING: CSRValue = 0b10;
 
+    /// Determine the CSR at the given 12-bit address, if it is implemented.
+    #[inline(always)]
+    pub fn try_parse(addr: u32) -> Option<CSRegister> {
+        CSRegister::from_repr(addr as usize)
+    }
+
     /// Determine the priviledge level required to access this CSR.
     #[inline(always)]
     pub fn privilege(self) -> Privilege {
@@ -373,6 +379,15 @@ impl CSRegister {
         (self as usize >> 10) & 0b11 == 0b11
     }
 
+    /// Determines if the register is part of the floating-point state
+    #[inline(always)]
+    pub fn is_floating_point(self) -> bool {
+        matches!(
+            self,
+            CSRegister::fflags | CSRegister::frm | CSRegister::fcsr
+        )
+    }
+
     /// Enforce the WPRI and WLRL field specifications.
     ///
     /// Either return the value to be written, or None to signify that no write is necessary,
@@ -562,19 +577,21 @@ impl CSRegister {
                 let satp_mode = new_value >> CSRegister::SATP_MODE_OFFSET;
                 match satp_mode {
                     // when address translation for memory address is active, consider the other fields valid
-                    CSRegister::SATP_MODE_SV39
-                    | CSRegister::SATP_MODE_SV48
-                    | CSRegister::SATP_MODE_SV57 => new_value,
+                    CSRegister::SATP_MODE_SV39 => new_value,
                     // The RISC-V spec has UNSPECIFIED behaviour when Bare mode is selected
                     // and any of the other fields contain non-zero values. (Section 4.1.11)
                     // Therefore we set all other fields to 0.
                     CSRegister::SATP_MODE_BARE => CSRegister::SATP_DEFAULT,
-                    // RISC-V spec explicitly mentions no update must take place
+                    // Sv48 and Sv57 are not supported: the RISC-V spec
+                    // explicitly mentions no update must take place
                     _ => return None,
                 }
             }
             CSRegister::mstatus => xstatus::apply_warl_mstatus(new_value),
             CSRegister::sstatus => xstatus::apply_warl_sstatus(new_value),
+            CSRegister::fflags => new_value & CSRegister::FFLAGS_MASK,
+            CSRegister::frm => new_value & CSRegister::FRM_MASK,
+            CSRegister::fcsr => new_value & CSRegister::FCSR_MASK,
             _ => new_value,
         };
         Some(write_value)
@@ -650,14 +667,21 @@ impl CSRegister {
     /// Since extension C is supported, we only make the low bit read-only 0
     const WARL_MASK_XEPC: CSRValue = !1;
 
+    /// Accrued exception flags, bits 4:0 of `fcsr` (Section 11.2)
+    pub const FFLAGS_MASK: CSRValue = ones(5);
+
+    /// Dynamic rounding mode, bits 7:5 of `fcsr` (Section 11.2)
+    pub const FRM_MASK: CSRValue = ones(3);
+    const FRM_OFFSET: u64 = 5;
+
+    const FCSR_MASK: CSRValue = ones(8);
+
     // allowed `MODE` for `satp` register.
     // Section 4.1.11
     /// `satp.MODE = satp[63:60]`
-    const SATP_MODE_OFFSET: u32 = 60;
-    const SATP_MODE_BARE: CSRValue = 0;
-    const SATP_MODE_SV39: CSRValue = 8;
-    const SATP_MODE_SV48: CSRValue = 9;
-    const SATP_MODE_SV57: CSRValue = 10;
+    pub const SATP_MODE_OFFSET: u32 = 60;
+    pub const SATP_MODE_BARE: CSRValue = 0;
+    pub const SATP_MODE_SV39: CSRValue = 8;
     /// We consider the default `SATP` value to be the `BARE` mode.
     const SATP_DEFAULT: CSRValue = CSRegister::SATP_MODE_BARE << CSRegister::SATP_MODE_OFFSET;
 
@@ -789,8 +813,9 @@ impl CSRegister {
             }
 
             CSRegister::fcsr => {
-                // fcsr is a combination of fflags and fcsr
-                CSRegister::fflags.default_value() & CSRegister::frm.default_value() << 5
+                // fcsr is a combination of fflags and frm
+                CSRegister::fflags.default_value()
+                    | CSRegister::frm.default_value() << CSRegister::FRM_OFFSET
             }
 
             CSRegister::pmpcfg0
@@ -1016,11 +1041,7 @@ impl CSRegister {
 /// Value in a CSR
 pub type CSRValue = u64;
 
-/// RISC-V exceptions
-#[derive(PartialEq, Eq)]
-pub enum Exception {
-    IllegalInstruction,
-}
+pub use crate::traps::Exception;
 
 /// Return type of read/write operations
 pub type Result<R> = core::result::Result<R, Exception>;
@@ -1058,9 +1079,9 @@ pub struct CSRegisters<M: backend::Manager> {
 
 impl<M: backend::Manager> CSRegisters<M> {
     /// Transform the write operation to account for shadow registers.
-    /// (e.g. `sstatus` register)
+    /// (e.g. `sstatus` register, `fflags` and `frm` are fields of `fcsr`)
     ///
-    /// Sections 3.1.6 & 4.1.1
+    /// Sections 3.1.6, 4.1.1 & 11.2
     #[inline(always)]
     fn transform_write(&self, reg: CSRegister, value: CSRValue) -> (CSRegister, CSRValue) {
         match reg {
@@ -1070,28 +1091,57 @@ impl<M: backend::Manager> CSRegisters<M> {
                 let mstatus_only = mstatus & !xstatus::SSTATUS_FIELDS_MASK;
                 (CSRegister::mstatus, sstatus_only | mstatus_only)
             }
+            CSRegister::fflags => {
+                let fcsr = self.registers.read(CSRegister::fcsr as usize);
+                let fcsr = fcsr & !CSRegister::FFLAGS_MASK | value;
+                (CSRegister::fcsr, fcsr)
+            }
+            CSRegister::frm => {
+                let fcsr = self.registers.read(CSRegister::fcsr as usize);
+                let frm_mask = CSRegister::FRM_MASK << CSRegister::FRM_OFFSET;
+                let fcsr = fcsr & !frm_mask | value << CSRegister::FRM_OFFSET;
+                (CSRegister::fcsr, fcsr)
+            }
             _ => (reg, value),
         }
     }
 
     /// Transform a read operation to account for shadow registers.
-    /// (e.g. `sstatus` register)
+    /// (e.g. `sstatus` register, `fflags` and `frm` are fields of `fcsr`)
     ///
-    /// `mstatus_value` holds the value of `mstatus` if known, `None` otherwise.
-    /// `mstatus` is read only if `sstatus` is requested and `mstatus` is not known already
+    /// `backing_value` holds the value of the register backing `reg` (e.g. `mstatus` for
+    /// `sstatus`) if known, `None` otherwise. The backing register is read only if it is not
+    /// known already.
     ///
-    /// Sections 3.1.6 & 4.1.1
+    /// Sections 3.1.6, 4.1.1 & 11.2
     #[inline(always)]
-    fn transform_read(&self, reg: CSRegister, mstatus_value: Option<CSRValue>) -> CSRValue {
-        let read_mstatus = || self.registers.read(CSRegister::mstatus as usize);
+    fn transform_read(&self, reg: CSRegister, backing_value: Option<CSRValue>) -> CSRValue {
+        let read = |backing: CSRegister| {
+            backing_value.unwrap_or_else(|| self.registers.read(backing as usize))
+        };
 
         match reg {
-            CSRegister::sstatus => {
-                let mstatus = mstatus_value.unwrap_or_else(read_mstatus);
-                xstatus::sstatus_from_mstatus(mstatus)
+            CSRegister::sstatus => xstatus::sstatus_from_mstatus(read(CSRegister::mstatus)),
+            CSRegister::fflags => read(CSRegister::fcsr) & CSRegister::FFLAGS_MASK,
+            CSRegister::frm => {
+                read(CSRegister::fcsr) >> CSRegister::FRM_OFFSET & CSRegister::FRM_MASK
             }
-            CSRegister::mstatus => mstatus_value.unwrap_or_else(read_mstatus),
-            _ => self.registers.read(reg as usize),
+            _ => read(reg),
+        }
+    }
+
+    /// Mark the floating-point state as modified in `mstatus.FS`.
+    ///
+    /// Section 3.1.6.6
+    #[inline(always)]
+    pub fn mark_fs_dirty(&mut self) {
+        let mstatus = self.registers.read(CSRegister::mstatus as usize);
+        if xstatus::get_FS(mstatus) != xstatus::ExtensionValue::Dirty {
+            let mstatus = xstatus::set_FS(mstatus, xstatus::ExtensionValue::Dirty);
+            self.registers.write(
+                CSRegister::mstatus as usize,
+                xstatus::apply_warl_mstatus(mstatus),
+            );
         }
     }
 
@@ -1102,6 +1152,10 @@ impl<M: backend::Manager> CSRegisters<M> {
         // Respect field specifications (e.g. WPRI, WLRL, WARL)
         // extra function to read mstatus if needed
         if let Some(value) = reg.make_value_writable(value) {
+            if reg.is_floating_point() {
+                self.mark_fs_dirty();
+            }
+
             let (reg, value) = self.transform_write(reg, value);
             self.registers.write(reg as usize, value);
         }
@@ -1126,6 +1180,10 @@ impl<M: backend::Manager> CSRegisters<M> {
         // Respect field specifications (e.g. WPRI, WLRL, WARL)
 
         if let Some(value) = reg.make_value_writable(value) {
+            if reg.is_floating_point() {
+                self.mark_fs_dirty();
+            }
+
             let (upd_reg, value) = self.transform_write(reg, value);
             let old_value = self.registers.replace(upd_reg as usize, value);
 
@@ -1191,7 +1249,7 @@ mod tests {
                 tests::{test_determinism, ManagerFor},
                 Backend, BackendManagement, Layout, Region,
             },
-            csregisters::{CSRegister, CSRegisters, CSRegistersLayout, Exception},
+            csregisters::{xstatus, CSRegister, CSRegisters, CSRegistersLayout, Exception},
             mode::Mode,
         },
     };
@@ -1340,9 +1398,11 @@ mod tests {
         assert_eq!(check_wrapped(csreg::satp, 0x0000_FFFF_0000_FFFF), Some(0x0));
         assert_eq!(check_wrapped(csreg::satp, 0x4200_FFFF_FFFF_FFFF), None);
         assert_eq!(
-            check_wrapped(csreg::satp, 0x90F0_0000_FFFF_0000),
-            Some(0x90F0_0000_FFFF_0000)
+            check_wrapped(csreg::satp, 0x80F0_0000_FFFF_0000),
+            Some(0x80F0_0000_FFFF_0000)
         );
+        // Sv48 is not supported
+        assert_eq!(check_wrapped(csreg::satp, 0x90F0_0000_FFFF_0000), None);
 
         // mstatus
         // uxl & sxl fields are set
@@ -1438,6 +1498,34 @@ mod tests {
         assert_eq!(read_sstatus, 1 << 63 | 0b10 << 32 | 0b11 << 9 | 0 << 8);
     });
 
+    backend_test!(test_fcsr, F, {
+        let mut backend = F::new::<CSRegistersLayout>();
+        let placed = CSRegistersLayout::placed().into_location();
+
+        let mut csrs: CSRegisters<
+            <F::Backend<CSRegistersLayout> as BackendManagement>::Manager<'_>,
+        > = CSRegisters::bind(backend.allocate(placed));
+
+        // fflags and frm are views of fcsr, upper bits are ignored
+        csrs.write(CSRegister::fcsr, 0xfff);
+        assert_eq!(csrs.read(CSRegister::fcsr), 0xff);
+        assert_eq!(csrs.read(CSRegister::fflags), 0b1_1111);
+        assert_eq!(csrs.read(CSRegister::frm), 0b111);
+
+        assert_eq!(csrs.replace(CSRegister::frm, 0b1010), 0b111);
+        assert_eq!(csrs.read(CSRegister::fcsr), 0b010 << 5 | 0b1_1111);
+
+        assert_eq!(csrs.replace(CSRegister::fflags, 0b10), 0b1_1111);
+        assert_eq!(csrs.read(CSRegister::fcsr), 0b010 << 5 | 0b0_0010);
+        assert_eq!(csrs.registers.read(CSRegister::fflags as usize), 0);
+        assert_eq!(csrs.registers.read(CSRegister::frm as usize), 0);
+
+        // Writing the floating-point state marks it dirty
+        let mstatus = csrs.read(CSRegister::mstatus);
+        assert_eq!(xstatus::get_FS(mstatus), xstatus::ExtensionValue::Dirty);
+        assert!(xstatus::get_SD(mstatus));
+    });
+
     backend_test!(test_reset, F, {
         test_determinism::<F, CSRegistersLayout, _>(|space| {
             let mut csregs: CSRegisters<ManagerFor<'_, F, CSRegistersLayout>> =
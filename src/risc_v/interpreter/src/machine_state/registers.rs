@@ -1,4 +1,4 @@
-// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+// SPDX-FileCopyrightText: 2023-2024 TriliTech <contact@trili.tech>
 //
 // SPDX-License-Identifier: MIT
 
@@ -185,7 +185,7 @@ impl<M: backend::Manager> XRegisters<M> {
 /// Floating-point number register index
 #[allow(non_camel_case_types)] // To make names consistent with specification
 #[repr(usize)]
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum FRegister {
     f0 = 0,
     f1,
@@ -224,6 +224,45 @@ pub enum FRegister {
 // We want those constructors at the module top-level.
 pub use FRegister::*;
 
+pub fn parse_fregister(r: u32) -> FRegister {
+    use FRegister::*;
+    match r {
+        0b0_0000 => f0,
+        0b0_0001 => f1,
+        0b0_0010 => f2,
+        0b0_0011 => f3,
+        0b0_0100 => f4,
+        0b0_0101 => f5,
+        0b0_0110 => f6,
+        0b0_0111 => f7,
+        0b0_1000 => f8,
+        0b0_1001 => f9,
+        0b0_1010 => f10,
+        0b0_1011 => f11,
+        0b0_1100 => f12,
+        0b0_1101 => f13,
+        0b0_1110 => f14,
+        0b0_1111 => f15,
+        0b1_0000 => f16,
+        0b1_0001 => f17,
+        0b1_0010 => f18,
+        0b1_0011 => f19,
+        0b1_0100 => f20,
+        0b1_0101 => f21,
+        0b1_0110 => f22,
+        0b1_0111 => f23,
+        0b1_1000 => f24,
+        0b1_1001 => f25,
+        0b1_1010 => f26,
+        0b1_1011 => f27,
+        0b1_1100 => f28,
+        0b1_1101 => f29,
+        0b1_1110 => f30,
+        0b1_1111 => f31,
+        _ => panic!("Invalid register"),
+    }
+}
+
 // ABI register names
 pub const ft0: FRegister = f0;
 pub const ft1: FRegister = f1;
@@ -259,11 +298,25 @@ pub const ft10: FRegister = f30;
 pub const ft11: FRegister = f31;
 
 /// Floating-point number register value
-// XXX: We probably want this wrapper around f64 to implement deterministic
-// floating-point arithmetic.
+///
+/// The raw bits are stored rather than a host floating-point number, all
+/// arithmetic is done in software (see [crate::softfloat]). Single-precision
+/// values are NaN-boxed in the lower 32 bits. (Section 12.2 - Unprivileged spec)
 #[repr(transparent)]
-#[derive(Clone, Copy, PartialEq, PartialOrd, Default)]
-pub struct FValue(f64);
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct FValue(u64);
+
+impl From<u64> for FValue {
+    fn from(value: u64) -> Self {
+        FValue(value)
+    }
+}
+
+impl From<FValue> for u64 {
+    fn from(value: FValue) -> Self {
+        value.0
+    }
+}
 
 impl backend::Elem for FValue {
     #[inline(always)]
@@ -4,7 +4,11 @@
 
 pub mod instruction;
 
-use crate::machine_state::registers::{parse_register, XRegister};
+use crate::machine_state::{
+    csregisters::CSRegister,
+    registers::{parse_fregister, parse_register, sp, x0, FRegister, XRegister},
+};
+use crate::softfloat::RoundingMode;
 use core::ops::Range;
 use instruction::*;
 
@@ -30,6 +34,11 @@ fn funct7(instr: u32) -> u32 {
     bits(instr, 25, 7)
 }
 
+#[inline(always)]
+fn funct5(instr: u32) -> u32 {
+    bits(instr, 27, 5)
+}
+
 #[inline(always)]
 fn rd(instr: u32) -> XRegister {
     parse_register(bits(instr, 7, 5))
@@ -45,6 +54,48 @@ fn rs2(instr: u32) -> XRegister {
     parse_register(bits(instr, 20, 5))
 }
 
+#[inline(always)]
+fn frd(instr: u32) -> FRegister {
+    parse_fregister(bits(instr, 7, 5))
+}
+
+#[inline(always)]
+fn frs1(instr: u32) -> FRegister {
+    parse_fregister(bits(instr, 15, 5))
+}
+
+#[inline(always)]
+fn frs2(instr: u32) -> FRegister {
+    parse_fregister(bits(instr, 20, 5))
+}
+
+#[inline(always)]
+fn frs3(instr: u32) -> FRegister {
+    parse_fregister(bits(instr, 27, 5))
+}
+
+/// Floating-point format of the operands
+#[inline(always)]
+fn fmt(instr: u32) -> u32 {
+    bits(instr, 25, 2)
+}
+
+/// Rounding mode, `None` for the reserved encodings
+#[inline(always)]
+fn rm(instr: u32) -> Option<InstrRoundingMode> {
+    match funct3(instr) {
+        0b111 => Some(InstrRoundingMode::Dynamic),
+        rm => RoundingMode::try_from(rm as u64)
+            .ok()
+            .map(InstrRoundingMode::Static),
+    }
+}
+
+#[inline(always)]
+fn csr(instr: u32) -> Option<CSRegister> {
+    CSRegister::try_parse(bits(instr, 20, 12))
+}
+
 #[inline(always)]
 fn imm_11_6(instr: u32) -> u32 {
     bits(instr, 26, 6) << 1
@@ -149,6 +200,171 @@ macro_rules! j_instr {
     };
 }
 
+macro_rules! amo_instr {
+    ($enum_variant:ident, $instr:expr) => {
+        $enum_variant(instruction::AmoArgs {
+            rd: rd($instr),
+            rs1: rs1($instr),
+            rs2: rs2($instr),
+            aq: bits($instr, 26, 1) != 0,
+            rl: bits($instr, 25, 1) != 0,
+        })
+    };
+}
+
+macro_rules! csr_instr {
+    ($enum_variant:ident, $instr:expr) => {
+        match csr($instr) {
+            Some(csr) => $enum_variant(instruction::CsrArgs {
+                rd: rd($instr),
+                rs1: rs1($instr),
+                csr,
+            }),
+            None => Unknown { instr: $instr },
+        }
+    };
+}
+
+macro_rules! csri_instr {
+    ($enum_variant:ident, $instr:expr) => {
+        match csr($instr) {
+            Some(csr) => $enum_variant(instruction::CsriArgs {
+                rd: rd($instr),
+                // The immediate is the unsigned 5-bit field in place of rs1
+                imm: bits($instr, 15, 5) as u64,
+                csr,
+            }),
+            None => Unknown { instr: $instr },
+        }
+    };
+}
+
+macro_rules! f_load_instr {
+    ($enum_variant:ident, $instr:expr) => {
+        $enum_variant(instruction::FLoadArgs {
+            rd: frd($instr),
+            rs1: rs1($instr),
+            imm: i_imm($instr),
+        })
+    };
+}
+
+macro_rules! f_store_instr {
+    ($enum_variant:ident, $instr:expr) => {
+        $enum_variant(instruction::FStoreArgs {
+            rs1: rs1($instr),
+            rs2: frs2($instr),
+            imm: s_imm($instr),
+        })
+    };
+}
+
+macro_rules! f_r_instr {
+    ($enum_variant:ident, $instr:expr) => {
+        $enum_variant(instruction::FRTypeArgs {
+            rd: frd($instr),
+            rs1: frs1($instr),
+            rs2: frs2($instr),
+        })
+    };
+}
+
+macro_rules! f_rm_instr {
+    ($enum_variant:ident, $instr:expr) => {
+        match rm($instr) {
+            Some(rm) => $enum_variant(instruction::FRmArgs {
+                rd: frd($instr),
+                rs1: frs1($instr),
+                rs2: frs2($instr),
+                rm,
+            }),
+            None => Unknown { instr: $instr },
+        }
+    };
+}
+
+macro_rules! f_r4_instr {
+    ($enum_variant:ident, $instr:expr) => {
+        match rm($instr) {
+            Some(rm) => $enum_variant(instruction::FR4Args {
+                rd: frd($instr),
+                rs1: frs1($instr),
+                rs2: frs2($instr),
+                rs3: frs3($instr),
+                rm,
+            }),
+            None => Unknown { instr: $instr },
+        }
+    };
+}
+
+macro_rules! f_r1_instr {
+    ($enum_variant:ident, $instr:expr) => {
+        match rm($instr) {
+            Some(rm) => $enum_variant(instruction::FR1Args {
+                rd: frd($instr),
+                rs1: frs1($instr),
+                rm,
+            }),
+            None => Unknown { instr: $instr },
+        }
+    };
+}
+
+macro_rules! f_cmp_instr {
+    ($enum_variant:ident, $instr:expr) => {
+        $enum_variant(instruction::FCmpArgs {
+            rd: rd($instr),
+            rs1: frs1($instr),
+            rs2: frs2($instr),
+        })
+    };
+}
+
+macro_rules! xf_rm_instr {
+    ($enum_variant:ident, $instr:expr) => {
+        match rm($instr) {
+            Some(rm) => $enum_variant(instruction::XFRmArgs {
+                rd: rd($instr),
+                rs1: frs1($instr),
+                rm,
+            }),
+            None => Unknown { instr: $instr },
+        }
+    };
+}
+
+macro_rules! fx_rm_instr {
+    ($enum_variant:ident, $instr:expr) => {
+        match rm($instr) {
+            Some(rm) => $enum_variant(instruction::FXRmArgs {
+                rd: frd($instr),
+                rs1: rs1($instr),
+                rm,
+            }),
+            None => Unknown { instr: $instr },
+        }
+    };
+}
+
+macro_rules! xf_instr {
+    ($enum_variant:ident, $instr:expr) => {
+        $enum_variant(instruction::XFArgs {
+            rd: rd($instr),
+            rs1: frs1($instr),
+        })
+    };
+}
+
+macro_rules! fx_instr {
+    ($enum_variant:ident, $instr:expr) => {
+        $enum_variant(instruction::FXArgs {
+            rd: frd($instr),
+            rs1: rs1($instr),
+        })
+    };
+}
+
 const OP_ARITH: u32 = 0b011_0011;
 const OP_ARITH_W: u32 = 0b011_1011;
 const OP_ARITH_I: u32 = 0b001_0011;
@@ -156,12 +372,20 @@ const OP_LOAD: u32 = 0b000_0011;
 const OP_ARITH_IW: u32 = 0b001_1011;
 const OP_SYNCH: u32 = 0b000_1111;
 const OP_ENV: u32 = 0b111_0011;
+const OP_AMO: u32 = 0b010_1111;
 const OP_STORE: u32 = 0b010_0011;
 const OP_BRANCH: u32 = 0b110_0011;
 const OP_LUI: u32 = 0b011_0111;
 const OP_AUIPC: u32 = 0b001_0111;
 const OP_JAL: u32 = 0b110_1111;
 const OP_JALR: u32 = 0b110_0111;
+const OP_LOAD_FP: u32 = 0b000_0111;
+const OP_STORE_FP: u32 = 0b010_0111;
+const OP_MADD: u32 = 0b100_0011;
+const OP_MSUB: u32 = 0b100_0111;
+const OP_NMSUB: u32 = 0b100_1011;
+const OP_NMADD: u32 = 0b100_1111;
+const OP_FP: u32 = 0b101_0011;
 
 const F3_0: u32 = 0b000;
 const F3_1: u32 = 0b001;
@@ -173,8 +397,43 @@ const F3_6: u32 = 0b110;
 const F3_7: u32 = 0b111;
 
 const F7_0: u32 = 0b0;
+const F7_1: u32 = 0b1;
 const F7_20: u32 = 0b10_0000;
 
+const F5_AMOADD: u32 = 0b0_0000;
+const F5_AMOSWAP: u32 = 0b0_0001;
+const F5_LR: u32 = 0b0_0010;
+const F5_SC: u32 = 0b0_0011;
+const F5_AMOXOR: u32 = 0b0_0100;
+const F5_AMOOR: u32 = 0b0_1000;
+const F5_AMOAND: u32 = 0b0_1100;
+const F5_AMOMIN: u32 = 0b1_0000;
+const F5_AMOMAX: u32 = 0b1_0100;
+const F5_AMOMINU: u32 = 0b1_1000;
+const F5_AMOMAXU: u32 = 0b1_1100;
+
+const F5_FADD: u32 = 0b0_0000;
+const F5_FSUB: u32 = 0b0_0001;
+const F5_FMUL: u32 = 0b0_0010;
+const F5_FDIV: u32 = 0b0_0011;
+const F5_FSGNJ: u32 = 0b0_0100;
+const F5_FMINMAX: u32 = 0b0_0101;
+const F5_FCVT_FF: u32 = 0b0_1000;
+const F5_FSQRT: u32 = 0b0_1011;
+const F5_FCMP: u32 = 0b1_0100;
+const F5_FCVT_XF: u32 = 0b1_1000;
+const F5_FCVT_FX: u32 = 0b1_1010;
+const F5_FMV_XF: u32 = 0b1_1100;
+const F5_FMV_FX: u32 = 0b1_1110;
+
+const FMT_S: u32 = 0b00;
+const FMT_D: u32 = 0b01;
+
+const F12_SRET: i64 = 0b0001_0000_0010;
+const F12_WFI: i64 = 0b0001_0000_0101;
+const F12_MRET: i64 = 0b0011_0000_0010;
+const F7_SFENCE_VMA: i64 = 0b000_1001;
+
 fn parse_uncompressed_instruction(instr: u32) -> Instr {
     use Instr::*;
     match opcode(instr) {
@@ -182,38 +441,46 @@ fn parse_uncompressed_instruction(instr: u32) -> Instr {
         OP_ARITH => match funct3(instr) {
             F3_0 => match funct7(instr) {
                 F7_0 => r_instr!(Add, instr),
+                F7_1 => r_instr!(Mul, instr),
                 F7_20 => r_instr!(Sub, instr),
                 _ => Unknown { instr },
             },
             F3_4 => match funct7(instr) {
                 F7_0 => r_instr!(Xor, instr),
+                F7_1 => r_instr!(Div, instr),
                 _ => Unknown { instr },
             },
             F3_6 => match funct7(instr) {
                 F7_0 => r_instr!(Or, instr),
+                F7_1 => r_instr!(Rem, instr),
                 _ => Unknown { instr },
             },
             F3_7 => match funct7(instr) {
                 F7_0 => r_instr!(And, instr),
+                F7_1 => r_instr!(Remu, instr),
                 _ => Unknown { instr },
             },
             F3_1 => match funct7(instr) {
                 F7_0 => r_instr!(Sll, instr),
+                F7_1 => r_instr!(Mulh, instr),
                 _ => Unknown { instr },
             },
             F3_5 => match funct7(instr) {
                 F7_0 => r_instr!(Srl, instr),
+                F7_1 => r_instr!(Divu, instr),
                 F7_20 => r_instr!(Sra, instr),
                 _ => Unknown { instr },
             },
 
             F3_2 => match funct7(instr) {
                 F7_0 => r_instr!(Slt, instr),
+                F7_1 => r_instr!(Mulhsu, instr),
                 _ => Unknown { instr },
             },
 
             F3_3 => match funct7(instr) {
                 F7_0 => r_instr!(Sltu, instr),
+                F7_1 => r_instr!(Mulhu, instr),
                 _ => Unknown { instr },
             },
             _ => Unknown { instr },
@@ -221,15 +488,62 @@ fn parse_uncompressed_instruction(instr: u32) -> Instr {
         OP_ARITH_W => match funct3(instr) {
             F3_0 => match funct7(instr) {
                 F7_0 => r_instr!(Addw, instr),
+                F7_1 => r_instr!(Mulw, instr),
                 F7_20 => r_instr!(Subw, instr),
                 _ => Unknown { instr },
             },
             F3_1 => r_instr!(Sllw, instr),
+            F3_4 => match funct7(instr) {
+                F7_1 => r_instr!(Divw, instr),
+                _ => Unknown { instr },
+            },
             F3_5 => match funct7(instr) {
                 F7_0 => r_instr!(Srlw, instr),
+                F7_1 => r_instr!(Divuw, instr),
                 F7_20 => r_instr!(Sraw, instr),
                 _ => Unknown { instr },
             },
+            F3_6 => match funct7(instr) {
+                F7_1 => r_instr!(Remw, instr),
+                _ => Unknown { instr },
+            },
+            F3_7 => match funct7(instr) {
+                F7_1 => r_instr!(Remuw, instr),
+                _ => Unknown { instr },
+            },
+            _ => Unknown { instr },
+        },
+
+        // Atomic instructions
+        OP_AMO => match funct3(instr) {
+            F3_2 => match funct5(instr) {
+                F5_LR if bits(instr, 20, 5) == 0 => amo_instr!(Lrw, instr),
+                F5_SC => amo_instr!(Scw, instr),
+                F5_AMOSWAP => amo_instr!(Amoswapw, instr),
+                F5_AMOADD => amo_instr!(Amoaddw, instr),
+                F5_AMOXOR => amo_instr!(Amoxorw, instr),
+                F5_AMOAND => amo_instr!(Amoandw, instr),
+                F5_AMOOR => amo_instr!(Amoorw, instr),
+                F5_AMOMIN => amo_instr!(Amominw, instr),
+                F5_AMOMAX => amo_instr!(Amomaxw, instr),
+                F5_AMOMINU => amo_instr!(Amominuw, instr),
+                F5_AMOMAXU => amo_instr!(Amomaxuw, instr),
+                _ => Unknown { instr },
+            },
+            F3_3 => match funct5(instr) {
+                F5_LR if bits(instr, 20, 5) == 0 => amo_instr!(Lrd, instr),
+                F5_SC => amo_instr!(Scd, instr),
+                F5_AMOSWAP => amo_instr!(Amoswapd, instr),
+                F5_AMOADD => amo_instr!(Amoaddd, instr),
+                F5_AMOXOR => amo_instr!(Amoxord, instr),
+                F5_AMOAND => amo_instr!(Amoandd, instr),
+                F5_AMOOR => amo_instr!(Amoord, instr),
+                F5_AMOMIN => amo_instr!(Amomind, instr),
+                F5_AMOMAX => amo_instr!(Amomaxd, instr),
+                F5_AMOMINU => amo_instr!(Amominud, instr),
+                F5_AMOMAXU => amo_instr!(Amomaxud, instr),
+                _ => Unknown { instr },
+            },
             _ => Unknown { instr },
         },
 
@@ -281,14 +595,29 @@ fn parse_uncompressed_instruction(instr: u32) -> Instr {
         },
         OP_SYNCH => match funct3(instr) {
             F3_0 => i_instr!(Fence, instr),
+            F3_1 => FenceI,
             _ => Unknown { instr },
         },
         OP_ENV => match funct3(instr) {
             F3_0 => match i_imm(instr) {
                 0b0 => Ecall,
                 0b1 => Ebreak,
+                F12_SRET => Sret,
+                F12_WFI => Wfi,
+                F12_MRET => Mret,
+                // The upper 7 bits of the immediate hold funct7, the lower 5 rs2
+                imm if imm >> 5 == F7_SFENCE_VMA && rd(instr).is_zero() => SFenceVma {
+                    vaddr: rs1(instr),
+                    asid: rs2(instr),
+                },
                 _ => Unknown { instr },
             },
+            F3_1 => csr_instr!(Csrrw, instr),
+            F3_2 => csr_instr!(Csrrs, instr),
+            F3_3 => csr_instr!(Csrrc, instr),
+            F3_5 => csri_instr!(Csrrwi, instr),
+            F3_6 => csri_instr!(Csrrsi, instr),
+            F3_7 => csri_instr!(Csrrci, instr),
             _ => Unknown { instr },
         },
 
@@ -322,13 +651,485 @@ fn parse_uncompressed_instruction(instr: u32) -> Instr {
             F3_0 => i_instr!(Jalr, instr),
             _ => Unknown { instr },
         },
+
+        // F and D instructions
+        OP_LOAD_FP => match funct3(instr) {
+            F3_2 => f_load_instr!(Flw, instr),
+            F3_3 => f_load_instr!(Fld, instr),
+            _ => Unknown { instr },
+        },
+        OP_STORE_FP => match funct3(instr) {
+            F3_2 => f_store_instr!(Fsw, instr),
+            F3_3 => f_store_instr!(Fsd, instr),
+            _ => Unknown { instr },
+        },
+        OP_MADD => match fmt(instr) {
+            FMT_S => f_r4_instr!(Fmadds, instr),
+            FMT_D => f_r4_instr!(Fmaddd, instr),
+            _ => Unknown { instr },
+        },
+        OP_MSUB => match fmt(instr) {
+            FMT_S => f_r4_instr!(Fmsubs, instr),
+            FMT_D => f_r4_instr!(Fmsubd, instr),
+            _ => Unknown { instr },
+        },
+        OP_NMSUB => match fmt(instr) {
+            FMT_S => f_r4_instr!(Fnmsubs, instr),
+            FMT_D => f_r4_instr!(Fnmsubd, instr),
+            _ => Unknown { instr },
+        },
+        OP_NMADD => match fmt(instr) {
+            FMT_S => f_r4_instr!(Fnmadds, instr),
+            FMT_D => f_r4_instr!(Fnmaddd, instr),
+            _ => Unknown { instr },
+        },
+        OP_FP => parse_fp_instruction(instr),
+        _ => Unknown { instr },
+    }
+}
+
+/// Parse an instruction of the `OP-FP` major opcode. (c.f. Table 24.2 - Unprivileged spec)
+fn parse_fp_instruction(instr: u32) -> Instr {
+    use Instr::*;
+    // Unary operations use the rs2 field to encode their variant
+    let rs2_bits = bits(instr, 20, 5);
+
+    match (funct5(instr), fmt(instr)) {
+        (F5_FADD, FMT_S) => f_rm_instr!(Fadds, instr),
+        (F5_FADD, FMT_D) => f_rm_instr!(Faddd, instr),
+        (F5_FSUB, FMT_S) => f_rm_instr!(Fsubs, instr),
+        (F5_FSUB, FMT_D) => f_rm_instr!(Fsubd, instr),
+        (F5_FMUL, FMT_S) => f_rm_instr!(Fmuls, instr),
+        (F5_FMUL, FMT_D) => f_rm_instr!(Fmuld, instr),
+        (F5_FDIV, FMT_S) => f_rm_instr!(Fdivs, instr),
+        (F5_FDIV, FMT_D) => f_rm_instr!(Fdivd, instr),
+        (F5_FSQRT, FMT_S) if rs2_bits == 0 => f_r1_instr!(Fsqrts, instr),
+        (F5_FSQRT, FMT_D) if rs2_bits == 0 => f_r1_instr!(Fsqrtd, instr),
+        (F5_FSGNJ, FMT_S) => match funct3(instr) {
+            F3_0 => f_r_instr!(Fsgnjs, instr),
+            F3_1 => f_r_instr!(Fsgnjns, instr),
+            F3_2 => f_r_instr!(Fsgnjxs, instr),
+            _ => Unknown { instr },
+        },
+        (F5_FSGNJ, FMT_D) => match funct3(instr) {
+            F3_0 => f_r_instr!(Fsgnjd, instr),
+            F3_1 => f_r_instr!(Fsgnjnd, instr),
+            F3_2 => f_r_instr!(Fsgnjxd, instr),
+            _ => Unknown { instr },
+        },
+        (F5_FMINMAX, FMT_S) => match funct3(instr) {
+            F3_0 => f_r_instr!(Fmins, instr),
+            F3_1 => f_r_instr!(Fmaxs, instr),
+            _ => Unknown { instr },
+        },
+        (F5_FMINMAX, FMT_D) => match funct3(instr) {
+            F3_0 => f_r_instr!(Fmind, instr),
+            F3_1 => f_r_instr!(Fmaxd, instr),
+            _ => Unknown { instr },
+        },
+        // The source format is held in the rs2 field
+        (F5_FCVT_FF, FMT_S) if rs2_bits == FMT_D => f_r1_instr!(Fcvtsd, instr),
+        (F5_FCVT_FF, FMT_D) if rs2_bits == FMT_S => f_r1_instr!(Fcvtds, instr),
+        (F5_FCMP, FMT_S) => match funct3(instr) {
+            F3_0 => f_cmp_instr!(Fles, instr),
+            F3_1 => f_cmp_instr!(Flts, instr),
+            F3_2 => f_cmp_instr!(Feqs, instr),
+            _ => Unknown { instr },
+        },
+        (F5_FCMP, FMT_D) => match funct3(instr) {
+            F3_0 => f_cmp_instr!(Fled, instr),
+            F3_1 => f_cmp_instr!(Fltd, instr),
+            F3_2 => f_cmp_instr!(Feqd, instr),
+            _ => Unknown { instr },
+        },
+        (F5_FCVT_XF, FMT_S) => match rs2_bits {
+            0b00 => xf_rm_instr!(Fcvtws, instr),
+            0b01 => xf_rm_instr!(Fcvtwus, instr),
+            0b10 => xf_rm_instr!(Fcvtls, instr),
+            0b11 => xf_rm_instr!(Fcvtlus, instr),
+            _ => Unknown { instr },
+        },
+        (F5_FCVT_XF, FMT_D) => match rs2_bits {
+            0b00 => xf_rm_instr!(Fcvtwd, instr),
+            0b01 => xf_rm_instr!(Fcvtwud, instr),
+            0b10 => xf_rm_instr!(Fcvtld, instr),
+            0b11 => xf_rm_instr!(Fcvtlud, instr),
+            _ => Unknown { instr },
+        },
+        (F5_FCVT_FX, FMT_S) => match rs2_bits {
+            0b00 => fx_rm_instr!(Fcvtsw, instr),
+            0b01 => fx_rm_instr!(Fcvtswu, instr),
+            0b10 => fx_rm_instr!(Fcvtsl, instr),
+            0b11 => fx_rm_instr!(Fcvtslu, instr),
+            _ => Unknown { instr },
+        },
+        (F5_FCVT_FX, FMT_D) => match rs2_bits {
+            0b00 => fx_rm_instr!(Fcvtdw, instr),
+            0b01 => fx_rm_instr!(Fcvtdwu, instr),
+            0b10 => fx_rm_instr!(Fcvtdl, instr),
+            0b11 => fx_rm_instr!(Fcvtdlu, instr),
+            _ => Unknown { instr },
+        },
+        (F5_FMV_XF, FMT_S) if rs2_bits == 0 => match funct3(instr) {
+            F3_0 => xf_instr!(Fmvxw, instr),
+            F3_1 => xf_instr!(Fclasss, instr),
+            _ => Unknown { instr },
+        },
+        (F5_FMV_XF, FMT_D) if rs2_bits == 0 => match funct3(instr) {
+            F3_0 => xf_instr!(Fmvxd, instr),
+            F3_1 => xf_instr!(Fclassd, instr),
+            _ => Unknown { instr },
+        },
+        (F5_FMV_FX, FMT_S) if rs2_bits == 0 && funct3(instr) == F3_0 => fx_instr!(Fmvwx, instr),
+        (F5_FMV_FX, FMT_D) if rs2_bits == 0 && funct3(instr) == F3_0 => fx_instr!(Fmvdx, instr),
         _ => Unknown { instr },
     }
 }
 
-fn parse_compressed_instruction(bytes: u16) -> Instr {
-    // TODO parse a compressed instruction
-    Instr::UnknownCompressed { instr: bytes }
+// Compressed instructions (c.f. Chapter 16 - Unprivileged spec)
+
+#[inline(always)]
+fn c_bits(instr: u16, pos: usize, n: usize) -> u32 {
+    bits(instr as u32, pos, n)
+}
+
+#[inline(always)]
+fn c_opcode(instr: u16) -> u32 {
+    c_bits(instr, 0, 2)
+}
+
+#[inline(always)]
+fn c_funct3(instr: u16) -> u32 {
+    c_bits(instr, 13, 3)
+}
+
+#[inline(always)]
+fn c_rd_rs1(instr: u16) -> XRegister {
+    parse_register(c_bits(instr, 7, 5))
+}
+
+#[inline(always)]
+fn c_rs2(instr: u16) -> XRegister {
+    parse_register(c_bits(instr, 2, 5))
+}
+
+/// `rd'` or `rs1'` field, one of the 8 most used registers `x8` to `x15`
+#[inline(always)]
+fn c_rd_rs1_prime(instr: u16) -> XRegister {
+    parse_register(8 + c_bits(instr, 7, 3))
+}
+
+/// `rd'` or `rs2'` field, one of the 8 most used registers `x8` to `x15`
+#[inline(always)]
+fn c_rd_rs2_prime(instr: u16) -> XRegister {
+    parse_register(8 + c_bits(instr, 2, 3))
+}
+
+#[inline(always)]
+fn c_frd(instr: u16) -> FRegister {
+    parse_fregister(c_bits(instr, 7, 5))
+}
+
+#[inline(always)]
+fn c_frs2(instr: u16) -> FRegister {
+    parse_fregister(c_bits(instr, 2, 5))
+}
+
+/// `rd'` or `rs2'` field, one of the 8 most used registers `f8` to `f15`
+#[inline(always)]
+fn c_frd_frs2_prime(instr: u16) -> FRegister {
+    parse_fregister(8 + c_bits(instr, 2, 3))
+}
+
+/// Sign-extend the lowest `n` bits of `value`.
+#[inline(always)]
+fn sign_extend(value: u32, n: u32) -> i64 {
+    ((value as i64) << (64 - n)) >> (64 - n)
+}
+
+// Compressed immediates are scattered across the instruction, each segment
+// is extracted then shifted into place (c.f. Section 16.3).
+
+fn ci_imm(instr: u16) -> i64 {
+    // imm[5] | imm[4:0]
+    sign_extend(c_bits(instr, 12, 1) << 5 | c_bits(instr, 2, 5), 6)
+}
+
+fn ci_shamt(instr: u16) -> i64 {
+    // shamt[5] | shamt[4:0]
+    (c_bits(instr, 12, 1) << 5 | c_bits(instr, 2, 5)) as i64
+}
+
+fn ciw_imm(instr: u16) -> i64 {
+    // nzuimm[5:4|9:6|2|3]
+    (c_bits(instr, 11, 2) << 4
+        | c_bits(instr, 7, 4) << 6
+        | c_bits(instr, 6, 1) << 2
+        | c_bits(instr, 5, 1) << 3) as i64
+}
+
+fn cl_w_imm(instr: u16) -> i64 {
+    // uimm[5:3] | uimm[2|6]
+    (c_bits(instr, 10, 3) << 3 | c_bits(instr, 6, 1) << 2 | c_bits(instr, 5, 1) << 6) as i64
+}
+
+fn cl_d_imm(instr: u16) -> i64 {
+    // uimm[5:3] | uimm[7:6]
+    (c_bits(instr, 10, 3) << 3 | c_bits(instr, 5, 2) << 6) as i64
+}
+
+fn addi16sp_imm(instr: u16) -> i64 {
+    // nzimm[9] | nzimm[4|6|8:7|5]
+    let imm = c_bits(instr, 12, 1) << 9
+        | c_bits(instr, 6, 1) << 4
+        | c_bits(instr, 5, 1) << 6
+        | c_bits(instr, 3, 2) << 7
+        | c_bits(instr, 2, 1) << 5;
+    sign_extend(imm, 10)
+}
+
+fn c_lui_imm(instr: u16) -> i64 {
+    // nzimm[17] | nzimm[16:12]
+    sign_extend(c_bits(instr, 12, 1) << 17 | c_bits(instr, 2, 5) << 12, 18)
+}
+
+fn cj_imm(instr: u16) -> i64 {
+    // offset[11|4|9:8|10|6|7|3:1|5]
+    let imm = c_bits(instr, 12, 1) << 11
+        | c_bits(instr, 11, 1) << 4
+        | c_bits(instr, 9, 2) << 8
+        | c_bits(instr, 8, 1) << 10
+        | c_bits(instr, 7, 1) << 6
+        | c_bits(instr, 6, 1) << 7
+        | c_bits(instr, 3, 3) << 1
+        | c_bits(instr, 2, 1) << 5;
+    sign_extend(imm, 12)
+}
+
+fn cb_imm(instr: u16) -> i64 {
+    // offset[8|4:3] | offset[7:6|2:1|5]
+    let imm = c_bits(instr, 12, 1) << 8
+        | c_bits(instr, 10, 2) << 3
+        | c_bits(instr, 5, 2) << 6
+        | c_bits(instr, 3, 2) << 1
+        | c_bits(instr, 2, 1) << 5;
+    sign_extend(imm, 9)
+}
+
+fn lwsp_imm(instr: u16) -> i64 {
+    // uimm[5] | uimm[4:2|7:6]
+    (c_bits(instr, 12, 1) << 5 | c_bits(instr, 4, 3) << 2 | c_bits(instr, 2, 2) << 6) as i64
+}
+
+fn ldsp_imm(instr: u16) -> i64 {
+    // uimm[5] | uimm[4:3|8:6]
+    (c_bits(instr, 12, 1) << 5 | c_bits(instr, 5, 2) << 3 | c_bits(instr, 2, 3) << 6) as i64
+}
+
+fn swsp_imm(instr: u16) -> i64 {
+    // uimm[5:2|7:6]
+    (c_bits(instr, 9, 4) << 2 | c_bits(instr, 7, 2) << 6) as i64
+}
+
+fn sdsp_imm(instr: u16) -> i64 {
+    // uimm[5:3|8:6]
+    (c_bits(instr, 10, 3) << 3 | c_bits(instr, 7, 3) << 6) as i64
+}
+
+const C_Q0: u32 = 0b00;
+const C_Q1: u32 = 0b01;
+const C_Q2: u32 = 0b10;
+
+fn parse_compressed_instruction(instr: u16) -> Instr {
+    use Instr::*;
+    match c_opcode(instr) {
+        C_Q0 => match c_funct3(instr) {
+            F3_0 => match ciw_imm(instr) {
+                // Also covers the all-zero instruction, which is illegal
+                0 => UnknownCompressed { instr },
+                imm => CAddi4spn(ITypeArgs {
+                    rd: c_rd_rs2_prime(instr),
+                    rs1: sp,
+                    imm,
+                }),
+            },
+            F3_1 => CFld(FLoadArgs {
+                rd: c_frd_frs2_prime(instr),
+                rs1: c_rd_rs1_prime(instr),
+                imm: cl_d_imm(instr),
+            }),
+            F3_2 => CLw(ITypeArgs {
+                rd: c_rd_rs2_prime(instr),
+                rs1: c_rd_rs1_prime(instr),
+                imm: cl_w_imm(instr),
+            }),
+            F3_3 => CLd(ITypeArgs {
+                rd: c_rd_rs2_prime(instr),
+                rs1: c_rd_rs1_prime(instr),
+                imm: cl_d_imm(instr),
+            }),
+            F3_5 => CFsd(FStoreArgs {
+                rs1: c_rd_rs1_prime(instr),
+                rs2: c_frd_frs2_prime(instr),
+                imm: cl_d_imm(instr),
+            }),
+            F3_6 => CSw(SBTypeArgs {
+                rs1: c_rd_rs1_prime(instr),
+                rs2: c_rd_rs2_prime(instr),
+                imm: cl_w_imm(instr),
+            }),
+            F3_7 => CSd(SBTypeArgs {
+                rs1: c_rd_rs1_prime(instr),
+                rs2: c_rd_rs2_prime(instr),
+                imm: cl_d_imm(instr),
+            }),
+            _ => UnknownCompressed { instr },
+        },
+        C_Q1 => match c_funct3(instr) {
+            F3_0 => match c_rd_rs1(instr) {
+                rd if rd.is_zero() => CNop,
+                rd => CAddi(ITypeArgs {
+                    rd,
+                    rs1: rd,
+                    imm: ci_imm(instr),
+                }),
+            },
+            F3_1 => match c_rd_rs1(instr) {
+                rd if rd.is_zero() => UnknownCompressed { instr },
+                rd => CAddiw(ITypeArgs {
+                    rd,
+                    rs1: rd,
+                    imm: ci_imm(instr),
+                }),
+            },
+            F3_2 => CLi(ITypeArgs {
+                rd: c_rd_rs1(instr),
+                rs1: x0,
+                imm: ci_imm(instr),
+            }),
+            F3_3 => match c_rd_rs1(instr) {
+                rd if rd == sp => match addi16sp_imm(instr) {
+                    0 => UnknownCompressed { instr },
+                    imm => CAddi16sp(ITypeArgs { rd, rs1: rd, imm }),
+                },
+                rd => match c_lui_imm(instr) {
+                    0 => UnknownCompressed { instr },
+                    imm => CLui(UJTypeArgs { rd, imm }),
+                },
+            },
+            F3_4 => {
+                let rd = c_rd_rs1_prime(instr);
+                match c_bits(instr, 10, 2) {
+                    0b00 => CSrli(ITypeArgs {
+                        rd,
+                        rs1: rd,
+                        imm: ci_shamt(instr),
+                    }),
+                    0b01 => CSrai(ITypeArgs {
+                        rd,
+                        rs1: rd,
+                        imm: ci_shamt(instr),
+                    }),
+                    0b10 => CAndi(ITypeArgs {
+                        rd,
+                        rs1: rd,
+                        imm: ci_imm(instr),
+                    }),
+                    _ => {
+                        let args = RTypeArgs {
+                            rd,
+                            rs1: rd,
+                            rs2: c_rd_rs2_prime(instr),
+                        };
+                        match (c_bits(instr, 12, 1), c_bits(instr, 5, 2)) {
+                            (0, 0b00) => CSub(args),
+                            (0, 0b01) => CXor(args),
+                            (0, 0b10) => COr(args),
+                            (0, 0b11) => CAnd(args),
+                            (1, 0b00) => CSubw(args),
+                            (1, 0b01) => CAddw(args),
+                            _ => UnknownCompressed { instr },
+                        }
+                    }
+                }
+            }
+            F3_5 => CJ { imm: cj_imm(instr) },
+            F3_6 => CBeqz {
+                rs1: c_rd_rs1_prime(instr),
+                imm: cb_imm(instr),
+            },
+            F3_7 => CBnez {
+                rs1: c_rd_rs1_prime(instr),
+                imm: cb_imm(instr),
+            },
+            _ => UnknownCompressed { instr },
+        },
+        C_Q2 => match c_funct3(instr) {
+            F3_0 => CSlli(ITypeArgs {
+                rd: c_rd_rs1(instr),
+                rs1: c_rd_rs1(instr),
+                imm: ci_shamt(instr),
+            }),
+            F3_1 => CFldsp(FLoadArgs {
+                rd: c_frd(instr),
+                rs1: sp,
+                imm: ldsp_imm(instr),
+            }),
+            F3_2 => match c_rd_rs1(instr) {
+                rd if rd.is_zero() => UnknownCompressed { instr },
+                rd => CLwsp(ITypeArgs {
+                    rd,
+                    rs1: sp,
+                    imm: lwsp_imm(instr),
+                }),
+            },
+            F3_3 => match c_rd_rs1(instr) {
+                rd if rd.is_zero() => UnknownCompressed { instr },
+                rd => CLdsp(ITypeArgs {
+                    rd,
+                    rs1: sp,
+                    imm: ldsp_imm(instr),
+                }),
+            },
+            F3_4 => {
+                let rd_rs1 = c_rd_rs1(instr);
+                let rs2 = c_rs2(instr);
+                match (c_bits(instr, 12, 1), rd_rs1.is_zero(), rs2.is_zero()) {
+                    (0, true, true) => UnknownCompressed { instr },
+                    (0, false, true) => CJr { rs1: rd_rs1 },
+                    (0, _, false) => CMv(RTypeArgs {
+                        rd: rd_rs1,
+                        rs1: x0,
+                        rs2,
+                    }),
+                    (_, true, true) => CEbreak,
+                    (_, false, true) => CJalr { rs1: rd_rs1 },
+                    (_, _, false) => CAdd(RTypeArgs {
+                        rd: rd_rs1,
+                        rs1: rd_rs1,
+                        rs2,
+                    }),
+                }
+            }
+            F3_5 => CFsdsp(FStoreArgs {
+                rs1: sp,
+                rs2: c_frs2(instr),
+                imm: sdsp_imm(instr),
+            }),
+            F3_6 => CSwsp(SBTypeArgs {
+                rs1: sp,
+                rs2: c_rs2(instr),
+                imm: swsp_imm(instr),
+            }),
+            F3_7 => CSdsp(SBTypeArgs {
+                rs1: sp,
+                rs2: c_rs2(instr),
+                imm: sdsp_imm(instr),
+            }),
+            _ => UnknownCompressed { instr },
+        },
+        _ => UnknownCompressed { instr },
+    }
 }
 
 /// Attempt to parse `bytes` into an instruction. If `bytes` encodes a 2-byte
@@ -369,8 +1170,13 @@ pub fn parse_segment(contents: &[u8], range: Range<usize>) -> Vec<Instr> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::machine_state::registers::FRegister::*;
     use crate::machine_state::registers::XRegister::*;
-    use instruction::{ITypeArgs, SBTypeArgs, UJTypeArgs};
+    use instruction::{
+        AmoArgs, CsrArgs, CsriArgs, FCmpArgs, FLoadArgs, FR1Args, FR4Args, FRTypeArgs, FRmArgs,
+        FStoreArgs, FXArgs, FXRmArgs, ITypeArgs, InstrRoundingMode, RTypeArgs, SBTypeArgs,
+        UJTypeArgs, XFArgs, XFRmArgs,
+    };
 
     // rv64ui-p-addiw
     // 0000000080000000 <_start>:
@@ -387,9 +1193,11 @@ mod tests {
 
         let expected = [
             Instr::Jal(UJTypeArgs { rd: x0, imm: 0x50 }),
-            Instr::Unknown {
-                instr: u32::from_le_bytes([0x73, 0x2f, 0x20, 0x34]),
-            },
+            Instr::Csrrs(CsrArgs {
+                rd: x30,
+                rs1: x0,
+                csr: CSRegister::mcause,
+            }),
             Instr::Addi(ITypeArgs {
                 rd: x31,
                 rs1: x0,
@@ -421,17 +1229,20 @@ mod tests {
                 rs1: x0,
                 imm: 21,
             }),
-            Instr::UnknownCompressed {
-                instr: u16::from_le_bytes([0x05, 0x64]),
-            },
+            Instr::CLui(UJTypeArgs {
+                rd: x8,
+                imm: 0x1000,
+            }),
             Instr::Addiw(ITypeArgs {
                 rd: x8,
                 rs1: x8,
                 imm: 564,
             }),
-            Instr::UnknownCompressed {
-                instr: u16::from_le_bytes([0x12, 0x04]),
-            },
+            Instr::CSlli(ITypeArgs {
+                rd: x8,
+                rs1: x8,
+                imm: 4,
+            }),
             Instr::Lui(UJTypeArgs {
                 rd: x7,
                 imm: 0x12 << 12,
@@ -456,12 +1267,16 @@ mod tests {
     fn test_3() {
         let bytes: [u8; 5] = [0x1, 0x5, 0x64, 0x1b, 0x4];
         let expected = [
-            Instr::UnknownCompressed {
-                instr: u16::from_le_bytes([0x1, 0x5]),
-            },
-            Instr::UnknownCompressed {
-                instr: u16::from_le_bytes([0x64, 0x1b]),
-            },
+            Instr::CAddi(ITypeArgs {
+                rd: x10,
+                rs1: x10,
+                imm: 0,
+            }),
+            Instr::CAddi4spn(ITypeArgs {
+                rd: x9,
+                rs1: x2,
+                imm: 444,
+            }),
         ];
         let instructions = parse_block(&bytes);
         assert_eq!(instructions, expected)
@@ -494,4 +1309,581 @@ mod tests {
         let instructions = parse_block(&bytes);
         assert_eq!(instructions, expected)
     }
+
+    // 80000044:	30200073          	mret
+    // 80000048:	10200073          	sret
+    // 8000004c:	10500073          	wfi
+    // 80000050:	0000100f          	fence.i
+    // 80000054:	3052d073          	csrwi	mtvec,5
+    // 80000058:	7c001073          	csrw	0x7c0,zero
+    #[test]
+    fn test_privileged() {
+        let bytes: [u8; 24] = [
+            0x73, 0x0, 0x20, 0x30, 0x73, 0x0, 0x20, 0x10, 0x73, 0x0, 0x50, 0x10, 0xf, 0x10, 0x0,
+            0x0, 0x73, 0xd0, 0x52, 0x30, 0x73, 0x10, 0x0, 0x7c,
+        ];
+        let expected = [
+            Instr::Mret,
+            Instr::Sret,
+            Instr::Wfi,
+            Instr::FenceI,
+            Instr::Csrrwi(CsriArgs {
+                rd: x0,
+                imm: 5,
+                csr: CSRegister::mtvec,
+            }),
+            // Unimplemented CSRs can't be accessed
+            Instr::Unknown {
+                instr: u32::from_le_bytes([0x73, 0x10, 0x0, 0x7c]),
+            },
+        ];
+        let instructions = parse_block(&bytes);
+        assert_eq!(instructions, expected);
+
+        // sfence.vma a0, a1
+        assert_eq!(
+            parse_uncompressed_instruction(0x12b50073),
+            Instr::SFenceVma {
+                vaddr: x10,
+                asid: x11
+            }
+        );
+        // sfence.vma
+        assert_eq!(
+            parse_uncompressed_instruction(0x12000073),
+            Instr::SFenceVma {
+                vaddr: x0,
+                asid: x0
+            }
+        );
+    }
+
+    //     mul     a0, a1, a2
+    //     mulhsu  t0, t1, t2
+    //     divu    s0, s1, a0
+    //     remw    a5, a4, a3
+    //     divuw   a0, a0, a1
+    //     lr.w.aq a0, (a1)
+    //     sc.d.rl a2, a3, (a4)
+    //     amoswap.w       a0, a1, (a2)
+    //     amomaxu.d.aqrl  t0, t1, (t2)
+    //     amoadd.d        zero, a1, (sp)
+    #[test]
+    fn test_m_a() {
+        let bytes: [u8; 40] = [
+            0x33, 0x85, 0xc5, 0x02, 0xb3, 0x22, 0x73, 0x02, 0x33, 0xd4, 0xa4, 0x02, 0xbb, 0x67,
+            0xd7, 0x02, 0x3b, 0x55, 0xb5, 0x02, 0x2f, 0xa5, 0x05, 0x14, 0x2f, 0x36, 0xd7, 0x1a,
+            0x2f, 0x25, 0xb6, 0x08, 0xaf, 0xb2, 0x63, 0xe6, 0x2f, 0x30, 0xb1, 0x00,
+        ];
+        let expected = [
+            Instr::Mul(RTypeArgs {
+                rd: x10,
+                rs1: x11,
+                rs2: x12,
+            }),
+            Instr::Mulhsu(RTypeArgs {
+                rd: x5,
+                rs1: x6,
+                rs2: x7,
+            }),
+            Instr::Divu(RTypeArgs {
+                rd: x8,
+                rs1: x9,
+                rs2: x10,
+            }),
+            Instr::Remw(RTypeArgs {
+                rd: x15,
+                rs1: x14,
+                rs2: x13,
+            }),
+            Instr::Divuw(RTypeArgs {
+                rd: x10,
+                rs1: x10,
+                rs2: x11,
+            }),
+            Instr::Lrw(AmoArgs {
+                rd: x10,
+                rs1: x11,
+                rs2: x0,
+                aq: true,
+                rl: false,
+            }),
+            Instr::Scd(AmoArgs {
+                rd: x12,
+                rs1: x14,
+                rs2: x13,
+                aq: false,
+                rl: true,
+            }),
+            Instr::Amoswapw(AmoArgs {
+                rd: x10,
+                rs1: x12,
+                rs2: x11,
+                aq: false,
+                rl: false,
+            }),
+            Instr::Amomaxud(AmoArgs {
+                rd: x5,
+                rs1: x7,
+                rs2: x6,
+                aq: true,
+                rl: true,
+            }),
+            Instr::Amoaddd(AmoArgs {
+                rd: x0,
+                rs1: x2,
+                rs2: x11,
+                aq: false,
+                rl: false,
+            }),
+        ];
+        let instructions = parse_block(&bytes);
+        assert_eq!(instructions, expected)
+    }
+
+    // One instance of each RV64C instruction, as assembled by llvm-mc
+    #[test]
+    fn test_compressed() {
+        let encodings: [(u16, Instr); 33] = [
+            // c.addi4spn s0, sp, 1020
+            (
+                0x1fe0,
+                Instr::CAddi4spn(ITypeArgs {
+                    rd: x8,
+                    rs1: x2,
+                    imm: 1020,
+                }),
+            ),
+            // c.lw a0, 124(a1)
+            (
+                0x5de8,
+                Instr::CLw(ITypeArgs {
+                    rd: x10,
+                    rs1: x11,
+                    imm: 124,
+                }),
+            ),
+            // c.ld a2, 248(a3)
+            (
+                0x7ef0,
+                Instr::CLd(ITypeArgs {
+                    rd: x12,
+                    rs1: x13,
+                    imm: 248,
+                }),
+            ),
+            // c.sw a4, 64(a5)
+            (
+                0xc3b8,
+                Instr::CSw(SBTypeArgs {
+                    rs1: x15,
+                    rs2: x14,
+                    imm: 64,
+                }),
+            ),
+            // c.sd s1, 8(s0)
+            (
+                0xe404,
+                Instr::CSd(SBTypeArgs {
+                    rs1: x8,
+                    rs2: x9,
+                    imm: 8,
+                }),
+            ),
+            // c.nop
+            (0x0001, Instr::CNop),
+            // c.addi a0, -32
+            (
+                0x1501,
+                Instr::CAddi(ITypeArgs {
+                    rd: x10,
+                    rs1: x10,
+                    imm: -32,
+                }),
+            ),
+            // c.addiw a1, 31
+            (
+                0x25fd,
+                Instr::CAddiw(ITypeArgs {
+                    rd: x11,
+                    rs1: x11,
+                    imm: 31,
+                }),
+            ),
+            // c.li a2, -1
+            (
+                0x567d,
+                Instr::CLi(ITypeArgs {
+                    rd: x12,
+                    rs1: x0,
+                    imm: -1,
+                }),
+            ),
+            // c.addi16sp sp, -512
+            (
+                0x7101,
+                Instr::CAddi16sp(ITypeArgs {
+                    rd: x2,
+                    rs1: x2,
+                    imm: -512,
+                }),
+            ),
+            // c.lui a3, 0xfffe0
+            (
+                0x7681,
+                Instr::CLui(UJTypeArgs {
+                    rd: x13,
+                    imm: -0x20000,
+                }),
+            ),
+            // c.srli s0, 63
+            (
+                0x907d,
+                Instr::CSrli(ITypeArgs {
+                    rd: x8,
+                    rs1: x8,
+                    imm: 63,
+                }),
+            ),
+            // c.srai s1, 1
+            (
+                0x8485,
+                Instr::CSrai(ITypeArgs {
+                    rd: x9,
+                    rs1: x9,
+                    imm: 1,
+                }),
+            ),
+            // c.andi a0, -4
+            (
+                0x9971,
+                Instr::CAndi(ITypeArgs {
+                    rd: x10,
+                    rs1: x10,
+                    imm: -4,
+                }),
+            ),
+            // c.sub s0, s1
+            (
+                0x8c05,
+                Instr::CSub(RTypeArgs {
+                    rd: x8,
+                    rs1: x8,
+                    rs2: x9,
+                }),
+            ),
+            // c.xor a0, a1
+            (
+                0x8d2d,
+                Instr::CXor(RTypeArgs {
+                    rd: x10,
+                    rs1: x10,
+                    rs2: x11,
+                }),
+            ),
+            // c.or a2, a3
+            (
+                0x8e55,
+                Instr::COr(RTypeArgs {
+                    rd: x12,
+                    rs1: x12,
+                    rs2: x13,
+                }),
+            ),
+            // c.and a4, a5
+            (
+                0x8f7d,
+                Instr::CAnd(RTypeArgs {
+                    rd: x14,
+                    rs1: x14,
+                    rs2: x15,
+                }),
+            ),
+            // c.subw s0, a5
+            (
+                0x9c1d,
+                Instr::CSubw(RTypeArgs {
+                    rd: x8,
+                    rs1: x8,
+                    rs2: x15,
+                }),
+            ),
+            // c.addw s1, a0
+            (
+                0x9ca9,
+                Instr::CAddw(RTypeArgs {
+                    rd: x9,
+                    rs1: x9,
+                    rs2: x10,
+                }),
+            ),
+            // c.j -2048
+            (0xb001, Instr::CJ { imm: -2048 }),
+            // c.beqz a0, 254
+            (0xcd7d, Instr::CBeqz { rs1: x10, imm: 254 }),
+            // c.bnez s1, -256
+            (0xf081, Instr::CBnez { rs1: x9, imm: -256 }),
+            // c.slli ra, 12
+            (
+                0x00b2,
+                Instr::CSlli(ITypeArgs {
+                    rd: x1,
+                    rs1: x1,
+                    imm: 12,
+                }),
+            ),
+            // c.lwsp t0, 252(sp)
+            (
+                0x52fe,
+                Instr::CLwsp(ITypeArgs {
+                    rd: x5,
+                    rs1: x2,
+                    imm: 252,
+                }),
+            ),
+            // c.ldsp t1, 504(sp)
+            (
+                0x737e,
+                Instr::CLdsp(ITypeArgs {
+                    rd: x6,
+                    rs1: x2,
+                    imm: 504,
+                }),
+            ),
+            // c.jr ra
+            (0x8082, Instr::CJr { rs1: x1 }),
+            // c.mv a0, t0
+            (
+                0x8516,
+                Instr::CMv(RTypeArgs {
+                    rd: x10,
+                    rs1: x0,
+                    rs2: x5,
+                }),
+            ),
+            // c.ebreak
+            (0x9002, Instr::CEbreak),
+            // c.jalr t1
+            (0x9302, Instr::CJalr { rs1: x6 }),
+            // c.add sp, a0
+            (
+                0x912a,
+                Instr::CAdd(RTypeArgs {
+                    rd: x2,
+                    rs1: x2,
+                    rs2: x10,
+                }),
+            ),
+            // c.swsp ra, 252(sp)
+            (
+                0xdf86,
+                Instr::CSwsp(SBTypeArgs {
+                    rs1: x2,
+                    rs2: x1,
+                    imm: 252,
+                }),
+            ),
+            // c.sdsp s11, 504(sp)
+            (
+                0xffee,
+                Instr::CSdsp(SBTypeArgs {
+                    rs1: x2,
+                    rs2: x27,
+                    imm: 504,
+                }),
+            ),
+        ];
+
+        for (bytes, expected) in encodings {
+            assert_eq!(parse_compressed_instruction(bytes), expected);
+        }
+
+        // Reserved encodings: all zeroes, c.addiw and c.lwsp with rd = x0,
+        // c.lui with a zero immediate
+        for bytes in [0x0000, 0x2001, 0x4002, 0x6681] {
+            assert_eq!(
+                parse_compressed_instruction(bytes),
+                Instr::UnknownCompressed { instr: bytes }
+            );
+        }
+    }
+
+    // Instances of the RV64F and RV64D instructions, as assembled by llvm-mc
+    #[test]
+    fn test_floating_point() {
+        use InstrRoundingMode::{Dynamic, Static};
+        use RoundingMode::{RNE, RTZ};
+
+        let encodings: [(u32, Instr); 17] = [
+            // flw fa0, 8(a1)
+            (
+                0x0085a507,
+                Instr::Flw(FLoadArgs {
+                    rd: f10,
+                    rs1: x11,
+                    imm: 8,
+                }),
+            ),
+            // fsd fs0, -16(sp)
+            (
+                0xfe813827,
+                Instr::Fsd(FStoreArgs {
+                    rs1: x2,
+                    rs2: f8,
+                    imm: -16,
+                }),
+            ),
+            // fadd.s fa0, fa1, fa2
+            (
+                0x00c5f553,
+                Instr::Fadds(FRmArgs {
+                    rd: f10,
+                    rs1: f11,
+                    rs2: f12,
+                    rm: Dynamic,
+                }),
+            ),
+            // fmul.d ft0, ft1, ft2, rtz
+            (
+                0x12209053,
+                Instr::Fmuld(FRmArgs {
+                    rd: f0,
+                    rs1: f1,
+                    rs2: f2,
+                    rm: Static(RTZ),
+                }),
+            ),
+            // fmadd.s fa0, fa1, fa2, fa3
+            (
+                0x68c5f543,
+                Instr::Fmadds(FR4Args {
+                    rd: f10,
+                    rs1: f11,
+                    rs2: f12,
+                    rs3: f13,
+                    rm: Dynamic,
+                }),
+            ),
+            // fnmadd.d fa0, fa1, fa2, fa3, rne
+            (
+                0x6ac5854f,
+                Instr::Fnmaddd(FR4Args {
+                    rd: f10,
+                    rs1: f11,
+                    rs2: f12,
+                    rs3: f13,
+                    rm: Static(RNE),
+                }),
+            ),
+            // fsqrt.d fa0, fa1
+            (
+                0x5a05f553,
+                Instr::Fsqrtd(FR1Args {
+                    rd: f10,
+                    rs1: f11,
+                    rm: Dynamic,
+                }),
+            ),
+            // fneg.s fa0, fa1
+            (
+                0x20b59553,
+                Instr::Fsgnjns(FRTypeArgs {
+                    rd: f10,
+                    rs1: f11,
+                    rs2: f11,
+                }),
+            ),
+            // fmax.d fa0, fa1, fa2
+            (
+                0x2ac59553,
+                Instr::Fmaxd(FRTypeArgs {
+                    rd: f10,
+                    rs1: f11,
+                    rs2: f12,
+                }),
+            ),
+            // fcvt.s.d fa0, fa1
+            (
+                0x4015f553,
+                Instr::Fcvtsd(FR1Args {
+                    rd: f10,
+                    rs1: f11,
+                    rm: Dynamic,
+                }),
+            ),
+            // fcvt.d.s fa0, fa1
+            (
+                0x42058553,
+                Instr::Fcvtds(FR1Args {
+                    rd: f10,
+                    rs1: f11,
+                    rm: Static(RNE),
+                }),
+            ),
+            // flt.d a0, fa0, fa1
+            (
+                0xa2b51553,
+                Instr::Fltd(FCmpArgs {
+                    rd: x10,
+                    rs1: f10,
+                    rs2: f11,
+                }),
+            ),
+            // fcvt.wu.s a0, fa0, rtz
+            (
+                0xc0151553,
+                Instr::Fcvtwus(XFRmArgs {
+                    rd: x10,
+                    rs1: f10,
+                    rm: Static(RTZ),
+                }),
+            ),
+            // fcvt.d.lu fa0, a0
+            (
+                0xd2357553,
+                Instr::Fcvtdlu(FXRmArgs {
+                    rd: f10,
+                    rs1: x10,
+                    rm: Dynamic,
+                }),
+            ),
+            // fmv.x.w a0, fa0
+            (0xe0050553, Instr::Fmvxw(XFArgs { rd: x10, rs1: f10 })),
+            // fclass.d a0, fa0
+            (0xe2051553, Instr::Fclassd(XFArgs { rd: x10, rs1: f10 })),
+            // fmv.d.x fa0, a0
+            (0xf2050553, Instr::Fmvdx(FXArgs { rd: f10, rs1: x10 })),
+        ];
+
+        for (bytes, expected) in encodings {
+            assert_eq!(parse_uncompressed_instruction(bytes), expected);
+        }
+
+        // Reserved rounding modes 0b101 & 0b110
+        for bytes in [0x00c5d553, 0x00c5e553] {
+            assert_eq!(
+                parse_uncompressed_instruction(bytes),
+                Instr::Unknown { instr: bytes }
+            );
+        }
+
+        // c.fld fa0, 8(a1)
+        assert_eq!(
+            parse_compressed_instruction(0x2588),
+            Instr::CFld(FLoadArgs {
+                rd: f10,
+                rs1: x11,
+                imm: 8,
+            })
+        );
+        // c.fsdsp fs0, 16(sp)
+        assert_eq!(
+            parse_compressed_instruction(0xa822),
+            Instr::CFsdsp(FStoreArgs {
+                rs1: x2,
+                rs2: f8,
+                imm: 16,
+            })
+        );
+    }
 }
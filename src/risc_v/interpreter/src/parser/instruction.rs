@@ -2,7 +2,11 @@
 //
 // SPDX-License-Identifier: MIT
 
-use crate::machine_state::registers::XRegister;
+use crate::machine_state::{
+    csregisters::CSRegister,
+    registers::{FRegister, XRegister},
+};
+use crate::softfloat::RoundingMode;
 
 #[derive(Debug, PartialEq)]
 pub struct RTypeArgs {
@@ -31,6 +35,121 @@ pub struct UJTypeArgs {
     pub imm: i64,
 }
 
+#[derive(Debug, PartialEq)]
+pub struct CsrArgs {
+    pub rd: XRegister,
+    pub rs1: XRegister,
+    pub csr: CSRegister,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CsriArgs {
+    pub rd: XRegister,
+    pub imm: u64,
+    pub csr: CSRegister,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AmoArgs {
+    pub rd: XRegister,
+    pub rs1: XRegister,
+    pub rs2: XRegister,
+    pub aq: bool,
+    pub rl: bool,
+}
+
+/// Rounding mode operand of floating-point instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstrRoundingMode {
+    /// Use the rounding mode held in `frm`
+    Dynamic,
+    Static(RoundingMode),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FLoadArgs {
+    pub rd: FRegister,
+    pub rs1: XRegister,
+    pub imm: i64,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FStoreArgs {
+    pub rs1: XRegister,
+    pub rs2: FRegister,
+    pub imm: i64,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FRTypeArgs {
+    pub rd: FRegister,
+    pub rs1: FRegister,
+    pub rs2: FRegister,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FRmArgs {
+    pub rd: FRegister,
+    pub rs1: FRegister,
+    pub rs2: FRegister,
+    pub rm: InstrRoundingMode,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FR4Args {
+    pub rd: FRegister,
+    pub rs1: FRegister,
+    pub rs2: FRegister,
+    pub rs3: FRegister,
+    pub rm: InstrRoundingMode,
+}
+
+/// Single operand floating-point instruction, e.g. `FSQRT` or `FCVT.S.D`
+#[derive(Debug, PartialEq)]
+pub struct FR1Args {
+    pub rd: FRegister,
+    pub rs1: FRegister,
+    pub rm: InstrRoundingMode,
+}
+
+/// Floating-point comparison, writing to an integer register
+#[derive(Debug, PartialEq)]
+pub struct FCmpArgs {
+    pub rd: XRegister,
+    pub rs1: FRegister,
+    pub rs2: FRegister,
+}
+
+/// Conversion from a floating-point to an integer register
+#[derive(Debug, PartialEq)]
+pub struct XFRmArgs {
+    pub rd: XRegister,
+    pub rs1: FRegister,
+    pub rm: InstrRoundingMode,
+}
+
+/// Conversion from an integer to a floating-point register
+#[derive(Debug, PartialEq)]
+pub struct FXRmArgs {
+    pub rd: FRegister,
+    pub rs1: XRegister,
+    pub rm: InstrRoundingMode,
+}
+
+/// Move or classification from a floating-point to an integer register
+#[derive(Debug, PartialEq)]
+pub struct XFArgs {
+    pub rd: XRegister,
+    pub rs1: FRegister,
+}
+
+/// Move from an integer to a floating-point register
+#[derive(Debug, PartialEq)]
+pub struct FXArgs {
+    pub rd: FRegister,
+    pub rs1: XRegister,
+}
+
 /// RISC-V parsed instructions. Along with legal instructions, potentially
 /// illegal instructions are parsed as `Unknown` or `UnknownCompressed`.
 /// These instructions are successfully parsed, but must not be interpreted.
@@ -75,6 +194,7 @@ pub enum Instr {
     Lwu(ITypeArgs),
     Ld(ITypeArgs),
     Fence(ITypeArgs),
+    FenceI,
     Ecall,
     Ebreak,
 
@@ -100,6 +220,213 @@ pub enum Instr {
     Jal(UJTypeArgs),
     Jalr(ITypeArgs),
 
+    // RV64M multiplication and division instructions
+    Mul(RTypeArgs),
+    Mulh(RTypeArgs),
+    Mulhsu(RTypeArgs),
+    Mulhu(RTypeArgs),
+    Div(RTypeArgs),
+    Divu(RTypeArgs),
+    Rem(RTypeArgs),
+    Remu(RTypeArgs),
+    Mulw(RTypeArgs),
+    Divw(RTypeArgs),
+    Divuw(RTypeArgs),
+    Remw(RTypeArgs),
+    Remuw(RTypeArgs),
+
+    // RV64A atomic instructions
+    Lrw(AmoArgs),
+    Scw(AmoArgs),
+    Amoswapw(AmoArgs),
+    Amoaddw(AmoArgs),
+    Amoxorw(AmoArgs),
+    Amoandw(AmoArgs),
+    Amoorw(AmoArgs),
+    Amominw(AmoArgs),
+    Amomaxw(AmoArgs),
+    Amominuw(AmoArgs),
+    Amomaxuw(AmoArgs),
+    Lrd(AmoArgs),
+    Scd(AmoArgs),
+    Amoswapd(AmoArgs),
+    Amoaddd(AmoArgs),
+    Amoxord(AmoArgs),
+    Amoandd(AmoArgs),
+    Amoord(AmoArgs),
+    Amomind(AmoArgs),
+    Amomaxd(AmoArgs),
+    Amominud(AmoArgs),
+    Amomaxud(AmoArgs),
+
+    // RV64F single-precision floating-point instructions
+    Flw(FLoadArgs),
+    Fsw(FStoreArgs),
+    Fadds(FRmArgs),
+    Fsubs(FRmArgs),
+    Fmuls(FRmArgs),
+    Fdivs(FRmArgs),
+    Fsqrts(FR1Args),
+    Fmins(FRTypeArgs),
+    Fmaxs(FRTypeArgs),
+    Fmadds(FR4Args),
+    Fmsubs(FR4Args),
+    Fnmsubs(FR4Args),
+    Fnmadds(FR4Args),
+    Fsgnjs(FRTypeArgs),
+    Fsgnjns(FRTypeArgs),
+    Fsgnjxs(FRTypeArgs),
+    Feqs(FCmpArgs),
+    Flts(FCmpArgs),
+    Fles(FCmpArgs),
+    Fclasss(XFArgs),
+    Fmvxw(XFArgs),
+    Fmvwx(FXArgs),
+    Fcvtws(XFRmArgs),
+    Fcvtwus(XFRmArgs),
+    Fcvtls(XFRmArgs),
+    Fcvtlus(XFRmArgs),
+    Fcvtsw(FXRmArgs),
+    Fcvtswu(FXRmArgs),
+    Fcvtsl(FXRmArgs),
+    Fcvtslu(FXRmArgs),
+
+    // RV64D double-precision floating-point instructions
+    Fld(FLoadArgs),
+    Fsd(FStoreArgs),
+    Faddd(FRmArgs),
+    Fsubd(FRmArgs),
+    Fmuld(FRmArgs),
+    Fdivd(FRmArgs),
+    Fsqrtd(FR1Args),
+    Fmind(FRTypeArgs),
+    Fmaxd(FRTypeArgs),
+    Fmaddd(FR4Args),
+    Fmsubd(FR4Args),
+    Fnmsubd(FR4Args),
+    Fnmaddd(FR4Args),
+    Fsgnjd(FRTypeArgs),
+    Fsgnjnd(FRTypeArgs),
+    Fsgnjxd(FRTypeArgs),
+    Feqd(FCmpArgs),
+    Fltd(FCmpArgs),
+    Fled(FCmpArgs),
+    Fclassd(XFArgs),
+    Fmvxd(XFArgs),
+    Fmvdx(FXArgs),
+    Fcvtwd(XFRmArgs),
+    Fcvtwud(XFRmArgs),
+    Fcvtld(XFRmArgs),
+    Fcvtlud(XFRmArgs),
+    Fcvtdw(FXRmArgs),
+    Fcvtdwu(FXRmArgs),
+    Fcvtdl(FXRmArgs),
+    Fcvtdlu(FXRmArgs),
+    Fcvtsd(FR1Args),
+    Fcvtds(FR1Args),
+
+    // Zicsr instructions
+    Csrrw(CsrArgs),
+    Csrrs(CsrArgs),
+    Csrrc(CsrArgs),
+    Csrrwi(CsriArgs),
+    Csrrsi(CsriArgs),
+    Csrrci(CsriArgs),
+
+    // Privileged instructions
+    Mret,
+    Sret,
+    Wfi,
+    SFenceVma { vaddr: XRegister, asid: XRegister },
+
+    // RV64C compressed instructions, with the operands of the instruction
+    // they expand to
+    CAddi4spn(ITypeArgs),
+    CLw(ITypeArgs),
+    CLd(ITypeArgs),
+    CSw(SBTypeArgs),
+    CSd(SBTypeArgs),
+    CNop,
+    CAddi(ITypeArgs),
+    CAddiw(ITypeArgs),
+    CLi(ITypeArgs),
+    CAddi16sp(ITypeArgs),
+    CLui(UJTypeArgs),
+    CSrli(ITypeArgs),
+    CSrai(ITypeArgs),
+    CAndi(ITypeArgs),
+    CSub(RTypeArgs),
+    CXor(RTypeArgs),
+    COr(RTypeArgs),
+    CAnd(RTypeArgs),
+    CSubw(RTypeArgs),
+    CAddw(RTypeArgs),
+    CJ { imm: i64 },
+    CBeqz { rs1: XRegister, imm: i64 },
+    CBnez { rs1: XRegister, imm: i64 },
+    CSlli(ITypeArgs),
+    CLwsp(ITypeArgs),
+    CLdsp(ITypeArgs),
+    CJr { rs1: XRegister },
+    CMv(RTypeArgs),
+    CEbreak,
+    CJalr { rs1: XRegister },
+    CAdd(RTypeArgs),
+    CSwsp(SBTypeArgs),
+    CSdsp(SBTypeArgs),
+    CFld(FLoadArgs),
+    CFsd(FStoreArgs),
+    CFldsp(FLoadArgs),
+    CFsdsp(FStoreArgs),
+
     Unknown { instr: u32 },
     UnknownCompressed { instr: u16 },
 }
+
+impl Instr {
+    /// Width of the encoded instruction in bytes
+    pub fn width(&self) -> u64 {
+        use Instr::*;
+        match self {
+            CAddi4spn(_)
+            | CLw(_)
+            | CLd(_)
+            | CSw(_)
+            | CSd(_)
+            | CNop
+            | CAddi(_)
+            | CAddiw(_)
+            | CLi(_)
+            | CAddi16sp(_)
+            | CLui(_)
+            | CSrli(_)
+            | CSrai(_)
+            | CAndi(_)
+            | CSub(_)
+            | CXor(_)
+            | COr(_)
+            | CAnd(_)
+            | CSubw(_)
+            | CAddw(_)
+            | CJ { .. }
+            | CBeqz { .. }
+            | CBnez { .. }
+            | CSlli(_)
+            | CLwsp(_)
+            | CLdsp(_)
+            | CJr { .. }
+            | CMv(_)
+            | CEbreak
+            | CJalr { .. }
+            | CAdd(_)
+            | CSwsp(_)
+            | CSdsp(_)
+            | CFld(_)
+            | CFsd(_)
+            | CFldsp(_)
+            | CFsdsp(_)
+            | UnknownCompressed { .. } => 2,
+            _ => 4,
+        }
+    }
+}
@@ -0,0 +1,425 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Proof-generating virtual machine
+//!
+//! The PVM wraps a [MachineState] and implements the part of the SBI which
+//! requires the rollup node to supply data, i.e. inbox messages and reveals.
+//! When the kernel asks for such data the PVM stops evaluating until the
+//! node provides it.
+
+use crate::{
+    machine_state::{
+        address_translation::AccessType,
+        bus::{devices::DEVICES_ADDRESS_SPACE_LENGTH, main_memory, Address, Addressable},
+        mode::Mode,
+        registers::{a0, a1, a2, a3, a6, a7},
+        MachineState, MachineStateLayout,
+    },
+    state_backend::{self as backend, Atom, Cell},
+    traps::Exception,
+};
+use tezos_smart_rollup_constants::riscv::{
+    SBI_CONSOLE_PUTCHAR, SBI_FIRMWARE_TEZOS, SBI_TEZOS_INBOX_NEXT, SBI_TEZOS_REVEAL_PREIMAGE,
+};
+
+/// SBI error code for unsupported extensions or functions
+const SBI_ERR_NOT_SUPPORTED: i64 = -2;
+
+/// SBI error code for invalid parameters
+const SBI_ERR_INVALID_PARAM: i64 = -3;
+
+/// Maximum number of bytes in a reveal request
+pub const MAX_REVEAL_REQUEST_SIZE: u64 = 4096;
+
+/// Status of the PVM
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PvmStatus {
+    #[default]
+    Evaluating = 0,
+    WaitingForInput = 1,
+    WaitingForReveal = 2,
+}
+
+impl From<u8> for PvmStatus {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => Self::WaitingForInput,
+            2 => Self::WaitingForReveal,
+            _ => Self::Evaluating,
+        }
+    }
+}
+
+/// Errors when interacting with the PVM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PvmError {
+    /// The boot sector does not fit into main memory.
+    BootSectorTooLarge,
+
+    /// The PVM was not in the status required by the operation.
+    UnexpectedStatus(PvmStatus),
+}
+
+/// Layout of [Pvm]
+pub type PvmLayout<ML> = (MachineStateLayout<ML>, Atom<u8>);
+
+/// Proof-generating virtual machine
+pub struct Pvm<ML: main_memory::MainMemoryLayout, M: backend::Manager> {
+    pub machine_state: MachineState<ML, M>,
+    status: Cell<u8, M>,
+}
+
+impl<ML: main_memory::MainMemoryLayout, M: backend::Manager> Pvm<ML, M> {
+    /// Bind the PVM to the given allocated space.
+    pub fn bind(space: backend::AllocatedOf<PvmLayout<ML>, M>) -> Self {
+        Self {
+            machine_state: MachineState::bind(space.0),
+            status: Cell::bind(space.1),
+        }
+    }
+
+    /// Reset the PVM and load the boot sector at the start of main memory.
+    /// Execution starts at the first byte of the boot sector in M-mode.
+    pub fn reset(&mut self, boot_sector: &[u8]) -> Result<(), PvmError> {
+        if boot_sector.len() > ML::BYTES {
+            return Err(PvmError::BootSectorTooLarge);
+        }
+
+        self.machine_state
+            .reset(Mode::Machine, DEVICES_ADDRESS_SPACE_LENGTH);
+        self.status.write(PvmStatus::Evaluating as u8);
+
+        for (offset, byte) in boot_sector.iter().enumerate() {
+            self.machine_state
+                .bus
+                .write(DEVICES_ADDRESS_SPACE_LENGTH + offset as Address, *byte)
+                .map_err(|_| PvmError::BootSectorTooLarge)?;
+        }
+
+        Ok(())
+    }
+
+    /// Current status of the PVM
+    pub fn status(&self) -> PvmStatus {
+        PvmStatus::from(self.status.read())
+    }
+
+    /// Execute at most `max_steps` instructions, returning the number of
+    /// instructions that were executed. Nothing is executed unless the PVM is
+    /// evaluating, and execution stops once the PVM waits for the node.
+    pub fn eval_max(&mut self, max_steps: usize) -> usize {
+        let mut steps = 0;
+
+        while steps < max_steps && self.status() == PvmStatus::Evaluating {
+            let result = self.machine_state.step(max_steps - steps);
+            steps += result.steps;
+
+            if result.exception.is_some() {
+                self.handle_sbi_call();
+            }
+        }
+
+        steps
+    }
+
+    /// Provide the inbox message requested by the kernel. The payload is
+    /// truncated to the size of the kernel's buffer.
+    pub fn provide_input(
+        &mut self,
+        level: u32,
+        counter: u32,
+        payload: &[u8],
+    ) -> Result<(), PvmError> {
+        self.expect_status(PvmStatus::WaitingForInput)?;
+
+        let xregs = &self.machine_state.hart.xregisters;
+        let buffer = xregs.read(a0);
+        let max_len = xregs.read(a1);
+        let payload = &payload[..payload.len().min(max_len as usize)];
+
+        self.complete_sbi_call(|pvm| {
+            pvm.write_bytes(buffer, payload)?;
+
+            let xregs = &mut pvm.machine_state.hart.xregisters;
+            xregs.write(a0, level as u64);
+            xregs.write(a1, counter as u64);
+            xregs.write(a2, payload.len() as u64);
+            Ok(())
+        });
+
+        Ok(())
+    }
+
+    /// Read the reveal request of the kernel, e.g. the hash of a preimage.
+    pub fn reveal_request(&mut self) -> Result<Vec<u8>, PvmError> {
+        self.expect_status(PvmStatus::WaitingForReveal)?;
+
+        let xregs = &self.machine_state.hart.xregisters;
+        let request = xregs.read(a0);
+        let request_len = xregs.read(a1);
+
+        // The request was read successfully when the kernel made the call.
+        // Reading it again does not change the state.
+        Ok(self
+            .read_bytes(request, request_len)
+            .expect("Reveal request was validated by the SBI call"))
+    }
+
+    /// Provide the data requested by the kernel. The data is truncated to
+    /// the size of the kernel's buffer.
+    pub fn provide_reveal(&mut self, data: &[u8]) -> Result<(), PvmError> {
+        self.expect_status(PvmStatus::WaitingForReveal)?;
+
+        let xregs = &self.machine_state.hart.xregisters;
+        let buffer = xregs.read(a2);
+        let max_len = xregs.read(a3);
+        let data = &data[..data.len().min(max_len as usize)];
+
+        self.complete_sbi_call(|pvm| {
+            pvm.write_bytes(buffer, data)?;
+            pvm.machine_state
+                .hart
+                .xregisters
+                .write(a0, data.len() as u64);
+            Ok(())
+        });
+
+        Ok(())
+    }
+
+    fn expect_status(&self, expected: PvmStatus) -> Result<(), PvmError> {
+        match self.status() {
+            status if status == expected => Ok(()),
+            status => Err(PvmError::UnexpectedStatus(status)),
+        }
+    }
+
+    /// Handle an environment call from the kernel. The program counter
+    /// already points past the `ecall` instruction.
+    fn handle_sbi_call(&mut self) {
+        let xregs = &mut self.machine_state.hart.xregisters;
+
+        match (xregs.read(a7), xregs.read(a6)) {
+            (SBI_CONSOLE_PUTCHAR, _) => {
+                // The PVM has no console, output is discarded.
+                xregs.write(a0, 0);
+            }
+
+            (SBI_FIRMWARE_TEZOS, SBI_TEZOS_INBOX_NEXT) => {
+                self.status.write(PvmStatus::WaitingForInput as u8);
+            }
+
+            (SBI_FIRMWARE_TEZOS, SBI_TEZOS_REVEAL_PREIMAGE) => {
+                let request = xregs.read(a0);
+                let request_len = xregs.read(a1);
+
+                if request_len > MAX_REVEAL_REQUEST_SIZE {
+                    xregs.write(a0, SBI_ERR_INVALID_PARAM as u64);
+                    return;
+                }
+
+                // Make sure the request can be read before waiting for the
+                // node, which cannot deal with a faulty request.
+                match self.read_bytes(request, request_len) {
+                    Ok(_) => self.status.write(PvmStatus::WaitingForReveal as u8),
+                    Err(exception) => self.trap_sbi_call(exception),
+                }
+            }
+
+            _ => xregs.write(a0, SBI_ERR_NOT_SUPPORTED as u64),
+        }
+    }
+
+    /// Finish the pending SBI call and resume evaluation. A fault while
+    /// writing the results traps as if raised by the `ecall` instruction.
+    fn complete_sbi_call(&mut self, f: impl FnOnce(&mut Self) -> Result<(), Exception>) {
+        self.status.write(PvmStatus::Evaluating as u8);
+
+        if let Err(exception) = f(self) {
+            self.trap_sbi_call(exception);
+        }
+    }
+
+    /// Take a trap for the `ecall` instruction preceding the program
+    /// counter.
+    fn trap_sbi_call(&mut self, exception: Exception) {
+        let ecall_pc = self.machine_state.hart.pc.read().wrapping_sub(4);
+        let handler = self.machine_state.hart.take_trap(exception, ecall_pc);
+        self.machine_state.hart.pc.write(handler);
+    }
+
+    fn read_bytes(&mut self, address: Address, len: u64) -> Result<Vec<u8>, Exception> {
+        (0..len)
+            .map(|offset| {
+                self.machine_state
+                    .read_virtual(address.wrapping_add(offset), AccessType::Load)
+            })
+            .collect()
+    }
+
+    fn write_bytes(&mut self, address: Address, data: &[u8]) -> Result<(), Exception> {
+        data.iter().enumerate().try_for_each(|(offset, byte)| {
+            self.machine_state
+                .write_virtual(address.wrapping_add(offset as u64), *byte)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pvm, PvmError, PvmLayout, PvmStatus, MAX_REVEAL_REQUEST_SIZE};
+    use crate::{
+        backend_test, create_backend,
+        machine_state::{
+            bus::{devices::DEVICES_ADDRESS_SPACE_LENGTH, main_memory::tests::T1K},
+            csregisters::CSRegister,
+            registers::{a0, a1, a2, a3, a6, a7},
+        },
+        state_backend::{tests::ManagerFor, Backend, Layout},
+    };
+    use tezos_smart_rollup_constants::riscv::{
+        SBI_FIRMWARE_TEZOS, SBI_TEZOS_INBOX_NEXT, SBI_TEZOS_REVEAL_PREIMAGE,
+    };
+
+    const BUFFER: u64 = DEVICES_ADDRESS_SPACE_LENGTH + 0x200;
+    const REQUEST: u64 = DEVICES_ADDRESS_SPACE_LENGTH + 0x300;
+
+    // Boot sector which issues the SBI call set up in the argument registers
+    // and then loops
+    //     ecall
+    //     j 0
+    const BOOT_SECTOR: [u8; 8] = [0x73, 0x00, 0x00, 0x00, 0x6f, 0x00, 0x00, 0x00];
+
+    macro_rules! create_pvm {
+        ($backend:ident, $F:ty) => {{
+            let mut pvm: Pvm<T1K, ManagerFor<'_, $F, PvmLayout<T1K>>> =
+                Pvm::bind($backend.allocate(PvmLayout::<T1K>::placed().into_location()));
+            pvm.reset(&BOOT_SECTOR).unwrap();
+            pvm
+        }};
+    }
+
+    backend_test!(test_boot_sector, F, {
+        let mut backend = create_backend!(PvmLayout<T1K>, F);
+        let mut pvm = create_pvm!(backend, F);
+
+        assert_eq!(pvm.status(), PvmStatus::Evaluating);
+        assert_eq!(
+            pvm.machine_state.hart.pc.read(),
+            DEVICES_ADDRESS_SPACE_LENGTH
+        );
+        assert_eq!(pvm.reset(&[0; 2048]), Err(PvmError::BootSectorTooLarge));
+
+        // Unsupported SBI calls return an error and evaluation continues.
+        pvm.machine_state.hart.xregisters.write(a7, 0x1234);
+        assert_eq!(pvm.eval_max(5), 5);
+        assert_eq!(pvm.status(), PvmStatus::Evaluating);
+        assert_eq!(pvm.machine_state.hart.xregisters.read(a0), -2_i64 as u64);
+    });
+
+    backend_test!(test_input, F, {
+        let mut backend = create_backend!(PvmLayout<T1K>, F);
+        let mut pvm = create_pvm!(backend, F);
+
+        let xregs = &mut pvm.machine_state.hart.xregisters;
+        xregs.write(a7, SBI_FIRMWARE_TEZOS);
+        xregs.write(a6, SBI_TEZOS_INBOX_NEXT);
+        xregs.write(a0, BUFFER);
+        xregs.write(a1, 4);
+
+        assert_eq!(
+            pvm.provide_input(0, 0, &[]),
+            Err(PvmError::UnexpectedStatus(PvmStatus::Evaluating))
+        );
+
+        // Evaluation stops at the SBI call until the input is provided.
+        assert_eq!(pvm.eval_max(10), 1);
+        assert_eq!(pvm.status(), PvmStatus::WaitingForInput);
+        assert_eq!(pvm.eval_max(10), 0);
+
+        pvm.provide_input(5, 7, b"message").unwrap();
+        assert_eq!(pvm.status(), PvmStatus::Evaluating);
+
+        let xregs = &pvm.machine_state.hart.xregisters;
+        assert_eq!(xregs.read(a0), 5);
+        assert_eq!(xregs.read(a1), 7);
+        assert_eq!(xregs.read(a2), 4);
+        assert_eq!(pvm.read_bytes(BUFFER, 5), Ok(b"mess\0".to_vec()));
+
+        assert_eq!(pvm.eval_max(10), 10);
+    });
+
+    backend_test!(test_reveal, F, {
+        let mut backend = create_backend!(PvmLayout<T1K>, F);
+        let mut pvm = create_pvm!(backend, F);
+
+        pvm.write_bytes(REQUEST, b"hash").unwrap();
+        let xregs = &mut pvm.machine_state.hart.xregisters;
+        xregs.write(a7, SBI_FIRMWARE_TEZOS);
+        xregs.write(a6, SBI_TEZOS_REVEAL_PREIMAGE);
+        xregs.write(a0, REQUEST);
+        xregs.write(a1, 4);
+        xregs.write(a2, BUFFER);
+        xregs.write(a3, 16);
+
+        assert_eq!(pvm.eval_max(10), 1);
+        assert_eq!(pvm.status(), PvmStatus::WaitingForReveal);
+        assert_eq!(pvm.reveal_request(), Ok(b"hash".to_vec()));
+        assert_eq!(
+            pvm.provide_input(0, 0, &[]),
+            Err(PvmError::UnexpectedStatus(PvmStatus::WaitingForReveal))
+        );
+
+        pvm.provide_reveal(b"preimage").unwrap();
+        assert_eq!(pvm.status(), PvmStatus::Evaluating);
+        assert_eq!(pvm.machine_state.hart.xregisters.read(a0), 8);
+        assert_eq!(pvm.read_bytes(BUFFER, 8), Ok(b"preimage".to_vec()));
+        assert_eq!(
+            pvm.reveal_request(),
+            Err(PvmError::UnexpectedStatus(PvmStatus::Evaluating))
+        );
+
+        // Oversized requests are rejected without waiting for the node.
+        pvm.reset(&BOOT_SECTOR).unwrap();
+        let xregs = &mut pvm.machine_state.hart.xregisters;
+        xregs.write(a7, SBI_FIRMWARE_TEZOS);
+        xregs.write(a6, SBI_TEZOS_REVEAL_PREIMAGE);
+        xregs.write(a1, MAX_REVEAL_REQUEST_SIZE + 1);
+        assert_eq!(pvm.eval_max(10), 10);
+        assert_eq!(pvm.machine_state.hart.xregisters.read(a0), -3_i64 as u64);
+    });
+
+    backend_test!(test_faulty_buffer, F, {
+        let mut backend = create_backend!(PvmLayout<T1K>, F);
+        let mut pvm = create_pvm!(backend, F);
+
+        // The trap handler is the looping instruction of the boot sector.
+        pvm.machine_state
+            .hart
+            .csregisters
+            .write(CSRegister::mtvec, DEVICES_ADDRESS_SPACE_LENGTH + 4);
+
+        let xregs = &mut pvm.machine_state.hart.xregisters;
+        xregs.write(a7, SBI_FIRMWARE_TEZOS);
+        xregs.write(a6, SBI_TEZOS_INBOX_NEXT);
+        xregs.write(a0, 0);
+        xregs.write(a1, 16);
+
+        assert_eq!(pvm.eval_max(10), 1);
+        pvm.provide_input(1, 2, b"message").unwrap();
+
+        // Writing the message into the devices address space faults.
+        assert_eq!(pvm.status(), PvmStatus::Evaluating);
+        assert_eq!(
+            pvm.machine_state.hart.pc.read(),
+            DEVICES_ADDRESS_SPACE_LENGTH + 4
+        );
+        assert_eq!(
+            pvm.machine_state.hart.csregisters.read(CSRegister::mepc),
+            DEVICES_ADDRESS_SPACE_LENGTH
+        );
+    });
+}
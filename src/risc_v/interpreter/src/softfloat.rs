@@ -0,0 +1,1028 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Software implementation of IEEE-754 binary32 and binary64 arithmetic
+//!
+//! The host FPU is never used: exception flags, rounding modes and NaN
+//! payloads must be identical on every host for the execution to be
+//! deterministic. Values are manipulated as raw bit patterns, binary32 values
+//! occupy the lower 32 bits of a `u64`.
+//!
+//! Behaviour which IEEE-754 leaves to the implementation follows the RISC-V
+//! conventions (Chapter 11 - Unprivileged spec): NaN results are always the
+//! canonical NaN and tininess is detected after rounding.
+
+/// Rounding modes (c.f. Table 11.1 - Unprivileged spec)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// Round to nearest, ties to even
+    RNE = 0b000,
+    /// Round towards zero
+    RTZ = 0b001,
+    /// Round down (towards negative infinity)
+    RDN = 0b010,
+    /// Round up (towards positive infinity)
+    RUP = 0b011,
+    /// Round to nearest, ties to max magnitude
+    RMM = 0b100,
+}
+
+impl TryFrom<u64> for RoundingMode {
+    type Error = ();
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            0b000 => Ok(RoundingMode::RNE),
+            0b001 => Ok(RoundingMode::RTZ),
+            0b010 => Ok(RoundingMode::RDN),
+            0b011 => Ok(RoundingMode::RUP),
+            0b100 => Ok(RoundingMode::RMM),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Accrued exception flags, laid out as in `fflags` (c.f. Table 11.2)
+pub type ExceptionFlags = u64;
+
+/// Inexact
+pub const FLAG_NX: ExceptionFlags = 1 << 0;
+/// Underflow
+pub const FLAG_UF: ExceptionFlags = 1 << 1;
+/// Overflow
+pub const FLAG_OF: ExceptionFlags = 1 << 2;
+/// Divide by zero
+pub const FLAG_DZ: ExceptionFlags = 1 << 3;
+/// Invalid operation
+pub const FLAG_NV: ExceptionFlags = 1 << 4;
+
+/// Binary interchange format
+pub trait Format {
+    /// Number of bits of the biased exponent
+    const EXP_BITS: u32;
+
+    /// Number of bits of the trailing significand
+    const FRAC_BITS: u32;
+
+    /// The canonical NaN, a positive quiet NaN with an empty payload
+    const CANONICAL_NAN: u64;
+
+    /// Total number of bits of an encoded value
+    const WIDTH: u32 = 1 + Self::EXP_BITS + Self::FRAC_BITS;
+
+    const SIGN_BIT: u64 = 1 << (Self::EXP_BITS + Self::FRAC_BITS);
+
+    const FRAC_MASK: u64 = (1 << Self::FRAC_BITS) - 1;
+
+    /// Biased exponent of infinities and NaNs
+    const EXP_MAX: i32 = (1 << Self::EXP_BITS) - 1;
+
+    const BIAS: i32 = (1 << (Self::EXP_BITS - 1)) - 1;
+
+    /// Exponent of the smallest normal number
+    const EMIN: i32 = 1 - Self::BIAS;
+}
+
+/// Single-precision format
+pub enum F32 {}
+
+impl Format for F32 {
+    const EXP_BITS: u32 = 8;
+    const FRAC_BITS: u32 = 23;
+    const CANONICAL_NAN: u64 = 0x7fc0_0000;
+}
+
+/// Double-precision format
+pub enum F64 {}
+
+impl Format for F64 {
+    const EXP_BITS: u32 = 11;
+    const FRAC_BITS: u32 = 52;
+    const CANONICAL_NAN: u64 = 0x7ff8_0000_0000_0000;
+}
+
+/// Decoded floating-point value, without its sign
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    NaN {
+        signaling: bool,
+    },
+    Infinity,
+    Zero,
+    /// Non-zero finite value `sig * 2^exp`
+    Finite {
+        sig: u128,
+        exp: i32,
+    },
+}
+
+fn unpack<F: Format>(bits: u64) -> (bool, Class) {
+    let sign = bits & F::SIGN_BIT != 0;
+    let biased_exp = ((bits >> F::FRAC_BITS) & ((1 << F::EXP_BITS) - 1)) as i32;
+    let frac = bits & F::FRAC_MASK;
+
+    let class = match (biased_exp, frac) {
+        (0, 0) => Class::Zero,
+        (0, _) => Class::Finite {
+            sig: frac as u128,
+            exp: F::EMIN - F::FRAC_BITS as i32,
+        },
+        (e, 0) if e == F::EXP_MAX => Class::Infinity,
+        (e, _) if e == F::EXP_MAX => Class::NaN {
+            signaling: frac >> (F::FRAC_BITS - 1) == 0,
+        },
+        (e, _) => Class::Finite {
+            sig: (frac | 1 << F::FRAC_BITS) as u128,
+            exp: e - F::BIAS - F::FRAC_BITS as i32,
+        },
+    };
+
+    (sign, class)
+}
+
+fn pack<F: Format>(sign: bool, biased_exp: i32, frac: u64) -> u64 {
+    (sign as u64) << (F::WIDTH - 1) | (biased_exp as u64) << F::FRAC_BITS | frac
+}
+
+fn zero<F: Format>(sign: bool) -> u64 {
+    pack::<F>(sign, 0, 0)
+}
+
+fn infinity<F: Format>(sign: bool) -> u64 {
+    pack::<F>(sign, F::EXP_MAX, 0)
+}
+
+/// Result of an invalid operation
+fn invalid<F: Format>(flags: &mut ExceptionFlags) -> u64 {
+    *flags |= FLAG_NV;
+    F::CANONICAL_NAN
+}
+
+/// Result of an operation with a NaN operand: signaling NaNs are invalid.
+fn propagate_nan<F: Format>(classes: &[Class], flags: &mut ExceptionFlags) -> u64 {
+    if classes
+        .iter()
+        .any(|class| *class == Class::NaN { signaling: true })
+    {
+        *flags |= FLAG_NV;
+    }
+    F::CANONICAL_NAN
+}
+
+fn is_nan(class: &Class) -> bool {
+    matches!(class, Class::NaN { .. })
+}
+
+/// Sign of an exact zero sum of operands with opposite signs (Section 6.3 - IEEE-754)
+fn zero_sum_sign(rm: RoundingMode) -> bool {
+    rm == RoundingMode::RDN
+}
+
+/// Shift `sig` to the right, OR-ing the shifted-out bits into the least
+/// significant bit of the result.
+fn shift_right_jam(sig: u128, shift: u32) -> u128 {
+    match shift {
+        0 => sig,
+        1..=127 => (sig >> shift) | (sig & ((1 << shift) - 1) != 0) as u128,
+        _ => (sig != 0) as u128,
+    }
+}
+
+/// Shift `sig * 2^exp` so that the most significant bit of `sig` is at `pos`.
+fn normalise(sig: u128, exp: i32, pos: u32) -> (u128, i32) {
+    let msb = 127 - sig.leading_zeros();
+    if msb <= pos {
+        (sig << (pos - msb), exp - (pos - msb) as i32)
+    } else {
+        (sig >> (msb - pos), exp + (msb - pos) as i32)
+    }
+}
+
+/// Round `sig` to a multiple of `2^shift`, returning the quotient and whether
+/// the rounding was inexact.
+fn round_shift(sig: u128, shift: i32, sign: bool, rm: RoundingMode) -> (u128, bool) {
+    if shift <= 0 {
+        return (sig << -shift, false);
+    }
+
+    let (quotient, round, sticky) = match shift {
+        1..=127 => (
+            sig >> shift,
+            (sig >> (shift - 1)) & 1 != 0,
+            sig & ((1 << (shift - 1)) - 1) != 0,
+        ),
+        128 => (0, sig >> 127 != 0, sig & (u128::MAX >> 1) != 0),
+        _ => (0, false, sig != 0),
+    };
+
+    let inexact = round || sticky;
+    let increment = match rm {
+        RoundingMode::RNE => round && (sticky || quotient & 1 != 0),
+        RoundingMode::RTZ => false,
+        RoundingMode::RDN => inexact && sign,
+        RoundingMode::RUP => inexact && !sign,
+        RoundingMode::RMM => round,
+    };
+
+    (quotient + increment as u128, inexact)
+}
+
+/// Round the non-zero value `(-1)^sign * sig * 2^exp` to the format `F`.
+fn round_pack<F: Format>(
+    sign: bool,
+    sig: u128,
+    exp: i32,
+    rm: RoundingMode,
+    flags: &mut ExceptionFlags,
+) -> u64 {
+    debug_assert_ne!(sig, 0);
+
+    let frac_bits = F::FRAC_BITS as i32;
+    let unbiased_exp = exp + (127 - sig.leading_zeros()) as i32;
+
+    // The result is tiny if it is below the smallest normal number after
+    // rounding with an unbounded exponent range.
+    let tiny = unbiased_exp < F::EMIN - 1
+        || (unbiased_exp == F::EMIN - 1 && {
+            let shift = unbiased_exp - frac_bits - exp;
+            let (rounded, _) = round_shift(sig, shift, sign, rm);
+            rounded >> (frac_bits + 1) == 0
+        });
+
+    // Exponent of the least significant bit of the result
+    let lsb_exp = unbiased_exp.max(F::EMIN) - frac_bits;
+    let (sig, inexact) = round_shift(sig, lsb_exp - exp, sign, rm);
+
+    // Rounding may have carried into a new bit
+    let (sig, lsb_exp) = if sig >> (frac_bits + 1) != 0 {
+        (sig >> 1, lsb_exp + 1)
+    } else {
+        (sig, lsb_exp)
+    };
+
+    if inexact {
+        *flags |= FLAG_NX;
+        if tiny {
+            *flags |= FLAG_UF;
+        }
+    }
+
+    if sig == 0 {
+        return zero::<F>(sign);
+    }
+
+    let biased_exp = if sig >> frac_bits == 0 {
+        0
+    } else {
+        lsb_exp + frac_bits + F::BIAS
+    };
+
+    if biased_exp >= F::EXP_MAX {
+        *flags |= FLAG_OF | FLAG_NX;
+        let to_infinity = match rm {
+            RoundingMode::RNE | RoundingMode::RMM => true,
+            RoundingMode::RTZ => false,
+            RoundingMode::RDN => sign,
+            RoundingMode::RUP => !sign,
+        };
+        return if to_infinity {
+            infinity::<F>(sign)
+        } else {
+            pack::<F>(sign, F::EXP_MAX - 1, F::FRAC_MASK)
+        };
+    }
+
+    pack::<F>(sign, biased_exp, sig as u64 & F::FRAC_MASK)
+}
+
+/// Exact sum of two non-zero finite values, whose significands have at most
+/// 125 bits. The result is returned as `(sign, sig, exp)`.
+fn add_finite(
+    (sign_a, sig_a, exp_a): (bool, u128, i32),
+    (sign_b, sig_b, exp_b): (bool, u128, i32),
+) -> (bool, u128, i32) {
+    // Both significands are aligned on bit 125, leaving room for the carry.
+    // Since the original significands have at most 106 bits, the lowest 20
+    // bits are clear: shifting the smaller operand to the right by up to 20
+    // bits is exact, and larger shifts only lose bits far below the rounding
+    // position, which are accounted for in the sticky bit.
+    let a = normalise(sig_a, exp_a, 125);
+    let b = normalise(sig_b, exp_b, 125);
+
+    let ((sign_a, (sig_a, exp)), (sign_b, (sig_b, exp_b))) = if a.1 >= b.1 {
+        ((sign_a, a), (sign_b, b))
+    } else {
+        ((sign_b, b), (sign_a, a))
+    };
+    let sig_b = shift_right_jam(sig_b, (exp - exp_b) as u32);
+
+    if sign_a == sign_b {
+        (sign_a, sig_a + sig_b, exp)
+    } else if sig_a >= sig_b {
+        (sign_a, sig_a - sig_b, exp)
+    } else {
+        (sign_b, sig_b - sig_a, exp)
+    }
+}
+
+/// Sum `a + b`
+pub fn add<F: Format>(a: u64, b: u64, rm: RoundingMode, flags: &mut ExceptionFlags) -> u64 {
+    let (sign_a, class_a) = unpack::<F>(a);
+    let (sign_b, class_b) = unpack::<F>(b);
+
+    match (class_a, class_b) {
+        (Class::NaN { .. }, _) | (_, Class::NaN { .. }) => {
+            propagate_nan::<F>(&[class_a, class_b], flags)
+        }
+        (Class::Infinity, Class::Infinity) if sign_a != sign_b => invalid::<F>(flags),
+        (Class::Infinity, _) => infinity::<F>(sign_a),
+        (_, Class::Infinity) => infinity::<F>(sign_b),
+        (Class::Zero, Class::Zero) if sign_a == sign_b => zero::<F>(sign_a),
+        (Class::Zero, Class::Zero) => zero::<F>(zero_sum_sign(rm)),
+        (Class::Zero, _) => b,
+        (_, Class::Zero) => a,
+        (
+            Class::Finite {
+                sig: sig_a,
+                exp: exp_a,
+            },
+            Class::Finite {
+                sig: sig_b,
+                exp: exp_b,
+            },
+        ) => match add_finite((sign_a, sig_a, exp_a), (sign_b, sig_b, exp_b)) {
+            (_, 0, _) => zero::<F>(zero_sum_sign(rm)),
+            (sign, sig, exp) => round_pack::<F>(sign, sig, exp, rm, flags),
+        },
+    }
+}
+
+/// Difference `a - b`
+pub fn sub<F: Format>(a: u64, b: u64, rm: RoundingMode, flags: &mut ExceptionFlags) -> u64 {
+    add::<F>(a, b ^ F::SIGN_BIT, rm, flags)
+}
+
+/// Product `a * b`
+pub fn mul<F: Format>(a: u64, b: u64, rm: RoundingMode, flags: &mut ExceptionFlags) -> u64 {
+    let (sign_a, class_a) = unpack::<F>(a);
+    let (sign_b, class_b) = unpack::<F>(b);
+    let sign = sign_a != sign_b;
+
+    match (class_a, class_b) {
+        (Class::NaN { .. }, _) | (_, Class::NaN { .. }) => {
+            propagate_nan::<F>(&[class_a, class_b], flags)
+        }
+        (Class::Infinity, Class::Zero) | (Class::Zero, Class::Infinity) => invalid::<F>(flags),
+        (Class::Infinity, _) | (_, Class::Infinity) => infinity::<F>(sign),
+        (Class::Zero, _) | (_, Class::Zero) => zero::<F>(sign),
+        (
+            Class::Finite {
+                sig: sig_a,
+                exp: exp_a,
+            },
+            Class::Finite {
+                sig: sig_b,
+                exp: exp_b,
+            },
+        ) => round_pack::<F>(sign, sig_a * sig_b, exp_a + exp_b, rm, flags),
+    }
+}
+
+/// Quotient `a / b`
+pub fn div<F: Format>(a: u64, b: u64, rm: RoundingMode, flags: &mut ExceptionFlags) -> u64 {
+    let (sign_a, class_a) = unpack::<F>(a);
+    let (sign_b, class_b) = unpack::<F>(b);
+    let sign = sign_a != sign_b;
+
+    match (class_a, class_b) {
+        (Class::NaN { .. }, _) | (_, Class::NaN { .. }) => {
+            propagate_nan::<F>(&[class_a, class_b], flags)
+        }
+        (Class::Infinity, Class::Infinity) | (Class::Zero, Class::Zero) => invalid::<F>(flags),
+        (Class::Infinity, _) => infinity::<F>(sign),
+        (_, Class::Infinity) | (Class::Zero, _) => zero::<F>(sign),
+        (_, Class::Zero) => {
+            *flags |= FLAG_DZ;
+            infinity::<F>(sign)
+        }
+        (
+            Class::Finite {
+                sig: sig_a,
+                exp: exp_a,
+            },
+            Class::Finite {
+                sig: sig_b,
+                exp: exp_b,
+            },
+        ) => {
+            // The quotient of the normalised significands has at least 64
+            // bits, the remainder only matters for the sticky bit.
+            let (sig_a, exp_a) = normalise(sig_a, exp_a, 63);
+            let (sig_b, exp_b) = normalise(sig_b, exp_b, 63);
+            let dividend = sig_a << 64;
+            let quotient = dividend / sig_b;
+            let sticky = dividend % sig_b != 0;
+            round_pack::<F>(
+                sign,
+                quotient | sticky as u128,
+                exp_a - 64 - exp_b,
+                rm,
+                flags,
+            )
+        }
+    }
+}
+
+/// Integer square root, returning whether the result is inexact
+fn isqrt(value: u128) -> (u128, bool) {
+    let mut remainder = value;
+    let mut root = 0;
+    let mut bit = 1 << 126;
+
+    while bit > value {
+        bit >>= 2;
+    }
+
+    while bit != 0 {
+        if remainder >= root + bit {
+            remainder -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+
+    (root, remainder != 0)
+}
+
+/// Square root of `a`
+pub fn sqrt<F: Format>(a: u64, rm: RoundingMode, flags: &mut ExceptionFlags) -> u64 {
+    match unpack::<F>(a) {
+        (_, class @ Class::NaN { .. }) => propagate_nan::<F>(&[class], flags),
+        (sign, Class::Zero) => zero::<F>(sign),
+        (true, _) => invalid::<F>(flags),
+        (false, Class::Infinity) => infinity::<F>(false),
+        (false, Class::Finite { sig, exp }) => {
+            // The exponent must be even to be halved, the root of the
+            // normalised significand has at least 62 bits.
+            let (sig, exp) = normalise(sig, exp, 125);
+            let (sig, exp) = if exp % 2 != 0 {
+                (sig << 1, exp - 1)
+            } else {
+                (sig, exp)
+            };
+            let (root, inexact) = isqrt(sig);
+            round_pack::<F>(false, root | inexact as u128, exp / 2, rm, flags)
+        }
+    }
+}
+
+/// Fused multiply-add `a * b + c`, with a single rounding
+pub fn mul_add<F: Format>(
+    a: u64,
+    b: u64,
+    c: u64,
+    rm: RoundingMode,
+    flags: &mut ExceptionFlags,
+) -> u64 {
+    let (sign_a, class_a) = unpack::<F>(a);
+    let (sign_b, class_b) = unpack::<F>(b);
+    let (sign_c, class_c) = unpack::<F>(c);
+    let sign_prod = sign_a != sign_b;
+
+    // Multiplying infinity by zero is invalid, even when the addend is a
+    // quiet NaN (Section 11.6 - Unprivileged spec)
+    if matches!(
+        (class_a, class_b),
+        (Class::Infinity, Class::Zero) | (Class::Zero, Class::Infinity)
+    ) {
+        propagate_nan::<F>(&[class_c], flags);
+        return invalid::<F>(flags);
+    }
+
+    if is_nan(&class_a) || is_nan(&class_b) || is_nan(&class_c) {
+        return propagate_nan::<F>(&[class_a, class_b, class_c], flags);
+    }
+
+    match (class_a, class_b, class_c) {
+        (Class::Infinity, _, Class::Infinity) | (_, Class::Infinity, Class::Infinity)
+            if sign_prod != sign_c =>
+        {
+            invalid::<F>(flags)
+        }
+        (Class::Infinity, _, _) | (_, Class::Infinity, _) => infinity::<F>(sign_prod),
+        (_, _, Class::Infinity) => infinity::<F>(sign_c),
+        (Class::Zero, _, Class::Zero) | (_, Class::Zero, Class::Zero) => {
+            zero::<F>(if sign_prod == sign_c {
+                sign_c
+            } else {
+                zero_sum_sign(rm)
+            })
+        }
+        (Class::Zero, _, _) | (_, Class::Zero, _) => c,
+        (
+            Class::Finite {
+                sig: sig_a,
+                exp: exp_a,
+            },
+            Class::Finite {
+                sig: sig_b,
+                exp: exp_b,
+            },
+            Class::Zero,
+        ) => round_pack::<F>(sign_prod, sig_a * sig_b, exp_a + exp_b, rm, flags),
+        (
+            Class::Finite {
+                sig: sig_a,
+                exp: exp_a,
+            },
+            Class::Finite {
+                sig: sig_b,
+                exp: exp_b,
+            },
+            Class::Finite {
+                sig: sig_c,
+                exp: exp_c,
+            },
+        ) => match add_finite(
+            (sign_prod, sig_a * sig_b, exp_a + exp_b),
+            (sign_c, sig_c, exp_c),
+        ) {
+            (_, 0, _) => zero::<F>(zero_sum_sign(rm)),
+            (sign, sig, exp) => round_pack::<F>(sign, sig, exp, rm, flags),
+        },
+        (Class::NaN { .. }, _, _) | (_, Class::NaN { .. }, _) | (_, _, Class::NaN { .. }) => {
+            unreachable!("NaN operands are handled above")
+        }
+    }
+}
+
+/// Convert `a` from the format `S` to the format `D`.
+pub fn convert<S: Format, D: Format>(a: u64, rm: RoundingMode, flags: &mut ExceptionFlags) -> u64 {
+    match unpack::<S>(a) {
+        (_, class @ Class::NaN { .. }) => propagate_nan::<D>(&[class], flags),
+        (sign, Class::Infinity) => infinity::<D>(sign),
+        (sign, Class::Zero) => zero::<D>(sign),
+        (sign, Class::Finite { sig, exp }) => round_pack::<D>(sign, sig, exp, rm, flags),
+    }
+}
+
+/// Convert `a` to an integer of `width` bits, signed or not.
+///
+/// Out-of-range values and NaNs are invalid, the result then saturates
+/// (c.f. Table 11.4 - Unprivileged spec).
+pub fn to_int<F: Format>(
+    a: u64,
+    signed: bool,
+    width: u32,
+    rm: RoundingMode,
+    flags: &mut ExceptionFlags,
+) -> i128 {
+    let (min, max): (i128, i128) = if signed {
+        (-(1 << (width - 1)), (1 << (width - 1)) - 1)
+    } else {
+        (0, (1 << width) - 1)
+    };
+
+    let (sign, class) = unpack::<F>(a);
+    let (magnitude, inexact) = match class {
+        Class::NaN { .. } => {
+            *flags |= FLAG_NV;
+            return max;
+        }
+        Class::Infinity => (u128::MAX, false),
+        Class::Zero => (0, false),
+        // Such values are beyond the range of any supported integer
+        Class::Finite { exp, .. } if exp > 64 => (u128::MAX, false),
+        Class::Finite { sig, exp } => round_shift(sig, -exp, sign, rm),
+    };
+
+    let magnitude = i128::try_from(magnitude).unwrap_or(i128::MAX);
+    let value = if sign { -magnitude } else { magnitude };
+
+    if value < min || value > max {
+        *flags |= FLAG_NV;
+        return if sign { min } else { max };
+    }
+
+    if inexact {
+        *flags |= FLAG_NX;
+    }
+    value
+}
+
+/// Convert the integer `value` to the format `F`.
+pub fn from_int<F: Format>(value: i128, rm: RoundingMode, flags: &mut ExceptionFlags) -> u64 {
+    match value {
+        0 => zero::<F>(false),
+        _ => round_pack::<F>(value < 0, value.unsigned_abs(), 0, rm, flags),
+    }
+}
+
+/// Key ordering non-NaN values, with both zeros being equal
+fn order_key<F: Format>(a: u64) -> i64 {
+    let magnitude = (a & !F::SIGN_BIT) as i64;
+    if a & F::SIGN_BIT != 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+/// Quiet equality comparison, only signaling NaNs are invalid
+pub fn eq<F: Format>(a: u64, b: u64, flags: &mut ExceptionFlags) -> bool {
+    let (_, class_a) = unpack::<F>(a);
+    let (_, class_b) = unpack::<F>(b);
+
+    if is_nan(&class_a) || is_nan(&class_b) {
+        propagate_nan::<F>(&[class_a, class_b], flags);
+        return false;
+    }
+
+    order_key::<F>(a) == order_key::<F>(b)
+}
+
+/// Signaling less-than comparison, any NaN is invalid
+pub fn lt<F: Format>(a: u64, b: u64, flags: &mut ExceptionFlags) -> bool {
+    if is_nan(&unpack::<F>(a).1) || is_nan(&unpack::<F>(b).1) {
+        *flags |= FLAG_NV;
+        return false;
+    }
+
+    order_key::<F>(a) < order_key::<F>(b)
+}
+
+/// Signaling less-than-or-equal comparison, any NaN is invalid
+pub fn le<F: Format>(a: u64, b: u64, flags: &mut ExceptionFlags) -> bool {
+    if is_nan(&unpack::<F>(a).1) || is_nan(&unpack::<F>(b).1) {
+        *flags |= FLAG_NV;
+        return false;
+    }
+
+    order_key::<F>(a) <= order_key::<F>(b)
+}
+
+/// `minimumNumber` or `maximumNumber` of IEEE-754 (2019)
+fn min_max<F: Format>(a: u64, b: u64, max: bool, flags: &mut ExceptionFlags) -> u64 {
+    let (sign_a, class_a) = unpack::<F>(a);
+    let (_, class_b) = unpack::<F>(b);
+
+    match (is_nan(&class_a), is_nan(&class_b)) {
+        (true, true) => propagate_nan::<F>(&[class_a, class_b], flags),
+        (true, false) => {
+            propagate_nan::<F>(&[class_a], flags);
+            b
+        }
+        (false, true) => {
+            propagate_nan::<F>(&[class_b], flags);
+            a
+        }
+        (false, false) => {
+            let (key_a, key_b) = (order_key::<F>(a), order_key::<F>(b));
+            // -0 is considered less than +0
+            let a_first = key_a < key_b || (key_a == key_b && sign_a);
+            if a_first != max {
+                a
+            } else {
+                b
+            }
+        }
+    }
+}
+
+/// Minimum of `a` and `b`, NaNs are only returned if both values are NaNs.
+pub fn min<F: Format>(a: u64, b: u64, flags: &mut ExceptionFlags) -> u64 {
+    min_max::<F>(a, b, false, flags)
+}
+
+/// Maximum of `a` and `b`, NaNs are only returned if both values are NaNs.
+pub fn max<F: Format>(a: u64, b: u64, flags: &mut ExceptionFlags) -> u64 {
+    min_max::<F>(a, b, true, flags)
+}
+
+/// Class mask of `a`, as returned by `FCLASS` (c.f. Table 11.5)
+pub fn classify<F: Format>(a: u64) -> u64 {
+    let bit = match unpack::<F>(a) {
+        (true, Class::Infinity) => 0,
+        (true, Class::Finite { sig, .. }) if sig >> F::FRAC_BITS != 0 => 1,
+        (true, Class::Finite { .. }) => 2,
+        (true, Class::Zero) => 3,
+        (false, Class::Zero) => 4,
+        (false, Class::Finite { sig, .. }) if sig >> F::FRAC_BITS == 0 => 5,
+        (false, Class::Finite { .. }) => 6,
+        (false, Class::Infinity) => 7,
+        (_, Class::NaN { signaling: true }) => 8,
+        (_, Class::NaN { signaling: false }) => 9,
+    };
+    1 << bit
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::{prop_assert_eq, proptest};
+
+    const ALL_RM: [RoundingMode; 5] = [
+        RoundingMode::RNE,
+        RoundingMode::RTZ,
+        RoundingMode::RDN,
+        RoundingMode::RUP,
+        RoundingMode::RMM,
+    ];
+
+    // The host implements round-to-nearest-even exactly, it is used as a
+    // reference for non-NaN results.
+
+    fn check_f64(result: u64, expected: f64) -> bool {
+        if expected.is_nan() {
+            result == F64::CANONICAL_NAN
+        } else {
+            result == expected.to_bits()
+        }
+    }
+
+    fn check_f32(result: u64, expected: f32) -> bool {
+        if expected.is_nan() {
+            result == F32::CANONICAL_NAN
+        } else {
+            result == expected.to_bits() as u64
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_f64_arith_rne(a: u64, b: u64, c: u64) {
+            let (fa, fb, fc) = (f64::from_bits(a), f64::from_bits(b), f64::from_bits(c));
+            let rm = RoundingMode::RNE;
+            let mut flags = 0;
+
+            prop_assert_eq!(check_f64(add::<F64>(a, b, rm, &mut flags), fa + fb), true);
+            prop_assert_eq!(check_f64(sub::<F64>(a, b, rm, &mut flags), fa - fb), true);
+            prop_assert_eq!(check_f64(mul::<F64>(a, b, rm, &mut flags), fa * fb), true);
+            prop_assert_eq!(check_f64(div::<F64>(a, b, rm, &mut flags), fa / fb), true);
+            prop_assert_eq!(check_f64(sqrt::<F64>(a, rm, &mut flags), fa.sqrt()), true);
+            prop_assert_eq!(
+                check_f64(mul_add::<F64>(a, b, c, rm, &mut flags), fa.mul_add(fb, fc)),
+                true
+            );
+        }
+
+        #[test]
+        fn test_f32_arith_rne(a: u32, b: u32, c: u32) {
+            let (fa, fb, fc) = (f32::from_bits(a), f32::from_bits(b), f32::from_bits(c));
+            let (a, b, c) = (a as u64, b as u64, c as u64);
+            let rm = RoundingMode::RNE;
+            let mut flags = 0;
+
+            prop_assert_eq!(check_f32(add::<F32>(a, b, rm, &mut flags), fa + fb), true);
+            prop_assert_eq!(check_f32(sub::<F32>(a, b, rm, &mut flags), fa - fb), true);
+            prop_assert_eq!(check_f32(mul::<F32>(a, b, rm, &mut flags), fa * fb), true);
+            prop_assert_eq!(check_f32(div::<F32>(a, b, rm, &mut flags), fa / fb), true);
+            prop_assert_eq!(check_f32(sqrt::<F32>(a, rm, &mut flags), fa.sqrt()), true);
+            prop_assert_eq!(
+                check_f32(mul_add::<F32>(a, b, c, rm, &mut flags), fa.mul_add(fb, fc)),
+                true
+            );
+        }
+
+        #[test]
+        fn test_conversions_rne(a: u64, b: u32, i: i64) {
+            let rm = RoundingMode::RNE;
+            let mut flags = 0;
+
+            let fa = f64::from_bits(a);
+            prop_assert_eq!(check_f32(convert::<F64, F32>(a, rm, &mut flags), fa as f32), true);
+            let fb = f32::from_bits(b);
+            prop_assert_eq!(check_f64(convert::<F32, F64>(b as u64, rm, &mut flags), fb as f64), true);
+
+            prop_assert_eq!(check_f64(from_int::<F64>(i as i128, rm, &mut flags), i as f64), true);
+            prop_assert_eq!(check_f32(from_int::<F32>(i as i128, rm, &mut flags), i as f32), true);
+
+            // The host conversion truncates and saturates
+            let truncated = to_int::<F64>(a, true, 64, RoundingMode::RTZ, &mut flags);
+            prop_assert_eq!(truncated as i64, if fa.is_nan() { i64::MAX } else { fa as i64 });
+            let truncated = to_int::<F64>(a, false, 32, RoundingMode::RTZ, &mut flags);
+            prop_assert_eq!(truncated as u32, if fa.is_nan() { u32::MAX } else { fa as u32 });
+        }
+
+        #[test]
+        fn test_compare(a: u64, b: u64) {
+            let (fa, fb) = (f64::from_bits(a), f64::from_bits(b));
+            let mut flags = 0;
+
+            prop_assert_eq!(eq::<F64>(a, b, &mut flags), fa == fb);
+            prop_assert_eq!(lt::<F64>(a, b, &mut flags), fa < fb);
+            prop_assert_eq!(le::<F64>(a, b, &mut flags), fa <= fb);
+        }
+    }
+
+    #[test]
+    fn test_rounding_modes() {
+        let one = 1.0f64.to_bits();
+        let three = 3.0f64.to_bits();
+        let third = |rm| {
+            let mut flags = 0;
+            let result = div::<F64>(one, three, rm, &mut flags);
+            assert_eq!(flags, FLAG_NX);
+            result
+        };
+
+        let nearest = (1.0f64 / 3.0).to_bits();
+        assert_eq!(third(RoundingMode::RNE), nearest);
+        assert_eq!(third(RoundingMode::RMM), nearest);
+        assert_eq!(third(RoundingMode::RTZ), nearest);
+        assert_eq!(third(RoundingMode::RDN), nearest);
+        assert_eq!(third(RoundingMode::RUP), nearest + 1);
+
+        // Ties: 1 + 2^-53 is halfway between 1 and its successor
+        let half_ulp = 2.0f64.powi(-53).to_bits();
+        for (rm, expected) in [
+            (RoundingMode::RNE, one),
+            (RoundingMode::RTZ, one),
+            (RoundingMode::RDN, one),
+            (RoundingMode::RUP, one + 1),
+            (RoundingMode::RMM, one + 1),
+        ] {
+            let mut flags = 0;
+            assert_eq!(add::<F64>(one, half_ulp, rm, &mut flags), expected);
+            assert_eq!(flags, FLAG_NX);
+        }
+
+        // Exact zero sums are negative only when rounding down
+        for rm in ALL_RM {
+            let mut flags = 0;
+            let expected = if rm == RoundingMode::RDN { 1 << 63 } else { 0 };
+            assert_eq!(sub::<F64>(one, one, rm, &mut flags), expected);
+            assert_eq!(flags, 0);
+        }
+    }
+
+    #[test]
+    fn test_exception_flags() {
+        let max = f32::MAX.to_bits() as u64;
+        let min_subnormal = 1;
+        let one = 1.0f32.to_bits() as u64;
+        let half = 0.5f32.to_bits() as u64;
+        let inf = f32::INFINITY.to_bits() as u64;
+        let snan = 0x7f80_0001;
+        let qnan = F32::CANONICAL_NAN;
+
+        let mut flags = 0;
+        assert_eq!(mul::<F32>(max, max, RoundingMode::RNE, &mut flags), inf);
+        assert_eq!(flags, FLAG_OF | FLAG_NX);
+
+        // Overflows towards zero saturate to the largest finite value
+        let mut flags = 0;
+        assert_eq!(mul::<F32>(max, max, RoundingMode::RTZ, &mut flags), max);
+        assert_eq!(flags, FLAG_OF | FLAG_NX);
+
+        let mut flags = 0;
+        assert_eq!(
+            mul::<F32>(min_subnormal, half, RoundingMode::RNE, &mut flags),
+            0
+        );
+        assert_eq!(flags, FLAG_UF | FLAG_NX);
+
+        // Exact subnormal results do not underflow
+        let mut flags = 0;
+        assert_eq!(
+            mul::<F32>(2, half, RoundingMode::RNE, &mut flags),
+            min_subnormal
+        );
+        assert_eq!(flags, 0);
+
+        let mut flags = 0;
+        assert_eq!(div::<F32>(one, 0, RoundingMode::RNE, &mut flags), inf);
+        assert_eq!(flags, FLAG_DZ);
+
+        let mut flags = 0;
+        assert_eq!(
+            add::<F32>(inf, inf | F32::SIGN_BIT, RoundingMode::RNE, &mut flags),
+            qnan
+        );
+        assert_eq!(flags, FLAG_NV);
+
+        // Quiet NaNs only raise the invalid flag for signaling comparisons
+        let mut flags = 0;
+        assert_eq!(add::<F32>(qnan, one, RoundingMode::RNE, &mut flags), qnan);
+        assert!(!eq::<F32>(qnan, one, &mut flags));
+        assert_eq!(flags, 0);
+        assert!(!lt::<F32>(qnan, one, &mut flags));
+        assert_eq!(flags, FLAG_NV);
+
+        let mut flags = 0;
+        assert_eq!(add::<F32>(snan, one, RoundingMode::RNE, &mut flags), qnan);
+        assert_eq!(flags, FLAG_NV);
+
+        // Infinity times zero is invalid even with a quiet NaN addend
+        let mut flags = 0;
+        assert_eq!(
+            mul_add::<F32>(inf, 0, qnan, RoundingMode::RNE, &mut flags),
+            qnan
+        );
+        assert_eq!(flags, FLAG_NV);
+    }
+
+    #[test]
+    fn test_tininess_after_rounding() {
+        // The largest subnormal rounds up to the smallest normal number
+        // without underflowing, since tininess is detected after rounding.
+        let largest_subnormal = F64::FRAC_MASK;
+        let just_above = 0x3ff0_0000_0000_0001; // 1 + 2^-52
+        let mut flags = 0;
+        assert_eq!(
+            mul::<F64>(largest_subnormal, just_above, RoundingMode::RUP, &mut flags),
+            1 << 52
+        );
+        assert_eq!(flags, FLAG_NX);
+
+        let mut flags = 0;
+        assert_eq!(
+            mul::<F64>(largest_subnormal, just_above, RoundingMode::RTZ, &mut flags),
+            largest_subnormal
+        );
+        assert_eq!(flags, FLAG_NX | FLAG_UF);
+    }
+
+    #[test]
+    fn test_to_int() {
+        let mut flags = 0;
+        let minus_half = (-0.5f64).to_bits();
+        let big = 1e20f64.to_bits();
+        let minus_one = (-1.0f64).to_bits();
+
+        assert_eq!(
+            to_int::<F64>(minus_half, false, 32, RoundingMode::RTZ, &mut flags),
+            0
+        );
+        assert_eq!(flags, FLAG_NX);
+
+        let mut flags = 0;
+        assert_eq!(
+            to_int::<F64>(minus_half, true, 32, RoundingMode::RDN, &mut flags),
+            -1
+        );
+        assert_eq!(
+            to_int::<F64>(minus_half, true, 32, RoundingMode::RMM, &mut flags),
+            -1
+        );
+        assert_eq!(
+            to_int::<F64>(minus_half, true, 32, RoundingMode::RNE, &mut flags),
+            0
+        );
+        assert_eq!(flags, FLAG_NX);
+
+        let mut flags = 0;
+        assert_eq!(
+            to_int::<F64>(minus_one, false, 64, RoundingMode::RNE, &mut flags),
+            0
+        );
+        assert_eq!(flags, FLAG_NV);
+
+        let mut flags = 0;
+        assert_eq!(
+            to_int::<F64>(big, true, 64, RoundingMode::RNE, &mut flags),
+            i64::MAX as i128
+        );
+        assert_eq!(flags, FLAG_NV);
+
+        let mut flags = 0;
+        assert_eq!(
+            to_int::<F64>(F64::CANONICAL_NAN, false, 32, RoundingMode::RNE, &mut flags),
+            u32::MAX as i128
+        );
+        assert_eq!(flags, FLAG_NV);
+    }
+
+    #[test]
+    fn test_min_max_classify() {
+        let mut flags = 0;
+        let pos_zero = 0;
+        let neg_zero = F64::SIGN_BIT;
+        let one = 1.0f64.to_bits();
+        let snan = 0x7ff0_0000_0000_0001;
+
+        assert_eq!(min::<F64>(pos_zero, neg_zero, &mut flags), neg_zero);
+        assert_eq!(max::<F64>(neg_zero, pos_zero, &mut flags), pos_zero);
+        assert_eq!(min::<F64>(F64::CANONICAL_NAN, one, &mut flags), one);
+        assert_eq!(flags, 0);
+
+        assert_eq!(max::<F64>(snan, one, &mut flags), one);
+        assert_eq!(flags, FLAG_NV);
+        assert_eq!(max::<F64>(snan, snan, &mut flags), F64::CANONICAL_NAN);
+
+        assert_eq!(classify::<F64>(f64::NEG_INFINITY.to_bits()), 1 << 0);
+        assert_eq!(classify::<F64>((-1.0f64).to_bits()), 1 << 1);
+        assert_eq!(classify::<F64>(neg_zero | 1), 1 << 2);
+        assert_eq!(classify::<F64>(neg_zero), 1 << 3);
+        assert_eq!(classify::<F64>(pos_zero), 1 << 4);
+        assert_eq!(classify::<F64>(1), 1 << 5);
+        assert_eq!(classify::<F64>(one), 1 << 6);
+        assert_eq!(classify::<F64>(f64::INFINITY.to_bits()), 1 << 7);
+        assert_eq!(classify::<F64>(snan), 1 << 8);
+        assert_eq!(classify::<F64>(F64::CANONICAL_NAN), 1 << 9);
+        assert_eq!(classify::<F32>(F32::CANONICAL_NAN), 1 << 9);
+    }
+}
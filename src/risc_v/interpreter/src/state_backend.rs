@@ -56,6 +56,7 @@
 //! [Locations]: Location
 
 pub mod memory_backend;
+pub mod merkle_backend;
 
 mod layout;
 pub use layout::*;
@@ -445,6 +446,7 @@ pub mod tests {
                 }
 
                 inner::<$crate::state_backend::memory_backend::tests::InMemoryBackendFactory>();
+                inner::<$crate::state_backend::merkle_backend::tests::MerkleBackendFactory>();
             }
         };
     }
@@ -1,4 +1,4 @@
-// SPDX-FileCopyrightText: 2023 TriliTech <contact@trili.tech>
+// SPDX-FileCopyrightText: 2023-2024 TriliTech <contact@trili.tech>
 //
 // SPDX-License-Identifier: MIT
 
@@ -206,6 +206,59 @@ where
     }
 }
 
+impl<A, B, C, D, E, F> Layout for (A, B, C, D, E, F)
+where
+    A: Layout,
+    B: Layout,
+    C: Layout,
+    D: Layout,
+    E: Layout,
+    F: Layout,
+{
+    type Placed = (
+        A::Placed,
+        B::Placed,
+        C::Placed,
+        D::Placed,
+        E::Placed,
+        F::Placed,
+    );
+
+    fn place_with(alloc: &mut Choreographer) -> Self::Placed {
+        (
+            A::place_with(alloc),
+            B::place_with(alloc),
+            C::place_with(alloc),
+            D::place_with(alloc),
+            E::place_with(alloc),
+            F::place_with(alloc),
+        )
+    }
+
+    type Allocated<Back: super::Manager> = (
+        A::Allocated<Back>,
+        B::Allocated<Back>,
+        C::Allocated<Back>,
+        D::Allocated<Back>,
+        E::Allocated<Back>,
+        F::Allocated<Back>,
+    );
+
+    fn allocate<Back: super::Manager>(
+        backend: &mut Back,
+        placed: Self::Placed,
+    ) -> Self::Allocated<Back> {
+        (
+            A::allocate(backend, placed.0),
+            B::allocate(backend, placed.1),
+            C::allocate(backend, placed.2),
+            D::allocate(backend, placed.3),
+            E::allocate(backend, placed.4),
+            F::allocate(backend, placed.5),
+        )
+    }
+}
+
 impl<T, const LEN: usize> Layout for [T; LEN]
 where
     T: Layout,
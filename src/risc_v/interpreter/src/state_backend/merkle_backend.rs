@@ -0,0 +1,830 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Merkleized state backend
+//!
+//! The backing storage is divided into leaves: one per region of the layout,
+//! where regions larger than [MAX_LEAF_SIZE] (e.g. main memory) are split into
+//! pages of that size. A binary Merkle tree over these leaves yields the root
+//! hash of the state. Padding between regions is not part of any leaf.
+//!
+//! Regions allocated by a [MerkleManager] report the leaves they access. This
+//! keeps the tree up to date incrementally and allows [proving](MerkleBackend::prove)
+//! a state transition with a [Proof] that only holds the leaves accessed by
+//! the transition.
+
+use crate::state_backend::{
+    self as backend,
+    memory_backend::{InMemoryBackend, SliceManager},
+    Elem, Layout,
+};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    mem,
+    ops::Range,
+    rc::Rc,
+    slice,
+};
+
+/// Maximum number of bytes in a leaf
+pub const MAX_LEAF_SIZE: usize = 4096;
+
+/// Number of bytes in a [Hash]
+pub const HASH_SIZE: usize = 32;
+
+/// BLAKE2b hash of a node in the Merkle tree
+pub type Hash = [u8; HASH_SIZE];
+
+/// Hash of the padding leaves which complete the tree
+const PADDING_HASH: Hash = [0; HASH_SIZE];
+
+fn hash_bytes(data: &[u8]) -> Hash {
+    let mut hash = PADDING_HASH;
+    hash.copy_from_slice(
+        blake2b_simd::Params::new()
+            .hash_length(HASH_SIZE)
+            .hash(data)
+            .as_bytes(),
+    );
+    hash
+}
+
+fn hash_children(left: &Hash, right: &Hash) -> Hash {
+    let mut hash = PADDING_HASH;
+    hash.copy_from_slice(
+        blake2b_simd::Params::new()
+            .hash_length(HASH_SIZE)
+            .to_state()
+            .update(left)
+            .update(right)
+            .finalize()
+            .as_bytes(),
+    );
+    hash
+}
+
+/// Contiguous bytes of the backing storage hashed as a whole
+#[derive(Debug, Clone, Copy)]
+struct Leaf {
+    offset: usize,
+    len: usize,
+}
+
+impl Leaf {
+    fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.len
+    }
+}
+
+/// Accesses to the leaves through regions
+#[derive(Default)]
+struct AccessLog {
+    /// Leaves written since the tree was last updated
+    dirty: BTreeSet<usize>,
+
+    /// Contents of the accessed leaves prior to their first access, if the
+    /// accesses are being recorded
+    recorded: Option<BTreeMap<usize, Vec<u8>>>,
+}
+
+/// Leaves of the backing storage and the accesses to them
+#[derive(Default)]
+struct Tracker {
+    leaves: Rc<[Leaf]>,
+    log: RefCell<AccessLog>,
+}
+
+impl Tracker {
+    /// Whether accesses are being recorded
+    fn recording(&self) -> bool {
+        self.log.borrow().recorded.is_some()
+    }
+
+    /// Indices of the leaves overlapping `len` bytes at `offset`
+    fn leaves_in(&self, offset: usize, len: usize) -> Range<usize> {
+        let start = self
+            .leaves
+            .partition_point(|leaf| leaf.offset + leaf.len <= offset);
+        let end = self
+            .leaves
+            .partition_point(|leaf| leaf.offset < offset + len);
+        start..end.max(start)
+    }
+
+    /// Note an access to `len` bytes at `offset` within the region at
+    /// `region_offset`, whose bytes prior to the access are `region`.
+    fn touch(&self, region_offset: usize, region: &[u8], offset: usize, len: usize, write: bool) {
+        let mut log = self.log.borrow_mut();
+        let AccessLog { dirty, recorded } = &mut *log;
+
+        for index in self.leaves_in(region_offset + offset, len) {
+            if write {
+                dirty.insert(index);
+            }
+
+            if let Some(recorded) = recorded {
+                recorded.entry(index).or_insert_with(|| {
+                    let start = self.leaves[index].offset - region_offset;
+                    region[start..start + self.leaves[index].len].to_vec()
+                });
+            }
+        }
+    }
+}
+
+/// Region which reports accesses to its [Tracker]
+pub struct MerkleRegion<'backend, E, const LEN: usize> {
+    data: &'backend mut [E; LEN],
+    offset: usize,
+    tracker: &'backend Tracker,
+}
+
+impl<'backend, E: Elem, const LEN: usize> MerkleRegion<'backend, E, LEN> {
+    fn touch(&self, index: usize, count: usize, write: bool) {
+        // SAFETY: Elements are plain values which may be viewed as bytes.
+        let bytes = unsafe {
+            slice::from_raw_parts(self.data.as_ptr().cast::<u8>(), mem::size_of::<[E; LEN]>())
+        };
+        let size = mem::size_of::<E>();
+        self.tracker
+            .touch(self.offset, bytes, index * size, count * size, write);
+    }
+}
+
+impl<'backend, E: Elem, const LEN: usize> backend::Region for MerkleRegion<'backend, E, LEN> {
+    type Elem = E;
+
+    const LEN: usize = LEN;
+
+    fn read(&self, index: usize) -> E {
+        self.touch(index, 1, false);
+        <[E; LEN] as backend::Region>::read(self.data, index)
+    }
+
+    fn read_all(&self) -> Vec<E> {
+        self.touch(0, LEN, false);
+        <[E; LEN] as backend::Region>::read_all(self.data)
+    }
+
+    fn read_some(&self, offset: usize, buffer: &mut [E]) {
+        self.touch(offset, buffer.len(), false);
+        <[E; LEN] as backend::Region>::read_some(self.data, offset, buffer)
+    }
+
+    fn write(&mut self, index: usize, value: E) {
+        self.touch(index, 1, true);
+        <[E; LEN] as backend::Region>::write(self.data, index, value)
+    }
+
+    fn write_all(&mut self, value: &[E]) {
+        self.touch(0, LEN, true);
+        <[E; LEN] as backend::Region>::write_all(self.data, value)
+    }
+
+    fn write_some(&mut self, index: usize, buffer: &[E]) {
+        self.touch(index, buffer.len(), true);
+        <[E; LEN] as backend::Region>::write_some(self.data, index, buffer)
+    }
+
+    fn replace(&mut self, index: usize, value: E) -> E {
+        self.touch(index, 1, true);
+        <[E; LEN] as backend::Region>::replace(self.data, index, value)
+    }
+}
+
+/// Dynamic region which reports accesses to its [Tracker]
+pub struct MerkleDynRegion<'backend, const LEN: usize> {
+    data: &'backend mut [u8; LEN],
+    offset: usize,
+    tracker: &'backend Tracker,
+}
+
+impl<'backend, const LEN: usize> backend::DynRegion for MerkleDynRegion<'backend, LEN> {
+    const LEN: usize = LEN;
+
+    fn read<E: Elem>(&self, address: usize) -> E {
+        self.tracker
+            .touch(self.offset, self.data, address, mem::size_of::<E>(), false);
+        <[u8; LEN] as backend::DynRegion>::read(self.data, address)
+    }
+
+    fn write<E: Elem>(&mut self, address: usize, value: E) {
+        // Writes leaving the bytes unchanged, e.g. resetting zeroed main
+        // memory, don't dirty any leaf. Recorded accesses must be replayed
+        // faithfully however.
+        let mut stored = value;
+        stored.to_stored_in_place();
+        // SAFETY: Elements are plain values which may be viewed as bytes.
+        let bytes = unsafe {
+            slice::from_raw_parts((&stored as *const E).cast::<u8>(), mem::size_of::<E>())
+        };
+        if self.data.get(address..address + bytes.len()) == Some(bytes) && !self.tracker.recording()
+        {
+            return;
+        }
+
+        self.tracker
+            .touch(self.offset, self.data, address, mem::size_of::<E>(), true);
+        <[u8; LEN] as backend::DynRegion>::write(self.data, address, value)
+    }
+}
+
+/// Manager for Merkleized backing storage
+pub struct MerkleManager<'backend> {
+    storage: SliceManager<'backend>,
+    tracker: &'backend Tracker,
+
+    /// Offsets and sizes of the allocated regions
+    regions: Vec<(usize, usize)>,
+}
+
+impl<'backend> MerkleManager<'backend> {
+    fn new(storage: &'backend mut [u8], tracker: &'backend Tracker) -> Self {
+        Self {
+            storage: SliceManager::new(storage),
+            tracker,
+            regions: Vec::new(),
+        }
+    }
+}
+
+impl<'backend> backend::Manager for MerkleManager<'backend> {
+    type Region<E: Elem, const LEN: usize> = MerkleRegion<'backend, E, LEN>;
+
+    fn allocate_region<E: Elem, const LEN: usize>(
+        &mut self,
+        loc: backend::Location<[E; LEN]>,
+    ) -> Self::Region<E, LEN> {
+        let offset = loc.offset();
+        self.regions.push((offset, loc.size()));
+
+        MerkleRegion {
+            data: self.storage.allocate_region(loc),
+            offset,
+            tracker: self.tracker,
+        }
+    }
+
+    type DynRegion<const LEN: usize> = MerkleDynRegion<'backend, LEN>;
+
+    fn allocate_dyn_region<const LEN: usize>(
+        &mut self,
+        loc: backend::Location<[u8; LEN]>,
+    ) -> Self::DynRegion<LEN> {
+        let offset = loc.offset();
+        self.regions.push((offset, loc.size()));
+
+        MerkleDynRegion {
+            data: self.storage.allocate_dyn_region(loc),
+            offset,
+            tracker: self.tracker,
+        }
+    }
+}
+
+/// Proof of a state transition
+///
+/// It contains the leaves accessed by the transition, as they were before it,
+/// and the hashes of all subtrees without accessed leaves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proof {
+    leaves: BTreeMap<usize, Vec<u8>>,
+    hashes: BTreeMap<usize, Hash>,
+}
+
+/// Reasons for rejecting a [Proof]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofError {
+    /// The proof does not describe a tree of the expected shape.
+    Malformed,
+
+    /// The proof does not match the hash of the initial state.
+    InitialHashMismatch,
+
+    /// The transition accessed leaves which are not part of the proof.
+    MissingLeaves,
+
+    /// The transition does not lead to the final state.
+    FinalHashMismatch,
+}
+
+/// Merkleized state backend
+pub struct MerkleBackend<L> {
+    storage: InMemoryBackend<L>,
+    tracker: Tracker,
+
+    /// Hashes of the perfect binary tree over the leaves: the root is at
+    /// index 1, the children of node `n` are at `2n` and `2n + 1`
+    nodes: Vec<Hash>,
+
+    /// Leaves hashed since the backend was allocated. All other leaves are
+    /// still zeroed, unless they are dirty.
+    written: BTreeSet<usize>,
+}
+
+impl<L: Layout> MerkleBackend<L> {
+    /// Allocate a backend for state types of layout `L`.
+    pub fn new() -> (Self, L::Placed) {
+        let (mut storage, placed) = InMemoryBackend::<L>::new();
+        let leaves = Self::trace_leaves(&mut storage);
+
+        let width = leaves.len().next_power_of_two();
+        let mut nodes = vec![PADDING_HASH; 2 * width];
+
+        // The storage starts out zeroed, many leaves therefore share hashes.
+        let mut zero_hashes = BTreeMap::new();
+        for (index, leaf) in leaves.iter().enumerate() {
+            nodes[width + index] = *zero_hashes
+                .entry(leaf.len)
+                .or_insert_with(|| hash_bytes(&vec![0; leaf.len]));
+        }
+
+        for node in (1..width).rev() {
+            nodes[node] = hash_children(&nodes[2 * node], &nodes[2 * node + 1]);
+        }
+
+        let backend = Self {
+            storage,
+            tracker: Tracker {
+                leaves: leaves.into(),
+                log: RefCell::default(),
+            },
+            nodes,
+            written: BTreeSet::new(),
+        };
+
+        (backend, placed)
+    }
+
+    /// Determine the leaves from the regions of the layout.
+    fn trace_leaves(storage: &mut InMemoryBackend<L>) -> Vec<Leaf> {
+        let tracker = Tracker::default();
+        let mut manager = MerkleManager::new(storage.borrow_mut(), &tracker);
+        L::allocate(&mut manager, L::placed().into_location());
+
+        let mut regions = manager.regions;
+        regions.sort();
+
+        let mut leaves = Vec::new();
+        for (offset, size) in regions {
+            for start in (0..size).step_by(MAX_LEAF_SIZE) {
+                leaves.push(Leaf {
+                    offset: offset + start,
+                    len: MAX_LEAF_SIZE.min(size - start),
+                });
+            }
+        }
+
+        leaves
+    }
+
+    /// Number of leaves in the perfect binary tree, including padding
+    fn width(&self) -> usize {
+        self.nodes.len() / 2
+    }
+
+    /// Indices of the leaves below `node`
+    fn leaves_below(&self, node: usize) -> Range<usize> {
+        let depth = node.ilog2();
+        let span = self.width() >> depth;
+        let start = (node - (1 << depth)) * span;
+        start..start + span
+    }
+
+    fn leaf_bytes(&self, index: usize) -> &[u8] {
+        &self.storage.borrow()[self.tracker.leaves[index].range()]
+    }
+
+    /// Root hash of the state
+    pub fn root_hash(&mut self) -> Hash {
+        let width = self.width();
+        let dirty = mem::take(&mut self.tracker.log.get_mut().dirty);
+
+        // All leaves are at the same depth, hence the nodes to update can be
+        // processed one level at a time.
+        let mut level = BTreeSet::new();
+        for &index in dirty.iter() {
+            self.nodes[width + index] = hash_bytes(self.leaf_bytes(index));
+            level.insert((width + index) / 2);
+        }
+        self.written.extend(dirty);
+
+        while !level.is_empty() {
+            let mut parents = BTreeSet::new();
+
+            for node in level.into_iter().filter(|node| *node > 0) {
+                self.nodes[node] = hash_children(&self.nodes[2 * node], &self.nodes[2 * node + 1]);
+                parents.insert(node / 2);
+            }
+
+            level = parents;
+        }
+
+        self.nodes[1]
+    }
+
+    /// Run `f` against the state, recording the leaves it accesses.
+    fn record<T>(
+        &mut self,
+        f: impl FnOnce(backend::AllocatedOf<L, MerkleManager<'_>>) -> T,
+    ) -> (T, BTreeMap<usize, Vec<u8>>) {
+        self.tracker.log.get_mut().recorded = Some(BTreeMap::new());
+        let result = f(backend::Backend::allocate(
+            self,
+            L::placed().into_location(),
+        ));
+        let recorded = self.tracker.log.get_mut().recorded.take();
+        (result, recorded.unwrap_or_default())
+    }
+
+    /// Run the state transition `f` and produce a proof of it.
+    pub fn prove<T>(
+        &mut self,
+        f: impl FnOnce(backend::AllocatedOf<L, MerkleManager<'_>>) -> T,
+    ) -> (T, Proof) {
+        // Subtrees not accessed by the transition are part of the proof.
+        // Their hashes must reflect any earlier modifications.
+        self.root_hash();
+
+        let (result, leaves) = self.record(f);
+        let mut hashes = BTreeMap::new();
+        self.collect_hashes(1, &leaves, &mut hashes);
+
+        (result, Proof { leaves, hashes })
+    }
+
+    /// Collect the hashes of the subtrees below `node` without accessed
+    /// leaves.
+    fn collect_hashes(
+        &self,
+        node: usize,
+        leaves: &BTreeMap<usize, Vec<u8>>,
+        hashes: &mut BTreeMap<usize, Hash>,
+    ) {
+        if leaves.range(self.leaves_below(node)).next().is_none() {
+            hashes.insert(node, self.nodes[node]);
+        } else if node < self.width() {
+            self.collect_hashes(2 * node, leaves, hashes);
+            self.collect_hashes(2 * node + 1, leaves, hashes);
+        }
+    }
+
+    /// Root hash of the tree described by `proof`, taking the contents of its
+    /// leaves from the storage
+    fn proof_hash(&self, proof: &Proof, node: usize) -> Result<Hash, ProofError> {
+        if let Some(hash) = proof.hashes.get(&node) {
+            // Leaves below a given hash would not be authenticated.
+            return match proof.leaves.range(self.leaves_below(node)).next() {
+                None => Ok(*hash),
+                Some(_) => Err(ProofError::Malformed),
+            };
+        }
+
+        if node < self.width() {
+            let left = self.proof_hash(proof, 2 * node)?;
+            let right = self.proof_hash(proof, 2 * node + 1)?;
+            Ok(hash_children(&left, &right))
+        } else if proof.leaves.contains_key(&(node - self.width())) {
+            Ok(hash_bytes(self.leaf_bytes(node - self.width())))
+        } else {
+            Err(ProofError::Malformed)
+        }
+    }
+
+    /// Verify that the state transition `f` leads from the state with hash
+    /// `initial_hash` to the state with hash `final_hash`, using only the
+    /// data in `proof`.
+    pub fn verify<T>(
+        proof: &Proof,
+        initial_hash: &Hash,
+        final_hash: &Hash,
+        f: impl FnOnce(backend::AllocatedOf<L, MerkleManager<'_>>) -> T,
+    ) -> Result<T, ProofError> {
+        let (mut backend, _) = Self::new();
+
+        for (&index, data) in proof.leaves.iter() {
+            match backend.tracker.leaves.get(index) {
+                Some(leaf) if leaf.len == data.len() => {
+                    backend.storage.borrow_mut()[leaf.range()].copy_from_slice(data)
+                }
+                _ => return Err(ProofError::Malformed),
+            }
+        }
+
+        if backend.proof_hash(proof, 1)? != *initial_hash {
+            return Err(ProofError::InitialHashMismatch);
+        }
+
+        let (result, accessed) = backend.record(f);
+
+        if !accessed
+            .keys()
+            .all(|index| proof.leaves.contains_key(index))
+        {
+            return Err(ProofError::MissingLeaves);
+        }
+
+        if backend.proof_hash(proof, 1)? != *final_hash {
+            return Err(ProofError::FinalHashMismatch);
+        }
+
+        Ok(result)
+    }
+}
+
+impl<L: Layout> Clone for MerkleBackend<L> {
+    /// The copy starts from zeroed storage, which the allocator hands out
+    /// lazily, and only copies the leaves that were written. Untouched pages
+    /// of large layouts, e.g. main memory, are therefore never copied.
+    fn clone(&self) -> Self {
+        let (mut storage, _) = InMemoryBackend::<L>::new();
+        let log = self.tracker.log.borrow();
+
+        for &index in self.written.union(&log.dirty) {
+            let range = self.tracker.leaves[index].range();
+            storage.borrow_mut()[range.clone()].copy_from_slice(&self.storage.borrow()[range]);
+        }
+
+        Self {
+            storage,
+            tracker: Tracker {
+                leaves: self.tracker.leaves.clone(),
+                log: RefCell::new(AccessLog {
+                    dirty: log.dirty.clone(),
+                    recorded: None,
+                }),
+            },
+            nodes: self.nodes.clone(),
+            written: self.written.clone(),
+        }
+    }
+}
+
+impl<L: Layout> backend::BackendManagement for MerkleBackend<L> {
+    type Manager<'backend> = MerkleManager<'backend>;
+}
+
+impl<L: Layout> backend::Backend for MerkleBackend<L> {
+    type Layout = L;
+
+    fn allocate(
+        &mut self,
+        placed: backend::PlacedOf<Self::Layout>,
+    ) -> backend::AllocatedOf<Self::Layout, Self::Manager<'_>> {
+        let mut manager = MerkleManager::new(self.storage.borrow_mut(), &self.tracker);
+        L::allocate(&mut manager, placed)
+    }
+
+    fn read(&self, index: usize, buffer: &mut [u8]) {
+        self.storage.read(index, buffer)
+    }
+
+    fn write(&mut self, index: usize, buffer: &[u8]) {
+        self.storage.write(index, buffer);
+
+        let leaves = self.tracker.leaves_in(index, buffer.len());
+        self.tracker.log.get_mut().dirty.extend(leaves);
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{
+        machine_state::{
+            bus::{devices::DEVICES_ADDRESS_SPACE_LENGTH, main_memory::tests::T32K, Addressable},
+            mode::Mode,
+            MachineState, MachineStateLayout,
+        },
+        state_backend::{
+            memory_backend::InMemoryBackend, AllocatedOf, Array, Atom, Backend, Cell, Manager,
+            Region,
+        },
+    };
+
+    type L = (Atom<u64>, Array<u8, 10000>);
+
+    struct T<M: Manager> {
+        first: Cell<u64, M>,
+        second: M::Region<u8, 10000>,
+    }
+
+    impl<M: Manager> T<M> {
+        fn bind(space: AllocatedOf<L, M>) -> Self {
+            T {
+                first: space.0,
+                second: space.1,
+            }
+        }
+    }
+
+    #[test]
+    fn test_leaves_and_root_hash() {
+        let (mut backend, _) = MerkleBackend::<L>::new();
+
+        // One leaf for the atom, three pages for the array
+        let lens: Vec<_> = backend.tracker.leaves.iter().map(|leaf| leaf.len).collect();
+        assert_eq!(lens, [8, 4096, 4096, 1808]);
+
+        // The root hash agrees with a tree built from scratch.
+        let check = |backend: &mut MerkleBackend<L>| {
+            let leaves: Vec<_> = (0..4).map(|i| hash_bytes(backend.leaf_bytes(i))).collect();
+            let expected = hash_children(
+                &hash_children(&leaves[0], &leaves[1]),
+                &hash_children(&leaves[2], &leaves[3]),
+            );
+            assert_eq!(backend.root_hash(), expected);
+        };
+
+        check(&mut backend);
+        let initial = backend.root_hash();
+
+        let mut state = T::bind(backend.allocate(L::placed().into_location()));
+        state.first.write(42);
+        state.second.write(5000, 1);
+        check(&mut backend);
+        assert_ne!(backend.root_hash(), initial);
+
+        backend.write(8, &[0; 10000]);
+        backend.write(0, &[0; 8]);
+        assert_eq!(backend.root_hash(), initial);
+    }
+
+    #[test]
+    fn test_clone() {
+        let (mut backend, _) = MerkleBackend::<L>::new();
+        let mut state = T::bind(backend.allocate(L::placed().into_location()));
+        state.second.write(100, 1);
+        let hashed = backend.root_hash();
+
+        // Leaves written after the last hash are copied as well.
+        let mut state = T::bind(backend.allocate(L::placed().into_location()));
+        state.first.write(42);
+        state.second.write(9000, 2);
+
+        let mut copy = backend.clone();
+        assert_eq!(copy.storage.borrow(), backend.storage.borrow());
+        assert_eq!(copy.written, BTreeSet::from([1]));
+        assert_eq!(copy.root_hash(), backend.root_hash());
+        assert_ne!(copy.root_hash(), hashed);
+
+        // Copies evolve independently.
+        let mut state = T::bind(copy.allocate(L::placed().into_location()));
+        state.second.write(5000, 3);
+        assert_ne!(copy.root_hash(), backend.root_hash());
+
+        let state = T::bind(backend.allocate(L::placed().into_location()));
+        assert_eq!(state.second.read(5000), 0);
+        assert_eq!(state.second.read(9000), 2);
+    }
+
+    #[test]
+    fn test_proof() {
+        let (mut backend, _) = MerkleBackend::<L>::new();
+        let mut state = T::bind(backend.allocate(L::placed().into_location()));
+        state.second.write_all(&[7; 10000]);
+        let initial = backend.root_hash();
+
+        let transition = |space: AllocatedOf<L, MerkleManager<'_>>| {
+            let mut state = T::bind(space);
+            let value = state.second.read(9000) as u64;
+            state.first.write(value);
+            value
+        };
+
+        let (result, proof) = backend.prove(transition);
+        assert_eq!(result, 7);
+        let after = backend.root_hash();
+
+        // Only the atom and the last page were accessed.
+        assert_eq!(proof.leaves.keys().copied().collect::<Vec<_>>(), [0, 3]);
+
+        assert_eq!(
+            MerkleBackend::<L>::verify(&proof, &initial, &after, transition),
+            Ok(7)
+        );
+        assert_eq!(
+            MerkleBackend::<L>::verify(&proof, &after, &after, transition),
+            Err(ProofError::InitialHashMismatch)
+        );
+        assert_eq!(
+            MerkleBackend::<L>::verify(&proof, &initial, &initial, transition),
+            Err(ProofError::FinalHashMismatch)
+        );
+
+        // Transitions accessing other leaves can't be verified.
+        let other = |space: AllocatedOf<L, MerkleManager<'_>>| {
+            let mut state = T::bind(space);
+            state.first.write(7);
+            state.second.read(0) as u64
+        };
+        assert_eq!(
+            MerkleBackend::<L>::verify(&proof, &initial, &after, other),
+            Err(ProofError::MissingLeaves)
+        );
+
+        // Tampering with the leaves breaks the initial hash.
+        let mut tampered = proof.clone();
+        tampered.leaves.get_mut(&3).unwrap()[0] = 8;
+        assert_eq!(
+            MerkleBackend::<L>::verify(&tampered, &initial, &after, transition),
+            Err(ProofError::InitialHashMismatch)
+        );
+
+        // Hashes must not cover leaves that are part of the proof.
+        let mut tampered = proof.clone();
+        tampered.hashes.insert(1, initial);
+        assert_eq!(
+            MerkleBackend::<L>::verify(&tampered, &initial, &after, transition),
+            Err(ProofError::Malformed)
+        );
+
+        let mut tampered = proof;
+        tampered.leaves.remove(&0);
+        assert_eq!(
+            MerkleBackend::<L>::verify(&tampered, &initial, &after, transition),
+            Err(ProofError::Malformed)
+        );
+    }
+
+    #[test]
+    fn test_step_proof() {
+        type ML = MachineStateLayout<T32K>;
+
+        // A load from the last page of main memory
+        //     lui t0, 0x40008
+        //     ld t1, -8(t0)
+        let program: [u32; 2] = [0x400082b7, 0xff82b303];
+
+        let (mut backend, _) = MerkleBackend::<ML>::new();
+        let mut state =
+            MachineState::<T32K, _>::bind(backend.allocate(ML::placed().into_location()));
+        state.reset(Mode::Machine, DEVICES_ADDRESS_SPACE_LENGTH);
+        for (i, instr) in program.iter().enumerate() {
+            state
+                .bus
+                .write(DEVICES_ADDRESS_SPACE_LENGTH + 4 * i as u64, *instr)
+                .unwrap();
+        }
+        state
+            .bus
+            .write(DEVICES_ADDRESS_SPACE_LENGTH + 0x8000 - 8, 0x1234_u64)
+            .unwrap();
+
+        let step = |space: AllocatedOf<ML, MerkleManager<'_>>| {
+            let mut state = MachineState::<T32K, _>::bind(space);
+            state.step(1);
+            state
+                .hart
+                .xregisters
+                .read(crate::machine_state::registers::t1)
+        };
+
+        let mut hashes = vec![backend.root_hash()];
+        let mut proofs = Vec::new();
+        for _ in 0..2 {
+            let (_, proof) = backend.prove(step);
+            hashes.push(backend.root_hash());
+            proofs.push(proof);
+        }
+
+        for (i, proof) in proofs.iter().enumerate() {
+            let result = MerkleBackend::<ML>::verify(proof, &hashes[i], &hashes[i + 1], step);
+            assert_eq!(result, Ok(if i == 0 { 0 } else { 0x1234 }));
+        }
+
+        // Main memory comes last in the layout. The first step only touches
+        // its first page, the second one its last page as well.
+        let pages = 0x8000 / MAX_LEAF_SIZE;
+        let memory = backend.tracker.leaves.len() - pages;
+        let accessed_pages = |proof: &Proof| {
+            proof
+                .leaves
+                .keys()
+                .filter_map(|index| index.checked_sub(memory))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(accessed_pages(&proofs[0]), [0]);
+        assert_eq!(accessed_pages(&proofs[1]), [0, pages - 1]);
+        assert!(proofs[1].leaves.len() < backend.tracker.leaves.len() / 2);
+
+        // The Merkleized backend matches the in-memory backend.
+        let (mut memory, placed) = InMemoryBackend::<ML>::new();
+        memory.write(0, backend.storage.borrow());
+        let mut state = MachineState::<T32K, _>::bind(memory.allocate(placed));
+        assert_eq!(state.hart.pc.read(), DEVICES_ADDRESS_SPACE_LENGTH + 8);
+        assert_eq!(state.step(1).steps, 1);
+    }
+
+    pub struct MerkleBackendFactory;
+
+    impl backend::tests::TestBackendFactory for MerkleBackendFactory {
+        type Backend<L: Layout> = MerkleBackend<L>;
+
+        fn new<L: Layout>() -> Self::Backend<L> {
+            MerkleBackend::<L>::new().0
+        }
+    }
+}
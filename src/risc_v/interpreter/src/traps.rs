@@ -0,0 +1,124 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Synchronous exceptions raised by the hart
+//!
+//! Section 3.1.15 & 3.1.16 - privileged spec
+
+use crate::machine_state::{bus::Address, csregisters::CSRValue};
+
+/// RISC-V exceptions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exception {
+    /// Fetching the instruction at the given address failed
+    InstructionAccessFault(Address),
+    IllegalInstruction,
+    Breakpoint,
+    /// Loading from the given address failed
+    LoadAccessFault(Address),
+    /// Storing to the given address failed
+    StoreAccessFault(Address),
+    EnvCallFromUMode,
+    EnvCallFromSMode,
+    EnvCallFromMMode,
+    /// Translating the address of the instruction to fetch failed
+    InstructionPageFault(Address),
+    /// Translating the address to load from failed
+    LoadPageFault(Address),
+    /// Translating the address to store to failed
+    StorePageFault(Address),
+}
+
+impl Exception {
+    /// Exception code, as written to `mcause` or `scause` (table 3.6)
+    pub fn exception_code(&self) -> CSRValue {
+        match self {
+            Exception::InstructionAccessFault(_) => 1,
+            Exception::IllegalInstruction => 2,
+            Exception::Breakpoint => 3,
+            Exception::LoadAccessFault(_) => 5,
+            Exception::StoreAccessFault(_) => 7,
+            Exception::EnvCallFromUMode => 8,
+            Exception::EnvCallFromSMode => 9,
+            Exception::EnvCallFromMMode => 11,
+            Exception::InstructionPageFault(_) => 12,
+            Exception::LoadPageFault(_) => 13,
+            Exception::StorePageFault(_) => 15,
+        }
+    }
+
+    /// Trap value, as written to `mtval` or `stval`
+    ///
+    /// Access and page faults report the faulting address, all other
+    /// exceptions write 0, as the spec allows.
+    pub fn trap_value(&self) -> CSRValue {
+        match self {
+            Exception::InstructionAccessFault(addr)
+            | Exception::LoadAccessFault(addr)
+            | Exception::StoreAccessFault(addr)
+            | Exception::InstructionPageFault(addr)
+            | Exception::LoadPageFault(addr)
+            | Exception::StorePageFault(addr) => *addr,
+            Exception::IllegalInstruction
+            | Exception::Breakpoint
+            | Exception::EnvCallFromUMode
+            | Exception::EnvCallFromSMode
+            | Exception::EnvCallFromMMode => 0,
+        }
+    }
+}
+
+/// Exceptions handled by the execution environment instead of the hart
+///
+/// The machine has no M-mode firmware: environment calls from S-mode (SBI
+/// calls) and from M-mode are serviced by the embedder of the interpreter,
+/// which plays the part of the execution environment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvironException {
+    EnvCallFromSMode,
+    EnvCallFromMMode,
+}
+
+impl TryFrom<&Exception> for EnvironException {
+    type Error = ();
+
+    fn try_from(value: &Exception) -> Result<Self, Self::Error> {
+        match value {
+            Exception::EnvCallFromSMode => Ok(EnvironException::EnvCallFromSMode),
+            Exception::EnvCallFromMMode => Ok(EnvironException::EnvCallFromMMode),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EnvironException, Exception};
+
+    #[test]
+    fn test_environ_exceptions() {
+        assert_eq!(
+            EnvironException::try_from(&Exception::EnvCallFromSMode),
+            Ok(EnvironException::EnvCallFromSMode)
+        );
+        assert_eq!(
+            EnvironException::try_from(&Exception::EnvCallFromMMode),
+            Ok(EnvironException::EnvCallFromMMode)
+        );
+
+        // Environment calls from U-mode are regular traps into the supervisor.
+        assert!(EnvironException::try_from(&Exception::EnvCallFromUMode).is_err());
+        assert!(EnvironException::try_from(&Exception::IllegalInstruction).is_err());
+    }
+
+    #[test]
+    fn test_trap_values() {
+        assert_eq!(Exception::LoadAccessFault(0x1234).trap_value(), 0x1234);
+        assert_eq!(Exception::LoadAccessFault(0x1234).exception_code(), 5);
+        assert_eq!(Exception::IllegalInstruction.trap_value(), 0);
+        assert_eq!(Exception::EnvCallFromMMode.exception_code(), 11);
+        assert_eq!(Exception::StorePageFault(0x1000).trap_value(), 0x1000);
+        assert_eq!(Exception::StorePageFault(0x1000).exception_code(), 15);
+    }
+}
@@ -143,6 +143,43 @@ pub fn load_elf(mem: &mut impl Memory, start: u64, contents: &[u8]) -> Result<Lo
     }
 }
 
+/// [FunctionSymbol] is an entry of the ELF symbol table describing a function
+pub struct FunctionSymbol {
+    /// (possibly mangled) name of the function
+    pub name: String,
+    /// address of the first instruction of the function once loaded
+    pub address: u64,
+    /// size of the function's code in bytes, 0 if unknown
+    pub size: u64,
+}
+
+/// Extracts the function symbols of an ELF file. If the file is relocatable, symbol addresses
+/// are offset by the `start` address it would be loaded at, see [load_elf].
+pub fn function_symbols(contents: &[u8], start: u64) -> Result<Vec<FunctionSymbol>, Error> {
+    let elf = Elf::parse(contents)?;
+    let offset = if elf.header.e_type == ET_DYN {
+        start
+    } else {
+        0
+    };
+
+    let symbols = elf
+        .syms
+        .iter()
+        .filter(|sym| sym.is_function() && sym.st_value != 0)
+        .filter_map(|sym| {
+            let name = elf.strtab.get_at(sym.st_name)?;
+            Some(FunctionSymbol {
+                name: name.to_owned(),
+                address: sym.st_value + offset,
+                size: sym.st_size,
+            })
+        })
+        .collect();
+
+    Ok(symbols)
+}
+
 impl Memory for rvemu::bus::Bus {
     fn write_bytes(&mut self, paddr: u64, bytes: &[u8]) -> Result<(), Error> {
         for (i, b) in bytes.iter().enumerate() {
@@ -4,6 +4,37 @@ module Types = Api_types
 module Functions (S : FOREIGN) = struct
   open S
 
-  let add =
-    foreign "octez_risc_v_add" (uintptr_t @-> uintptr_t @-> returning uintptr_t)
+  (* States are opaque pointers to [OctezRiscVPvm]. *)
+  let pvm = ptr void
+
+  let pvm_new =
+    foreign "octez_risc_v_pvm_new" (ptr uint8_t @-> size_t @-> returning pvm)
+
+  let pvm_free = foreign "octez_risc_v_pvm_free" (pvm @-> returning void)
+
+  let pvm_clone = foreign "octez_risc_v_pvm_clone" (pvm @-> returning pvm)
+
+  let pvm_state_hash =
+    foreign "octez_risc_v_pvm_state_hash" (pvm @-> ptr uint8_t @-> returning void)
+
+  let pvm_status = foreign "octez_risc_v_pvm_status" (pvm @-> returning uint8_t)
+
+  let pvm_eval_max =
+    foreign "octez_risc_v_pvm_eval_max" (pvm @-> uint64_t @-> returning uint64_t)
+
+  let pvm_provide_input =
+    foreign
+      "octez_risc_v_pvm_provide_input"
+      (pvm @-> uint32_t @-> uint32_t @-> ptr uint8_t @-> size_t
+     @-> returning bool)
+
+  let pvm_reveal_request =
+    foreign
+      "octez_risc_v_pvm_reveal_request"
+      (pvm @-> ptr uint8_t @-> size_t @-> returning intptr_t)
+
+  let pvm_provide_reveal =
+    foreign
+      "octez_risc_v_pvm_provide_reveal"
+      (pvm @-> ptr uint8_t @-> size_t @-> returning bool)
 end
@@ -1,8 +1,73 @@
 open Api
 open Ctypes
 
-let add (x : int) (y : int) =
-  let x_ptr = Uintptr.of_int x in
-  let y_ptr = Uintptr.of_int y in
-  let res = Functions.add x_ptr y_ptr in
-  Uintptr.to_int res
+type state = {pvm : unit ptr}
+
+type status = Evaluating | Waiting_for_input | Waiting_for_reveal
+
+let state_hash_size = 32
+
+(* Reveal requests are bounded by the PVM. *)
+let max_reveal_request_size = 4096
+
+let of_pvm pvm =
+  let state = {pvm} in
+  Gc.finalise (fun {pvm} -> Functions.pvm_free pvm) state ;
+  state
+
+(* Run [f] with a pointer to a copy of [s] and its length. *)
+let with_bytes s f =
+  let buffer = CArray.of_string s in
+  let data = coerce (ptr char) (ptr uint8_t) (CArray.start buffer) in
+  let res = f data (Unsigned.Size_t.of_int (String.length s)) in
+  ignore (Sys.opaque_identity buffer) ;
+  res
+
+(* Read [len] bytes of [buffer] into a string. *)
+let to_string buffer len =
+  String.init len (fun i ->
+      Char.chr (Unsigned.UInt8.to_int (CArray.get buffer i)))
+
+let create boot_sector =
+  let pvm = with_bytes boot_sector Functions.pvm_new in
+  if is_null pvm then None else Some (of_pvm pvm)
+
+let clone {pvm} = of_pvm (Functions.pvm_clone pvm)
+
+let state_hash {pvm} =
+  let buffer = CArray.make uint8_t state_hash_size in
+  Functions.pvm_state_hash pvm (CArray.start buffer) ;
+  to_string buffer state_hash_size
+
+let status {pvm} =
+  match Unsigned.UInt8.to_int (Functions.pvm_status pvm) with
+  | 0 -> Evaluating
+  | 1 -> Waiting_for_input
+  | 2 -> Waiting_for_reveal
+  | n -> failwith (Printf.sprintf "Unknown RISC-V PVM status %d" n)
+
+let eval_max {pvm} max_steps =
+  Functions.pvm_eval_max pvm (Unsigned.UInt64.of_int max_steps)
+  |> Unsigned.UInt64.to_int
+
+let provide_input {pvm} ~level ~counter payload =
+  with_bytes
+    payload
+    (Functions.pvm_provide_input
+       pvm
+       (Unsigned.UInt32.of_int level)
+       (Unsigned.UInt32.of_int counter))
+
+let reveal_request {pvm} =
+  let buffer = CArray.make uint8_t max_reveal_request_size in
+  let len =
+    Functions.pvm_reveal_request
+      pvm
+      (CArray.start buffer)
+      (Unsigned.Size_t.of_int max_reveal_request_size)
+    |> Intptr.to_int
+  in
+  if len < 0 then None else Some (to_string buffer len)
+
+let provide_reveal {pvm} data =
+  with_bytes data (Functions.pvm_provide_reveal pvm)
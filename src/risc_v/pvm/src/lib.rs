@@ -1,6 +1,378 @@
-use risc_v_interpreter::add;
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
 
+//! C API of the RISC-V PVM, used by the OCaml bindings of the rollup node
+//!
+//! A PVM state is handed out as an opaque pointer to [OctezRiscVPvm] which
+//! must be released using [octez_risc_v_pvm_free].
+
+use risc_v_interpreter::{
+    machine_state::bus::main_memory::{MainMemoryLayout, M1G},
+    pvm::{Pvm, PvmError, PvmLayout, PvmStatus},
+    state_backend::{
+        merkle_backend::{MerkleBackend, MerkleManager, HASH_SIZE},
+        Backend, Layout,
+    },
+};
+use std::{ptr, slice};
+
+/// Number of bytes in a state hash
+pub const STATE_HASH_SIZE: usize = HASH_SIZE;
+
+/// State of the PVM together with its backing storage
+pub struct PvmState<ML: MainMemoryLayout> {
+    backend: MerkleBackend<PvmLayout<ML>>,
+}
+
+impl<ML: MainMemoryLayout> PvmState<ML> {
+    /// Create a PVM state which starts executing the given boot sector.
+    pub fn new(boot_sector: &[u8]) -> Result<Self, PvmError> {
+        let (backend, _) = MerkleBackend::new();
+        let mut state = Self { backend };
+        state.with_pvm(|pvm| pvm.reset(boot_sector))?;
+        Ok(state)
+    }
+
+    fn with_pvm<R>(&mut self, f: impl FnOnce(&mut Pvm<ML, MerkleManager<'_>>) -> R) -> R {
+        let placed = PvmLayout::<ML>::placed().into_location();
+        let mut pvm = Pvm::bind(self.backend.allocate(placed));
+        f(&mut pvm)
+    }
+
+    /// Merkle root hash of the state, only rehashing the parts written
+    /// since the last call
+    pub fn hash(&mut self) -> [u8; STATE_HASH_SIZE] {
+        self.backend.root_hash()
+    }
+
+    /// See [Pvm::status].
+    pub fn status(&mut self) -> PvmStatus {
+        self.with_pvm(|pvm| pvm.status())
+    }
+
+    /// See [Pvm::eval_max].
+    pub fn eval_max(&mut self, max_steps: usize) -> usize {
+        self.with_pvm(|pvm| pvm.eval_max(max_steps))
+    }
+
+    /// See [Pvm::provide_input].
+    pub fn provide_input(
+        &mut self,
+        level: u32,
+        counter: u32,
+        payload: &[u8],
+    ) -> Result<(), PvmError> {
+        self.with_pvm(|pvm| pvm.provide_input(level, counter, payload))
+    }
+
+    /// See [Pvm::reveal_request].
+    pub fn reveal_request(&mut self) -> Result<Vec<u8>, PvmError> {
+        self.with_pvm(|pvm| pvm.reveal_request())
+    }
+
+    /// See [Pvm::provide_reveal].
+    pub fn provide_reveal(&mut self, data: &[u8]) -> Result<(), PvmError> {
+        self.with_pvm(|pvm| pvm.provide_reveal(data))
+    }
+}
+
+impl<ML: MainMemoryLayout> Clone for PvmState<ML> {
+    /// Only the parts of the state that were written are copied.
+    fn clone(&self) -> Self {
+        Self {
+            backend: self.backend.clone(),
+        }
+    }
+}
+
+/// Opaque PVM state handed to C
+pub struct OctezRiscVPvm(PvmState<M1G>);
+
+/// Status of the PVM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OctezRiscVPvmStatus {
+    Evaluating = 0,
+    WaitingForInput = 1,
+    WaitingForReveal = 2,
+}
+
+impl From<PvmStatus> for OctezRiscVPvmStatus {
+    fn from(status: PvmStatus) -> Self {
+        match status {
+            PvmStatus::Evaluating => Self::Evaluating,
+            PvmStatus::WaitingForInput => Self::WaitingForInput,
+            PvmStatus::WaitingForReveal => Self::WaitingForReveal,
+        }
+    }
+}
+
+/// View `len` bytes at `data` as a slice. `data` may be null if `len` is 0.
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len)
+    }
+}
+
+/// Create a PVM state from a boot sector of `len` bytes. Returns null if the
+/// boot sector does not fit into main memory.
+///
+/// # Safety
+///
+/// `boot_sector` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn octez_risc_v_pvm_new(
+    boot_sector: *const u8,
+    len: usize,
+) -> *mut OctezRiscVPvm {
+    match PvmState::new(bytes(boot_sector, len)) {
+        Ok(state) => Box::into_raw(Box::new(OctezRiscVPvm(state))),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Release a PVM state.
+///
+/// # Safety
+///
+/// `pvm` must have been obtained from this API and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn octez_risc_v_pvm_free(pvm: *mut OctezRiscVPvm) {
+    if !pvm.is_null() {
+        drop(Box::from_raw(pvm));
+    }
+}
+
+/// Copy a PVM state.
+///
+/// # Safety
+///
+/// `pvm` must be a valid PVM state.
+#[no_mangle]
+pub unsafe extern "C" fn octez_risc_v_pvm_clone(pvm: *const OctezRiscVPvm) -> *mut OctezRiscVPvm {
+    Box::into_raw(Box::new(OctezRiscVPvm((*pvm).0.clone())))
+}
+
+/// Write the [STATE_HASH_SIZE] bytes of the state hash to `out`.
+///
+/// # Safety
+///
+/// `pvm` must be a valid PVM state and `out` must point to
+/// [STATE_HASH_SIZE] writable bytes.
 #[no_mangle]
-pub extern "C" fn octez_risc_v_add(left: usize, right: usize) -> usize {
-    add(left, right)
+pub unsafe extern "C" fn octez_risc_v_pvm_state_hash(pvm: *mut OctezRiscVPvm, out: *mut u8) {
+    let hash = (*pvm).0.hash();
+    ptr::copy_nonoverlapping(hash.as_ptr(), out, STATE_HASH_SIZE);
+}
+
+/// Current status of the PVM
+///
+/// # Safety
+///
+/// `pvm` must be a valid PVM state.
+#[no_mangle]
+pub unsafe extern "C" fn octez_risc_v_pvm_status(pvm: *mut OctezRiscVPvm) -> OctezRiscVPvmStatus {
+    (*pvm).0.status().into()
+}
+
+/// Execute at most `max_steps` instructions and return the number of
+/// instructions that were executed.
+///
+/// # Safety
+///
+/// `pvm` must be a valid PVM state.
+#[no_mangle]
+pub unsafe extern "C" fn octez_risc_v_pvm_eval_max(pvm: *mut OctezRiscVPvm, max_steps: u64) -> u64 {
+    let max_steps = usize::try_from(max_steps).unwrap_or(usize::MAX);
+    (*pvm).0.eval_max(max_steps) as u64
+}
+
+/// Provide an inbox message. Returns false if the PVM is not waiting for
+/// input.
+///
+/// # Safety
+///
+/// `pvm` must be a valid PVM state and `payload` must point to `len`
+/// readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn octez_risc_v_pvm_provide_input(
+    pvm: *mut OctezRiscVPvm,
+    level: u32,
+    counter: u32,
+    payload: *const u8,
+    len: usize,
+) -> bool {
+    (*pvm)
+        .0
+        .provide_input(level, counter, bytes(payload, len))
+        .is_ok()
+}
+
+/// Copy at most `max_len` bytes of the reveal request to `out` and return
+/// the length of the whole request, or -1 if the PVM is not waiting for a
+/// reveal.
+///
+/// # Safety
+///
+/// `pvm` must be a valid PVM state and `out` must point to `max_len`
+/// writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn octez_risc_v_pvm_reveal_request(
+    pvm: *mut OctezRiscVPvm,
+    out: *mut u8,
+    max_len: usize,
+) -> isize {
+    match (*pvm).0.reveal_request() {
+        Ok(request) => {
+            ptr::copy_nonoverlapping(request.as_ptr(), out, request.len().min(max_len));
+            request.len() as isize
+        }
+        Err(_) => -1,
+    }
+}
+
+/// Provide the data for the pending reveal request. Returns false if the PVM
+/// is not waiting for a reveal.
+///
+/// # Safety
+///
+/// `pvm` must be a valid PVM state and `data` must point to `len` readable
+/// bytes.
+#[no_mangle]
+pub unsafe extern "C" fn octez_risc_v_pvm_provide_reveal(
+    pvm: *mut OctezRiscVPvm,
+    data: *const u8,
+    len: usize,
+) -> bool {
+    (*pvm).0.provide_reveal(bytes(data, len)).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use risc_v_interpreter::machine_state::bus::main_memory::Sizes;
+
+    type M4K = Sizes<4096>;
+
+    // Boot sector reading an inbox message into a buffer, revealing the
+    // preimage of its first 4 bytes and then looping
+    //     li a7, SBI_FIRMWARE_TEZOS
+    //     li a6, SBI_TEZOS_INBOX_NEXT
+    //     auipc a0, 0
+    //     addi a0, a0, 0x100
+    //     li a1, 16
+    //     ecall
+    //     li a6, SBI_TEZOS_REVEAL_PREIMAGE
+    //     auipc a0, 0
+    //     addi a0, a0, 0xec
+    //     li a1, 4
+    //     addi a2, a0, 0x100
+    //     li a3, 16
+    //     ecall
+    //     j 0
+    fn boot_sector() -> Vec<u8> {
+        let program: [u32; 14] = [
+            0x0a0008b7, // lui a7, 0xa000
+            0x00100813, // li a6, 1
+            0x00000517, // auipc a0, 0
+            0x10050513, // addi a0, a0, 0x100
+            0x01000593, // li a1, 16
+            0x00000073, // ecall
+            0x00800813, // li a6, 8
+            0x00000517, // auipc a0, 0
+            0x0ec50513, // addi a0, a0, 0xec
+            0x00400593, // li a1, 4
+            0x10050613, // addi a2, a0, 0x100
+            0x01000693, // li a3, 16
+            0x00000073, // ecall
+            0x0000006f, // j 0
+        ];
+        program
+            .iter()
+            .flat_map(|instr| instr.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn test_pvm_state() {
+        let mut state = PvmState::<M4K>::new(&boot_sector()).unwrap();
+        let initial_hash = state.hash();
+        assert_eq!(
+            initial_hash,
+            PvmState::<M4K>::new(&boot_sector()).unwrap().hash()
+        );
+        assert!(PvmState::<M4K>::new(&[0; 8192]).is_err());
+
+        assert_eq!(state.eval_max(100), 6);
+        assert_eq!(state.status(), PvmStatus::WaitingForInput);
+        assert_ne!(state.hash(), initial_hash);
+
+        // Copies evolve independently.
+        let mut waiting = state.clone();
+        assert_eq!(waiting.hash(), state.hash());
+        state.provide_input(1, 0, b"hash-and-more").unwrap();
+        assert_ne!(state.hash(), waiting.hash());
+
+        assert_eq!(state.eval_max(100), 7);
+        assert_eq!(state.status(), PvmStatus::WaitingForReveal);
+        assert_eq!(state.reveal_request(), Ok(b"hash".to_vec()));
+
+        state.provide_reveal(b"preimage").unwrap();
+        assert_eq!(state.eval_max(100), 100);
+        assert_eq!(state.status(), PvmStatus::Evaluating);
+    }
+
+    #[test]
+    fn test_c_api() {
+        let boot_sector = boot_sector();
+
+        unsafe {
+            let pvm = octez_risc_v_pvm_new(boot_sector.as_ptr(), boot_sector.len());
+            assert!(!pvm.is_null());
+            assert_eq!(
+                octez_risc_v_pvm_status(pvm),
+                OctezRiscVPvmStatus::Evaluating
+            );
+            assert_eq!(octez_risc_v_pvm_reveal_request(pvm, ptr::null_mut(), 0), -1);
+
+            assert_eq!(octez_risc_v_pvm_eval_max(pvm, 100), 6);
+            assert_eq!(
+                octez_risc_v_pvm_status(pvm),
+                OctezRiscVPvmStatus::WaitingForInput
+            );
+            assert!(!octez_risc_v_pvm_provide_reveal(pvm, ptr::null(), 0));
+
+            let message = b"hash";
+            assert!(octez_risc_v_pvm_provide_input(
+                pvm,
+                1,
+                0,
+                message.as_ptr(),
+                message.len()
+            ));
+            assert_eq!(octez_risc_v_pvm_eval_max(pvm, 100), 7);
+            assert_eq!(
+                octez_risc_v_pvm_status(pvm),
+                OctezRiscVPvmStatus::WaitingForReveal
+            );
+
+            let mut request = [0; 2];
+            assert_eq!(
+                octez_risc_v_pvm_reveal_request(pvm, request.as_mut_ptr(), request.len()),
+                4
+            );
+            assert_eq!(&request, b"ha");
+
+            assert!(octez_risc_v_pvm_provide_reveal(pvm, ptr::null(), 0));
+            assert_eq!(
+                octez_risc_v_pvm_status(pvm),
+                OctezRiscVPvmStatus::Evaluating
+            );
+
+            octez_risc_v_pvm_free(pvm);
+        }
+    }
 }
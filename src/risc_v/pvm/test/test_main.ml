@@ -1,9 +1,68 @@
 open Octez_risc_v_pvm
 
-let add_test () =
-  let res = Main.add 5 6 in
-  Alcotest.(check int "add" res 11)
+(* Boot sector reading an inbox message of at most 16 bytes, revealing the
+   preimage of its first 4 bytes and then looping. See the tests of the Rust
+   crate for the assembly. *)
+let boot_sector =
+  [
+    0x0a0008b7l;
+    0x00100813l;
+    0x00000517l;
+    0x10050513l;
+    0x01000593l;
+    0x00000073l;
+    0x00800813l;
+    0x00000517l;
+    0x0ec50513l;
+    0x00400593l;
+    0x10050613l;
+    0x01000693l;
+    0x00000073l;
+    0x0000006fl;
+  ]
+  |> List.map (fun instr ->
+         let b = Bytes.create 4 in
+         Bytes.set_int32_le b 0 instr ;
+         Bytes.to_string b)
+  |> String.concat ""
 
-let tests = [("Main", [("add", `Quick, add_test)])]
+let status =
+  Alcotest.testable
+    (fun fmt status ->
+      Format.pp_print_string
+        fmt
+        (match status with
+        | Main.Evaluating -> "Evaluating"
+        | Waiting_for_input -> "Waiting_for_input"
+        | Waiting_for_reveal -> "Waiting_for_reveal"))
+    ( = )
+
+let pvm_test () =
+  let state = Option.get (Main.create boot_sector) in
+  Alcotest.(check status "initial status" Main.Evaluating (Main.status state)) ;
+  Alcotest.(check int "steps until input" 6 (Main.eval_max state 100)) ;
+  Alcotest.(
+    check status "waiting for input" Main.Waiting_for_input (Main.status state)) ;
+  Alcotest.(check bool "unexpected reveal" false (Main.provide_reveal state "")) ;
+  let copy = Main.clone state in
+  Alcotest.(
+    check
+      bool
+      "input"
+      true
+      (Main.provide_input state ~level:1 ~counter:0 "hash")) ;
+  Alcotest.(check int "steps until reveal" 7 (Main.eval_max state 100)) ;
+  Alcotest.(
+    check status "waiting for reveal" Main.Waiting_for_reveal (Main.status state)) ;
+  Alcotest.(
+    check (option string) "request" (Some "hash") (Main.reveal_request state)) ;
+  Alcotest.(check bool "reveal" true (Main.provide_reveal state "preimage")) ;
+  Alcotest.(check status "evaluating" Main.Evaluating (Main.status state)) ;
+  Alcotest.(check status "copy" Main.Waiting_for_input (Main.status copy)) ;
+  let hash = Main.state_hash state in
+  Alcotest.(check int "hash size" Main.state_hash_size (String.length hash)) ;
+  Alcotest.(check bool "hashes differ" false (hash = Main.state_hash copy))
+
+let tests = [("Main", [("pvm", `Quick, pvm_test)])]
 
 let () = Alcotest.run ~__FILE__ "RISC-V interpreter" tests
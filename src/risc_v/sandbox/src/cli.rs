@@ -1,4 +1,5 @@
 use clap::Parser;
+use std::path::PathBuf;
 
 /// Structure that encodes the CLI options, flags and commands
 #[derive(Debug, Clone, Parser)]
@@ -11,10 +12,31 @@ pub struct Cli {
     #[arg(long)]
     pub initrd: Option<String>,
 
+    /// Path to the inbox file, in JSON or YAML format, listing the messages
+    /// of each level
+    #[arg(long)]
+    pub inbox: Option<String>,
+
+    /// Directory containing the preimages which may be revealed, named after
+    /// their hex-encoded hashes
+    #[arg(long)]
+    pub preimages_dir: Option<PathBuf>,
+
     /// Keep going after the inbox has been drained.
     #[arg(short, long)]
     pub keep_going: bool,
 
+    /// Wait for GDB to connect on the given local port and let it control
+    /// the execution
+    #[arg(long)]
+    pub gdb: Option<u16>,
+
+    /// Count the instructions executed per function and call stack, write
+    /// them to the given file in the folded stack format used by flamegraph
+    /// tools and print a summary of the hottest functions
+    #[arg(long)]
+    pub profile: Option<PathBuf>,
+
     /// Support some POSIX-style system calls
     #[arg(long)]
     pub posix: bool,
@@ -0,0 +1,820 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Stub for the GDB Remote Serial Protocol
+//!
+//! More information on the protocol:
+//!   - https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+
+use std::{
+    collections::BTreeSet,
+    error::Error,
+    fmt::Write as _,
+    io::{self, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+};
+
+/// Number of registers exposed to GDB: `x0` to `x31` and `pc`
+const NUM_REGISTERS: usize = 33;
+
+/// Index of the program counter amongst the registers
+const PC_REGISTER: usize = 32;
+
+/// Number of steps between checks for an interrupt request from GDB
+const INTERRUPT_CHECK_INTERVAL: usize = 1024;
+
+/// Byte sent by GDB to interrupt the target
+const INTERRUPT: u8 = 0x03;
+
+/// Stop reply for a trap, i.e. after a step, breakpoint, interrupt or
+/// exception
+const STOP_TRAP: &str = "S05";
+
+/// Reply once the target has stopped running
+const EXITED: &str = "W00";
+
+/// Reply to a packet which could not be parsed
+const MALFORMED_PACKET: &str = "E01";
+
+/// Maximum size of a packet, advertised to GDB
+const PACKET_SIZE: usize = 0x4000;
+
+/// Outcome of executing a single instruction of the target
+pub enum Step {
+    /// The instruction was executed.
+    Executed,
+    /// The instruction raised an exception the target does not handle, or
+    /// the target is stuck. GDB is told of it as a trap, while the target is
+    /// still at the faulting instruction.
+    Exception(String),
+    /// The machine has stopped running.
+    Exited,
+}
+
+/// Machine which can be debugged through the stub
+pub trait Target {
+    /// Read the register at the given index, see [NUM_REGISTERS].
+    fn read_register(&self, index: usize) -> u64;
+
+    /// Write the register at the given index, see [NUM_REGISTERS].
+    fn write_register(&mut self, index: usize, value: u64);
+
+    /// Read a byte at a virtual address.
+    fn read_byte(&mut self, address: u64) -> Option<u8>;
+
+    /// Write a byte at a virtual address.
+    fn write_byte(&mut self, address: u64, value: u8) -> Option<()>;
+
+    /// Execute a single instruction.
+    fn step(&mut self) -> Result<Step, Box<dyn Error>>;
+}
+
+/// Target description, which tells GDB the architecture and register layout
+fn target_xml() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+         <target version=\"1.0\">\
+         <architecture>riscv:rv64</architecture>\
+         <feature name=\"org.gnu.gdb.riscv.cpu\">",
+    );
+
+    for index in 0..32 {
+        let kind = match index {
+            2 => "data_ptr",
+            1 | 8 => "code_ptr",
+            _ => "int",
+        };
+        let _ = write!(
+            xml,
+            "<reg name=\"x{index}\" bitsize=\"64\" type=\"{kind}\"/>"
+        );
+    }
+
+    xml.push_str("<reg name=\"pc\" bitsize=\"64\" type=\"code_ptr\"/></feature></target>");
+    xml
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn hex_u64(value: &str) -> Result<u64, Box<dyn Error>> {
+    Ok(u64::from_str_radix(value, 16)?)
+}
+
+/// Parse a packet argument of the form `addr,len`.
+fn address_and_length(args: &str) -> Result<(u64, usize), Box<dyn Error>> {
+    let (address, length) = args.split_once(',').ok_or("Missing length")?;
+    Ok((hex_u64(address)?, hex_u64(length)? as usize))
+}
+
+/// Incoming data from GDB
+enum Incoming {
+    Packet(String),
+    Interrupt,
+}
+
+/// Packet sent by GDB
+#[derive(Debug, PartialEq)]
+enum Command {
+    /// `?`: reason why the target stopped
+    Status,
+    /// `g`: read all the registers
+    ReadRegisters,
+    /// `G`: write all the registers
+    WriteRegisters(Vec<u8>),
+    /// `p`: read a single register
+    ReadRegister(usize),
+    /// `P`: write a single register
+    WriteRegister(usize, u64),
+    /// `m`: read memory
+    ReadMemory(u64, usize),
+    /// `M`: write memory
+    WriteMemory(u64, Vec<u8>),
+    /// `Z0`/`Z1`: insert a breakpoint
+    InsertBreakpoint(u64),
+    /// `z0`/`z1`: remove a breakpoint
+    RemoveBreakpoint(u64),
+    /// `s`: execute a single instruction, possibly from another address
+    Step(Option<u64>),
+    /// `c`: resume execution, possibly from another address
+    Continue(Option<u64>),
+    /// `H`: select a thread, there is only one
+    SetThread,
+    Detach,
+    Kill,
+    /// Anything else, answered by [GdbStub::query]
+    Query(String),
+}
+
+/// Parse the content of a packet.
+fn parse_command(packet: &str) -> Result<Command, Box<dyn Error>> {
+    let (command, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+    let resume_address = |args: &str| -> Result<Option<u64>, Box<dyn Error>> {
+        if args.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(hex_u64(args)?))
+        }
+    };
+
+    let command = match command {
+        "?" => Command::Status,
+        "g" => Command::ReadRegisters,
+        "G" => Command::WriteRegisters(hex::decode(args)?),
+        "p" => Command::ReadRegister(hex_u64(args)? as usize),
+
+        "P" => {
+            let (index, value) = args.split_once('=').ok_or("Missing register value")?;
+            let value: [u8; 8] = hex::decode(value)?
+                .try_into()
+                .map_err(|_| "Invalid register value")?;
+            Command::WriteRegister(hex_u64(index)? as usize, u64::from_le_bytes(value))
+        }
+
+        "m" => {
+            let (address, length) = address_and_length(args)?;
+            Command::ReadMemory(address, length)
+        }
+
+        "M" => {
+            let (location, data) = args.split_once(':').ok_or("Missing data")?;
+            let (address, _) = address_and_length(location)?;
+            Command::WriteMemory(address, hex::decode(data)?)
+        }
+
+        // Software and hardware breakpoints are handled alike, without
+        // modifying the guest memory.
+        "Z" | "z" if args.starts_with('0') || args.starts_with('1') => {
+            let location = args.get(2..).ok_or("Missing breakpoint address")?;
+            let (address, _) = address_and_length(location)?;
+
+            if command == "Z" {
+                Command::InsertBreakpoint(address)
+            } else {
+                Command::RemoveBreakpoint(address)
+            }
+        }
+
+        "s" => Command::Step(resume_address(args)?),
+        "c" => Command::Continue(resume_address(args)?),
+        "H" => Command::SetThread,
+        "D" => Command::Detach,
+        "k" => Command::Kill,
+        _ => Command::Query(packet.to_owned()),
+    };
+
+    Ok(command)
+}
+
+/// Whether the two hex digits of a packet checksum match its data.
+fn valid_checksum(data: &[u8], sum: &[u8]) -> bool {
+    std::str::from_utf8(sum)
+        .ok()
+        .and_then(|sum| u8::from_str_radix(sum, 16).ok())
+        == Some(checksum(data))
+}
+
+/// Wrap data in a packet, with its checksum.
+fn frame(data: &str) -> String {
+    format!("${data}#{:02x}", checksum(data.as_bytes()))
+}
+
+/// Outcome of handling a packet
+enum Action {
+    Reply(String),
+    Detach,
+    Kill,
+}
+
+/// Stub speaking the GDB Remote Serial Protocol to a single client
+pub struct GdbStub {
+    stream: TcpStream,
+    breakpoints: BTreeSet<u64>,
+}
+
+impl GdbStub {
+    /// Wait for GDB to connect on the given local port.
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        eprintln!("Waiting for GDB to connect on port {port}");
+
+        let (stream, peer) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        eprintln!("GDB connected from {peer}");
+
+        Ok(Self {
+            stream,
+            breakpoints: BTreeSet::new(),
+        })
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0u8];
+        self.stream.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    /// Receive the next packet or interrupt request. Acknowledgements are
+    /// skipped, and packets with a bad checksum are retransmitted.
+    fn receive(&mut self) -> io::Result<Incoming> {
+        loop {
+            match self.read_byte()? {
+                b'$' => {}
+                INTERRUPT => return Ok(Incoming::Interrupt),
+                _ => continue,
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    byte => data.push(byte),
+                }
+            }
+
+            let mut sum = [0u8; 2];
+            self.stream.read_exact(&mut sum)?;
+            if !valid_checksum(&data, &sum) {
+                // Ask for retransmission.
+                self.stream.write_all(b"-")?;
+                continue;
+            }
+
+            self.stream.write_all(b"+")?;
+            return Ok(Incoming::Packet(
+                String::from_utf8_lossy(&data).into_owned(),
+            ));
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = frame(data);
+
+        loop {
+            self.stream.write_all(packet.as_bytes())?;
+
+            match self.read_byte()? {
+                b'-' => continue,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Check whether GDB requested an interrupt, without blocking.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut byte = [0u8];
+        let result = self.stream.read(&mut byte);
+        self.stream.set_nonblocking(false)?;
+
+        match result {
+            Ok(1) => Ok(byte[0] == INTERRUPT),
+            Ok(_) => Err(io::ErrorKind::UnexpectedEof.into()),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Run the target until it hits a breakpoint, stops running or GDB
+    /// interrupts it. Returns the stop reply.
+    fn resume(&mut self, target: &mut impl Target) -> Result<String, Box<dyn Error>> {
+        for steps in 1.. {
+            match target.step()? {
+                Step::Executed => {}
+                Step::Exception(_) => return Ok(STOP_TRAP.to_owned()),
+                Step::Exited => return Ok(EXITED.to_owned()),
+            }
+
+            if self
+                .breakpoints
+                .contains(&target.read_register(PC_REGISTER))
+            {
+                break;
+            }
+
+            if steps % INTERRUPT_CHECK_INTERVAL == 0 && self.interrupted()? {
+                break;
+            }
+        }
+
+        Ok(STOP_TRAP.to_owned())
+    }
+
+    /// Handle a packet from GDB. Malformed packets are answered with an
+    /// error reply.
+    fn handle(&mut self, target: &mut impl Target, packet: &str) -> Result<Action, Box<dyn Error>> {
+        let Ok(command) = parse_command(packet) else {
+            return Ok(Action::Reply(MALFORMED_PACKET.to_owned()));
+        };
+
+        let reply = match command {
+            Command::Status => STOP_TRAP.to_owned(),
+
+            Command::ReadRegisters => (0..NUM_REGISTERS)
+                .map(|index| hex::encode(target.read_register(index).to_le_bytes()))
+                .collect(),
+
+            Command::WriteRegisters(bytes) => {
+                for (index, value) in bytes.chunks_exact(8).take(NUM_REGISTERS).enumerate() {
+                    target.write_register(index, u64::from_le_bytes(value.try_into()?));
+                }
+                "OK".to_owned()
+            }
+
+            Command::ReadRegister(index) if index < NUM_REGISTERS => {
+                hex::encode(target.read_register(index).to_le_bytes())
+            }
+
+            Command::WriteRegister(index, value) if index < NUM_REGISTERS => {
+                target.write_register(index, value);
+                "OK".to_owned()
+            }
+
+            Command::ReadRegister(_) | Command::WriteRegister(_, _) => "E00".to_owned(),
+
+            // The hex-encoded bytes must fit in a packet.
+            Command::ReadMemory(_, length) if length > PACKET_SIZE / 2 => "E00".to_owned(),
+
+            Command::ReadMemory(address, length) => {
+                let bytes: Option<Vec<u8>> = (0..length as u64)
+                    .map(|offset| target.read_byte(address.wrapping_add(offset)))
+                    .collect();
+
+                match bytes {
+                    Some(bytes) => hex::encode(bytes),
+                    None => "E14".to_owned(),
+                }
+            }
+
+            Command::WriteMemory(address, data) => {
+                let written: Option<()> =
+                    data.into_iter().enumerate().try_for_each(|(offset, byte)| {
+                        target.write_byte(address.wrapping_add(offset as u64), byte)
+                    });
+
+                match written {
+                    Some(()) => "OK".to_owned(),
+                    None => "E14".to_owned(),
+                }
+            }
+
+            Command::InsertBreakpoint(address) => {
+                self.breakpoints.insert(address);
+                "OK".to_owned()
+            }
+
+            Command::RemoveBreakpoint(address) => {
+                self.breakpoints.remove(&address);
+                "OK".to_owned()
+            }
+
+            Command::Step(address) => {
+                if let Some(address) = address {
+                    target.write_register(PC_REGISTER, address);
+                }
+
+                match target.step()? {
+                    Step::Executed => STOP_TRAP.to_owned(),
+                    Step::Exception(_) => STOP_TRAP.to_owned(),
+                    Step::Exited => EXITED.to_owned(),
+                }
+            }
+
+            Command::Continue(address) => {
+                if let Some(address) = address {
+                    target.write_register(PC_REGISTER, address);
+                }
+
+                self.resume(target)?
+            }
+
+            Command::SetThread => "OK".to_owned(),
+
+            Command::Detach => {
+                self.send("OK")?;
+                return Ok(Action::Detach);
+            }
+
+            Command::Kill => return Ok(Action::Kill),
+
+            Command::Query(packet) => self.query(&packet),
+        };
+
+        Ok(Action::Reply(reply))
+    }
+
+    /// Answer general queries. Unsupported packets get an empty reply.
+    fn query(&self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return format!("PacketSize={PACKET_SIZE:x};qXfer:features:read+");
+        }
+
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let xml = target_xml();
+            let Ok((offset, length)) = address_and_length(range) else {
+                return "E00".to_owned();
+            };
+
+            let start = (offset as usize).min(xml.len());
+            let end = start.saturating_add(length).min(xml.len());
+            let marker = if end == xml.len() { 'l' } else { 'm' };
+            return format!("{marker}{}", &xml[start..end]);
+        }
+
+        match packet {
+            "qAttached" => "1".to_owned(),
+            "qC" => "QC1".to_owned(),
+            "qfThreadInfo" => "m1".to_owned(),
+            "qsThreadInfo" => "l".to_owned(),
+            _ => String::new(),
+        }
+    }
+
+    /// Serve GDB until it detaches or kills the target. After detaching, the
+    /// target runs to completion.
+    pub fn run(&mut self, target: &mut impl Target) -> Result<(), Box<dyn Error>> {
+        loop {
+            let packet = match self.receive()? {
+                Incoming::Packet(packet) => packet,
+
+                // The target is already stopped.
+                Incoming::Interrupt => {
+                    self.send(STOP_TRAP)?;
+                    continue;
+                }
+            };
+
+            match self.handle(target, &packet)? {
+                Action::Reply(reply) => {
+                    self.send(&reply)?;
+
+                    if reply == EXITED {
+                        return Ok(());
+                    }
+                }
+
+                Action::Detach => loop {
+                    match target.step()? {
+                        Step::Executed => {}
+                        Step::Exception(description) => return Err(description.into()),
+                        Step::Exited => return Ok(()),
+                    }
+                },
+
+                Action::Kill => return Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MEMORY_START: u64 = 0x1000;
+    const EXCEPTION_ADDRESS: u64 = 0x1100;
+    const MAX_STEPS: usize = 64;
+
+    /// Machine executing 4-byte no-ops from [MEMORY_START], raising an
+    /// exception at [EXCEPTION_ADDRESS] and exiting after [MAX_STEPS].
+    struct FakeTarget {
+        registers: [u64; NUM_REGISTERS],
+        memory: Vec<u8>,
+        steps: usize,
+    }
+
+    impl FakeTarget {
+        fn new() -> Self {
+            let mut registers = [0; NUM_REGISTERS];
+            registers[PC_REGISTER] = MEMORY_START;
+
+            Self {
+                registers,
+                memory: (0..16).collect(),
+                steps: 0,
+            }
+        }
+
+        fn pc(&self) -> u64 {
+            self.registers[PC_REGISTER]
+        }
+    }
+
+    impl Target for FakeTarget {
+        fn read_register(&self, index: usize) -> u64 {
+            self.registers[index]
+        }
+
+        fn write_register(&mut self, index: usize, value: u64) {
+            self.registers[index] = value;
+        }
+
+        fn read_byte(&mut self, address: u64) -> Option<u8> {
+            let offset = address.checked_sub(MEMORY_START)?;
+            self.memory.get(offset as usize).copied()
+        }
+
+        fn write_byte(&mut self, address: u64, value: u8) -> Option<()> {
+            let offset = address.checked_sub(MEMORY_START)?;
+            *self.memory.get_mut(offset as usize)? = value;
+            Some(())
+        }
+
+        fn step(&mut self) -> Result<Step, Box<dyn Error>> {
+            if self.steps == MAX_STEPS {
+                return Ok(Step::Exited);
+            }
+            self.steps += 1;
+
+            if self.pc() == EXCEPTION_ADDRESS {
+                return Ok(Step::Exception("Exception".to_owned()));
+            }
+
+            self.registers[PC_REGISTER] += 4;
+            Ok(Step::Executed)
+        }
+    }
+
+    /// Stub connected to a client which never sends anything.
+    fn stub() -> (GdbStub, TcpStream) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        let stub = GdbStub {
+            stream,
+            breakpoints: BTreeSet::new(),
+        };
+        (stub, client)
+    }
+
+    fn reply(stub: &mut GdbStub, target: &mut FakeTarget, packet: &str) -> String {
+        match stub.handle(target, packet).unwrap() {
+            Action::Reply(reply) => reply,
+            Action::Detach => panic!("Unexpected detach on {packet}"),
+            Action::Kill => panic!("Unexpected kill on {packet}"),
+        }
+    }
+
+    #[test]
+    fn checksums() {
+        assert_eq!(frame("OK"), "$OK#9a");
+        assert_eq!(frame(""), "$#00");
+        assert!(valid_checksum(b"OK", b"9a"));
+        assert!(valid_checksum(b"OK", b"9A"));
+        assert!(!valid_checksum(b"OK", b"9b"));
+        assert!(!valid_checksum(b"OK", b"zz"));
+    }
+
+    #[test]
+    fn bad_checksums_are_retransmitted() {
+        const BAD_PACKETS: usize = 1000;
+        let (mut stub, mut client) = stub();
+
+        for _ in 0..BAD_PACKETS {
+            client.write_all(b"$OK#00").unwrap();
+        }
+        client.write_all(frame("OK").as_bytes()).unwrap();
+
+        match stub.receive().unwrap() {
+            Incoming::Packet(packet) => assert_eq!(packet, "OK"),
+            Incoming::Interrupt => panic!("Unexpected interrupt"),
+        }
+
+        let mut acks = vec![0u8; BAD_PACKETS + 1];
+        client.read_exact(&mut acks).unwrap();
+        assert_eq!(acks[..BAD_PACKETS], [b'-'; BAD_PACKETS]);
+        assert_eq!(acks[BAD_PACKETS], b'+');
+    }
+
+    #[test]
+    fn parse_packets() {
+        assert_eq!(parse_command("?").unwrap(), Command::Status);
+        assert_eq!(parse_command("g").unwrap(), Command::ReadRegisters);
+        assert_eq!(parse_command("p20").unwrap(), Command::ReadRegister(32));
+        assert_eq!(
+            parse_command("P1=0100000000000000").unwrap(),
+            Command::WriteRegister(1, 1)
+        );
+        assert_eq!(
+            parse_command("m80000000,4").unwrap(),
+            Command::ReadMemory(0x8000_0000, 4)
+        );
+        assert_eq!(
+            parse_command("M80000000,2:0102").unwrap(),
+            Command::WriteMemory(0x8000_0000, vec![1, 2])
+        );
+        assert_eq!(
+            parse_command("Z0,80000010,4").unwrap(),
+            Command::InsertBreakpoint(0x8000_0010)
+        );
+        assert_eq!(
+            parse_command("z1,80000010,4").unwrap(),
+            Command::RemoveBreakpoint(0x8000_0010)
+        );
+        assert_eq!(parse_command("s").unwrap(), Command::Step(None));
+        assert_eq!(
+            parse_command("c80000000").unwrap(),
+            Command::Continue(Some(0x8000_0000))
+        );
+        assert_eq!(
+            parse_command("qSupported:swbreak+").unwrap(),
+            Command::Query("qSupported:swbreak+".to_owned())
+        );
+        // Watchpoints are not supported.
+        assert_eq!(
+            parse_command("Z2,80000010,4").unwrap(),
+            Command::Query("Z2,80000010,4".to_owned())
+        );
+    }
+
+    #[test]
+    fn reject_malformed_packets() {
+        for packet in [
+            "Z0",
+            "z1",
+            "Z0,",
+            "m80000000",
+            "mzz,4",
+            "P1",
+            "P1=01",
+            "G0",
+            "px",
+        ] {
+            assert!(parse_command(packet).is_err(), "{packet}");
+        }
+    }
+
+    #[test]
+    fn read_and_write_registers() {
+        let (mut stub, _client) = stub();
+        let mut target = FakeTarget::new();
+
+        assert_eq!(reply(&mut stub, &mut target, "P5=2a00000000000000"), "OK");
+        assert_eq!(target.registers[5], 42);
+        assert_eq!(reply(&mut stub, &mut target, "p5"), "2a00000000000000");
+        assert_eq!(reply(&mut stub, &mut target, "p20"), "0010000000000000");
+
+        let registers = reply(&mut stub, &mut target, "g");
+        assert_eq!(registers.len(), NUM_REGISTERS * 16);
+        assert_eq!(&registers[5 * 16..6 * 16], "2a00000000000000");
+
+        let registers = "0100000000000000".repeat(NUM_REGISTERS);
+        assert_eq!(
+            reply(&mut stub, &mut target, &format!("G{registers}")),
+            "OK"
+        );
+        assert_eq!(target.registers, [1; NUM_REGISTERS]);
+
+        // Registers past the program counter do not exist.
+        assert_eq!(reply(&mut stub, &mut target, "p21"), "E00");
+        assert_eq!(reply(&mut stub, &mut target, "P21=0000000000000000"), "E00");
+    }
+
+    #[test]
+    fn read_and_write_memory() {
+        let (mut stub, _client) = stub();
+        let mut target = FakeTarget::new();
+
+        assert_eq!(reply(&mut stub, &mut target, "m1002,3"), "020304");
+        assert_eq!(reply(&mut stub, &mut target, "M1002,2:abcd"), "OK");
+        assert_eq!(reply(&mut stub, &mut target, "m1001,4"), "01abcd04");
+
+        // Accesses partly out of the memory fail.
+        assert_eq!(reply(&mut stub, &mut target, "m100e,4"), "E14");
+        assert_eq!(reply(&mut stub, &mut target, "Mfff,2:0102"), "E14");
+
+        // Reads which would not fit in a packet are refused.
+        assert_eq!(reply(&mut stub, &mut target, "m1000,2001"), "E00");
+        assert_eq!(reply(&mut stub, &mut target, "m0,ffffffffffff"), "E00");
+    }
+
+    #[test]
+    fn step_and_continue() {
+        let (mut stub, _client) = stub();
+        let mut target = FakeTarget::new();
+
+        assert_eq!(reply(&mut stub, &mut target, "?"), STOP_TRAP);
+        assert_eq!(reply(&mut stub, &mut target, "s"), STOP_TRAP);
+        assert_eq!(target.pc(), 0x1004);
+        assert_eq!(reply(&mut stub, &mut target, "s1020"), STOP_TRAP);
+        assert_eq!(target.pc(), 0x1024);
+
+        // Continuing stops at the exception, which is reported as a trap.
+        assert_eq!(reply(&mut stub, &mut target, "c"), STOP_TRAP);
+        assert_eq!(target.pc(), EXCEPTION_ADDRESS);
+
+        assert_eq!(reply(&mut stub, &mut target, "c1000"), EXITED);
+        assert_eq!(target.steps, MAX_STEPS);
+    }
+
+    #[test]
+    fn breakpoints() {
+        let (mut stub, _client) = stub();
+        let mut target = FakeTarget::new();
+
+        assert_eq!(reply(&mut stub, &mut target, "Z0,1010,4"), "OK");
+        assert_eq!(reply(&mut stub, &mut target, "Z1,1020,4"), "OK");
+
+        assert_eq!(reply(&mut stub, &mut target, "c"), STOP_TRAP);
+        assert_eq!(target.pc(), 0x1010);
+        assert_eq!(reply(&mut stub, &mut target, "c"), STOP_TRAP);
+        assert_eq!(target.pc(), 0x1020);
+
+        assert_eq!(reply(&mut stub, &mut target, "z0,1010,4"), "OK");
+        assert_eq!(reply(&mut stub, &mut target, "c1000"), STOP_TRAP);
+        assert_eq!(target.pc(), 0x1020);
+    }
+
+    #[test]
+    fn queries() {
+        let (mut stub, _client) = stub();
+        let mut target = FakeTarget::new();
+
+        assert_eq!(
+            reply(&mut stub, &mut target, "qSupported:swbreak+;hwbreak+"),
+            "PacketSize=4000;qXfer:features:read+"
+        );
+        assert_eq!(reply(&mut stub, &mut target, "qAttached"), "1");
+        assert_eq!(reply(&mut stub, &mut target, "Hg0"), "OK");
+        assert_eq!(reply(&mut stub, &mut target, "vMustReplyEmpty"), "");
+
+        let xml = target_xml();
+        let first = reply(
+            &mut stub,
+            &mut target,
+            "qXfer:features:read:target.xml:0,10",
+        );
+        assert_eq!(first, format!("m{}", &xml[..0x10]));
+        let rest = reply(
+            &mut stub,
+            &mut target,
+            "qXfer:features:read:target.xml:10,ffff",
+        );
+        assert_eq!(rest, format!("l{}", &xml[0x10..]));
+    }
+
+    #[test]
+    fn malformed_packets_get_an_error() {
+        let (mut stub, _client) = stub();
+        let mut target = FakeTarget::new();
+
+        assert_eq!(reply(&mut stub, &mut target, "Z0"), MALFORMED_PACKET);
+        assert_eq!(reply(&mut stub, &mut target, "mzz,4"), MALFORMED_PACKET);
+        assert_eq!(target.pc(), MEMORY_START);
+    }
+
+    #[test]
+    fn kill() {
+        let (mut stub, _client) = stub();
+        let mut target = FakeTarget::new();
+
+        assert!(matches!(
+            stub.handle(&mut target, "k").unwrap(),
+            Action::Kill
+        ));
+    }
+}
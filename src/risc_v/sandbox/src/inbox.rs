@@ -1,13 +1,42 @@
-use std::collections::LinkedList;
-use tezos_crypto_rs::hash::{BlockHash, ContractKt1Hash};
+use crate::michelson::Expr;
+use serde::Deserialize;
+use std::{collections::LinkedList, error::Error, fs, path::Path};
+use tezos_crypto_rs::hash::{BlockHash, ContractKt1Hash, ContractTz1Hash};
+use tezos_data_encoding::enc::BinWriter;
 use tezos_smart_rollup_encoding::{
-    inbox::{InboxMessage, InfoPerLevel, InternalInboxMessage, Transfer},
+    inbox::{InboxMessage, InfoPerLevel, InternalInboxMessage},
     michelson,
     public_key_hash::PublicKeyHash,
     smart_rollup::SmartRollupAddress,
     timestamp::Timestamp,
 };
 
+/// Message in an inbox file
+///
+/// The format matches the one of the WASM debugger: a transfer only requires
+/// its Michelson payload, the other fields default to the zero addresses
+/// (respectively the rollup address for the destination). External and
+/// serialised messages are hex-encoded.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum FileMessage {
+    Transfer {
+        payload: String,
+        sender: Option<String>,
+        source: Option<String>,
+        destination: Option<String>,
+    },
+    External {
+        external: String,
+    },
+    Serialized {
+        serialized: String,
+    },
+}
+
+/// Contents of an inbox file: the messages of each level
+type InboxFile = Vec<Vec<FileMessage>>;
+
 /// Inbox builder
 pub struct InboxBuilder {
     levels: LinkedList<LinkedList<Vec<u8>>>,
@@ -84,25 +113,92 @@ impl InboxBuilder {
     }
 
     /// Inject a transfer notification.
-    pub fn insert_transfer<E: michelson::Michelson>(
+    pub fn insert_transfer(
         &mut self,
         sender: ContractKt1Hash,
         source: PublicKeyHash,
         destination: SmartRollupAddress,
-        payload: E,
-    ) -> &mut Self {
-        let msg = InboxMessage::Internal(InternalInboxMessage::Transfer(Transfer {
-            payload,
-            sender,
-            source,
-            destination,
-        }));
+        payload: &Expr,
+    ) -> Result<&mut Self, Box<dyn Error>> {
+        // The Michelson payload is encoded by hand as it may be any data
+        // expression. The tags identify internal messages and transfers.
+        let mut data = vec![0, 0];
+        payload.encode(&mut data);
+        sender.bin_write(&mut data)?;
+        source.bin_write(&mut data)?;
+        destination.bin_write(&mut data)?;
 
-        let mut data = Vec::new();
-        msg.serialize(&mut data)
-            .expect("Failed to serialise transfer");
+        Ok(self.insert_raw(data))
+    }
+
+    /// Inject the messages of an inbox file, given in either JSON or YAML
+    /// format. Messages of subsequent levels start new levels.
+    pub fn load_file(
+        &mut self,
+        path: impl AsRef<Path>,
+        rollup: &SmartRollupAddress,
+    ) -> Result<&mut Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+
+        let levels: InboxFile = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&contents)?,
+            _ => serde_yaml::from_str(&contents)?,
+        };
+
+        for (index, messages) in levels.into_iter().enumerate() {
+            if index > 0 {
+                self.next_level();
+            }
 
-        self.insert_raw(data)
+            for message in messages {
+                self.insert_file_message(message, rollup).map_err(|err| {
+                    format!(
+                        "Invalid message at level {index} of {}: {err}",
+                        path.display()
+                    )
+                })?;
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Inject a message from an inbox file.
+    fn insert_file_message(
+        &mut self,
+        message: FileMessage,
+        rollup: &SmartRollupAddress,
+    ) -> Result<&mut Self, Box<dyn Error>> {
+        match message {
+            FileMessage::Transfer {
+                payload,
+                sender,
+                source,
+                destination,
+            } => {
+                let sender = match sender {
+                    Some(sender) => ContractKt1Hash::from_base58_check(&sender)?,
+                    None => ContractKt1Hash(vec![0; 20]),
+                };
+
+                let source = match source {
+                    Some(source) => PublicKeyHash::from_b58check(&source)?,
+                    None => PublicKeyHash::Ed25519(ContractTz1Hash(vec![0; 20])),
+                };
+
+                let destination = match destination {
+                    Some(destination) => SmartRollupAddress::from_b58check(&destination)?,
+                    None => rollup.clone(),
+                };
+
+                self.insert_transfer(sender, source, destination, &Expr::parse(&payload)?)
+            }
+
+            FileMessage::External { external } => Ok(self.insert_external(hex::decode(external)?)),
+
+            FileMessage::Serialized { serialized } => Ok(self.insert_raw(hex::decode(serialized)?)),
+        }
     }
 
     /// Inject a raw message.
@@ -164,8 +260,139 @@ impl Inbox {
         }
     }
 
+    /// Level of the message that was returned last.
+    pub fn level(&self) -> u32 {
+        self.current_level
+    }
+
     /// Count the number of nones that have been returned by the inbox so far.
     pub fn none_count(&self) -> usize {
         self.none_counter
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, path::PathBuf};
+    use tezos_smart_rollup_encoding::{
+        inbox::Transfer,
+        michelson::{MichelsonInt, MichelsonPair, MichelsonString},
+    };
+
+    const ROLLUP: &str = "sr1UNDWPUYVeomgG15wn5jSw689EJ4RNnVQa";
+    const SENDER: &str = "KT1EfTusMLoeCAAGd9MZJn5yKzFr6kJU5U91";
+    const SOURCE: &str = "tz1dJ21ejKD17t7HKcKkTPuwQphgcSiehTYi";
+
+    const INBOX_JSON: &str = r#"[
+  [
+    { "external": "0102" },
+    {
+      "payload": "Some 1",
+      "sender": "KT1EfTusMLoeCAAGd9MZJn5yKzFr6kJU5U91",
+      "source": "tz1dJ21ejKD17t7HKcKkTPuwQphgcSiehTYi"
+    }
+  ],
+  [
+    { "payload": "Unit" }
+  ]
+]"#;
+
+    const INBOX_YAML: &str = "
+- - external: '0102'
+  - payload: Some 1
+    sender: KT1EfTusMLoeCAAGd9MZJn5yKzFr6kJU5U91
+    source: tz1dJ21ejKD17t7HKcKkTPuwQphgcSiehTYi
+- - payload: Unit
+";
+
+    fn messages(builder: InboxBuilder) -> Vec<(u32, u32, Vec<u8>)> {
+        let mut inbox = builder.build();
+        std::iter::from_fn(|| inbox.next()).collect()
+    }
+
+    fn write_inbox_file(name: &str, contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("{}-{name}", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn load_file(path: &Path) -> InboxBuilder {
+        let rollup = SmartRollupAddress::from_b58check(ROLLUP).unwrap();
+        let mut builder = InboxBuilder::new();
+        builder.load_file(path, &rollup).unwrap();
+        fs::remove_file(path).unwrap();
+        builder
+    }
+
+    #[test]
+    fn transfer_matches_sdk_encoding() {
+        let rollup = SmartRollupAddress::from_b58check(ROLLUP).unwrap();
+        let sender = ContractKt1Hash::from_base58_check(SENDER).unwrap();
+        let source = PublicKeyHash::from_b58check(SOURCE).unwrap();
+
+        let mut builder = InboxBuilder::new();
+        builder
+            .insert_transfer(
+                sender.clone(),
+                source.clone(),
+                rollup.clone(),
+                &Expr::parse(r#"Pair 42 "foo""#).unwrap(),
+            )
+            .unwrap();
+
+        let transfer = InboxMessage::Internal(InternalInboxMessage::Transfer(Transfer {
+            payload: MichelsonPair(MichelsonInt::from(42), MichelsonString("foo".into())),
+            sender,
+            source,
+            destination: rollup,
+        }));
+        let mut expected = Vec::new();
+        transfer.serialize(&mut expected).unwrap();
+
+        // The transfer follows the start of level and info per level.
+        let messages = messages(builder);
+        assert_eq!((0, 2), (messages[2].0, messages[2].1));
+        assert_eq!(expected, messages[2].2);
+    }
+
+    #[test]
+    fn json_and_yaml_files_are_equivalent() {
+        let rollup = SmartRollupAddress::from_b58check(ROLLUP).unwrap();
+        let mut expected = InboxBuilder::new();
+        expected
+            .insert_external([1u8, 2])
+            .insert_transfer(
+                ContractKt1Hash::from_base58_check(SENDER).unwrap(),
+                PublicKeyHash::from_b58check(SOURCE).unwrap(),
+                rollup.clone(),
+                &Expr::parse("Some 1").unwrap(),
+            )
+            .unwrap()
+            .next_level()
+            .insert_transfer(
+                ContractKt1Hash(vec![0; 20]),
+                PublicKeyHash::Ed25519(ContractTz1Hash(vec![0; 20])),
+                rollup,
+                &Expr::parse("Unit").unwrap(),
+            )
+            .unwrap();
+        let expected = messages(expected);
+
+        let json = load_file(&write_inbox_file("inbox.json", INBOX_JSON));
+        assert_eq!(expected, messages(json));
+
+        let yaml = load_file(&write_inbox_file("inbox.yaml", INBOX_YAML));
+        assert_eq!(expected, messages(yaml));
+    }
+
+    #[test]
+    fn invalid_inbox_file() {
+        let rollup = SmartRollupAddress::from_b58check(ROLLUP).unwrap();
+        let path = write_inbox_file("invalid.json", r#"[[{ "payload": "Foo" }]]"#);
+
+        let mut builder = InboxBuilder::new();
+        assert!(builder.load_file(&path, &rollup).is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,24 +1,188 @@
+use goblin::elf::{header::ET_DYN, Elf};
 use kernel_loader::Memory;
-use rvemu::{emulator::Emulator, exception::Exception};
-use std::{error::Error, fs};
-use tezos_crypto_rs::hash::ContractKt1Hash;
-use tezos_smart_rollup_encoding::{
-    michelson::MichelsonUnit, public_key_hash::PublicKeyHash, smart_rollup::SmartRollupAddress,
+use rvemu::{
+    cpu::{AccessType, BYTE, WORD},
+    emulator::Emulator,
+    exception::Exception,
+    interrupt::Interrupt,
 };
+use std::{error::Error, fs};
+use tezos_smart_rollup_encoding::smart_rollup::SmartRollupAddress;
+use tezos_smart_rollup_mock::InMemoryStore;
 
 mod boot;
 mod cli;
 mod devicetree;
+mod gdb;
 mod inbox;
 mod input;
+mod michelson;
+mod preimages;
+mod profiler;
 mod rv;
 mod syscall;
 
+/// Number of entries in each table of the profile summary
+const PROFILE_SUMMARY_LENGTH: usize = 20;
+
 /// Convert a RISC-V exception into an error.
 pub fn exception_to_error(exc: Exception) -> Box<dyn Error> {
     format!("{:?}", exc).into()
 }
 
+/// Handler for environment calls
+type SyscallHandler = fn(&mut Emulator, &mut syscall::Rollup) -> Result<(), Box<dyn Error>>;
+
+/// Trap raised by an instruction, which has not been taken yet
+enum PendingTrap {
+    Exception(Exception),
+    Interrupt(Interrupt),
+}
+
+/// Emulated machine running a kernel against the rollup state
+struct Sandbox {
+    emu: Emulator,
+    rollup: syscall::Rollup,
+    handle_syscall: SyscallHandler,
+
+    /// Keep going after the inbox has been drained.
+    keep_going: bool,
+
+    /// Instruction-count profiler, if enabled
+    profiler: Option<profiler::Profiler>,
+
+    /// Trap reported by the last step, taken at the next one so that the
+    /// machine can be inspected at the faulting instruction in between.
+    pending_trap: Option<PendingTrap>,
+}
+
+impl Sandbox {
+    /// Whether the kernel should keep running
+    fn running(&self) -> bool {
+        self.rollup.inbox.none_count() < 2 || self.keep_going
+    }
+
+    /// Fetch the instruction at a virtual address. Compressed instructions
+    /// only occupy the lower half of the result.
+    fn fetch(&mut self, address: u64) -> Option<u32> {
+        let address = self
+            .emu
+            .cpu
+            .translate(address, AccessType::Instruction)
+            .ok()?;
+        let value = self.emu.cpu.bus.read(address, WORD).ok()?;
+        Some(value as u32)
+    }
+
+    /// Execute a single instruction. Exceptions which the sandbox does not
+    /// handle are reported before their trap is taken, which happens at the
+    /// next step instead. So is an instruction looping in place, as the
+    /// kernel is then stuck.
+    fn step(&mut self) -> Result<gdb::Step, Box<dyn Error>> {
+        match self.pending_trap.take() {
+            Some(PendingTrap::Exception(exception)) => {
+                exception.take_trap(&mut self.emu.cpu);
+                return Ok(gdb::Step::Executed);
+            }
+            Some(PendingTrap::Interrupt(interrupt)) => {
+                interrupt.take_trap(&mut self.emu.cpu);
+                return Ok(gdb::Step::Executed);
+            }
+            None => {}
+        }
+
+        // Address of the instruction being executed, the program counter is
+        // updated by the execution.
+        let pc = self.emu.cpu.pc;
+        let instr = match self.profiler {
+            Some(_) => self.fetch(pc),
+            None => None,
+        };
+
+        self.emu.cpu.devices_increment();
+
+        if let Some(interrupt) = self.emu.cpu.check_pending_interrupt() {
+            // We don't do anything with the devices at the moment. So we'll
+            // just stop if they magically come alive.
+            let description = format!("Interrupt {:?} at {:#x}", interrupt, pc);
+            self.pending_trap = Some(PendingTrap::Interrupt(interrupt));
+            return Ok(gdb::Step::Exception(description));
+        }
+
+        match self.emu.cpu.execute() {
+            Ok(_) => {}
+
+            Err(Exception::EnvironmentCallFromSMode | Exception::EnvironmentCallFromUMode) => {
+                (self.handle_syscall)(&mut self.emu, &mut self.rollup).map_err(
+                    |err| -> Box<dyn Error> {
+                        format!("Failed to handle environment call at {pc:x}: {}", err)
+                            .as_str()
+                            .into()
+                    },
+                )?;
+
+                // We need to update the program counter ourselves now.
+                // This is a recent change in behaviour in RVEmu.
+                self.emu.cpu.pc += 4;
+            }
+
+            Err(exception) => {
+                // Don't bother handling other exceptions. For now they're
+                // all fatal. The program counter still points to the
+                // faulting instruction.
+                let description = format!("Exception {:?} at {:#x}", exception, pc);
+                self.pending_trap = Some(PendingTrap::Exception(exception));
+                return Ok(gdb::Step::Exception(description));
+            }
+        }
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record(pc, instr.unwrap_or_default(), self.emu.cpu.pc);
+        }
+
+        if self.emu.cpu.pc == pc {
+            return Ok(gdb::Step::Exception(format!("Stuck at {:#x}", pc)));
+        }
+
+        Ok(gdb::Step::Executed)
+    }
+}
+
+impl gdb::Target for Sandbox {
+    fn read_register(&self, index: usize) -> u64 {
+        match index {
+            32 => self.emu.cpu.pc,
+            _ => self.emu.cpu.xregs.read(index as u64),
+        }
+    }
+
+    fn write_register(&mut self, index: usize, value: u64) {
+        match index {
+            32 => self.emu.cpu.pc = value,
+            _ => self.emu.cpu.xregs.write(index as u64, value),
+        }
+    }
+
+    fn read_byte(&mut self, address: u64) -> Option<u8> {
+        let address = self.emu.cpu.translate(address, AccessType::Load).ok()?;
+        let value = self.emu.cpu.bus.read(address, BYTE).ok()?;
+        Some(value as u8)
+    }
+
+    fn write_byte(&mut self, address: u64, value: u8) -> Option<()> {
+        let address = self.emu.cpu.translate(address, AccessType::Store).ok()?;
+        self.emu.cpu.bus.write_bytes(address, &[value]).ok()
+    }
+
+    fn step(&mut self) -> Result<gdb::Step, Box<dyn Error>> {
+        if !self.running() {
+            return Ok(gdb::Step::Exited);
+        }
+
+        Sandbox::step(self)
+    }
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     let cli = cli::parse();
 
@@ -29,10 +193,10 @@ fn main() -> Result<(), Box<dyn Error>> {
     let initrd_addr = input::configure_emulator(&contents, &mut emu)?;
 
     // Load the initial ramdisk to memory.
-    let initrd_info = cli
-        .initrd
-        .map(|initrd_path| -> Result<_, Box<dyn Error>> {
-            let initrd = fs::read(initrd_path)?;
+    let initrd = cli.initrd.as_ref().map(fs::read).transpose()?;
+    let initrd_info = initrd
+        .as_ref()
+        .map(|initrd| -> Result<_, Box<dyn Error>> {
             emu.cpu.bus.write_bytes(initrd_addr, initrd.as_slice())?;
             Ok(devicetree::InitialRamDisk {
                 start: initrd_addr,
@@ -60,28 +224,20 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     // Prepare inbox
     let mut inbox = inbox::InboxBuilder::new();
-    inbox
-        .insert_external(vec![1, 2, 3, 4])
-        .insert_external(vec![1, 4, 3, 2])
-        .next_level()
-        .insert_external(vec![1, 1])
-        .next_level()
-        .insert_external(vec![1, 2])
-        .next_level()
-        .insert_transfer(
-            ContractKt1Hash::from_base58_check("KT1EfTusMLoeCAAGd9MZJn5yKzFr6kJU5U91").unwrap(),
-            PublicKeyHash::from_b58check("tz1dJ21ejKD17t7HKcKkTPuwQphgcSiehTYi").unwrap(),
-            meta.address.clone(),
-            MichelsonUnit,
-        );
-    let mut inbox = inbox.build();
+    if let Some(inbox_file) = cli.inbox {
+        inbox.load_file(inbox_file, &meta.address)?;
+    }
+
+    let rollup = syscall::Rollup {
+        meta,
+        inbox: inbox.build(),
+        preimages: preimages::Preimages::new(cli.preimages_dir),
+        durable: InMemoryStore::default(),
+        outbox: Vec::new(),
+    };
 
     let handle_syscall = if cli.posix {
-        fn dummy(
-            emu: &mut Emulator,
-            _: &syscall::RollupMetadata,
-            _: &mut inbox::Inbox,
-        ) -> Result<(), Box<dyn Error>> {
+        fn dummy(emu: &mut Emulator, _: &mut syscall::Rollup) -> Result<(), Box<dyn Error>> {
             syscall::handle_posix(emu)
         }
         dummy
@@ -89,56 +245,58 @@ fn main() -> Result<(), Box<dyn Error>> {
         syscall::handle_sbi
     };
 
-    let mut prev_pc = emu.cpu.pc;
-
-    while inbox.none_count() < 2 || cli.keep_going {
-        emu.cpu.devices_increment();
-
-        if let Some(interrupt) = emu.cpu.check_pending_interrupt() {
-            interrupt.take_trap(&mut emu.cpu);
-
-            // We don't do anything with the devices at the moment. So we'll
-            // just panic if they magically come alive.
-            panic!("Interrupt {:?}", interrupt);
-        }
+    // Symbolise the profile using the same load address as the kernel.
+    let profiler = cli
+        .profile
+        .as_ref()
+        .map(|_| -> Result<_, Box<dyn Error>> {
+            let symbols = kernel_loader::function_symbols(&contents, rvemu::bus::DRAM_BASE)?;
+            let mut profiler = profiler::Profiler::new(symbols);
 
-        emu.cpu
-            .execute()
-            .map(|_| ())
-            .or_else(|exception| -> Result<(), Box<dyn Error>> {
-                match exception {
-                    Exception::EnvironmentCallFromSMode | Exception::EnvironmentCallFromUMode => {
-                        handle_syscall(&mut emu, &meta, &mut inbox).map_err(
-                            |err| -> Box<dyn Error> {
-                                format!("Failed to handle environment call at {prev_pc:x}: {}", err)
-                                    .as_str()
-                                    .into()
-                            },
-                        )?;
-
-                        // We need to update the program counter ourselves now.
-                        // This is a recent change in behaviour in RVEmu.
-                        emu.cpu.pc += 4;
-
-                        Ok(())
+            // An ELF initial ramdisk is the kernel started by the boot loader
+            // given as input, e.g. with HermitOS.
+            if let Some(initrd) = &initrd {
+                if let Ok(elf) = Elf::parse(initrd) {
+                    let symbols = kernel_loader::function_symbols(initrd, 0)?;
+                    if elf.header.e_type == ET_DYN {
+                        let loader = rvemu::bus::DRAM_BASE..initrd_addr;
+                        profiler.defer_symbols(loader, elf.entry, symbols);
+                    } else {
+                        profiler.add_symbols(symbols);
                     }
+                }
+            }
 
-                    _ => {
-                        let trap = exception.take_trap(&mut emu.cpu);
+            Ok(profiler)
+        })
+        .transpose()?;
 
-                        // Don't bother handling other exceptions. For now they're
-                        // all fatal.
-                        panic!("Exception {:?} at {:#x}: {:?}", exception, prev_pc, trap)
-                    }
-                }
-            })?;
+    let mut sandbox = Sandbox {
+        emu,
+        rollup,
+        handle_syscall,
+        keep_going: cli.keep_going,
+        profiler,
+        pending_trap: None,
+    };
 
-        // If the program loops in place we assume it is stuck.
-        if prev_pc == emu.cpu.pc {
-            panic!("Stuck at {:#x}", prev_pc);
+    if let Some(port) = cli.gdb {
+        gdb::GdbStub::listen(port)?.run(&mut sandbox)?;
+    } else {
+        while sandbox.running() {
+            if let gdb::Step::Exception(description) = sandbox.step()? {
+                return Err(description.into());
+            }
         }
+    }
+
+    for (level, message) in &sandbox.rollup.outbox {
+        println!("Outbox message at level {level}: {}", hex::encode(message));
+    }
 
-        prev_pc = emu.cpu.pc;
+    if let (Some(profiler), Some(path)) = (&mut sandbox.profiler, &cli.profile) {
+        profiler.write_folded(path)?;
+        eprint!("{}", profiler.summary(PROFILE_SUMMARY_LENGTH));
     }
 
     Ok(())
@@ -0,0 +1,426 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Michelson data expressions in their textual form (e.g. `Pair 1 "foo"`), as
+//! found in the payloads of transfers in inbox files, and their binary
+//! Micheline encoding.
+//!
+//! Only data constructors are supported, i.e. no instructions.
+
+use std::{error::Error, iter::Peekable, str::CharIndices};
+
+/// Data constructors and their primitive tags in the binary encoding
+const PRIMITIVES: [(&str, u8); 9] = [
+    ("False", 3),
+    ("Elt", 4),
+    ("Left", 5),
+    ("None", 6),
+    ("Pair", 7),
+    ("Right", 8),
+    ("Some", 9),
+    ("True", 10),
+    ("Unit", 11),
+];
+
+/// Michelson data expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    /// Integer, represented by its sign and its decimal digits
+    Int {
+        negative: bool,
+        digits: Vec<u8>,
+    },
+    String(String),
+    Bytes(Vec<u8>),
+    Seq(Vec<Expr>),
+    Prim {
+        tag: u8,
+        args: Vec<Expr>,
+        annots: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Int { negative: bool, digits: Vec<u8> },
+    String(String),
+    Bytes(Vec<u8>),
+    Ident(String),
+    Annot(String),
+    Open,
+    Close,
+    OpenBrace,
+    CloseBrace,
+    Semicolon,
+}
+
+struct Lexer<'a> {
+    source: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            chars: source.char_indices().peekable(),
+        }
+    }
+
+    /// Consume characters while `pred` holds and return the source from
+    /// `start` up to the next character.
+    fn take_while(&mut self, start: usize, pred: impl Fn(char) -> bool) -> &'a str {
+        while self.chars.next_if(|(_, c)| pred(*c)).is_some() {}
+
+        let end = self
+            .chars
+            .peek()
+            .map_or(self.source.len(), |(index, _)| *index);
+        &self.source[start..end]
+    }
+
+    fn string(&mut self) -> Result<Token, Box<dyn Error>> {
+        let mut value = String::new();
+
+        loop {
+            match self.chars.next().map(|(_, c)| c) {
+                Some('"') => return Ok(Token::String(value)),
+                Some('\\') => value.push(match self.chars.next().map(|(_, c)| c) {
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some('t') => '\t',
+                    Some('b') => '\x08',
+                    c => return Err(format!("Invalid escape sequence \\{c:?}").into()),
+                }),
+                Some(c) => value.push(c),
+                None => return Err("Unterminated string literal".into()),
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token>, Box<dyn Error>> {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+
+        let Some((start, c)) = self.chars.next() else {
+            return Ok(None);
+        };
+
+        let token = match c {
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '{' => Token::OpenBrace,
+            '}' => Token::CloseBrace,
+            ';' => Token::Semicolon,
+            '"' => self.string()?,
+
+            '0' if self.chars.next_if(|(_, c)| *c == 'x').is_some() => {
+                let hex = self.take_while(start + 2, |c| c.is_ascii_hexdigit());
+                Token::Bytes(hex::decode(hex)?)
+            }
+
+            '-' | '0'..='9' => {
+                let negative = c == '-';
+                let start = if negative { start + 1 } else { start };
+                let digits = self.take_while(start, |c| c.is_ascii_digit());
+
+                if digits.is_empty() {
+                    return Err("Expected digits after '-'".into());
+                }
+
+                Token::Int {
+                    negative,
+                    digits: digits.bytes().map(|d| d - b'0').collect(),
+                }
+            }
+
+            '%' | '@' | ':' => {
+                let annot = self.take_while(start, |c| {
+                    c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '%' | '@')
+                });
+                Token::Annot(annot.to_owned())
+            }
+
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let ident = self.take_while(start, |c| c.is_ascii_alphanumeric() || c == '_');
+                Token::Ident(ident.to_owned())
+            }
+
+            c => return Err(format!("Unexpected character {c:?} at offset {start}").into()),
+        };
+
+        Ok(Some(token))
+    }
+}
+
+struct Parser {
+    tokens: Peekable<std::vec::IntoIter<Token>>,
+}
+
+impl Parser {
+    fn expect(&mut self, expected: Token) -> Result<(), Box<dyn Error>> {
+        match self.tokens.next() {
+            Some(token) if token == expected => Ok(()),
+            token => Err(format!("Expected {expected:?}, found {token:?}").into()),
+        }
+    }
+
+    fn primitive(name: &str) -> Result<u8, Box<dyn Error>> {
+        PRIMITIVES
+            .iter()
+            .find_map(|(prim, tag)| (*prim == name).then_some(*tag))
+            .ok_or_else(|| format!("Unsupported primitive {name}").into())
+    }
+
+    /// Parse an expression. Primitives only take annotations and arguments
+    /// if `applied` is set, otherwise they must be parenthesised.
+    fn expr(&mut self, applied: bool) -> Result<Expr, Box<dyn Error>> {
+        let expr = match self.tokens.next() {
+            Some(Token::Int { negative, digits }) => Expr::Int { negative, digits },
+            Some(Token::String(value)) => Expr::String(value),
+            Some(Token::Bytes(value)) => Expr::Bytes(value),
+
+            Some(Token::Open) => {
+                let expr = self.expr(true)?;
+                self.expect(Token::Close)?;
+                expr
+            }
+
+            Some(Token::OpenBrace) => {
+                let mut items = Vec::new();
+
+                while self.tokens.peek() != Some(&Token::CloseBrace) {
+                    items.push(self.expr(true)?);
+
+                    if self.tokens.next_if_eq(&Token::Semicolon).is_none() {
+                        break;
+                    }
+                }
+
+                self.expect(Token::CloseBrace)?;
+                Expr::Seq(items)
+            }
+
+            Some(Token::Ident(name)) => {
+                let tag = Self::primitive(&name)?;
+                let mut args = Vec::new();
+                let mut annots = Vec::new();
+
+                if applied {
+                    while let Some(Token::Annot(annot)) = self
+                        .tokens
+                        .next_if(|token| matches!(token, Token::Annot(_)))
+                    {
+                        annots.push(annot);
+                    }
+                }
+
+                while applied
+                    && !matches!(
+                        self.tokens.peek(),
+                        None | Some(Token::Close | Token::CloseBrace | Token::Semicolon)
+                    )
+                {
+                    args.push(self.expr(false)?);
+                }
+
+                Expr::Prim { tag, args, annots }
+            }
+
+            token => return Err(format!("Unexpected token {token:?}").into()),
+        };
+
+        Ok(expr)
+    }
+}
+
+impl Expr {
+    /// Parse a Michelson data expression.
+    pub fn parse(source: &str) -> Result<Self, Box<dyn Error>> {
+        let mut lexer = Lexer::new(source);
+        let mut tokens = Vec::new();
+        while let Some(token) = lexer.next_token()? {
+            tokens.push(token);
+        }
+
+        let mut parser = Parser {
+            tokens: tokens.into_iter().peekable(),
+        };
+        let expr = parser.expr(true)?;
+
+        match parser.tokens.next() {
+            None => Ok(expr),
+            Some(token) => Err(format!("Unexpected trailing token {token:?}").into()),
+        }
+    }
+
+    /// Append the binary Micheline encoding of the expression to `out`.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Expr::Int { negative, digits } => {
+                out.push(0);
+                encode_zarith(*negative, digits.clone(), out);
+            }
+
+            Expr::String(value) => {
+                out.push(1);
+                encode_dynamic(value.as_bytes(), out);
+            }
+
+            Expr::Bytes(value) => {
+                out.push(10);
+                encode_dynamic(value, out);
+            }
+
+            Expr::Seq(items) => {
+                let mut content = Vec::new();
+                items.iter().for_each(|item| item.encode(&mut content));
+
+                out.push(2);
+                encode_dynamic(&content, out);
+            }
+
+            Expr::Prim { tag, args, annots } if args.len() <= 2 => {
+                // Primitives with up to 2 arguments have dedicated encodings,
+                // with and without annotations.
+                let annotated = !annots.is_empty();
+                out.push(3 + 2 * args.len() as u8 + annotated as u8);
+                out.push(*tag);
+                args.iter().for_each(|arg| arg.encode(out));
+
+                if annotated {
+                    encode_dynamic(annots.join(" ").as_bytes(), out);
+                }
+            }
+
+            Expr::Prim { tag, args, annots } => {
+                let mut content = Vec::new();
+                args.iter().for_each(|arg| arg.encode(&mut content));
+
+                out.push(9);
+                out.push(*tag);
+                encode_dynamic(&content, out);
+                encode_dynamic(annots.join(" ").as_bytes(), out);
+            }
+        }
+    }
+}
+
+/// Append `data` prefixed by its length.
+fn encode_dynamic(data: &[u8], out: &mut Vec<u8>) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(data);
+}
+
+/// Divide a number given by its decimal digits by `divisor`, returning the
+/// remainder.
+fn div_rem(digits: &mut Vec<u8>, divisor: u32) -> u8 {
+    let mut rem = 0;
+
+    for digit in digits.iter_mut() {
+        let value = rem * 10 + *digit as u32;
+        *digit = (value / divisor) as u8;
+        rem = value % divisor;
+    }
+
+    let zeros = digits.iter().take_while(|d| **d == 0).count();
+    digits.drain(..zeros);
+
+    rem as u8
+}
+
+/// Append the Zarith encoding of an integer: little-endian groups of 7 bits
+/// with a continuation bit, where the first group has only 6 bits as it also
+/// holds the sign.
+fn encode_zarith(negative: bool, mut digits: Vec<u8>, out: &mut Vec<u8>) {
+    let mut byte = div_rem(&mut digits, 64);
+    let negative = negative && (byte != 0 || !digits.is_empty());
+
+    if negative {
+        byte |= 0x40;
+    }
+
+    while !digits.is_empty() {
+        out.push(byte | 0x80);
+        byte = div_rem(&mut digits, 128);
+    }
+
+    out.push(byte);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(source: &str) -> String {
+        let mut out = Vec::new();
+        Expr::parse(source).unwrap().encode(&mut out);
+        hex::encode(out)
+    }
+
+    #[test]
+    fn zarith() {
+        assert_eq!(encode("0"), "0000");
+        assert_eq!(encode("-0"), "0000");
+        assert_eq!(encode("1"), "0001");
+        assert_eq!(encode("-1"), "0041");
+        assert_eq!(encode("63"), "003f");
+        assert_eq!(encode("64"), "008001");
+        assert_eq!(encode("-64"), "00c001");
+        assert_eq!(encode("1000"), "00a80f");
+        assert_eq!(encode("-1000"), "00e80f");
+        assert_eq!(encode("123456789"), "0095b4de75");
+
+        // 2^64 does not fit in a machine integer.
+        assert_eq!(encode("18446744073709551616"), "0080808080808080808004");
+    }
+
+    #[test]
+    fn strings_and_bytes() {
+        assert_eq!(encode(r#""""#), "0100000000");
+        assert_eq!(encode(r#""foo""#), "0100000003666f6f");
+        assert_eq!(encode(r#""a\"b\n""#), "01000000046122620a");
+        assert_eq!(encode("0x"), "0a00000000");
+        assert_eq!(encode("0xabCD"), "0a00000002abcd");
+    }
+
+    #[test]
+    fn sequences() {
+        assert_eq!(encode("{}"), "0200000000");
+        assert_eq!(encode("{ 1 ; 2 }"), "020000000400010002");
+        assert_eq!(encode("{ 1 ; 2 ; }"), "020000000400010002");
+        assert_eq!(encode("{ {} }"), "02000000050200000000");
+    }
+
+    #[test]
+    fn primitives() {
+        assert_eq!(encode("Unit"), "030b");
+        assert_eq!(encode("Some 1"), "05090001");
+        assert_eq!(encode(r#"Pair 1 "foo""#), "070700010100000003666f6f");
+        assert_eq!(encode("Left (Right True)"), "05050508030a");
+        assert_eq!(encode("Pair 1 2 3"), "09070000000600010002000300000000");
+    }
+
+    #[test]
+    fn annotated_primitives() {
+        assert_eq!(encode("Unit %a"), "040b000000022561");
+        assert_eq!(encode("Some @v 1"), "06090001000000024076");
+        assert_eq!(encode("Pair %a :t 1 2"), "080700010002000000052561203a74");
+        assert_eq!(
+            encode("Pair %a 1 2 3"),
+            "090700000006000100020003000000022561"
+        );
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        assert!(Expr::parse("Foo").is_err());
+        assert!(Expr::parse("-").is_err());
+        assert!(Expr::parse(r#""foo"#).is_err());
+        assert!(Expr::parse("Pair 1 2)").is_err());
+        assert!(Expr::parse("{ 1 ; 2").is_err());
+        assert!(Expr::parse("Pair (Unit) %a").is_err());
+    }
+}
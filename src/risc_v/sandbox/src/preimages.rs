@@ -0,0 +1,32 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+use std::{error::Error, fs, path::PathBuf};
+
+/// Preimages available to the kernel through reveals
+pub struct Preimages {
+    /// Directory containing a file for each preimage, named after the
+    /// hex-encoded hash of that preimage
+    directory: Option<PathBuf>,
+}
+
+impl Preimages {
+    /// Look up preimages in the given directory, if any.
+    pub fn new(directory: Option<PathBuf>) -> Self {
+        Self { directory }
+    }
+
+    /// Retrieve the preimage of a reveal hash.
+    pub fn get(&self, hash: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let name = hex::encode(hash);
+
+        let Some(directory) = &self.directory else {
+            return Err(format!("No preimage directory to look up {name}").into());
+        };
+
+        let path = directory.join(&name);
+        fs::read(&path)
+            .map_err(|err| format!("Failed to read preimage {}: {err}", path.display()).into())
+    }
+}
@@ -0,0 +1,479 @@
+// SPDX-FileCopyrightText: 2024 TriliTech <contact@trili.tech>
+//
+// SPDX-License-Identifier: MIT
+
+//! Instruction-count profiler
+//!
+//! Every executed instruction is counted against its address and against the
+//! call stack it ran in. Call stacks are reconstructed from the `jal`/`jalr`
+//! instructions following the standard calling convention: jumps which link
+//! the return address register are calls, jumps through it are returns.
+//!
+//! The report is written in the folded stack format, which flamegraph tools
+//! such as `flamegraph.pl` or `inferno-flamegraph` consume directly. Rust
+//! symbols are demangled so that the frames are readable.
+
+use kernel_loader::FunctionSymbol;
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs::File,
+    io::{self, BufWriter, Write},
+    ops::Range,
+    path::Path,
+};
+
+/// Name of the frame for code outside any known function
+const UNKNOWN_FRAME: &str = "[unknown]";
+
+/// Human readable name of a (possibly mangled) Rust symbol, without its hash.
+/// Other symbols are left untouched.
+fn demangle(name: &str) -> String {
+    format!("{:#}", rustc_demangle::demangle(name))
+}
+
+/// Function a frame belongs to, as an index into the symbol table
+type Frame = Option<usize>;
+
+/// Effect of an instruction on the call stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Jump {
+    Call,
+    Return,
+}
+
+/// Registers used to hold return addresses: `ra` and `t0`
+fn is_link(register: u32) -> bool {
+    register == 1 || register == 5
+}
+
+/// Decode the effect of an instruction (which may be compressed) on the call
+/// stack.
+fn classify(instr: u32) -> Option<Jump> {
+    if instr & 0b11 != 0b11 {
+        let quadrant = instr & 0b11;
+        let funct4 = (instr >> 12) & 0b1111;
+        let rs1 = (instr >> 7) & 0b11111;
+        let rs2 = (instr >> 2) & 0b11111;
+
+        return match (quadrant, funct4) {
+            // c.jr
+            (0b10, 0b1000) if rs2 == 0 && is_link(rs1) => Some(Jump::Return),
+            // c.jalr
+            (0b10, 0b1001) if rs2 == 0 && rs1 != 0 => Some(Jump::Call),
+            _ => None,
+        };
+    }
+
+    let opcode = instr & 0b111_1111;
+    let rd = (instr >> 7) & 0b11111;
+    let rs1 = (instr >> 15) & 0b11111;
+
+    match opcode {
+        // jal
+        0b110_1111 if is_link(rd) => Some(Jump::Call),
+        // jalr
+        0b110_0111 if is_link(rd) => Some(Jump::Call),
+        0b110_0111 if rd == 0 && is_link(rs1) => Some(Jump::Return),
+        _ => None,
+    }
+}
+
+/// Node of the call tree
+struct Node {
+    parent: usize,
+    frame: Frame,
+    children: HashMap<Frame, usize>,
+
+    /// Instructions executed in this exact call stack
+    count: u64,
+}
+
+/// Index of the root of the call tree, which has no frame of its own
+const ROOT: usize = 0;
+
+/// Symbols of a relocatable kernel loaded at runtime, e.g. by a boot
+/// loader, at an address which is only known once it starts executing
+struct DeferredSymbols {
+    /// Code running before the kernel, e.g. the boot loader
+    loader: Range<u64>,
+
+    /// Entry point of the kernel, relative to its load address
+    entry: u64,
+
+    /// Function symbols, relative to the load address
+    symbols: Vec<FunctionSymbol>,
+}
+
+/// Profiler counting executed instructions
+pub struct Profiler {
+    /// Function symbols, frames refer to them by index
+    symbols: Vec<FunctionSymbol>,
+
+    /// Indices of the function symbols, sorted by address
+    by_address: Vec<usize>,
+
+    /// Symbols added once the kernel they belong to starts executing
+    deferred: Option<DeferredSymbols>,
+
+    /// Call tree, indexed by node
+    nodes: Vec<Node>,
+
+    /// Node of the current call stack
+    current: usize,
+
+    /// Address range and frame of the last looked up function
+    cached: (Range<u64>, Frame),
+
+    /// Instructions executed per address
+    pc_counts: HashMap<u64, u64>,
+
+    /// Instructions executed in total
+    total: u64,
+}
+
+impl Profiler {
+    /// Create a profiler which resolves addresses using the given symbols.
+    pub fn new(symbols: Vec<FunctionSymbol>) -> Self {
+        let mut profiler = Self {
+            symbols: Vec::new(),
+            by_address: Vec::new(),
+            deferred: None,
+            nodes: vec![Node {
+                parent: ROOT,
+                frame: None,
+                children: HashMap::new(),
+                count: 0,
+            }],
+            current: ROOT,
+            cached: (0..0, None),
+            pc_counts: HashMap::new(),
+            total: 0,
+        };
+        profiler.add_symbols(symbols);
+        profiler
+    }
+
+    /// Resolve addresses using additional symbols, e.g. those of a kernel
+    /// loaded by the profiled boot loader.
+    pub fn add_symbols(&mut self, symbols: Vec<FunctionSymbol>) {
+        self.symbols
+            .extend(symbols.into_iter().map(|symbol| FunctionSymbol {
+                name: demangle(&symbol.name),
+                ..symbol
+            }));
+
+        let mut by_address: Vec<usize> = (0..self.symbols.len()).collect();
+        by_address.sort_by_key(|index| self.symbols[*index].address);
+        self.by_address = by_address;
+        self.cached = (0..0, None);
+    }
+
+    /// Add the symbols of a relocatable kernel, relative to its load address,
+    /// once it starts executing. The kernel is considered started when code
+    /// outside the `loader` range runs for the first time, which must be the
+    /// kernel `entry` point.
+    pub fn defer_symbols(&mut self, loader: Range<u64>, entry: u64, symbols: Vec<FunctionSymbol>) {
+        self.deferred = Some(DeferredSymbols {
+            loader,
+            entry,
+            symbols,
+        });
+    }
+
+    /// Find the function containing an address. Functions of unknown size
+    /// extend up to the next symbol.
+    fn lookup(&mut self, address: u64) -> Frame {
+        if self.cached.0.contains(&address) {
+            return self.cached.1;
+        }
+
+        let position = self
+            .by_address
+            .partition_point(|index| self.symbols[*index].address <= address)
+            .checked_sub(1);
+
+        let found = position.and_then(|position| {
+            let index = self.by_address[position];
+            let symbol = &self.symbols[index];
+            let end = match symbol.size {
+                0 => self
+                    .by_address
+                    .get(position + 1)
+                    .map_or(u64::MAX, |next| self.symbols[*next].address),
+                size => symbol.address + size,
+            };
+            (address < end).then_some((symbol.address..end, Some(index)))
+        });
+
+        let (range, frame) = found.unwrap_or((address..address + 1, None));
+        self.cached = (range, frame);
+        frame
+    }
+
+    /// Node for calling `frame` from the given node
+    fn child(&mut self, parent: usize, frame: Frame) -> usize {
+        if let Some(child) = self.nodes[parent].children.get(&frame) {
+            return *child;
+        }
+
+        let child = self.nodes.len();
+        self.nodes.push(Node {
+            parent,
+            frame,
+            children: HashMap::new(),
+            count: 0,
+        });
+        self.nodes[parent].children.insert(frame, child);
+        child
+    }
+
+    /// Record the execution of instruction `instr` at `pc`, after which the
+    /// machine continued at `next_pc`.
+    pub fn record(&mut self, pc: u64, instr: u32, next_pc: u64) {
+        if let Some(deferred) = &self.deferred {
+            if !deferred.loader.contains(&pc) {
+                let base = pc.wrapping_sub(deferred.entry);
+                let symbols = self
+                    .deferred
+                    .take()
+                    .map(|deferred| deferred.symbols)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|symbol| FunctionSymbol {
+                        address: symbol.address.wrapping_add(base),
+                        ..symbol
+                    })
+                    .collect();
+                self.add_symbols(symbols);
+            }
+        }
+
+        // Jumps that are neither calls nor returns (e.g. tail calls) as well
+        // as traps move to another function without changing the stack depth.
+        let frame = self.lookup(pc);
+        if self.current == ROOT || self.nodes[self.current].frame != frame {
+            let parent = self.nodes[self.current].parent;
+            self.current = self.child(parent, frame);
+        }
+
+        self.nodes[self.current].count += 1;
+        *self.pc_counts.entry(pc).or_default() += 1;
+        self.total += 1;
+
+        match classify(instr) {
+            Some(Jump::Call) => {
+                let callee = self.lookup(next_pc);
+                self.current = self.child(self.current, callee);
+            }
+            Some(Jump::Return) => self.current = self.nodes[self.current].parent,
+            None => {}
+        }
+    }
+
+    fn frame_name(&self, frame: Frame) -> &str {
+        frame.map_or(UNKNOWN_FRAME, |index| self.symbols[index].name.as_str())
+    }
+
+    /// Frames of the call stack ending at the given node, outermost first
+    fn stack(&self, mut node: usize) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        while node != ROOT {
+            frames.push(self.nodes[node].frame);
+            node = self.nodes[node].parent;
+        }
+        frames.reverse();
+        frames
+    }
+
+    /// Instruction counts per call stack in the folded stack format: one
+    /// `outer;...;inner count` line per stack.
+    fn folded(&self) -> impl Iterator<Item = String> + '_ {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, data)| data.count > 0)
+            .map(|(node, data)| {
+                let stack: Vec<&str> = self
+                    .stack(node)
+                    .into_iter()
+                    .map(|frame| self.frame_name(frame))
+                    .collect();
+                format!("{} {}", stack.join(";"), data.count)
+            })
+    }
+
+    /// Write the instruction counts per call stack in the folded stack
+    /// format.
+    pub fn write_folded(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+
+        for line in self.folded() {
+            writeln!(out, "{line}")?;
+        }
+
+        out.flush()
+    }
+
+    /// Summarise the `top` functions with the most instructions executed in
+    /// their own code ("self"), alongside the instructions executed while
+    /// they were on the call stack ("total"), and the `top` hottest
+    /// addresses.
+    pub fn summary(&mut self, top: usize) -> String {
+        let mut self_counts: HashMap<Frame, u64> = HashMap::new();
+        let pc_counts: Vec<(u64, u64)> = self.pc_counts.iter().map(|(k, v)| (*k, *v)).collect();
+        for (pc, count) in &pc_counts {
+            *self_counts.entry(self.lookup(*pc)).or_default() += count;
+        }
+
+        let mut total_counts: HashMap<Frame, u64> = HashMap::new();
+        for (node, data) in self.nodes.iter().enumerate() {
+            let mut frames = self.stack(node);
+            frames.sort_unstable();
+            frames.dedup();
+
+            for frame in frames {
+                *total_counts.entry(frame).or_default() += data.count;
+            }
+        }
+
+        let executed = self.total;
+        let percent = |count: u64| 100.0 * count as f64 / executed.max(1) as f64;
+        let mut out = format!("Executed {executed} instructions\n\n");
+
+        let mut functions: Vec<(Frame, u64)> = self_counts.into_iter().collect();
+        functions.sort_by(|a, b| b.1.cmp(&a.1));
+
+        let _ = writeln!(
+            out,
+            "{:>14} {:>7} {:>14} {:>7}  function",
+            "self", "", "total", ""
+        );
+        for (frame, count) in functions.into_iter().take(top) {
+            let total = total_counts.get(&frame).copied().unwrap_or_default();
+            let _ = writeln!(
+                out,
+                "{count:>14} {:>6.2}% {total:>14} {:>6.2}%  {}",
+                percent(count),
+                percent(total),
+                self.frame_name(frame)
+            );
+        }
+
+        let mut addresses = pc_counts;
+        addresses.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        let _ = writeln!(out, "\n{:>14} {:>7}  address", "count", "");
+        for (pc, count) in addresses.into_iter().take(top) {
+            let location = match self.lookup(pc) {
+                Some(index) => format!(
+                    "{}+{:#x}",
+                    self.symbols[index].name,
+                    pc - self.symbols[index].address
+                ),
+                None => UNKNOWN_FRAME.to_owned(),
+            };
+            let _ = writeln!(
+                out,
+                "{count:>14} {:>6.2}%  {pc:#x} {location}",
+                percent(count)
+            );
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOP: u32 = 0x0000_0013;
+    const CALL: u32 = 0x0000_00ef; // jal ra, 0
+    const TAIL: u32 = 0x0000_006f; // jal zero, 0
+    const RET: u32 = 0x0000_8067; // jalr zero, 0(ra)
+
+    fn symbol(name: &str, address: u64, size: u64) -> FunctionSymbol {
+        FunctionSymbol {
+            name: name.to_owned(),
+            address,
+            size,
+        }
+    }
+
+    #[test]
+    fn classify_jumps() {
+        assert_eq!(classify(CALL), Some(Jump::Call));
+        assert_eq!(classify(RET), Some(Jump::Return));
+        assert_eq!(classify(TAIL), None);
+        assert_eq!(classify(NOP), None);
+        // jalr t0, 0(a5)
+        assert_eq!(classify(0x0007_82e7), Some(Jump::Call));
+        // c.jr ra
+        assert_eq!(classify(0x8082), Some(Jump::Return));
+        // c.jalr a5
+        assert_eq!(classify(0x9782), Some(Jump::Call));
+        // c.jr a5
+        assert_eq!(classify(0x8782), None);
+    }
+
+    #[test]
+    fn record_call_stacks() {
+        let mut profiler = Profiler::new(vec![symbol("f", 0x200, 0), symbol("main", 0x100, 0x20)]);
+
+        profiler.record(0x100, NOP, 0x104);
+        profiler.record(0x104, CALL, 0x200);
+        profiler.record(0x200, NOP, 0x204);
+        profiler.record(0x204, RET, 0x108);
+        profiler.record(0x108, TAIL, 0x50);
+        profiler.record(0x50, NOP, 0x54);
+
+        let folded: Vec<String> = profiler.folded().collect();
+        assert_eq!(folded, ["main 3", "main;f 2", "[unknown] 1"]);
+    }
+
+    #[test]
+    fn deferred_symbols() {
+        let mut profiler = Profiler::new(vec![symbol("loader", 0x100, 0x20)]);
+        profiler.defer_symbols(0x100..0x120, 0x10, vec![symbol("kernel", 0, 0x40)]);
+
+        profiler.record(0x100, TAIL, 0x1010);
+        profiler.record(0x1010, NOP, 0x1014);
+
+        let folded: Vec<String> = profiler.folded().collect();
+        assert_eq!(folded, ["loader 1", "kernel 1"]);
+    }
+
+    #[test]
+    fn demangle_symbols() {
+        let mut profiler = Profiler::new(vec![
+            symbol("_ZN6kernel4main17h0123456789abcdefE", 0x100, 0x20),
+            symbol("memcpy", 0x200, 0x20),
+        ]);
+
+        profiler.record(0x100, CALL, 0x200);
+        profiler.record(0x200, NOP, 0x204);
+
+        let folded: Vec<String> = profiler.folded().collect();
+        assert_eq!(folded, ["kernel::main 1", "kernel::main;memcpy 1"]);
+    }
+
+    #[test]
+    fn summarise() {
+        let mut profiler = Profiler::new(vec![symbol("main", 0x100, 0x20), symbol("f", 0x200, 0)]);
+
+        profiler.record(0x100, CALL, 0x200);
+        profiler.record(0x200, NOP, 0x204);
+        profiler.record(0x204, NOP, 0x208);
+        profiler.record(0x208, RET, 0x104);
+
+        let summary = profiler.summary(1);
+        assert!(summary.starts_with("Executed 4 instructions\n"));
+        assert!(summary.contains(&format!(
+            "{:>14} {:>6.2}% {:>14} {:>6.2}%  f\n",
+            3, 75.0, 3, 75.0
+        )));
+        assert!(!summary.contains("main\n"));
+        assert!(summary.contains(&format!("{:>14} {:>6.2}%  0x100 main+0x0\n", 1, 25.0)));
+    }
+}
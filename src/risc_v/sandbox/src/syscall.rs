@@ -6,7 +6,8 @@
 //!   - https://www.scs.stanford.edu/~zyedidia/docs/riscv/riscv-sbi.pdf
 
 use crate::inbox::Inbox;
-use crate::rv::{A0, A1, A2, A3, A6, A7};
+use crate::preimages::Preimages;
+use crate::rv::{A0, A1, A2, A3, A4, A6, A7};
 use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
 use kernel_loader::Memory;
 use rvemu::cpu::{AccessType, BYTE};
@@ -19,9 +20,14 @@ use std::{
 use tezos_smart_rollup_constants::riscv::{
     SBI_CONSOLE_PUTCHAR, SBI_FIRMWARE_TEZOS, SBI_SHUTDOWN, SBI_TEZOS_BLAKE2B_HASH256,
     SBI_TEZOS_ED25519_SIGN, SBI_TEZOS_ED25519_VERIFY, SBI_TEZOS_INBOX_NEXT, SBI_TEZOS_META_ADDRESS,
-    SBI_TEZOS_META_ORIGINATION_LEVEL,
+    SBI_TEZOS_META_ORIGINATION_LEVEL, SBI_TEZOS_REVEAL_PREIMAGE, SBI_TEZOS_STORE_COPY,
+    SBI_TEZOS_STORE_DELETE, SBI_TEZOS_STORE_DELETE_VALUE, SBI_TEZOS_STORE_HAS,
+    SBI_TEZOS_STORE_LIST_SIZE, SBI_TEZOS_STORE_MOVE, SBI_TEZOS_STORE_READ,
+    SBI_TEZOS_STORE_VALUE_SIZE, SBI_TEZOS_STORE_WRITE, SBI_TEZOS_WRITE_OUTPUT,
 };
+use tezos_smart_rollup_core::{INPUT_OUTPUT_TOO_LARGE, MAX_FILE_CHUNK_SIZE, MAX_OUTPUT_SIZE};
 use tezos_smart_rollup_encoding::smart_rollup::SmartRollupAddress;
+use tezos_smart_rollup_mock::InMemoryStore;
 
 type SBIResult = Result<(), Box<dyn Error>>;
 
@@ -98,6 +104,25 @@ pub struct RollupMetadata {
     pub address: SmartRollupAddress,
 }
 
+/// Rollup state which the kernel accesses through SBI calls
+pub struct Rollup {
+    /// Metadata of the rollup
+    pub meta: RollupMetadata,
+
+    /// Inbox the kernel reads from
+    pub inbox: Inbox,
+
+    /// Preimages the kernel may reveal
+    pub preimages: Preimages,
+
+    /// Durable storage of the kernel
+    pub durable: InMemoryStore,
+
+    /// Messages written to the outbox, along with the inbox level at which
+    /// they were written
+    pub outbox: Vec<(u32, Vec<u8>)>,
+}
+
 /// Provide the rollup's origination level.
 fn sbi_tezos_meta_origination_level(emu: &mut Emulator, meta: &RollupMetadata) -> SBIResult {
     emu.cpu.xregs.write(A0, meta.origination_level);
@@ -173,8 +198,196 @@ fn sbi_tezos_blake2b_hash256(emu: &mut Emulator) -> SBIResult {
     Ok(())
 }
 
+/// Reveal the preimage of a hash.
+fn sbi_tezos_reveal_preimage(emu: &mut Emulator, preimages: &Preimages) -> SBIResult {
+    let hash_addr = read_physical_address(emu, A0)?;
+    let hash_len = emu.cpu.xregs.read(A1);
+    let hash = read_memory(emu, hash_addr, hash_len)?;
+
+    let preimage = preimages.get(hash.as_slice())?;
+
+    let dest_addr = read_physical_address(emu, A2)?;
+    let max_bytes = emu.cpu.xregs.read(A3);
+
+    let length = max_bytes.min(preimage.len() as u64);
+    emu.cpu
+        .bus
+        .write_bytes(dest_addr, &preimage[0..length as usize])?;
+    emu.cpu.xregs.write(A0, length);
+
+    Ok(())
+}
+
+/// Read a buffer whose address and length are given in registers.
+fn read_buffer(emu: &mut Emulator, addr_reg: u64, len_reg: u64) -> Result<Vec<u8>, Box<dyn Error>> {
+    let addr = read_physical_address(emu, addr_reg)?;
+    let len = emu.cpu.xregs.read(len_reg);
+    read_memory(emu, addr, len)
+}
+
+/// Return a (possibly negative) result code to the caller.
+fn write_result(emu: &mut Emulator, result: i64) {
+    emu.cpu.xregs.write(A0, result as u64);
+}
+
+/// Write a message to the outbox.
+fn sbi_tezos_write_output(emu: &mut Emulator, rollup: &mut Rollup) -> SBIResult {
+    if emu.cpu.xregs.read(A1) > MAX_OUTPUT_SIZE as u64 {
+        write_result(emu, INPUT_OUTPUT_TOO_LARGE as i64);
+        return Ok(());
+    }
+
+    let message = read_buffer(emu, A0, A1)?;
+    rollup.outbox.push((rollup.inbox.level(), message));
+
+    write_result(emu, 0);
+    Ok(())
+}
+
+/// Check whether a path in the durable storage has a value or subkeys.
+fn sbi_tezos_store_has(emu: &mut Emulator, durable: &InMemoryStore) -> SBIResult {
+    let path = read_buffer(emu, A0, A1)?;
+
+    // SAFETY: The pointer and length describe `path`.
+    let result = unsafe { durable.store_has(path.as_ptr(), path.len()) };
+
+    write_result(emu, result as i64);
+    Ok(())
+}
+
+/// Read part of a value in the durable storage.
+fn sbi_tezos_store_read(emu: &mut Emulator, durable: &InMemoryStore) -> SBIResult {
+    let path = read_buffer(emu, A0, A1)?;
+    let offset = emu.cpu.xregs.read(A2) as usize;
+    let max_bytes = emu.cpu.xregs.read(A4).min(MAX_FILE_CHUNK_SIZE as u64);
+    let mut buffer = vec![0u8; max_bytes as usize];
+
+    // SAFETY: The pointers and lengths describe `path` and `buffer`.
+    let result = unsafe {
+        durable.store_read(
+            path.as_ptr(),
+            path.len(),
+            offset,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+        )
+    };
+
+    if result > 0 {
+        let dest_addr = read_physical_address(emu, A3)?;
+        emu.cpu
+            .bus
+            .write_bytes(dest_addr, &buffer[0..result as usize])?;
+    }
+
+    write_result(emu, result as i64);
+    Ok(())
+}
+
+/// Write part of a value in the durable storage.
+fn sbi_tezos_store_write(emu: &mut Emulator, durable: &mut InMemoryStore) -> SBIResult {
+    if emu.cpu.xregs.read(A4) > MAX_FILE_CHUNK_SIZE as u64 {
+        write_result(emu, INPUT_OUTPUT_TOO_LARGE as i64);
+        return Ok(());
+    }
+
+    let path = read_buffer(emu, A0, A1)?;
+    let offset = emu.cpu.xregs.read(A2) as usize;
+    let data = read_buffer(emu, A3, A4)?;
+
+    // SAFETY: The pointers and lengths describe `path` and `data`.
+    let result = unsafe {
+        durable.store_write(path.as_ptr(), path.len(), offset, data.as_ptr(), data.len())
+    };
+
+    write_result(emu, result as i64);
+    Ok(())
+}
+
+/// Delete the value and subkeys of a path in the durable storage.
+fn sbi_tezos_store_delete(emu: &mut Emulator, durable: &mut InMemoryStore) -> SBIResult {
+    let path = read_buffer(emu, A0, A1)?;
+
+    // SAFETY: The pointer and length describe `path`.
+    let result = unsafe { durable.store_delete(path.as_ptr(), path.len()) };
+
+    write_result(emu, result as i64);
+    Ok(())
+}
+
+/// Delete the value of a path in the durable storage.
+fn sbi_tezos_store_delete_value(emu: &mut Emulator, durable: &mut InMemoryStore) -> SBIResult {
+    let path = read_buffer(emu, A0, A1)?;
+
+    // SAFETY: The pointer and length describe `path`.
+    let result = unsafe { durable.store_delete_value(path.as_ptr(), path.len()) };
+
+    write_result(emu, result as i64);
+    Ok(())
+}
+
+/// Count the subkeys of a path in the durable storage.
+fn sbi_tezos_store_list_size(emu: &mut Emulator, durable: &InMemoryStore) -> SBIResult {
+    let path = read_buffer(emu, A0, A1)?;
+
+    // SAFETY: The pointer and length describe `path`.
+    let result = unsafe { durable.store_list_size(path.as_ptr(), path.len()) };
+
+    write_result(emu, result);
+    Ok(())
+}
+
+/// Move the value and subkeys of a path in the durable storage to another.
+fn sbi_tezos_store_move(emu: &mut Emulator, durable: &mut InMemoryStore) -> SBIResult {
+    let from_path = read_buffer(emu, A0, A1)?;
+    let to_path = read_buffer(emu, A2, A3)?;
+
+    // SAFETY: The pointers and lengths describe `from_path` and `to_path`.
+    let result = unsafe {
+        durable.store_move(
+            from_path.as_ptr(),
+            from_path.len(),
+            to_path.as_ptr(),
+            to_path.len(),
+        )
+    };
+
+    write_result(emu, result as i64);
+    Ok(())
+}
+
+/// Copy the value and subkeys of a path in the durable storage to another.
+fn sbi_tezos_store_copy(emu: &mut Emulator, durable: &mut InMemoryStore) -> SBIResult {
+    let from_path = read_buffer(emu, A0, A1)?;
+    let to_path = read_buffer(emu, A2, A3)?;
+
+    // SAFETY: The pointers and lengths describe `from_path` and `to_path`.
+    let result = unsafe {
+        durable.store_copy(
+            from_path.as_ptr(),
+            from_path.len(),
+            to_path.as_ptr(),
+            to_path.len(),
+        )
+    };
+
+    write_result(emu, result as i64);
+    Ok(())
+}
+
+/// Provide the size of a value in the durable storage.
+fn sbi_tezos_store_value_size(emu: &mut Emulator, durable: &InMemoryStore) -> SBIResult {
+    let path = read_buffer(emu, A0, A1)?;
+
+    // SAFETY: The pointer and length describe `path`.
+    let result = unsafe { durable.store_value_size(path.as_ptr(), path.len()) };
+
+    write_result(emu, result as i64);
+    Ok(())
+}
+
 /// Handle a system call originating from the user program.
-pub fn handle_sbi(emu: &mut Emulator, meta: &RollupMetadata, inbox: &mut Inbox) -> SBIResult {
+pub fn handle_sbi(emu: &mut Emulator, rollup: &mut Rollup) -> SBIResult {
     // TODO: https://gitlab.com/tezos/tezos/-/issues/6767
     // Feed errors back to caller instead of raising them in the sandbox.
     // This means this function most likely should return unit.
@@ -187,12 +400,27 @@ pub fn handle_sbi(emu: &mut Emulator, meta: &RollupMetadata, inbox: &mut Inbox)
         SBI_FIRMWARE_TEZOS => {
             let sbi_function = emu.cpu.xregs.read(A6);
             match sbi_function {
-                SBI_TEZOS_INBOX_NEXT => sbi_tezos_inbox_next(emu, inbox),
-                SBI_TEZOS_META_ORIGINATION_LEVEL => sbi_tezos_meta_origination_level(emu, meta),
-                SBI_TEZOS_META_ADDRESS => sbi_tezos_meta_address(emu, meta),
+                SBI_TEZOS_INBOX_NEXT => sbi_tezos_inbox_next(emu, &mut rollup.inbox),
+                SBI_TEZOS_META_ORIGINATION_LEVEL => {
+                    sbi_tezos_meta_origination_level(emu, &rollup.meta)
+                }
+                SBI_TEZOS_META_ADDRESS => sbi_tezos_meta_address(emu, &rollup.meta),
                 SBI_TEZOS_ED25519_SIGN => sbi_tezos_ed25519_sign(emu),
                 SBI_TEZOS_ED25519_VERIFY => sbi_tezos_ed25519_verify(emu),
                 SBI_TEZOS_BLAKE2B_HASH256 => sbi_tezos_blake2b_hash256(emu),
+                SBI_TEZOS_REVEAL_PREIMAGE => sbi_tezos_reveal_preimage(emu, &rollup.preimages),
+                SBI_TEZOS_WRITE_OUTPUT => sbi_tezos_write_output(emu, rollup),
+                SBI_TEZOS_STORE_HAS => sbi_tezos_store_has(emu, &rollup.durable),
+                SBI_TEZOS_STORE_READ => sbi_tezos_store_read(emu, &rollup.durable),
+                SBI_TEZOS_STORE_WRITE => sbi_tezos_store_write(emu, &mut rollup.durable),
+                SBI_TEZOS_STORE_DELETE => sbi_tezos_store_delete(emu, &mut rollup.durable),
+                SBI_TEZOS_STORE_DELETE_VALUE => {
+                    sbi_tezos_store_delete_value(emu, &mut rollup.durable)
+                }
+                SBI_TEZOS_STORE_LIST_SIZE => sbi_tezos_store_list_size(emu, &rollup.durable),
+                SBI_TEZOS_STORE_MOVE => sbi_tezos_store_move(emu, &mut rollup.durable),
+                SBI_TEZOS_STORE_COPY => sbi_tezos_store_copy(emu, &mut rollup.durable),
+                SBI_TEZOS_STORE_VALUE_SIZE => sbi_tezos_store_value_size(emu, &rollup.durable),
                 _ => Err(format!(
                     "Unimplemented Tezos SBI extension ({sbi_extension}) function {sbi_function}"
                 )
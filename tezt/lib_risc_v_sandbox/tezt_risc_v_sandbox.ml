@@ -25,13 +25,14 @@
 
 let risc_v_sandbox = Uses.make ~tag:"risc_v_sandbox" ~path:"./risc-v-sandbox"
 
-let run_kernel ?(posix = false) ~input ?initrd () =
+let run_kernel ?(posix = false) ~input ?initrd ?inbox () =
   let process =
     Process.spawn
       ~hooks:Tezt_tezos.Tezos_regression.hooks
       (Uses.path risc_v_sandbox)
       (["--input"; input]
       @ Option.fold ~none:[] ~some:(fun initrd -> ["--initrd"; initrd]) initrd
+      @ Option.fold ~none:[] ~some:(fun inbox -> ["--inbox"; inbox]) inbox
       @ if posix then ["--posix"] else [])
   in
   Process.check process
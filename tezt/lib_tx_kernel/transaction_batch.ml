@@ -32,8 +32,8 @@ type t = verifiable_operation list
 
 let empty = []
 
-let add_transfer ~counter ~signer_secret_key ?signer ~destination ~ticketer
-    ~ticket_content ~amount t =
+let add_transfer ~counter ?(fee = 0) ~signer_secret_key ?signer ~destination
+    ~ticketer ~ticket_content ~amount t =
   let signer =
     Option.value
       ~default:
@@ -52,6 +52,7 @@ let add_transfer ~counter ~signer_secret_key ?signer ~destination ~ticketer
     {
       signer;
       counter = Int64.of_int counter;
+      fee = Int64.of_int fee;
       contents =
         Transfer {destination; ticket_hash; amount = Int64.of_int amount};
     }
@@ -65,8 +66,8 @@ let add_transfer ~counter ~signer_secret_key ?signer ~destination ~ticketer
      to preserve the ordering. *)
   verifiable_operation :: t
 
-let add_withdraw ~counter ~signer_secret_key ?signer ~destination ~entrypoint
-    ~ticketer ~ticket_content ~amount t =
+let add_withdraw ~counter ?(fee = 0) ~signer_secret_key ?signer ~destination
+    ~entrypoint ~ticketer ~ticket_content ~amount t =
   let signer =
     Option.value
       ~default:
@@ -87,6 +88,7 @@ let add_withdraw ~counter ~signer_secret_key ?signer ~destination ~entrypoint
     {
       signer;
       counter = Int64.of_int counter;
+      fee = Int64.of_int fee;
       contents = Withdraw {destination; ticket; entrypoint};
     }
   in
@@ -111,13 +113,18 @@ let make_encoded_batch ?(wrap_with = `None) t =
       t
   in
   let encoded_batch_body = String.concat "" encoded_verifiable_operations in
-  let encoded_batch = Binary.to_string_exn string encoded_batch_body in
+  (* Batches are tagged, whether posted as external messages, revealed from
+     DAC or published on the DAL. *)
+  let batch_tag = "\004" in
+  let encoded_batch =
+    batch_tag ^ Binary.to_string_exn string encoded_batch_body
+  in
   match wrap_with with
   | `None -> encoded_batch
   | `External_message_frame sc_rollup_addr ->
-      let batch_tag = "\001" in
-      let contents = batch_tag ^ encoded_batch in
-      let external_message = Targetted {address = sc_rollup_addr; contents} in
+      let external_message =
+        Targetted {address = sc_rollup_addr; contents = encoded_batch}
+      in
       let encoded_external_message =
         Binary.to_string_exn external_message_frame_encoding external_message
       in
@@ -44,12 +44,16 @@ type t
 (** An empty batch. *)
 val empty : t
 
-(** [add_transfer ~counter ~signer_secret_key ?signer ~destination ~ticketer
-    ~ticket_content ~amount t] adds a ticket transfer operation to the batch.
+(** [add_transfer ~counter ?fee ~signer_secret_key ?signer ~destination
+    ~ticketer ~ticket_content ~amount t] adds a ticket transfer operation to the
+    batch.
 
     @param counter The transaction counter that should increase by one with each
     transaction executed by the signer's account.
 
+    @param fee The fee paid by the signer for the operation, in the fee ticket
+    of the kernel. Defaults to [0].
+
     @param signer_secret_key The secret key of the sender. Used for signing the operation.
 
     @param signer Allows to manually set the signer of the operation,
@@ -65,6 +69,7 @@ val empty : t
     @param amount The number of tickets to transfer. *)
 val add_transfer :
   counter:int ->
+  ?fee:int ->
   signer_secret_key:Signature.Ed25519.Secret_key.t ->
   ?signer:Types.signer ->
   destination:Signature.Ed25519.Public_key_hash.t ->
@@ -74,12 +79,16 @@ val add_transfer :
   t ->
   t
 
-(** [add_withdraw ~counter ~signer_secret_key ?signer ~destination ~entrypoint ~ticketer
-    ~ticket_content ~amount t] adds a ticket withdraw operation to the batch.
+(** [add_withdraw ~counter ?fee ~signer_secret_key ?signer ~destination
+    ~entrypoint ~ticketer ~ticket_content ~amount t] adds a ticket withdraw
+    operation to the batch.
 
     @param counter The transaction counter that should increase by one with each
     transaction executed by the signer's account.
 
+    @param fee The fee paid by the signer for the operation, in the fee ticket
+    of the kernel. Defaults to [0].
+
     @param signer_secret_key The secret key of the withdrawer. Used for signing the operation.
 
     @param signer Allows to manually set the signer of the operation,
@@ -97,6 +106,7 @@ val add_transfer :
     @param amount The number of tickets to withdraw. *)
 val add_withdraw :
   counter:int ->
+  ?fee:int ->
   signer_secret_key:Signature.Ed25519.Secret_key.t ->
   ?signer:Types.signer ->
   destination:Contract_hash.t ->
@@ -59,6 +59,7 @@ type signer =
 type operation = {
   signer : signer;
   counter : int64;
+  fee : int64;
   contents : operation_content;
 }
 
@@ -71,27 +72,27 @@ type targetted = {address : Hashed.Smart_rollup_address.t; contents : string}
 
 type external_message_frame = Targetted of targetted
 
+(* We require a slightly hacky int <-> little-endian conversion
+   since the rust implementation uses little-endian but
+   data-encoding does not support little-endian ints.
+
+   TODO: https://gitlab.com/tezos/tezos/-/issues/6384
+   Get rid of this awkward conversion by using big endian in
+   the rust implementation. *)
+let rev_bytes b =
+  b |> Bytes.to_seq |> List.of_seq |> List.rev |> List.to_seq |> Bytes.of_seq
+
+let le_int64_of_int i =
+  let be_int64 = Data_encoding.(Binary.to_bytes_exn int64 i) in
+  rev_bytes be_int64
+
+let int_of_le_int64 b =
+  let le_int64 = rev_bytes b in
+  let b = rev_bytes le_int64 in
+  Data_encoding.(Binary.of_bytes_exn int64 b)
+
 let operation_transfer_encoding =
   let open Data_encoding in
-  (* We require a slightly hacky int <-> little-endian conversion
-     since the rust implementation uses little-endian but
-     data-encoding does not support little-endian ints.
-
-     TODO: https://gitlab.com/tezos/tezos/-/issues/6384
-     Get rid of this awkward conversion by using big endian in
-     the rust implementation. *)
-  let rev_bytes b =
-    b |> Bytes.to_seq |> List.of_seq |> List.rev |> List.to_seq |> Bytes.of_seq
-  in
-  let le_int64_of_int i =
-    let be_int64 = Binary.to_bytes_exn int64 i in
-    rev_bytes be_int64
-  in
-  let int_of_le_int64 b =
-    let le_int64 = rev_bytes b in
-    let b = rev_bytes le_int64 in
-    Binary.of_bytes_exn int64 b
-  in
   conv
     (fun {destination; ticket_hash; amount} ->
       (destination, ticket_hash, le_int64_of_int amount))
@@ -210,11 +211,14 @@ let signer_encoding =
 let operation_encoding =
   let open Data_encoding in
   conv
-    (fun {signer; counter; contents} -> (signer, counter, contents))
-    (fun (signer, counter, contents) -> {signer; counter; contents})
-    (obj3
+    (fun {signer; counter; fee; contents} ->
+      (signer, counter, le_int64_of_int fee, contents))
+    (fun (signer, counter, fee, contents) ->
+      {signer; counter; fee = int_of_le_int64 fee; contents})
+    (obj4
        (req "signer" signer_encoding)
        (req "counter" int64)
+       (req "fee" Fixed.(bytes 8))
        (req "contents" operation_content_encoding))
 
 let verifiable_operation_encoding =
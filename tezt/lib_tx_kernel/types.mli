@@ -75,6 +75,7 @@ type signer =
 type operation = {
   signer : signer;
   counter : int64;
+  fee : int64;
   contents : operation_content;
 }
 
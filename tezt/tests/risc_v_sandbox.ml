@@ -27,6 +27,7 @@ let test_dummy_kernel () =
   Tezt_risc_v_sandbox.run_kernel
     ~input:"tezt/tests/riscv-tests/hermit-loader"
     ~initrd:"risc-v-dummy.elf"
+    ~inbox:"src/risc_v/dummy_kernel/inbox.json"
     ()
 
 let fold_dir_lwt ~f ~acc dirname =